@@ -0,0 +1,3 @@
+-- timestamp in milliseconds since epoch until which notifications are suppressed
+ALTER TABLE channels
+ADD COLUMN muted_until INTEGER;
@@ -0,0 +1,3 @@
+-- encoded Vec<RecentFile>
+ALTER TABLE metadata
+ADD COLUMN recent_files BLOB;
@@ -0,0 +1,3 @@
+-- pinned channels are listed above the unpinned ones
+ALTER TABLE channels
+ADD COLUMN pinned BOOLEAN NOT NULL DEFAULT FALSE;
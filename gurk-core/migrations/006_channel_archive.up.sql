@@ -0,0 +1,3 @@
+-- archived channels are hidden from the channel list by default
+ALTER TABLE channels
+ADD COLUMN archived BOOLEAN NOT NULL DEFAULT FALSE;
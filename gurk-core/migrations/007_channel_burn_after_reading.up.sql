@@ -0,0 +1,3 @@
+-- messages of burn-after-reading channels are never persisted
+ALTER TABLE channels
+ADD COLUMN burn_after_reading BOOLEAN NOT NULL DEFAULT FALSE;
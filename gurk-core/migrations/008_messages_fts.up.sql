@@ -0,0 +1,33 @@
+-- full-text index of the texts of the messages (without edits)
+--
+-- rowid is the arrived_at of the message; the index is kept up to date by the triggers below
+CREATE VIRTUAL TABLE messages_fts USING fts5(
+    message,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+INSERT INTO messages_fts(rowid, message)
+SELECT arrived_at, message FROM messages
+WHERE edit IS NULL AND message IS NOT NULL;
+
+-- messages are stored with REPLACE, which does not fire delete triggers
+CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages
+BEGIN
+    DELETE FROM messages_fts WHERE rowid = new.arrived_at;
+    INSERT INTO messages_fts(rowid, message)
+    SELECT new.arrived_at, new.message
+    WHERE new.edit IS NULL AND new.message IS NOT NULL;
+END;
+
+CREATE TRIGGER messages_fts_update AFTER UPDATE ON messages
+BEGIN
+    DELETE FROM messages_fts WHERE rowid = old.arrived_at;
+    INSERT INTO messages_fts(rowid, message)
+    SELECT new.arrived_at, new.message
+    WHERE new.edit IS NULL AND new.message IS NOT NULL;
+END;
+
+CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages
+BEGIN
+    DELETE FROM messages_fts WHERE rowid = old.arrived_at;
+END;
@@ -0,0 +1,3 @@
+-- encoded BTreeMap<Uuid, ContactNotes>
+ALTER TABLE metadata
+ADD COLUMN contact_notes BLOB;
@@ -0,0 +1,3 @@
+-- only the newest messages of the channel are kept, if set
+ALTER TABLE channels
+ADD COLUMN max_messages INTEGER;
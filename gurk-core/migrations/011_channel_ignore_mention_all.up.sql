@@ -0,0 +1,3 @@
+-- `@all` mentions in the group are not treated as mentions of our user
+ALTER TABLE channels
+ADD COLUMN ignore_mention_all BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,3 @@
+-- Overrides how much of the messages is shown in notifications: `full`, `sender` or `hidden`
+ALTER TABLE channels
+ADD COLUMN notification_content TEXT;
@@ -0,0 +1,3 @@
+-- encoded BTreeMap<Uuid, LocalContact>
+ALTER TABLE metadata
+ADD COLUMN contacts BLOB;
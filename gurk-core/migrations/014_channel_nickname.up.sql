@@ -0,0 +1,3 @@
+-- local name shown instead of the name of the group, if set
+ALTER TABLE channels
+ADD COLUMN nickname TEXT;
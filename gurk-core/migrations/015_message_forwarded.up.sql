@@ -0,0 +1,3 @@
+-- the message was forwarded from another channel by our user
+ALTER TABLE messages
+ADD COLUMN forwarded BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,3 @@
+-- the reason why sending our message failed, kept for resending it after a restart
+ALTER TABLE messages
+ADD COLUMN send_failed TEXT;
@@ -0,0 +1,4 @@
+-- the sender and the timestamp identify a message; new messages are inserted with
+-- `ON CONFLICT DO NOTHING`, so that replayed messages do not overwrite the stored ones
+CREATE UNIQUE INDEX idx_messages_sender_timestamp
+ON messages (channel_id, from_id, arrived_at);
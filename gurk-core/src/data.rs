@@ -0,0 +1,592 @@
+//! Part of the app which is serialized
+
+use std::collections::HashSet;
+
+use anyhow::anyhow;
+use presage::libsignal_service::zkgroup::groups::{GroupMasterKey, GroupSecretParams};
+use presage::proto;
+use presage::proto::data_message::Quote;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::receipt::Receipt;
+use crate::signal::{Attachment, GroupIdentifierBytes, GroupMasterKeyBytes};
+
+/// Word in an outgoing group message, which mentions all other members of the group
+pub const MENTION_ALL: &str = "@all";
+
+/// Placeholder of a mention in the text of a message
+pub const MENTION_PLACEHOLDER: char = '\u{fffc}';
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Channel {
+    pub id: ChannelId,
+    pub name: String,
+    pub group_data: Option<GroupData>,
+    pub unread_messages: u32,
+    pub typing: TypingSet,
+    /// Notifications are suppressed until this timestamp (in milliseconds since epoch)
+    pub muted_until: Option<u64>,
+    /// Pinned channels are always listed above the unpinned ones
+    pub pinned: bool,
+    /// Archived channels are listed below all other channels and hidden by default
+    pub archived: bool,
+    /// Messages of burn-after-reading channels are never persisted and are dropped from memory
+    /// when switching away from the channel
+    pub burn_after_reading: bool,
+    /// If set, only this many of the newest messages are kept; older messages are deleted when
+    /// new ones arrive
+    pub max_messages: Option<usize>,
+    /// Whether `@all` mentions in this group are treated like any other message, instead of as
+    /// a mention of our user
+    pub ignore_mention_all: bool,
+    /// If set, overrides how much of the messages of this channel is shown in notifications
+    pub notification_content: Option<NotificationContent>,
+    /// Local name shown instead of the name of the group
+    ///
+    /// The nicknames of contacts are kept with the other local settings of contacts instead.
+    pub nickname: Option<String>,
+}
+
+/// How much of a message is shown in its notification
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationContent {
+    /// Sender and text of the message
+    #[default]
+    Full,
+    /// Only the sender of the message
+    Sender,
+    /// Only "New message", without the sender
+    Hidden,
+}
+
+impl NotificationContent {
+    pub const VARIANTS: &'static [&'static str] = &["full", "sender", "hidden"];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Sender => "sender",
+            Self::Hidden => "hidden",
+        }
+    }
+}
+
+impl std::str::FromStr for NotificationContent {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(Self::Full),
+            "sender" => Ok(Self::Sender),
+            "hidden" => Ok(Self::Hidden),
+            _ => Err(anyhow!("unknown notification content: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypingSet {
+    SingleTyping(bool),
+    GroupTyping(HashSet<Uuid>),
+}
+
+impl TypingSet {
+    pub fn new(is_group: bool) -> Self {
+        if is_group {
+            Self::GroupTyping(Default::default())
+        } else {
+            Self::SingleTyping(false)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupData {
+    #[serde(default)]
+    pub master_key_bytes: GroupMasterKeyBytes,
+    pub members: Vec<Uuid>,
+    pub revision: u32,
+}
+
+impl Channel {
+    /// Value of `muted_until` for channels muted without a time limit
+    ///
+    /// Fits into a signed 64-bit integer, so that it can be stored in the database.
+    pub const MUTED_FOREVER: u64 = i64::MAX as u64;
+
+    /// Whether the channel is muted at the given timestamp (in milliseconds since epoch)
+    pub fn is_muted(&self, now: u64) -> bool {
+        self.muted_until
+            .is_some_and(|muted_until| now < muted_until)
+    }
+
+    /// Name of the channel, or the local nickname of the group if set
+    pub fn shown_name(&self) -> &str {
+        self.nickname.as_deref().unwrap_or(&self.name)
+    }
+
+    pub fn reset_writing(&mut self, user: Uuid) -> bool {
+        match &mut self.typing {
+            TypingSet::GroupTyping(ref mut hash_set) => hash_set.remove(&user),
+            TypingSet::SingleTyping(true) => {
+                self.typing = TypingSet::SingleTyping(false);
+                true
+            }
+            TypingSet::SingleTyping(false) => false,
+        }
+    }
+
+    pub fn is_writing(&self) -> bool {
+        match &self.typing {
+            TypingSet::GroupTyping(a) => !a.is_empty(),
+            TypingSet::SingleTyping(a) => *a,
+        }
+    }
+
+    pub fn user_id(&self) -> Option<Uuid> {
+        match self.id {
+            ChannelId::User(id) => Some(id),
+            ChannelId::Group(_) => None,
+        }
+    }
+
+    /// Replaces the `@all` words of an outgoing group message by mentions of the other members
+    ///
+    /// Signal has no mention of a whole group, so each member except our user is mentioned
+    /// instead. Returns `None` if there is nothing to replace.
+    pub fn expand_mention_all(
+        &self,
+        text: &str,
+        user_id: Uuid,
+    ) -> Option<(String, Vec<BodyRange>)> {
+        let members: Vec<Uuid> = self
+            .group_data
+            .as_ref()?
+            .members
+            .iter()
+            .copied()
+            .filter(|&id| id != user_id)
+            .collect();
+        if members.is_empty() || !text.contains(MENTION_ALL) {
+            return None;
+        }
+        let is_word_char = |c: char| c.is_alphanumeric() || c == '_' || c == '@';
+
+        let mut expanded = String::with_capacity(text.len());
+        // length of the expanded text in UTF-16 code units, in which body ranges are counted
+        let mut expanded_len = 0;
+        let mut ranges = Vec::new();
+        let mut rest = text;
+        while let Some(idx) = rest.find(MENTION_ALL) {
+            let (before, after) = rest.split_at(idx);
+            let after = &after[MENTION_ALL.len()..];
+            expanded.push_str(before);
+            expanded_len += before.encode_utf16().count();
+            if expanded.ends_with(is_word_char) || after.starts_with(is_word_char) {
+                // e.g. part of an email address
+                expanded.push_str(MENTION_ALL);
+                expanded_len += MENTION_ALL.len();
+            } else {
+                for (i, &id) in members.iter().enumerate() {
+                    if i > 0 {
+                        expanded.push(' ');
+                        expanded_len += 1;
+                    }
+                    let start = u16::try_from(expanded_len).ok()?;
+                    expanded.push(MENTION_PLACEHOLDER);
+                    expanded_len += 1;
+                    ranges.push(BodyRange {
+                        start,
+                        end: start.checked_add(1)?,
+                        value: AssociatedValue::MentionUuid(id),
+                    });
+                }
+            }
+            rest = after;
+        }
+        expanded.push_str(rest);
+        (!ranges.is_empty()).then_some((expanded, ranges))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChannelId {
+    User(Uuid),
+    Group(GroupIdentifierBytes),
+}
+
+impl From<Uuid> for ChannelId {
+    fn from(id: Uuid) -> Self {
+        ChannelId::User(id)
+    }
+}
+
+impl TryFrom<&[u8]> for ChannelId {
+    type Error = UnexpectedGroupBytesLen;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        bytes
+            .try_into()
+            .map(ChannelId::Group)
+            .map_err(|_| UnexpectedGroupBytesLen(bytes.len()))
+    }
+}
+
+impl PartialEq<Uuid> for ChannelId {
+    fn eq(&self, other: &Uuid) -> bool {
+        match self {
+            ChannelId::User(id) => id == other,
+            ChannelId::Group(_) => false,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("unexpected group bytes length: {0}")]
+pub struct UnexpectedGroupBytesLen(usize);
+
+impl ChannelId {
+    pub fn from_master_key_bytes(bytes: impl AsRef<[u8]>) -> anyhow::Result<Self> {
+        let master_key_ar = bytes
+            .as_ref()
+            .try_into()
+            .map_err(|_| anyhow!("invalid group master key"))?;
+        let master_key = GroupMasterKey::new(master_key_ar);
+        let secret_params = GroupSecretParams::derive_from_master_key(master_key);
+        let group_id = secret_params.get_group_identifier();
+        Ok(Self::Group(group_id))
+    }
+
+    pub fn user(&self) -> Option<Uuid> {
+        match self {
+            ChannelId::User(uuid) => Some(*uuid),
+            _ => None,
+        }
+    }
+
+    pub fn is_user(&self) -> bool {
+        matches!(self, ChannelId::User(_))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TypingAction {
+    Started,
+    Stopped,
+}
+
+impl TypingAction {
+    pub fn from_i32(i: i32) -> Self {
+        match i {
+            0 => Self::Started,
+            1 => Self::Stopped,
+            _ => {
+                error!("Got incorrect TypingAction : {}", i);
+                Self::Stopped
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Message {
+    pub from_id: Uuid,
+    pub message: Option<String>,
+    pub arrived_at: u64,
+    #[serde(default)]
+    pub quote: Option<Box<Message>>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    #[serde(default)]
+    pub reactions: Vec<(Uuid, String)>,
+    #[serde(default)]
+    pub receipt: Receipt,
+    #[serde(default)]
+    pub body_ranges: Vec<BodyRange>,
+    /// Why sending our message failed, if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send_failed: Option<String>,
+    /// Arrived at of the originally edited message
+    ///
+    /// When several edits are done, this is the arrived_at of the very first original message.
+    #[serde(default)]
+    pub edit: Option<u64>,
+    /// Whether the message was edited
+    #[serde(default)]
+    pub edited: bool,
+    /// Whether our user forwarded the message from another channel
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub forwarded: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BodyRange {
+    pub start: u16,
+    pub end: u16,
+    pub value: AssociatedValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AssociatedValue {
+    MentionUuid(Uuid),
+    Style(Style),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Style {
+    #[default]
+    None,
+    Bold,
+    Italic,
+    Spoiler,
+    Strikethrough,
+    Monospace,
+}
+
+impl Style {
+    fn from_proto(value: proto::body_range::Style) -> Self {
+        match value {
+            proto::body_range::Style::None => Self::None,
+            proto::body_range::Style::Bold => Self::Bold,
+            proto::body_range::Style::Italic => Self::Italic,
+            proto::body_range::Style::Spoiler => Self::Spoiler,
+            proto::body_range::Style::Strikethrough => Self::Strikethrough,
+            proto::body_range::Style::Monospace => Self::Monospace,
+        }
+    }
+
+    fn to_proto(&self) -> proto::body_range::Style {
+        match self {
+            Style::None => proto::body_range::Style::None,
+            Style::Bold => proto::body_range::Style::Bold,
+            Style::Italic => proto::body_range::Style::Italic,
+            Style::Spoiler => proto::body_range::Style::Spoiler,
+            Style::Strikethrough => proto::body_range::Style::Strikethrough,
+            Style::Monospace => proto::body_range::Style::Monospace,
+        }
+    }
+}
+
+impl From<&BodyRange> for proto::BodyRange {
+    fn from(range: &BodyRange) -> Self {
+        let associtated_value = match &range.value {
+            AssociatedValue::MentionUuid(id) => {
+                proto::body_range::AssociatedValue::MentionAci(id.to_string())
+            }
+            AssociatedValue::Style(style) => {
+                proto::body_range::AssociatedValue::Style(style.to_proto().into())
+            }
+        };
+        Self {
+            start: Some(range.start.into()),
+            length: Some((range.end - range.start).into()),
+            associated_value: Some(associtated_value),
+        }
+    }
+}
+
+impl BodyRange {
+    pub fn from_proto(proto: proto::BodyRange) -> Option<Self> {
+        let value = match proto.associated_value? {
+            proto::body_range::AssociatedValue::MentionAci(uuid) => {
+                let uuid = uuid.parse().ok()?;
+                AssociatedValue::MentionUuid(uuid)
+            }
+            proto::body_range::AssociatedValue::Style(style) => AssociatedValue::Style(
+                proto::body_range::Style::try_from(style)
+                    .map(Style::from_proto)
+                    .unwrap_or_default(),
+            ),
+        };
+        Some(Self {
+            start: proto.start?.try_into().ok()?,
+            end: (proto.start? + proto.length?).try_into().ok()?,
+            value,
+        })
+    }
+}
+
+impl Message {
+    pub fn new(
+        from_id: Uuid,
+        message: Option<String>,
+        body_ranges: impl IntoIterator<Item = BodyRange>,
+        arrived_at: u64,
+        attachments: Vec<Attachment>,
+    ) -> Self {
+        Self {
+            from_id,
+            message,
+            arrived_at,
+            quote: None,
+            attachments,
+            reactions: Default::default(),
+            receipt: Receipt::Sent,
+            body_ranges: body_ranges.into_iter().collect(),
+            send_failed: Default::default(),
+            edit: Default::default(),
+            edited: Default::default(),
+            forwarded: Default::default(),
+        }
+    }
+
+    pub fn text(from_id: Uuid, arrived_at: u64, message: String) -> Self {
+        Self {
+            from_id,
+            message: Some(message),
+            arrived_at,
+            quote: Default::default(),
+            attachments: Default::default(),
+            reactions: Default::default(),
+            receipt: Default::default(),
+            body_ranges: Default::default(),
+            send_failed: Default::default(),
+            edit: Default::default(),
+            edited: Default::default(),
+            forwarded: Default::default(),
+        }
+    }
+
+    pub fn from_quote(quote: Quote) -> Option<Message> {
+        Some(Message {
+            from_id: quote.author_aci?.parse().ok()?,
+            message: quote.text,
+            arrived_at: quote.id?,
+            quote: None,
+            attachments: Default::default(),
+            reactions: Default::default(),
+            receipt: Receipt::Sent,
+            body_ranges: quote
+                .body_ranges
+                .into_iter()
+                .filter_map(BodyRange::from_proto)
+                .collect(),
+            send_failed: Default::default(),
+            edit: Default::default(),
+            edited: Default::default(),
+            forwarded: Default::default(),
+        })
+    }
+
+    /// Ids of the users mentioned in the message
+    pub fn mentions(&self) -> impl Iterator<Item = Uuid> + '_ {
+        self.body_ranges
+            .iter()
+            .filter_map(|range| match range.value {
+                AssociatedValue::MentionUuid(id) => Some(id),
+                AssociatedValue::Style(_) => None,
+            })
+    }
+
+    /// Whether the message mentions all group `members` except its sender, like an expanded
+    /// `@all`
+    ///
+    /// In groups with only one other member, a mention of all members cannot be told apart from
+    /// a mention of the member, so it is never considered a mention of all.
+    pub fn mentions_all(&self, members: &[Uuid]) -> bool {
+        let mentioned: HashSet<Uuid> = self.mentions().collect();
+        let others: Vec<&Uuid> = members.iter().filter(|&&id| id != self.from_id).collect();
+        others.len() > 1 && others.iter().all(|id| mentioned.contains(id))
+    }
+
+    /// Returns whether this message is an edit of an another message
+    pub fn is_edit(&self) -> bool {
+        self.edit.is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.message.is_none()
+            && self.attachments.is_empty()
+            && self.reactions.is_empty()
+            && self.quote.is_none()
+    }
+
+    /// Estimated number of bytes the message occupies in memory, including its quote
+    pub fn estimated_size(&self) -> usize {
+        let text = self.message.as_ref().map_or(0, String::capacity);
+        let quote = self
+            .quote
+            .as_ref()
+            .map_or(0, |quote| quote.estimated_size());
+        let attachments: usize = self
+            .attachments
+            .iter()
+            .map(|attachment| {
+                std::mem::size_of::<Attachment>()
+                    + attachment.id.capacity()
+                    + attachment.content_type.capacity()
+                    + attachment.filename.capacity()
+            })
+            .sum();
+        let reactions: usize = self
+            .reactions
+            .iter()
+            .map(|(_, emoji)| std::mem::size_of::<(Uuid, String)>() + emoji.capacity())
+            .sum();
+        let body_ranges = self.body_ranges.len() * std::mem::size_of::<BodyRange>();
+        let send_failed = self.send_failed.as_ref().map_or(0, String::capacity);
+        std::mem::size_of::<Self>()
+            + text
+            + quote
+            + attachments
+            + reactions
+            + body_ranges
+            + send_failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group_channel(members: Vec<Uuid>) -> Channel {
+        Channel {
+            id: ChannelId::Group([0; 32]),
+            name: "group".to_string(),
+            group_data: Some(GroupData {
+                master_key_bytes: [0; 32],
+                members,
+                revision: 1,
+            }),
+            unread_messages: 0,
+            typing: TypingSet::new(true),
+            muted_until: None,
+            pinned: false,
+            archived: false,
+            burn_after_reading: false,
+            max_messages: None,
+            ignore_mention_all: false,
+            notification_content: None,
+            nickname: None,
+        }
+    }
+
+    #[test]
+    fn test_expand_mention_all() {
+        let user_id = Uuid::new_v4();
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let channel = group_channel(vec![user_id, alice, bob]);
+
+        let (text, ranges) = channel
+            .expand_mention_all("😀 @all, lunch?", user_id)
+            .unwrap();
+        assert_eq!(text, "😀 \u{fffc} \u{fffc}, lunch?");
+        // the emoji is 2 UTF-16 code units long
+        let mention = |start, id| BodyRange {
+            start,
+            end: start + 1,
+            value: AssociatedValue::MentionUuid(id),
+        };
+        assert_eq!(ranges, [mention(3, alice), mention(5, bob)]);
+
+        let message = Message::new(user_id, Some(text), ranges, 1, vec![]);
+        assert!(message.mentions_all(&[user_id, alice, bob]));
+        assert!(!message.mentions_all(&[user_id, alice, bob, Uuid::new_v4()]));
+
+        assert_eq!(channel.expand_mention_all("mail me@all.org", user_id), None);
+        assert_eq!(channel.expand_mention_all("hi", user_id), None);
+    }
+}
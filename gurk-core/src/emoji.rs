@@ -1,3 +1,5 @@
+//! Replacement of emoji shortcodes like `:thumbsup:` in texts
+
 use std::borrow::Cow;
 
 use once_cell::sync::Lazy;
@@ -5,7 +7,7 @@ use regex::{Captures, Regex};
 
 static REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r":([a-z1238+-][a-z0-9_-]*):").unwrap());
 
-pub(crate) fn replace_shortcodes(text: &str) -> Cow<str> {
+pub fn replace_shortcodes(text: &str) -> Cow<str> {
     REGEX.replace_all(text, Replacer)
 }
 
@@ -0,0 +1,12 @@
+//! Core of the gurk Signal client: Signal backend, storage and message model
+//!
+//! This crate does not depend on the terminal UI, so that other frontends and tools can build on
+//! it.
+
+pub mod data;
+pub mod emoji;
+pub mod receipt;
+pub mod redact;
+pub mod signal;
+pub mod storage;
+pub mod util;
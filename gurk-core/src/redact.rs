@@ -0,0 +1,81 @@
+//! Redaction of sensitive data in logs
+//!
+//! By default, only metadata (ids, timestamps, kinds of messages) is logged. Message contents,
+//! names and file names are only logged when explicitly enabled by the `--log-sensitive` flag.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static LOG_SENSITIVE: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables logging of sensitive data
+pub fn set_log_sensitive(enabled: bool) {
+    LOG_SENSITIVE.store(enabled, Ordering::Relaxed);
+}
+
+fn is_log_sensitive() -> bool {
+    LOG_SENSITIVE.load(Ordering::Relaxed)
+}
+
+/// Value which is only logged if logging of sensitive data is enabled
+///
+/// Otherwise, the value is formatted as `<redacted>`.
+pub struct Sensitive<T>(pub T);
+
+impl<T> Sensitive<T> {
+    /// Formats the value with `fmt` if it is logged, or else as `<redacted>`
+    fn fmt_with(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        is_logged: bool,
+        fmt: impl FnOnce(&T, &mut fmt::Formatter<'_>) -> fmt::Result,
+    ) -> fmt::Result {
+        if is_logged {
+            fmt(&self.0, f)
+        } else {
+            f.write_str("<redacted>")
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Sensitive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_with(f, is_log_sensitive(), T::fmt)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Sensitive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_with(f, is_log_sensitive(), T::fmt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Formats the sensitive value as if logging of sensitive data was set, without touching the
+    /// global flag shared by the tests running in parallel
+    struct Logged<'a, T>(&'a Sensitive<T>, bool);
+
+    impl<T: fmt::Display> fmt::Display for Logged<'_, T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.fmt_with(f, self.1, T::fmt)
+        }
+    }
+
+    impl<T: fmt::Debug> fmt::Debug for Logged<'_, T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.fmt_with(f, self.1, T::fmt)
+        }
+    }
+
+    #[test]
+    fn test_sensitive() {
+        let value = Sensitive("secret");
+        assert_eq!(format!("{}", Logged(&value, false)), "<redacted>");
+        assert_eq!(format!("{:?}", Logged(&value, false)), "<redacted>");
+        assert_eq!(format!("{}", Logged(&value, true)), "secret");
+        assert_eq!(format!("{:?}", Logged(&value, true)), "\"secret\"");
+    }
+}
@@ -7,6 +7,7 @@ use mime_guess::{get_mime_extensions, Mime};
 use presage::proto::AttachmentPointer;
 use tracing::info;
 
+use crate::redact::Sensitive;
 use crate::signal::Attachment;
 use crate::util::utc_timestamp_msec_to_local;
 
@@ -16,6 +17,23 @@ pub(super) fn save(
     data_dir: impl AsRef<Path>,
     pointer: AttachmentPointer,
     data: Vec<u8>,
+) -> anyhow::Result<Attachment> {
+    let attachment = locate(data_dir, &pointer)?;
+    let filepath = &attachment.filename;
+    let filedir = filepath.parent().context("attachment without directory")?;
+    std::fs::create_dir_all(filedir)
+        .with_context(|| format!("failed to create dir: {}", filedir.display()))?;
+    std::fs::write(filepath, data)
+        .with_context(|| format!("failed to save attachment at: {}", filepath.display()))?;
+
+    info!(dest = %Sensitive(filepath.display()), "saved attachment");
+    Ok(attachment)
+}
+
+/// Attachment with the path it is saved to, without saving it
+pub(super) fn locate(
+    data_dir: impl AsRef<Path>,
+    pointer: &AttachmentPointer,
 ) -> anyhow::Result<Attachment> {
     let base_dir = data_dir.as_ref().join("files");
 
@@ -30,7 +48,7 @@ pub(super) fn save(
         .parse()
         .unwrap_or(APPLICATION_OCTET_STREAM);
 
-    let name = derive_name(&pointer, digest, &mime);
+    let name = derive_name(pointer, digest, &mime);
 
     let date = pointer
         .upload_timestamp
@@ -40,13 +58,6 @@ pub(super) fn save(
     let filedir = base_dir.join(date.to_string());
     let filepath = conflict_free_filename(&filedir, name);
 
-    std::fs::create_dir_all(&filedir)
-        .with_context(|| format!("failed to create dir: {}", filedir.display()))?;
-    std::fs::write(&filepath, data)
-        .with_context(|| format!("failed to save attachment at: {}", filepath.display()))?;
-
-    info!(dest =% filepath.display(), "saved attachment");
-
     Ok(Attachment {
         id: digest_hex,
         content_type: mime.to_string(),
@@ -1,5 +1,6 @@
 //! Implementation of [`crate::signal::SignalManager`] via `presage`
 
+use std::path::PathBuf;
 use std::pin::Pin;
 
 use anyhow::Context;
@@ -7,41 +8,53 @@ use async_trait::async_trait;
 use presage::libsignal_service::content::{Content, ContentBody};
 use presage::libsignal_service::prelude::ProfileKey;
 use presage::libsignal_service::protocol::ServiceId;
-use presage::libsignal_service::sender::AttachmentSpec;
+use presage::libsignal_service::push_service::ServiceError;
+use presage::libsignal_service::sender::{AttachmentSpec, MessageSenderError};
 use presage::manager::{ReceivingMode, Registered};
 use presage::model::contacts::Contact;
 use presage::model::groups::Group;
+use presage::proto::access_control::AccessRequired;
 use presage::proto::data_message::{Quote, Reaction};
+use presage::proto::member::Role;
 use presage::proto::{AttachmentPointer, DataMessage, EditMessage, GroupContextV2, ReceiptMessage};
 use presage::store::ContentsStore;
 use presage_store_sled::SledStore;
-use tokio::sync::oneshot;
+use tokio::sync::{mpsc, oneshot};
 use tokio_stream::Stream;
 use tracing::error;
 use uuid::Uuid;
 
 use crate::data::{Channel, ChannelId, GroupData, Message};
 use crate::receipt::Receipt;
+use crate::redact::Sensitive;
 use crate::util::utc_now_timestamp_msec;
 
 use super::{
-    attachment, Attachment, GroupMasterKeyBytes, ProfileKeyBytes, ResolvedGroup, SignalManager,
+    attachment, Attachment, CaptchaRequired, GroupMasterKeyBytes, GroupRequest, GroupRequests,
+    LinkedDevice, PendingSend, ProfileKeyBytes, RateLimited, ResolvedGroup, SendProgress,
+    SignalManager,
 };
 
-pub(super) struct PresageManager {
+/// Id of the primary device of an account
+const PRIMARY_DEVICE_ID: u32 = 1;
+
+/// Signal manager of a linked device, backed by a presage sled store
+pub struct PresageManager {
     manager: presage::Manager<SledStore, Registered>,
+    /// Directory the attachments are saved in, in the subdirectory `files`
+    data_dir: PathBuf,
 }
 
 impl PresageManager {
-    pub(super) fn new(manager: presage::Manager<SledStore, Registered>) -> Self {
-        Self { manager }
+    pub fn new(manager: presage::Manager<SledStore, Registered>, data_dir: PathBuf) -> Self {
+        Self { manager, data_dir }
     }
 }
 
 #[async_trait(?Send)]
 impl SignalManager for PresageManager {
     fn clone_boxed(&self) -> Box<dyn SignalManager> {
-        Box::new(Self::new(self.manager.clone()))
+        Box::new(Self::new(self.manager.clone(), self.data_dir.clone()))
     }
 
     fn user_id(&self) -> Uuid {
@@ -85,10 +98,14 @@ impl SignalManager for PresageManager {
         attachment_pointer: AttachmentPointer,
     ) -> anyhow::Result<Attachment> {
         let attachment_data = self.manager.get_attachment(&attachment_pointer).await?;
-        let data_dir = dirs::data_dir()
-            .context("could not find data directory")?
-            .join("gurk");
-        attachment::save(data_dir, attachment_pointer, attachment_data)
+        attachment::save(&self.data_dir, attachment_pointer, attachment_data)
+    }
+
+    fn locate_attachment(
+        &self,
+        attachment_pointer: &AttachmentPointer,
+    ) -> anyhow::Result<Attachment> {
+        attachment::locate(&self.data_dir, attachment_pointer)
     }
 
     fn send_receipt(&self, sender_uuid: Uuid, timestamps: Vec<u64>, receipt: Receipt) {
@@ -117,11 +134,19 @@ impl SignalManager for PresageManager {
         quote_message: Option<&Message>,
         edit_message_timestamp: Option<u64>,
         attachments: Vec<(AttachmentSpec, Vec<u8>)>,
-    ) -> (Message, oneshot::Receiver<anyhow::Result<()>>) {
+        timestamp: Option<u64>,
+    ) -> (Message, PendingSend) {
         let mut message: String = crate::emoji::replace_shortcodes(&text).into_owned();
+        let body_ranges = match channel.expand_mention_all(&message, self.user_id()) {
+            Some((expanded, body_ranges)) => {
+                message = expanded;
+                body_ranges
+            }
+            None => Vec::new(),
+        };
         let has_attachments = !attachments.is_empty();
 
-        let timestamp = utc_now_timestamp_msec();
+        let timestamp = timestamp.unwrap_or_else(utc_now_timestamp_msec);
 
         let quote = quote_message.map(|message| Quote {
             id: Some(message.arrived_at),
@@ -135,6 +160,7 @@ impl SignalManager for PresageManager {
         let mut data_message = DataMessage {
             body: Some(message.clone()),
             quote,
+            body_ranges: body_ranges.iter().map(From::from).collect(),
             ..Default::default()
         };
 
@@ -156,10 +182,15 @@ impl SignalManager for PresageManager {
         }
 
         let (response_tx, response) = oneshot::channel();
+        let (progress_tx, progress) = mpsc::unbounded_channel();
+        let mut abort = None;
         match channel.id {
             ChannelId::User(uuid) => {
                 let mut manager = self.manager.clone();
-                tokio::task::spawn_local(async move {
+                let task = tokio::task::spawn_local(async move {
+                    if has_attachments {
+                        let _ = progress_tx.send(SendProgress::UploadingAttachments);
+                    }
                     if let Err(error) =
                         upload_attachments(&manager, attachments, &mut data_message).await
                     {
@@ -167,6 +198,7 @@ impl SignalManager for PresageManager {
                         let _ = response_tx.send(Err(error));
                         return;
                     }
+                    let _ = progress_tx.send(SendProgress::Sending);
 
                     let body = if let Some(target_sent_timestamp) = edit_message_timestamp {
                         ContentBody::EditMessage(EditMessage {
@@ -182,11 +214,12 @@ impl SignalManager for PresageManager {
                         .await
                     {
                         error!(dest =% uuid, %error, "failed to send message");
-                        let _ = response_tx.send(Err(error.into()));
+                        let _ = response_tx.send(Err(send_error(error)));
                         return;
                     }
                     let _ = response_tx.send(Ok(()));
                 });
+                abort = Some(task.abort_handle());
             }
             ChannelId::Group(_) => {
                 if let Some(group_data) = channel.group_data.as_ref() {
@@ -199,7 +232,10 @@ impl SignalManager for PresageManager {
                         ..Default::default()
                     });
 
-                    tokio::task::spawn_local(async move {
+                    let task = tokio::task::spawn_local(async move {
+                        if has_attachments {
+                            let _ = progress_tx.send(SendProgress::UploadingAttachments);
+                        }
                         if let Err(error) =
                             upload_attachments(&manager, attachments, &mut data_message).await
                         {
@@ -207,6 +243,7 @@ impl SignalManager for PresageManager {
                             let _ = response_tx.send(Err(error));
                             return;
                         }
+                        let _ = progress_tx.send(SendProgress::Sending);
 
                         let body = if let Some(target_sent_timestamp) = edit_message_timestamp {
                             ContentBody::EditMessage(EditMessage {
@@ -222,11 +259,12 @@ impl SignalManager for PresageManager {
                             .await
                         {
                             error!(%error, "failed to send group message");
-                            let _ = response_tx.send(Err(error.into()));
+                            let _ = response_tx.send(Err(send_error(error)));
                             return;
                         }
                         let _ = response_tx.send(Ok(()));
                     });
+                    abort = Some(task.abort_handle());
                 } else {
                     error!("cannot send to broken channel without group data");
                 }
@@ -241,12 +279,18 @@ impl SignalManager for PresageManager {
             attachments: Default::default(),
             reactions: Default::default(),
             receipt: Receipt::Sent,
-            body_ranges: Default::default(),
+            body_ranges,
             send_failed: Default::default(),
             edit: edit_message_timestamp,
             edited: edit_message_timestamp.is_some(),
+            forwarded: false,
+        };
+        let pending = PendingSend {
+            response,
+            progress,
+            abort,
         };
-        (message, response)
+        (message, pending)
     }
 
     fn send_reaction(&self, channel: &Channel, message: &Message, emoji: String, remove: bool) {
@@ -274,7 +318,7 @@ impl SignalManager for PresageManager {
                         .await
                     {
                         // TODO: Proper error handling
-                        error!("failed to send reaction {} to {}: {}", &emoji, uuid, e);
+                        error!(emoji = %Sensitive(&emoji), dest =% uuid, error =% e, "failed to send reaction");
                     }
                 });
             }
@@ -294,7 +338,7 @@ impl SignalManager for PresageManager {
                         .await
                     {
                         // TODO: Proper error handling
-                        error!("failed to send group reaction {}: {}", &emoji, e);
+                        error!(emoji = %Sensitive(&emoji), error =% e, "failed to send group reaction");
                     }
                 });
             }
@@ -322,6 +366,30 @@ impl SignalManager for PresageManager {
         }
     }
 
+    async fn is_registered(
+        &self,
+        id: Uuid,
+        profile_key: Option<ProfileKeyBytes>,
+    ) -> anyhow::Result<Option<bool>> {
+        let profile_key = match profile_key {
+            Some(profile_key) => ProfileKey::create(profile_key),
+            None => match self.manager.store().profile_key(&id).await? {
+                Some(profile_key) => profile_key,
+                None => return Ok(None),
+            },
+        };
+        match self
+            .manager
+            .clone()
+            .retrieve_profile_by_uuid(id, profile_key)
+            .await
+        {
+            Ok(_) => Ok(Some(true)),
+            Err(presage::Error::ServiceError(ServiceError::NotFoundError)) => Ok(Some(false)),
+            Err(error) => Err(error.into()),
+        }
+    }
+
     async fn request_contacts_sync(&self) -> anyhow::Result<()> {
         Ok(self.manager.clone().sync_contacts().await?)
     }
@@ -372,6 +440,107 @@ impl SignalManager for PresageManager {
                 .flatten(),
         )
     }
+
+    async fn group_requests(
+        &self,
+        master_key_bytes: GroupMasterKeyBytes,
+    ) -> anyhow::Result<GroupRequests> {
+        let group = self
+            .manager
+            .store()
+            .group(master_key_bytes)
+            .await?
+            .context("no group found")?;
+        let user_id = self.user_id();
+        let is_admin = group
+            .members
+            .iter()
+            .any(|member| member.uuid == user_id && member.role == Role::Administrator);
+        let link_approval = group.access_control.as_ref().is_some_and(|access_control| {
+            access_control.add_from_invite_link == AccessRequired::Administrator
+        });
+        let mut join_requests: Vec<GroupRequest> = group
+            .requesting_members
+            .iter()
+            .map(|member| GroupRequest {
+                user_id: member.uuid,
+                added_by: None,
+                timestamp: member.timestamp,
+            })
+            .collect();
+        join_requests.sort_unstable_by_key(|request| request.timestamp);
+        let mut invites: Vec<GroupRequest> = group
+            .pending_members
+            .iter()
+            .map(|member| GroupRequest {
+                user_id: member.uuid,
+                added_by: Some(member.added_by_uuid),
+                timestamp: member.timestamp,
+            })
+            .collect();
+        invites.sort_unstable_by_key(|invite| invite.timestamp);
+        Ok(GroupRequests {
+            is_admin,
+            link_approval,
+            join_requests,
+            invites,
+        })
+    }
+
+    async fn submit_captcha(&self, token: &str, captcha: &str) -> anyhow::Result<()> {
+        self.manager
+            .submit_recaptcha_challenge(token, captcha)
+            .await?;
+        Ok(())
+    }
+
+    fn device_id(&self) -> u32 {
+        self.manager
+            .registration_data()
+            .device_id
+            .unwrap_or(PRIMARY_DEVICE_ID)
+    }
+
+    async fn linked_devices(&self) -> anyhow::Result<Vec<LinkedDevice>> {
+        let devices = self.manager.devices().await?;
+        Ok(devices
+            .into_iter()
+            .map(|device| LinkedDevice {
+                id: device.id as u32,
+                name: device.name,
+                created: device.created,
+                last_seen: device.last_seen,
+            })
+            .collect())
+    }
+
+    async fn unlink_device(&self, device_id: u32) -> anyhow::Result<()> {
+        self.manager.unlink_secondary(device_id.into()).await?;
+        Ok(())
+    }
+}
+
+/// Converts an error of sending a message, detecting a captcha challenge or a rate limit
+///
+/// Challenges without a captcha, e.g. a push challenge, cannot be solved by a linked device, so
+/// they are handled like a rate limit.
+fn send_error<E>(error: presage::Error<E>) -> anyhow::Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    match error {
+        presage::Error::MessageSenderError(MessageSenderError::ProofRequired {
+            token,
+            options,
+        }) if options.iter().any(|option| option == "recaptcha") => {
+            CaptchaRequired { token }.into()
+        }
+        presage::Error::MessageSenderError(
+            MessageSenderError::ProofRequired { .. }
+            | MessageSenderError::ServiceError(ServiceError::RateLimitExceeded),
+        ) => RateLimited.into(),
+        error => error.into(),
+    }
 }
 
 async fn upload_attachments(
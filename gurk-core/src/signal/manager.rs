@@ -0,0 +1,218 @@
+//! Abstraction of a Signal client
+
+use std::fmt;
+use std::path::PathBuf;
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use presage::libsignal_service::content::Content;
+use presage::libsignal_service::sender::AttachmentSpec;
+use presage::model::contacts::Contact;
+use presage::model::groups::Group;
+use presage::proto::AttachmentPointer;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::AbortHandle;
+use tokio_stream::Stream;
+use uuid::Uuid;
+
+use crate::data::{Channel, GroupData, Message};
+use crate::receipt::Receipt;
+
+use super::{GroupMasterKeyBytes, ProfileKeyBytes};
+
+/// Abstract functionalities of Signal required by the app, that is, dependency inversion
+#[async_trait(?Send)]
+pub trait SignalManager {
+    fn clone_boxed(&self) -> Box<dyn SignalManager>;
+
+    fn user_id(&self) -> Uuid;
+
+    async fn resolve_group(
+        &mut self,
+        master_key_bytes: GroupMasterKeyBytes,
+    ) -> anyhow::Result<ResolvedGroup>;
+
+    async fn save_attachment(
+        &mut self,
+        attachment_pointer: AttachmentPointer,
+    ) -> anyhow::Result<Attachment>;
+
+    /// Attachment with the path it would be saved to by [`SignalManager::save_attachment`],
+    /// without downloading it
+    fn locate_attachment(
+        &self,
+        attachment_pointer: &AttachmentPointer,
+    ) -> anyhow::Result<Attachment>;
+
+    fn send_receipt(&self, sender_uuid: Uuid, timestamps: Vec<u64>, receipt: Receipt);
+
+    /// Sends the text to the channel
+    ///
+    /// The message is sent with the given `timestamp`, when resending a message, otherwise with
+    /// the current time.
+    fn send_text(
+        &self,
+        channel: &Channel,
+        text: String,
+        quote_message: Option<&Message>,
+        edit_message_timestamp: Option<u64>,
+        attachments: Vec<(AttachmentSpec, Vec<u8>)>,
+        timestamp: Option<u64>,
+    ) -> (Message, PendingSend);
+
+    fn send_reaction(&self, channel: &Channel, message: &Message, emoji: String, remove: bool);
+
+    async fn profile_name(&self, id: Uuid) -> Option<String>;
+
+    /// Resolves contact name from user's profile via Signal server
+    async fn resolve_profile_name(
+        &mut self,
+        id: Uuid,
+        profile_key: ProfileKeyBytes,
+    ) -> Option<String>;
+
+    /// Whether the user is registered on Signal
+    ///
+    /// Checked by retrieving the profile of the user, which requires their profile key. If no
+    /// profile key is given or stored, the user cannot be checked and `None` is returned.
+    async fn is_registered(
+        &self,
+        id: Uuid,
+        profile_key: Option<ProfileKeyBytes>,
+    ) -> anyhow::Result<Option<bool>>;
+
+    async fn request_contacts_sync(&self) -> anyhow::Result<()>;
+
+    async fn contact(&self, id: Uuid) -> Option<Contact>;
+
+    async fn receive_messages(&mut self) -> anyhow::Result<Pin<Box<dyn Stream<Item = Content>>>>;
+
+    async fn contacts(&self) -> Box<dyn Iterator<Item = Contact>>;
+    async fn groups(&self) -> Box<dyn Iterator<Item = (GroupMasterKeyBytes, Group)>>;
+
+    /// Join requests and pending invites of the group, as kept by the Signal store
+    async fn group_requests(
+        &self,
+        master_key_bytes: GroupMasterKeyBytes,
+    ) -> anyhow::Result<GroupRequests>;
+
+    /// Submits the solved captcha of a challenge, see [`CaptchaRequired`]
+    async fn submit_captcha(&self, token: &str, captcha: &str) -> anyhow::Result<()>;
+
+    /// Id of this device in the account
+    fn device_id(&self) -> u32;
+
+    /// All devices linked to the account, including the primary device and this one
+    async fn linked_devices(&self) -> anyhow::Result<Vec<LinkedDevice>>;
+
+    /// Unlinks the device from the account; its credentials become invalid
+    async fn unlink_device(&self, device_id: u32) -> anyhow::Result<()>;
+}
+
+/// Page for solving a captcha; on success, it links to `signalcaptcha://<captcha>`
+pub const CAPTCHA_URL: &str = "https://signalcaptchas.org/challenge/generate.html";
+
+/// Error of sending a message, when the server requires solving a captcha first
+///
+/// After the captcha is submitted with [`SignalManager::submit_captcha`], the message can be sent
+/// again.
+#[derive(Debug, thiserror::Error)]
+#[error("rate limited, a captcha must be solved to continue sending")]
+pub struct CaptchaRequired {
+    /// Token of the challenge
+    pub token: String,
+}
+
+/// Message being sent in the background, see [`SignalManager::send_text`]
+pub struct PendingSend {
+    /// Result of sending the message
+    pub response: oneshot::Receiver<anyhow::Result<()>>,
+    /// Steps of sending, reported when they are reached
+    pub progress: mpsc::UnboundedReceiver<SendProgress>,
+    /// Aborts sending, if it is still running
+    pub abort: Option<AbortHandle>,
+}
+
+impl PendingSend {
+    /// Sending which finished right away with the result
+    pub fn finished(result: anyhow::Result<()>) -> Self {
+        let (response_tx, response) = oneshot::channel();
+        let _ = response_tx.send(result);
+        Self {
+            response,
+            progress: mpsc::unbounded_channel().1,
+            abort: None,
+        }
+    }
+}
+
+/// Step of sending a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendProgress {
+    UploadingAttachments,
+    Sending,
+}
+
+impl fmt::Display for SendProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UploadingAttachments => write!(f, "uploading attachments"),
+            Self::Sending => write!(f, "sending"),
+        }
+    }
+}
+
+/// Error of sending a message, when the server rate limits sending without offering a captcha
+///
+/// The message can be sent again after waiting for a while.
+#[derive(Debug, thiserror::Error)]
+#[error("rate limited, sending again later")]
+pub struct RateLimited;
+
+/// Device linked to the account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkedDevice {
+    pub id: u32,
+    pub name: Option<String>,
+    pub created: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// Users waiting to become members of a group
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupRequests {
+    /// Whether our user is an admin of the group, and thus sees the requests
+    pub is_admin: bool,
+    /// Whether joining via the group link requires the approval of an admin
+    pub link_approval: bool,
+    /// Users who asked to join via the group link, oldest first
+    pub join_requests: Vec<GroupRequest>,
+    /// Users invited by a member who did not accept yet, oldest first
+    pub invites: Vec<GroupRequest>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupRequest {
+    pub user_id: Uuid,
+    /// Member who invited the user, for invites
+    pub added_by: Option<Uuid>,
+    /// Time of the request in milliseconds since epoch
+    pub timestamp: u64,
+}
+
+pub struct ResolvedGroup {
+    pub name: String,
+    pub group_data: GroupData,
+    pub profile_keys: Vec<ProfileKeyBytes>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Attachment {
+    pub id: String,
+    pub content_type: String,
+    pub filename: PathBuf,
+    pub size: u32,
+}
@@ -0,0 +1,21 @@
+//! Signal backend abstraction and its implementation based on presage
+
+mod attachment;
+mod r#impl;
+mod manager;
+pub mod test;
+
+pub use self::manager::{
+    Attachment, CaptchaRequired, GroupRequest, GroupRequests, LinkedDevice, PendingSend,
+    RateLimited, ResolvedGroup, SendProgress, SignalManager, CAPTCHA_URL,
+};
+pub use self::r#impl::PresageManager;
+
+// TODO: these should be either re-exported from presage/libsignal-service
+const PROFILE_KEY_LEN: usize = 32;
+const GROUP_MASTER_KEY_LEN: usize = 32;
+const GROUP_IDENTIFIER_LEN: usize = 32;
+
+pub type ProfileKeyBytes = [u8; PROFILE_KEY_LEN];
+pub type GroupMasterKeyBytes = [u8; GROUP_MASTER_KEY_LEN];
+pub type GroupIdentifierBytes = [u8; GROUP_IDENTIFIER_LEN];
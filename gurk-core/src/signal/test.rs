@@ -0,0 +1,307 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::pin::Pin;
+use std::{cell::RefCell, rc::Rc};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::DateTime;
+use presage::libsignal_service::content::Content;
+use presage::libsignal_service::prelude::AttachmentIdentifier;
+use presage::libsignal_service::sender::AttachmentSpec;
+use presage::model::contacts::Contact;
+use presage::model::groups::Group;
+use presage::proto::data_message::Quote;
+use presage::proto::AttachmentPointer;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::Stream;
+use uuid::Uuid;
+
+use crate::data::{Channel, GroupData, Message};
+use crate::receipt::Receipt;
+use crate::util::utc_now_timestamp_msec;
+
+use super::{
+    Attachment, CaptchaRequired, GroupMasterKeyBytes, GroupRequests, LinkedDevice, PendingSend,
+    ProfileKeyBytes, RateLimited, ResolvedGroup, SignalManager,
+};
+
+/// Id of the device of the mock
+const DEVICE_ID: u32 = 2;
+
+/// Signal manager mock which does not send any messages.
+pub struct SignalManagerMock {
+    user_id: Uuid,
+    pub sent_messages: Rc<RefCell<Vec<Message>>>,
+    /// If set, sending fails with [`CaptchaRequired`] with this token until a captcha is
+    /// submitted
+    pub captcha_token: Rc<RefCell<Option<String>>>,
+    /// If set, sending fails with [`RateLimited`]
+    pub rate_limited: Rc<RefCell<bool>>,
+    /// If set, sending fails with this error
+    pub send_error: Rc<RefCell<Option<String>>>,
+    /// Devices linked to the account: the primary device and this one
+    pub devices: Rc<RefCell<Vec<LinkedDevice>>>,
+    /// Users reported as not registered on Signal
+    pub unregistered: Rc<RefCell<BTreeSet<Uuid>>>,
+    /// Users whose registration cannot be checked, e.g. for lacking their profile key
+    pub unchecked: Rc<RefCell<BTreeSet<Uuid>>>,
+    /// Join requests and invites of groups; groups without an entry are not found
+    pub group_requests: Rc<RefCell<BTreeMap<GroupMasterKeyBytes, GroupRequests>>>,
+    /// Messages passed to [`SignalManagerMock::incoming`] are received, e.g. for scripting
+    /// conversations
+    incoming_tx: mpsc::UnboundedSender<Content>,
+    incoming: Rc<RefCell<Option<mpsc::UnboundedReceiver<Content>>>>,
+}
+
+impl SignalManagerMock {
+    pub fn new() -> Self {
+        let (incoming_tx, incoming) = mpsc::unbounded_channel();
+        Self {
+            user_id: Uuid::nil(),
+            sent_messages: Default::default(),
+            captcha_token: Default::default(),
+            rate_limited: Default::default(),
+            send_error: Default::default(),
+            devices: Rc::new(RefCell::new(vec![
+                linked_device(1, None),
+                linked_device(DEVICE_ID, Some("gurk")),
+            ])),
+            unregistered: Default::default(),
+            unchecked: Default::default(),
+            group_requests: Default::default(),
+            incoming_tx,
+            incoming: Rc::new(RefCell::new(Some(incoming))),
+        }
+    }
+
+    /// Sender of messages which are received by the stream of [`SignalManager::receive_messages`]
+    pub fn incoming(&self) -> mpsc::UnboundedSender<Content> {
+        self.incoming_tx.clone()
+    }
+}
+
+fn linked_device(id: u32, name: Option<&str>) -> LinkedDevice {
+    let created = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    LinkedDevice {
+        id,
+        name: name.map(ToString::to_string),
+        created,
+        last_seen: created,
+    }
+}
+
+impl Default for SignalManagerMock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait(?Send)]
+impl SignalManager for SignalManagerMock {
+    fn user_id(&self) -> Uuid {
+        self.user_id
+    }
+
+    async fn resolve_group(
+        &mut self,
+        master_key_bytes: super::GroupMasterKeyBytes,
+    ) -> anyhow::Result<ResolvedGroup> {
+        Ok(ResolvedGroup {
+            name: "some_group".to_string(),
+            group_data: GroupData {
+                master_key_bytes,
+                members: Default::default(),
+                revision: 0,
+            },
+            profile_keys: Default::default(),
+        })
+    }
+
+    async fn save_attachment(
+        &mut self,
+        attachment_pointer: AttachmentPointer,
+    ) -> anyhow::Result<Attachment> {
+        let id = match attachment_pointer.attachment_identifier.unwrap() {
+            AttachmentIdentifier::CdnId(id) => id.to_string(),
+            AttachmentIdentifier::CdnKey(id) => id,
+        };
+        Ok(Attachment {
+            id,
+            content_type: attachment_pointer.content_type.unwrap(),
+            filename: "somefile".to_string().into(),
+            size: attachment_pointer.size.unwrap(),
+        })
+    }
+
+    fn locate_attachment(
+        &self,
+        attachment_pointer: &AttachmentPointer,
+    ) -> anyhow::Result<Attachment> {
+        let id = match attachment_pointer.attachment_identifier.clone().unwrap() {
+            AttachmentIdentifier::CdnId(id) => id.to_string(),
+            AttachmentIdentifier::CdnKey(id) => id,
+        };
+        Ok(Attachment {
+            id,
+            content_type: attachment_pointer.content_type().to_string(),
+            filename: format!("not-downloaded-{}", attachment_pointer.file_name()).into(),
+            size: attachment_pointer.size.unwrap_or_default(),
+        })
+    }
+
+    fn send_receipt(&self, _: Uuid, _: Vec<u64>, _: Receipt) {}
+
+    fn send_text(
+        &self,
+        channel: &Channel,
+        text: String,
+        quote_message: Option<&Message>,
+        _edit_message_timestamp: Option<u64>,
+        _attachments: Vec<(AttachmentSpec, Vec<u8>)>,
+        timestamp: Option<u64>,
+    ) -> (Message, PendingSend) {
+        let message: String = crate::emoji::replace_shortcodes(&text).into_owned();
+        let (message, body_ranges) = channel
+            .expand_mention_all(&message, self.user_id())
+            .unwrap_or((message, Vec::new()));
+        let timestamp = timestamp.unwrap_or_else(utc_now_timestamp_msec);
+        let quote = quote_message.map(|message| Quote {
+            id: Some(message.arrived_at),
+            author_aci: Some(message.from_id.to_string()),
+            text: message.message.clone(),
+            ..Default::default()
+        });
+        let quote_message = quote.and_then(Message::from_quote).map(Box::new);
+        let message = Message {
+            from_id: self.user_id(),
+            message: Some(message),
+            arrived_at: timestamp,
+            quote: quote_message,
+            attachments: Default::default(),
+            reactions: Default::default(),
+            receipt: Receipt::Sent,
+            body_ranges,
+            send_failed: Default::default(),
+            edit: Default::default(),
+            edited: Default::default(),
+            forwarded: Default::default(),
+        };
+        let result = if let Some(token) = self.captcha_token.borrow().clone() {
+            Err(CaptchaRequired { token }.into())
+        } else if *self.rate_limited.borrow() {
+            Err(RateLimited.into())
+        } else if let Some(error) = self.send_error.borrow().clone() {
+            Err(anyhow::anyhow!(error))
+        } else {
+            self.sent_messages.borrow_mut().push(message.clone());
+            Ok(())
+        };
+        (message, PendingSend::finished(result))
+    }
+
+    fn send_reaction(&self, _channel: &Channel, _message: &Message, _emoji: String, _remove: bool) {
+    }
+
+    async fn resolve_profile_name(
+        &mut self,
+        _id: Uuid,
+        _profile_key: ProfileKeyBytes,
+    ) -> Option<String> {
+        None
+    }
+
+    async fn is_registered(
+        &self,
+        id: Uuid,
+        _profile_key: Option<ProfileKeyBytes>,
+    ) -> anyhow::Result<Option<bool>> {
+        if self.unchecked.borrow().contains(&id) {
+            return Ok(None);
+        }
+        Ok(Some(!self.unregistered.borrow().contains(&id)))
+    }
+
+    async fn request_contacts_sync(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn profile_name(&self, _id: Uuid) -> Option<String> {
+        None
+    }
+
+    async fn contact(&self, _id: Uuid) -> Option<Contact> {
+        None
+    }
+
+    /// Only the first stream receives the incoming messages; the ones after reconnecting are empty
+    async fn receive_messages(&mut self) -> anyhow::Result<Pin<Box<dyn Stream<Item = Content>>>> {
+        match self.incoming.borrow_mut().take() {
+            Some(incoming) => Ok(Box::pin(UnboundedReceiverStream::new(incoming))),
+            None => Ok(Box::pin(tokio_stream::empty())),
+        }
+    }
+
+    fn clone_boxed(&self) -> Box<dyn SignalManager> {
+        Box::new(Self {
+            user_id: self.user_id,
+            sent_messages: self.sent_messages.clone(),
+            captcha_token: self.captcha_token.clone(),
+            rate_limited: self.rate_limited.clone(),
+            send_error: self.send_error.clone(),
+            devices: self.devices.clone(),
+            unregistered: self.unregistered.clone(),
+            unchecked: self.unchecked.clone(),
+            group_requests: self.group_requests.clone(),
+            incoming_tx: self.incoming_tx.clone(),
+            incoming: self.incoming.clone(),
+        })
+    }
+
+    async fn contacts(&self) -> Box<dyn Iterator<Item = Contact>> {
+        Box::new(std::iter::empty())
+    }
+
+    async fn groups(&self) -> Box<dyn Iterator<Item = (GroupMasterKeyBytes, Group)>> {
+        Box::new(std::iter::empty())
+    }
+
+    async fn group_requests(
+        &self,
+        master_key_bytes: GroupMasterKeyBytes,
+    ) -> anyhow::Result<GroupRequests> {
+        self.group_requests
+            .borrow()
+            .get(&master_key_bytes)
+            .cloned()
+            .context("no group found")
+    }
+
+    async fn submit_captcha(&self, token: &str, _captcha: &str) -> anyhow::Result<()> {
+        let mut captcha_token = self.captcha_token.borrow_mut();
+        anyhow::ensure!(
+            captcha_token.as_deref() == Some(token),
+            "unknown captcha token"
+        );
+        *captcha_token = None;
+        Ok(())
+    }
+
+    fn device_id(&self) -> u32 {
+        DEVICE_ID
+    }
+
+    async fn linked_devices(&self) -> anyhow::Result<Vec<LinkedDevice>> {
+        Ok(self.devices.borrow().clone())
+    }
+
+    async fn unlink_device(&self, device_id: u32) -> anyhow::Result<()> {
+        let mut devices = self.devices.borrow_mut();
+        let idx = devices
+            .iter()
+            .position(|device| device.id == device_id)
+            .context("unknown device")?;
+        devices.remove(idx);
+        Ok(())
+    }
+}
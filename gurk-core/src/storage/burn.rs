@@ -0,0 +1,289 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use itertools::Itertools;
+use uuid::Uuid;
+
+use crate::data::{Channel, ChannelId, Message};
+
+use super::{
+    is_arrived_before, is_message_or_edit, last_messages_before, limit_cutoff,
+    matches_search_query, newest_message_ids, MemoryUsage, MessageId, Metadata, Storage,
+};
+
+/// Keeps the messages of burn-after-reading channels in memory only
+///
+/// All other data is stored in the underlying storage. Messages which were persisted before the
+/// channel was marked as burn-after-reading are still read from the underlying storage.
+pub struct BurnAfterReading<S: Storage> {
+    /// Messages and edits of burn-after-reading channels
+    messages: BTreeMap<MessageId, Message>,
+    storage: S,
+}
+
+impl<S: Storage> BurnAfterReading<S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            messages: Default::default(),
+            storage,
+        }
+    }
+
+    fn is_burn_after_reading(&self, channel_id: ChannelId) -> bool {
+        self.storage
+            .channel(channel_id)
+            .is_some_and(|channel| channel.burn_after_reading)
+    }
+
+    /// Messages and edits kept in memory for the given channel, sorted by arrived_at
+    fn in_memory(&self, channel_id: ChannelId) -> impl DoubleEndedIterator<Item = &Message> {
+        self.messages
+            .range(MessageId::new(channel_id, 0)..=MessageId::new(channel_id, u64::MAX))
+            .map(|(_, message)| message)
+    }
+
+    /// Merges persisted messages with the ones kept in memory
+    ///
+    /// A message kept in memory replaces a persisted message with the same arrived_at.
+    fn merge<'a>(
+        persisted: Box<dyn DoubleEndedIterator<Item = Cow<'a, Message>> + 'a>,
+        in_memory: impl Iterator<Item = &'a Message>,
+    ) -> Box<dyn DoubleEndedIterator<Item = Cow<'a, Message>> + 'a> {
+        let mut in_memory = in_memory.peekable();
+        if in_memory.peek().is_none() {
+            return persisted;
+        }
+        let merged: Vec<_> = persisted
+            .merge_join_by(in_memory.map(Cow::Borrowed), |a, b| {
+                a.arrived_at.cmp(&b.arrived_at)
+            })
+            .map(|message| message.reduce(|_persisted, in_memory| in_memory))
+            .collect();
+        Box::new(merged.into_iter())
+    }
+}
+
+impl<S: Storage> Storage for BurnAfterReading<S> {
+    fn channels(&self) -> Box<dyn Iterator<Item = Cow<Channel>> + '_> {
+        self.storage.channels()
+    }
+
+    fn channel(&self, channel_id: ChannelId) -> Option<Cow<Channel>> {
+        self.storage.channel(channel_id)
+    }
+
+    fn store_channel(&mut self, channel: Channel) -> Cow<Channel> {
+        self.storage.store_channel(channel)
+    }
+
+    fn messages(
+        &self,
+        channel_id: ChannelId,
+    ) -> Box<dyn DoubleEndedIterator<Item = Cow<Message>> + '_> {
+        Self::merge(
+            self.storage.messages(channel_id),
+            self.in_memory(channel_id)
+                .filter(|message| message.edit.is_none()),
+        )
+    }
+
+    fn messages_before(
+        &self,
+        channel_id: ChannelId,
+        arrived_at: u64,
+        limit: usize,
+    ) -> Vec<Cow<Message>> {
+        if self.in_memory(channel_id).next().is_none() {
+            return self.storage.messages_before(channel_id, arrived_at, limit);
+        }
+        last_messages_before(self.messages(channel_id), arrived_at, limit)
+    }
+
+    fn limit_cutoff(&self, channel_id: ChannelId, limit: usize) -> Option<u64> {
+        if self.in_memory(channel_id).next().is_none() {
+            return self.storage.limit_cutoff(channel_id, limit);
+        }
+        limit_cutoff(self.messages(channel_id), limit)
+    }
+
+    fn message(&self, message_id: MessageId) -> Option<Cow<Message>> {
+        match self.messages.get(&message_id) {
+            Some(message) => Some(Cow::Borrowed(message)),
+            None => self.storage.message(message_id),
+        }
+    }
+
+    fn message_channel(&self, arrived_at: u64) -> Option<ChannelId> {
+        self.storage.message_channel(arrived_at).or_else(|| {
+            self.messages
+                .keys()
+                .find(|message_id| message_id.arrived_at == arrived_at)
+                .map(|message_id| message_id.channel_id)
+        })
+    }
+
+    fn edits(
+        &self,
+        message_id: MessageId,
+    ) -> Box<dyn DoubleEndedIterator<Item = Cow<Message>> + '_> {
+        Self::merge(
+            self.storage.edits(message_id),
+            self.in_memory(message_id.channel_id)
+                .filter(move |message| message.edit == Some(message_id.arrived_at)),
+        )
+    }
+
+    fn search_messages(&self, query: &str, limit: usize) -> Vec<MessageId> {
+        let in_memory = self
+            .messages
+            .iter()
+            .filter(|(_, message)| !message.is_edit() && matches_search_query(message, query))
+            .map(|(&message_id, _)| message_id);
+        let persisted = self.storage.search_messages(query, limit);
+        newest_message_ids(in_memory.chain(persisted), limit)
+    }
+
+    fn failed_messages(&self) -> Vec<MessageId> {
+        // messages kept in memory only are gone after a restart anyway
+        self.storage.failed_messages()
+    }
+
+    fn store_message(&mut self, channel_id: ChannelId, message: Message) -> Cow<Message> {
+        if !self.is_burn_after_reading(channel_id) {
+            return self.storage.store_message(channel_id, message);
+        }
+        let message_id = MessageId::new(channel_id, message.arrived_at);
+        self.messages.insert(message_id, message);
+        Cow::Borrowed(&self.messages[&message_id])
+    }
+
+    fn store_new_message(
+        &mut self,
+        channel_id: ChannelId,
+        message: Message,
+    ) -> Option<Cow<Message>> {
+        if !self.is_burn_after_reading(channel_id) {
+            return self.storage.store_new_message(channel_id, message);
+        }
+        let message_id = MessageId::new(channel_id, message.arrived_at);
+        if self.message(message_id).is_some() {
+            return None;
+        }
+        Some(self.store_message(channel_id, message))
+    }
+
+    fn delete_messages_before(&mut self, channel_id: ChannelId, arrived_at: u64) {
+        self.messages.retain(|message_id, message| {
+            message_id.channel_id != channel_id || !is_arrived_before(message, arrived_at)
+        });
+        self.storage.delete_messages_before(channel_id, arrived_at);
+    }
+
+    fn delete_message(&mut self, message_id: MessageId) {
+        self.messages.retain(|id, message| {
+            id.channel_id != message_id.channel_id
+                || !is_message_or_edit(message, message_id.arrived_at)
+        });
+        self.storage.delete_message(message_id);
+    }
+
+    fn forget_messages(&mut self, channel_id: ChannelId) {
+        self.messages
+            .retain(|message_id, _| message_id.channel_id != channel_id);
+        self.storage.forget_messages(channel_id);
+    }
+
+    fn messages_in_memory(&self, channel_id: ChannelId) -> MemoryUsage {
+        MemoryUsage::of(self.in_memory(channel_id)) + self.storage.messages_in_memory(channel_id)
+    }
+
+    fn names(&self) -> Box<dyn Iterator<Item = (Uuid, Cow<str>)> + '_> {
+        self.storage.names()
+    }
+
+    fn name(&self, id: Uuid) -> Option<Cow<str>> {
+        self.storage.name(id)
+    }
+
+    fn store_name(&mut self, id: Uuid, name: String) -> Cow<str> {
+        self.storage.store_name(id, name)
+    }
+
+    fn metadata(&self) -> Cow<Metadata> {
+        self.storage.metadata()
+    }
+
+    fn store_metadata(&mut self, metadata: Metadata) -> Cow<Metadata> {
+        self.storage.store_metadata(metadata)
+    }
+
+    fn save(&mut self) -> anyhow::Result<()> {
+        self.storage.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::TypingSet;
+    use crate::receipt::Receipt;
+    use crate::storage::{ForgetfulStorage, MemCache};
+
+    use super::*;
+
+    fn test_message(arrived_at: u64, text: &str) -> Message {
+        Message {
+            from_id: Uuid::nil(),
+            message: Some(text.to_string()),
+            arrived_at,
+            quote: None,
+            attachments: Default::default(),
+            reactions: Default::default(),
+            receipt: Receipt::Sent,
+            body_ranges: Default::default(),
+            send_failed: Default::default(),
+            edit: Default::default(),
+            edited: Default::default(),
+            forwarded: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_messages_of_burn_after_reading_channels_are_not_persisted() {
+        let channel_id = ChannelId::User(Uuid::new_v4());
+        let mut storage = BurnAfterReading::new(MemCache::new(ForgetfulStorage));
+        storage.store_channel(Channel {
+            id: channel_id,
+            name: "secret".to_string(),
+            group_data: None,
+            unread_messages: 0,
+            typing: TypingSet::new(false),
+            muted_until: None,
+            pinned: false,
+            archived: false,
+            burn_after_reading: false,
+            max_messages: None,
+            ignore_mention_all: false,
+            notification_content: None,
+            nickname: None,
+        });
+        storage.store_message(channel_id, test_message(1, "persisted"));
+
+        let mut channel = storage.channel(channel_id).unwrap().into_owned();
+        channel.burn_after_reading = true;
+        storage.store_channel(channel);
+        storage.store_message(channel_id, test_message(2, "burned"));
+
+        // only the first message reached the underlying storage
+        assert_eq!(storage.storage.messages(channel_id).count(), 1);
+        let texts: Vec<_> = storage
+            .messages(channel_id)
+            .map(|message| message.message.clone().unwrap())
+            .collect();
+        assert_eq!(texts, ["persisted", "burned"]);
+        assert!(storage.message(MessageId::new(channel_id, 2)).is_some());
+
+        storage.forget_messages(channel_id);
+        assert_eq!(storage.messages(channel_id).count(), 1);
+        assert!(storage.message(MessageId::new(channel_id, 2)).is_none());
+    }
+}
@@ -60,6 +60,14 @@ pub async fn sync_from_signal(manager: &dyn SignalManager, storage: &mut dyn Sto
                 group_data: None,
                 unread_messages: 0,
                 typing: TypingSet::new(false),
+                muted_until: None,
+                pinned: false,
+                archived: false,
+                burn_after_reading: false,
+                max_messages: None,
+                ignore_mention_all: false,
+                notification_content: None,
+                nickname: None,
             });
         }
     }
@@ -120,6 +128,14 @@ pub async fn sync_from_signal(manager: &dyn SignalManager, storage: &mut dyn Sto
                     group_data: Some(new_group_data()),
                     unread_messages: 0,
                     typing: TypingSet::new(true),
+                    muted_until: None,
+                    pinned: false,
+                    archived: false,
+                    burn_after_reading: false,
+                    max_messages: None,
+                    ignore_mention_all: false,
+                    notification_content: None,
+                    nickname: None,
                 });
             }
         }
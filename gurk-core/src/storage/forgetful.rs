@@ -44,6 +44,10 @@ impl Storage for ForgetfulStorage {
         Cow::Owned(message)
     }
 
+    fn delete_messages_before(&mut self, _channel_id: ChannelId, _arrived_at: u64) {}
+
+    fn delete_message(&mut self, _message_id: MessageId) {}
+
     fn names(&self) -> Box<dyn Iterator<Item = (Uuid, Cow<str>)> + '_> {
         Box::new(std::iter::empty())
     }
@@ -64,7 +68,9 @@ impl Storage for ForgetfulStorage {
         Cow::Owned(metadata)
     }
 
-    fn save(&mut self) {}
+    fn save(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
 
     fn message_channel(&self, _arrived_at: u64) -> Option<ChannelId> {
         None
@@ -1,19 +1,34 @@
 use std::borrow::Cow;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::io::{BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use tracing::{error, info};
+use serde_json::Value;
+use tracing::{info, warn};
 use uuid::Uuid;
 
-use crate::data::{Channel, ChannelId, GroupData, Message, TypingSet};
+use crate::data::{Channel, ChannelId, GroupData, Message, NotificationContent, TypingSet};
 
-use super::{MessageId, Metadata, Storage};
+use super::{
+    is_arrived_before, is_message_or_edit, ContactNotes, LocalContact, MemoryUsage, MessageId,
+    Metadata, RecentFile, Storage,
+};
+
+/// Version of the format of the stored data
+///
+/// Increment it when adding a migration to `MIGRATIONS`.
+const DATA_VERSION: u64 = 1;
+
+/// Migration of the stored data from version `idx` to version `idx + 1`
+type Migration = fn(&mut Value) -> anyhow::Result<()>;
+
+const MIGRATIONS: [Migration; DATA_VERSION as usize] = [migrate_group_ids];
 
 pub struct JsonStorage {
     data_path: PathBuf,
@@ -23,16 +38,25 @@ pub struct JsonStorage {
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 struct JsonStorageData {
+    /// Version of the format, see `DATA_VERSION`
+    #[serde(default)]
+    version: u64,
     channels: JsonChannels,
     /// Names retrieved from:
     /// - profiles, when registered as main device)
     /// - contacts, when linked as secondary device
     /// - UUID when both have failed
     ///
-    /// Do not use directly, use [`App::name_by_id`] instead.
+    /// Do not use directly, use `App::name_by_id` of the TUI instead.
     names: HashMap<Uuid, String>,
     #[serde(default)]
     contacts_sync_request_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    recent_files: Vec<RecentFile>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    contact_notes: BTreeMap<Uuid, ContactNotes>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    contacts: BTreeMap<Uuid, LocalContact>,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -54,6 +78,22 @@ pub struct JsonChannel {
     unread_messages: u32,
     #[serde(skip)]
     typing: Option<TypingSet>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    muted_until: Option<u64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pinned: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    archived: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    burn_after_reading: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_messages: Option<usize>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    ignore_mention_all: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notification_content: Option<NotificationContent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nickname: Option<String>,
 }
 
 impl From<&JsonChannel> for Channel {
@@ -68,6 +108,14 @@ impl From<&JsonChannel> for Channel {
                 .typing
                 .clone()
                 .unwrap_or_else(|| TypingSet::new(is_group)),
+            muted_until: channel.muted_until,
+            pinned: channel.pinned,
+            archived: channel.archived,
+            burn_after_reading: channel.burn_after_reading,
+            max_messages: channel.max_messages,
+            ignore_mention_all: channel.ignore_mention_all,
+            notification_content: channel.notification_content,
+            nickname: channel.nickname.clone(),
         }
     }
 }
@@ -81,6 +129,14 @@ impl From<(Channel, Vec<Message>)> for JsonChannel {
             messages,
             unread_messages: channel.unread_messages,
             typing: Some(channel.typing),
+            muted_until: channel.muted_until,
+            pinned: channel.pinned,
+            archived: channel.archived,
+            burn_after_reading: channel.burn_after_reading,
+            max_messages: channel.max_messages,
+            ignore_mention_all: channel.ignore_mention_all,
+            notification_content: channel.notification_content,
+            nickname: channel.nickname.clone(),
         }
     }
 }
@@ -88,7 +144,7 @@ impl From<(Channel, Vec<Message>)> for JsonChannel {
 impl JsonStorage {
     pub fn new(data_path: &Path, fallback_data_path: Option<&Path>) -> anyhow::Result<Self> {
         let mut data_path = data_path;
-        if !data_path.exists() {
+        if !data_path.exists() && !with_suffix(data_path, ".bak").exists() {
             // try also to load from a fallback (legacy) data path
             if let Some(fallback_data_path) = fallback_data_path.as_ref() {
                 data_path = fallback_data_path;
@@ -96,8 +152,9 @@ impl JsonStorage {
         }
 
         // if data file exists, be conservative and fail rather than overriding and losing the messages
-        let mut data = if data_path.exists() {
-            Self::load_data_from(data_path).with_context(|| {
+        let backup_path = with_suffix(data_path, ".bak");
+        let mut data = if data_path.exists() || backup_path.exists() {
+            Self::load_data_or_backup(data_path, &backup_path).with_context(|| {
                 format!(
                     "failed to load stored data from '{}':\n\
             This might happen due to incompatible data model when Gurk is upgraded.\n\
@@ -106,22 +163,13 @@ impl JsonStorage {
                 )
             })?
         } else {
-            Self::load_data_from(data_path).unwrap_or_default()
+            JsonStorageData {
+                version: DATA_VERSION,
+                ..Default::default()
+            }
         };
 
         for channel in &mut data.channels.items {
-            // migration:
-            // The master key in ChannelId::Group was replaced by group identifier,
-            // the former was stored in group_data.
-            match (channel.id, channel.group_data.as_mut()) {
-                (ChannelId::Group(id), Some(group_data))
-                    if group_data.master_key_bytes == [0; 32] =>
-                {
-                    group_data.master_key_bytes = id;
-                    channel.id = ChannelId::from_master_key_bytes(id)?;
-                }
-                _ => (),
-            }
             // invariant: messages are sorted by arrived_at
             channel.messages.sort_unstable_by_key(|msg| msg.arrived_at);
         }
@@ -136,20 +184,147 @@ impl JsonStorage {
     fn load_data_from(data_path: &Path) -> anyhow::Result<JsonStorageData> {
         info!("loading app data from: {}", data_path.display());
         let f = BufReader::new(File::open(data_path)?);
-        Ok(serde_json::from_reader(f)?)
+        let mut data: Value = serde_json::from_reader(f)?;
+        migrate(&mut data)?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    /// Loads the data, or the backup of the previous save if the data is missing or unreadable
+    ///
+    /// The data is unreadable if it was truncated outside of `try_save`, e.g. by an older version.
+    fn load_data_or_backup(
+        data_path: &Path,
+        backup_path: &Path,
+    ) -> anyhow::Result<JsonStorageData> {
+        match Self::load_data_from(data_path) {
+            Ok(data) => Ok(data),
+            Err(error) if backup_path.exists() => {
+                warn!(%error, "failed to load app data, falling back to the backup");
+                Self::load_data_from(backup_path).map_err(|_| error)
+            }
+            Err(error) => Err(error),
+        }
     }
 
+    /// Writes the data to a temporary file, which atomically replaces the data file
+    ///
+    /// The previous data file is kept as `.bak` by linking it before the replacement, so that the
+    /// data file exists at any time and a crash while saving never leaves behind a truncated one.
     fn try_save(&mut self) -> anyhow::Result<()> {
         if self.is_dirty {
             info!("saving app data to: {}", self.data_path.display());
-            let f = BufWriter::new(File::create(&self.data_path)?);
-            serde_json::to_writer(f, &self.data)?;
+            let tmp_path = with_suffix(&self.data_path, ".tmp");
+            let mut f = BufWriter::new(File::create(&tmp_path)?);
+            serde_json::to_writer(&mut f, &self.data)?;
+            f.flush()?;
+            f.get_ref().sync_all()?;
+            drop(f);
+
+            if self.data_path.exists() {
+                keep_backup(&self.data_path, &with_suffix(&self.data_path, ".bak"))?;
+            }
+            std::fs::rename(&tmp_path, &self.data_path)?;
+            sync_parent_dir(&self.data_path);
             self.is_dirty = false;
         }
         Ok(())
     }
 }
 
+/// Upgrades the stored data to the current version of the format
+///
+/// Data without version is from before the versioning, i.e. version 0. Data of a newer version
+/// cannot be downgraded.
+fn migrate(data: &mut Value) -> anyhow::Result<()> {
+    let version = match data.get("version") {
+        Some(version) => version.as_u64().context("invalid data version")?,
+        None => 0,
+    };
+    if version > DATA_VERSION {
+        bail!(
+            "data version {version} is newer than the supported version {DATA_VERSION}; \
+            it was saved by a newer version of gurk"
+        );
+    }
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        info!(from, to = from + 1, "migrating app data");
+        migration(data).with_context(|| format!("failed to migrate data version {from}"))?;
+    }
+    data.as_object_mut()
+        .context("invalid data")?
+        .insert("version".to_string(), DATA_VERSION.into());
+    Ok(())
+}
+
+/// The master key in `ChannelId::Group` was replaced by the group identifier; the master key is
+/// stored in the group data
+fn migrate_group_ids(data: &mut Value) -> anyhow::Result<()> {
+    let channels = data
+        .pointer_mut("/channels/items")
+        .and_then(Value::as_array_mut);
+    for channel in channels.into_iter().flatten() {
+        let Some(master_key) = channel.pointer("/id/Group").cloned() else {
+            continue;
+        };
+        let Some(group_data) = channel.get_mut("group_data").and_then(Value::as_object_mut) else {
+            continue;
+        };
+        let master_key_bytes: [u8; 32] = match group_data.get("master_key_bytes") {
+            Some(bytes) => serde_json::from_value(bytes.clone())?,
+            None => [0; 32],
+        };
+        if master_key_bytes != [0; 32] {
+            continue;
+        }
+        let master_key: [u8; 32] = serde_json::from_value(master_key)?;
+        group_data.insert(
+            "master_key_bytes".to_string(),
+            serde_json::to_value(master_key)?,
+        );
+        channel["id"] = serde_json::to_value(ChannelId::from_master_key_bytes(master_key)?)?;
+    }
+    Ok(())
+}
+
+/// Path with the suffix appended to the file name, e.g. `gurk.data.json.bak`
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().map(OsString::from).unwrap_or_default();
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
+
+/// Replaces the backup with the file, which stays in place
+///
+/// The file is hard linked, or copied where the file system does not support hard links.
+fn keep_backup(path: &Path, backup_path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(backup_path) {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => return Err(error),
+    }
+    if std::fs::hard_link(path, backup_path).is_err() {
+        std::fs::copy(path, backup_path)?;
+    }
+    Ok(())
+}
+
+/// Persists the renames in the directory of the path
+///
+/// Directories cannot be opened for syncing on Windows, where renames are durable anyway.
+fn sync_parent_dir(path: &Path) {
+    #[cfg(unix)]
+    {
+        let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) else {
+            return;
+        };
+        if let Err(error) = File::open(dir).and_then(|dir| dir.sync_all()) {
+            warn!(%error, dir =% dir.display(), "failed to sync data directory");
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
 impl Storage for JsonStorage {
     fn channels(&self) -> Box<dyn Iterator<Item = Cow<Channel>> + '_> {
         Box::new(
@@ -283,6 +458,36 @@ impl Storage for JsonStorage {
         Cow::Borrowed(&self.data.channels.items[channel_idx].messages[idx])
     }
 
+    fn delete_messages_before(&mut self, channel_id: ChannelId, arrived_at: u64) {
+        if let Some(channel) = self
+            .data
+            .channels
+            .items
+            .iter_mut()
+            .find(|ch| ch.id == channel_id)
+        {
+            channel
+                .messages
+                .retain(|message| !is_arrived_before(message, arrived_at));
+            self.is_dirty = true;
+        }
+    }
+
+    fn delete_message(&mut self, message_id: MessageId) {
+        if let Some(channel) = self
+            .data
+            .channels
+            .items
+            .iter_mut()
+            .find(|ch| ch.id == message_id.channel_id)
+        {
+            channel
+                .messages
+                .retain(|message| !is_message_or_edit(message, message_id.arrived_at));
+            self.is_dirty = true;
+        }
+    }
+
     fn names(&self) -> Box<dyn Iterator<Item = (Uuid, Cow<str>)> + '_> {
         Box::new(
             self.data
@@ -317,6 +522,9 @@ impl Storage for JsonStorage {
         Cow::Owned(Metadata {
             contacts_sync_request_at: self.data.contacts_sync_request_at,
             fully_migrated: None,
+            recent_files: self.data.recent_files.clone(),
+            contact_notes: self.data.contact_notes.clone(),
+            contacts: self.data.contacts.clone(),
         })
     }
 
@@ -324,16 +532,30 @@ impl Storage for JsonStorage {
         let Metadata {
             contacts_sync_request_at,
             fully_migrated: _unsupported_in_json,
+            ref recent_files,
+            ref contact_notes,
+            ref contacts,
         } = metadata;
         self.data.contacts_sync_request_at = contacts_sync_request_at;
+        self.data.recent_files.clone_from(recent_files);
+        self.data.contact_notes.clone_from(contact_notes);
+        self.data.contacts.clone_from(contacts);
         self.is_dirty = true;
         Cow::Owned(metadata)
     }
 
-    fn save(&mut self) {
-        if let Err(e) = self.try_save() {
-            error!(error =% e, "failed to save json storage");
-        }
+    fn save(&mut self) -> anyhow::Result<()> {
+        self.try_save().context("failed to save json storage")
+    }
+
+    fn messages_in_memory(&self, channel_id: ChannelId) -> MemoryUsage {
+        let channel = self
+            .data
+            .channels
+            .items
+            .iter()
+            .find(|channel| channel.id == channel_id);
+        MemoryUsage::of(channel.into_iter().flat_map(|channel| &channel.messages))
     }
 
     fn message_channel(&self, arrived_at: u64) -> Option<ChannelId> {
@@ -378,9 +600,18 @@ mod tests {
                 send_failed: Default::default(),
                 edit: Default::default(),
                 edited: Default::default(),
+                forwarded: Default::default(),
             }],
             unread_messages: 1,
             typing: Some(TypingSet::SingleTyping(false)),
+            muted_until: None,
+            pinned: false,
+            archived: false,
+            burn_after_reading: false,
+            max_messages: None,
+            ignore_mention_all: false,
+            notification_content: None,
+            nickname: None,
         };
         let channel2 = JsonChannel {
             id: ChannelId::Group(*b"4149b9686807fdb4a8c95d9b5413bbcd"),
@@ -398,15 +629,25 @@ mod tests {
                 send_failed: Default::default(),
                 edit: Default::default(),
                 edited: Default::default(),
+                forwarded: Default::default(),
             }],
             unread_messages: 2,
             typing: Some(TypingSet::GroupTyping(Default::default())),
+            muted_until: None,
+            pinned: false,
+            archived: false,
+            burn_after_reading: false,
+            max_messages: None,
+            ignore_mention_all: false,
+            notification_content: None,
+            nickname: None,
         };
         let names = [
             (user_id1, "ellie".to_string()),
             (user_id2, "joel".to_string()),
         ];
         let data = JsonStorageData {
+            version: DATA_VERSION,
             channels: JsonChannels {
                 items: vec![channel1, channel2],
             },
@@ -414,6 +655,9 @@ mod tests {
             contacts_sync_request_at: DateTime::parse_from_rfc3339("2000-01-01T00:00:00Z")
                 .ok()
                 .map(|dt| dt.with_timezone(&Utc)),
+            recent_files: Default::default(),
+            contact_notes: Default::default(),
+            contacts: Default::default(),
         };
 
         let mut settings = insta::Settings::clone_current();
@@ -425,7 +669,7 @@ mod tests {
 
     fn json_storage_from_snapshot() -> impl Storage {
         let json =
-            include_str!("snapshots/gurk__storage__json__tests__json_storage_data_model.snap")
+            include_str!("snapshots/gurk_core__storage__json__tests__json_storage_data_model.snap")
                 .rsplit("---")
                 .next()
                 .unwrap();
@@ -478,6 +722,14 @@ mod tests {
             group_data: None,
             unread_messages: 42,
             typing: TypingSet::SingleTyping(false),
+            muted_until: None,
+            pinned: false,
+            archived: false,
+            burn_after_reading: false,
+            max_messages: None,
+            ignore_mention_all: false,
+            notification_content: None,
+            nickname: None,
         });
         let channels: Vec<_> = storage.channels().collect();
         assert_eq!(channels.len(), 3);
@@ -561,6 +813,7 @@ mod tests {
                 send_failed: Default::default(),
                 edit: Default::default(),
                 edited: Default::default(),
+                forwarded: Default::default(),
             },
         );
 
@@ -573,6 +826,24 @@ mod tests {
         assert_eq!(messages[1].message.as_deref(), Some("new msg"));
     }
 
+    #[test]
+    fn test_json_storage_store_new_message_deduplicated() {
+        let mut storage = json_storage_from_snapshot();
+        let channel_id = uuid!("966960e0-a8cd-43f1-ac7a-2c986dd470cd").into();
+        let from_id = uuid!("a955d20f-6b83-4e69-846e-a99b1779ff7a");
+        let arrived_at = 1664832050000;
+        let replayed = Message::text(from_id, arrived_at, "replayed".to_owned());
+        assert_eq!(storage.store_new_message(channel_id, replayed), None);
+        let message = storage
+            .message(MessageId::new(channel_id, arrived_at))
+            .unwrap();
+        assert_eq!(message.message.as_deref(), Some("hello"));
+
+        let new = Message::text(from_id, arrived_at + 2, "new".to_owned());
+        assert!(storage.store_new_message(channel_id, new).is_some());
+        assert_eq!(storage.messages(channel_id).count(), 2);
+    }
+
     #[test]
     fn test_json_storage_names() {
         let mut storage = json_storage_from_snapshot();
@@ -605,6 +876,9 @@ mod tests {
                 .store_metadata(Metadata {
                     contacts_sync_request_at: Some(dt),
                     fully_migrated: None,
+                    recent_files: Default::default(),
+                    contact_notes: Default::default(),
+                    contacts: Default::default(),
                 })
                 .contacts_sync_request_at,
             Some(dt)
@@ -624,4 +898,72 @@ mod tests {
         assert_eq!(storage.message_channel(1664832050004), Some(channel_id));
         assert_eq!(storage.message_channel(0), None);
     }
+
+    #[test]
+    fn test_json_storage_save_keeps_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_path = dir.path().join("gurk.data.json");
+        let backup_path = dir.path().join("gurk.data.json.bak");
+        let user_id = uuid!("966960e0-a8cd-43f1-ac7a-2c986dd470cd");
+
+        let mut storage = JsonStorage::new(&data_path, None).unwrap();
+        storage.store_name(user_id, "ellie".to_string());
+        storage.save().unwrap();
+        assert!(data_path.exists());
+        assert!(!backup_path.exists());
+
+        storage.store_name(user_id, "joel".to_string());
+        storage.save().unwrap();
+        assert!(!dir.path().join("gurk.data.json.tmp").exists());
+        let storage = JsonStorage::new(&data_path, None).unwrap();
+        assert_eq!(storage.name(user_id).unwrap(), "joel");
+
+        // a truncated data file falls back to the previous save
+        std::fs::write(&data_path, "{\"channels\":").unwrap();
+        let storage = JsonStorage::new(&data_path, None).unwrap();
+        assert_eq!(storage.name(user_id).unwrap(), "ellie");
+
+        // so does a missing data file
+        std::fs::remove_file(&data_path).unwrap();
+        let storage = JsonStorage::new(&data_path, None).unwrap();
+        assert_eq!(storage.name(user_id).unwrap(), "ellie");
+    }
+
+    #[test]
+    fn test_json_storage_migrate() {
+        let master_key = [1u8; 32];
+        let mut data = serde_json::json!({
+            "channels": {
+                "items": [{
+                    "id": { "Group": master_key },
+                    "name": "group-channel",
+                    "group_data": { "members": [], "revision": 1 },
+                    "messages": [],
+                }],
+            },
+            "names": {},
+        });
+        migrate(&mut data).unwrap();
+        let data: JsonStorageData = serde_json::from_value(data).unwrap();
+        assert_eq!(data.version, DATA_VERSION);
+        let channel = &data.channels.items[0];
+        assert_eq!(
+            channel.id,
+            ChannelId::from_master_key_bytes(master_key).unwrap()
+        );
+        assert_eq!(
+            channel.group_data.as_ref().unwrap().master_key_bytes,
+            master_key
+        );
+
+        // migrated data is left as is
+        let mut value = serde_json::to_value(&data).unwrap();
+        migrate(&mut value).unwrap();
+        let migrated: JsonStorageData = serde_json::from_value(value).unwrap();
+        assert_eq!(migrated.channels.items[0].id, channel.id);
+
+        let mut newer = serde_json::json!({ "version": DATA_VERSION + 1 });
+        let error = migrate(&mut newer).unwrap_err();
+        assert!(error.to_string().contains("newer version of gurk"));
+    }
 }
@@ -0,0 +1,458 @@
+use std::borrow::Cow;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::iter;
+
+use uuid::Uuid;
+
+use crate::data::{Channel, ChannelId, Message};
+
+use super::{
+    is_arrived_before, is_message_or_edit, last_messages_before, matches_search_query,
+    newest_message_ids, MemoryUsage, MessageId, Metadata, Storage,
+};
+
+/// Caches the data of the underlying Storage in memory
+///
+/// The following data is NOT cached:
+///
+/// * edits
+/// * messages older than the last `message_limit` messages of a channel, if limited
+///
+/// When limited, the oldest cached messages of a channel are dropped from the cache as new
+/// messages arrive, so that the memory used by a channel stays bounded.
+pub struct MemCache<S: Storage> {
+    channels: Vec<Channel>,
+    channels_index: BTreeMap<ChannelId, usize>,
+    messages: BTreeMap<ChannelId, Vec<Message>>,
+    messages_index: BTreeMap<MessageId, usize>,
+    /// Arrival time of the oldest cached message of channels with older messages in storage
+    truncated: BTreeMap<ChannelId, u64>,
+    message_limit: Option<usize>,
+    names: BTreeMap<Uuid, String>,
+    metadata: Metadata,
+    storage: S,
+}
+
+impl<S: Storage> MemCache<S> {
+    pub fn new(storage: S) -> Self {
+        Self::load(storage, None)
+    }
+
+    /// Caches only the last `limit` messages of each channel
+    ///
+    /// Older messages are read from the underlying storage on demand.
+    pub fn with_message_limit(storage: S, limit: usize) -> Self {
+        Self::load(storage, Some(limit.max(1)))
+    }
+
+    fn load(storage: S, message_limit: Option<usize>) -> Self {
+        let mut channels: Vec<Channel> = Vec::new();
+        let mut channels_index = BTreeMap::new();
+        let mut messages: BTreeMap<ChannelId, Vec<Message>> = BTreeMap::new();
+        let mut messages_index: BTreeMap<MessageId, usize> = BTreeMap::new();
+        let mut truncated = BTreeMap::new();
+
+        // build in-memory cache
+        for channel in storage.channels() {
+            let channel_messages = messages.entry(channel.id).or_default();
+            let mut loaded = match message_limit {
+                // one more to find out whether there are older messages
+                Some(limit) => storage.messages_before(channel.id, u64::MAX, limit + 1),
+                None => storage.messages(channel.id).collect(),
+            };
+            if message_limit.is_some_and(|limit| loaded.len() > limit) {
+                loaded.remove(0);
+                truncated.insert(channel.id, loaded[0].arrived_at);
+            }
+            for message in loaded {
+                let message_id = MessageId::new(channel.id, message.arrived_at);
+                messages_index.insert(message_id, channel_messages.len());
+                channel_messages.push(message.clone().into_owned());
+            }
+            channels_index.insert(channel.id, channels.len());
+            channels.push(channel.clone().into_owned());
+        }
+
+        let names = storage
+            .names()
+            .map(|(id, name)| (id, name.into_owned()))
+            .collect();
+
+        let metadata = storage.metadata().into_owned();
+
+        Self {
+            channels,
+            channels_index,
+            messages,
+            messages_index,
+            truncated,
+            message_limit,
+            names,
+            metadata,
+            storage,
+        }
+    }
+
+    /// Caches the stored message, unless it is older than the cached messages of the channel
+    fn cache_message(&mut self, channel_id: ChannelId, message: Message) {
+        let message_id = MessageId::new(channel_id, message.arrived_at);
+        match self.messages_index.entry(message_id) {
+            Entry::Vacant(entry) => {
+                let is_older_than_cached = self
+                    .truncated
+                    .get(&channel_id)
+                    .is_some_and(|&oldest| message.arrived_at < oldest);
+                if !is_older_than_cached {
+                    let messages = self.messages.entry(channel_id).or_default();
+                    entry.insert(messages.len());
+                    messages.push(message);
+                    self.drop_oldest_cached(channel_id);
+                }
+            }
+            Entry::Occupied(entry) => {
+                let idx = *entry.get();
+                let messages = self.messages.entry(channel_id).or_default();
+                let stored_message = &mut messages[idx];
+                *stored_message = message;
+            }
+        }
+    }
+
+    /// Drops the oldest cached messages of the channel beyond the message limit
+    ///
+    /// The dropped messages are still read from the underlying storage.
+    fn drop_oldest_cached(&mut self, channel_id: ChannelId) {
+        let Some(limit) = self.message_limit else {
+            return;
+        };
+        let Some(messages) = self.messages.get_mut(&channel_id) else {
+            return;
+        };
+        if messages.len() <= limit {
+            return;
+        }
+        let excess = messages.len() - limit;
+        for message in messages.drain(..excess) {
+            self.messages_index
+                .remove(&MessageId::new(channel_id, message.arrived_at));
+        }
+        for (idx, message) in messages.iter().enumerate() {
+            let message_id = MessageId::new(channel_id, message.arrived_at);
+            self.messages_index.insert(message_id, idx);
+        }
+        self.truncated.insert(channel_id, messages[0].arrived_at);
+    }
+}
+
+impl<S: Storage> Storage for MemCache<S> {
+    fn channels(&self) -> Box<dyn Iterator<Item = Cow<Channel>> + '_> {
+        Box::new(self.channels.iter().map(Cow::Borrowed))
+    }
+
+    fn channel(&self, channel_id: ChannelId) -> Option<Cow<Channel>> {
+        let idx = *self.channels_index.get(&channel_id)?;
+        self.channels.get(idx).map(Cow::Borrowed)
+    }
+
+    fn store_channel(&mut self, channel: Channel) -> Cow<Channel> {
+        match self.channels_index.entry(channel.id) {
+            Entry::Vacant(entry) => {
+                entry.insert(self.channels.len());
+                self.channels.push(channel.clone());
+            }
+            Entry::Occupied(entry) => {
+                let idx = *entry.get();
+                let stored_channel = &mut self.channels[idx];
+                *stored_channel = channel.clone();
+            }
+        }
+        self.storage.store_channel(channel)
+    }
+
+    fn messages(
+        &self,
+        channel_id: ChannelId,
+    ) -> Box<dyn DoubleEndedIterator<Item = Cow<Message>> + '_> {
+        let cached = self
+            .messages
+            .get(&channel_id)
+            .into_iter()
+            .flatten()
+            .map(Cow::Borrowed);
+        if let Some(&oldest) = self.truncated.get(&channel_id) {
+            // read lazily, so that going through the newest messages stays in the cache
+            let older = iter::once_with(move || self.storage.messages(channel_id))
+                .flatten()
+                .filter(move |message| message.arrived_at < oldest);
+            Box::new(older.chain(cached))
+        } else {
+            Box::new(cached)
+        }
+    }
+
+    fn messages_before(
+        &self,
+        channel_id: ChannelId,
+        arrived_at: u64,
+        limit: usize,
+    ) -> Vec<Cow<Message>> {
+        let cached = self
+            .messages
+            .get(&channel_id)
+            .into_iter()
+            .flatten()
+            .map(Cow::Borrowed);
+        let mut messages = last_messages_before(Box::new(cached), arrived_at, limit);
+        if let Some(&oldest) = self.truncated.get(&channel_id) {
+            if messages.len() < limit {
+                let older = self.storage.messages_before(
+                    channel_id,
+                    arrived_at.min(oldest),
+                    limit - messages.len(),
+                );
+                messages.splice(0..0, older);
+            }
+        }
+        messages
+    }
+
+    fn edits(
+        &self,
+        message_id: MessageId,
+    ) -> Box<dyn DoubleEndedIterator<Item = Cow<Message>> + '_> {
+        self.storage.edits(message_id) // Edits are not cached
+    }
+
+    fn limit_cutoff(&self, channel_id: ChannelId, limit: usize) -> Option<u64> {
+        let cached = self.messages.get(&channel_id)?;
+        match cached.len().checked_sub(limit + 1) {
+            Some(idx) => Some(cached[idx].arrived_at + 1),
+            // the older messages are only in the underlying storage
+            None if self.truncated.contains_key(&channel_id) => {
+                self.storage.limit_cutoff(channel_id, limit)
+            }
+            None => None,
+        }
+    }
+
+    fn message(&self, message_id: MessageId) -> Option<Cow<Message>> {
+        let messages = self.messages.get(&message_id.channel_id)?;
+        let cached = self
+            .messages_index
+            .get(&message_id)
+            .and_then(|&idx| messages.get(idx).map(Cow::Borrowed));
+        if let Some(message) = cached {
+            Some(message)
+        } else {
+            let message = self.storage.message(message_id)?;
+            Some(message)
+        }
+    }
+
+    fn search_messages(&self, query: &str, limit: usize) -> Vec<MessageId> {
+        // the underlying storage may not keep all cached messages, e.g. if it is forgetful
+        let cached = self.messages.iter().flat_map(|(&channel_id, messages)| {
+            messages
+                .iter()
+                .filter(|message| !message.is_edit() && matches_search_query(message, query))
+                .map(move |message| MessageId::new(channel_id, message.arrived_at))
+        });
+        let stored = self.storage.search_messages(query, limit);
+        newest_message_ids(cached.chain(stored), limit)
+    }
+
+    fn failed_messages(&self) -> Vec<MessageId> {
+        // the cache is written through, so the underlying storage knows all persisted messages
+        self.storage.failed_messages()
+    }
+
+    fn store_message(&mut self, channel_id: ChannelId, message: Message) -> Cow<Message> {
+        self.cache_message(channel_id, message.clone());
+        self.storage.store_message(channel_id, message)
+    }
+
+    fn store_new_message(
+        &mut self,
+        channel_id: ChannelId,
+        message: Message,
+    ) -> Option<Cow<Message>> {
+        // the underlying storage may not keep all cached messages, e.g. if it is forgetful
+        let message_id = MessageId::new(channel_id, message.arrived_at);
+        if self.messages_index.contains_key(&message_id) {
+            return None;
+        }
+        let message = self
+            .storage
+            .store_new_message(channel_id, message)?
+            .into_owned();
+        self.cache_message(channel_id, message.clone());
+        Some(Cow::Owned(message))
+    }
+
+    fn delete_messages_before(&mut self, channel_id: ChannelId, arrived_at: u64) {
+        if let Some(messages) = self.messages.get_mut(&channel_id) {
+            messages.retain(|message| !is_arrived_before(message, arrived_at));
+            self.messages_index
+                .retain(|message_id, _| message_id.channel_id != channel_id);
+            for (idx, message) in messages.iter().enumerate() {
+                let message_id = MessageId::new(channel_id, message.arrived_at);
+                self.messages_index.insert(message_id, idx);
+            }
+        }
+        if self
+            .truncated
+            .get(&channel_id)
+            .is_some_and(|&oldest| oldest <= arrived_at)
+        {
+            // all messages which were not cached are deleted
+            self.truncated.remove(&channel_id);
+        }
+        self.storage.delete_messages_before(channel_id, arrived_at);
+    }
+
+    fn delete_message(&mut self, message_id: MessageId) {
+        let channel_id = message_id.channel_id;
+        if let Some(messages) = self.messages.get_mut(&channel_id) {
+            messages.retain(|message| !is_message_or_edit(message, message_id.arrived_at));
+            self.messages_index
+                .retain(|message_id, _| message_id.channel_id != channel_id);
+            for (idx, message) in messages.iter().enumerate() {
+                let message_id = MessageId::new(channel_id, message.arrived_at);
+                self.messages_index.insert(message_id, idx);
+            }
+        }
+        self.storage.delete_message(message_id);
+    }
+
+    fn messages_in_memory(&self, channel_id: ChannelId) -> MemoryUsage {
+        MemoryUsage::of(self.messages.get(&channel_id).into_iter().flatten())
+            + self.storage.messages_in_memory(channel_id)
+    }
+
+    fn names(&self) -> Box<dyn Iterator<Item = (Uuid, Cow<str>)> + '_> {
+        Box::new(
+            self.names
+                .iter()
+                .map(|(id, name)| (*id, name.as_str().into())),
+        )
+    }
+
+    fn name(&self, id: Uuid) -> Option<Cow<str>> {
+        self.names.get(&id).map(String::as_str).map(Cow::Borrowed)
+    }
+
+    fn store_name(&mut self, id: Uuid, name: String) -> Cow<str> {
+        match self.names.entry(id) {
+            Entry::Vacant(entry) => {
+                entry.insert(name.clone());
+            }
+            Entry::Occupied(mut entry) => {
+                entry.insert(name.clone());
+            }
+        }
+        self.storage.store_name(id, name)
+    }
+
+    fn metadata(&self) -> Cow<Metadata> {
+        Cow::Borrowed(&self.metadata)
+    }
+
+    fn store_metadata(&mut self, metadata: Metadata) -> Cow<Metadata> {
+        self.metadata = metadata.clone();
+        self.storage.store_metadata(metadata)
+    }
+
+    fn save(&mut self) -> anyhow::Result<()> {
+        self.storage.save()
+    }
+
+    fn message_channel(&self, arrived_at: u64) -> Option<ChannelId> {
+        // message arrived_at to channel_id conversion is not cached
+        self.storage.message_channel(arrived_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use crate::data::TypingSet;
+    use crate::storage::SqliteStorage;
+
+    use super::*;
+
+    #[test]
+    fn test_memcache_with_message_limit() {
+        let url: Url = "sqlite::memory:".parse().unwrap();
+        let mut storage = SqliteStorage::open(&url, None).unwrap();
+        let user_id = Uuid::new_v4();
+        let channel_id = ChannelId::User(user_id);
+        storage.store_channel(Channel {
+            id: channel_id,
+            name: "direct-channel".to_owned(),
+            group_data: None,
+            unread_messages: 0,
+            typing: TypingSet::new(false),
+            muted_until: None,
+            pinned: false,
+            archived: false,
+            burn_after_reading: false,
+            max_messages: None,
+            ignore_mention_all: false,
+            notification_content: None,
+            nickname: None,
+        });
+        for arrived_at in 1..=5 {
+            let message = Message::new(user_id, None, [], arrived_at, Vec::new());
+            storage.store_message(channel_id, message);
+        }
+
+        let mut cache = MemCache::with_message_limit(storage, 2);
+        let arrived_at = |messages: Vec<Cow<Message>>| -> Vec<u64> {
+            messages.iter().map(|message| message.arrived_at).collect()
+        };
+        assert_eq!(cache.messages[&channel_id].len(), 2);
+        assert_eq!(
+            arrived_at(cache.messages(channel_id).collect()),
+            [1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            arrived_at(cache.messages_before(channel_id, u64::MAX, 3)),
+            [3, 4, 5]
+        );
+        assert_eq!(arrived_at(cache.messages_before(channel_id, 3, 10)), [1, 2]);
+        assert!(cache.message(MessageId::new(channel_id, 1)).is_some());
+        assert_eq!(cache.limit_cutoff(channel_id, 1), Some(5));
+        assert_eq!(cache.limit_cutoff(channel_id, 3), Some(3));
+        assert_eq!(cache.limit_cutoff(channel_id, 5), None);
+
+        // older messages are not cached, but stored
+        let message = Message::new(user_id, None, [], 0, Vec::new());
+        cache.store_message(channel_id, message);
+        assert_eq!(cache.messages[&channel_id].len(), 2);
+        assert_eq!(arrived_at(cache.messages_before(channel_id, 2, 10)), [0, 1]);
+
+        // the oldest cached messages are dropped when new ones arrive
+        let message = Message::new(user_id, None, [], 6, Vec::new());
+        cache.store_message(channel_id, message);
+        assert_eq!(cache.messages_in_memory(channel_id).messages, 2);
+        assert!(cache
+            .messages_index
+            .contains_key(&MessageId::new(channel_id, 6)));
+        assert!(!cache
+            .messages_index
+            .contains_key(&MessageId::new(channel_id, 4)));
+        assert_eq!(
+            arrived_at(cache.messages(channel_id).collect()),
+            [0, 1, 2, 3, 4, 5, 6]
+        );
+        assert_eq!(
+            cache
+                .message(MessageId::new(channel_id, 4))
+                .unwrap()
+                .arrived_at,
+            4
+        );
+    }
+}
@@ -0,0 +1,500 @@
+mod burn;
+mod copy;
+mod forgetful;
+mod json;
+mod memcache;
+mod sql;
+
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::data::{Channel, ChannelId, Message};
+
+pub use burn::BurnAfterReading;
+pub use copy::{copy, sync_from_signal};
+pub use forgetful::ForgetfulStorage;
+pub use json::JsonStorage;
+pub use memcache::MemCache;
+pub use sql::SqliteStorage;
+
+/// Storage of channels, messages, names and metadata.
+///
+/// Used to persist the data to disk.
+///
+/// ## Implementation note
+///
+/// The implementer can choose whether to return owning or
+/// borrowed objects from the storage. This depends whether the objects are stored as is, or are
+/// converted and/or serialized.
+pub trait Storage {
+    /// Channels in no particular order
+    fn channels(&self) -> Box<dyn Iterator<Item = Cow<Channel>> + '_>;
+    /// Gets the channel by id
+    fn channel(&self, channel_id: ChannelId) -> Option<Cow<Channel>>;
+    /// Stores the given `channel` and returns it back
+    fn store_channel(&mut self, channel: Channel) -> Cow<Channel>;
+
+    /// Messages sorted by arrived_at in ascending order
+    ///
+    /// No edited messages must be included.
+    fn messages(
+        &self,
+        channel_id: ChannelId,
+    ) -> Box<dyn DoubleEndedIterator<Item = Cow<Message>> + '_>;
+    /// The last `limit` messages arrived before `arrived_at`, sorted by arrived_at in ascending
+    /// order
+    ///
+    /// Used for loading the history of a channel page by page. No edited messages must be
+    /// included.
+    fn messages_before(
+        &self,
+        channel_id: ChannelId,
+        arrived_at: u64,
+        limit: usize,
+    ) -> Vec<Cow<Message>> {
+        last_messages_before(self.messages(channel_id), arrived_at, limit)
+    }
+    /// Arrived at before which the messages of the channel are beyond its `limit` newest ones
+    ///
+    /// Used for deleting the messages beyond a limit with `delete_messages_before`. `None` if the
+    /// channel has no more than `limit` messages. No edited messages must be counted.
+    fn limit_cutoff(&self, channel_id: ChannelId, limit: usize) -> Option<u64> {
+        limit_cutoff(self.messages(channel_id), limit)
+    }
+    /// Gets the message by id
+    fn message(&self, message_id: MessageId) -> Option<Cow<Message>>;
+    /// Ids of the messages containing all words of the `query`, newest first
+    ///
+    /// A word of the query matches the beginning of a word of the message case-insensitively. At
+    /// most `limit` messages are returned. Edits are not searched.
+    ///
+    /// The default implementation goes through all messages of all channels.
+    fn search_messages(&self, query: &str, limit: usize) -> Vec<MessageId> {
+        let ids = self.channels().flat_map(|channel| {
+            let channel_id = channel.id;
+            self.messages(channel_id)
+                .filter(|message| !message.is_edit() && matches_search_query(message, query))
+                .map(|message| MessageId::new(channel_id, message.arrived_at))
+                .collect::<Vec<_>>()
+        });
+        newest_message_ids(ids, limit)
+    }
+
+    /// Ids of the messages which failed to be sent, oldest first
+    ///
+    /// Used for offering to send them again after a restart. Edits are not included.
+    ///
+    /// The default implementation goes through all messages of all channels.
+    fn failed_messages(&self) -> Vec<MessageId> {
+        let mut ids: Vec<_> = self
+            .channels()
+            .flat_map(|channel| {
+                let channel_id = channel.id;
+                self.messages(channel_id)
+                    .filter(|message| message.send_failed.is_some())
+                    .map(|message| MessageId::new(channel_id, message.arrived_at))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        ids.sort_unstable_by_key(|id| id.arrived_at);
+        ids
+    }
+
+    fn message_channel(&self, arrived_at: u64) -> Option<ChannelId>;
+
+    /// Whether a message from the sender with the timestamp is already stored in the channel
+    ///
+    /// The sender and the timestamp identify a message, so that replayed envelopes and sync
+    /// messages of already known messages are not stored twice.
+    fn contains_message(&self, channel_id: ChannelId, from_id: Uuid, arrived_at: u64) -> bool {
+        self.message(MessageId::new(channel_id, arrived_at))
+            .is_some_and(|message| message.from_id == from_id)
+    }
+
+    fn edits(
+        &self,
+        message_id: MessageId,
+    ) -> Box<dyn DoubleEndedIterator<Item = Cow<Message>> + '_>;
+
+    /// Stores the message for the given `channel_id` and returns it back
+    ///
+    /// If a channel with this `channel_id` already exists in the storage, it is overridden.
+    /// Otherwise, the channel is added to the storage.
+    fn store_message(&mut self, channel_id: ChannelId, message: Message) -> Cow<Message>;
+
+    /// Stores the message for the given `channel_id`, unless it is a duplicate
+    ///
+    /// A message from the same sender with the same timestamp already stored in the channel is
+    /// kept as it is, e.g. with its reactions and receipts. A message of another sender with the
+    /// same timestamp is not overwritten either. Returns the stored message, or `None` if the
+    /// message was not stored.
+    fn store_new_message(
+        &mut self,
+        channel_id: ChannelId,
+        message: Message,
+    ) -> Option<Cow<Message>> {
+        if self
+            .message(MessageId::new(channel_id, message.arrived_at))
+            .is_some()
+        {
+            return None;
+        }
+        Some(self.store_message(channel_id, message))
+    }
+
+    /// Deletes the messages of the channel arrived before `arrived_at` including their edits
+    fn delete_messages_before(&mut self, channel_id: ChannelId, arrived_at: u64);
+
+    /// Deletes the message including its edits
+    fn delete_message(&mut self, message_id: MessageId);
+
+    /// Drops all messages of the channel which are only kept in memory
+    ///
+    /// Used for burn-after-reading channels whose messages are never persisted. Persisted
+    /// messages are not affected.
+    fn forget_messages(&mut self, _channel_id: ChannelId) {}
+
+    /// Messages of the channel kept in memory, including edits
+    ///
+    /// Storages reading the messages from disk on demand keep none.
+    fn messages_in_memory(&self, _channel_id: ChannelId) -> MemoryUsage {
+        MemoryUsage::default()
+    }
+
+    fn store_edited_message(
+        &mut self,
+        channel_id: ChannelId,
+        target_sent_timestampt: u64,
+        message: Message,
+    ) -> Option<Cow<Message>> {
+        // Note: target_sent_timestamp points to the previous edit or the original message
+        let prev_edited = self.message(MessageId::new(channel_id, target_sent_timestampt))?;
+
+        // get original message
+        let mut original = if let Some(arrived_at) = prev_edited.edit {
+            // previous edit => get original message
+            self.message(MessageId::new(channel_id, arrived_at))?
+                .into_owned()
+        } else {
+            // original message => first edit
+            let original = prev_edited.into_owned();
+
+            // preserve body of the original message; it is replaced below
+            let mut preserved = original.clone();
+            preserved.arrived_at = original.arrived_at + 1;
+            preserved.edit = Some(original.arrived_at);
+            self.store_message(channel_id, preserved);
+
+            original
+        };
+
+        // store the incoming edit
+        let body = message.message.clone();
+        self.store_message(
+            channel_id,
+            Message {
+                edit: Some(original.arrived_at),
+                ..message
+            },
+        );
+
+        // override the body of the original message
+        original.message = body;
+        original.edited = true;
+        Some(self.store_message(channel_id, original))
+    }
+
+    /// Names of contacts
+    fn names(&self) -> Box<dyn Iterator<Item = (Uuid, Cow<str>)> + '_>;
+    /// Gets the name for the given contact `id`
+    fn name(&self, id: Uuid) -> Option<Cow<str>>;
+    /// Stores a name for the given contact `id`
+    ///
+    /// If the name with this `id` already exists in the storage, it is overridden. Otherwise, it
+    /// the name is added to the storage.
+    fn store_name(&mut self, id: Uuid, name: String) -> Cow<str>;
+
+    /// Returns the metadata containing persisted flags and settings
+    fn metadata(&self) -> Cow<Metadata>;
+    /// Stores the new metadata in the storage overriding the previous one
+    fn store_metadata(&mut self, metadata: Metadata) -> Cow<Metadata>;
+
+    /// Persists the data in the storage
+    ///
+    /// ## Implementation note
+    ///
+    /// The implementers of this trait, can persist for each store call, if it is efficient enough.
+    /// This methods must guarantee that the data is persisted in any case.
+    fn save(&mut self) -> anyhow::Result<()>;
+
+    /// Returns `true` if this storage does not contains any channels and no names
+    fn is_empty(&self) -> bool {
+        self.channels().next().is_none() && self.names().next().is_none()
+    }
+}
+
+/// The last `limit` of the sorted messages arrived before `arrived_at`
+fn last_messages_before<'a>(
+    messages: Box<dyn DoubleEndedIterator<Item = Cow<'a, Message>> + 'a>,
+    arrived_at: u64,
+    limit: usize,
+) -> Vec<Cow<'a, Message>> {
+    let mut messages: Vec<_> = messages
+        .rev()
+        .skip_while(|message| message.arrived_at >= arrived_at)
+        .take(limit)
+        .collect();
+    messages.reverse();
+    messages
+}
+
+/// Arrived at before which the sorted messages are beyond the `limit` newest ones
+fn limit_cutoff<'a>(
+    mut messages: Box<dyn DoubleEndedIterator<Item = Cow<'a, Message>> + 'a>,
+    limit: usize,
+) -> Option<u64> {
+    let newest_beyond = messages.nth_back(limit)?;
+    Some(newest_beyond.arrived_at + 1)
+}
+
+/// Whether the text of the message contains all words of the query as word prefixes
+fn matches_search_query(message: &Message, query: &str) -> bool {
+    let Some(text) = message.message.as_deref() else {
+        return false;
+    };
+    let words: Vec<String> = search_words(text).collect();
+    let mut query_words = search_words(query).peekable();
+    query_words.peek().is_some()
+        && query_words.all(|query_word| words.iter().any(|word| word.starts_with(&query_word)))
+}
+
+/// Lowercase words of the text, similar to the tokenizer of the full-text index of SQLite
+fn search_words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Sorts the ids newest first and keeps the first `limit` distinct ones
+fn newest_message_ids(ids: impl IntoIterator<Item = MessageId>, limit: usize) -> Vec<MessageId> {
+    let mut ids: Vec<_> = ids.into_iter().collect();
+    ids.sort_unstable_by_key(|&id| (Reverse(id.arrived_at), id));
+    ids.dedup();
+    ids.truncate(limit);
+    ids
+}
+
+/// Whether the message, or the message edited by it, arrived before `arrived_at`
+fn is_arrived_before(message: &Message, arrived_at: u64) -> bool {
+    message.arrived_at < arrived_at || message.edit.is_some_and(|edit| edit < arrived_at)
+}
+
+/// Whether the message arrived at `arrived_at`, or is an edit of the message arrived then
+fn is_message_or_edit(message: &Message, arrived_at: u64) -> bool {
+    message.arrived_at == arrived_at || message.edit == Some(arrived_at)
+}
+
+/// Number of messages kept in memory and their estimated size
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub messages: usize,
+    pub bytes: usize,
+}
+
+impl MemoryUsage {
+    pub fn of<'a>(messages: impl IntoIterator<Item = &'a Message>) -> Self {
+        messages
+            .into_iter()
+            .fold(Self::default(), |usage, message| Self {
+                messages: usage.messages + 1,
+                bytes: usage.bytes + message.estimated_size(),
+            })
+    }
+}
+
+impl std::ops::Add for MemoryUsage {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            messages: self.messages + other.messages,
+            bytes: self.bytes + other.bytes,
+        }
+    }
+}
+
+/// A message is identified by its channel and time of arrived in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MessageId {
+    pub channel_id: ChannelId,
+    pub arrived_at: u64,
+}
+
+impl MessageId {
+    pub fn new(channel_id: ChannelId, arrived_at: u64) -> Self {
+        Self {
+            channel_id,
+            arrived_at,
+        }
+    }
+}
+
+/// Persisted metadata
+#[derive(Debug, Default, Clone)]
+pub struct Metadata {
+    /// The time of the last request to synchronize contacts
+    ///
+    /// Used to amortize calls to the backend.
+    pub contacts_sync_request_at: Option<DateTime<Utc>>,
+    pub fully_migrated: Option<bool>,
+    /// Recently sent or downloaded files, most recent first
+    pub recent_files: Vec<RecentFile>,
+    /// Notes about contacts, kept locally only
+    pub contact_notes: BTreeMap<Uuid, ContactNotes>,
+    /// Nicknames and blocked state of contacts, kept locally only
+    pub contacts: BTreeMap<Uuid, LocalContact>,
+}
+
+impl Metadata {
+    /// Maximum number of remembered recent files
+    pub const RECENT_FILES_LIMIT: usize = 50;
+
+    /// Adds the file as the most recent one
+    ///
+    /// If the file is already known, it is moved to the front. The oldest files are forgotten when
+    /// the limit is exceeded.
+    pub fn add_recent_file(&mut self, file: RecentFile) {
+        self.recent_files.retain(|recent| recent.path != file.path);
+        self.recent_files.insert(0, file);
+        self.recent_files.truncate(Self::RECENT_FILES_LIMIT);
+    }
+}
+
+/// File which was sent or downloaded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecentFile {
+    pub path: PathBuf,
+    pub kind: RecentFileKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecentFileKind {
+    Sent,
+    Downloaded,
+}
+
+/// Birthday and freeform notes about a contact
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContactNotes {
+    pub birthday: Option<Birthday>,
+    pub notes: Option<String>,
+}
+
+impl ContactNotes {
+    pub fn is_empty(&self) -> bool {
+        self.birthday.is_none() && self.notes.is_none()
+    }
+}
+
+/// Settings of a contact which are not synced with Signal
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocalContact {
+    /// Name shown instead of the name of the profile or the contact
+    pub nickname: Option<String>,
+    /// Whether messages, reactions and receipts of the contact are dropped
+    #[serde(default)]
+    pub blocked: bool,
+}
+
+impl LocalContact {
+    pub fn is_empty(&self) -> bool {
+        self.nickname.is_none() && !self.blocked
+    }
+}
+
+/// Day of a birthday, optionally with the year of birth
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Birthday {
+    pub year: Option<i32>,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Birthday {
+    /// Whether the birthday is celebrated on the date
+    ///
+    /// Birthdays on February 29 are celebrated on February 28 in common years.
+    pub fn is_on(&self, date: NaiveDate) -> bool {
+        let is_leap_day = (self.month, self.day) == (2, 29);
+        let day = if is_leap_day && !date.leap_year() {
+            28
+        } else {
+            self.day
+        };
+        date.month() == self.month && date.day() == day
+    }
+}
+
+/// Parses `MM-DD` or `YYYY-MM-DD`
+impl FromStr for Birthday {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split('-').collect();
+        let (year, month, day) = match parts[..] {
+            [month, day] => (None, month, day),
+            [year, month, day] => (Some(year.parse()?), month, day),
+            _ => anyhow::bail!("invalid birthday {s}, expected MM-DD or YYYY-MM-DD"),
+        };
+        let birthday = Self {
+            year,
+            month: month.parse()?,
+            day: day.parse()?,
+        };
+        // 2000 is a leap year, so that February 29 is valid without a year
+        NaiveDate::from_ymd_opt(year.unwrap_or(2000), birthday.month, birthday.day)
+            .ok_or_else(|| anyhow::anyhow!("invalid birthday {s}"))?;
+        Ok(birthday)
+    }
+}
+
+impl fmt::Display for Birthday {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(year) = self.year {
+            write!(f, "{year:04}-")?;
+        }
+        write!(f, "{:02}-{:02}", self.month, self.day)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_birthday() {
+        let birthday: Birthday = "1990-05-17".parse().unwrap();
+        assert_eq!(birthday.year, Some(1990));
+        assert_eq!(birthday.to_string(), "1990-05-17");
+        assert!(birthday.is_on(NaiveDate::from_ymd_opt(2026, 5, 17).unwrap()));
+        assert!(!birthday.is_on(NaiveDate::from_ymd_opt(2026, 5, 18).unwrap()));
+
+        let leap_day: Birthday = "02-29".parse().unwrap();
+        assert_eq!(leap_day.to_string(), "02-29");
+        assert!(leap_day.is_on(NaiveDate::from_ymd_opt(2027, 2, 28).unwrap()));
+        assert!(!leap_day.is_on(NaiveDate::from_ymd_opt(2028, 2, 28).unwrap()));
+        assert!(leap_day.is_on(NaiveDate::from_ymd_opt(2028, 2, 29).unwrap()));
+
+        assert!("2023-02-29".parse::<Birthday>().is_err());
+        assert!("13-01".parse::<Birthday>().is_err());
+        assert!("may 17".parse::<Birthday>().is_err());
+    }
+}
@@ -1,10 +1,12 @@
 use std::borrow::Cow;
 use std::cell::Cell;
+use std::collections::BTreeMap;
 use std::future::Future;
 use std::marker::PhantomData;
 use std::pin::Pin;
 use std::time::Instant;
 
+use chrono::{DateTime, Utc};
 use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteSynchronous};
 use sqlx::{ConnectOptions, Connection, SqliteConnection};
 use thread_local::ThreadLocal;
@@ -17,7 +19,7 @@ use crate::data::{BodyRange, Channel, ChannelId, GroupData, Message, TypingSet};
 use crate::receipt::Receipt;
 use crate::signal::Attachment;
 use crate::storage::copy::{self, Stats};
-use crate::storage::{MessageId, Metadata, Storage};
+use crate::storage::{ContactNotes, LocalContact, MessageId, Metadata, RecentFile, Storage};
 
 use super::encoding::BlobData;
 use super::encrypt::{encrypt_db, is_sqlite_encrypted_heuristics};
@@ -176,6 +178,116 @@ impl SqliteStorage {
         trace!(elapsed =? now.elapsed(), "sql executed");
         res
     }
+
+    /// Inserts the message, replacing a stored message with the same timestamp if `replace`
+    ///
+    /// Returns whether the message was stored.
+    fn insert_message(&self, channel_id: ChannelId, message: &Message, replace: bool) -> bool {
+        let channel_id = &channel_id;
+        let arrived_at: i64 = message
+            .arrived_at
+            .try_into()
+            .map_err(|_| MessageConvertError::InvalidTimestamp)
+            .ok_logged()
+            .unwrap();
+        let from_id = &message.from_id;
+        let message_msg = message.message.as_deref();
+        let quote: Option<i64> = message.quote.as_ref().and_then(|quote| {
+            quote
+                .arrived_at
+                .try_into()
+                .map_err(|_| MessageConvertError::InvalidTimestamp)
+                .ok_logged()
+        });
+        let receipt = BlobData(&message.receipt);
+        let body_ranges = BlobData(&message.body_ranges);
+        let attachments = BlobData(&message.attachments);
+        let reactions = BlobData(&message.reactions);
+        let edit: Option<i64> = message.edit.and_then(|edit| {
+            edit.try_into()
+                .map_err(|_| MessageConvertError::InvalidTimestamp)
+                .ok_logged()
+        });
+        let edited: bool = message.edited;
+        let forwarded: bool = message.forwarded;
+        let send_failed = message.send_failed.as_deref();
+        let inserted = self.execute(|ctx| {
+            let query = if replace {
+                sqlx::query!(
+                    "
+                    REPLACE INTO messages(
+                        arrived_at,
+                        channel_id,
+                        from_id,
+                        message,
+                        quote,
+                        receipt,
+                        body_ranges,
+                        attachments,
+                        reactions,
+                        edit,
+                        edited,
+                        forwarded,
+                        send_failed
+                    )
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                    ",
+                    arrived_at,
+                    channel_id,
+                    from_id,
+                    message_msg,
+                    quote,
+                    receipt,
+                    body_ranges,
+                    attachments,
+                    reactions,
+                    edit,
+                    edited,
+                    forwarded,
+                    send_failed
+                )
+            } else {
+                sqlx::query!(
+                    "
+                    INSERT INTO messages(
+                        arrived_at,
+                        channel_id,
+                        from_id,
+                        message,
+                        quote,
+                        receipt,
+                        body_ranges,
+                        attachments,
+                        reactions,
+                        edit,
+                        edited,
+                        forwarded,
+                        send_failed
+                    )
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                    ON CONFLICT DO NOTHING
+                    ",
+                    arrived_at,
+                    channel_id,
+                    from_id,
+                    message_msg,
+                    quote,
+                    receipt,
+                    body_ranges,
+                    attachments,
+                    reactions,
+                    edit,
+                    edited,
+                    forwarded,
+                    send_failed
+                )
+            };
+            Box::pin(query.execute(ctx.conn))
+        });
+        inserted
+            .ok_logged()
+            .is_some_and(|inserted| inserted.rows_affected() > 0)
+    }
 }
 
 impl Drop for SqliteStorage {
@@ -194,6 +306,14 @@ struct SqlChannel {
     group_master_key: Option<Vec<u8>>,
     group_revision: Option<i64>,
     group_members: Option<BlobData<Vec<Uuid>>>,
+    muted_until: Option<i64>,
+    pinned: bool,
+    archived: bool,
+    burn_after_reading: bool,
+    max_messages: Option<i64>,
+    ignore_mention_all: bool,
+    notification_content: Option<String>,
+    nickname: Option<String>,
 }
 
 impl SqlChannel {
@@ -204,6 +324,14 @@ impl SqlChannel {
             group_master_key,
             group_revision,
             group_members,
+            muted_until,
+            pinned,
+            archived,
+            burn_after_reading,
+            max_messages,
+            ignore_mention_all,
+            notification_content,
+            nickname,
         } = self;
         use ChannelConvertError::*;
         let group_data = match (group_master_key, group_revision, group_members) {
@@ -221,6 +349,20 @@ impl SqlChannel {
             group_data,
             unread_messages: Default::default(),
             typing: TypingSet::new(is_group),
+            muted_until: muted_until
+                .map(|muted_until| muted_until.try_into().map_err(|_| MutedUntil))
+                .transpose()?,
+            pinned,
+            archived,
+            burn_after_reading,
+            max_messages: max_messages
+                .map(|max_messages| max_messages.try_into().map_err(|_| MaxMessages))
+                .transpose()?,
+            ignore_mention_all,
+            notification_content: notification_content
+                .map(|content| content.parse().map_err(|_| NotificationContent))
+                .transpose()?,
+            nickname,
         })
     }
 }
@@ -241,6 +383,8 @@ struct SqlMessage {
     quote_receipt: Option<BlobData<Receipt>>,
     edit: Option<i64>,
     edited: bool,
+    forwarded: bool,
+    send_failed: Option<String>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -267,6 +411,8 @@ impl SqlMessage {
             quote_receipt,
             edit,
             edited,
+            forwarded,
+            send_failed,
         } = self;
 
         let quote = quote_arrived_at
@@ -301,13 +447,14 @@ impl SqlMessage {
             reactions: reactions.map(BlobData::into_inner).unwrap_or_default(),
             receipt: receipt.map(BlobData::into_inner).unwrap_or_default(),
             body_ranges: body_ranges.map(BlobData::into_inner).unwrap_or_default(),
-            send_failed: Default::default(),
+            send_failed,
             edit: edit.and_then(|edit| {
                 edit.try_into()
                     .map_err(|_| MessageConvertError::InvalidTimestamp)
                     .ok_logged()
             }),
             edited,
+            forwarded,
         })
     }
 }
@@ -318,6 +465,52 @@ enum ChannelConvertError {
     MasterKeyBytes,
     #[error("invalid revision")]
     Revision,
+    #[error("invalid muted until timestamp")]
+    MutedUntil,
+    #[error("invalid maximum number of messages")]
+    MaxMessages,
+    #[error("invalid notification content")]
+    NotificationContent,
+}
+
+struct SqlMetadata {
+    contacts_sync_request_at: Option<DateTime<Utc>>,
+    fully_migrated: Option<bool>,
+    recent_files: Option<BlobData<Vec<RecentFile>>>,
+    contact_notes: Option<BlobData<BTreeMap<Uuid, ContactNotes>>>,
+    contacts: Option<BlobData<BTreeMap<Uuid, LocalContact>>>,
+}
+
+impl SqlMetadata {
+    fn convert(self) -> Metadata {
+        Metadata {
+            contacts_sync_request_at: self.contacts_sync_request_at,
+            fully_migrated: self.fully_migrated,
+            recent_files: self
+                .recent_files
+                .map(BlobData::into_inner)
+                .unwrap_or_default(),
+            contact_notes: self
+                .contact_notes
+                .map(BlobData::into_inner)
+                .unwrap_or_default(),
+            contacts: self.contacts.map(BlobData::into_inner).unwrap_or_default(),
+        }
+    }
+}
+
+struct SqlMessageId {
+    arrived_at: i64,
+    channel_id: ChannelId,
+}
+
+impl SqlMessageId {
+    fn convert(self) -> Option<MessageId> {
+        Some(MessageId::new(
+            self.channel_id,
+            self.arrived_at.try_into().ok()?,
+        ))
+    }
 }
 
 struct SqlName {
@@ -331,7 +524,7 @@ impl Storage for SqliteStorage {
             Box::pin(sqlx::query_as!(
                 SqlChannel,
                 r#"
-                    SELECT id AS "id: _", name, group_master_key, group_revision, group_members AS "group_members: _"
+                    SELECT id AS "id: _", name, group_master_key, group_revision, group_members AS "group_members: _", muted_until, pinned, archived, burn_after_reading, max_messages, ignore_mention_all, notification_content, nickname
                     FROM channels
                 "#
             )
@@ -354,7 +547,7 @@ impl Storage for SqliteStorage {
                 Box::pin(sqlx::query_as!(
                     SqlChannel,
                     r#"
-                        SELECT id AS "id: _", name, group_master_key, group_revision, group_members AS "group_members: _"
+                        SELECT id AS "id: _", name, group_master_key, group_revision, group_members AS "group_members: _", muted_until, pinned, archived, burn_after_reading, max_messages, ignore_mention_all, notification_content, nickname
                         FROM channels
                         WHERE id = ?
                     "#,
@@ -380,18 +573,38 @@ impl Storage for SqliteStorage {
                 )
             })
             .unwrap_or_default();
+        let muted_until: Option<i64> = channel
+            .muted_until
+            .map(|muted_until| muted_until.try_into().unwrap_or(i64::MAX));
+        let pinned = channel.pinned;
+        let archived = channel.archived;
+        let burn_after_reading = channel.burn_after_reading;
+        let max_messages: Option<i64> = channel
+            .max_messages
+            .map(|max_messages| max_messages.try_into().unwrap_or(i64::MAX));
+        let ignore_mention_all = channel.ignore_mention_all;
+        let notification_content = channel.notification_content.map(|content| content.as_str());
+        let nickname = channel.nickname.as_deref();
         let inserted = self.execute(|ctx| {
             Box::pin(
                 sqlx::query!(
                     r#"
-                    REPLACE INTO channels(id, name, group_master_key, group_revision, group_members)
-                    VALUES (?, ?, ?, ?, ?)
+                    REPLACE INTO channels(id, name, group_master_key, group_revision, group_members, muted_until, pinned, archived, burn_after_reading, max_messages, ignore_mention_all, notification_content, nickname)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
                 "#,
                     id,
                     name,
                     group_master_key,
                     group_revision,
-                    group_members
+                    group_members,
+                    muted_until,
+                    pinned,
+                    archived,
+                    burn_after_reading,
+                    max_messages,
+                    ignore_mention_all,
+                    notification_content,
+                    nickname
                 )
                 .execute(ctx.conn),
             )
@@ -426,7 +639,9 @@ impl Storage for SqliteStorage {
                         q.body_ranges AS "quote_body_ranges: _",
                         q.receipt AS "quote_receipt: _",
                         NULL AS "edit: _",
-                        m.edited AS "edited: _"
+                        m.edited AS "edited: _",
+                        m.forwarded AS "forwarded: _",
+                        m.send_failed
                     FROM messages AS m
                     LEFT JOIN messages AS q ON q.arrived_at = m.quote AND q.channel_id = ?1
                     WHERE m.channel_id = ?1 AND m.edit IS NULL
@@ -446,6 +661,123 @@ impl Storage for SqliteStorage {
         )
     }
 
+    fn messages_before(
+        &self,
+        channel_id: ChannelId,
+        arrived_at: u64,
+        limit: usize,
+    ) -> Vec<Cow<Message>> {
+        let channel_id = &channel_id;
+        let arrived_at = i64::try_from(arrived_at).unwrap_or(i64::MAX);
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let messages = self.execute(|ctx| {
+            Box::pin(
+                sqlx::query_as!(
+                    SqlMessage,
+                    r#"
+                    SELECT
+                        m.arrived_at AS "arrived_at!",
+                        m.from_id AS "from_id: _",
+                        m.message,
+                        m.receipt AS "receipt: _",
+                        m.body_ranges AS "body_ranges: _",
+                        m.attachments AS "attachments: _",
+                        m.reactions AS "reactions: _",
+                        q.arrived_at AS "quote_arrived_at: _",
+                        q.from_id AS "quote_from_id: _",
+                        q.message AS quote_message,
+                        q.attachments AS "quote_attachments: _",
+                        q.body_ranges AS "quote_body_ranges: _",
+                        q.receipt AS "quote_receipt: _",
+                        NULL AS "edit: _",
+                        m.edited AS "edited: _",
+                        m.forwarded AS "forwarded: _",
+                        m.send_failed
+                    FROM messages AS m
+                    LEFT JOIN messages AS q ON q.arrived_at = m.quote AND q.channel_id = ?1
+                    WHERE m.channel_id = ?1 AND m.edit IS NULL AND m.arrived_at < ?2
+                    ORDER BY m.arrived_at DESC
+                    LIMIT ?3
+                "#,
+                    channel_id,
+                    arrived_at,
+                    limit,
+                )
+                .fetch_all(ctx.conn),
+            )
+        });
+        let mut messages: Vec<_> = messages
+            .ok_logged()
+            .into_iter()
+            .flatten()
+            .filter_map(|message| message.convert().ok_logged().map(Cow::Owned))
+            .collect();
+        messages.reverse();
+        messages
+    }
+
+    fn search_messages(&self, query: &str, limit: usize) -> Vec<MessageId> {
+        // each word is matched as prefix of a word in the full-text index
+        let query = query
+            .split_whitespace()
+            .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let ids = self.execute(|ctx| {
+            Box::pin(
+                sqlx::query_as!(
+                    SqlMessageId,
+                    r#"
+                    SELECT
+                        m.arrived_at AS "arrived_at!",
+                        m.channel_id AS "channel_id: _"
+                    FROM messages_fts
+                    JOIN messages AS m ON m.arrived_at = messages_fts.rowid
+                    WHERE messages_fts MATCH ?1
+                    ORDER BY m.arrived_at DESC
+                    LIMIT ?2
+                "#,
+                    query,
+                    limit,
+                )
+                .fetch_all(ctx.conn),
+            )
+        });
+        ids.ok_logged()
+            .into_iter()
+            .flatten()
+            .filter_map(SqlMessageId::convert)
+            .collect()
+    }
+
+    fn failed_messages(&self) -> Vec<MessageId> {
+        let ids = self.execute(|ctx| {
+            Box::pin(
+                sqlx::query_as!(
+                    SqlMessageId,
+                    r#"
+                    SELECT
+                        arrived_at AS "arrived_at!",
+                        channel_id AS "channel_id: _"
+                    FROM messages
+                    WHERE send_failed IS NOT NULL AND edit IS NULL
+                    ORDER BY arrived_at ASC
+                "#,
+                )
+                .fetch_all(ctx.conn),
+            )
+        });
+        ids.ok_logged()
+            .into_iter()
+            .flatten()
+            .filter_map(SqlMessageId::convert)
+            .collect()
+    }
+
     fn edits(
         &self,
         message_id: MessageId,
@@ -479,7 +811,9 @@ impl Storage for SqliteStorage {
                         q.body_ranges AS "quote_body_ranges: _",
                         q.receipt AS "quote_receipt: _",
                         NULL AS "edit: _",
-                        m.edited AS "edited: _"
+                        m.edited AS "edited: _",
+                        m.forwarded AS "forwarded: _",
+                        m.send_failed
                     FROM messages AS m
                     LEFT JOIN messages AS q ON q.arrived_at = m.quote AND q.channel_id = ?1
                     WHERE m.channel_id = ?1 AND m.edit == ?2
@@ -500,6 +834,34 @@ impl Storage for SqliteStorage {
         )
     }
 
+    fn limit_cutoff(&self, channel_id: ChannelId, limit: usize) -> Option<u64> {
+        let channel_id = &channel_id;
+        let offset = i64::try_from(limit).ok()?;
+        let newest_beyond = self.execute(|ctx| {
+            Box::pin(
+                sqlx::query!(
+                    r#"
+                    SELECT arrived_at AS "arrived_at!"
+                    FROM messages
+                    WHERE channel_id = ?1 AND edit IS NULL
+                    ORDER BY arrived_at DESC
+                    LIMIT 1 OFFSET ?2
+                "#,
+                    channel_id,
+                    offset,
+                )
+                .fetch_optional(ctx.conn),
+            )
+        });
+        let arrived_at: u64 = newest_beyond
+            .ok_logged()??
+            .arrived_at
+            .try_into()
+            .map_err(|_| MessageConvertError::InvalidTimestamp)
+            .ok_logged()?;
+        Some(arrived_at + 1)
+    }
+
     fn message(&self, message_id: MessageId) -> Option<Cow<Message>> {
         let channel_id = &message_id.channel_id;
         let arrived_at: i64 = message_id
@@ -527,7 +889,9 @@ impl Storage for SqliteStorage {
                         q.body_ranges AS "quote_body_ranges: _",
                         q.receipt AS "quote_receipt: _",
                         m.edit,
-                        m.edited as "edited: _"
+                        m.edited as "edited: _",
+                        m.forwarded AS "forwarded: _",
+                        m.send_failed
                     FROM messages AS m
                     LEFT JOIN messages AS q ON q.arrived_at = m.quote AND q.channel_id = ?1
                     WHERE m.channel_id = ?1 AND m.arrived_at = ?2
@@ -545,68 +909,49 @@ impl Storage for SqliteStorage {
     }
 
     fn store_message(&mut self, channel_id: ChannelId, message: Message) -> Cow<Message> {
+        self.insert_message(channel_id, &message, true);
+        Cow::Owned(message)
+    }
+
+    fn store_new_message(
+        &mut self,
+        channel_id: ChannelId,
+        message: Message,
+    ) -> Option<Cow<Message>> {
+        self.insert_message(channel_id, &message, false)
+            .then_some(Cow::Owned(message))
+    }
+
+    fn delete_messages_before(&mut self, channel_id: ChannelId, arrived_at: u64) {
         let channel_id = &channel_id;
-        let arrived_at: i64 = message
-            .arrived_at
-            .try_into()
-            .map_err(|_| MessageConvertError::InvalidTimestamp)
-            .ok_logged()
-            .unwrap();
-        let from_id = &message.from_id;
-        let message_msg = message.message.as_deref();
-        let quote: Option<i64> = message.quote.as_ref().and_then(|quote| {
-            quote
-                .arrived_at
-                .try_into()
-                .map_err(|_| MessageConvertError::InvalidTimestamp)
-                .ok_logged()
-        });
-        let receipt = BlobData(&message.receipt);
-        let body_ranges = BlobData(&message.body_ranges);
-        let attachments = BlobData(&message.attachments);
-        let reactions = BlobData(&message.reactions);
-        let edit: Option<i64> = message.edit.and_then(|edit| {
-            edit.try_into()
-                .map_err(|_| MessageConvertError::InvalidTimestamp)
-                .ok_logged()
-        });
-        let edited: bool = message.edited;
-        let inserted = self.execute(|ctx| {
+        let arrived_at = i64::try_from(arrived_at).unwrap_or(i64::MAX);
+        self.execute(|ctx| {
             Box::pin(
                 sqlx::query!(
-                    "
-                    REPLACE INTO messages(
-                        arrived_at,
-                        channel_id,
-                        from_id,
-                        message,
-                        quote,
-                        receipt,
-                        body_ranges,
-                        attachments,
-                        reactions,
-                        edit,
-                        edited
-                    )
-                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-                    ",
+                    "DELETE FROM messages WHERE channel_id = ?1 AND (arrived_at < ?2 OR edit < ?2)",
+                    channel_id,
                     arrived_at,
+                )
+                .execute(ctx.conn),
+            )
+        })
+        .ok_logged();
+    }
+
+    fn delete_message(&mut self, message_id: MessageId) {
+        let channel_id = &message_id.channel_id;
+        let arrived_at = i64::try_from(message_id.arrived_at).unwrap_or(i64::MAX);
+        self.execute(|ctx| {
+            Box::pin(
+                sqlx::query!(
+                    "DELETE FROM messages WHERE channel_id = ?1 AND (arrived_at = ?2 OR edit = ?2)",
                     channel_id,
-                    from_id,
-                    message_msg,
-                    quote,
-                    receipt,
-                    body_ranges,
-                    attachments,
-                    reactions,
-                    edit,
-                    edited
+                    arrived_at,
                 )
                 .execute(ctx.conn),
             )
-        });
-        inserted.ok_logged();
-        Cow::Owned(message)
+        })
+        .ok_logged();
     }
 
     fn names(&self) -> Box<dyn Iterator<Item = (Uuid, Cow<str>)> + '_> {
@@ -661,29 +1006,44 @@ impl Storage for SqliteStorage {
         let metadata = self.execute(|ctx| {
             Box::pin(
                 sqlx::query_as!(
-                    Metadata,
+                    SqlMetadata,
                     r#"
                         SELECT
                             contacts_sync_request_at AS "contacts_sync_request_at: _",
-                            fully_migrated AS "fully_migrated: _"
+                            fully_migrated AS "fully_migrated: _",
+                            recent_files AS "recent_files: _",
+                            contact_notes AS "contact_notes: _",
+                            contacts AS "contacts: _"
                         FROM metadata WHERE id = 0 LIMIT 1
                     "#,
                 )
                 .fetch_optional(ctx.conn),
             )
         });
-        Cow::Owned(metadata.ok_logged().flatten().unwrap_or_default())
+        Cow::Owned(
+            metadata
+                .ok_logged()
+                .flatten()
+                .map(SqlMetadata::convert)
+                .unwrap_or_default(),
+        )
     }
 
     fn store_metadata(&mut self, metadata: Metadata) -> Cow<Metadata> {
+        let recent_files = BlobData(metadata.recent_files.as_slice());
+        let contact_notes = BlobData(&metadata.contact_notes);
+        let contacts = BlobData(&metadata.contacts);
         self.execute(|ctx| {
             Box::pin(
                 sqlx::query!(
-                    "REPLACE INTO metadata(id, contacts_sync_request_at, fully_migrated)
-                     VALUES (?, ?, ?)",
+                    "REPLACE INTO metadata(id, contacts_sync_request_at, fully_migrated, recent_files, contact_notes, contacts)
+                     VALUES (?, ?, ?, ?, ?, ?)",
                     METADATA_ID,
                     metadata.contacts_sync_request_at,
-                    metadata.fully_migrated
+                    metadata.fully_migrated,
+                    recent_files,
+                    contact_notes,
+                    contacts
                 )
                 .execute(ctx.conn),
             )
@@ -692,7 +1052,9 @@ impl Storage for SqliteStorage {
         Cow::Owned(metadata)
     }
 
-    fn save(&mut self) {}
+    fn save(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
 
     fn message_channel(&self, arrived_at: u64) -> Option<ChannelId> {
         struct SqlChannelId {
@@ -727,10 +1089,11 @@ impl Storage for SqliteStorage {
 
 #[cfg(test)]
 mod tests {
-    use chrono::Utc;
     use tempfile::tempdir;
     use uuid::uuid;
 
+    use crate::storage::RecentFileKind;
+
     use super::*;
 
     fn fixtures() -> SqliteStorage {
@@ -744,6 +1107,14 @@ mod tests {
             group_data: None,
             unread_messages: 1,
             typing: TypingSet::new(false),
+            muted_until: None,
+            pinned: false,
+            archived: false,
+            burn_after_reading: false,
+            max_messages: None,
+            ignore_mention_all: false,
+            notification_content: None,
+            nickname: None,
         });
         storage.store_message(
             user_channel,
@@ -759,6 +1130,7 @@ mod tests {
                 send_failed: Default::default(),
                 edit: Default::default(),
                 edited: Default::default(),
+                forwarded: Default::default(),
             },
         );
 
@@ -772,6 +1144,14 @@ mod tests {
             group_data: None,
             unread_messages: 2,
             typing: TypingSet::new(true),
+            muted_until: None,
+            pinned: false,
+            archived: false,
+            burn_after_reading: false,
+            max_messages: None,
+            ignore_mention_all: false,
+            notification_content: None,
+            nickname: None,
         });
         storage.store_message(
             group_channel,
@@ -787,6 +1167,7 @@ mod tests {
                 send_failed: Default::default(),
                 edit: Default::default(),
                 edited: Default::default(),
+                forwarded: Default::default(),
             },
         );
 
@@ -830,6 +1211,116 @@ mod tests {
         assert_eq!(message.message.as_deref(), Some("hello"));
     }
 
+    #[test]
+    fn test_sqlite_storage_messages_before() {
+        let _ = tracing_subscriber::fmt().with_test_writer().try_init();
+        let mut storage = fixtures();
+        let id: Uuid = "966960e0-a8cd-43f1-ac7a-2c986dd470cd".parse().unwrap();
+        for arrived_at in 1664832050001..1664832050004 {
+            let message = Message::new(id, None, [], arrived_at, Vec::new());
+            storage.store_message(id.into(), message);
+        }
+
+        let arrived_at = |messages: Vec<Cow<Message>>| -> Vec<u64> {
+            messages.iter().map(|message| message.arrived_at).collect()
+        };
+        assert_eq!(
+            arrived_at(storage.messages_before(id.into(), u64::MAX, 2)),
+            [1664832050002, 1664832050003]
+        );
+        assert_eq!(
+            arrived_at(storage.messages_before(id.into(), 1664832050002, 10)),
+            [1664832050000, 1664832050001]
+        );
+    }
+
+    #[test]
+    fn test_sqlite_storage_limit_cutoff() {
+        let _ = tracing_subscriber::fmt().with_test_writer().try_init();
+        let mut storage = fixtures();
+        let id: Uuid = "966960e0-a8cd-43f1-ac7a-2c986dd470cd".parse().unwrap();
+        for arrived_at in 1664832050001..1664832050004 {
+            let message = Message::new(id, None, [], arrived_at, Vec::new());
+            storage.store_message(id.into(), message);
+        }
+
+        assert_eq!(storage.limit_cutoff(id.into(), 2), Some(1664832050002));
+        assert_eq!(storage.limit_cutoff(id.into(), 0), Some(1664832050004));
+        assert_eq!(storage.limit_cutoff(id.into(), 4), None);
+    }
+
+    #[test]
+    fn test_sqlite_storage_delete_messages_before() {
+        let _ = tracing_subscriber::fmt().with_test_writer().try_init();
+        let mut storage = fixtures();
+        let id: Uuid = "966960e0-a8cd-43f1-ac7a-2c986dd470cd".parse().unwrap();
+        let message = Message::new(id, Some("later".to_owned()), [], 1664832050002, Vec::new());
+        storage.store_message(id.into(), message);
+        // edit of the first message arrived after the cutoff
+        let mut edit = Message::new(id, Some("edited".to_owned()), [], 1664832050003, Vec::new());
+        edit.edit = Some(1664832050000);
+        storage.store_message(id.into(), edit);
+
+        storage.delete_messages_before(id.into(), 1664832050001);
+        let messages: Vec<_> = storage.messages(id.into()).collect();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].message.as_deref(), Some("later"));
+        assert_eq!(
+            storage.message(MessageId::new(id.into(), 1664832050003)),
+            None
+        );
+
+        // other channels are not affected
+        assert!(storage.message_channel(1664832050001).is_some());
+    }
+
+    #[test]
+    fn test_sqlite_storage_delete_message() {
+        let _ = tracing_subscriber::fmt().with_test_writer().try_init();
+        let mut storage = fixtures();
+        let id: Uuid = "966960e0-a8cd-43f1-ac7a-2c986dd470cd".parse().unwrap();
+        let message = Message::new(id, Some("later".to_owned()), [], 1664832050002, Vec::new());
+        storage.store_message(id.into(), message);
+        let mut edit = Message::new(id, Some("edited".to_owned()), [], 1664832050003, Vec::new());
+        edit.edit = Some(1664832050000);
+        storage.store_message(id.into(), edit);
+
+        storage.delete_message(MessageId::new(id.into(), 1664832050000));
+        let messages: Vec<_> = storage.messages(id.into()).collect();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].message.as_deref(), Some("later"));
+        assert_eq!(
+            storage.message(MessageId::new(id.into(), 1664832050003)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_sqlite_storage_search_messages() {
+        let _ = tracing_subscriber::fmt().with_test_writer().try_init();
+        let mut storage = fixtures();
+        let id: Uuid = "966960e0-a8cd-43f1-ac7a-2c986dd470cd".parse().unwrap();
+        let hello = MessageId::new(id.into(), 1664832050000);
+
+        assert_eq!(storage.search_messages("HEL", 10), [hello]);
+        assert!(storage.search_messages("hello world", 10).is_empty());
+        assert!(storage.search_messages("  ", 10).is_empty());
+        assert!(storage.search_messages("\"", 10).is_empty());
+        let world = storage.search_messages("world", 10);
+        assert_eq!(world.len(), 1);
+        assert_ne!(world[0].channel_id, id.into());
+
+        // the index is updated with the message
+        let mut message = storage.message(hello).unwrap().into_owned();
+        message.message = Some("changed".to_string());
+        storage.store_message(id.into(), message);
+        assert!(storage.search_messages("hello", 10).is_empty());
+        assert_eq!(storage.search_messages("change", 10), [hello]);
+
+        storage.delete_messages_before(id.into(), u64::MAX);
+        assert!(storage.search_messages("change", 10).is_empty());
+    }
+
     #[test]
     fn test_sqlite_storage_store_existing_message() {
         let _ = tracing_subscriber::fmt().with_test_writer().try_init();
@@ -853,6 +1344,31 @@ mod tests {
         assert_eq!(messages[0].message.as_deref(), Some("changed"));
     }
 
+    #[test]
+    fn test_sqlite_storage_store_new_message_deduplicated() {
+        let _ = tracing_subscriber::fmt().with_test_writer().try_init();
+        let mut storage = fixtures();
+        let channel_id = uuid!("966960e0-a8cd-43f1-ac7a-2c986dd470cd").into();
+        let from_id = uuid!("a955d20f-6b83-4e69-846e-a99b1779ff7a");
+        let other_id = uuid!("ac9b8aa1-691a-47e1-a566-d3e942945d07");
+        let arrived_at = 1664832050000;
+
+        let replayed = Message::text(from_id, arrived_at, "replayed".to_owned());
+        assert_eq!(storage.store_new_message(channel_id, replayed), None);
+        // a message of another sender with the same timestamp does not overwrite it either
+        let other = Message::text(other_id, arrived_at, "other".to_owned());
+        assert_eq!(storage.store_new_message(channel_id, other), None);
+        let message = storage
+            .message(MessageId::new(channel_id, arrived_at))
+            .unwrap();
+        assert_eq!(message.from_id, from_id);
+        assert_eq!(message.message.as_deref(), Some("hello"));
+
+        let new = Message::text(other_id, arrived_at + 2, "new".to_owned());
+        assert!(storage.store_new_message(channel_id, new).is_some());
+        assert_eq!(storage.messages(channel_id).count(), 2);
+    }
+
     #[test]
     fn test_sqlite_storage_store_new_message() {
         let _ = tracing_subscriber::fmt().with_test_writer().try_init();
@@ -897,6 +1413,7 @@ mod tests {
                 send_failed: Default::default(),
                 edit: Default::default(),
                 edited: Default::default(),
+                forwarded: Default::default(),
             },
         );
 
@@ -938,6 +1455,7 @@ mod tests {
         let _ = tracing_subscriber::fmt().with_test_writer().try_init();
         let mut storage = fixtures();
         assert_eq!(storage.metadata().contacts_sync_request_at, None);
+        let id = Uuid::new_v4();
 
         let dt = Utc::now();
         assert_eq!(
@@ -945,6 +1463,26 @@ mod tests {
                 .store_metadata(Metadata {
                     contacts_sync_request_at: Some(dt),
                     fully_migrated: Some(true),
+                    recent_files: vec![RecentFile {
+                        path: "/tmp/image.png".into(),
+                        kind: RecentFileKind::Sent,
+                    }],
+                    contact_notes: [(
+                        id,
+                        ContactNotes {
+                            birthday: Some("05-17".parse().unwrap()),
+                            notes: Some("met at the climbing gym".to_string()),
+                        },
+                    )]
+                    .into(),
+                    contacts: [(
+                        id,
+                        LocalContact {
+                            nickname: Some("Ali".to_string()),
+                            blocked: true,
+                        },
+                    )]
+                    .into(),
                 })
                 .contacts_sync_request_at,
             Some(dt)
@@ -953,9 +1491,22 @@ mod tests {
         let Metadata {
             contacts_sync_request_at,
             fully_migrated,
+            recent_files,
+            contact_notes,
+            contacts,
         } = storage.metadata().into_owned();
         assert_eq!(contacts_sync_request_at, Some(dt));
         assert_eq!(fully_migrated, Some(true));
+        assert_eq!(recent_files.len(), 1);
+        assert_eq!(recent_files[0].kind, RecentFileKind::Sent);
+        assert_eq!(
+            contact_notes[&id]
+                .birthday
+                .map(|birthday| birthday.to_string()),
+            Some("05-17".to_string())
+        );
+        assert_eq!(contacts[&id].nickname.as_deref(), Some("Ali"));
+        assert!(contacts[&id].blocked);
     }
 
     #[test]
@@ -1001,4 +1552,23 @@ mod tests {
         );
         assert_eq!(storage.message_channel(1664832050000), Some(channel_id));
     }
+
+    #[test]
+    fn test_sqlite_storage_failed_messages() {
+        let _ = tracing_subscriber::fmt().with_test_writer().try_init();
+        let mut storage = fixtures();
+        let from_id = uuid!("966960e0-a8cd-43f1-ac7a-2c986dd470cd");
+        let channel_id = ChannelId::User(uuid!("a955d20f-6b83-4e69-846e-a99b1779ff7a"));
+        assert_eq!(storage.failed_messages(), []);
+
+        let mut failed = Message::text(from_id, 1664832050000, "hello".to_owned());
+        failed.send_failed = Some("cancelled".to_owned());
+        storage.store_message(channel_id, failed);
+        let message_id = MessageId::new(channel_id, 1664832050000);
+        assert_eq!(storage.failed_messages(), [message_id]);
+        assert_eq!(
+            storage.message(message_id).unwrap().send_failed.as_deref(),
+            Some("cancelled")
+        );
+    }
 }
@@ -0,0 +1,14 @@
+use chrono::{DateTime, Local};
+
+pub fn utc_timestamp_msec_to_local(timestamp: u64) -> DateTime<Local> {
+    DateTime::from_timestamp((timestamp / 1000) as i64, (timestamp % 1000) as u32)
+        .expect("invalid datetime")
+        .with_timezone(&Local)
+}
+
+pub fn utc_now_timestamp_msec() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis() as u64
+}
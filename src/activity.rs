@@ -0,0 +1,166 @@
+//! Reports the number of unread messages to the terminal, e.g. for a badge in a status bar
+
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use base64::prelude::*;
+use tracing::warn;
+
+/// Reports changes of the number of unread messages as terminal user variable
+///
+/// The variable is set with the `OSC 1337 SetUserVar` escape sequence understood by WezTerm and
+/// iTerm2. Inside tmux, the sequence is passed through to the outer terminal (requires
+/// `allow-passthrough`), and the tmux user option `@<name>` is set in addition, so that it can be
+/// shown in the status line with `#{@<name>}`.
+///
+/// The summary of the unread messages is written into the terminal title, which tmux keeps as
+/// pane title, and into the user variable `<name>_summary`.
+pub struct ActivityReporter {
+    /// Name of the user variable; if unset, only the summary is written into the title
+    name: Option<String>,
+    in_tmux: bool,
+    last_count: Option<u32>,
+    last_summary: Option<String>,
+}
+
+impl ActivityReporter {
+    pub fn new(name: Option<String>) -> Self {
+        Self {
+            name,
+            in_tmux: std::env::var_os("TMUX").is_some(),
+            last_count: None,
+            last_summary: None,
+        }
+    }
+
+    /// Reports the number of unread messages, if it changed since the last report
+    pub fn report(&mut self, out: &mut impl Write, count: u32) -> io::Result<()> {
+        if self.last_count == Some(count) {
+            return Ok(());
+        }
+        self.last_count = Some(count);
+        let Some(name) = &self.name else {
+            return Ok(());
+        };
+        self.set_var(out, name, &count.to_string())?;
+        out.flush()
+    }
+
+    /// Reports the summary of the unread messages, if it changed since the last report
+    ///
+    /// Without summary, e.g. when the terminal is focused again, the title is reset.
+    pub fn report_summary(
+        &mut self,
+        out: &mut impl Write,
+        summary: Option<String>,
+    ) -> io::Result<()> {
+        if self.last_summary == summary {
+            return Ok(());
+        }
+        self.last_summary = summary;
+        let summary = self.last_summary.as_deref();
+        // tmux sets the pane title itself, so this is not passed through
+        write!(out, "\x1b]2;{}\x07", summary.unwrap_or("gurk"))?;
+        if let Some(name) = &self.name {
+            self.set_var(out, &format!("{name}_summary"), summary.unwrap_or_default())?;
+        }
+        out.flush()
+    }
+
+    fn set_var(&self, out: &mut impl Write, name: &str, value: &str) -> io::Result<()> {
+        let sequence = set_user_var(name, value);
+        if self.in_tmux {
+            out.write_all(tmux_passthrough(&sequence).as_bytes())?;
+            set_tmux_option(format!("@{name}"), value.to_string());
+            Ok(())
+        } else {
+            out.write_all(sequence.as_bytes())
+        }
+    }
+}
+
+fn set_tmux_option(option: String, value: String) {
+    // don't block the UI on tmux
+    std::thread::spawn(move || {
+        let status = Command::new("tmux")
+            .args(["set-option", "-gq", &option, &value])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        if let Err(error) = status {
+            warn!(%error, "failed to set tmux option");
+        }
+    });
+}
+
+fn set_user_var(name: &str, value: &str) -> String {
+    format!(
+        "\x1b]1337;SetUserVar={name}={}\x07",
+        BASE64_STANDARD.encode(value)
+    )
+}
+
+/// Wraps the escape sequence such that tmux passes it through to the outer terminal
+fn tmux_passthrough(sequence: &str) -> String {
+    format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_only_changes() {
+        let mut reporter = ActivityReporter {
+            name: Some("gurk_unread".to_string()),
+            in_tmux: false,
+            last_count: None,
+            last_summary: None,
+        };
+        let mut out = Vec::new();
+        reporter.report(&mut out, 12).unwrap();
+        assert_eq!(out, b"\x1b]1337;SetUserVar=gurk_unread=MTI=\x07");
+
+        out.clear();
+        reporter.report(&mut out, 12).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_report_summary() {
+        let mut reporter = ActivityReporter {
+            name: Some("gurk_unread".to_string()),
+            in_tmux: false,
+            last_count: None,
+            last_summary: None,
+        };
+        let mut out = Vec::new();
+        reporter.report_summary(&mut out, None).unwrap();
+        assert!(out.is_empty());
+
+        reporter
+            .report_summary(&mut out, Some("3 unread in 2 chats".to_string()))
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out.clone()).unwrap(),
+            "\x1b]2;3 unread in 2 chats\x07\
+             \x1b]1337;SetUserVar=gurk_unread_summary=MyB1bnJlYWQgaW4gMiBjaGF0cw==\x07"
+        );
+
+        out.clear();
+        reporter.report_summary(&mut out, None).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1b]2;gurk\x07\x1b]1337;SetUserVar=gurk_unread_summary=\x07"
+        );
+    }
+
+    #[test]
+    fn test_tmux_passthrough() {
+        assert_eq!(
+            tmux_passthrough("\x1b]1337;SetUserVar=a=MA==\x07"),
+            "\x1bPtmux;\x1b\x1b]1337;SetUserVar=a=MA==\x07\x1b\\"
+        );
+    }
+}
@@ -1,31 +1,63 @@
+use crate::attachment_cache::AttachmentCache;
+use crate::attachment_gallery::AttachmentGallery;
+use crate::attachment_picker::{attachment_input, AttachmentPicker};
+use crate::captcha_prompt::CaptchaPrompt;
+use crate::channel_info::ChannelInfo;
 use crate::channels::SelectChannel;
 use crate::command::{
-    get_keybindings, Command, DirectionVertical, ModeKeybinding, MoveAmountText, MoveAmountVisual,
-    MoveDirection, Widget, WindowMode,
+    self, get_keybindings, Command, DirectionVertical, MessageSelector, ModeKeybinding,
+    MoveAmountText, MoveAmountVisual, MoveDirection, Widget, WindowMode,
 };
-use crate::config::Config;
-use crate::data::{BodyRange, Channel, ChannelId, Message, TypingAction, TypingSet};
-use crate::event::Event;
+use crate::config::{ChannelOrder, Config, ReactionNotifications, ThemeConfig, ThemeMode};
+use crate::contacts::ContactList;
+use crate::cursor::Cursor;
+use crate::data::{
+    BodyRange, Channel, ChannelId, Message, NotificationContent, TypingAction, TypingSet,
+};
+use crate::dbus::{self, MessageSignal};
+use crate::effects::{EffectKind, Effects};
+use crate::error_log::{ErrorKind, ErrorLog};
+use crate::event::{Event, NotificationAction, NotificationSignal, Presence};
+use crate::history::InputHistory;
+use crate::hooks::{self, ChannelEvent, ChannelSummary, HookAction, HookEvent, MessageEvent};
 use crate::input::Input;
+use crate::local_delete::delete_attachment_files;
+use crate::memory_profile::ChannelMemory;
+use crate::message_search::MessageSearch;
+use crate::metrics::Metrics;
+use crate::names::Names;
+use crate::plugins::{PluginAction, Plugins};
+use crate::profile::Profile;
+use crate::rate_limit::RateLimitQueue;
+use crate::reaction_picker::ReactionPicker;
 use crate::receipt::{Receipt, ReceiptEvent, ReceiptHandler};
+use crate::recent_files::RecentFiles;
+use crate::redact::Sensitive;
+use crate::rpc;
+use crate::scheduler::Scheduler;
 use crate::signal::{
-    Attachment, GroupIdentifierBytes, GroupMasterKeyBytes, ProfileKeyBytes, ResolvedGroup,
-    SignalManager,
+    Attachment, CaptchaRequired, GroupIdentifierBytes, GroupMasterKeyBytes, LinkedDevice,
+    PendingSend, ProfileKeyBytes, RateLimited, ResolvedGroup, SignalManager,
+};
+use crate::snippet;
+use crate::storage::{ContactNotes, MessageId, RecentFile, RecentFileKind, Storage};
+use crate::ui::{
+    sender_palette, DirtyPanes, LayoutCache, Palette, Pane, PaneBuffers, TerminalCapabilities,
+    Theme,
 };
-use crate::storage::{MessageId, Storage};
-use crate::util::{self, LazyRegex, StatefulList, ATTACHMENT_REGEX, URL_REGEX};
-use std::cell::Cell;
-use std::io::Cursor;
+use crate::util::{self, expand_home, LazyRegex, StatefulList, ATTACHMENT_REGEX, URL_REGEX};
+use std::cell::RefCell;
 
 use anyhow::{anyhow, Context as _};
 use arboard::Clipboard;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use crokey::Combiner;
 use crossterm::event::{KeyCode, KeyEvent};
 use image::codecs::png::PngEncoder;
 use image::{ImageBuffer, ImageEncoder, Rgba};
 use itertools::Itertools;
 use notify_rust::Notification;
+use phonenumber::PhoneNumber;
 use presage::libsignal_service::content::{Content, ContentBody, Metadata};
 use presage::libsignal_service::sender::AttachmentSpec;
 use presage::proto::{
@@ -34,6 +66,7 @@ use presage::proto::{
     GroupContextV2,
 };
 use presage::proto::{AttachmentPointer, DataMessage, ReceiptMessage, SyncMessage, TypingMessage};
+use ratatui::style::Color;
 use regex::Regex;
 use tokio::sync::mpsc;
 use tracing::{debug, error, info, warn};
@@ -41,37 +74,200 @@ use uuid::Uuid;
 
 use std::borrow::Cow;
 use std::cmp::Reverse;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::convert::TryInto;
+use std::fmt;
 use std::future::Future;
-use std::path::Path;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 /// Amount of time to skip contacts sync after the last sync
 const CONTACTS_SYNC_DEADLINE_SEC: i64 = 60 * 60 * 24; // 1 day
 const CONTACTS_SYNC_TIMEOUT: Duration = Duration::from_secs(20);
+/// Interval of deleting the messages beyond the configured retention limits
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Number of messages loaded at once for deleting their attachments
+pub(crate) const DELETE_BATCH: usize = 500;
+/// How long the result of sending a message is shown in the status bar
+const SEND_STATUS_DURATION: Duration = Duration::from_secs(3);
+/// How long the result of the focus command is reused for the following notifications
+const FOCUS_COMMAND_TTL: Duration = Duration::from_secs(2);
+/// Text of the notifications about messages and reactions, which hide their content
+const NEW_MESSAGE: &str = "New message";
+const NEW_REACTION: &str = "New reaction";
+/// Identifiers of the actions of notifications; `default` is invoked by clicking the notification
+const OPEN_ACTION: &str = "default";
+const MARK_READ_ACTION: &str = "mark_read";
+/// Action for replying in the notification, supported by KDE Plasma and KDE Connect
+const REPLY_ACTION: &str = "inline-reply";
+/// Number of notifications remembered for handling their actions received via D-Bus
+const MAX_TRACKED_NOTIFICATIONS: usize = 100;
+/// Number of channels whose first page of messages is loaded at once, between handling events
+const LOAD_CHANNELS_BATCH: usize = 20;
 
 pub struct App {
     pub config: Config,
-    signal_manager: Box<dyn SignalManager>,
+    pub(crate) signal_manager: Box<dyn SignalManager>,
     pub storage: Box<dyn Storage>,
     pub channels: StatefulList<ChannelId>,
+    /// Positions of the channels in `channels`, see [`App::channel_idx`]
+    channel_index: RefCell<HashMap<ChannelId, usize>>,
     pub messages: BTreeMap<ChannelId, StatefulList<u64 /* arrived at*/>>,
+    /// Channels with older messages in storage than the loaded ones
+    partially_loaded: BTreeSet<ChannelId>,
+    /// Channels of which only the newest message is loaded yet, see
+    /// [`App::load_messages_in_background`]
+    unloaded_channels: BTreeSet<ChannelId>,
     pub help_scroll: (u16, u16),
+    /// Number of lines of the help shown at the last render
+    pub(crate) help_page_height: u16,
     pub user_id: Uuid,
     pub should_quit: bool,
+    /// What to do after quitting, e.g. relinking this device
+    pub after_quit: AfterQuit,
+    /// State of the connection to the Signal servers, shown in the status bar
+    pub connection_state: ConnectionState,
+    /// Messages sent while offline
+    pub(crate) outbox: Vec<QueuedMessage>,
+    /// Messages being sent, kept for resending them if the server requires a captcha
+    pending_sends: BTreeMap<MessageId, QueuedMessage>,
+    /// Timestamp of the last message sent, the next one is sent at least a millisecond later
+    last_sent_timestamp: u64,
+    /// Messages rejected by the server or failed to be sent, kept for retrying them
+    failed_sends: BTreeMap<MessageId, QueuedMessage>,
+    /// Messages rejected because sending was rate limited, resent after waiting
+    pub(crate) rate_limited: RateLimitQueue,
+    /// Running sends and downloads
+    pub(crate) effects: Effects,
+    /// Result of the last sent message, shown in the status bar for a while
+    send_status: Option<SendStatus>,
     url_regex: LazyRegex,
     attachment_regex: LazyRegex,
     display_help: bool,
     receipt_handler: ReceiptHandler,
     pub input: Input,
     pub is_multiline_input: bool,
-    editing: Option<MessageId>,
+    /// Message edited by sending the input
+    pub(crate) editing: Option<MessageId>,
+    send_preview: Option<SendPreview>,
+    pub(crate) input_history: InputHistory,
+    /// Error of the last command entered in the input box
+    pub(crate) command_error: Option<String>,
+    /// Completions or usage of the command in the input box
+    pub(crate) command_hint: Option<String>,
     pub(crate) select_channel: SelectChannel,
-    clipboard: Option<Clipboard>,
-    event_tx: mpsc::UnboundedSender<Event>,
-    // It is expensive to hit the signal manager contacts storage, so we cache it
-    names_cache: Cell<Option<BTreeMap<Uuid, String>>>,
+    pub(crate) attachment_picker: AttachmentPicker,
+    pub(crate) recent_files: RecentFiles,
+    pub(crate) attachment_gallery: AttachmentGallery,
+    pub(crate) contact_list: ContactList,
+    pub(crate) reaction_picker: ReactionPicker,
+    pub(crate) captcha_prompt: CaptchaPrompt,
+    pub(crate) message_search: MessageSearch,
+    /// Index of the downloaded attachments, for limiting their disk usage
+    pub attachment_cache: AttachmentCache,
+    /// Counters exposed by the metrics endpoint of the daemon
+    pub metrics: Metrics,
+    /// Whether the popup with the diagnostics is shown
+    pub(crate) show_diagnostics: bool,
+    /// Whether the diagnostics include the memory used by the messages of each channel
+    pub(crate) profile_memory: bool,
+    /// Memory profile shown in the diagnostics, collected when they are opened and on ticks
+    pub(crate) memory_profile: Vec<ChannelMemory>,
+    /// When the shown memory profile was collected
+    pub(crate) memory_profiled_at: Option<Instant>,
+    /// Recent errors, listed in a popup
+    pub(crate) error_log: ErrorLog,
+    /// Whether the network connection is metered, for not downloading attachments right away
+    pub(crate) is_metered: bool,
+    /// Background jobs, paused while not connected
+    pub(crate) scheduler: Scheduler,
+    /// Devices linked to the account shown in a popup, or the error of fetching them
+    pub(crate) linked_devices: Option<Result<Vec<LinkedDevice>, String>>,
+    /// Channels marked for applying a command to several channels at once
+    pub(crate) marked_channels: BTreeSet<ChannelId>,
+    /// Messages marked for applying a command to several messages of a channel at once
+    pub(crate) marked_messages: BTreeSet<MessageId>,
+    /// Whether archived channels are shown in the channel list
+    pub(crate) show_archived: bool,
+    /// Whether the time since the last message is shown under each channel
+    pub(crate) show_channel_details: bool,
+    /// Whether the channel list is hidden
+    pub(crate) sidebar_hidden: bool,
+    /// Whether the panel with the details of the selected channel is shown
+    pub(crate) show_channel_info: bool,
+    /// Details of the channel shown in the channel info panel
+    pub(crate) channel_info: Option<ChannelInfo>,
+    /// Whether the channel list is shown above the chat
+    pub(crate) stacked_layout: bool,
+    /// Whether the newest message is shown at the top of the chat
+    pub(crate) newest_first: bool,
+    /// Palette of the colors of the senders
+    pub(crate) sender_colors: Vec<Color>,
+    /// Symbols and colors the terminal can display
+    pub terminal: TerminalCapabilities,
+    /// Colors for the background of the terminal
+    pub theme: Theme,
+    /// Times from which the light and the dark theme are used in the `auto` theme mode
+    theme_schedule: Option<(NaiveTime, NaiveTime)>,
+    /// Colors of the parts of the UI
+    pub(crate) palette: Palette,
+    /// Panes to draw in the next frame
+    pub dirty: DirtyPanes,
+    /// Unchanged panes are copied from the previous frames
+    pub(crate) pane_buffers: PaneBuffers,
+    /// Reactions sent with `quick_react`, resolved from the shortcodes in the config
+    favorite_reactions: Vec<String>,
+    /// Region of phone numbers without country code
+    phone_region: Option<phonenumber::country::Id>,
+    /// When the last message was sent from the input box
+    last_sent_at: Option<Instant>,
+    /// Whether the terminal is focused; stays set if the terminal does not report focus changes
+    pub(crate) is_focused: bool,
+    /// When the terminal gained or lost the focus the last time
+    focus_changed_at: Instant,
+    /// Last result of the focus command, with the command and when it ran, so that a burst of
+    /// notifications runs it once
+    focus_command_result: RefCell<Option<(String, Instant, bool)>>,
+    /// When the last key was pressed, for detecting that the user is idle
+    last_key_at: Instant,
+    /// When the messages highlighted in the selected channel arrived
+    highlighted_messages: BTreeMap<MessageId, Instant>,
+    /// When the channel list was sorted the last time
+    last_sorted_at: Instant,
+    /// When the messages beyond the retention limits were deleted the last time
+    last_pruned_at: Option<Instant>,
+    /// Whether the config was changed, e.g. by importing a profile, and has to be saved
+    is_config_changed: bool,
+    /// Contacts with a birthday today, marked in the channel list
+    pub(crate) birthdays_today: BTreeSet<Uuid>,
+    /// Local date the birthdays were looked up the last time
+    birthdays_checked_on: Option<NaiveDate>,
+    pub(crate) clipboard: Option<Clipboard>,
+    /// Lua plugins, if any are installed
+    plugins: Option<Plugins>,
+    /// Connections to the RPC socket subscribed to received messages
+    pub(crate) rpc_subscribers: Vec<mpsc::UnboundedSender<String>>,
+    /// Received messages emitted as signals by the D-Bus service, if it runs
+    dbus_signals: Option<mpsc::UnboundedSender<MessageSignal>>,
+    /// Whether the signals of the notification daemon are received, by the D-Bus service or by
+    /// the listener started with `listen_notifications`
+    receives_notification_signals: bool,
+    /// Channels of the notifications shown, by the id of the notification, for handling their
+    /// actions received via D-Bus
+    notification_channels: RefCell<BTreeMap<u32, ChannelId>>,
+    /// Set via D-Bus, e.g. for silencing gurk during presentations
+    pub(crate) presence: Presence,
+    /// Running as daemon without the TUI
+    pub(crate) is_headless: bool,
+    /// Connection of the TUI which asked the daemon to hand over
+    pub(crate) detach_lease: Option<mpsc::UnboundedSender<String>>,
+    /// Running the demo, which must not touch the config of the user
+    pub(crate) is_demo: bool,
+    pub(crate) event_tx: mpsc::UnboundedSender<Event>,
+    /// Names of the known users; it is expensive to hit the signal manager contacts storage
+    pub(crate) names: RefCell<Names>,
+    /// Wrapped messages of the drawn channels
+    pub(crate) message_layouts: RefCell<LayoutCache>,
     pub mode_keybindings: ModeKeybinding,
 }
 
@@ -84,26 +280,24 @@ impl App {
         let user_id = signal_manager.user_id();
 
         // build index of channels and messages for using them as lists content
+        //
+        // Only the newest message of each channel is loaded, which is enough for sorting the
+        // channels. The other messages are loaded in the background.
         let mut channels: StatefulList<ChannelId> = Default::default();
         let mut messages: BTreeMap<_, StatefulList<_>> = BTreeMap::new();
+        let mut partially_loaded = BTreeSet::new();
+        let mut unloaded_channels = BTreeSet::new();
         for channel in storage.channels() {
             channels.items.push(channel.id);
             let channel_messages = &mut messages.entry(channel.id).or_default().items;
-            for message in storage.messages(channel.id) {
+            for message in storage.messages_before(channel.id, u64::MAX, 1) {
                 channel_messages.push(message.arrived_at);
             }
+            if !channel_messages.is_empty() {
+                partially_loaded.insert(channel.id);
+                unloaded_channels.insert(channel.id);
+            }
         }
-        channels.items.sort_unstable_by_key(|channel_id| {
-            let last_message_arrived_at = storage
-                .messages(*channel_id)
-                .next_back()
-                .map(|msg| msg.arrived_at);
-            let channel_name = storage
-                .channel(*channel_id)
-                .map(|channel| channel.name.clone());
-            (Reverse(last_message_arrived_at), channel_name)
-        });
-        channels.next();
 
         let clipboard = Clipboard::new()
             .map_err(|error| warn!(%error, "clipboard disabled"))
@@ -111,54 +305,190 @@ impl App {
 
         let (event_tx, event_rx) = mpsc::unbounded_channel();
 
-        let mode_keybindings = get_keybindings(&config.keybindings, config.default_keybindings)
-            .expect("keybinding configuration failed");
+        let mode_keybindings = get_keybindings(
+            &config.keybindings,
+            config.default_keybindings,
+            config.enter_sends,
+        )
+        .expect("keybinding configuration failed");
+
+        let show_channel_details = config.channel_details;
+        let sidebar_hidden = config.layout.hide_sidebar;
+        let stacked_layout = config.layout.stacked;
+        let newest_first = config.layout.newest_first;
+        let sender_colors = sender_palette(&config.sender_colors)?;
+        let phone_region = config.phone_numbers.region()?;
+        let theme_schedule = config.theme.schedule()?;
+        let theme = match config.theme.mode {
+            ThemeMode::Light => Theme::Light,
+            ThemeMode::Dark | ThemeMode::Auto => Theme::Dark,
+        };
+        let palette = Palette::from_config(&config.theme)?;
+        let favorite_reactions = config
+            .favorite_reactions
+            .iter()
+            .map(|reaction| {
+                to_emoji(reaction)
+                    .map(str::to_string)
+                    .with_context(|| format!("invalid favorite reaction: {reaction}"))
+            })
+            .collect::<anyhow::Result<_>>()?;
 
-        let app = Self {
+        let mut app = Self {
             config,
             signal_manager,
             user_id,
             storage,
             channels,
+            channel_index: Default::default(),
             messages,
+            partially_loaded,
+            unloaded_channels,
             help_scroll: (0, 0),
+            help_page_height: 0,
             should_quit: false,
+            after_quit: Default::default(),
             url_regex: LazyRegex::new(URL_REGEX),
             attachment_regex: LazyRegex::new(ATTACHMENT_REGEX),
             display_help: false,
             receipt_handler: ReceiptHandler::new(),
             input: Default::default(),
             is_multiline_input: false,
+            connection_state: Default::default(),
+            outbox: Default::default(),
+            pending_sends: Default::default(),
+            last_sent_timestamp: 0,
+            failed_sends: Default::default(),
+            rate_limited: Default::default(),
+            effects: Default::default(),
+            send_status: None,
             editing: None,
+            send_preview: None,
+            input_history: Default::default(),
+            command_error: None,
+            command_hint: None,
+            marked_channels: Default::default(),
+            marked_messages: Default::default(),
+            show_archived: false,
+            show_channel_details,
+            sidebar_hidden,
+            show_channel_info: false,
+            channel_info: None,
+            stacked_layout,
+            newest_first,
+            sender_colors,
+            terminal: Default::default(),
+            theme,
+            theme_schedule,
+            palette,
+            dirty: Default::default(),
+            pane_buffers: Default::default(),
+            favorite_reactions,
+            phone_region,
+            last_sent_at: None,
+            is_focused: true,
+            focus_changed_at: Instant::now(),
+            focus_command_result: Default::default(),
+            last_key_at: Instant::now(),
+            highlighted_messages: Default::default(),
+            last_sorted_at: Instant::now(),
+            last_pruned_at: None,
+            is_config_changed: false,
+            birthdays_today: Default::default(),
+            birthdays_checked_on: None,
+            plugins: None,
+            rpc_subscribers: Vec::new(),
+            dbus_signals: None,
+            receives_notification_signals: false,
+            notification_channels: Default::default(),
+            presence: Default::default(),
+            is_headless: false,
+            detach_lease: None,
+            is_demo: false,
             select_channel: Default::default(),
+            attachment_picker: Default::default(),
+            recent_files: Default::default(),
+            attachment_gallery: Default::default(),
+            contact_list: Default::default(),
+            reaction_picker: Default::default(),
+            captcha_prompt: Default::default(),
+            message_search: Default::default(),
+            attachment_cache: Default::default(),
+            metrics: Default::default(),
+            show_diagnostics: false,
+            memory_profile: Vec::new(),
+            memory_profiled_at: None,
+            profile_memory: false,
+            error_log: Default::default(),
+            is_metered: false,
+            scheduler: Default::default(),
+            linked_devices: None,
             clipboard,
             event_tx,
-            names_cache: Default::default(),
+            names: Default::default(),
+            message_layouts: Default::default(),
             mode_keybindings,
         };
+        app.sort_channels();
+        app.channels.next();
+        app.step_theme();
+
         Ok((app, event_rx))
     }
 
-    /// Resolve and cache all user names for the known user channels
+    /// Resolve and cache all user names for the known user channels, and the names and phone
+    /// numbers of the contacts
     pub async fn populate_names_cache(&self) {
-        let mut names_cache = BTreeMap::new();
+        let mut resolved = BTreeMap::new();
         for user_id in self
             .storage
             .channels()
             .filter_map(|channel| channel.id.user())
         {
             if let Some(name) = self.resolve_name(user_id).await {
-                names_cache.insert(user_id, name);
+                resolved.insert(user_id, name);
+            }
+        }
+        let contacts = self.signal_manager.contacts().await;
+        let mut names = self.names.borrow_mut();
+        for (user_id, name) in resolved {
+            names.set_display_name(user_id, name);
+        }
+        for contact in contacts {
+            if let Some(phone_number) = contact.phone_number {
+                names.set_phone_number(contact.uuid, phone_number);
             }
+            // contacts without a channel are named for finding them, e.g. with `/dm`
+            if names.display_name(contact.uuid).is_none() && !contact.name.trim().is_empty() {
+                names.set_display_name(contact.uuid, contact.name);
+            }
+        }
+        for (&user_id, contact) in &self.storage.metadata().contacts {
+            if contact.nickname.is_some() {
+                names.set_nickname(user_id, contact.nickname.clone());
+            }
+            names.set_blocked(user_id, contact.blocked);
         }
-        let mut cache = self.names_cache.take().unwrap_or_default();
-        cache.extend(names_cache);
-        self.names_cache.replace(Some(cache));
+        drop(names);
+        // the messages show the resolved names
+        self.message_layouts.borrow_mut().clear();
     }
 
     pub fn get_input(&mut self) -> &mut Input {
         if self.select_channel.is_shown {
             &mut self.select_channel.input
+        } else if self.attachment_picker.is_shown {
+            &mut self.attachment_picker.input
+        } else if let Some(save_as) = self.attachment_gallery.save_as.as_mut() {
+            save_as
+        } else if let Some(rename) = self.contact_list.rename.as_mut() {
+            rename
+        } else if self.captcha_prompt.is_shown {
+            &mut self.captcha_prompt.input
+        } else if self.message_search.is_shown {
+            &mut self.message_search.input
+        } else if let Some(search) = self.input_history.search.as_mut() {
+            &mut search.query
         } else {
             &mut self.input
         }
@@ -185,6 +515,21 @@ impl App {
         }
     }
 
+    /// Name of the current input mode shown in the status bar
+    pub(crate) fn mode_name(&self) -> &'static str {
+        if self.input_history.search.is_some() {
+            "search"
+        } else if self.editing.is_some() {
+            "edit"
+        } else if self.selected_message_id().is_some() {
+            "message-select"
+        } else if self.is_multiline_input {
+            "multi-line"
+        } else {
+            "insert"
+        }
+    }
+
     /// Returns the name of a user by their ID from the cache without resolving it.
     pub fn name_by_id_cached(&self, id: Uuid) -> String {
         if self.user_id == id {
@@ -192,10 +537,10 @@ impl App {
             return self.config.user.name.clone();
         };
 
-        let cache = self.names_cache.take().unwrap_or_default();
-        let name = cache.get(&id).cloned().unwrap_or_else(|| id.to_string());
-        self.names_cache.replace(Some(cache));
-        name
+        self.names
+            .borrow()
+            .display_name(id)
+            .map_or_else(|| id.to_string(), str::to_owned)
     }
 
     /// Resolves name of a user by their id
@@ -207,12 +552,12 @@ impl App {
     /// 3. internal gurk's user name table
     async fn resolve_name(&self, user_id: Uuid) -> Option<String> {
         if let Some(name) = self.signal_manager.profile_name(user_id).await {
-            debug!(name, "resolved name as profile name");
+            debug!(name = %Sensitive(&name), "resolved name as profile name");
             return Some(name);
         }
         if let Some(contact) = self.signal_manager.contact(user_id).await {
             if !contact.name.trim().is_empty() {
-                debug!(name = contact.name, "resolved name from contacts");
+                debug!(name = %Sensitive(&contact.name), "resolved name from contacts");
                 return Some(contact.name);
             } else {
                 debug!(%user_id, "resolved empty name from contacts, skipping");
@@ -223,7 +568,7 @@ impl App {
             .name(user_id)
             .filter(|name| !name.trim().is_empty())
         {
-            debug!(%name, "resolved name from storage");
+            debug!(name = %Sensitive(&name), "resolved name from storage");
             return Some(name.into_owned());
         }
         None
@@ -240,20 +585,36 @@ impl App {
         }
     }
 
+    /// Resolved name of a user by their id, ignoring the nickname
+    ///
+    /// The nickname is only shown, so this is the name kept e.g. as the name of the channel with
+    /// the user.
+    pub(crate) async fn resolved_name_by_id(&self, id: Uuid) -> String {
+        if self.user_id == id {
+            return self.config.user.name.clone();
+        }
+        let name = self.names.borrow().resolved_name(id).map(str::to_owned);
+        if let Some(name) = name {
+            name
+        } else if let Some(name) = self.resolve_name(id).await {
+            self.names.borrow_mut().set_display_name(id, name.clone());
+            self.message_layouts.borrow_mut().clear();
+            name
+        } else {
+            id.to_string()
+        }
+    }
+
     async fn name_by_id_or_cache<F>(&self, id: Uuid, on_miss: impl FnOnce(Uuid) -> F) -> String
     where
         F: Future<Output = Option<String>>,
     {
-        let cache = self.names_cache.take().unwrap_or_default();
-        let name = cache.get(&id).cloned();
-        self.names_cache.replace(Some(cache));
-
+        let name = self.names.borrow().display_name(id).map(str::to_owned);
         if let Some(name) = name {
             name
         } else if let Some(name) = on_miss(id).await {
-            let mut cache = self.names_cache.take().unwrap_or_default();
-            cache.insert(id, name.clone());
-            self.names_cache.replace(Some(cache));
+            self.names.borrow_mut().set_display_name(id, name.clone());
+            self.message_layouts.borrow_mut().clear();
             name
         } else {
             id.to_string()
@@ -264,13 +625,26 @@ impl App {
         if let Some(id) = channel.user_id() {
             self.name_by_id_cached(id).into()
         } else {
-            (&channel.name).into()
+            channel.shown_name().into()
         }
     }
 
     pub async fn on_command(&mut self, command: Command) -> anyhow::Result<()> {
+        let command = match command {
+            // with the newest message at the top, the previous message is the one below
+            Command::SelectMessage(direction, amount) if self.newest_first => {
+                Command::SelectMessage(direction.reversed(), amount)
+            }
+            command => command,
+        };
         match command {
-            Command::Help => self.toggle_help(),
+            Command::Help(None) => self.toggle_help(),
+            Command::Help(Some(name)) => match command::describe(&name) {
+                Some(description) => self.command_hint = Some(description),
+                None => self.command_error = Some(format!("no command named {name}")),
+            },
+            Command::Attach(path) => self.attach(&path),
+            Command::CompleteCommand => self.complete_command(),
             Command::MoveText(MoveDirection::Previous, MoveAmountText::Word) => {
                 self.get_input().move_back_word()
             }
@@ -293,9 +667,11 @@ impl App {
                 self.on_pgup()
             }
             Command::SelectMessage(MoveDirection::Next, MoveAmountVisual::Entry) => self.on_pgdn(),
+            Command::SelectMessage(direction, amount) => self.select_message(direction, amount),
             Command::KillBackwardLine => self.get_input().on_delete_line(),
             Command::KillWord => self.get_input().on_delete_word(),
-            Command::CopyMessage(_) => self.copy_selection(),
+            Command::CopyMessage(MessageSelector::Selected) => self.copy_selection(),
+            Command::CopyMessage(MessageSelector::Marked) => self.copy_marked_messages(),
             Command::KillLine => self.get_input().on_delete_suffix(),
             Command::SelectChannel(MoveDirection::Previous) => self.select_previous_channel(),
             Command::SelectChannel(MoveDirection::Next) => self.select_next_channel(),
@@ -307,11 +683,20 @@ impl App {
             Command::EditMessage => {
                 self.start_editing();
             }
+            Command::RetrySend => self.retry_send(),
+            Command::DownloadAttachments => self.download_selected_attachments(),
+            Command::Cancel => self.cancel_selected(),
+            Command::ForwardMessage => self.pick_forward_channel(),
+            Command::ToggleMessageMark => self.toggle_message_mark(),
+            Command::DeleteMarkedMessages => self.delete_marked_messages(),
+            Command::DeleteMessageLocally => self.delete_selected_message_locally(),
+            Command::ExportMessages(path) => self.export_marked_messages(&path),
             // Command::ReplyMessage => unimplemented!("{command:?}"),
             // Command::DeleteMessage => unimplemented!("{command:?}"),
             Command::ToggleChannelModal => {
                 if !self.select_channel.is_shown {
-                    self.select_channel.reset(&*self.storage);
+                    self.select_channel
+                        .reset(&*self.storage, &self.names.borrow());
                 }
                 self.select_channel.is_shown = !self.select_channel.is_shown;
             }
@@ -323,9 +708,251 @@ impl App {
                     self.add_reaction(idx, reaction).await;
                 }
             }
+            Command::QuickReact(n) => {
+                let reaction = n
+                    .checked_sub(1)
+                    .and_then(|idx| self.favorite_reactions.get(idx))
+                    .cloned();
+                if let (Some(idx), Some(reaction)) = (self.channels.state.selected(), reaction) {
+                    self.add_reaction(idx, Some(reaction)).await;
+                }
+            }
+            Command::ToggleReactionPicker => {
+                if self.reaction_picker.is_shown {
+                    self.reaction_picker.close();
+                } else {
+                    self.open_reaction_picker();
+                }
+            }
+            Command::ToggleCaptchaPrompt => {
+                if self.captcha_prompt.is_shown {
+                    self.captcha_prompt.close();
+                } else {
+                    self.captcha_prompt.show();
+                }
+            }
+            Command::ToggleMessageSearch => {
+                if self.message_search.is_shown {
+                    self.message_search.close();
+                } else if !self.select_channel.is_shown && !self.attachment_picker.is_shown {
+                    self.message_search.open();
+                }
+            }
+            Command::ToggleDiagnostics => {
+                self.show_diagnostics = !self.show_diagnostics;
+                self.refresh_memory_profile();
+            }
+            Command::ToggleErrorLog => self.error_log.toggle(),
+            Command::ToggleDevices => self.toggle_devices().await,
+            Command::UnlinkDevice => self.unlink_device().await,
+            Command::Relink => {
+                self.after_quit = AfterQuit::Relink;
+                self.should_quit = true;
+            }
+            Command::SelectSearchResult(MoveDirection::Previous) => self.message_search.prev(),
+            Command::SelectSearchResult(MoveDirection::Next) => self.message_search.next(),
+            Command::SelectReaction(MoveDirection::Previous) => self.reaction_picker.prev(),
+            Command::SelectReaction(MoveDirection::Next) => self.reaction_picker.next(),
+            Command::ReactSelected => {
+                // selecting our own reaction removes it
+                let reaction = self
+                    .reaction_picker
+                    .selected()
+                    .map(|item| (!item.is_own).then(|| item.emoji.clone()));
+                self.reaction_picker.close();
+                if let (Some(reaction), Some(idx)) = (reaction, self.channels.state.selected()) {
+                    self.add_reaction(idx, reaction).await;
+                }
+            }
             Command::OpenUrl => {
                 self.try_open_url();
             }
+            Command::ToggleAttachmentPicker => {
+                if self.attachment_picker.is_shown {
+                    self.attachment_picker.close();
+                } else if !self.select_channel.is_shown {
+                    self.attachment_picker.open();
+                }
+            }
+            Command::SelectAttachment(MoveDirection::Previous) => self.attachment_picker.prev(),
+            Command::SelectAttachment(MoveDirection::Next) => self.attachment_picker.next(),
+            Command::MarkAttachment => self.attachment_picker.toggle_mark(),
+            Command::ToggleRecentFiles => {
+                if self.recent_files.is_shown {
+                    self.recent_files.close();
+                } else if !self.select_channel.is_shown && !self.attachment_picker.is_shown {
+                    self.recent_files.open(&*self.storage);
+                }
+            }
+            Command::SelectRecentFile(MoveDirection::Previous) => self.recent_files.prev(),
+            Command::SelectRecentFile(MoveDirection::Next) => self.recent_files.next(),
+            Command::AttachRecentFile => {
+                if let Some(file) = self.recent_files.selected() {
+                    let path = file.path.clone();
+                    self.insert_attachments(&[path]);
+                }
+                self.recent_files.close();
+            }
+            Command::OpenRecentFile => {
+                if let Some(file) = self.recent_files.selected() {
+                    let path = file.path.clone();
+                    self.open_attachment(&path);
+                }
+                self.recent_files.close();
+            }
+            Command::ToggleAttachmentGallery => {
+                if self.attachment_gallery.is_shown {
+                    self.attachment_gallery.close();
+                } else if !self.select_channel.is_shown && !self.attachment_picker.is_shown {
+                    if let Some(&channel_id) = self
+                        .channels
+                        .state
+                        .selected()
+                        .and_then(|idx| self.channels.items.get(idx))
+                    {
+                        self.attachment_gallery.open(&*self.storage, channel_id);
+                    }
+                }
+            }
+            Command::SelectGalleryAttachment(MoveDirection::Previous) => {
+                self.attachment_gallery.prev()
+            }
+            Command::SelectGalleryAttachment(MoveDirection::Next) => self.attachment_gallery.next(),
+            Command::FilterGalleryAttachments => self.attachment_gallery.cycle_filter(),
+            Command::OpenGalleryAttachment => {
+                if let Some(item) = self.attachment_gallery.selected() {
+                    let path = item.attachment.filename.clone();
+                    if item.exists {
+                        self.open_attachment(&path);
+                    } else {
+                        // opened when downloaded
+                        self.attachment_gallery.status =
+                            Some(match self.download_attachment(&path) {
+                                Ok(()) => {
+                                    self.attachment_gallery.opening = Some(path);
+                                    "downloading…".to_string()
+                                }
+                                Err(error) => format!("failed to download: {error}"),
+                            });
+                    }
+                }
+            }
+            Command::ToggleSaveAttachment => {
+                if self.attachment_gallery.save_as.is_some() {
+                    self.attachment_gallery.cancel_save_as();
+                } else {
+                    self.attachment_gallery.start_save_as();
+                }
+            }
+            Command::DeleteGalleryAttachment => self.attachment_gallery.delete_selected(),
+            Command::ToggleContacts => {
+                if self.contact_list.is_shown {
+                    self.contact_list.close();
+                } else if !self.select_channel.is_shown && !self.attachment_picker.is_shown {
+                    self.open_contact_list().await;
+                }
+            }
+            Command::SelectContact(MoveDirection::Previous) => self.contact_list.prev(),
+            Command::SelectContact(MoveDirection::Next) => self.contact_list.next(),
+            Command::ToggleRenameContact => {
+                if self.contact_list.rename.is_some() {
+                    self.contact_list.cancel_rename();
+                } else {
+                    self.contact_list.start_rename();
+                }
+            }
+            Command::ToggleBlockContact => self.toggle_block_selected_contact(),
+            Command::OpenContactDm => self.open_selected_contact_dm().await,
+            Command::Mute(duration) => {
+                let muted_until = match duration {
+                    Some(duration) => util::utc_now_timestamp_msec()
+                        .saturating_add(duration.as_millis().try_into().unwrap_or(u64::MAX))
+                        .min(Channel::MUTED_FOREVER),
+                    None => Channel::MUTED_FOREVER,
+                };
+                self.set_muted_until(Some(muted_until));
+            }
+            Command::Unmute => self.set_muted_until(None),
+            Command::New(phone_number) => self.open_contact_channel(&phone_number).await,
+            Command::Dm(recipient) => self.open_direct_channel(&recipient).await,
+            Command::Birthday(birthday) => {
+                self.update_contact_notes(|notes| notes.birthday = birthday);
+                self.step_channel_info().await;
+            }
+            Command::Note(text) => {
+                let text = Some(text).filter(|text| !text.is_empty());
+                self.update_contact_notes(|notes| notes.notes = text);
+                self.step_channel_info().await;
+            }
+            Command::Group => {
+                self.show_channel_info = true;
+                self.step_channel_info().await;
+            }
+            Command::Nick(name) => {
+                let name = Some(name.trim().to_string()).filter(|name| !name.is_empty());
+                self.set_channel_nickname(name);
+                self.step_channel_info().await;
+            }
+            Command::SendMessage => {
+                if self.is_send_debounced() {
+                    debug!("ignoring send right after sending a message");
+                } else {
+                    self.submit_input().await?;
+                }
+            }
+            Command::TogglePin => self.toggle_pin(),
+            Command::ToggleArchive if !self.marked_channels.is_empty() => {
+                self.toggle_archive_marked()
+            }
+            Command::ToggleArchive => self.toggle_archive(),
+            Command::ToggleChannelMark => {
+                if let Some(&channel_id) = self.channels.selected_item() {
+                    if !self.marked_channels.remove(&channel_id) {
+                        self.marked_channels.insert(channel_id);
+                    }
+                }
+            }
+            Command::MarkRead => {
+                for channel_id in self.take_target_channels() {
+                    self.mark_read(channel_id);
+                }
+            }
+            Command::ToggleBurnAfterReading => self.toggle_burn_after_reading(),
+            Command::ToggleMentionAll => self.toggle_mention_all(),
+            Command::KeepMessages(max_messages) => self.set_max_messages(max_messages),
+            Command::NotificationContent(content) => self.set_notification_content(content),
+            Command::Snippet(name) => self.send_snippet(&name),
+            Command::ExportProfile(path) => self.export_profile(&path),
+            Command::ImportProfile(path) => self.import_profile(&path),
+            Command::ToggleChannelDetails => {
+                self.show_channel_details = !self.show_channel_details;
+            }
+            Command::ToggleChannelInfo => {
+                self.show_channel_info = !self.show_channel_info;
+                self.step_channel_info().await;
+            }
+            Command::ToggleSidebar => self.sidebar_hidden = !self.sidebar_hidden,
+            Command::SortChannels => self.sort_channels(),
+            Command::Clear => self.clear_channel(),
+            Command::Purge => {
+                for channel_id in self.take_target_channels() {
+                    let count = self.delete_messages_before(channel_id, u64::MAX);
+                    info!(?channel_id, count, "purged messages");
+                }
+            }
+            Command::ToggleStackedLayout => self.stacked_layout = !self.stacked_layout,
+            Command::ToggleMessageOrder => self.newest_first = !self.newest_first,
+            Command::ToggleArchivedChannels => {
+                self.show_archived = !self.show_archived;
+                self.clamp_channel_selection();
+            }
+            Command::RecallInput(direction) => self.recall_input(direction),
+            Command::SearchInputHistory => {
+                if let Some(&channel_id) = self.channels.selected_item() {
+                    self.input_history
+                        .search_older(channel_id, &*self.storage, self.user_id);
+                }
+            }
             Command::DeleteCharacter(MoveDirection::Previous) => {
                 self.get_input().on_backspace();
             }
@@ -333,14 +960,18 @@ impl App {
             Command::Quit => {
                 self.should_quit = true;
             }
-            Command::Scroll(Widget::Help, DirectionVertical::Up, MoveAmountVisual::Entry) => {
-                if self.help_scroll.0 >= 1 {
-                    self.help_scroll.0 -= 1
-                }
+            Command::Scroll(Widget::Help, direction, amount) => {
+                let amount = visual_amount(&amount, self.help_page_height.into())
+                    .try_into()
+                    .unwrap_or(u16::MAX);
+                self.help_scroll.0 = match direction {
+                    DirectionVertical::Up => self.help_scroll.0.saturating_sub(amount),
+                    // overscrolling is prevented when drawing the help
+                    DirectionVertical::Down => self.help_scroll.0.saturating_add(amount),
+                };
             }
-            Command::Scroll(Widget::Help, DirectionVertical::Down, MoveAmountVisual::Entry) => {
-                // TODO: prevent overscrolling
-                self.help_scroll.0 += 1
+            Command::Scroll(Widget::Messages, direction, amount) => {
+                self.scroll_messages(direction, amount)
             }
             Command::NoOp => {}
         }
@@ -348,19 +979,54 @@ impl App {
     }
 
     pub async fn on_key(&mut self, key: KeyEvent) -> anyhow::Result<()> {
+        self.command_error = None;
+        self.command_hint = None;
+        let was_reading = self.is_reading();
+        self.last_key_at = Instant::now();
+        if !was_reading {
+            self.reset_unread_messages();
+        }
         if let Some(cmd) = self.event_to_command(&key) {
             self.on_command(cmd.clone()).await?;
         } else {
             match key.code {
                 KeyCode::Char('\r') => self.get_input().put_char('\n'),
+                KeyCode::Enter if self.attachment_picker.is_shown => {
+                    if let Some(paths) = self.attachment_picker.pick() {
+                        self.insert_attachments(&paths);
+                    }
+                }
+                KeyCode::Enter if self.attachment_gallery.save_as.is_some() => {
+                    self.attachment_gallery.save_selected();
+                }
+                KeyCode::Enter if self.contact_list.rename.is_some() => {
+                    self.rename_selected_contact();
+                }
+                KeyCode::Enter if self.captcha_prompt.is_shown => {
+                    self.submit_captcha().await;
+                }
+                KeyCode::Enter if self.message_search.is_shown => {
+                    if let Some(message_id) = self.message_search.selected() {
+                        self.message_search.close();
+                        self.show_message(message_id);
+                    }
+                }
+                KeyCode::Enter if self.input_history.search.is_some() => {
+                    self.accept_history_search();
+                }
+                KeyCode::Esc if self.input_history.search.is_some() => {
+                    self.input_history.search = None;
+                }
                 KeyCode::Enter => {
                     if !self.select_channel.is_shown {
-                        if self.is_multiline_input {
+                        if self.is_multiline_input
+                            || (!self.config.enter_sends && !self.input.data.is_empty())
+                        {
                             self.get_input().new_line();
+                        } else if self.is_send_debounced() {
+                            debug!("ignoring enter right after sending a message");
                         } else if !self.input.data.is_empty() {
-                            if let Some(idx) = self.channels.state.selected() {
-                                self.send_input(idx);
-                            }
+                            self.submit_input().await?;
                         } else {
                             // input is empty
                             self.try_open_url();
@@ -369,6 +1035,11 @@ impl App {
                         if let Some(channel_id) = self.select_channel.selected_channel_id().copied()
                         {
                             self.select_channel.is_shown = false;
+                            let forward = std::mem::take(&mut self.select_channel.forward);
+                            if !forward.is_empty() {
+                                self.forward_messages(&forward, channel_id);
+                                return Ok(());
+                            }
                             let (idx, _) = self
                                 .channels
                                 .items
@@ -376,12 +1047,17 @@ impl App {
                                 .enumerate()
                                 .find(|(_, &id)| id == channel_id)
                                 .context("channel disappeared during channel select popup")?;
-                            self.channels.state.select(Some(idx));
+                            self.switch_to_channel(idx);
+                            if idx >= self.visible_channels_len() {
+                                self.show_archived = true;
+                            }
                         }
                     }
                 }
                 KeyCode::Esc => {
-                    if !self.reset_editing() {
+                    if self.send_preview.take().is_some() {
+                        // only close the preview
+                    } else if !self.reset_editing() {
                         self.reset_message_selection();
                     }
                 }
@@ -389,9 +1065,120 @@ impl App {
                 _ => {}
             }
         }
+        self.update_message_search();
         Ok(())
     }
 
+    /// Runs the input without the leading `/` as a command
+    ///
+    /// On a parse error, the input is kept and the error is shown.
+    async fn run_input_command(&mut self) -> anyhow::Result<()> {
+        if self.run_plugin_command() {
+            return Ok(());
+        }
+        match command::parse(&self.input.data[1..]) {
+            Ok(command) => {
+                self.take_input();
+                self.on_command(command).await
+            }
+            Err(error) => {
+                self.command_error = Some(error.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    /// Runs the input as command of a plugin, if a plugin registered it
+    fn run_plugin_command(&mut self) -> bool {
+        let line = &self.input.data[1..];
+        let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+        if !self.plugins.as_ref().is_some_and(|p| p.has_command(name)) {
+            return false;
+        }
+        let (name, args) = (name.to_string(), args.trim().to_string());
+        self.take_input();
+        self.sync_plugin_channels();
+        if let Some(plugins) = &self.plugins {
+            if let Err(error) = plugins.run_command(&name, &args) {
+                self.command_error = Some(error.to_string());
+            }
+        }
+        self.apply_plugin_actions();
+        true
+    }
+
+    /// Whether the input is a command, i.e. starts with `/`
+    fn is_command_input(&self) -> bool {
+        self.input.data.starts_with('/') && !self.input.data.starts_with("//")
+    }
+
+    /// Completes the last word of the command in the input box
+    ///
+    /// If there are several completions, the word is completed to their common prefix and the
+    /// completions are shown. Without completions, the usage of the command is shown.
+    fn complete_command(&mut self) {
+        if !self.is_command_input() {
+            return;
+        }
+        let line = &self.input.data[1..];
+        // names of contacts may contain spaces, so the whole name is completed
+        let (word_start, candidates, separator) = match line.strip_prefix("dm ") {
+            Some(name) => (
+                line.len() - name.len(),
+                self.complete_contact_name(name),
+                ", ",
+            ),
+            None => {
+                let word_start = line.rfind(char::is_whitespace).map_or(0, |idx| idx + 1);
+                let mut candidates = command::complete(line);
+                if word_start == 0 {
+                    // the name of the command is completed, including the ones of plugins
+                    let plugin_commands: Vec<String> = self
+                        .plugins
+                        .iter()
+                        .flat_map(Plugins::command_names)
+                        .filter(|name| name.starts_with(line) && !candidates.contains(name))
+                        .collect();
+                    candidates.extend(plugin_commands);
+                }
+                (word_start, candidates, " ")
+            }
+        };
+        let completion = match candidates.as_slice() {
+            [] => {
+                let name = line.split_whitespace().next().unwrap_or_default();
+                self.command_hint = command::describe(name);
+                return;
+            }
+            [candidate] => format!("{candidate} "),
+            [first, rest @ ..] => {
+                let common = common_prefix(first, rest);
+                self.command_hint = Some(candidates.join(separator));
+                // names are matched case-insensitively, so they may share less than was typed
+                let typed = &line[word_start..];
+                if common.chars().count() < typed.chars().count() {
+                    typed.to_string()
+                } else {
+                    common.to_string()
+                }
+            }
+        };
+        self.input.data.truncate(1 + word_start);
+        self.input.data.push_str(&completion);
+        self.input.cursor = Cursor::end(&self.input.data);
+    }
+
+    fn attach(&mut self, path: &str) {
+        let path = expand_home(path);
+        if !path.is_file() {
+            self.command_error = Some(format!("no such file: {}", path.display()));
+        } else if attachment_input(&path).is_none() {
+            self.command_error = Some("paths containing whitespace cannot be attached".to_string());
+        } else {
+            self.insert_attachments(&[path]);
+        }
+    }
+
     /// Tries to open the first url in the selected message.
     ///
     /// Does nothing if no message is selected and no url is contained in the message.
@@ -412,7 +1199,7 @@ impl App {
         Some(())
     }
 
-    fn selected_message_id(&self) -> Option<MessageId> {
+    pub(crate) fn selected_message_id(&self) -> Option<MessageId> {
         // Messages are shown in reversed order => selected is reversed
         let channel_id = self.channels.selected_item()?;
         let messages = self.messages.get(channel_id)?;
@@ -483,6 +1270,15 @@ impl App {
         Some(())
     }
 
+    /// Opens the reaction picker with the reactions on the selected message
+    fn open_reaction_picker(&mut self) -> Option<()> {
+        let reactions = self.selected_message()?.reactions.clone();
+        let mut reaction_picker = std::mem::take(&mut self.reaction_picker);
+        reaction_picker.open(&reactions, self.user_id, |id| self.name_by_id_cached(id));
+        self.reaction_picker = reaction_picker;
+        Some(())
+    }
+
     fn reset_message_selection(&mut self) {
         if let Some(channel_id) = self.channels.selected_item() {
             if let Some(messages) = self.messages.get_mut(channel_id) {
@@ -496,38 +1292,181 @@ impl App {
         self.get_input().take()
     }
 
+    /// Runs the command in the input box, or sends the input (possibly after a preview)
+    async fn submit_input(&mut self) -> anyhow::Result<()> {
+        if self.is_command_input() {
+            self.run_input_command().await?;
+        } else if !self.input.data.is_empty() {
+            if let Some(idx) = self.channels.state.selected() {
+                if self.config.send_preview && self.send_preview().is_none() {
+                    self.show_send_preview();
+                } else {
+                    self.send_preview = None;
+                    self.send_input(idx);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether sending is ignored, because a message was sent just before
+    ///
+    /// See [`Config::send_debounce_ms`].
+    fn is_send_debounced(&self) -> bool {
+        let debounce = Duration::from_millis(self.config.send_debounce_ms);
+        self.last_sent_at
+            .is_some_and(|sent_at| sent_at.elapsed() < debounce)
+    }
+
+    /// Stops highlighting the messages which arrived long enough ago
+    pub fn step_highlights(&mut self) {
+        let duration = Duration::from_millis(self.config.highlight_new_messages_ms);
+        let len = self.highlighted_messages.len();
+        self.highlighted_messages
+            .retain(|_, arrived_at| arrived_at.elapsed() < duration);
+        if self.highlighted_messages.len() < len {
+            self.dirty.mark(Pane::Messages);
+        }
+    }
+
+    /// Whether the message arrived in the selected channel recently and is highlighted
+    ///
+    /// See [`Config::highlight_new_messages_ms`].
+    pub(crate) fn is_highlighted(&self, message_id: MessageId) -> bool {
+        let duration = Duration::from_millis(self.config.highlight_new_messages_ms);
+        self.highlighted_messages
+            .get(&message_id)
+            .is_some_and(|arrived_at| arrived_at.elapsed() < duration)
+    }
+
     fn send_input(&mut self, channel_idx: usize) {
-        let input = self.take_input();
-        let (input, attachments) = self.extract_attachments(&input);
+        let mut input = self.take_input();
+        if input.starts_with("//") {
+            // escaped command
+            input.remove(0);
+        }
         let channel_id = self.channels.items[channel_idx];
+        self.input_history
+            .push(channel_id, &*self.storage, self.user_id, input.clone());
+        let editing = self.editing.take();
+        let quote = editing
+            .is_none()
+            .then(|| self.selected_message_id())
+            .flatten();
+
+        self.run_sent_hook(channel_id, &input);
+        self.send_or_queue(channel_id, input, quote, editing);
+
+        self.reset_message_selection();
+        self.reset_unread_messages();
+        self.last_sent_at = Some(Instant::now());
+    }
+
+    /// Sends the input to the channel
+    ///
+    /// If `resend_timestamp` is set, the message rejected before with this timestamp is sent again.
+    pub(crate) fn send_text(
+        &mut self,
+        channel_id: ChannelId,
+        input: String,
+        quote: Option<MessageId>,
+        editing: Option<MessageId>,
+        resend_timestamp: Option<u64>,
+    ) -> MessageId {
+        let message = QueuedMessage {
+            channel_id,
+            input,
+            quote,
+            editing,
+            attachments: None,
+            forwarded: false,
+        };
+        self.send_queued(message, resend_timestamp)
+    }
+
+    /// Sends the message to its channel
+    ///
+    /// If `resend_timestamp` is set, the message rejected before with this timestamp is sent again.
+    pub(crate) fn send_queued(
+        &mut self,
+        message: QueuedMessage,
+        resend_timestamp: Option<u64>,
+    ) -> MessageId {
+        self.metrics.messages_sent += 1;
+        let (text, attachments) = match &message.attachments {
+            Some(attachments) => (message.input.clone(), read_attachments(attachments)),
+            None => self.extract_attachments(&message.input),
+        };
+        let QueuedMessage {
+            channel_id,
+            quote,
+            editing,
+            ..
+        } = message;
         let channel = self
             .storage
             .channel(channel_id)
             .expect("non-existent channel");
-        let editing = self.editing.take();
-        let quote = editing.is_none().then(|| self.selected_message()).flatten();
-        let (sent_message, response) = self.signal_manager.send_text(
+        let quote_message = quote.and_then(|message_id| self.storage.message(message_id));
+        // the timestamp identifies the message, so messages sent within the same millisecond,
+        // e.g. when forwarding several ones, need distinct timestamps
+        let timestamp = resend_timestamp.unwrap_or_else(|| {
+            let timestamp = util::utc_now_timestamp_msec().max(self.last_sent_timestamp + 1);
+            self.last_sent_timestamp = timestamp;
+            timestamp
+        });
+        let (mut sent_message, pending) = self.signal_manager.send_text(
             &channel,
-            input,
-            quote.as_deref(),
+            text,
+            quote_message.as_deref(),
             editing.map(|id| id.arrived_at),
             attachments,
+            Some(timestamp),
         );
+        sent_message.forwarded = message.forwarded;
 
         let message_id = MessageId::new(channel_id, sent_message.arrived_at);
+        if editing.is_none() {
+            self.pending_sends.insert(message_id, message);
+        }
         let tx = self.event_tx.clone();
-        tokio::spawn(async move {
-            if let Ok(result) = response.await {
+        self.effects.start(EffectKind::Send(message_id), |id| {
+            let PendingSend {
+                mut response,
+                mut progress,
+                abort,
+            } = pending;
+            let task = tokio::spawn(async move {
+                // cancelling the effect also aborts sending
+                let _abort_send = scopeguard::guard(abort, |abort| {
+                    if let Some(abort) = abort {
+                        abort.abort();
+                    }
+                });
+                let result = loop {
+                    tokio::select! {
+                        result = &mut response => break result,
+                        Some(progress) = progress.recv() => {
+                            let _ = tx.send(Event::EffectProgress { id, progress });
+                        }
+                    }
+                };
+                let result = result.unwrap_or_else(|_| {
+                    error!(?message_id, "response for sending message was lost");
+                    Err(anyhow!("response for sending message was lost"))
+                });
                 tx.send(Event::SentTextResult { message_id, result })
                     .expect("event sender gone");
-            } else {
-                error!(?message_id, "response for sending message was lost");
-            }
+            });
+            task.abort_handle()
         });
 
         if let Some(id) = editing {
             self.storage
                 .store_edited_message(channel_id, id.arrived_at, sent_message);
+        } else if resend_timestamp.is_some() {
+            // replaces the rejected message
+            self.storage.store_message(channel_id, sent_message);
         } else {
             let sent_message = self.storage.store_message(channel_id, sent_message);
             self.messages
@@ -537,79 +1476,1906 @@ impl App {
                 .push(sent_message.arrived_at);
         };
 
-        self.reset_message_selection();
-        self.reset_unread_messages();
-        self.bubble_up_channel(channel_idx);
+        if let Some(channel_idx) = self.channel_idx(channel_id) {
+            self.bubble_up_channel(channel_idx);
+        }
+        message_id
     }
 
-    pub fn select_previous_channel(&mut self) {
-        self.reset_unread_messages();
-        self.channels.previous();
+    async fn toggle_devices(&mut self) {
+        if self.linked_devices.take().is_some() {
+            return;
+        }
+        let devices = self
+            .signal_manager
+            .linked_devices()
+            .await
+            .map_err(|error| format!("failed to fetch the linked devices: {error}"));
+        self.linked_devices = Some(devices);
     }
 
-    pub fn select_next_channel(&mut self) {
-        self.reset_unread_messages();
-        self.channels.next();
+    /// Id of this device in the account
+    pub(crate) fn device_id(&self) -> u32 {
+        self.signal_manager.device_id()
     }
 
-    pub fn on_pgup(&mut self) {
-        if let Some(channel_id) = self.channels.selected_item() {
-            self.messages
-                .get_mut(channel_id)
-                .expect("non-existent channel")
-                .next();
+    /// Unlinks this device from the account and quits; the credentials are deleted after quitting
+    async fn unlink_device(&mut self) {
+        let device_id = self.signal_manager.device_id();
+        if let Err(error) = self.signal_manager.unlink_device(device_id).await {
+            self.command_error = Some(format!("failed to unlink this device: {error}"));
+            return;
         }
+        info!(device_id, "unlinked this device");
+        self.after_quit = AfterQuit::DeleteCredentials(self.config.signal_db_path.clone());
+        self.should_quit = true;
     }
 
-    pub fn on_pgdn(&mut self) {
-        if let Some(channel_id) = self.channels.selected_item() {
-            self.messages
-                .get_mut(channel_id)
-                .expect("non-existent channel")
-                .previous()
+    fn export_profile(&mut self, path: &str) {
+        let channels: Vec<_> = self.storage.channels().collect();
+        let profile = Profile::new(
+            &self.config,
+            channels.iter().map(|channel| channel.as_ref()),
+        );
+        if let Err(error) = profile.save(&expand_home(path)) {
+            self.command_error = Some(format!("failed to export profile: {error}"));
         }
     }
 
-    pub fn reset_unread_messages(&mut self) {
-        if let Some(channel_id) = self.channels.selected_item() {
-            if let Some(channel) = self.storage.channel(*channel_id) {
-                if channel.unread_messages > 0 {
-                    let mut channel = channel.into_owned();
-                    channel.unread_messages = 0;
-                    self.storage.store_channel(channel);
-                }
+    /// Applies the config and the settings of the channels from the profile
+    ///
+    /// The user and the paths of the config are kept. Settings read at startup, e.g. the
+    /// keybindings, take effect after a restart.
+    fn import_profile(&mut self, path: &str) {
+        let profile = match Profile::load(&expand_home(path)) {
+            Ok(profile) => profile,
+            Err(error) => {
+                self.command_error = Some(format!("failed to import profile: {error}"));
+                return;
+            }
+        };
+        for settings in profile.channels() {
+            let Some(channel) = self.storage.channel(settings.id) else {
+                continue;
+            };
+            let mut channel = channel.into_owned();
+            settings.apply(&mut channel);
+            let max_messages = channel.max_messages;
+            self.storage.store_channel(channel);
+            if let Some(max_messages) = max_messages {
+                self.trim_messages(settings.id, max_messages);
             }
         }
+        self.sort_channels();
+        self.clamp_channel_selection();
+
+        let config = profile.config_for(&self.config);
+        let theme = config.theme.clone();
+        self.config = Config {
+            theme: self.config.theme.clone(),
+            ..config
+        };
+        self.reload_theme(theme);
+        self.is_config_changed = true;
     }
 
-    pub async fn on_message(&mut self, content: Content) -> anyhow::Result<()> {
-        // tracing::info!(?content, "incoming");
+    /// Saves the config, if it was changed, e.g. by importing a profile
+    pub fn save_config(&mut self) {
+        if !self.is_config_changed || self.is_demo {
+            return;
+        }
+        self.is_config_changed = false;
+        if let Err(error) = self.config.save_installed() {
+            self.report_error(ErrorKind::Config, format!("failed to save config: {error}"));
+        }
+    }
 
-        #[cfg(feature = "dev")]
-        if self.config.developer.dump_raw_messages {
-            if let Err(e) = crate::dev::dump_raw_message(&content) {
-                warn!(error = %e, "failed to dump raw message");
+    /// Saves the storage, the attachment index and the config
+    ///
+    /// A failure to save the storage is shown in a banner until saving succeeds again.
+    pub fn save(&mut self) {
+        match self.storage.save() {
+            Ok(()) => self.error_log.storage_error = None,
+            Err(error) => {
+                let message = format!("{error:#}");
+                self.report_error(ErrorKind::Storage, message.clone());
+                self.error_log.storage_error = Some(message);
             }
         }
+        if let Err(error) = self.attachment_cache.save() {
+            self.report_error(ErrorKind::Storage, format!("{error:#}"));
+        }
+        self.save_config();
+    }
 
-        let user_id = self.user_id;
+    /// Logs the error and adds it to the error log, showing it in the status bar for a while
+    pub fn report_error(&mut self, kind: ErrorKind, message: String) {
+        error!(%kind, "{message}");
+        self.error_log.push(kind, message);
+    }
 
-        if let ContentBody::SynchronizeMessage(SyncMessage { ref read, .. }) = content.body {
-            self.handle_read(read);
+    /// Sends the selected message again if sending it failed
+    fn retry_send(&mut self) {
+        let Some(message_id) = self.selected_message_id() else {
+            return;
+        };
+        if !self.failed_sends.contains_key(&message_id) && !self.rate_limited.contains(message_id) {
+            self.command_error = Some("the selected message was not rejected".to_string());
+            return;
         }
-
-        let (channel_idx, message) = match (content.metadata, content.body) {
-            // Private note message
-            (
+        if self.is_offline() {
+            self.command_error = Some("offline, retry when connected".to_string());
+            return;
+        }
+        if let Some(message) = self
+            .failed_sends
+            .remove(&message_id)
+            .or_else(|| self.rate_limited.remove(message_id))
+        {
+            info!(?message_id, "retrying to send message");
+            self.send_queued(message, Some(message_id.arrived_at));
+        }
+    }
+
+    /// Rebuilds the failed sends from the messages which failed to be sent before a restart, so
+    /// that they can be sent again with `retry_send`
+    ///
+    /// The names must be loaded already, since the mentions are sent as names.
+    pub fn restore_failed_sends(&mut self) {
+        for message_id in self.storage.failed_messages() {
+            let Some(message) = self.storage.message(message_id).map(Cow::into_owned) else {
+                continue;
+            };
+            if message.from_id != self.user_id || self.failed_sends.contains_key(&message_id) {
+                continue;
+            }
+            let channel_id = message_id.channel_id;
+            let failed = QueuedMessage {
+                channel_id,
+                input: self.spelled_out_text(&message),
+                quote: message
+                    .quote
+                    .as_ref()
+                    .map(|quote| MessageId::new(channel_id, quote.arrived_at)),
+                editing: None,
+                attachments: Some(message.attachments),
+                forwarded: message.forwarded,
+            };
+            self.failed_sends.insert(message_id, failed);
+        }
+        if !self.failed_sends.is_empty() {
+            info!(count = self.failed_sends.len(), "restored failed sends");
+        }
+    }
+
+    /// Resends the messages rejected because of a rate limit, once the wait is over
+    pub fn step_rate_limited_sends(&mut self) {
+        if self.is_offline() {
+            return;
+        }
+        let messages = self.rate_limited.take_due(Instant::now());
+        if !messages.is_empty() {
+            info!(count = messages.len(), "resending rate limited messages");
+            self.dirty.mark_all();
+        }
+        for (message_id, message) in messages {
+            self.send_queued(message, Some(message_id.arrived_at));
+        }
+    }
+
+    /// Sends the snippet to the selected (or marked) channels, with the placeholders filled in
+    /// for each of them
+    fn send_snippet(&mut self, name: &str) {
+        let Some(snippet) = self.config.snippets.get(name).cloned() else {
+            self.command_error = Some(format!("no snippet named {name}"));
+            return;
+        };
+        let now = util::utc_timestamp_msec_to_local(util::utc_now_timestamp_msec());
+        for channel_id in self.take_target_channels() {
+            let Some(name) = self
+                .storage
+                .channel(channel_id)
+                .map(|channel| self.channel_name(&channel).into_owned())
+            else {
+                continue;
+            };
+            let input = snippet::fill(&snippet, &name, now);
+            self.run_sent_hook(channel_id, &input);
+            self.send_or_queue(channel_id, input, None, None);
+        }
+        self.last_sent_at = Some(Instant::now());
+    }
+
+    /// Passes the received message to the `on_message_received` hook, the plugins and the
+    /// subscribers of the RPC socket
+    fn run_received_hooks(&mut self, channel_id: ChannelId, from: &str, message: &Message) {
+        self.metrics.messages_received += 1;
+        let hook = self.config.hooks.on_message_received.as_deref();
+        if hook.is_none()
+            && self.plugins.is_none()
+            && self.rpc_subscribers.is_empty()
+            && self.dbus_signals.is_none()
+        {
+            return;
+        }
+        if let Some(channel) = self.storage.channel(channel_id) {
+            let event = MessageEvent {
+                channel: ChannelEvent::new(channel_id, &channel.name),
+                from,
+                text: message.message.as_deref().unwrap_or_default(),
+                timestamp: message.arrived_at,
+                attachments: hooks::attachment_names(&message.attachments),
+            };
+            if let Some(plugins) = &self.plugins {
+                self.sync_plugin_channels();
+                plugins.on_message(&event);
+            }
+            rpc::notify_subscribers(&mut self.rpc_subscribers, &event);
+            if let Some(dbus_signals) = &self.dbus_signals {
+                let _ = dbus_signals.send(MessageSignal {
+                    channel: event.channel.id.clone(),
+                    from: from.to_string(),
+                    text: event.text.to_string(),
+                });
+            }
+            if let Some(command) = hook {
+                let event = HookEvent::MessageReceived(event);
+                hooks::spawn(command, &event, Some(channel_id), self.event_tx.clone());
+            }
+        }
+        self.apply_plugin_actions();
+    }
+
+    /// Runs the `on_message_sent` hook for the input sent (or queued) to the channel
+    fn run_sent_hook(&self, channel_id: ChannelId, input: &str) {
+        let Some(command) = &self.config.hooks.on_message_sent else {
+            return;
+        };
+        let Some(channel) = self.storage.channel(channel_id) else {
+            return;
+        };
+        let event = HookEvent::MessageSent(MessageEvent {
+            channel: ChannelEvent::new(channel_id, &channel.name),
+            from: &self.config.user.name,
+            text: input,
+            timestamp: util::utc_now_timestamp_msec(),
+            attachments: Vec::new(),
+        });
+        hooks::spawn(command, &event, Some(channel_id), self.event_tx.clone());
+    }
+
+    /// Loads the Lua plugins from the directory
+    pub fn load_plugins(&mut self, dir: &Path) {
+        match Plugins::load(dir) {
+            Ok(plugins) => self.plugins = Some(plugins),
+            Err(error) => warn!(%error, "failed to load plugins"),
+        }
+    }
+
+    /// Updates the channels seen by the plugins before calling them
+    fn sync_plugin_channels(&self) {
+        let Some(plugins) = &self.plugins else {
+            return;
+        };
+        plugins.set_channels(self.channel_summaries());
+    }
+
+    /// Channels in the order of the channel list, for scripts
+    pub(crate) fn channel_summaries(&self) -> Vec<ChannelSummary> {
+        self.channels
+            .items
+            .iter()
+            .filter_map(|&channel_id| self.storage.channel(channel_id))
+            .map(|channel| {
+                let name = self.channel_name(&channel).into_owned();
+                ChannelSummary::new(channel.id, name, channel.unread_messages)
+            })
+            .collect()
+    }
+
+    /// Applies the requests the plugins made while they were called
+    fn apply_plugin_actions(&mut self) {
+        let Some(plugins) = &self.plugins else {
+            return;
+        };
+        for action in plugins.take_actions() {
+            match action {
+                PluginAction::Send { channel_id, text } => {
+                    if self.storage.channel(channel_id).is_some() {
+                        self.send_or_queue(channel_id, text, None, None);
+                    } else {
+                        warn!(?channel_id, "plugin sent to an unknown channel");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Texts of the status bar segments of the plugins
+    pub(crate) fn plugin_status_segments(&self) -> Vec<String> {
+        self.plugins
+            .as_ref()
+            .map(Plugins::status_segments)
+            .unwrap_or_default()
+    }
+
+    /// Starts listening on the RPC socket, if it is configured
+    pub fn serve_rpc(&self) -> Option<tokio::task::JoinHandle<()>> {
+        let path = self.config.rpc_socket.as_ref()?;
+        #[cfg(unix)]
+        let result = rpc::serve(path, self.event_tx.clone());
+        #[cfg(not(unix))]
+        let result = Err(anyhow!(
+            "{} is not supported on this platform",
+            path.display()
+        ));
+        match result {
+            Ok(handle) => Some(handle),
+            Err(error) => {
+                error!(%error, "failed to start the RPC server");
+                None
+            }
+        }
+    }
+
+    /// Starts the D-Bus service, if it is enabled
+    pub fn serve_dbus(&mut self) -> Option<tokio::task::JoinHandle<()>> {
+        if !self.config.dbus {
+            return None;
+        }
+        let (tx, rx) = mpsc::unbounded_channel();
+        let handle = dbus::serve(self.event_tx.clone(), rx)?;
+        self.dbus_signals = Some(tx);
+        self.receives_notification_signals = true;
+        Some(handle)
+    }
+
+    /// Starts receiving the actions of the notifications, unless the D-Bus service receives them
+    ///
+    /// One listener receives the actions of all notifications, instead of waiting for each
+    /// notification to be closed.
+    pub fn listen_notifications(&mut self) -> Option<tokio::task::JoinHandle<()>> {
+        if !self.config.notifications
+            || !self.config.notification.actions
+            || self.receives_notification_signals
+        {
+            return None;
+        }
+        let handle = dbus::listen_notifications(self.event_tx.clone())?;
+        self.receives_notification_signals = true;
+        Some(handle)
+    }
+
+    /// Starts watching whether the connection is metered, if attachments should not be
+    /// downloaded right away on metered connections
+    pub fn watch_metered(&mut self) -> Option<tokio::task::JoinHandle<()>> {
+        if self.config.media.auto_download_when_metered {
+            return None;
+        }
+        dbus::watch_metered(self.event_tx.clone())
+    }
+
+    /// Prepares the app for running without the TUI
+    ///
+    /// No channel is open and nobody is looking, so that received messages stay unread and are
+    /// notified.
+    pub fn set_headless(&mut self) {
+        self.channels.state.select(None);
+        self.is_focused = false;
+        self.is_headless = true;
+    }
+
+    /// Connection of a TUI which asked the daemon to hand over, see [`rpc::detach_daemon`]
+    pub fn take_detach_lease(&mut self) -> Option<mpsc::UnboundedSender<String>> {
+        self.detach_lease.take()
+    }
+
+    /// Runs the `on_startup` hook
+    pub fn run_startup_hook(&self) {
+        if let Some(command) = &self.config.hooks.on_startup {
+            let event = HookEvent::Startup {
+                user: &self.config.user.name,
+            };
+            hooks::spawn(command, &event, None, self.event_tx.clone());
+        }
+    }
+
+    /// Applies the actions returned by a hook
+    ///
+    /// Replies are sent like messages typed by the user, but do not trigger `on_message_sent`.
+    fn on_hook_actions(&mut self, channel_id: ChannelId, actions: Vec<HookAction>) {
+        if self.storage.channel(channel_id).is_none() {
+            return;
+        }
+        for action in actions {
+            match action {
+                HookAction::Reply { text } => self.send_or_queue(channel_id, text, None, None),
+                HookAction::MarkRead => self.mark_read(channel_id),
+            }
+        }
+    }
+
+    /// Sends the text right away, e.g. from the command line, where attachments are given as
+    /// `file://` URLs
+    ///
+    /// The result arrives as [`Event::SentTextResult`] for the returned message.
+    pub fn send_message(&mut self, channel_id: ChannelId, text: String) -> MessageId {
+        self.send_text(channel_id, text, None, None, None)
+    }
+
+    /// Channel given by its id (as in hooks), or its name ignoring case
+    pub fn find_channel(&self, to: &str) -> anyhow::Result<ChannelId> {
+        if let Some(channel_id) = util::parse_channel_id(to) {
+            if self.storage.channel(channel_id).is_some() {
+                return Ok(channel_id);
+            }
+        }
+        if let Some(number) = util::parse_phone_number(to, self.phone_region) {
+            let user_id = self.names.borrow().by_phone_number(&number);
+            if let Some(channel_id) = user_id.map(ChannelId::from) {
+                if self.storage.channel(channel_id).is_some() {
+                    return Ok(channel_id);
+                }
+            }
+        }
+        // contacts are also found by the name they are shown with
+        let named: Vec<ChannelId> = self
+            .names
+            .borrow()
+            .by_display_name(to)
+            .map(ChannelId::from)
+            .collect();
+        let name = to.to_lowercase();
+        let mut found = self
+            .storage
+            .channels()
+            .filter(|channel| {
+                channel.name.to_lowercase() == name
+                    || channel.shown_name().to_lowercase() == name
+                    || named.contains(&channel.id)
+            })
+            .map(|channel| channel.id);
+        match (found.next(), found.next()) {
+            (Some(channel_id), None) => Ok(channel_id),
+            (Some(_), Some(_)) => Err(anyhow!(
+                "several contacts or groups are named {to}; please use the id"
+            )),
+            (None, _) => Err(anyhow!("no contact or group {to}")),
+        }
+    }
+
+    /// Sends the text to the channel, or queues it while offline
+    pub(crate) fn send_or_queue(
+        &mut self,
+        channel_id: ChannelId,
+        text: String,
+        quote: Option<MessageId>,
+        editing: Option<MessageId>,
+    ) {
+        if self.is_offline() {
+            debug!(?channel_id, "offline, queueing message");
+            self.outbox.push(QueuedMessage {
+                channel_id,
+                input: text,
+                quote,
+                editing,
+                attachments: None,
+                forwarded: false,
+            });
+        } else {
+            self.send_text(channel_id, text, quote, editing, None);
+        }
+    }
+
+    /// Whether the message is being sent, so that it cannot be deleted
+    pub(crate) fn is_being_sent(&self, message_id: MessageId) -> bool {
+        self.pending_sends.contains_key(&message_id)
+    }
+
+    /// Forgets the failed or rate limited send and the edit of the message deleted locally, so
+    /// that it is not sent again
+    pub(crate) fn forget_deleted_message(&mut self, message_id: MessageId) {
+        self.failed_sends.remove(&message_id);
+        self.rate_limited.remove(message_id);
+        self.highlighted_messages.remove(&message_id);
+        if self.editing == Some(message_id) {
+            self.editing = None;
+        }
+    }
+
+    /// Whether messages are queued instead of being sent, because there is no connection
+    pub(crate) fn is_offline(&self) -> bool {
+        matches!(
+            self.connection_state,
+            ConnectionState::Offline | ConnectionState::Reconnecting { .. }
+        )
+    }
+
+    /// Sends the messages queued while offline as soon as the connection is established
+    pub fn set_connection_state(&mut self, connection_state: ConnectionState) {
+        self.connection_state = connection_state;
+        self.scheduler
+            .set_paused(connection_state != ConnectionState::Connected);
+        if connection_state == ConnectionState::Connected && !self.outbox.is_empty() {
+            info!(count = self.outbox.len(), "sending queued messages");
+            for message in std::mem::take(&mut self.outbox) {
+                self.send_queued(message, None);
+            }
+        }
+    }
+
+    /// Submits the captcha entered in the captcha prompt and resends the rejected messages
+    ///
+    /// On failure, the prompt stays open and shows the error.
+    async fn submit_captcha(&mut self) {
+        let (Some(token), Some(captcha)) =
+            (self.captcha_prompt.token(), self.captcha_prompt.captcha())
+        else {
+            return;
+        };
+        let (token, captcha) = (token.to_owned(), captcha.to_owned());
+        if let Err(error) = self.signal_manager.submit_captcha(&token, &captcha).await {
+            warn!(%error, "failed to submit captcha");
+            self.captcha_prompt.error = Some(error.to_string());
+            return;
+        }
+        let messages = self.captcha_prompt.solved();
+        info!(count = messages.len(), "captcha solved, resending messages");
+        for (timestamp, message) in messages {
+            self.send_queued(message, Some(timestamp));
+        }
+    }
+
+    /// Total number of unread messages of all channels
+    pub fn unread_messages_len(&self) -> u32 {
+        self.channels
+            .items
+            .iter()
+            .filter_map(|&channel_id| self.storage.channel(channel_id))
+            .map(|channel| channel.unread_messages)
+            .sum()
+    }
+
+    /// Summary of the unread messages for the terminal title
+    ///
+    /// Only given after the terminal has been unfocused for `unread_summary_after_minutes`.
+    pub fn unread_summary(&self) -> Option<String> {
+        let minutes = self.config.unread_summary_after_minutes?;
+        if self.is_focused
+            || self.focus_changed_at.elapsed() < Duration::from_secs(minutes.saturating_mul(60))
+        {
+            return None;
+        }
+        let (messages, chats) = self
+            .channels
+            .items
+            .iter()
+            .filter_map(|&channel_id| self.storage.channel(channel_id))
+            .filter(|channel| channel.unread_messages > 0)
+            .fold((0, 0), |(messages, chats), channel| {
+                (messages + channel.unread_messages, chats + 1)
+            });
+        let plural = if chats == 1 { "" } else { "s" };
+        Some(format!("{messages} unread in {chats} chat{plural}"))
+    }
+
+    /// Number of messages waiting to be sent when the connection is established, or after the
+    /// rate limit of the server
+    pub fn queued_messages_len(&self) -> usize {
+        self.outbox.len() + self.rate_limited.len()
+    }
+
+    /// Number of messages of the channel which are queued or not yet confirmed by the server
+    pub(crate) fn sending_messages_len(&self, channel_id: ChannelId) -> usize {
+        let queued = self
+            .outbox
+            .iter()
+            .filter(|message| message.channel_id == channel_id)
+            .count();
+        let pending = self
+            .pending_sends
+            .keys()
+            .filter(|message_id| message_id.channel_id == channel_id)
+            .count();
+        queued + pending
+    }
+
+    /// Returns the preview of the message to be sent, if it was requested for the current input.
+    pub(crate) fn send_preview(&self) -> Option<&SendPreview> {
+        self.send_preview
+            .as_ref()
+            .filter(|preview| preview.input == self.input.data)
+    }
+
+    /// Computes the preview of the current input as it would be sent by `send_input`.
+    ///
+    /// Does not read any attachments, only their names are shown.
+    fn show_send_preview(&mut self) {
+        let input = self.input.data.clone();
+
+        let mut offset = 0;
+        let mut text = String::new();
+        let mut attachments = Vec::new();
+        for m in self.attachment_regex.compiled().find_iter(&input) {
+            let Some(path_str) = m.as_str().strip_prefix("file://") else {
+                continue;
+            };
+            let name = if path_str.starts_with("clip") {
+                "clipboard.png".to_string()
+            } else {
+                Path::new(path_str)
+                    .file_name()
+                    .map(|f| f.to_string_lossy().into())
+                    .unwrap_or_else(|| path_str.to_string())
+            };
+            attachments.push(name);
+            text.push_str(input[offset..m.start()].trim_end());
+            offset = m.end();
+        }
+        text.push_str(&input[offset..]);
+        let text = crate::emoji::replace_shortcodes(text.trim()).into_owned();
+
+        let editing = self.editing.is_some();
+        let quote = (!editing)
+            .then(|| self.selected_message())
+            .flatten()
+            .map(Cow::into_owned);
+
+        self.send_preview = Some(SendPreview {
+            input,
+            text,
+            attachments,
+            quote,
+            editing,
+        });
+    }
+
+    /// Replaces the input with the previous/next input sent in the selected channel
+    fn recall_input(&mut self, direction: MoveDirection) {
+        let Some(&channel_id) = self.channels.selected_item() else {
+            return;
+        };
+        if let Some(text) = self.input_history.recall(
+            channel_id,
+            &*self.storage,
+            self.user_id,
+            direction,
+            &self.input.data,
+        ) {
+            self.input.cursor = Cursor::end(&text);
+            self.input.data = text;
+        }
+    }
+
+    /// Appends the paths as attachments to the input
+    fn insert_attachments(&mut self, paths: &[std::path::PathBuf]) {
+        for attachment in paths.iter().filter_map(|path| attachment_input(path)) {
+            if !self.input.data.is_empty() && !self.input.data.ends_with(char::is_whitespace) {
+                self.input.data.push(' ');
+            }
+            self.input.data.push_str(&attachment);
+        }
+        self.input.cursor = Cursor::end(&self.input.data);
+    }
+
+    /// Replaces the input with the current match of the input history search
+    fn accept_history_search(&mut self) {
+        if let Some(&channel_id) = self.channels.selected_item() {
+            if let Some(text) = self.input_history.search_match(channel_id) {
+                self.input.data = text.to_string();
+                self.input.cursor = Cursor::end(&self.input.data);
+            }
+        }
+        self.input_history.search = None;
+    }
+
+    fn set_muted_until(&mut self, muted_until: Option<u64>) {
+        for channel_id in self.take_target_channels() {
+            let Some(channel) = self.storage.channel(channel_id) else {
+                continue;
+            };
+            let mut channel = channel.into_owned();
+            channel.muted_until = muted_until;
+            self.storage.store_channel(channel);
+        }
+    }
+
+    /// Channels a command applies to: the marked channels, or else the selected one
+    ///
+    /// The marks are cleared, since the command is applied to the marked channels now.
+    fn take_target_channels(&mut self) -> Vec<ChannelId> {
+        if self.marked_channels.is_empty() {
+            self.channels.selected_item().copied().into_iter().collect()
+        } else {
+            std::mem::take(&mut self.marked_channels)
+                .into_iter()
+                .collect()
+        }
+    }
+
+    fn toggle_pin(&mut self) {
+        let Some(channel_idx) = self.channels.state.selected() else {
+            return;
+        };
+        let channel_id = self.channels.items[channel_idx];
+        let Some(channel) = self.storage.channel(channel_id) else {
+            return;
+        };
+        let mut channel = channel.into_owned();
+        channel.pinned = !channel.pinned;
+        self.storage.store_channel(channel);
+        self.bubble_up_channel(channel_idx);
+    }
+
+    fn toggle_archive(&mut self) {
+        let Some(channel_idx) = self.channels.state.selected() else {
+            return;
+        };
+        let channel_id = self.channels.items[channel_idx];
+        let Some(channel) = self.storage.channel(channel_id) else {
+            return;
+        };
+        let mut channel = channel.into_owned();
+        channel.archived = !channel.archived;
+        self.storage.store_channel(channel);
+        self.bubble_up_channel(channel_idx);
+        let visible_channels_len = self.visible_channels_len();
+        if self.channels.state.selected() >= Some(visible_channels_len) {
+            // the channel was hidden => select the channel which took its place
+            self.burn_messages(channel_id);
+            self.channels
+                .state
+                .select(Some(channel_idx.min(visible_channels_len)));
+            self.clamp_channel_selection();
+        }
+    }
+
+    fn toggle_burn_after_reading(&mut self) {
+        let Some(&channel_id) = self.channels.selected_item() else {
+            return;
+        };
+        let Some(channel) = self.storage.channel(channel_id) else {
+            return;
+        };
+        let mut channel = channel.into_owned();
+        channel.burn_after_reading = !channel.burn_after_reading;
+        self.storage.store_channel(channel);
+    }
+
+    fn toggle_mention_all(&mut self) {
+        let Some(&channel_id) = self.channels.selected_item() else {
+            return;
+        };
+        let Some(channel) = self.storage.channel(channel_id) else {
+            return;
+        };
+        if channel.group_data.is_none() {
+            return;
+        }
+        let mut channel = channel.into_owned();
+        channel.ignore_mention_all = !channel.ignore_mention_all;
+        self.storage.store_channel(channel);
+    }
+
+    fn set_max_messages(&mut self, max_messages: Option<usize>) {
+        let Some(&channel_id) = self.channels.selected_item() else {
+            return;
+        };
+        let Some(channel) = self.storage.channel(channel_id) else {
+            return;
+        };
+        let mut channel = channel.into_owned();
+        channel.max_messages = max_messages;
+        self.storage.store_channel(channel);
+        if let Some(max_messages) = max_messages {
+            self.trim_messages(channel_id, max_messages);
+        }
+    }
+
+    /// Deletes the messages of the channel beyond the newest `max_messages`
+    fn trim_messages(&mut self, channel_id: ChannelId, max_messages: usize) {
+        if let Some(cutoff) = self.storage.limit_cutoff(channel_id, max_messages) {
+            self.delete_messages_before(channel_id, cutoff);
+        }
+    }
+
+    /// Archives the marked channels, or unarchives them if all of them are archived
+    fn toggle_archive_marked(&mut self) {
+        let channel_ids = self.take_target_channels();
+        let archived = !channel_ids.iter().all(|&channel_id| {
+            self.storage
+                .channel(channel_id)
+                .is_some_and(|channel| channel.archived)
+        });
+        for channel_id in channel_ids {
+            let Some(channel) = self.storage.channel(channel_id) else {
+                continue;
+            };
+            let mut channel = channel.into_owned();
+            channel.archived = archived;
+            self.storage.store_channel(channel);
+            if archived && !self.show_archived {
+                self.burn_messages(channel_id);
+            }
+        }
+        self.sort_channels();
+        self.clamp_channel_selection();
+    }
+
+    /// Drops the messages of a burn-after-reading channel from memory
+    ///
+    /// Messages persisted before the channel was marked as burn-after-reading are kept.
+    fn burn_messages(&mut self, channel_id: ChannelId) {
+        let burn_after_reading = self
+            .storage
+            .channel(channel_id)
+            .is_some_and(|channel| channel.burn_after_reading);
+        if !burn_after_reading {
+            return;
+        }
+        self.storage.forget_messages(channel_id);
+        // only the first page of the persisted messages is loaded again
+        let page_size = self.config.history_page_size.max(1);
+        let messages = self.messages.entry(channel_id).or_default();
+        messages.items = self
+            .storage
+            .messages_before(channel_id, u64::MAX, page_size)
+            .iter()
+            .map(|message| message.arrived_at)
+            .collect();
+        messages.state.select(None);
+        messages.rendered = Default::default();
+        if messages.items.len() == page_size {
+            self.partially_loaded.insert(channel_id);
+        } else {
+            self.partially_loaded.remove(&channel_id);
+        }
+    }
+
+    /// Must be called before another channel is selected
+    fn leave_selected_channel(&mut self) {
+        self.reset_unread_messages();
+        self.input_history.reset();
+        if let Some(&channel_id) = self.channels.selected_item() {
+            self.burn_messages(channel_id);
+        }
+    }
+
+    /// Selects the channel at the given index in the channel list
+    pub fn switch_to_channel(&mut self, channel_idx: usize) {
+        if self.channels.state.selected() != Some(channel_idx) {
+            self.leave_selected_channel();
+            self.channels.state.select(Some(channel_idx));
+        }
+        self.reset_unread_messages();
+    }
+
+    /// Section of the channel list the channel is listed in
+    pub(crate) fn channel_section(&self, channel_id: ChannelId) -> ChannelSection {
+        match self.storage.channel(channel_id) {
+            Some(channel) if channel.archived => ChannelSection::Archived,
+            Some(channel) if channel.pinned => ChannelSection::Pinned,
+            _ if self.config.channel_list.sections && !channel_id.is_user() => {
+                ChannelSection::Groups
+            }
+            _ => ChannelSection::Direct,
+        }
+    }
+
+    fn channel_order(&self, section: ChannelSection) -> ChannelOrder {
+        let config = &self.config.channel_list;
+        match section {
+            ChannelSection::Pinned => config.pinned,
+            ChannelSection::Direct => config.direct,
+            ChannelSection::Groups => config.groups,
+            ChannelSection::Archived => config.archived,
+        }
+    }
+
+    /// Whether a header is shown above the channel at the index, as the first of its section
+    pub(crate) fn starts_section(&self, channel_idx: usize) -> bool {
+        let section = |idx: usize| self.channel_section(self.channels.items[idx]);
+        self.config.channel_list.sections
+            && (channel_idx == 0 || section(channel_idx - 1) != section(channel_idx))
+    }
+
+    /// Index of the channel shown in the row of the channel list, counting the section headers
+    pub fn channel_at_row(&self, row: usize) -> Option<usize> {
+        let mut top = 0;
+        for channel_idx in 0..self.visible_channels_len() {
+            top += self.channel_item_height() + usize::from(self.starts_section(channel_idx));
+            if row < top {
+                return Some(channel_idx);
+            }
+        }
+        None
+    }
+
+    /// Number of channels shown in the channel list
+    ///
+    /// Archived channels are at the end of the list, so the shown channels are always a prefix
+    /// of `channels.items`.
+    pub fn visible_channels_len(&self) -> usize {
+        if self.show_archived {
+            self.channels.items.len()
+        } else {
+            self.channels.items.partition_point(|&channel_id| {
+                self.channel_section(channel_id) < ChannelSection::Archived
+            })
+        }
+    }
+
+    /// Whether the channel list is shown above the chat in a terminal of the given width
+    pub(crate) fn is_stacked_layout(&self, width: u16) -> bool {
+        self.stacked_layout
+            || self
+                .config
+                .layout
+                .stack_below_width
+                .is_some_and(|stack_below_width| width < stack_below_width)
+    }
+
+    /// Sorts the channel list by sections and the order of each section, keeping the selected
+    /// channel
+    ///
+    /// Pinned channels come first, then unpinned and finally archived channels. Within a section,
+    /// the channels are ordered as configured, by default with the newest messages first.
+    pub fn sort_channels(&mut self) {
+        let selected_channel_id = self.channels.selected_item().copied();
+        let mut items = std::mem::take(&mut self.channels.items);
+        items.sort_by_cached_key(|&channel_id| {
+            let section = self.channel_section(channel_id);
+            let channel = self.storage.channel(channel_id);
+            let last_message_arrived_at = self.last_message_arrived_at(channel_id);
+            let key = match self.channel_order(section) {
+                ChannelOrder::Recent => (false, Reverse(last_message_arrived_at), None),
+                ChannelOrder::Alphabetical => {
+                    let name = channel
+                        .as_ref()
+                        .map(|channel| self.channel_name(channel).to_lowercase());
+                    (false, Reverse(None), name)
+                }
+                ChannelOrder::UnreadFirst => {
+                    let is_read = !channel
+                        .as_ref()
+                        .is_some_and(|channel| channel.unread_messages > 0);
+                    (is_read, Reverse(last_message_arrived_at), None)
+                }
+            };
+            let channel_name = channel.map(|channel| channel.name.clone());
+            (section, key, channel_name)
+        });
+        self.channels.items = items;
+        self.rebuild_channel_index();
+        if let Some(channel_id) = selected_channel_id {
+            let selected_idx = self.channel_idx(channel_id);
+            self.channels.state.select(selected_idx);
+        }
+        self.last_sorted_at = Instant::now();
+        self.dirty.mark(Pane::Channels);
+    }
+
+    /// Sorts the channel list if the configured sort interval elapsed
+    pub fn step_channel_sorting(&mut self) {
+        if let Some(interval) = self.config.channel_sort_interval_secs {
+            if self.last_sorted_at.elapsed() >= Duration::from_secs(interval) {
+                self.sort_channels();
+            }
+        }
+    }
+
+    /// Deletes the messages beyond the retention limits from the config
+    ///
+    /// Runs once at startup and then every hour.
+    pub fn step_retention(&mut self) {
+        let retention_days = self.config.retention_days;
+        let max_messages = self.config.max_messages_per_channel;
+        if retention_days.is_none() && max_messages.is_none() {
+            return;
+        }
+        if self
+            .last_pruned_at
+            .is_some_and(|at| at.elapsed() < PRUNE_INTERVAL)
+        {
+            return;
+        }
+        self.last_pruned_at = Some(Instant::now());
+
+        let retention_cutoff = retention_days.map(|days| {
+            util::utc_now_timestamp_msec().saturating_sub(days.saturating_mul(24 * 3600 * 1000))
+        });
+        for channel_id in self.channels.items.clone() {
+            let channel_max_messages = self
+                .storage
+                .channel(channel_id)
+                .and_then(|channel| channel.max_messages);
+            // arrived_at before which the messages are beyond the limit
+            let count_cutoff = channel_max_messages
+                .into_iter()
+                .chain(max_messages)
+                .min()
+                .and_then(|max_messages| self.storage.limit_cutoff(channel_id, max_messages));
+            let Some(cutoff) = retention_cutoff.into_iter().chain(count_cutoff).max() else {
+                continue;
+            };
+            let count = self.delete_messages_before(channel_id, cutoff);
+            if count > 0 {
+                self.dirty.mark(Pane::Channels);
+                self.dirty.mark(Pane::Messages);
+                info!(
+                    ?channel_id,
+                    count, "deleted messages beyond retention limits"
+                );
+            }
+        }
+    }
+
+    /// Deletes the messages of the channel arrived before `arrived_at` and their attachments
+    ///
+    /// The messages are loaded in batches for deleting their attachments, newest first, so that
+    /// not all of them are in memory at once. Returns the number of deleted messages.
+    pub(crate) fn delete_messages_before(
+        &mut self,
+        channel_id: ChannelId,
+        arrived_at: u64,
+    ) -> usize {
+        let mut count = 0;
+        let mut before = arrived_at;
+        loop {
+            let batch = self
+                .storage
+                .messages_before(channel_id, before, DELETE_BATCH);
+            let attachments: Vec<_> = batch
+                .iter()
+                .flat_map(|message| &message.attachments)
+                .map(|attachment| attachment.filename.clone())
+                .collect();
+            count += batch.len();
+            let oldest = batch.first().map(|message| message.arrived_at);
+            let is_last = batch.len() < DELETE_BATCH;
+            drop(batch);
+            delete_attachment_files(attachments);
+            match oldest {
+                Some(oldest) if !is_last => before = oldest,
+                _ => break,
+            }
+        }
+        if count == 0 {
+            return 0;
+        }
+        self.storage.delete_messages_before(channel_id, arrived_at);
+
+        let messages = self.messages.entry(channel_id).or_default();
+        messages
+            .items
+            .retain(|&message_arrived_at| message_arrived_at >= arrived_at);
+        let last_idx = messages.items.len().checked_sub(1);
+        if messages.state.selected() > last_idx {
+            messages.state.select(last_idx);
+        }
+        messages.rendered.offset = messages.rendered.offset.min(last_idx.unwrap_or(0));
+        if messages.items.is_empty() {
+            // the messages not loaded yet were older
+            self.partially_loaded.remove(&channel_id);
+        }
+        count
+    }
+
+    /// Formats the phone number for display as configured
+    pub(crate) fn format_phone_number(&self, number: &PhoneNumber) -> String {
+        util::format_phone_number(number, self.phone_region, self.config.phone_numbers.format)
+    }
+
+    /// Selects the channel of the contact with the phone number, creating it if needed
+    ///
+    /// Only contacts synced from the primary device are found.
+    async fn open_contact_channel(&mut self, phone_number: &str) {
+        let Some(number) = util::parse_phone_number(phone_number, self.phone_region) else {
+            self.command_error = Some(format!("invalid phone number: {phone_number}"));
+            return;
+        };
+        let Some(user_id) = self.contact_by_phone_number(&number).await else {
+            let number = self.format_phone_number(&number);
+            self.command_error = Some(format!("no contact with phone number {number}"));
+            return;
+        };
+        let name = self
+            .names
+            .get_mut()
+            .resolved_name(user_id)
+            .map(str::to_owned);
+        let name = name.unwrap_or_else(|| self.format_phone_number(&number));
+        let channel_idx = self.ensure_contact_channel_exists(user_id, &name).await;
+        self.switch_to_channel(channel_idx);
+        if channel_idx >= self.visible_channels_len() {
+            self.show_archived = true;
+        }
+    }
+
+    /// Id of the contact with the phone number
+    ///
+    /// Only contacts synced from the primary device are found.
+    async fn contact_by_phone_number(&mut self, number: &PhoneNumber) -> Option<Uuid> {
+        if let Some(user_id) = self.names.get_mut().by_phone_number(number) {
+            return Some(user_id);
+        }
+        // synced after the names were populated
+        let e164 = number.format().mode(phonenumber::Mode::E164).to_string();
+        let contact = self.signal_manager.contacts().await.find(|contact| {
+            contact.phone_number.as_ref().is_some_and(|phone_number| {
+                phone_number
+                    .format()
+                    .mode(phonenumber::Mode::E164)
+                    .to_string()
+                    == e164
+            })
+        })?;
+        let names = self.names.get_mut();
+        names.set_phone_number(contact.uuid, number.clone());
+        if names.display_name(contact.uuid).is_none() && !contact.name.is_empty() {
+            names.set_display_name(contact.uuid, contact.name);
+        }
+        Some(contact.uuid)
+    }
+
+    /// Id of the user given by id, phone number or the name they are shown with
+    async fn find_user(&mut self, recipient: &str) -> Result<Uuid, String> {
+        if let Ok(user_id) = Uuid::parse_str(recipient) {
+            return Ok(user_id);
+        }
+        if let Some(number) = util::parse_phone_number(recipient, self.phone_region) {
+            return self.contact_by_phone_number(&number).await.ok_or_else(|| {
+                let number = self.format_phone_number(&number);
+                format!("no contact with phone number {number}")
+            });
+        }
+        let user_ids: Vec<Uuid> = self
+            .names
+            .borrow()
+            .by_display_name(recipient)
+            .filter(|&user_id| user_id != self.user_id)
+            .collect();
+        match user_ids[..] {
+            [user_id] => Ok(user_id),
+            [] => Err(format!("no contact named {recipient}")),
+            _ => Err(format!(
+                "several contacts are named {recipient}; please use the phone number or the id"
+            )),
+        }
+    }
+
+    /// Selects the direct channel of the user given by id, phone number or name
+    async fn open_direct_channel(&mut self, recipient: &str) {
+        match self.find_user(recipient).await {
+            Ok(user_id) => self.open_user_channel(user_id, recipient).await,
+            Err(error) => self.command_error = Some(error),
+        }
+    }
+
+    /// Selects the direct channel of the user, who is called `recipient` in errors
+    ///
+    /// The channel is created if needed, but only for users who are verified to be registered
+    /// on Signal. Without their profile key, users cannot be verified, and no channel is created
+    /// for them.
+    pub(crate) async fn open_user_channel(&mut self, user_id: Uuid, recipient: &str) {
+        let channel_idx = match self.channel_idx(user_id.into()) {
+            Some(channel_idx) => channel_idx,
+            None => {
+                let profile_key = self
+                    .names
+                    .get_mut()
+                    .get(user_id)
+                    .and_then(|user| user.profile_key);
+                match self
+                    .signal_manager
+                    .is_registered(user_id, profile_key)
+                    .await
+                {
+                    Ok(Some(true)) => {}
+                    Ok(Some(false)) => {
+                        self.command_error =
+                            Some(format!("{recipient} is not registered on Signal"));
+                        return;
+                    }
+                    Ok(None) => {
+                        self.command_error = Some(format!(
+                            "cannot check whether {recipient} is registered on Signal without \
+                            their profile key; wait for a message from them or a contacts sync"
+                        ));
+                        return;
+                    }
+                    Err(error) => {
+                        self.command_error = Some(format!(
+                            "failed to check whether {recipient} is registered on Signal: {error}"
+                        ));
+                        return;
+                    }
+                }
+                let name = self.resolved_name_by_id(user_id).await;
+                self.ensure_contact_channel_exists(user_id, &name).await
+            }
+        };
+        self.switch_to_channel(channel_idx);
+        if channel_idx >= self.visible_channels_len() {
+            self.show_archived = true;
+        }
+    }
+
+    /// Names of the known contacts starting with `prefix`, for completing `/dm`
+    fn complete_contact_name(&self, prefix: &str) -> Vec<String> {
+        let prefix = prefix.to_lowercase();
+        self.names
+            .borrow()
+            .display_names()
+            .filter(|name| name.to_lowercase().starts_with(&prefix))
+            .map(ToString::to_string)
+            .sorted_unstable()
+            .dedup()
+            .collect()
+    }
+
+    /// Changes the notes about the contact of the selected channel
+    fn update_contact_notes(&mut self, f: impl FnOnce(&mut ContactNotes)) {
+        let Some(user_id) = self.channels.selected_item().and_then(|id| id.user()) else {
+            self.command_error = Some("notes can only be kept about contacts".to_string());
+            return;
+        };
+        let mut metadata = self.storage.metadata().into_owned();
+        let notes = metadata.contact_notes.entry(user_id).or_default();
+        f(notes);
+        if notes.is_empty() {
+            metadata.contact_notes.remove(&user_id);
+        }
+        self.storage.store_metadata(metadata);
+        if let Some(today) = self.birthdays_checked_on {
+            self.birthdays_today = self.birthdays_on(today);
+        }
+        // collect the changed notes
+        self.channel_info = None;
+    }
+
+    /// Sets the local name of the contact or group of the selected channel, or clears it
+    ///
+    /// The name of a contact is shown everywhere, e.g. for the messages and mentions in groups.
+    fn set_channel_nickname(&mut self, nickname: Option<String>) {
+        let Some(&channel_id) = self.channels.selected_item() else {
+            return;
+        };
+        if let Some(user_id) = channel_id.user() {
+            self.set_nickname(user_id, nickname);
+        } else if let Some(channel) = self.storage.channel(channel_id) {
+            let mut channel = channel.into_owned();
+            channel.nickname = nickname;
+            self.storage.store_channel(channel);
+        }
+        // the order and the info of the channel use the shown name
+        self.sort_channels();
+        self.channel_info = None;
+    }
+
+    /// Switches the theme by the schedule in the `auto` theme mode
+    pub fn step_theme(&mut self) {
+        if self.config.theme.mode != ThemeMode::Auto {
+            return;
+        }
+        if let Some(schedule) = self.theme_schedule {
+            let now = util::utc_timestamp_msec_to_local(util::utc_now_timestamp_msec());
+            let theme = Theme::scheduled(schedule, now.time());
+            if theme != self.theme {
+                self.theme = theme;
+                self.dirty.mark_all();
+            }
+        }
+    }
+
+    /// Applies the theme of the changed config file
+    ///
+    /// If the theme is invalid, the current theme is kept and the error is shown.
+    pub fn reload_theme(&mut self, theme: ThemeConfig) {
+        if theme == self.config.theme {
+            return;
+        }
+        let reloaded = theme
+            .schedule()
+            .and_then(|schedule| Ok((schedule, Palette::from_config(&theme)?)));
+        let (schedule, palette) = match reloaded {
+            Ok(reloaded) => reloaded,
+            Err(error) => {
+                self.command_error = Some(format!("failed to reload theme: {error}"));
+                return;
+            }
+        };
+        match theme.mode {
+            ThemeMode::Light => self.theme = Theme::Light,
+            ThemeMode::Dark => self.theme = Theme::Dark,
+            // keeps the theme detected at startup, unless there is a schedule
+            ThemeMode::Auto => (),
+        }
+        self.theme_schedule = schedule;
+        self.palette = palette;
+        self.config.theme = theme;
+        self.dirty.mark_all();
+        self.step_theme();
+    }
+
+    /// Looks up the birthdays of the contacts once a day and shows a notification for each
+    pub fn step_birthday_reminders(&mut self) {
+        let today = util::utc_timestamp_msec_to_local(util::utc_now_timestamp_msec()).date_naive();
+        self.check_birthdays(today);
+    }
+
+    fn check_birthdays(&mut self, today: NaiveDate) {
+        if self.birthdays_checked_on == Some(today) {
+            return;
+        }
+        self.birthdays_checked_on = Some(today);
+        self.birthdays_today = self.birthdays_on(today);
+        self.dirty.mark(Pane::Channels);
+        if !self.config.birthday_reminders {
+            return;
+        }
+        for &user_id in &self.birthdays_today {
+            let name = self.name_by_id_cached(user_id);
+            self.notify("Birthday", &format!("{name} has a birthday today"));
+        }
+    }
+
+    fn birthdays_on(&self, date: NaiveDate) -> BTreeSet<Uuid> {
+        self.storage
+            .metadata()
+            .contact_notes
+            .iter()
+            .filter(|(_, notes)| notes.birthday.is_some_and(|birthday| birthday.is_on(date)))
+            .map(|(&user_id, _)| user_id)
+            .collect()
+    }
+
+    /// Collects the details of the selected channel, if the channel info panel is shown and
+    /// another channel was selected since
+    pub async fn step_channel_info(&mut self) {
+        if !self.show_channel_info {
+            self.channel_info = None;
+            return;
+        }
+        let Some(channel_id) = self
+            .channels
+            .state
+            .selected()
+            .and_then(|idx| self.channels.items.get(idx).copied())
+        else {
+            return;
+        };
+        if self.channel_info.as_ref().map(|info| info.channel_id) == Some(channel_id) {
+            return;
+        }
+        let Some(channel) = self.storage.channel(channel_id).map(Cow::into_owned) else {
+            return;
+        };
+        let contact = match channel_id.user() {
+            Some(user_id) => self.signal_manager.contact(user_id).await,
+            None => None,
+        };
+        let mut info = ChannelInfo::collect(&*self.storage, &channel, contact.as_ref(), |id| {
+            self.name_by_id_cached(id)
+        });
+        if let Some(group_data) = channel.group_data.as_ref() {
+            match self
+                .signal_manager
+                .group_requests(group_data.master_key_bytes)
+                .await
+            {
+                Ok(requests) => info.requests = Some(requests).filter(|requests| requests.is_admin),
+                Err(error) => warn!(%error, "failed to look up the requests of the group"),
+            }
+        }
+        if info.phone_number.is_none() {
+            // e.g. the contact is not stored by the Signal store yet
+            info.phone_number = channel_id
+                .user()
+                .and_then(|user_id| self.names.borrow().phone_number(user_id).cloned());
+        }
+        self.channel_info = Some(info);
+        self.dirty.mark(Pane::ChannelInfo);
+    }
+
+    /// Whether the channel is listed outside of its section
+    fn is_out_of_section(&self, channel_idx: usize) -> bool {
+        let rank = |idx: usize| self.channel_section(self.channels.items[idx]);
+        let channel_rank = rank(channel_idx);
+        (channel_idx > 0 && channel_rank < rank(channel_idx - 1))
+            || (channel_idx + 1 < self.channels.items.len() && rank(channel_idx + 1) < channel_rank)
+    }
+
+    /// Number of lines of a channel in the channel list
+    pub fn channel_item_height(&self) -> usize {
+        if self.show_channel_details {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Arrival time of the newest message in the channel
+    pub(crate) fn last_message_arrived_at(&self, channel_id: ChannelId) -> Option<u64> {
+        self.messages.get(&channel_id)?.items.last().copied()
+    }
+
+    /// Makes sure that the selected channel is not hidden
+    fn clamp_channel_selection(&mut self) {
+        let visible_channels_len = self.visible_channels_len();
+        if let Some(selected_idx) = self.channels.state.selected() {
+            if selected_idx >= visible_channels_len {
+                self.channels
+                    .state
+                    .select(visible_channels_len.checked_sub(1));
+            }
+        }
+    }
+
+    /// Whether notifications for the channel are suppressed
+    fn is_muted(&self, channel_id: ChannelId) -> bool {
+        if self.config.mute_groups && matches!(channel_id, ChannelId::Group(_)) {
+            return true;
+        }
+        self.storage
+            .channel(channel_id)
+            .is_some_and(|channel| channel.is_muted(util::utc_now_timestamp_msec()))
+    }
+
+    /// Whether the message mentions our user, which notifies even in muted channels
+    ///
+    /// A mention of all group members only counts, unless `@all` mentions are ignored in the
+    /// group.
+    fn is_mentioned(&self, channel_id: ChannelId, message: &Message) -> bool {
+        if !message.mentions().any(|id| id == self.user_id) {
+            return false;
+        }
+        let Some(channel) = self.storage.channel(channel_id) else {
+            return true;
+        };
+        match &channel.group_data {
+            Some(group_data) if channel.ignore_mention_all => {
+                !message.mentions_all(&group_data.members)
+            }
+            _ => true,
+        }
+    }
+
+    pub fn select_previous_channel(&mut self) {
+        self.leave_selected_channel();
+        self.channels.previous();
+    }
+
+    pub fn select_next_channel(&mut self) {
+        self.leave_selected_channel();
+        self.channels.next();
+        self.clamp_channel_selection();
+    }
+
+    pub fn on_pgup(&mut self) {
+        if let Some(channel_id) = self.channels.selected_item() {
+            self.messages
+                .get_mut(channel_id)
+                .expect("non-existent channel")
+                .next();
+        }
+    }
+
+    pub fn on_pgdn(&mut self) {
+        if let Some(channel_id) = self.channels.selected_item() {
+            self.messages
+                .get_mut(channel_id)
+                .expect("non-existent channel")
+                .previous()
+        }
+    }
+
+    /// Moves the message selection by more than one message
+    fn select_message(&mut self, direction: MoveDirection, amount: MoveAmountVisual) {
+        let Some(channel_id) = self.channels.selected_item() else {
+            return;
+        };
+        let messages = self
+            .messages
+            .get_mut(channel_id)
+            .expect("non-existent channel");
+        let Some(last_idx) = messages.items.len().checked_sub(1) else {
+            return;
+        };
+        let amount = visual_amount(&amount, messages.rendered.page_len);
+        let selected = messages.state.selected().unwrap_or(0);
+        let selected = match direction {
+            MoveDirection::Previous => selected.saturating_add(amount).min(last_idx),
+            MoveDirection::Next => selected.saturating_sub(amount),
+        };
+        messages.state.select(Some(selected));
+        self.load_older_messages();
+    }
+
+    /// Scrolls the message viewport without selecting a message
+    ///
+    /// While the viewport is at the newest message, it sticks to it when new messages arrive. The
+    /// direction is on screen, i.e. with the newest message at the top, scrolling up goes to newer
+    /// messages.
+    pub fn scroll_messages(&mut self, direction: DirectionVertical, amount: MoveAmountVisual) {
+        let Some(channel_id) = self.channels.selected_item() else {
+            return;
+        };
+        let direction = if self.newest_first {
+            direction.reversed()
+        } else {
+            direction
+        };
+        let messages = self
+            .messages
+            .get_mut(channel_id)
+            .expect("non-existent channel");
+        messages.state.select(None);
+        let max_offset = messages.items.len().saturating_sub(1);
+        let amount = visual_amount(&amount, messages.rendered.page_len);
+        let offset = &mut messages.rendered.offset;
+        *offset = match direction {
+            // scrolling beyond the oldest message is corrected when rendering
+            DirectionVertical::Up => offset.saturating_add(amount).min(max_offset),
+            DirectionVertical::Down => offset.saturating_sub(amount),
+        };
+        self.load_older_messages();
+    }
+
+    /// Loads the previous page of messages of the selected channel from the storage, when the
+    /// selection or the viewport comes close to the oldest loaded message
+    fn load_older_messages(&mut self) {
+        let Some(&channel_id) = self.channels.selected_item() else {
+            return;
+        };
+        let Some(messages) = self.messages.get(&channel_id) else {
+            return;
+        };
+        let position = messages
+            .state
+            .selected()
+            .unwrap_or(messages.rendered.offset);
+        if position + 2 * messages.rendered.page_len.max(1) >= messages.items.len() {
+            self.load_previous_page(channel_id);
+        }
+    }
+
+    /// Loads the page of messages before the oldest loaded message of the channel
+    ///
+    /// Returns whether there might be more messages to load.
+    fn load_previous_page(&mut self, channel_id: ChannelId) -> bool {
+        if !self.partially_loaded.contains(&channel_id) {
+            return false;
+        }
+        let messages = self.messages.entry(channel_id).or_default();
+        let page_size = self.config.history_page_size.max(1);
+        let oldest = messages.items.first().copied().unwrap_or(u64::MAX);
+        let older: Vec<u64> = self
+            .storage
+            .messages_before(channel_id, oldest, page_size)
+            .iter()
+            .map(|message| message.arrived_at)
+            .collect();
+        let has_more = older.len() == page_size;
+        if !has_more {
+            self.partially_loaded.remove(&channel_id);
+        }
+        // indices of the selection and the viewport count from the newest message
+        messages.items.splice(0..0, older);
+        has_more
+    }
+
+    /// Loads the first pages of the messages of the channels in batches, between handling the
+    /// other events
+    pub fn load_messages_in_background(&self) {
+        if !self.unloaded_channels.is_empty() {
+            self.event_tx
+                .send(Event::LoadMessages)
+                .expect("logic error: events channel closed");
+        }
+    }
+
+    /// Whether only the newest message of the channel is loaded yet
+    pub(crate) fn is_loading_messages(&self, channel_id: ChannelId) -> bool {
+        self.unloaded_channels.contains(&channel_id)
+    }
+
+    /// Loads the first pages of the messages of up to `max_channels` channels, starting with the
+    /// selected channel and then in the order of the channel list
+    fn load_first_pages(&mut self, max_channels: usize) {
+        let selected = self.channels.selected_item().copied();
+        let channel_ids: Vec<ChannelId> = selected
+            .into_iter()
+            .chain(self.channels.items.iter().copied())
+            .filter(|channel_id| self.unloaded_channels.contains(channel_id))
+            .unique()
+            .take(max_channels)
+            .collect();
+        for channel_id in channel_ids {
+            self.unloaded_channels.remove(&channel_id);
+            self.load_first_page(channel_id);
+        }
+    }
+
+    /// Loads the messages of the channel up to a page, before the loaded ones
+    ///
+    /// The messages received since the start are already loaded, and count towards the page.
+    fn load_first_page(&mut self, channel_id: ChannelId) {
+        if !self.partially_loaded.contains(&channel_id) {
+            return;
+        }
+        let messages = self.messages.entry(channel_id).or_default();
+        let page_size = self.config.history_page_size.max(1);
+        let missing = page_size.saturating_sub(messages.items.len());
+        if missing == 0 {
+            return;
+        }
+        let oldest = messages.items.first().copied().unwrap_or(u64::MAX);
+        let older: Vec<u64> = self
+            .storage
+            .messages_before(channel_id, oldest, missing)
+            .iter()
+            .map(|message| message.arrived_at)
+            .collect();
+        if older.len() < missing {
+            self.partially_loaded.remove(&channel_id);
+        }
+        // indices of the selection and the viewport count from the newest message
+        messages.items.splice(0..0, older);
+    }
+
+    /// Switches to the channel of the message and selects it, loading older messages as needed
+    pub(crate) fn show_message(&mut self, message_id: MessageId) {
+        let Some(channel_idx) = self.channel_idx(message_id.channel_id) else {
+            return;
+        };
+        self.switch_to_channel(channel_idx);
+        if channel_idx >= self.visible_channels_len() {
+            self.show_archived = true;
+        }
+        let channel_id = message_id.channel_id;
+        let position = loop {
+            let items = &self.messages.entry(channel_id).or_default().items;
+            if let Some(position) = items.iter().position(|&t| t == message_id.arrived_at) {
+                break Some(position);
+            }
+            if !self.load_previous_page(channel_id) {
+                break None;
+            }
+        };
+        // the message may have been deleted in the meantime
+        let Some(position) = position else {
+            return;
+        };
+        let messages = self.messages.entry(channel_id).or_default();
+        messages
+            .state
+            .select(Some(messages.items.len() - 1 - position));
+    }
+
+    /// Whether the user is reading the selected channel, so that arriving messages are read
+    ///
+    /// Depending on the config, this is not the case while the terminal is not focused or the
+    /// user is idle.
+    pub(crate) fn is_reading(&self) -> bool {
+        let mark_read = &self.config.mark_read;
+        let is_idle = mark_read.idle_minutes.is_some_and(|minutes| {
+            self.last_key_at.elapsed() >= Duration::from_secs(minutes.saturating_mul(60))
+        });
+        (self.is_focused || mark_read.when_unfocused) && !is_idle
+    }
+
+    /// Handles the terminal gaining or losing the focus
+    pub fn on_focus(&mut self, is_focused: bool) {
+        if self.is_focused != is_focused {
+            self.focus_changed_at = Instant::now();
+        }
+        self.is_focused = is_focused;
+        if self.is_reading() {
+            self.reset_unread_messages();
+            // the shown messages are read now
+            self.dirty.mark(Pane::Messages);
+        }
+    }
+
+    pub fn reset_unread_messages(&mut self) {
+        if let Some(&channel_id) = self.channels.selected_item() {
+            self.mark_read(channel_id);
+        }
+    }
+
+    fn mark_read(&mut self, channel_id: ChannelId) {
+        if let Some(channel) = self.storage.channel(channel_id) {
+            if channel.unread_messages > 0 {
+                let mut channel = channel.into_owned();
+                channel.unread_messages = 0;
+                self.storage.store_channel(channel);
+            }
+        }
+    }
+
+    /// Handles content received from the Signal servers
+    ///
+    /// This is the single receive path of the app: direct and group messages, reactions,
+    /// receipts, typing notifications and sync messages from other devices all arrive as presage
+    /// `Content` and are dispatched from here.
+    pub async fn on_message(&mut self, content: Content) -> anyhow::Result<()> {
+        // tracing::info!(?content, "incoming");
+
+        #[cfg(feature = "dev")]
+        if self.config.developer.dump_raw_messages {
+            if let Err(e) = crate::dev::dump_raw_message(&content) {
+                warn!(error = %e, "failed to dump raw message");
+            }
+        }
+
+        let user_id = self.user_id;
+
+        let sender_id = content.metadata.sender.raw_uuid();
+        if sender_id != user_id && self.is_blocked(sender_id) {
+            debug!(%sender_id, "dropping content of blocked contact");
+            return Ok(());
+        }
+
+        if let ContentBody::SynchronizeMessage(SyncMessage {
+            ref read,
+            ref blocked,
+            ..
+        }) = content.body
+        {
+            self.handle_read(read);
+            if let Some(blocked) = blocked {
+                self.handle_blocked_sync(blocked);
+            }
+        }
+
+        let (channel_idx, message) = match (content.metadata, content.body) {
+            // Private note message
+            (
                 _,
                 ContentBody::SynchronizeMessage(SyncMessage {
                     sent:
                         Some(Sent {
-                            destination_service_id: Some(destination_uuid),
+                            destination_service_id: Some(destination_uuid),
+                            timestamp: Some(timestamp),
+                            message:
+                                Some(DataMessage {
+                                    mut body,
+                                    attachments: attachment_pointers,
+                                    sticker,
+                                    body_ranges,
+                                    ..
+                                }),
+                            ..
+                        }),
+                    ..
+                }),
+            ) if destination_uuid.parse() == Ok(user_id) => {
+                let channel_idx = self.ensure_own_channel_exists();
+                let attachments = self.save_attachments(attachment_pointers).await;
+                add_emoji_from_sticker(&mut body, sticker);
+
+                let body_ranges = body_ranges.into_iter().filter_map(BodyRange::from_proto);
+
+                let message = Message::new(user_id, body, body_ranges, timestamp, attachments);
+                (channel_idx, message)
+            }
+            // reactions
+            (
+                Metadata { sender, .. },
+                ContentBody::SynchronizeMessage(SyncMessage {
+                    sent:
+                        Some(Sent {
+                            destination_service_id: destination_uuid,
+                            message:
+                                Some(DataMessage {
+                                    body: None,
+                                    group_v2,
+                                    reaction:
+                                        Some(Reaction {
+                                            emoji: Some(emoji),
+                                            remove,
+                                            target_author_aci: Some(target_author_uuid),
+                                            target_sent_timestamp: Some(target_sent_timestamp),
+                                            ..
+                                        }),
+                                    ..
+                                }),
+                            ..
+                        }),
+                    read,
+                    ..
+                }),
+            ) => {
+                let channel_id = if let Some(GroupContextV2 {
+                    master_key: Some(master_key),
+                    ..
+                }) = group_v2
+                {
+                    ChannelId::from_master_key_bytes(master_key)?
+                } else if let Some(uuid) = destination_uuid {
+                    ChannelId::User(uuid.parse()?)
+                } else {
+                    ChannelId::User(target_author_uuid.parse()?)
+                };
+
+                self.handle_reaction(
+                    channel_id,
+                    target_sent_timestamp,
+                    sender.raw_uuid(),
+                    emoji,
+                    HandleReactionOptions::new()
+                        .remove(remove.unwrap_or(false))
+                        .notify(true)
+                        .bell(true),
+                )
+                .await;
+                read.into_iter().for_each(|r| {
+                    self.handle_receipt(
+                        Uuid::parse_str(r.sender_aci.unwrap().as_str()).unwrap(),
+                        Receipt::Read,
+                        vec![r.timestamp.unwrap()],
+                    );
+                });
+                return Ok(());
+            }
+            (
+                Metadata { sender, .. },
+                ContentBody::DataMessage(DataMessage {
+                    body: None,
+                    group_v2,
+                    reaction:
+                        Some(Reaction {
+                            emoji: Some(emoji),
+                            remove,
+                            target_sent_timestamp: Some(target_sent_timestamp),
+                            target_author_aci: Some(target_author_uuid),
+                            ..
+                        }),
+                    ..
+                }),
+            ) => {
+                let channel_id = if let Some(GroupContextV2 {
+                    master_key: Some(master_key),
+                    ..
+                }) = group_v2
+                {
+                    ChannelId::from_master_key_bytes(master_key)?
+                } else if sender.raw_uuid() == self.user_id {
+                    // reaction from us => target author is the user channel
+                    ChannelId::User(target_author_uuid.parse()?)
+                } else {
+                    // reaction is from somebody else => they are the user channel
+                    ChannelId::User(sender.raw_uuid())
+                };
+
+                self.handle_reaction(
+                    channel_id,
+                    target_sent_timestamp,
+                    sender.raw_uuid(),
+                    emoji,
+                    HandleReactionOptions::new()
+                        .remove(remove.unwrap_or(false))
+                        .notify(true)
+                        .bell(true),
+                )
+                .await;
+                return Ok(());
+            }
+            // Direct/group message by us from a different device
+            (
+                Metadata { sender, .. },
+                ContentBody::SynchronizeMessage(SyncMessage {
+                    sent:
+                        Some(Sent {
+                            destination_service_id: destination_uuid,
                             timestamp: Some(timestamp),
                             message:
                                 Some(DataMessage {
                                     mut body,
+                                    profile_key,
+                                    group_v2,
+                                    quote,
                                     attachments: attachment_pointers,
                                     sticker,
                                     body_ranges,
@@ -619,1170 +3385,3098 @@ impl App {
                         }),
                     ..
                 }),
-            ) if destination_uuid.parse() == Ok(user_id) => {
-                let channel_idx = self.ensure_own_channel_exists();
+            ) if sender.raw_uuid() == user_id => {
+                let channel_idx = if let Some(GroupContextV2 {
+                    master_key: Some(master_key),
+                    revision: Some(revision),
+                    ..
+                }) = group_v2
+                {
+                    // message to a group
+                    let master_key = master_key
+                        .try_into()
+                        .map_err(|_| anyhow!("invalid master key"))?;
+                    self.ensure_group_channel_exists(master_key, revision)
+                        .await
+                        .context("failed to create group channel")?
+                } else if let Some(destination_uuid) = destination_uuid {
+                    let profile_key = profile_key
+                        .context("sync message with destination without profile key")?
+                        .try_into()
+                        .map_err(|_| anyhow!("invalid profile key"))?;
+                    let destination_uuid = destination_uuid.parse()?;
+                    let name = self.resolved_name_by_id(destination_uuid).await;
+                    self.ensure_user_is_known(destination_uuid, Some(profile_key))
+                        .await;
+                    self.ensure_contact_channel_exists(destination_uuid, &name)
+                        .await
+                } else {
+                    debug!("dropping a sync message not attached to a channel");
+                    return Ok(());
+                };
+
+                if self.is_duplicate_message(channel_idx, user_id, timestamp) {
+                    return Ok(());
+                }
+
+                add_emoji_from_sticker(&mut body, sticker);
+                let quote = quote.and_then(Message::from_quote).map(Box::new);
                 let attachments = self.save_attachments(attachment_pointers).await;
+                let body_ranges = body_ranges.into_iter().filter_map(BodyRange::from_proto);
+
+                let message = Message {
+                    quote,
+                    ..Message::new(user_id, body, body_ranges, timestamp, attachments)
+                };
+
+                if message.is_empty() {
+                    debug!("dropping empty message");
+                    return Ok(());
+                }
+
+                (channel_idx, message)
+            }
+            // Incoming direct/group message
+            (
+                Metadata { sender, .. },
+                ContentBody::DataMessage(DataMessage {
+                    mut body,
+                    group_v2,
+                    timestamp: Some(timestamp),
+                    profile_key,
+                    quote,
+                    attachments: attachment_pointers,
+                    sticker,
+                    body_ranges,
+                    ..
+                }),
+            ) => {
+                let (channel_idx, from) = if let Some(GroupContextV2 {
+                    master_key: Some(master_key),
+                    revision: Some(revision),
+                    ..
+                }) = group_v2
+                {
+                    // incoming group message
+                    // profile_key can be None and is not required for known contacts
+                    let profile_key = match profile_key {
+                        Some(profile_key) => Some(
+                            profile_key
+                                .try_into()
+                                .map_err(|_| anyhow!("invalid profile key"))?,
+                        ),
+                        None => None,
+                    };
+                    let master_key = master_key
+                        .try_into()
+                        .map_err(|_| anyhow!("invalid group master key"))?;
+                    let channel_idx = self
+                        .ensure_group_channel_exists(master_key, revision)
+                        .await
+                        .context("failed to create group channel")?;
+
+                    self.ensure_user_is_known(sender.raw_uuid(), profile_key)
+                        .await;
+                    let from = self.name_by_id(sender.raw_uuid()).await;
+
+                    (channel_idx, from)
+                } else {
+                    // incoming direct message
+                    let profile_key = profile_key
+                        .context("sync message with destination without profile key")?
+                        .try_into()
+                        .map_err(|_| anyhow!("invalid profile key"))?;
+                    self.ensure_user_is_known(sender.raw_uuid(), Some(profile_key))
+                        .await;
+                    let name = self.resolved_name_by_id(sender.raw_uuid()).await;
+                    let channel_idx = self
+                        .ensure_contact_channel_exists(sender.raw_uuid(), &name)
+                        .await;
+                    // Reset typing notification as the Tipyng::Stop are not always sent by the server when a message is sent.
+                    let channel_id = self.channels.items[channel_idx];
+                    let mut channel = self
+                        .storage
+                        .channel(channel_id)
+                        .expect("non-existent channel")
+                        .into_owned();
+                    let from = channel.name.clone();
+                    if channel.reset_writing(sender.raw_uuid()) {
+                        self.storage.store_channel(channel);
+                    }
+                    (channel_idx, from)
+                };
+
+                if self.is_duplicate_message(channel_idx, sender.raw_uuid(), timestamp) {
+                    return Ok(());
+                }
+
                 add_emoji_from_sticker(&mut body, sticker);
 
-                let body_ranges = body_ranges.into_iter().filter_map(BodyRange::from_proto);
+                let attachments = self.save_attachments(attachment_pointers).await;
+                let quote = quote.and_then(Message::from_quote).map(Box::new);
+                let body_ranges = body_ranges.into_iter().filter_map(BodyRange::from_proto);
+                let message = Message {
+                    quote,
+                    ..Message::new(sender.raw_uuid(), body, body_ranges, timestamp, attachments)
+                };
+
+                let channel_id = self.channels.items[channel_idx];
+                if !self.is_muted(channel_id) || self.is_mentioned(channel_id, &message) {
+                    self.notify_about_message(
+                        channel_id,
+                        &from,
+                        message.message.as_deref(),
+                        &message.attachments,
+                    );
+                }
+
+                // Send "Delivered" receipt
+                self.add_receipt_event(ReceiptEvent::new(
+                    sender.raw_uuid(),
+                    timestamp,
+                    Receipt::Delivered,
+                ));
+
+                if message.is_empty() {
+                    return Ok(());
+                }
+
+                self.run_received_hooks(channel_id, &from, &message);
+
+                (channel_idx, message)
+            }
+            (metadata, ContentBody::SynchronizeMessage(sync_message)) => {
+                return self.handle_sync_message(metadata, sync_message);
+            }
+            (
+                Metadata { sender, .. },
+                ContentBody::ReceiptMessage(ReceiptMessage {
+                    r#type: Some(receipt_type),
+                    timestamp: timestamps,
+                }),
+            ) => {
+                let receipt = Receipt::from_i32(receipt_type);
+                self.handle_receipt(sender.raw_uuid(), receipt, timestamps);
+                return Ok(());
+            }
+
+            (
+                Metadata { sender, .. },
+                ContentBody::TypingMessage(TypingMessage {
+                    timestamp: Some(timest),
+                    group_id,
+                    action: Some(act),
+                }),
+            ) => {
+                let group_id_bytes = match group_id.map(TryInto::try_into).transpose() {
+                    Ok(bytes) => bytes,
+                    Err(_) => {
+                        error!("invalid group id: failed to convert to group identified bytes");
+                        return Ok(());
+                    }
+                };
+                if self
+                    .handle_typing(
+                        sender.raw_uuid(),
+                        group_id_bytes,
+                        TypingAction::from_i32(act),
+                        timest,
+                    )
+                    .is_err()
+                {
+                    error!("failed to handle typing: unknown error");
+                }
+                return Ok(());
+            }
+
+            (metadata, body) => {
+                info!(?metadata, body = ?Sensitive(body), "skipping unhandled message");
+                return Ok(());
+            }
+        };
+
+        self.add_message_to_channel(channel_idx, message);
+
+        Ok(())
+    }
+
+    /// Whether the message was received before, e.g. replayed after a reconnect
+    fn is_duplicate_message(&self, channel_idx: usize, from_id: Uuid, arrived_at: u64) -> bool {
+        let channel_id = self.channels.items[channel_idx];
+        let is_duplicate = self
+            .storage
+            .contains_message(channel_id, from_id, arrived_at);
+        if is_duplicate {
+            debug!(arrived_at, "dropping duplicate message");
+        }
+        is_duplicate
+    }
+
+    fn notify_about_message(
+        &mut self,
+        channel_id: ChannelId,
+        from: &str,
+        body: Option<&str>,
+        attachments: &[Attachment],
+    ) {
+        if !self.is_notification_suppressed() {
+            let attachments_text = notification_text_for_attachments(attachments);
+            let notification = [body, attachments_text.as_deref()]
+                .into_iter()
+                .flatten()
+                .join(" ");
+            if !notification.is_empty() {
+                let (summary, text) = match self.notification_content(channel_id) {
+                    NotificationContent::Full => (from, notification.as_str()),
+                    NotificationContent::Sender => (from, NEW_MESSAGE),
+                    NotificationContent::Hidden => (NEW_MESSAGE, ""),
+                };
+                self.notify_about(channel_id, summary, text);
+            }
+        }
+        self.bell();
+    }
+
+    /// How much of the messages of the channel is shown in notifications
+    fn notification_content(&self, channel_id: ChannelId) -> NotificationContent {
+        self.storage
+            .channel(channel_id)
+            .and_then(|channel| channel.notification_content)
+            .unwrap_or(self.config.notification.content)
+    }
+
+    /// Whether notifications about messages are suppressed, because gurk is focused or the user
+    /// does not want to be disturbed
+    fn is_notification_suppressed(&self) -> bool {
+        if self.presence == Presence::DoNotDisturb {
+            return true;
+        }
+        let config = &self.config.notification;
+        if config.when_focused {
+            return false;
+        }
+        match config.focus_command.as_deref() {
+            Some(command) => self.is_focused_by_command(command),
+            None => self.is_focused,
+        }
+    }
+
+    /// Whether gurk is focused according to the focus command, reusing its result for
+    /// `FOCUS_COMMAND_TTL`
+    fn is_focused_by_command(&self, command: &str) -> bool {
+        let mut result = self.focus_command_result.borrow_mut();
+        if let Some((cached_command, ran_at, is_focused)) = result.as_ref() {
+            if cached_command == command && ran_at.elapsed() < FOCUS_COMMAND_TTL {
+                return *is_focused;
+            }
+        }
+        let is_focused = run_focus_command(command);
+        *result = Some((command.to_string(), Instant::now(), is_focused));
+        is_focused
+    }
+
+    fn set_notification_content(&mut self, content: Option<NotificationContent>) {
+        let Some(&channel_id) = self.channels.selected_item() else {
+            return;
+        };
+        let Some(channel) = self.storage.channel(channel_id) else {
+            return;
+        };
+        let mut channel = channel.into_owned();
+        channel.notification_content = content;
+        self.storage.store_channel(channel);
+    }
+
+    pub fn step_receipts(&mut self) {
+        self.receipt_handler.step(self.signal_manager.as_ref());
+    }
+
+    fn handle_typing(
+        &mut self,
+        sender_uuid: Uuid,
+        group_id: Option<GroupIdentifierBytes>,
+        action: TypingAction,
+        _timestamp: u64,
+    ) -> Result<(), ()> {
+        if let Some(gid) = group_id {
+            let mut channel = self
+                .storage
+                .channel(ChannelId::Group(gid))
+                .ok_or(())?
+                .into_owned();
+            if let TypingSet::GroupTyping(ref mut hash_set) = channel.typing {
+                match action {
+                    TypingAction::Started => {
+                        hash_set.insert(sender_uuid);
+                    }
+                    TypingAction::Stopped => {
+                        hash_set.remove(&sender_uuid);
+                    }
+                }
+                self.storage.store_channel(channel);
+            } else {
+                error!("Got a single typing instead of hash set on a group");
+            }
+        } else {
+            let mut channel = self
+                .storage
+                .channel(ChannelId::User(sender_uuid))
+                .ok_or(())?
+                .into_owned();
+            if let TypingSet::SingleTyping(_) = channel.typing {
+                match action {
+                    TypingAction::Started => {
+                        channel.typing = TypingSet::SingleTyping(true);
+                    }
+                    TypingAction::Stopped => {
+                        channel.typing = TypingSet::SingleTyping(false);
+                    }
+                }
+                self.storage.store_channel(channel);
+            } else {
+                error!("Got a hash set instead of single typing on a direct chat");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn add_receipt_event(&mut self, event: ReceiptEvent) {
+        self.receipt_handler.add_receipt_event(event);
+    }
+
+    fn handle_receipt(&mut self, sender_uuid: Uuid, receipt: Receipt, timestamps: Vec<u64>) {
+        let sender_channels: Vec<ChannelId> = self
+            .storage
+            .channels()
+            .filter(|channel| match channel.id {
+                ChannelId::User(uuid) => uuid == sender_uuid,
+                ChannelId::Group(_) => channel
+                    .group_data
+                    .as_ref()
+                    .map(|group_data| group_data.members.contains(&sender_uuid))
+                    .unwrap_or(false),
+            })
+            .map(|channel| channel.id)
+            .collect();
+
+        if timestamps.is_empty() {
+            return;
+        }
+
+        let mut found_channel_id = None;
+        let mut messages_to_store = Vec::new();
+
+        'outer: for channel_id in sender_channels {
+            for &ts in &timestamps {
+                // looked up by id, so that the history of the channel is not read
+                if let Some(msg) = self
+                    .storage
+                    .message(MessageId::new(channel_id, ts))
+                    .filter(|msg| !msg.is_edit())
+                {
+                    let mut msg = msg.into_owned();
+                    if msg.receipt < receipt {
+                        msg.receipt = msg.receipt.max(receipt);
+                        messages_to_store.push(msg);
+                    }
+                    found_channel_id = Some(channel_id);
+                }
+            }
+
+            if found_channel_id.is_some() {
+                // if one ts was found, then all other ts have to be in the same channel
+                break 'outer;
+            }
+        }
+
+        if let Some(channel_id) = found_channel_id {
+            for message in messages_to_store {
+                self.storage.store_message(channel_id, message);
+            }
+        }
+    }
+
+    async fn handle_reaction(
+        &mut self,
+        channel_id: ChannelId,
+        target_sent_timestamp: u64,
+        sender_uuid: Uuid,
+        emoji: String,
+        HandleReactionOptions {
+            remove,
+            notify,
+            bell,
+        }: HandleReactionOptions,
+    ) -> Option<()> {
+        let mut message = self
+            .storage
+            .message(MessageId::new(channel_id, target_sent_timestamp))?
+            .into_owned();
+
+        let reaction_idx = message
+            .reactions
+            .iter()
+            .position(|(from_id, _)| from_id == &sender_uuid);
+        let is_added = if let Some(idx) = reaction_idx {
+            if remove {
+                message.reactions.swap_remove(idx);
+                false
+            } else {
+                message.reactions[idx].1.clone_from(&emoji);
+                true
+            }
+        } else {
+            message.reactions.push((sender_uuid, emoji.clone()));
+            true
+        };
+        let message = self.storage.store_message(channel_id, message);
+        let is_notified = self.is_reaction_notified(channel_id);
+
+        if is_added && channel_id != ChannelId::User(self.user_id) {
+            // Notification
+            let mut notification = format!("reacted {emoji}");
+            if let Some(text) = message.message.as_ref() {
+                notification.push_str(" to: ");
+                notification.push_str(text);
+            }
+
+            // makes borrow checker happy
+            let channel = self.storage.channel(channel_id)?;
+            let channel_name = channel.shown_name().to_string();
+
+            let sender_name = self.name_by_id(sender_uuid).await;
+            let summary = if let ChannelId::Group(_) = channel_id {
+                Cow::from(format!("{sender_name} in {channel_name}"))
+            } else {
+                Cow::from(sender_name)
+            };
+
+            let is_muted = self.is_muted(channel_id);
+            if notify && is_notified && !is_muted && !self.is_notification_suppressed() {
+                let text = format!("{summary} {notification}");
+                let (summary, text) = match self.notification_content(channel_id) {
+                    NotificationContent::Full => (summary.as_ref(), text.as_str()),
+                    NotificationContent::Sender => (summary.as_ref(), NEW_REACTION),
+                    NotificationContent::Hidden => (NEW_REACTION, ""),
+                };
+                self.notify_about(channel_id, summary, text);
+            }
+
+            if bell && is_notified && !is_muted {
+                self.bell();
+            }
+
+            let channel_idx = self.channel_idx(channel_id).expect("non-existent channel");
+            self.touch_channel(channel_idx);
+        }
+
+        Some(())
+    }
+
+    /// Whether reactions in the channel trigger a notification and the bell
+    fn is_reaction_notified(&self, channel_id: ChannelId) -> bool {
+        match self.config.reaction_notifications {
+            ReactionNotifications::Always => true,
+            ReactionNotifications::DirectOnly => channel_id.is_user(),
+            ReactionNotifications::Never => false,
+        }
+    }
+
+    async fn ensure_group_channel_exists(
+        &mut self,
+        master_key: GroupMasterKeyBytes,
+        revision: u32,
+    ) -> anyhow::Result<usize> {
+        let channel_id = ChannelId::from_master_key_bytes(master_key)?;
+        if let Some(channel_idx) = self.channel_idx(channel_id) {
+            // existing channel
+            let channel = self
+                .storage
+                .channel(channel_id)
+                .expect("non-existent channel");
+
+            let is_stale = match channel.group_data.as_ref() {
+                Some(group_data) => group_data.revision != revision,
+                None => true,
+            };
+            if is_stale {
+                let ResolvedGroup {
+                    name,
+                    group_data,
+                    profile_keys,
+                } = self.signal_manager.resolve_group(master_key).await?;
+
+                let mut channel = channel.into_owned();
+
+                self.ensure_users_are_known(
+                    group_data
+                        .members
+                        .iter()
+                        .copied()
+                        .zip(profile_keys.into_iter()),
+                )
+                .await;
+
+                channel.name = name;
+                channel.group_data = Some(group_data);
+                self.storage.store_channel(channel);
+            }
+            Ok(channel_idx)
+        } else {
+            // new channel
+            let ResolvedGroup {
+                name,
+                group_data,
+                profile_keys,
+            } = self.signal_manager.resolve_group(master_key).await?;
+
+            self.ensure_users_are_known(
+                group_data
+                    .members
+                    .iter()
+                    .copied()
+                    .zip(profile_keys.into_iter()),
+            )
+            .await;
+
+            let channel = Channel {
+                id: channel_id,
+                name,
+                group_data: Some(group_data),
+                unread_messages: 0,
+                typing: TypingSet::GroupTyping(Default::default()),
+                muted_until: None,
+                pinned: false,
+                archived: false,
+                burn_after_reading: false,
+                max_messages: None,
+                ignore_mention_all: false,
+                notification_content: None,
+                nickname: None,
+            };
+            self.storage.store_channel(channel);
+
+            let channel_idx = self.channels.items.len();
+            self.channels.items.push(channel_id);
 
-                let message = Message::new(user_id, body, body_ranges, timestamp, attachments);
-                (channel_idx, message)
-            }
-            // reactions
-            (
-                Metadata { sender, .. },
-                ContentBody::SynchronizeMessage(SyncMessage {
-                    sent:
-                        Some(Sent {
-                            destination_service_id: destination_uuid,
-                            message:
-                                Some(DataMessage {
-                                    body: None,
-                                    group_v2,
-                                    reaction:
-                                        Some(Reaction {
-                                            emoji: Some(emoji),
-                                            remove,
-                                            target_author_aci: Some(target_author_uuid),
-                                            target_sent_timestamp: Some(target_sent_timestamp),
-                                            ..
-                                        }),
-                                    ..
-                                }),
-                            ..
-                        }),
-                    read,
-                    ..
-                }),
-            ) => {
-                let channel_id = if let Some(GroupContextV2 {
-                    master_key: Some(master_key),
-                    ..
-                }) = group_v2
-                {
-                    ChannelId::from_master_key_bytes(master_key)?
-                } else if let Some(uuid) = destination_uuid {
-                    ChannelId::User(uuid.parse()?)
-                } else {
-                    ChannelId::User(target_author_uuid.parse()?)
-                };
+            Ok(channel_idx)
+        }
+    }
 
-                self.handle_reaction(
-                    channel_id,
-                    target_sent_timestamp,
-                    sender.raw_uuid(),
-                    emoji,
-                    HandleReactionOptions::new()
-                        .remove(remove.unwrap_or(false))
-                        .notify(true)
-                        .bell(true),
-                )
+    /// Updates the names and the members of the known groups
+    pub(crate) async fn refresh_groups(&mut self) {
+        let groups: Vec<_> = self
+            .storage
+            .channels()
+            .filter_map(|channel| Some((channel.id, channel.group_data.as_ref()?.master_key_bytes)))
+            .collect();
+        for (channel_id, master_key) in groups {
+            let ResolvedGroup {
+                name,
+                group_data,
+                profile_keys,
+            } = match self.signal_manager.resolve_group(master_key).await {
+                Ok(group) => group,
+                Err(error) => {
+                    warn!(%error, "failed to refresh group");
+                    continue;
+                }
+            };
+            let Some(mut channel) = self.storage.channel(channel_id).map(Cow::into_owned) else {
+                continue;
+            };
+            if channel.name == name && channel.group_data.as_ref() == Some(&group_data) {
+                continue;
+            }
+            self.ensure_users_are_known(group_data.members.iter().copied().zip(profile_keys))
                 .await;
-                read.into_iter().for_each(|r| {
-                    self.handle_receipt(
-                        Uuid::parse_str(r.sender_aci.unwrap().as_str()).unwrap(),
-                        Receipt::Read,
-                        vec![r.timestamp.unwrap()],
-                    );
-                });
-                return Ok(());
+            info!(revision = group_data.revision, "refreshed group");
+            channel.name = name;
+            channel.group_data = Some(group_data);
+            self.storage.store_channel(channel);
+        }
+    }
+
+    async fn ensure_user_is_known(&mut self, uuid: Uuid, profile_key: Option<ProfileKeyBytes>) {
+        // a profile key learned before, e.g. from a group, resolves the name of a new contact
+        let names = self.names.get_mut();
+        let profile_key = match profile_key {
+            Some(profile_key) => {
+                names.set_profile_key(uuid, profile_key);
+                Some(profile_key)
             }
-            (
-                Metadata { sender, .. },
-                ContentBody::DataMessage(DataMessage {
-                    body: None,
-                    group_v2,
-                    reaction:
-                        Some(Reaction {
-                            emoji: Some(emoji),
-                            remove,
-                            target_sent_timestamp: Some(target_sent_timestamp),
-                            target_author_aci: Some(target_author_uuid),
-                            ..
-                        }),
-                    ..
-                }),
-            ) => {
-                let channel_id = if let Some(GroupContextV2 {
-                    master_key: Some(master_key),
-                    ..
-                }) = group_v2
-                {
-                    ChannelId::from_master_key_bytes(master_key)?
-                } else if sender.raw_uuid() == self.user_id {
-                    // reaction from us => target author is the user channel
-                    ChannelId::User(target_author_uuid.parse()?)
-                } else {
-                    // reaction is from somebody else => they are the user channel
-                    ChannelId::User(sender.raw_uuid())
-                };
+            None => names.get(uuid).and_then(|user| user.profile_key),
+        };
+        // is_known <=>
+        //   * in names, and
+        //   * is not empty
+        //   * is not a phone numbers, and
+        //   * is not their uuid
+        let is_known = self
+            .storage
+            .name(uuid)
+            .filter(|name| {
+                !name.is_empty()
+                    && !util::is_phone_number(name)
+                    && Uuid::parse_str(name) != Ok(uuid)
+            })
+            .is_some();
+        if !is_known {
+            let name = if let Some(name) = self.signal_manager.profile_name(uuid).await {
+                name
+            } else {
+                match profile_key {
+                    Some(profile_key) => {
+                        // try to resolve from signal service via their profile
+                        self.signal_manager
+                            .resolve_profile_name(uuid, profile_key)
+                            .await
+                            .unwrap_or_else(|| uuid.to_string())
+                    }
+                    None => {
+                        // cannot be resolved
+                        uuid.to_string()
+                    }
+                }
+            };
+            if name != uuid.to_string() && self.names.get_mut().set_display_name(uuid, name.clone())
+            {
+                self.message_layouts.get_mut().clear();
+            }
+            self.storage.store_name(uuid, name);
+        }
+    }
+
+    async fn ensure_users_are_known(
+        &mut self,
+        users_with_keys: impl Iterator<Item = (Uuid, ProfileKeyBytes)>,
+    ) {
+        // TODO: Run in parallel
+        for (uuid, profile_key) in users_with_keys {
+            self.ensure_user_is_known(uuid, Some(profile_key)).await;
+        }
+    }
+
+    fn ensure_own_channel_exists(&mut self) -> usize {
+        let user_id = self.user_id;
+        if let Some(channel_idx) = self.channel_idx(user_id.into()) {
+            channel_idx
+        } else {
+            let channel = Channel {
+                id: user_id.into(),
+                name: self.config.user.name.clone(),
+                group_data: None,
+                unread_messages: 0,
+                typing: TypingSet::SingleTyping(false),
+                muted_until: None,
+                pinned: false,
+                archived: false,
+                burn_after_reading: false,
+                max_messages: None,
+                ignore_mention_all: false,
+                notification_content: None,
+                nickname: None,
+            };
+            let channel = self.storage.store_channel(channel);
+
+            let channel_idx = self.channels.items.len();
+            self.channels.items.push(channel.id);
+
+            channel_idx
+        }
+    }
+
+    /// Index of the channel with the user, created if it does not exist yet
+    ///
+    /// `name` is kept as the name of the channel, so it is the resolved name of the user and not
+    /// the nickname, see [`App::resolved_name_by_id`].
+    pub(crate) async fn ensure_contact_channel_exists(&mut self, uuid: Uuid, name: &str) -> usize {
+        if let Some(channel_idx) = self.channel_idx(uuid.into()) {
+            let channel = self
+                .storage
+                .channel(uuid.into())
+                .expect("non-existent channel");
+            if channel.name != name {
+                let mut channel = channel.into_owned();
+                channel.name = name.to_string();
+                self.storage.store_channel(channel);
+            }
+            channel_idx
+        } else {
+            let channel = Channel {
+                id: uuid.into(),
+                name: name.to_string(),
+                group_data: None,
+                unread_messages: 0,
+                typing: TypingSet::SingleTyping(false),
+                muted_until: None,
+                pinned: false,
+                archived: false,
+                burn_after_reading: false,
+                max_messages: None,
+                ignore_mention_all: false,
+                notification_content: None,
+                nickname: None,
+            };
+            let channel = self.storage.store_channel(channel);
+
+            let channel_idx = self.channels.items.len();
+            self.channels.items.push(channel.id);
+
+            channel_idx
+        }
+    }
+
+    pub(crate) fn add_message_to_channel(&mut self, channel_idx: usize, message: Message) {
+        let channel_id = self.channels.items[channel_idx];
+
+        let Some(message) = self.storage.store_new_message(channel_id, message) else {
+            debug!(?channel_id, "dropping duplicate message");
+            return;
+        };
+
+        let is_selected = self.channels.state.selected() == Some(channel_idx);
+        if is_selected
+            && message.from_id != self.user_id
+            && self.config.highlight_new_messages_ms > 0
+        {
+            let duration = Duration::from_millis(self.config.highlight_new_messages_ms);
+            self.highlighted_messages
+                .retain(|_, arrived_at| arrived_at.elapsed() < duration);
+            self.highlighted_messages.insert(
+                MessageId::new(channel_id, message.arrived_at),
+                Instant::now(),
+            );
+        }
+
+        let messages = self.messages.entry(channel_id).or_default();
+        messages.items.push(message.arrived_at);
+
+        if let Some(idx) = messages.state.selected() {
+            // keep selection on the old message
+            messages.state.select(Some(idx + 1));
+        } else if messages.rendered.offset > 0 {
+            // keep the viewport on the old messages, unless it is at the bottom
+            messages.rendered.offset += 1;
+        }
+
+        self.touch_channel(channel_idx);
+
+        let max_messages = self
+            .storage
+            .channel(channel_id)
+            .and_then(|channel| channel.max_messages);
+        if let Some(max_messages) = max_messages {
+            self.trim_messages(channel_id, max_messages);
+        }
+    }
+
+    pub(crate) fn touch_channel(&mut self, channel_idx: usize) {
+        if self.channels.state.selected() != Some(channel_idx) || !self.is_reading() {
+            let channel_id = self.channels.items[channel_idx];
+            let mut channel = self
+                .storage
+                .channel(channel_id)
+                .expect("non-existent channel")
+                .into_owned();
+            channel.unread_messages += 1;
+            self.storage.store_channel(channel);
+        } else {
+            self.reset_unread_messages();
+        }
+
+        // with a sort interval, the order only changes when sorting, unless a new channel has to
+        // be moved into its section
+        if self.config.channel_sort_interval_secs.is_none() || self.is_out_of_section(channel_idx) {
+            let section = self.channel_section(self.channels.items[channel_idx]);
+            if self.channel_order(section) == ChannelOrder::Recent {
+                self.bubble_up_channel(channel_idx);
+            } else {
+                self.sort_channels();
+            }
+        }
+    }
+
+    /// Position of the channel in the channel list
+    ///
+    /// Looked up in the index, which is rebuilt if it is outdated, e.g. after adding a channel.
+    pub(crate) fn channel_idx(&self, channel_id: ChannelId) -> Option<usize> {
+        let idx = self.channel_index.borrow().get(&channel_id).copied();
+        match idx {
+            Some(idx) if self.channels.items.get(idx) == Some(&channel_id) => Some(idx),
+            _ => {
+                self.rebuild_channel_index();
+                self.channel_index.borrow().get(&channel_id).copied()
+            }
+        }
+    }
 
-                self.handle_reaction(
-                    channel_id,
-                    target_sent_timestamp,
-                    sender.raw_uuid(),
-                    emoji,
-                    HandleReactionOptions::new()
-                        .remove(remove.unwrap_or(false))
-                        .notify(true)
-                        .bell(true),
-                )
-                .await;
-                return Ok(());
+    fn rebuild_channel_index(&self) {
+        let index = self
+            .channels
+            .items
+            .iter()
+            .enumerate()
+            .map(|(idx, &channel_id)| (channel_id, idx))
+            .collect();
+        self.channel_index.replace(index);
+    }
+
+    /// Moves the channel to the beginning of its section in the list
+    ///
+    /// A pinned channel is moved to the very beginning of the list, an unpinned one right below
+    /// the pinned channels, and an archived one right below all other channels.
+    fn bubble_up_channel(&mut self, channel_idx: usize) {
+        let channel_id = self.channels.items[channel_idx];
+        let section = self.channel_section(channel_id);
+        let target_idx = self
+            .channels
+            .items
+            .iter()
+            .filter(|&&id| id != channel_id && self.channel_section(id) < section)
+            .count();
+
+        let channels = &mut self.channels;
+        let channel_id = channels.items.remove(channel_idx);
+        channels.items.insert(target_idx, channel_id);
+        // only the channels between the old and the new position moved
+        let mut channel_index = self.channel_index.borrow_mut();
+        for idx in target_idx.min(channel_idx)..=target_idx.max(channel_idx) {
+            channel_index.insert(channels.items[idx], idx);
+        }
+        match channels.state.selected() {
+            Some(selected_idx) if selected_idx == channel_idx => {
+                channels.state.select(Some(target_idx));
             }
-            // Direct/group message by us from a different device
-            (
-                Metadata { sender, .. },
-                ContentBody::SynchronizeMessage(SyncMessage {
-                    sent:
-                        Some(Sent {
-                            destination_service_id: destination_uuid,
-                            timestamp: Some(timestamp),
-                            message:
-                                Some(DataMessage {
-                                    mut body,
-                                    profile_key,
-                                    group_v2,
-                                    quote,
-                                    attachments: attachment_pointers,
-                                    sticker,
-                                    body_ranges,
-                                    ..
-                                }),
-                            ..
-                        }),
-                    ..
-                }),
-            ) if sender.raw_uuid() == user_id => {
-                let channel_idx = if let Some(GroupContextV2 {
-                    master_key: Some(master_key),
-                    revision: Some(revision),
-                    ..
-                }) = group_v2
-                {
-                    // message to a group
-                    let master_key = master_key
-                        .try_into()
-                        .map_err(|_| anyhow!("invalid master key"))?;
-                    self.ensure_group_channel_exists(master_key, revision)
-                        .await
-                        .context("failed to create group channel")?
-                } else if let Some(destination_uuid) = destination_uuid {
-                    let profile_key = profile_key
-                        .context("sync message with destination without profile key")?
-                        .try_into()
-                        .map_err(|_| anyhow!("invalid profile key"))?;
-                    let destination_uuid = destination_uuid.parse()?;
-                    let name = self.name_by_id(destination_uuid).await;
-                    self.ensure_user_is_known(destination_uuid, Some(profile_key))
-                        .await;
-                    self.ensure_contact_channel_exists(destination_uuid, &name)
-                        .await
-                } else {
-                    debug!("dropping a sync message not attached to a channel");
-                    return Ok(());
-                };
+            Some(selected_idx) if (target_idx..channel_idx).contains(&selected_idx) => {
+                channels.state.select(Some(selected_idx + 1));
+            }
+            Some(selected_idx) if (channel_idx + 1..=target_idx).contains(&selected_idx) => {
+                channels.state.select(Some(selected_idx - 1));
+            }
+            _ => {}
+        }
+    }
 
-                add_emoji_from_sticker(&mut body, sticker);
-                let quote = quote.and_then(Message::from_quote).map(Box::new);
-                let attachments = self.save_attachments(attachment_pointers).await;
-                let body_ranges = body_ranges.into_iter().filter_map(BodyRange::from_proto);
+    fn notify(&self, summary: &str, text: &str) {
+        self.show_notification(summary, text, None);
+    }
 
-                let message = Message {
-                    quote,
-                    ..Message::new(user_id, body, body_ranges, timestamp, attachments)
-                };
+    /// Shows the notification about a message in the channel, with actions for the channel
+    fn notify_about(&self, channel_id: ChannelId, summary: &str, text: &str) {
+        self.show_notification(summary, text, Some(channel_id));
+    }
 
-                if message.is_empty() {
-                    debug!("dropping empty message");
-                    return Ok(());
+    fn show_notification(&self, summary: &str, text: &str, channel_id: Option<ChannelId>) {
+        if !self.config.notifications {
+            return;
+        }
+        let config = &self.config.notification;
+        let mut notification = Notification::new();
+        notification.summary(summary).body(text);
+        #[cfg(all(unix, not(target_os = "macos")))]
+        notification.urgency(match config.urgency {
+            crate::config::NotificationUrgency::Low => notify_rust::Urgency::Low,
+            crate::config::NotificationUrgency::Normal => notify_rust::Urgency::Normal,
+            crate::config::NotificationUrgency::Critical => notify_rust::Urgency::Critical,
+        });
+        let channel_id = channel_id.filter(|_| config.actions);
+        if channel_id.is_some() {
+            notification
+                .action(OPEN_ACTION, "Open")
+                .action(MARK_READ_ACTION, "Mark read");
+            // inline replies are only reported via the signals of the notification daemon
+            #[cfg(all(unix, not(target_os = "macos")))]
+            if self.receives_notification_signals {
+                notification.action(REPLY_ACTION, "Reply");
+            }
+        }
+        match notification.show() {
+            Ok(handle) => {
+                if let Some(channel_id) = channel_id {
+                    self.track_notification_action(handle, channel_id);
                 }
-
-                (channel_idx, message)
             }
-            // Incoming direct/group message
-            (
-                Metadata { sender, .. },
-                ContentBody::DataMessage(DataMessage {
-                    mut body,
-                    group_v2,
-                    timestamp: Some(timestamp),
-                    profile_key,
-                    quote,
-                    attachments: attachment_pointers,
-                    sticker,
-                    body_ranges,
-                    ..
-                }),
-            ) => {
-                let (channel_idx, from) = if let Some(GroupContextV2 {
-                    master_key: Some(master_key),
-                    revision: Some(revision),
-                    ..
-                }) = group_v2
-                {
-                    // incoming group message
-                    // profile_key can be None and is not required for known contacts
-                    let profile_key = match profile_key {
-                        Some(profile_key) => Some(
-                            profile_key
-                                .try_into()
-                                .map_err(|_| anyhow!("invalid profile key"))?,
-                        ),
-                        None => None,
-                    };
-                    let master_key = master_key
-                        .try_into()
-                        .map_err(|_| anyhow!("invalid group master key"))?;
-                    let channel_idx = self
-                        .ensure_group_channel_exists(master_key, revision)
-                        .await
-                        .context("failed to create group channel")?;
+            Err(e) => error!("failed to send notification: {}", e),
+        }
+        if let Some(command) = config.sound_command.clone() {
+            // don't block the UI on playing the sound
+            std::thread::spawn(move || {
+                if let Err(error) = util::shell_command(&command).status() {
+                    warn!(%error, "failed to run the notification sound command");
+                }
+            });
+        }
+    }
 
-                    self.ensure_user_is_known(sender.raw_uuid(), profile_key)
-                        .await;
-                    let from = self.name_by_id(sender.raw_uuid()).await;
+    /// Remembers the channel of the notification, so that its actions received as signals of the
+    /// notification daemon are handled
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn track_notification_action(
+        &self,
+        handle: notify_rust::NotificationHandle,
+        channel_id: ChannelId,
+    ) {
+        if self.receives_notification_signals {
+            self.track_notification(handle.id(), channel_id);
+        }
+    }
 
-                    (channel_idx, from)
-                } else {
-                    // incoming direct message
-                    let profile_key = profile_key
-                        .context("sync message with destination without profile key")?
-                        .try_into()
-                        .map_err(|_| anyhow!("invalid profile key"))?;
-                    self.ensure_user_is_known(sender.raw_uuid(), Some(profile_key))
-                        .await;
-                    let name = self.name_by_id(sender.raw_uuid()).await;
-                    let channel_idx = self
-                        .ensure_contact_channel_exists(sender.raw_uuid(), &name)
-                        .await;
-                    // Reset typing notification as the Tipyng::Stop are not always sent by the server when a message is sent.
-                    let channel_id = self.channels.items[channel_idx];
-                    let mut channel = self
-                        .storage
-                        .channel(channel_id)
-                        .expect("non-existent channel")
-                        .into_owned();
-                    let from = channel.name.clone();
-                    if channel.reset_writing(sender.raw_uuid()) {
-                        self.storage.store_channel(channel);
+    /// Actions are only supported by the notification daemons of Linux and BSD
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    fn track_notification_action<H>(&self, _handle: H, _channel_id: ChannelId) {}
+
+    /// Remembers the channel of the notification, forgetting the oldest notifications
+    fn track_notification(&self, notification_id: u32, channel_id: ChannelId) {
+        let mut channels = self.notification_channels.borrow_mut();
+        channels.insert(notification_id, channel_id);
+        while channels.len() > MAX_TRACKED_NOTIFICATIONS {
+            channels.pop_first();
+        }
+    }
+
+    fn bell(&self) {
+        if self.config.bell && self.presence != Presence::DoNotDisturb {
+            print!("\x07");
+        }
+    }
+
+    fn extract_attachments(&mut self, input: &str) -> (String, Vec<(AttachmentSpec, Vec<u8>)>) {
+        let mut offset = 0;
+        let mut clean_input = String::new();
+        let mut sent_paths = Vec::new();
+
+        let re = self.attachment_regex.compiled();
+        let attachments = re.find_iter(input).filter_map(|m| {
+            let path_str = m.as_str().strip_prefix("file://")?;
+
+            let (contents, content_type, file_name) = if path_str.starts_with("clip") {
+                let img = self.clipboard.as_mut()?.get_image().ok()?;
+
+                let png: ImageBuffer<Rgba<_>, _> =
+                    ImageBuffer::from_raw(img.width as _, img.height as _, img.bytes)?;
+
+                let mut bytes = Vec::new();
+                let mut cursor = std::io::Cursor::new(&mut bytes);
+                let encoder = PngEncoder::new(&mut cursor);
+
+                let data: Vec<_> = png.into_raw().iter().map(|b| b.swap_bytes()).collect();
+                encoder
+                    .write_image(
+                        &data,
+                        img.width as _,
+                        img.height as _,
+                        image::ExtendedColorType::Rgba8,
+                    )
+                    .ok()?;
+
+                (
+                    bytes,
+                    "image/png".to_string(),
+                    Some("clipboard.png".to_string()),
+                )
+            } else {
+                let path = Path::new(path_str);
+                let mut contents = std::fs::read(path).ok()?;
+                let content_type = mime_guess::from_path(path)
+                    .first()
+                    .map(|mime| mime.essence_str().to_string())
+                    .unwrap_or_default();
+                if let Some(max_size) = self.config.media.image_max_size {
+                    if content_type.starts_with("image/") {
+                        contents = scale_down_image(contents, max_size);
                     }
-                    (channel_idx, from)
-                };
+                }
+                let file_name = path.file_name().map(|f| f.to_string_lossy().into());
+                sent_paths.push(std::fs::canonicalize(path).unwrap_or_else(|_| path.into()));
 
-                add_emoji_from_sticker(&mut body, sticker);
+                (contents, content_type, file_name)
+            };
 
-                let attachments = self.save_attachments(attachment_pointers).await;
-                self.notify_about_message(&from, body.as_deref(), &attachments);
+            clean_input.push_str(input[offset..m.start()].trim_end());
+            offset = m.end();
 
-                // Send "Delivered" receipt
-                self.add_receipt_event(ReceiptEvent::new(
-                    sender.raw_uuid(),
-                    timestamp,
-                    Receipt::Delivered,
-                ));
+            let spec = AttachmentSpec {
+                content_type,
+                length: contents.len(),
+                file_name,
+                preview: None,
+                voice_note: None,
+                borderless: None,
+                width: None,
+                height: None,
+                caption: None,
+                blur_hash: None,
+            };
+            Some((spec, contents))
+        });
 
-                let quote = quote.and_then(Message::from_quote).map(Box::new);
-                let body_ranges = body_ranges.into_iter().filter_map(BodyRange::from_proto);
-                let message = Message {
-                    quote,
-                    ..Message::new(sender.raw_uuid(), body, body_ranges, timestamp, attachments)
-                };
+        let attachments = attachments.collect();
+        clean_input.push_str(&input[offset..]);
+        let clean_input = clean_input.trim().to_string();
 
-                if message.is_empty() {
-                    return Ok(());
-                }
+        self.add_recent_files(sent_paths.into_iter().map(|path| RecentFile {
+            path,
+            kind: RecentFileKind::Sent,
+        }));
 
-                (channel_idx, message)
-            }
-            (metadata, ContentBody::SynchronizeMessage(sync_message)) => {
-                return self.handle_sync_message(metadata, sync_message);
-            }
-            (
-                Metadata { sender, .. },
-                ContentBody::ReceiptMessage(ReceiptMessage {
-                    r#type: Some(receipt_type),
-                    timestamp: timestamps,
-                }),
-            ) => {
-                let receipt = Receipt::from_i32(receipt_type);
-                self.handle_receipt(sender.raw_uuid(), receipt, timestamps);
-                return Ok(());
-            }
+        (clean_input, attachments)
+    }
 
-            (
-                Metadata { sender, .. },
-                ContentBody::TypingMessage(TypingMessage {
-                    timestamp: Some(timest),
-                    group_id,
-                    action: Some(act),
-                }),
-            ) => {
-                let group_id_bytes = match group_id.map(TryInto::try_into).transpose() {
-                    Ok(bytes) => bytes,
-                    Err(_) => {
-                        error!("invalid group id: failed to convert to group identified bytes");
-                        return Ok(());
+    async fn save_attachments(
+        &mut self,
+        attachment_pointers: Vec<AttachmentPointer>,
+    ) -> Vec<Attachment> {
+        let mut attachments = vec![];
+        let mut downloaded = vec![];
+        for attachment_pointer in attachment_pointers {
+            if !self.should_auto_download(&attachment_pointer) {
+                match self.signal_manager.locate_attachment(&attachment_pointer) {
+                    Ok(attachment) => {
+                        debug!(
+                            size = attachment.size,
+                            "not downloading attachment right away"
+                        );
+                        self.attachment_cache.insert_pending(
+                            attachment.filename.clone(),
+                            attachment.size.into(),
+                            &attachment_pointer,
+                        );
+                        attachments.push(attachment);
                     }
-                };
-                if self
-                    .handle_typing(
-                        sender.raw_uuid(),
-                        group_id_bytes,
-                        TypingAction::from_i32(act),
-                        timest,
-                    )
-                    .is_err()
-                {
-                    error!("failed to handle typing: unknown error");
+                    Err(e) => warn!("failed to locate attachment: {}", e),
                 }
-                return Ok(());
+                continue;
             }
-
-            unhandled => {
-                info!(?unhandled, "skipping unhandled message");
-                return Ok(());
+            match self
+                .signal_manager
+                .save_attachment(attachment_pointer.clone())
+                .await
+            {
+                Ok(attachment) => {
+                    self.attachment_cache.insert(
+                        attachment.filename.clone(),
+                        attachment.size.into(),
+                        &attachment_pointer,
+                    );
+                    downloaded.push(attachment.filename.clone());
+                    attachments.push(attachment);
+                }
+                Err(e) => warn!("failed to save attachment: {}", e),
             }
-        };
+        }
+        self.shrink_attachment_cache();
+        self.add_recent_files(downloaded.into_iter().map(|path| RecentFile {
+            path,
+            kind: RecentFileKind::Downloaded,
+        }));
+        attachments
+    }
 
-        self.add_message_to_channel(channel_idx, message);
+    /// Whether the received attachment is downloaded right away, see [`MediaConfig`]
+    ///
+    /// [`MediaConfig`]: crate::config::MediaConfig
+    fn should_auto_download(&self, pointer: &AttachmentPointer) -> bool {
+        let media = &self.config.media;
+        let max_bytes = media
+            .auto_download_max_mb
+            .map(|max_mb| max_mb.saturating_mul(1024 * 1024));
+        media.auto_download
+            && (media.auto_download_when_metered || !self.is_metered)
+            && max_bytes.is_none_or(|max_bytes| u64::from(pointer.size()) <= max_bytes)
+    }
+
+    /// Downloads the attachments of the selected message which were not downloaded yet
+    fn download_selected_attachments(&mut self) {
+        let Some(paths) = self.selected_message().map(|message| {
+            message
+                .attachments
+                .iter()
+                .map(|attachment| attachment.filename.clone())
+                .filter(|path| !path.exists())
+                .collect::<Vec<_>>()
+        }) else {
+            return;
+        };
+        if paths.is_empty() {
+            self.command_error = Some("no attachments to download".to_string());
+            return;
+        }
+        for path in paths {
+            if let Err(error) = self.download_attachment(&path) {
+                let message = format!("{}: {error}", path.display());
+                self.report_error(ErrorKind::Receive, message);
+            }
+        }
+    }
 
+    /// Downloads the deleted attachment again in the background, unless it is downloading already
+    ///
+    /// The result is handled as [`Event::AttachmentDownloaded`].
+    fn download_attachment(&mut self, path: &Path) -> anyhow::Result<()> {
+        let kind = EffectKind::Download(path.to_path_buf());
+        if self.effects.find(&kind).is_some() {
+            return Ok(());
+        }
+        let pointer = self
+            .attachment_cache
+            .pointer(path)
+            .context("cannot be downloaded again")?;
+        let mut signal_manager = self.signal_manager.clone_boxed();
+        let tx = self.event_tx.clone();
+        let path = path.to_path_buf();
+        self.effects.start(kind, |_| {
+            let task = tokio::task::spawn_local(async move {
+                let result = signal_manager.save_attachment(pointer).await;
+                let _ = tx.send(Event::AttachmentDownloaded { path, result });
+            });
+            task.abort_handle()
+        });
         Ok(())
     }
 
-    fn notify_about_message(&mut self, from: &str, body: Option<&str>, attachments: &[Attachment]) {
-        let attachments_text = notification_text_for_attachments(attachments);
-        let notification = [body, attachments_text.as_deref()]
+    /// Opens the attachment with the default application
+    fn open_attachment(&mut self, path: &Path) {
+        self.attachment_cache.touch(path);
+        if let Err(error) = opener::open(path) {
+            error!(path =% Sensitive(path.display()), %error, "failed to open");
+        }
+    }
+
+    /// Cancels sending the selected message, or downloading its attachments
+    ///
+    /// A cancelled message is kept as failed, so that it can be sent again with `retry_send`.
+    fn cancel_selected(&mut self) {
+        let Some(message_id) = self.selected_message_id() else {
+            return;
+        };
+        if self.effects.cancel(&EffectKind::Send(message_id)).is_some() {
+            info!(?message_id, "cancelled sending message");
+            if let Some(pending) = self.pending_sends.remove(&message_id) {
+                self.failed_sends.insert(message_id, pending);
+            }
+            if let Some(mut message) = self.storage.message(message_id).map(Cow::into_owned) {
+                message.send_failed = Some("cancelled".to_string());
+                self.storage.store_message(message_id.channel_id, message);
+            }
+            return;
+        }
+        let paths: Vec<_> = self
+            .selected_message()
+            .map(|message| {
+                message
+                    .attachments
+                    .iter()
+                    .map(|attachment| attachment.filename.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let cancelled = paths
             .into_iter()
-            .flatten()
-            .join(" ");
-        if !notification.is_empty() {
-            self.notify(from, &notification);
+            .filter(|path| {
+                self.effects
+                    .cancel(&EffectKind::Download(path.clone()))
+                    .is_some()
+            })
+            .count();
+        if cancelled == 0 {
+            self.command_error = Some("nothing to cancel for the selected message".to_string());
         }
-        self.bell();
     }
 
-    pub fn step_receipts(&mut self) {
-        self.receipt_handler.step(self.signal_manager.as_ref());
+    /// Deletes the least recently used attachments beyond the configured disk usage
+    pub fn shrink_attachment_cache(&mut self) {
+        if let Some(max_mb) = self.config.max_attachments_mb {
+            self.attachment_cache
+                .shrink_to(max_mb.saturating_mul(1024 * 1024));
+        }
     }
 
-    fn handle_typing(
-        &mut self,
-        sender_uuid: Uuid,
-        group_id: Option<GroupIdentifierBytes>,
-        action: TypingAction,
-        _timestamp: u64,
-    ) -> Result<(), ()> {
-        if let Some(gid) = group_id {
-            let mut channel = self
-                .storage
-                .channel(ChannelId::Group(gid))
-                .ok_or(())?
-                .into_owned();
-            if let TypingSet::GroupTyping(ref mut hash_set) = channel.typing {
-                match action {
-                    TypingAction::Started => {
-                        hash_set.insert(sender_uuid);
+    /// Forgets the attachments which can no longer be downloaded
+    pub(crate) fn forget_expired_attachments(&mut self) {
+        self.attachment_cache
+            .forget_expired(util::utc_now_timestamp_msec());
+    }
+
+    fn add_recent_files(&mut self, files: impl IntoIterator<Item = RecentFile>) {
+        let mut files = files.into_iter().peekable();
+        if files.peek().is_none() {
+            return;
+        }
+        let mut metadata = self.storage.metadata().into_owned();
+        for file in files {
+            metadata.add_recent_file(file);
+        }
+        self.storage.store_metadata(metadata);
+    }
+
+    pub fn toggle_help(&mut self) {
+        self.display_help = !self.display_help;
+    }
+
+    pub fn is_help(&self) -> bool {
+        self.display_help
+    }
+
+    pub fn request_contacts_sync(
+        &self,
+    ) -> Option<impl Future<Output = anyhow::Result<DateTime<Utc>>> + 'static> {
+        let now = Utc::now();
+        let metadata = self.storage.metadata();
+        let do_sync = metadata
+            .contacts_sync_request_at
+            .map(|dt| dt + chrono::Duration::seconds(CONTACTS_SYNC_DEADLINE_SEC) < now)
+            .unwrap_or(true);
+        let signal_manager = self.signal_manager.clone_boxed();
+        do_sync.then_some(async move {
+            info!(timeout =? CONTACTS_SYNC_TIMEOUT, "requesting contact sync");
+            tokio::time::timeout(
+                CONTACTS_SYNC_TIMEOUT,
+                signal_manager.request_contacts_sync(),
+            )
+            .await??;
+            Ok(Utc::now())
+        })
+    }
+
+    pub fn is_select_channel_shown(&self) -> bool {
+        self.select_channel.is_shown
+    }
+
+    pub fn select_channel_prev(&mut self) {
+        self.select_channel.prev();
+    }
+
+    pub fn select_channel_next(&mut self) {
+        self.select_channel.next();
+    }
+
+    pub fn copy_selection(&mut self) {
+        if let Some(message) = self.selected_message() {
+            if let Some(text) = message.message.as_ref() {
+                let text = text.clone();
+                if let Some(clipboard) = self.clipboard.as_mut() {
+                    if let Err(error) = clipboard.set_text(text) {
+                        error!(%error, "failed to copy text to clipboard");
+                    } else {
+                        info!("copied selected text to clipboard");
                     }
-                    TypingAction::Stopped => {
-                        hash_set.remove(&sender_uuid);
+                }
+            }
+        }
+    }
+
+    pub fn handle_event(&mut self, event: Event) -> anyhow::Result<()> {
+        match event {
+            Event::SentTextResult { message_id, result } => {
+                self.effects.finish(&EffectKind::Send(message_id));
+                let pending = self.pending_sends.remove(&message_id);
+                self.update_send_status(message_id.channel_id, &result);
+                if result.is_ok() {
+                    self.rate_limited.sent();
+                }
+                if let Err(error) = result {
+                    self.metrics.send_failures += 1;
+                    if let Some(pending) = pending {
+                        if let Some(captcha) = error.downcast_ref::<CaptchaRequired>() {
+                            info!(?message_id, "sending requires a captcha");
+                            self.captcha_prompt.open(
+                                captcha.token.clone(),
+                                message_id.arrived_at,
+                                pending,
+                            );
+                        } else if error.is::<RateLimited>() {
+                            info!(?message_id, "sending was rate limited");
+                            self.rate_limited.push(message_id, pending, Instant::now());
+                        } else {
+                            self.failed_sends.insert(message_id, pending);
+                        }
                     }
+                    self.report_error(ErrorKind::Send, format!("failed to send message: {error}"));
+                    let mut message = self
+                        .storage
+                        .message(message_id)
+                        .context("no message")?
+                        .into_owned();
+                    message.send_failed = Some(error.to_string());
+                    self.storage.store_message(message_id.channel_id, message);
                 }
-                self.storage.store_channel(channel);
-            } else {
-                error!("Got a single typing instead of hash set on a group");
             }
-        } else {
-            let mut channel = self
-                .storage
-                .channel(ChannelId::User(sender_uuid))
-                .ok_or(())?
-                .into_owned();
-            if let TypingSet::SingleTyping(_) = channel.typing {
-                match action {
-                    TypingAction::Started => {
-                        channel.typing = TypingSet::SingleTyping(true);
+            Event::NotificationAction { channel_id, action } => {
+                self.on_notification_action(channel_id, action)
+            }
+            Event::HookActions {
+                channel_id,
+                actions,
+            } => self.on_hook_actions(channel_id, actions),
+            Event::RpcRequest { request, reply } => self.on_rpc_request(request, reply),
+            Event::DemoMessage {
+                channel_id,
+                from,
+                text,
+            } => self.on_demo_message(channel_id, from, text),
+            Event::NotificationSignal {
+                notification_id,
+                signal,
+            } => self.on_notification_signal(notification_id, signal),
+            Event::SendMessage { channel_id, text } => {
+                if self.storage.channel(channel_id).is_some() {
+                    self.send_or_queue(channel_id, text, None, None);
+                } else {
+                    warn!(?channel_id, "not sending to an unknown channel");
+                }
+            }
+            Event::SetPresence(presence) => self.presence = presence,
+            Event::Metered(is_metered) => {
+                info!(is_metered, "network connection changed");
+                self.is_metered = is_metered;
+            }
+            Event::JobDue(job) => self.scheduler.set_due(job),
+            Event::LoadMessages => {
+                self.load_first_pages(LOAD_CHANNELS_BATCH);
+                self.load_messages_in_background();
+            }
+            Event::EffectProgress { id, progress } => self.effects.set_progress(id, progress),
+            Event::AttachmentDownloaded { path, result } => {
+                self.effects.finish(&EffectKind::Download(path.clone()));
+                let result = result.and_then(|attachment| {
+                    Ok(self.attachment_cache.restore(&path, &attachment.filename)?)
+                });
+                let is_opening = self.attachment_gallery.opening.as_ref() == Some(&path);
+                if is_opening {
+                    self.attachment_gallery.opening = None;
+                }
+                match result {
+                    Ok(()) => {
+                        self.attachment_gallery.restore(&path);
+                        if is_opening {
+                            self.attachment_gallery.status = None;
+                            self.open_attachment(&path);
+                        }
+                        self.shrink_attachment_cache();
                     }
-                    TypingAction::Stopped => {
-                        channel.typing = TypingSet::SingleTyping(false);
+                    Err(error) => {
+                        if is_opening {
+                            self.attachment_gallery.status =
+                                Some(format!("failed to download: {error}"));
+                        }
+                        let message = format!("failed to download {}: {error}", path.display());
+                        self.report_error(ErrorKind::Receive, message);
                     }
                 }
-                self.storage.store_channel(channel);
-            } else {
-                error!("Got a hash set instead of single typing on a direct chat");
             }
         }
         Ok(())
     }
 
-    pub fn add_receipt_event(&mut self, event: ReceiptEvent) {
-        self.receipt_handler.add_receipt_event(event);
+    fn on_notification_action(&mut self, channel_id: ChannelId, action: NotificationAction) {
+        match action {
+            NotificationAction::Open => {
+                if let Some(channel_idx) = self.channel_idx(channel_id) {
+                    self.switch_to_channel(channel_idx);
+                }
+            }
+            NotificationAction::MarkRead => self.mark_read(channel_id),
+            NotificationAction::Reply(text) => {
+                self.send_or_queue(channel_id, text, None, None);
+                self.mark_read(channel_id);
+            }
+        }
+    }
+
+    /// Handles the action of a notification shown by gurk, received as signal of the notification
+    /// daemon
+    fn on_notification_signal(&mut self, notification_id: u32, signal: NotificationSignal) {
+        let Some(channel_id) = self
+            .notification_channels
+            .borrow_mut()
+            .remove(&notification_id)
+        else {
+            return; // not ours
+        };
+        let action = match signal {
+            NotificationSignal::ActionInvoked(key) => match key.as_str() {
+                OPEN_ACTION => NotificationAction::Open,
+                MARK_READ_ACTION => NotificationAction::MarkRead,
+                // followed by `Replied`
+                REPLY_ACTION => {
+                    self.track_notification(notification_id, channel_id);
+                    return;
+                }
+                _ => return,
+            },
+            NotificationSignal::Replied(text) => NotificationAction::Reply(text),
+        };
+        self.on_notification_action(channel_id, action);
+    }
+
+    fn update_send_status(&mut self, channel_id: ChannelId, result: &anyhow::Result<()>) {
+        let name = match self.storage.channel(channel_id) {
+            Some(channel) => self.channel_name(&channel).into_owned(),
+            None => "unknown channel".to_string(),
+        };
+        let (text, is_error) = match result {
+            Ok(()) => (format!("Sent to {name} ✓"), false),
+            Err(error) => (format!("Failed to send to {name}: {error}"), true),
+        };
+        self.send_status = Some(SendStatus {
+            text,
+            is_error,
+            at: Instant::now(),
+        });
+    }
+
+    /// Result of the last sent message, unless it was shown long enough
+    pub(crate) fn send_status(&self) -> Option<&SendStatus> {
+        self.send_status
+            .as_ref()
+            .filter(|status| status.at.elapsed() < SEND_STATUS_DURATION)
+    }
+
+    pub(crate) fn is_editing(&self) -> bool {
+        self.editing.is_some()
+    }
+
+    /// Returns `true` if editing was reset, otherwise `false`
+    fn reset_editing(&mut self) -> bool {
+        let is_reset = self.editing.take().is_some();
+        if is_reset {
+            self.take_input();
+        }
+        is_reset
     }
 
-    fn handle_receipt(&mut self, sender_uuid: Uuid, receipt: Receipt, mut timestamps: Vec<u64>) {
-        let sender_channels: Vec<ChannelId> = self
-            .storage
-            .channels()
-            .filter(|channel| match channel.id {
-                ChannelId::User(uuid) => uuid == sender_uuid,
-                ChannelId::Group(_) => channel
-                    .group_data
-                    .as_ref()
-                    .map(|group_data| group_data.members.contains(&sender_uuid))
-                    .unwrap_or(false),
-            })
-            .map(|channel| channel.id)
-            .collect();
+    fn start_editing(&mut self) -> Option<()> {
+        if !self.input.is_empty() {
+            return None;
+        }
 
-        timestamps.sort_unstable_by_key(|&ts| Reverse(ts));
-        if timestamps.is_empty() {
-            return;
+        let message_id = self.selected_message_id()?;
+        let message = self.storage.message(message_id)?;
+
+        if message.from_id != self.user_id {
+            return None;
         }
 
-        let mut found_channel_id = None;
-        let mut messages_to_store = Vec::new();
+        let target_sent_timestamp = self
+            .storage
+            .edits(message_id)
+            .last()
+            .map(|last_edit| last_edit.arrived_at)
+            .unwrap_or(message.arrived_at);
+        let message_id = MessageId::new(message_id.channel_id, target_sent_timestamp);
+        let text = message.message.clone()?;
 
-        'outer: for channel_id in sender_channels {
-            let mut messages = self.storage.messages(channel_id).rev();
-            for &ts in &timestamps {
-                // Note: `&mut` is needed to advance the iterator `messages` with each `ts`.
-                // Since these are sorted in reverse order, we can continue advancing messages
-                // without consuming them.
-                if let Some(msg) = (&mut messages)
-                    .take_while(|msg| msg.arrived_at >= ts)
-                    .find(|msg| msg.arrived_at == ts)
-                {
-                    let mut msg = msg.into_owned();
-                    if msg.receipt < receipt {
-                        msg.receipt = msg.receipt.max(receipt);
-                        messages_to_store.push(msg);
-                    }
-                    found_channel_id = Some(channel_id);
-                }
-            }
+        self.editing.replace(message_id);
+        self.input.data = text;
+        self.input.on_end();
 
-            if found_channel_id.is_some() {
-                // if one ts was found, then all other ts have to be in the same channel
-                break 'outer;
-            }
-        }
+        Some(())
+    }
 
-        if let Some(channel_id) = found_channel_id {
-            for message in messages_to_store {
-                self.storage.store_message(channel_id, message);
+    pub fn event_to_command<'r>(&'r self, event: &KeyEvent) -> Option<&'r Command> {
+        let mut combiner = Combiner::default();
+        let keys_pressed = combiner.transform(*event)?;
+        let modes = if self.is_help() {
+            vec![WindowMode::Anywhere, WindowMode::Help]
+        } else if self.show_diagnostics {
+            vec![WindowMode::Anywhere, WindowMode::Diagnostics]
+        } else if self.error_log.is_shown {
+            vec![WindowMode::Anywhere, WindowMode::ErrorLog]
+        } else if self.linked_devices.is_some() {
+            vec![WindowMode::Anywhere, WindowMode::Devices]
+        } else if self.is_select_channel_shown() {
+            vec![WindowMode::Anywhere, WindowMode::ChannelModal]
+        } else if self.attachment_picker.is_shown {
+            vec![WindowMode::Anywhere, WindowMode::AttachmentPicker]
+        } else if self.recent_files.is_shown {
+            vec![WindowMode::Anywhere, WindowMode::RecentFiles]
+        } else if self.attachment_gallery.save_as.is_some() {
+            vec![WindowMode::Anywhere, WindowMode::SaveAttachment]
+        } else if self.attachment_gallery.is_shown {
+            vec![WindowMode::Anywhere, WindowMode::AttachmentGallery]
+        } else if self.contact_list.rename.is_some() {
+            vec![WindowMode::Anywhere, WindowMode::RenameContact]
+        } else if self.contact_list.is_shown {
+            vec![WindowMode::Anywhere, WindowMode::Contacts]
+        } else if self.reaction_picker.is_shown {
+            vec![WindowMode::Anywhere, WindowMode::ReactionPicker]
+        } else if self.captcha_prompt.is_shown {
+            vec![WindowMode::Anywhere, WindowMode::CaptchaPrompt]
+        } else if self.message_search.is_shown {
+            vec![WindowMode::Anywhere, WindowMode::MessageSearch]
+        } else if self.is_multiline_input {
+            vec![
+                WindowMode::Anywhere,
+                WindowMode::Multiline,
+                WindowMode::Normal,
+            ]
+        } else if self.is_command_input() {
+            vec![
+                WindowMode::Anywhere,
+                WindowMode::CommandInput,
+                WindowMode::Normal,
+            ]
+        } else if self.input.is_empty() && self.selected_message_id().is_some() {
+            vec![
+                WindowMode::Anywhere,
+                WindowMode::MessageSelected,
+                WindowMode::Normal,
+            ]
+        } else {
+            vec![WindowMode::Anywhere, WindowMode::Normal]
+        };
+        for mode in modes {
+            if let Some(kb) = self.mode_keybindings.get(&mode) {
+                if let Some(cmd) = kb.get(&keys_pressed) {
+                    return Some(cmd);
+                }
             }
         }
+        let is_gallery_shown =
+            self.attachment_gallery.is_shown && self.attachment_gallery.save_as.is_none();
+        let is_contact_list_shown =
+            self.contact_list.is_shown && self.contact_list.rename.is_none();
+        if self.is_help()
+            || self.recent_files.is_shown
+            || is_gallery_shown
+            || is_contact_list_shown
+            || self.reaction_picker.is_shown
+        {
+            // Swallow event
+            Some(&Command::NoOp)
+        } else {
+            None
+        }
     }
+}
 
-    async fn handle_reaction(
-        &mut self,
-        channel_id: ChannelId,
-        target_sent_timestamp: u64,
-        sender_uuid: Uuid,
-        emoji: String,
-        HandleReactionOptions {
-            remove,
-            notify,
-            bell,
-        }: HandleReactionOptions,
-    ) -> Option<()> {
-        let mut message = self
-            .storage
-            .message(MessageId::new(channel_id, target_sent_timestamp))?
-            .into_owned();
+#[derive(Debug, Default)]
+struct HandleReactionOptions {
+    remove: bool,
+    notify: bool,
+    bell: bool,
+}
 
-        let reaction_idx = message
-            .reactions
-            .iter()
-            .position(|(from_id, _)| from_id == &sender_uuid);
-        let is_added = if let Some(idx) = reaction_idx {
-            if remove {
-                message.reactions.swap_remove(idx);
-                false
-            } else {
-                message.reactions[idx].1.clone_from(&emoji);
-                true
-            }
-        } else {
-            message.reactions.push((sender_uuid, emoji.clone()));
-            true
-        };
-        let message = self.storage.store_message(channel_id, message);
+impl HandleReactionOptions {
+    fn new() -> Self {
+        Default::default()
+    }
 
-        if is_added && channel_id != ChannelId::User(self.user_id) {
-            // Notification
-            let mut notification = format!("reacted {emoji}");
-            if let Some(text) = message.message.as_ref() {
-                notification.push_str(" to: ");
-                notification.push_str(text);
-            }
+    fn remove(self, remove: bool) -> Self {
+        Self { remove, ..self }
+    }
 
-            // makes borrow checker happy
-            let channel = self.storage.channel(channel_id)?;
-            let channel_name = channel.name.clone();
+    fn notify(self, notify: bool) -> Self {
+        Self { notify, ..self }
+    }
 
-            let sender_name = self.name_by_id(sender_uuid).await;
-            let summary = if let ChannelId::Group(_) = channel_id {
-                Cow::from(format!("{sender_name} in {channel_name}"))
-            } else {
-                Cow::from(sender_name)
-            };
+    fn bell(self, bell: bool) -> Self {
+        Self { bell, ..self }
+    }
+}
 
-            if notify {
-                self.notify(&summary, &format!("{summary} {notification}"));
-            }
+/// What to do after the app quit
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum AfterQuit {
+    #[default]
+    Exit,
+    /// Link this device again, keeping the messages
+    Relink,
+    /// Delete the Signal database with the credentials of the unlinked device
+    DeleteCredentials(PathBuf),
+}
 
-            if bell {
-                self.bell();
-            }
+/// Section of the channel list, in the order of the list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum ChannelSection {
+    Pinned,
+    /// All unpinned channels, unless the groups are listed in their own section
+    Direct,
+    Groups,
+    Archived,
+}
 
-            let channel_idx = self
-                .channels
-                .items
-                .iter()
-                .position(|id| id == &channel_id)
-                .expect("non-existent channel");
-            self.touch_channel(channel_idx);
+impl ChannelSection {
+    pub(crate) fn title(self) -> &'static str {
+        match self {
+            Self::Pinned => "Pinned",
+            Self::Direct => "Direct",
+            Self::Groups => "Groups",
+            Self::Archived => "Archived",
         }
+    }
+}
 
-        Some(())
+/// State of the connection used for receiving messages
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    #[default]
+    Connecting,
+    Connected,
+    /// The connection was lost, and is established again at the time
+    Reconnecting {
+        at: Instant,
+    },
+    Offline,
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connecting => write!(f, "connecting"),
+            Self::Connected => write!(f, "connected"),
+            Self::Reconnecting { at } => match at.checked_duration_since(Instant::now()) {
+                Some(after) if !after.is_zero() => {
+                    write!(f, "reconnecting in {}s", after.as_secs() + 1)
+                }
+                _ => write!(f, "reconnecting"),
+            },
+            Self::Offline => write!(f, "offline"),
+        }
     }
+}
 
-    async fn ensure_group_channel_exists(
-        &mut self,
-        master_key: GroupMasterKeyBytes,
-        revision: u32,
-    ) -> anyhow::Result<usize> {
-        let channel_id = ChannelId::from_master_key_bytes(master_key)?;
-        if let Some(channel_idx) = self.channels.items.iter().position(|id| id == &channel_id) {
-            // existing channel
-            let channel = self
-                .storage
-                .channel(channel_id)
-                .expect("non-existent channel");
+/// Message sent while offline, waiting for the connection to be established, or rejected until a
+/// captcha is solved
+#[derive(Debug)]
+pub(crate) struct QueuedMessage {
+    pub(crate) channel_id: ChannelId,
+    pub(crate) input: String,
+    pub(crate) quote: Option<MessageId>,
+    pub(crate) editing: Option<MessageId>,
+    /// Files attached to the input sent as is, instead of attaching the files linked in the input
+    ///
+    /// Set for messages not typed by our user, e.g. forwarded ones, so that their text is never
+    /// read as links to local files.
+    pub(crate) attachments: Option<Vec<Attachment>>,
+    /// Whether the message is forwarded from another channel
+    pub(crate) forwarded: bool,
+}
 
-            let is_stale = match channel.group_data.as_ref() {
-                Some(group_data) => group_data.revision != revision,
-                None => true,
-            };
-            if is_stale {
-                let ResolvedGroup {
-                    name,
-                    group_data,
-                    profile_keys,
-                } = self.signal_manager.resolve_group(master_key).await?;
+/// Message in the input box as it will be sent, shown before sending if enabled in the config
+#[derive(Debug, Clone)]
+pub(crate) struct SendPreview {
+    /// Input from which the preview was computed
+    input: String,
+    pub(crate) text: String,
+    pub(crate) attachments: Vec<String>,
+    pub(crate) quote: Option<Message>,
+    pub(crate) editing: bool,
+}
 
-                let mut channel = channel.into_owned();
+/// Result of sending a message, shown in the status bar
+#[derive(Debug, Clone)]
+pub(crate) struct SendStatus {
+    pub(crate) text: String,
+    pub(crate) is_error: bool,
+    at: Instant,
+}
 
-                self.ensure_users_are_known(
-                    group_data
-                        .members
-                        .iter()
-                        .copied()
-                        .zip(profile_keys.into_iter()),
-                )
-                .await;
+/// Number of entries to move by, given the number of entries fitting into the viewport
+fn visual_amount(amount: &MoveAmountVisual, page_len: usize) -> usize {
+    match amount {
+        MoveAmountVisual::Entry => 1,
+        MoveAmountVisual::HalfScreen => (page_len / 2).max(1),
+        MoveAmountVisual::Screen => page_len.max(1),
+        MoveAmountVisual::End => usize::MAX,
+    }
+}
 
-                channel.name = name;
-                channel.group_data = Some(group_data);
-                self.storage.store_channel(channel);
-            }
-            Ok(channel_idx)
-        } else {
-            // new channel
-            let ResolvedGroup {
-                name,
-                group_data,
-                profile_keys,
-            } = self.signal_manager.resolve_group(master_key).await?;
+/// Returns an emoji string if `s` is an emoji or if `s` is a GitHub emoji shortcode.
+pub fn to_emoji(s: &str) -> Option<&str> {
+    let s = s.trim();
+    if emojis::get(s).is_some() {
+        Some(s)
+    } else {
+        let s = s.strip_prefix(':')?.strip_suffix(':')?;
+        Some(emojis::get_by_shortcode(s)?.as_str())
+    }
+}
+
+fn open_url(message: &Message, url_regex: &Regex) -> Option<()> {
+    let text = message.message.as_ref()?;
+    let m = url_regex.find(text)?;
+    let url = m.as_str();
+    if let Err(error) = opener::open(url) {
+        error!(url = %Sensitive(url), %error, "failed to open");
+    }
+    Some(())
+}
 
-            self.ensure_users_are_known(
-                group_data
-                    .members
-                    .iter()
-                    .copied()
-                    .zip(profile_keys.into_iter()),
-            )
-            .await;
+/// Longest prefix of `first` which all the other candidates share, compared case-insensitively
+fn common_prefix<'a>(first: &'a str, rest: &[String]) -> &'a str {
+    let mut prefix = first;
+    for candidate in rest {
+        let len = prefix
+            .char_indices()
+            .zip(candidate.chars())
+            .take_while(|&((_, a), b)| a.to_lowercase().eq(b.to_lowercase()))
+            .last()
+            .map_or(0, |((idx, a), _)| idx + a.len_utf8());
+        prefix = &prefix[..len];
+    }
+    prefix
+}
 
-            let channel = Channel {
-                id: channel_id,
-                name,
-                group_data: Some(group_data),
-                unread_messages: 0,
-                typing: TypingSet::GroupTyping(Default::default()),
+/// Reads the files of the attachments for sending them again as they are
+///
+/// Files which cannot be read anymore are skipped.
+fn read_attachments(attachments: &[Attachment]) -> Vec<(AttachmentSpec, Vec<u8>)> {
+    attachments
+        .iter()
+        .filter_map(|attachment| {
+            let contents = std::fs::read(&attachment.filename)
+                .inspect_err(|error| {
+                    let path = attachment.filename.display();
+                    warn!(%error, path = %Sensitive(path), "failed to read attachment");
+                })
+                .ok()?;
+            let spec = AttachmentSpec {
+                content_type: attachment.content_type.clone(),
+                length: contents.len(),
+                file_name: attachment
+                    .filename
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into()),
+                preview: None,
+                voice_note: None,
+                borderless: None,
+                width: None,
+                height: None,
+                caption: None,
+                blur_hash: None,
             };
-            self.storage.store_channel(channel);
-
-            let channel_idx = self.channels.items.len();
-            self.channels.items.push(channel_id);
+            Some((spec, contents))
+        })
+        .collect()
+}
 
-            Ok(channel_idx)
+/// Scales the image down to fit into `max_size` pixels, keeping its format
+///
+/// The image is returned unchanged if it fits, or if its format cannot be decoded.
+fn scale_down_image(contents: Vec<u8>, max_size: u32) -> Vec<u8> {
+    let Ok(format) = image::guess_format(&contents) else {
+        return contents;
+    };
+    let image = match image::load_from_memory_with_format(&contents, format) {
+        Ok(image) if image.width() > max_size || image.height() > max_size => image,
+        _ => return contents,
+    };
+    let scaled = image.resize(max_size, max_size, image::imageops::FilterType::Triangle);
+    let mut bytes = Vec::new();
+    match scaled.write_to(&mut std::io::Cursor::new(&mut bytes), format) {
+        Ok(()) => {
+            debug!(from = contents.len(), to = bytes.len(), "scaled down image");
+            bytes
+        }
+        Err(error) => {
+            warn!(%error, "failed to scale down image");
+            contents
         }
     }
+}
 
-    async fn ensure_user_is_known(&mut self, uuid: Uuid, profile_key: Option<ProfileKeyBytes>) {
-        // is_known <=>
-        //   * in names, and
-        //   * is not empty
-        //   * is not a phone numbers, and
-        //   * is not their uuid
-        let is_known = self
-            .storage
-            .name(uuid)
-            .filter(|name| {
-                !name.is_empty()
-                    && !util::is_phone_number(name)
-                    && Uuid::parse_str(name) != Ok(uuid)
-            })
-            .is_some();
-        if !is_known {
-            let name = if let Some(name) = self.signal_manager.profile_name(uuid).await {
-                name
-            } else {
-                match profile_key {
-                    Some(profile_key) => {
-                        // try to resolve from signal service via their profile
-                        self.signal_manager
-                            .resolve_profile_name(uuid, profile_key)
-                            .await
-                            .unwrap_or_else(|| uuid.to_string())
-                    }
-                    None => {
-                        // cannot be resolved
-                        uuid.to_string()
-                    }
-                }
-            };
-            self.storage.store_name(uuid, name);
+/// Runs the focus-detection command of the config; gurk is focused when it succeeds
+///
+/// The command blocks the event loop, so it should return quickly.
+fn run_focus_command(command: &str) -> bool {
+    match util::shell_command(command).status() {
+        Ok(status) => status.success(),
+        Err(error) => {
+            warn!(%error, "failed to run the focus command");
+            false
         }
     }
+}
 
-    async fn ensure_users_are_known(
-        &mut self,
-        users_with_keys: impl Iterator<Item = (Uuid, ProfileKeyBytes)>,
+fn notification_text_for_attachments(attachments: &[Attachment]) -> Option<String> {
+    match attachments.len() {
+        0 => None,
+        1 => Some("<attachment>".into()),
+        n => Some(format!("<attachments ({n})>")),
+    }
+}
+
+fn add_emoji_from_sticker(body: &mut Option<String>, sticker: Option<Sticker>) {
+    if let Some(Sticker { emoji: Some(e), .. }) = sticker {
+        *body = Some(format!("<{e}>"));
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use crate::config::ThemePreset;
+    use crate::config::User;
+    use crate::data::{AssociatedValue, GroupData};
+    use crate::signal::test::SignalManagerMock;
+    use crate::signal::{GroupRequest, GroupRequests};
+    use crate::storage::{Birthday, BurnAfterReading, ForgetfulStorage, MemCache};
+
+    use crossterm::event::KeyModifiers;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    pub(crate) fn test_app() -> (
+        App,
+        mpsc::UnboundedReceiver<Event>,
+        Rc<RefCell<Vec<Message>>>,
     ) {
-        // TODO: Run in parallel
-        for (uuid, profile_key) in users_with_keys {
-            self.ensure_user_is_known(uuid, Some(profile_key)).await;
+        test_app_with(SignalManagerMock::new())
+    }
+
+    /// Channel without any settings, typing state matching the kind of the id
+    pub(crate) fn test_channel(id: ChannelId, name: &str) -> Channel {
+        let typing = match id {
+            ChannelId::User(_) => TypingSet::SingleTyping(false),
+            ChannelId::Group(_) => TypingSet::GroupTyping(Default::default()),
+        };
+        Channel {
+            id,
+            name: name.to_string(),
+            group_data: None,
+            unread_messages: 0,
+            typing,
+            muted_until: None,
+            pinned: false,
+            archived: false,
+            burn_after_reading: false,
+            max_messages: None,
+            ignore_mention_all: false,
+            notification_content: None,
+            nickname: None,
         }
     }
 
-    fn ensure_own_channel_exists(&mut self) -> usize {
-        let user_id = self.user_id;
-        if let Some(channel_idx) = self
-            .channels
-            .items
-            .iter()
-            .position(|channel_id| channel_id == &user_id)
-        {
-            channel_idx
-        } else {
-            let channel = Channel {
-                id: user_id.into(),
-                name: self.config.user.name.clone(),
-                group_data: None,
-                unread_messages: 0,
-                typing: TypingSet::SingleTyping(false),
-            };
-            let channel = self.storage.store_channel(channel);
+    pub(crate) fn test_app_with(
+        signal_manager: SignalManagerMock,
+    ) -> (
+        App,
+        mpsc::UnboundedReceiver<Event>,
+        Rc<RefCell<Vec<Message>>>,
+    ) {
+        let sent_messages = signal_manager.sent_messages.clone();
 
-            let channel_idx = self.channels.items.len();
-            self.channels.items.push(channel.id);
+        let mut storage = MemCache::new(ForgetfulStorage);
 
-            channel_idx
-        }
+        let channel_id = ChannelId::User(Uuid::new_v4());
+        let channel = Channel {
+            id: channel_id,
+            name: "test".to_string(),
+            group_data: Some(GroupData {
+                master_key_bytes: GroupMasterKeyBytes::default(),
+                members: vec![signal_manager.user_id()],
+                revision: 1,
+            }),
+            unread_messages: 1,
+            typing: TypingSet::GroupTyping(Default::default()),
+            muted_until: None,
+            pinned: false,
+            archived: false,
+            burn_after_reading: false,
+            max_messages: None,
+            ignore_mention_all: false,
+            notification_content: None,
+            nickname: None,
+        };
+        storage.store_channel(channel);
+        storage.store_message(
+            channel_id,
+            Message {
+                from_id: signal_manager.user_id(),
+                message: Some("First message".to_string()),
+                arrived_at: 0,
+                quote: Default::default(),
+                attachments: Default::default(),
+                reactions: Default::default(),
+                receipt: Default::default(),
+                body_ranges: Default::default(),
+                send_failed: Default::default(),
+                edit: Default::default(),
+                edited: Default::default(),
+                forwarded: Default::default(),
+            },
+        );
+
+        let user = User {
+            name: "Tyler Durden".to_string(),
+            phone_number: "+0000000000".to_string(),
+        };
+        let (mut app, events) = App::try_new(
+            Config::with_user(user),
+            Box::new(signal_manager),
+            Box::new(BurnAfterReading::new(storage)),
+        )
+        .unwrap();
+        app.channels.state.select(Some(0));
+
+        (app, events, sent_messages)
     }
 
-    pub(crate) async fn ensure_contact_channel_exists(&mut self, uuid: Uuid, name: &str) -> usize {
-        if let Some(channel_idx) = self
-            .channels
-            .items
-            .iter()
-            .position(|channel_id| channel_id == &uuid)
-        {
-            let channel = self
-                .storage
-                .channel(uuid.into())
-                .expect("non-existent channel");
-            if channel.name != name {
-                let mut channel = channel.into_owned();
-                channel.name = name.to_string();
-                self.storage.store_channel(channel);
-            }
-            channel_idx
-        } else {
-            let channel = Channel {
-                id: uuid.into(),
-                name: name.to_string(),
-                group_data: None,
-                unread_messages: 0,
-                typing: TypingSet::SingleTyping(false),
-            };
-            let channel = self.storage.store_channel(channel);
+    #[test]
+    fn test_load_older_messages_on_scroll() {
+        let (app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let mut storage = app.storage;
+        for arrived_at in 1..=4 {
+            let message = Message::new(app.user_id, None, [], arrived_at, Vec::new());
+            storage.store_message(channel_id, message);
+        }
+        let mut config = app.config;
+        config.history_page_size = 2;
+        let (mut app, _events) =
+            App::try_new(config, Box::new(SignalManagerMock::new()), storage).unwrap();
+        app.channels.state.select(Some(0));
+        app.load_first_pages(usize::MAX);
+        assert_eq!(app.messages[&channel_id].items, [3, 4]);
 
-            let channel_idx = self.channels.items.len();
-            self.channels.items.push(channel.id);
+        app.scroll_messages(DirectionVertical::Up, MoveAmountVisual::Entry);
+        assert_eq!(app.messages[&channel_id].items, [1, 2, 3, 4]);
+        assert!(app.partially_loaded.contains(&channel_id));
 
-            channel_idx
+        app.scroll_messages(DirectionVertical::Up, MoveAmountVisual::Entry);
+        assert_eq!(app.messages[&channel_id].items, [0, 1, 2, 3, 4]);
+        assert!(!app.partially_loaded.contains(&channel_id));
+    }
+
+    #[test]
+    fn test_load_messages_in_background() {
+        let (app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let mut storage = app.storage;
+        for arrived_at in 1..=4 {
+            let message = Message::new(app.user_id, None, [], arrived_at, Vec::new());
+            storage.store_message(channel_id, message);
         }
+        let mut config = app.config;
+        config.history_page_size = 3;
+        let (mut app, mut events) =
+            App::try_new(config, Box::new(SignalManagerMock::new()), storage).unwrap();
+        assert_eq!(app.messages[&channel_id].items, [4]);
+        assert!(app.is_loading_messages(channel_id));
+
+        // received while loading
+        app.add_message_to_channel(0, Message::new(app.user_id, None, [], 5, Vec::new()));
+        app.load_messages_in_background();
+        let event = events.try_recv().unwrap();
+        assert!(matches!(event, Event::LoadMessages));
+        app.handle_event(event).unwrap();
+        assert_eq!(app.messages[&channel_id].items, [3, 4, 5]);
+        assert!(!app.is_loading_messages(channel_id));
+        assert!(app.partially_loaded.contains(&channel_id));
+        assert!(events.try_recv().is_err());
     }
 
-    fn add_message_to_channel(&mut self, channel_idx: usize, message: Message) {
-        let channel_id = self.channels.items[channel_idx];
+    #[test]
+    fn test_show_message_loads_older_messages() {
+        let (app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let mut storage = app.storage;
+        for arrived_at in 1..=6 {
+            let message = Message::new(app.user_id, None, [], arrived_at, Vec::new());
+            storage.store_message(channel_id, message);
+        }
+        let mut config = app.config;
+        config.history_page_size = 2;
+        let (mut app, _events) =
+            App::try_new(config, Box::new(SignalManagerMock::new()), storage).unwrap();
+        app.load_first_pages(usize::MAX);
+        assert_eq!(app.messages[&channel_id].items, [5, 6]);
+
+        app.show_message(MessageId::new(channel_id, 2));
+        assert_eq!(app.channels.state.selected(), Some(0));
+        assert_eq!(app.messages[&channel_id].items, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(
+            app.selected_message_id(),
+            Some(MessageId::new(channel_id, 2))
+        );
+    }
 
-        let message = self.storage.store_message(channel_id, message);
+    #[tokio::test]
+    async fn test_prune_and_purge_messages() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let dir = tempfile::tempdir().unwrap();
+        let filename = dir.path().join("photo.jpg");
+        std::fs::write(&filename, "photo").unwrap();
+        let attachment = Attachment {
+            id: "photo".to_string(),
+            content_type: "image/jpeg".to_string(),
+            filename: filename.clone(),
+            size: 5,
+        };
+        app.add_message_to_channel(0, Message::new(app.user_id, None, [], 1, vec![attachment]));
+        for arrived_at in 2..=4 {
+            app.add_message_to_channel(0, Message::new(app.user_id, None, [], arrived_at, vec![]));
+        }
 
-        let messages = self.messages.entry(channel_id).or_default();
-        messages.items.push(message.arrived_at);
+        app.config.max_messages_per_channel = Some(2);
+        app.step_retention();
+        assert_eq!(app.messages[&channel_id].items, [3, 4]);
+        let stored: Vec<_> = app
+            .storage
+            .messages(channel_id)
+            .map(|message| message.arrived_at)
+            .collect();
+        assert_eq!(stored, [3, 4]);
+        assert!(!filename.exists());
 
-        if let Some(idx) = messages.state.selected() {
-            // keep selection on the old message
-            messages.state.select(Some(idx + 1));
+        app.on_command(Command::Purge).await.unwrap();
+        assert!(app.messages[&channel_id].items.is_empty());
+        assert_eq!(app.storage.messages(channel_id).count(), 0);
+    }
+
+    #[test]
+    fn test_delete_messages_before_in_batches() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let dir = tempfile::tempdir().unwrap();
+        let stored_before = app.storage.messages(channel_id).count();
+        let last = 2 * DELETE_BATCH as u64 + 1;
+        let mut files = Vec::new();
+        for arrived_at in 1..=last {
+            let mut attachments = Vec::new();
+            if arrived_at == 1 || arrived_at == DELETE_BATCH as u64 + 1 {
+                let filename = dir.path().join(format!("{arrived_at}.jpg"));
+                std::fs::write(&filename, "photo").unwrap();
+                files.push(filename.clone());
+                attachments.push(Attachment {
+                    id: arrived_at.to_string(),
+                    content_type: "image/jpeg".to_string(),
+                    filename,
+                    size: 5,
+                });
+            }
+            let message = Message::new(app.user_id, None, [], arrived_at, attachments);
+            app.storage.store_message(channel_id, message);
         }
 
-        self.touch_channel(channel_idx);
+        let count = app.delete_messages_before(channel_id, last);
+        assert_eq!(count, stored_before + 2 * DELETE_BATCH);
+        let stored: Vec<_> = app
+            .storage
+            .messages(channel_id)
+            .map(|message| message.arrived_at)
+            .collect();
+        assert_eq!(stored, [last]);
+        assert!(files.iter().all(|filename| !filename.exists()));
     }
 
-    pub(crate) fn touch_channel(&mut self, channel_idx: usize) {
-        if self.channels.state.selected() != Some(channel_idx) {
-            let channel_id = self.channels.items[channel_idx];
-            let mut channel = self
-                .storage
-                .channel(channel_id)
-                .expect("non-existent channel")
-                .into_owned();
-            channel.unread_messages += 1;
-            self.storage.store_channel(channel);
-        } else {
-            self.reset_unread_messages();
+    #[tokio::test]
+    async fn test_keep_messages() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let stored = |app: &App| -> Vec<u64> {
+            app.storage
+                .messages(channel_id)
+                .map(|message| message.arrived_at)
+                .collect()
+        };
+        for arrived_at in 1..=3 {
+            app.add_message_to_channel(0, Message::new(app.user_id, None, [], arrived_at, vec![]));
         }
 
-        self.bubble_up_channel(channel_idx);
+        app.on_command(Command::KeepMessages(Some(2)))
+            .await
+            .unwrap();
+        assert_eq!(stored(&app), [2, 3]);
+        app.add_message_to_channel(0, Message::new(app.user_id, None, [], 4, vec![]));
+        assert_eq!(stored(&app), [3, 4]);
+        assert_eq!(app.messages[&channel_id].items, [3, 4]);
+
+        app.on_command(Command::KeepMessages(None)).await.unwrap();
+        app.add_message_to_channel(0, Message::new(app.user_id, None, [], 5, vec![]));
+        assert_eq!(stored(&app), [3, 4, 5]);
     }
 
-    fn bubble_up_channel(&mut self, channel_idx: usize) {
-        // bubble up channel to the beginning of the list
-        let channels = &mut self.channels;
-        for (prev, next) in (0..channel_idx).zip(1..channel_idx + 1).rev() {
-            channels.items.swap(prev, next);
+    #[test]
+    fn test_channel_idx() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let first = app.channels.items[0];
+        let first_channel = app.storage.channel(first).unwrap().into_owned();
+        let others: Vec<_> = (0..3).map(|_| ChannelId::User(Uuid::new_v4())).collect();
+        for &id in &others {
+            app.storage.store_channel(Channel {
+                id,
+                group_data: None,
+                typing: TypingSet::SingleTyping(false),
+                ..first_channel.clone()
+            });
+            app.channels.items.push(id);
         }
-        match channels.state.selected() {
-            Some(selected_idx) if selected_idx == channel_idx => channels.state.select(Some(0)),
-            Some(selected_idx) if selected_idx < channel_idx => {
-                channels.state.select(Some(selected_idx + 1));
-            }
-            _ => {}
+        assert_eq!(app.channel_idx(first), Some(0));
+        assert_eq!(app.channel_idx(others[2]), Some(3));
+
+        app.bubble_up_channel(3);
+        assert_eq!(app.channels.items, [others[2], first, others[0], others[1]]);
+        for (idx, &channel_id) in app.channels.items.iter().enumerate() {
+            assert_eq!(app.channel_idx(channel_id), Some(idx));
         }
+        assert_eq!(app.channel_idx(ChannelId::User(Uuid::new_v4())), None);
     }
 
-    fn notify(&self, summary: &str, text: &str) {
-        if self.config.notifications {
-            if let Err(e) = Notification::new().summary(summary).body(text).show() {
-                error!("failed to send notification: {}", e);
-            }
-        }
+    #[tokio::test]
+    async fn test_commands_on_marked_channels() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let first = app.channels.items[0];
+        let second = ChannelId::User(Uuid::new_v4());
+        let first_channel = app.storage.channel(first).unwrap().into_owned();
+        app.storage.store_channel(Channel {
+            id: second,
+            group_data: None,
+            typing: TypingSet::SingleTyping(false),
+            ..first_channel
+        });
+        app.channels.items.push(second);
+        let channel = |app: &App, channel_id| app.storage.channel(channel_id).unwrap().into_owned();
+
+        app.on_command(Command::ToggleChannelMark).await.unwrap();
+        app.channels.state.select(Some(1));
+        app.on_command(Command::ToggleChannelMark).await.unwrap();
+        app.on_command(Command::MarkRead).await.unwrap();
+        assert_eq!(channel(&app, first).unread_messages, 0);
+        assert_eq!(channel(&app, second).unread_messages, 0);
+        assert!(app.marked_channels.is_empty());
+
+        app.marked_channels.extend([first, second]);
+        app.on_command(Command::Mute(None)).await.unwrap();
+        assert!(channel(&app, first).muted_until.is_some());
+        assert!(channel(&app, second).muted_until.is_some());
+
+        // without marks, only the selected channel
+        app.on_command(Command::Unmute).await.unwrap();
+        assert!(channel(&app, first).muted_until.is_some());
+        assert!(channel(&app, second).muted_until.is_none());
+
+        app.marked_channels.extend([first, second]);
+        app.on_command(Command::ToggleArchive).await.unwrap();
+        assert!(channel(&app, first).archived);
+        assert!(channel(&app, second).archived);
+        assert_eq!(app.visible_channels_len(), 0);
     }
 
-    fn bell(&self) {
-        if self.config.bell {
-            print!("\x07");
+    #[tokio::test]
+    async fn test_complete_command() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let tab = KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE);
+        for c in "/unmu".chars() {
+            app.get_input().put_char(c);
         }
-    }
+        app.on_key(tab).await.unwrap();
+        assert_eq!(app.input.data, "/unmute ");
 
-    fn extract_attachments(&mut self, input: &str) -> (String, Vec<(AttachmentSpec, Vec<u8>)>) {
-        let mut offset = 0;
-        let mut clean_input = String::new();
+        app.take_input();
+        for c in "/select_message next e".chars() {
+            app.get_input().put_char(c);
+        }
+        app.on_key(tab).await.unwrap();
+        assert_eq!(app.input.data, "/select_message next e");
+        assert_eq!(app.command_hint.as_deref(), Some("entry end"));
 
-        let re = self.attachment_regex.compiled();
-        let attachments = re.find_iter(input).filter_map(|m| {
-            let path_str = m.as_str().strip_prefix("file://")?;
+        app.take_input();
+        for c in "/keep_messages ".chars() {
+            app.get_input().put_char(c);
+        }
+        app.on_key(tab).await.unwrap();
+        assert!(app
+            .command_hint
+            .as_deref()
+            .is_some_and(|hint| hint.starts_with("keep_messages [count]: ")));
+    }
 
-            let (contents, content_type, file_name) = if path_str.starts_with("clip") {
-                let img = self.clipboard.as_mut()?.get_image().ok()?;
+    #[tokio::test]
+    async fn test_complete_plugin_command() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("greet.lua"),
+            r#"gurk.register_command("greet_all", function() end)"#,
+        )
+        .unwrap();
+        app.load_plugins(dir.path());
+        let tab = KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE);
 
-                let png: ImageBuffer<Rgba<_>, _> =
-                    ImageBuffer::from_raw(img.width as _, img.height as _, img.bytes)?;
+        "/greet".chars().for_each(|c| app.get_input().put_char(c));
+        app.on_key(tab).await.unwrap();
+        assert_eq!(app.input.data, "/greet_all ");
+    }
 
-                let mut bytes = Vec::new();
-                let mut cursor = Cursor::new(&mut bytes);
-                let encoder = PngEncoder::new(&mut cursor);
+    #[tokio::test]
+    async fn test_dm_command() {
+        let signal_manager = SignalManagerMock::new();
+        let unregistered = signal_manager.unregistered.clone();
+        let unchecked = signal_manager.unchecked.clone();
+        let (mut app, _events, _sent_messages) = test_app_with(signal_manager);
+        let alice = Uuid::new_v4();
+        let names = app.names.get_mut();
+        names.set_display_name(alice, "Alice Liddell".to_string());
+
+        // the whole name is completed
+        let tab = KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE);
+        for c in "/dm ali".chars() {
+            app.get_input().put_char(c);
+        }
+        app.on_key(tab).await.unwrap();
+        assert_eq!(app.input.data, "/dm Alice Liddell ");
+        app.take_input();
+
+        // names are matched and completed case-insensitively
+        app.names
+            .get_mut()
+            .set_display_name(Uuid::new_v4(), "alfred".to_string());
+        "/dm AL".chars().for_each(|c| app.get_input().put_char(c));
+        app.on_key(tab).await.unwrap();
+        assert_eq!(app.input.data, "/dm Al");
+        assert_eq!(app.command_hint.as_deref(), Some("Alice Liddell, alfred"));
+        app.take_input();
+
+        app.on_command(Command::Dm("alice liddell".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(app.command_error, None);
+        assert_eq!(app.channels.selected_item(), Some(&ChannelId::User(alice)));
+        assert_eq!(
+            app.storage.channel(alice.into()).unwrap().name,
+            "Alice Liddell"
+        );
 
-                let data: Vec<_> = png.into_raw().iter().map(|b| b.swap_bytes()).collect();
-                encoder
-                    .write_image(
-                        &data,
-                        img.width as _,
-                        img.height as _,
-                        image::ExtendedColorType::Rgba8,
-                    )
-                    .ok()?;
+        // no channel is created for unregistered users
+        let bob = Uuid::new_v4();
+        unregistered.borrow_mut().insert(bob);
+        app.on_command(Command::Dm(bob.to_string())).await.unwrap();
+        assert!(app.command_error.is_some());
+        assert_eq!(app.channel_idx(bob.into()), None);
+
+        // nor for users who cannot be checked
+        let carol = Uuid::new_v4();
+        unchecked.borrow_mut().insert(carol);
+        app.command_error = None;
+        app.on_command(Command::Dm(carol.to_string()))
+            .await
+            .unwrap();
+        assert!(app.command_error.is_some());
+        assert_eq!(app.channel_idx(carol.into()), None);
+
+        app.command_error = None;
+        app.on_command(Command::Dm("nobody".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(
+            app.command_error.as_deref(),
+            Some("no contact named nobody")
+        );
+    }
 
-                (
-                    bytes,
-                    "image/png".to_string(),
-                    Some("clipboard.png".to_string()),
-                )
-            } else {
-                let path = Path::new(path_str);
-                let contents = std::fs::read(path).ok()?;
-                let content_type = mime_guess::from_path(path)
-                    .first()
-                    .map(|mime| mime.essence_str().to_string())
-                    .unwrap_or_default();
-                let file_name = path.file_name().map(|f| f.to_string_lossy().into());
+    #[tokio::test]
+    async fn test_attach_command() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cat.jpg");
+        std::fs::write(&path, b"meow").unwrap();
+
+        app.on_command(Command::Attach(path.to_str().unwrap().to_string()))
+            .await
+            .unwrap();
+        assert_eq!(app.input.data, format!("file://{}", path.display()));
+
+        app.on_command(Command::Attach("/nonexistent.jpg".to_string()))
+            .await
+            .unwrap();
+        assert!(app.command_error.is_some());
+    }
 
-                (contents, content_type, file_name)
-            };
+    #[tokio::test]
+    async fn test_plugins() {
+        let (mut app, _events, sent_messages) = test_app();
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("greet.lua"),
+            r#"
+            gurk.register_command("greet", function(args)
+                gurk.send(gurk.channels()[1].id, "Hi " .. args)
+            end)
+            gurk.status(function() return "greeter" end)
+            "#,
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("broken.lua"), "syntax error").unwrap();
+        app.load_plugins(dir.path());
+        assert_eq!(app.plugin_status_segments(), ["greeter"]);
 
-            clean_input.push_str(input[offset..m.start()].trim_end());
-            offset = m.end();
+        for c in "/greet Tyler".chars() {
+            app.get_input().put_char(c);
+        }
+        app.run_input_command().await.unwrap();
+        assert_eq!(app.input.data, "");
+        assert_eq!(app.command_error, None);
+        assert_eq!(sent_messages.borrow().len(), 1);
+        assert_eq!(
+            sent_messages.borrow()[0].message.as_deref(),
+            Some("Hi Tyler")
+        );
+    }
 
-            let spec = AttachmentSpec {
-                content_type,
-                length: contents.len(),
-                file_name,
-                preview: None,
-                voice_note: None,
-                borderless: None,
-                width: None,
-                height: None,
-                caption: None,
-                blur_hash: None,
-            };
-            Some((spec, contents))
+    #[tokio::test]
+    async fn test_send_snippet() {
+        let (mut app, _events, sent_messages) = test_app();
+        app.config
+            .snippets
+            .insert("checkin".to_string(), "Hi {first_name}!".to_string());
+        let first = app.channels.items[0];
+        // notes channel, named like our user
+        let second = ChannelId::User(app.user_id);
+        let first_channel = app.storage.channel(first).unwrap().into_owned();
+        app.storage.store_channel(Channel {
+            id: second,
+            group_data: None,
+            typing: TypingSet::SingleTyping(false),
+            ..first_channel
         });
+        app.channels.items.push(second);
+        app.messages.entry(second).or_default();
+
+        app.marked_channels.extend([first, second]);
+        app.on_command(Command::Snippet("checkin".to_string()))
+            .await
+            .unwrap();
+        let sent: Vec<_> = sent_messages
+            .borrow()
+            .iter()
+            .map(|message| message.message.clone().unwrap())
+            .collect();
+        assert_eq!(sent.len(), 2);
+        assert!(sent.contains(&"Hi Tyler!".to_string()));
+        assert!(app.marked_channels.is_empty());
+
+        app.on_command(Command::Snippet("unknown".to_string()))
+            .await
+            .unwrap();
+        assert!(app.command_error.is_some());
+        assert_eq!(sent_messages.borrow().len(), 2);
+    }
 
-        let attachments = attachments.collect();
-        clean_input.push_str(&input[offset..]);
-        let clean_input = clean_input.trim().to_string();
+    #[tokio::test]
+    async fn test_export_and_import_profile() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let pinned = |app: &App| app.storage.channel(channel_id).unwrap().pinned;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("profile.toml");
+        let path = path.to_str().unwrap();
 
-        (clean_input, attachments)
+        app.channels.state.select(Some(0));
+        app.on_command(Command::TogglePin).await.unwrap();
+        app.on_command(Command::ExportProfile(path.to_string()))
+            .await
+            .unwrap();
+        assert!(app.command_error.is_none());
+
+        app.on_command(Command::TogglePin).await.unwrap();
+        app.config.notifications = false;
+        app.on_command(Command::ImportProfile(path.to_string()))
+            .await
+            .unwrap();
+        assert!(pinned(&app));
+        assert!(app.config.notifications);
+        assert!(app.is_config_changed);
+
+        app.on_command(Command::ImportProfile("/nonexistent.toml".to_string()))
+            .await
+            .unwrap();
+        assert!(app.command_error.is_some());
     }
 
-    async fn save_attachments(
-        &mut self,
-        attachment_pointers: Vec<AttachmentPointer>,
-    ) -> Vec<Attachment> {
-        let mut attachments = vec![];
-        for attachment_pointer in attachment_pointers {
-            match self
-                .signal_manager
-                .save_attachment(attachment_pointer)
-                .await
-            {
-                Ok(attachment) => attachments.push(attachment),
-                Err(e) => warn!("failed to save attachment: {}", e),
+    #[tokio::test]
+    async fn test_devices() {
+        let (mut app, _events, _sent_messages) = test_app();
+
+        app.on_command(Command::ToggleDevices).await.unwrap();
+        let devices = app.linked_devices.clone().unwrap().unwrap();
+        assert_eq!(devices.len(), 2);
+        assert!(devices.iter().any(|device| device.id == app.device_id()));
+        app.on_command(Command::ToggleDevices).await.unwrap();
+        assert!(app.linked_devices.is_none());
+
+        app.on_command(Command::UnlinkDevice).await.unwrap();
+        assert!(app.should_quit);
+        assert_eq!(
+            app.after_quit,
+            AfterQuit::DeleteCredentials(app.config.signal_db_path.clone())
+        );
+        let devices = app.signal_manager.linked_devices().await.unwrap();
+        assert!(devices.iter().all(|device| device.id != app.device_id()));
+    }
+
+    #[tokio::test]
+    async fn test_send_input() {
+        let (mut app, mut events, sent_messages) = test_app();
+        let input = "Hello, World!";
+        for c in input.chars() {
+            app.get_input().put_char(c);
+        }
+        app.send_input(0);
+
+        assert_eq!(sent_messages.borrow().len(), 1);
+        let msg = sent_messages.borrow()[0].clone();
+        assert_eq!(msg.message.as_ref().unwrap(), input);
+
+        let channel_id = app.channels.items[0];
+        let channel = app.storage.channel(channel_id).unwrap();
+        assert_eq!(channel.unread_messages, 0);
+
+        assert_eq!(app.get_input().data, "");
+
+        match events.recv().await.unwrap() {
+            Event::SentTextResult { message_id, result } => {
+                assert_eq!(message_id.arrived_at, msg.arrived_at);
+                assert!(result.is_ok());
             }
         }
-        attachments
     }
 
-    pub fn toggle_help(&mut self) {
-        self.display_help = !self.display_help;
+    #[tokio::test]
+    async fn test_send_status() {
+        let (mut app, mut events, _sent_messages) = test_app();
+        app.get_input().put_char('a');
+        app.send_input(0);
+        let event = events.recv().await.unwrap();
+        app.handle_event(event).unwrap();
+        let status = app.send_status().unwrap();
+        assert_eq!(status.text, "Sent to test ✓");
+        assert!(!status.is_error);
+
+        let message_id = MessageId::new(app.channels.items[0], 1);
+        let result = Err(anyhow!("rejected"));
+        app.update_send_status(message_id.channel_id, &result);
+        let status = app.send_status().unwrap();
+        assert_eq!(status.text, "Failed to send to test: rejected");
+        assert!(status.is_error);
+
+        app.send_status.as_mut().unwrap().at -= SEND_STATUS_DURATION;
+        assert!(app.send_status().is_none());
     }
 
-    pub fn is_help(&self) -> bool {
-        self.display_help
+    #[tokio::test]
+    async fn test_keep_unread_while_not_reading() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let unread = |app: &App| app.storage.channel(channel_id).unwrap().unread_messages;
+        let from = Uuid::new_v4();
+        app.reset_unread_messages();
+
+        // read while unfocused by default
+        app.on_focus(false);
+        app.add_message_to_channel(0, Message::new(from, Some("a".into()), [], 1, vec![]));
+        assert_eq!(unread(&app), 0);
+
+        app.config.mark_read.when_unfocused = false;
+        app.add_message_to_channel(0, Message::new(from, Some("b".into()), [], 2, vec![]));
+        assert_eq!(unread(&app), 1);
+        app.on_focus(true);
+        assert_eq!(unread(&app), 0);
+
+        app.config.mark_read.idle_minutes = Some(5);
+        app.last_key_at = Instant::now() - Duration::from_secs(6 * 60);
+        app.add_message_to_channel(0, Message::new(from, Some("c".into()), [], 3, vec![]));
+        assert_eq!(unread(&app), 1);
+        app.on_key(KeyEvent::new(KeyCode::Char('x'), KeyModifiers::NONE))
+            .await
+            .unwrap();
+        assert_eq!(unread(&app), 0);
     }
 
-    pub fn request_contacts_sync(
-        &self,
-    ) -> Option<impl Future<Output = anyhow::Result<DateTime<Utc>>> + 'static> {
-        let now = Utc::now();
-        let metadata = self.storage.metadata();
-        let do_sync = metadata
-            .contacts_sync_request_at
-            .map(|dt| dt + chrono::Duration::seconds(CONTACTS_SYNC_DEADLINE_SEC) < now)
-            .unwrap_or(true);
-        let signal_manager = self.signal_manager.clone_boxed();
-        do_sync.then_some(async move {
-            info!(timeout =? CONTACTS_SYNC_TIMEOUT, "requesting contact sync");
-            tokio::time::timeout(
-                CONTACTS_SYNC_TIMEOUT,
-                signal_manager.request_contacts_sync(),
-            )
-            .await??;
-            Ok(Utc::now())
-        })
-    }
+    #[tokio::test]
+    async fn test_unread_summary() {
+        let (mut app, _events, _sent_messages) = test_app();
+        app.on_focus(false);
+        assert_eq!(app.unread_summary(), None);
 
-    pub fn is_select_channel_shown(&self) -> bool {
-        self.select_channel.is_shown
+        app.config.unread_summary_after_minutes = Some(10);
+        assert_eq!(app.unread_summary(), None);
+        app.focus_changed_at -= Duration::from_secs(11 * 60);
+        assert_eq!(app.unread_summary().as_deref(), Some("1 unread in 1 chat"));
+
+        app.on_focus(true);
+        assert_eq!(app.unread_summary(), None);
     }
 
-    pub fn select_channel_prev(&mut self) {
-        self.select_channel.prev();
+    #[tokio::test]
+    async fn test_notification_content() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        assert_eq!(
+            app.notification_content(channel_id),
+            NotificationContent::Full
+        );
+
+        app.config.notification.content = NotificationContent::Sender;
+        assert_eq!(
+            app.notification_content(channel_id),
+            NotificationContent::Sender
+        );
+
+        app.channels.state.select(Some(0));
+        app.on_command(Command::NotificationContent(Some(
+            NotificationContent::Hidden,
+        )))
+        .await
+        .unwrap();
+        assert_eq!(
+            app.notification_content(channel_id),
+            NotificationContent::Hidden
+        );
+        app.on_command(Command::NotificationContent(None))
+            .await
+            .unwrap();
+        assert_eq!(
+            app.notification_content(channel_id),
+            NotificationContent::Sender
+        );
     }
 
-    pub fn select_channel_next(&mut self) {
-        self.select_channel.next();
+    #[tokio::test]
+    async fn test_notification_actions() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let unread = |app: &App| app.storage.channel(channel_id).unwrap().unread_messages;
+        assert_eq!(unread(&app), 1);
+
+        app.handle_event(Event::NotificationAction {
+            channel_id,
+            action: NotificationAction::MarkRead,
+        })
+        .unwrap();
+        assert_eq!(unread(&app), 0);
+
+        app.channels.state.select(None);
+        app.handle_event(Event::NotificationAction {
+            channel_id,
+            action: NotificationAction::Open,
+        })
+        .unwrap();
+        assert_eq!(app.channels.selected_item(), Some(&channel_id));
     }
 
-    pub fn copy_selection(&mut self) {
-        if let Some(message) = self.selected_message() {
-            if let Some(text) = message.message.as_ref() {
-                let text = text.clone();
-                if let Some(clipboard) = self.clipboard.as_mut() {
-                    if let Err(error) = clipboard.set_text(text) {
-                        error!(%error, "failed to copy text to clipboard");
-                    } else {
-                        info!("copied selected text to clipboard");
-                    }
-                }
-            }
-        }
+    #[tokio::test]
+    async fn test_hook_actions() {
+        let (mut app, _events, sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+
+        app.handle_event(Event::HookActions {
+            channel_id,
+            actions: vec![
+                HookAction::Reply {
+                    text: "I'm on vacation".to_string(),
+                },
+                HookAction::MarkRead,
+            ],
+        })
+        .unwrap();
+        assert_eq!(sent_messages.borrow().len(), 1);
+        assert_eq!(
+            sent_messages.borrow()[0].message.as_deref(),
+            Some("I'm on vacation")
+        );
+        assert_eq!(app.storage.channel(channel_id).unwrap().unread_messages, 0);
     }
 
-    pub fn handle_event(&mut self, event: Event) -> anyhow::Result<()> {
-        match event {
-            Event::SentTextResult { message_id, result } => {
-                if let Err(error) = result {
-                    let mut message = self
-                        .storage
-                        .message(message_id)
-                        .context("no message")?
-                        .into_owned();
-                    message.send_failed = Some(error.to_string());
-                    self.storage.store_message(message_id.channel_id, message);
-                }
-            }
+    #[tokio::test]
+    async fn test_notification_signals() {
+        let (mut app, _events, sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        app.track_notification(7, channel_id);
+
+        // not shown by gurk
+        app.handle_event(Event::NotificationSignal {
+            notification_id: 8,
+            signal: NotificationSignal::Replied("Hi".to_string()),
+        })
+        .unwrap();
+        assert!(sent_messages.borrow().is_empty());
+
+        app.handle_event(Event::NotificationSignal {
+            notification_id: 7,
+            signal: NotificationSignal::ActionInvoked(REPLY_ACTION.to_string()),
+        })
+        .unwrap();
+        app.handle_event(Event::NotificationSignal {
+            notification_id: 7,
+            signal: NotificationSignal::Replied("On my way".to_string()),
+        })
+        .unwrap();
+        assert_eq!(sent_messages.borrow().len(), 1);
+        assert_eq!(
+            sent_messages.borrow()[0].message.as_deref(),
+            Some("On my way")
+        );
+        assert_eq!(app.storage.channel(channel_id).unwrap().unread_messages, 0);
+        assert!(app.notification_channels.borrow().is_empty());
+
+        for id in 0..MAX_TRACKED_NOTIFICATIONS as u32 + 1 {
+            app.track_notification(id, channel_id);
         }
-        Ok(())
+        assert_eq!(
+            app.notification_channels.borrow().len(),
+            MAX_TRACKED_NOTIFICATIONS
+        );
+        assert!(!app.notification_channels.borrow().contains_key(&0));
     }
 
-    pub(crate) fn is_editing(&self) -> bool {
-        self.editing.is_some()
+    #[tokio::test]
+    async fn test_presence() {
+        let (mut app, _events, _sent_messages) = test_app();
+        assert!(!app.is_notification_suppressed());
+        app.handle_event(Event::SetPresence("dnd".parse().unwrap()))
+            .unwrap();
+        assert!(app.is_notification_suppressed());
+        app.handle_event(Event::SetPresence(Presence::Available))
+            .unwrap();
+        assert!(!app.is_notification_suppressed());
+        assert!("busy".parse::<Presence>().is_err());
     }
 
-    /// Returns `true` if editing was reset, otherwise `false`
-    fn reset_editing(&mut self) -> bool {
-        let is_reset = self.editing.take().is_some();
-        if is_reset {
-            self.take_input();
-        }
-        is_reset
+    #[test]
+    fn test_connection_state_display() {
+        assert_eq!(ConnectionState::Connected.to_string(), "connected");
+        let at = Instant::now() + Duration::from_millis(4500);
+        assert_eq!(
+            ConnectionState::Reconnecting { at }.to_string(),
+            "reconnecting in 5s"
+        );
+        let at = Instant::now();
+        assert_eq!(
+            ConnectionState::Reconnecting { at }.to_string(),
+            "reconnecting"
+        );
     }
 
-    fn start_editing(&mut self) -> Option<()> {
-        if !self.input.is_empty() {
-            return None;
+    #[tokio::test]
+    async fn test_find_channel() {
+        let (app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        assert_eq!(app.find_channel("Test").unwrap(), channel_id);
+        assert_eq!(
+            app.find_channel(&util::channel_id_to_string(channel_id))
+                .unwrap(),
+            channel_id
+        );
+        assert!(app.find_channel("nobody").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_notifications_suppressed_while_focused() {
+        let (mut app, _events, _sent_messages) = test_app();
+        assert!(!app.is_notification_suppressed());
+
+        app.config.notification.when_focused = false;
+        assert!(app.is_notification_suppressed());
+        app.on_focus(false);
+        assert!(!app.is_notification_suppressed());
+
+        if cfg!(unix) {
+            app.config.notification.focus_command = Some("true".to_string());
+            assert!(app.is_notification_suppressed());
+            app.config.notification.focus_command = Some("false".to_string());
+            assert!(!app.is_notification_suppressed());
         }
+    }
 
-        let message_id = self.selected_message_id()?;
-        let message = self.storage.message(message_id)?;
+    #[tokio::test]
+    async fn test_resend_after_captcha() {
+        let signal_manager = SignalManagerMock::new();
+        let captcha_token = signal_manager.captcha_token.clone();
+        captcha_token.replace(Some("token".to_string()));
+        let (mut app, mut events, sent_messages) = test_app_with(signal_manager);
+        let channel_id = app.channels.items[0];
 
-        if message.from_id != self.user_id {
-            return None;
+        for c in "Hello".chars() {
+            app.get_input().put_char(c);
         }
-
-        let target_sent_timestamp = self
+        app.send_input(0);
+        let event = events.recv().await.unwrap();
+        app.handle_event(event).unwrap();
+        assert!(sent_messages.borrow().is_empty());
+        assert!(app.captcha_prompt.is_shown);
+        let rejected = app
             .storage
-            .edits(message_id)
+            .messages(channel_id)
             .last()
-            .map(|last_edit| last_edit.arrived_at)
-            .unwrap_or(message.arrived_at);
-        let message_id = MessageId::new(message_id.channel_id, target_sent_timestamp);
-        let text = message.message.clone()?;
+            .unwrap()
+            .into_owned();
+        assert!(rejected.send_failed.is_some());
 
-        self.editing.replace(message_id);
-        self.input.data = text;
-        self.input.on_end();
+        for c in "signalcaptcha://solved".chars() {
+            app.get_input().put_char(c);
+        }
+        app.on_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
+            .await
+            .unwrap();
+        assert!(!app.captcha_prompt.is_shown);
+        assert_eq!(*captcha_token.borrow(), None);
 
-        Some(())
+        // resent with the original timestamp, replacing the rejected message
+        assert_eq!(sent_messages.borrow().len(), 1);
+        assert_eq!(sent_messages.borrow()[0].arrived_at, rejected.arrived_at);
+        let event = events.recv().await.unwrap();
+        app.handle_event(event).unwrap();
+        let messages: Vec<_> = app.storage.messages(channel_id).collect();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].message.as_deref(), Some("Hello"));
+        assert_eq!(messages[1].send_failed, None);
+        assert_eq!(app.messages[&channel_id].items.len(), 2);
     }
 
-    pub fn event_to_command<'r>(&'r self, event: &KeyEvent) -> Option<&'r Command> {
-        let mut combiner = Combiner::default();
-        let keys_pressed = combiner.transform(*event)?;
-        let modes = if self.is_help() {
-            vec![WindowMode::Anywhere, WindowMode::Help]
-        } else if self.is_select_channel_shown() {
-            vec![WindowMode::Anywhere, WindowMode::ChannelModal]
-        } else if self.is_multiline_input {
-            vec![
-                WindowMode::Anywhere,
-                WindowMode::Multiline,
-                WindowMode::Normal,
-            ]
-        } else if self.input.is_empty() {
-            vec![
-                WindowMode::Anywhere,
-                WindowMode::MessageSelected,
-                WindowMode::Normal,
-            ]
-        } else {
-            vec![WindowMode::Anywhere, WindowMode::Normal]
-        };
-        for mode in modes {
-            if let Some(kb) = self.mode_keybindings.get(&mode) {
-                if let Some(cmd) = kb.get(&keys_pressed) {
-                    return Some(cmd);
-                }
-            }
+    #[tokio::test]
+    async fn test_resend_after_rate_limit() {
+        let signal_manager = SignalManagerMock::new();
+        let rate_limited = signal_manager.rate_limited.clone();
+        rate_limited.replace(true);
+        let (mut app, mut events, sent_messages) = test_app_with(signal_manager);
+        app.set_connection_state(ConnectionState::Connected);
+        let channel_id = app.channels.items[0];
+
+        for c in "Hello".chars() {
+            app.get_input().put_char(c);
         }
-        if self.is_help() {
-            // Swallow event
-            Some(&Command::NoOp)
-        } else {
-            None
+        app.send_input(0);
+        let event = events.recv().await.unwrap();
+        app.handle_event(event).unwrap();
+        assert!(!app.captcha_prompt.is_shown);
+        assert_eq!(app.queued_messages_len(), 1);
+        let retry_at = app.rate_limited.retry_at().unwrap();
+        assert!(retry_at > Instant::now() + Duration::from_secs(30));
+        let rejected = app
+            .storage
+            .messages(channel_id)
+            .last()
+            .unwrap()
+            .into_owned();
+        let message_id = MessageId::new(channel_id, rejected.arrived_at);
+
+        // rejected again after waiting, the wait doubles
+        let messages = app.rate_limited.take_due(retry_at);
+        assert!(messages.contains_key(&message_id));
+        for (message_id, message) in messages {
+            app.rate_limited.push(message_id, message, retry_at);
         }
+        assert_eq!(
+            app.rate_limited.retry_at(),
+            Some(retry_at + Duration::from_secs(120))
+        );
+
+        // resent right away when retried
+        rate_limited.replace(false);
+        app.messages
+            .get_mut(&channel_id)
+            .unwrap()
+            .state
+            .select(Some(0));
+        app.retry_send();
+        assert_eq!(sent_messages.borrow().len(), 1);
+        assert_eq!(sent_messages.borrow()[0].arrived_at, rejected.arrived_at);
+        assert_eq!(app.queued_messages_len(), 0);
+        assert_eq!(app.rate_limited.retry_at(), None);
     }
-}
 
-#[derive(Debug, Default)]
-struct HandleReactionOptions {
-    remove: bool,
-    notify: bool,
-    bell: bool,
-}
+    #[tokio::test]
+    async fn test_gallery_downloads_attachment_in_background() {
+        use presage::libsignal_service::prelude::AttachmentIdentifier;
 
-impl HandleReactionOptions {
-    fn new() -> Self {
-        Default::default()
-    }
+        let (mut app, mut events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("photo.jpg");
+        let attachment = Attachment {
+            id: "photo".to_string(),
+            content_type: "image/jpeg".to_string(),
+            filename: path.clone(),
+            size: 4,
+        };
+        let message = Message::new(app.user_id, None, [], 10, vec![attachment]);
+        app.storage.store_message(channel_id, message);
+        app.attachment_gallery.open(&*app.storage, channel_id);
+
+        app.on_command(Command::OpenGalleryAttachment)
+            .await
+            .unwrap();
+        assert_eq!(
+            app.attachment_gallery.status.as_deref(),
+            Some("failed to download: cannot be downloaded again")
+        );
 
-    fn remove(self, remove: bool) -> Self {
-        Self { remove, ..self }
+        let pointer = AttachmentPointer {
+            attachment_identifier: Some(AttachmentIdentifier::CdnKey("photo".to_string())),
+            content_type: Some("image/jpeg".to_string()),
+            size: Some(4),
+            ..Default::default()
+        };
+        app.attachment_cache
+            .insert_pending(path.clone(), 4, &pointer);
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                app.on_command(Command::OpenGalleryAttachment)
+                    .await
+                    .unwrap();
+                assert_eq!(app.attachment_gallery.opening, Some(path.clone()));
+                assert_eq!(app.effects.len(), 1);
+
+                // the file of the mock download does not exist
+                let event = events.recv().await.unwrap();
+                app.handle_event(event).unwrap();
+            })
+            .await;
+        assert!(app.effects.is_empty());
+        assert_eq!(app.attachment_gallery.opening, None);
+        assert!(app
+            .attachment_gallery
+            .status
+            .as_deref()
+            .unwrap()
+            .starts_with("failed to download: "));
+        assert!(!app.attachment_gallery.selected().unwrap().exists);
     }
 
-    fn notify(self, notify: bool) -> Self {
-        Self { notify, ..self }
-    }
+    #[tokio::test]
+    async fn test_cancel_send() {
+        let (mut app, mut events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
 
-    fn bell(self, bell: bool) -> Self {
-        Self { bell, ..self }
-    }
-}
+        for c in "Hello".chars() {
+            app.get_input().put_char(c);
+        }
+        app.send_input(0);
+        assert_eq!(app.effects.len(), 1);
+        app.messages
+            .get_mut(&channel_id)
+            .unwrap()
+            .state
+            .select(Some(0));
+        app.cancel_selected();
+        assert!(app.effects.is_empty());
+        tokio::task::yield_now().await;
+        assert!(events.try_recv().is_err());
 
-/// Returns an emoji string if `s` is an emoji or if `s` is a GitHub emoji shortcode.
-pub fn to_emoji(s: &str) -> Option<&str> {
-    let s = s.trim();
-    if emojis::get(s).is_some() {
-        Some(s)
-    } else {
-        let s = s.strip_prefix(':')?.strip_suffix(':')?;
-        Some(emojis::get_by_shortcode(s)?.as_str())
-    }
-}
+        let cancelled = app
+            .storage
+            .messages(channel_id)
+            .last()
+            .unwrap()
+            .into_owned();
+        assert_eq!(cancelled.send_failed.as_deref(), Some("cancelled"));
+        let message_id = MessageId::new(channel_id, cancelled.arrived_at);
+        assert!(app.failed_sends.contains_key(&message_id));
 
-fn open_url(message: &Message, url_regex: &Regex) -> Option<()> {
-    let text = message.message.as_ref()?;
-    let m = url_regex.find(text)?;
-    let url = m.as_str();
-    if let Err(error) = opener::open(url) {
-        error!(url, %error, "failed to open");
+        app.cancel_selected();
+        assert!(app.command_error.is_some());
     }
-    Some(())
-}
 
-fn notification_text_for_attachments(attachments: &[Attachment]) -> Option<String> {
-    match attachments.len() {
-        0 => None,
-        1 => Some("<attachment>".into()),
-        n => Some(format!("<attachments ({n})>")),
-    }
-}
+    #[tokio::test]
+    async fn test_retry_send() {
+        let signal_manager = SignalManagerMock::new();
+        let send_error = signal_manager.send_error.clone();
+        send_error.replace(Some("rate limited".to_string()));
+        let (mut app, mut events, sent_messages) = test_app_with(signal_manager);
+        let channel_id = app.channels.items[0];
 
-fn add_emoji_from_sticker(body: &mut Option<String>, sticker: Option<Sticker>) {
-    if let Some(Sticker { emoji: Some(e), .. }) = sticker {
-        *body = Some(format!("<{e}>"));
-    }
-}
+        for c in "Hello".chars() {
+            app.get_input().put_char(c);
+        }
+        app.send_input(0);
+        let event = events.recv().await.unwrap();
+        app.handle_event(event).unwrap();
+        assert_eq!(app.error_log.len(), 1);
+        assert!(app.error_log.toast().is_some());
+        let rejected = app
+            .storage
+            .messages(channel_id)
+            .last()
+            .unwrap()
+            .into_owned();
+        assert!(rejected.send_failed.is_some());
 
-#[cfg(test)]
-pub(crate) mod tests {
-    use super::*;
+        send_error.replace(None);
+        app.messages
+            .get_mut(&channel_id)
+            .unwrap()
+            .state
+            .select(Some(0));
+        app.retry_send();
+        assert_eq!(sent_messages.borrow().len(), 1);
+        assert_eq!(sent_messages.borrow()[0].arrived_at, rejected.arrived_at);
+        let event = events.recv().await.unwrap();
+        app.handle_event(event).unwrap();
+        let messages: Vec<_> = app.storage.messages(channel_id).collect();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].send_failed, None);
+
+        // only rejected messages are sent again
+        app.retry_send();
+        assert_eq!(sent_messages.borrow().len(), 1);
+        assert!(app.command_error.is_some());
+    }
 
-    use crate::config::User;
-    use crate::data::GroupData;
-    use crate::signal::test::SignalManagerMock;
-    use crate::storage::{ForgetfulStorage, MemCache};
+    #[test]
+    fn test_reaction_notifications_always() {
+        let (mut app, _events, _sent_messages) = test_app();
+        app.config.reaction_notifications = ReactionNotifications::Always;
+        assert!(app.is_reaction_notified(ChannelId::User(Uuid::new_v4())));
+        assert!(app.is_reaction_notified(ChannelId::Group([1; 32])));
+    }
 
-    use std::cell::RefCell;
-    use std::rc::Rc;
+    #[test]
+    fn test_reaction_notifications_direct_only() {
+        let (mut app, _events, _sent_messages) = test_app();
+        app.config.reaction_notifications = ReactionNotifications::DirectOnly;
+        assert!(app.is_reaction_notified(ChannelId::User(Uuid::new_v4())));
+        assert!(!app.is_reaction_notified(ChannelId::Group([1; 32])));
+    }
 
-    pub(crate) fn test_app() -> (
-        App,
-        mpsc::UnboundedReceiver<Event>,
-        Rc<RefCell<Vec<Message>>>,
-    ) {
-        let signal_manager = SignalManagerMock::new();
-        let sent_messages = signal_manager.sent_messages.clone();
+    #[test]
+    fn test_reaction_notifications_never() {
+        let (mut app, _events, _sent_messages) = test_app();
+        app.config.reaction_notifications = ReactionNotifications::Never;
+        assert!(!app.is_reaction_notified(ChannelId::User(Uuid::new_v4())));
+        assert!(!app.is_reaction_notified(ChannelId::Group([1; 32])));
+    }
 
-        let mut storage = MemCache::new(ForgetfulStorage);
+    #[tokio::test]
+    async fn test_restore_failed_sends() {
+        let (mut app, _events, sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let mut failed = Message::new(app.user_id, Some("Hello".into()), [], 1, Vec::new());
+        failed.send_failed = Some("network error".to_string());
+        failed.forwarded = true;
+        app.add_message_to_channel(0, failed);
 
-        let channel_id = ChannelId::User(Uuid::new_v4());
-        let channel = Channel {
-            id: channel_id,
-            name: "test".to_string(),
-            group_data: Some(GroupData {
-                master_key_bytes: GroupMasterKeyBytes::default(),
-                members: vec![signal_manager.user_id()],
-                revision: 1,
-            }),
-            unread_messages: 1,
-            typing: TypingSet::GroupTyping(Default::default()),
-        };
-        storage.store_channel(channel);
-        storage.store_message(
-            channel_id,
-            Message {
-                from_id: signal_manager.user_id(),
-                message: Some("First message".to_string()),
-                arrived_at: 0,
-                quote: Default::default(),
-                attachments: Default::default(),
-                reactions: Default::default(),
-                receipt: Default::default(),
-                body_ranges: Default::default(),
-                send_failed: Default::default(),
-                edit: Default::default(),
-                edited: Default::default(),
-            },
-        );
+        app.restore_failed_sends();
+        app.messages
+            .get_mut(&channel_id)
+            .unwrap()
+            .state
+            .select(Some(0));
+        app.retry_send();
+        assert_eq!(app.command_error, None);
+        let sent = sent_messages.borrow();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].message.as_deref(), Some("Hello"));
+        assert_eq!(sent[0].arrived_at, 1);
+        let resent = app.storage.message(MessageId::new(channel_id, 1)).unwrap();
+        assert!(resent.forwarded);
+        assert_eq!(resent.send_failed, None);
+    }
 
-        let user = User {
-            name: "Tyler Durden".to_string(),
-            phone_number: "+0000000000".to_string(),
+    #[tokio::test]
+    async fn test_should_auto_download() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let pointer = AttachmentPointer {
+            size: Some(3 * 1024 * 1024),
+            ..Default::default()
         };
-        let (mut app, events) = App::try_new(
-            Config::with_user(user),
-            Box::new(signal_manager),
-            Box::new(storage),
-        )
-        .unwrap();
-        app.channels.state.select(Some(0));
+        assert!(app.should_auto_download(&pointer));
+
+        app.config.media.auto_download_max_mb = Some(2);
+        assert!(!app.should_auto_download(&pointer));
+        app.config.media.auto_download_max_mb = Some(3);
+        assert!(app.should_auto_download(&pointer));
+
+        app.config.media.auto_download_when_metered = false;
+        app.handle_event(Event::Metered(true)).unwrap();
+        assert!(!app.should_auto_download(&pointer));
+        app.handle_event(Event::Metered(false)).unwrap();
+        assert!(app.should_auto_download(&pointer));
+    }
 
-        (app, events, sent_messages)
+    #[test]
+    fn test_scale_down_image() {
+        let mut png = Vec::new();
+        image::RgbaImage::new(40, 20)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        assert_eq!(scale_down_image(png.clone(), 40), png);
+        let scaled = image::load_from_memory(&scale_down_image(png, 10)).unwrap();
+        assert_eq!((scaled.width(), scaled.height()), (10, 5));
+
+        let text = b"not an image".to_vec();
+        assert_eq!(scale_down_image(text.clone(), 10), text);
     }
 
-    #[tokio::test]
-    async fn test_send_input() {
-        let (mut app, mut events, sent_messages) = test_app();
-        let input = "Hello, World!";
-        for c in input.chars() {
-            app.get_input().put_char(c);
-        }
-        app.send_input(0);
+    #[test]
+    fn test_scale_down_jpeg() {
+        let mut jpeg = Vec::new();
+        image::RgbImage::new(20, 40)
+            .write_to(
+                &mut std::io::Cursor::new(&mut jpeg),
+                image::ImageFormat::Jpeg,
+            )
+            .unwrap();
 
-        assert_eq!(sent_messages.borrow().len(), 1);
-        let msg = sent_messages.borrow()[0].clone();
-        assert_eq!(msg.message.as_ref().unwrap(), input);
+        let scaled = scale_down_image(jpeg, 10);
+        assert_eq!(
+            image::guess_format(&scaled).unwrap(),
+            image::ImageFormat::Jpeg
+        );
+        let scaled = image::load_from_memory(&scaled).unwrap();
+        assert_eq!((scaled.width(), scaled.height()), (5, 10));
+    }
 
-        let channel_id = app.channels.items[0];
-        let channel = app.storage.channel(channel_id).unwrap();
-        assert_eq!(channel.unread_messages, 0);
+    #[tokio::test]
+    async fn test_reload_theme() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let mut theme = app.config.theme.clone();
+        theme.mode = ThemeMode::Light;
+        theme.preset = ThemePreset::Solarized;
+        app.reload_theme(theme.clone());
+        assert_eq!(app.theme, Theme::Light);
+        assert_eq!(app.palette, Palette::preset(ThemePreset::Solarized));
+
+        // an invalid theme is not applied
+        theme.colors.selected = Some("no color".to_string());
+        app.reload_theme(theme);
+        assert!(app.command_error.is_some());
+        assert_eq!(app.palette, Palette::preset(ThemePreset::Solarized));
+        assert_eq!(app.config.theme.colors.selected, None);
+    }
 
-        assert_eq!(app.get_input().data, "");
+    #[tokio::test]
+    async fn test_mention_all() {
+        let (mut app, _events, sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut channel = app.storage.channel(channel_id).unwrap().into_owned();
+        let group_data = channel.group_data.as_mut().unwrap();
+        group_data.members.extend([alice, bob]);
+        app.storage.store_channel(channel.clone());
 
-        match events.recv().await.unwrap() {
-            Event::SentTextResult { message_id, result } => {
-                assert_eq!(message_id.arrived_at, msg.arrived_at);
-                assert!(result.is_ok());
-            }
+        for c in "@all hi".chars() {
+            app.get_input().put_char(c);
         }
+        app.send_input(0);
+        let sent = sent_messages.borrow()[0].clone();
+        assert_eq!(sent.mentions().collect::<Vec<_>>(), [alice, bob]);
+
+        let (text, body_ranges) = channel.expand_mention_all("@all hi", alice).unwrap();
+        let mention_all = Message::new(alice, Some(text), body_ranges, 1, vec![]);
+        let mention = Message::new(
+            alice,
+            Some("\u{fffc} hi".to_string()),
+            [BodyRange {
+                start: 0,
+                end: 1,
+                value: AssociatedValue::MentionUuid(app.user_id),
+            }],
+            2,
+            vec![],
+        );
+        assert!(app.is_mentioned(channel_id, &mention_all));
+
+        app.on_command(Command::ToggleMentionAll).await.unwrap();
+        assert!(!app.is_mentioned(channel_id, &mention_all));
+        assert!(app.is_mentioned(channel_id, &mention));
     }
 
     #[tokio::test]
@@ -1831,6 +6525,521 @@ pub(crate) mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_send_input_with_preview() {
+        let (mut app, _events, sent_messages) = test_app();
+        app.config.send_preview = true;
+        for c in "Hello :smile:".chars() {
+            app.get_input().put_char(c);
+        }
+
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+        app.on_key(enter).await.unwrap();
+        assert!(sent_messages.borrow().is_empty());
+        let preview = app.send_preview().expect("no preview");
+        assert_eq!(preview.text, "Hello 😄");
+        assert!(!preview.editing);
+
+        // changing the input invalidates the preview
+        app.get_input().put_char('!');
+        assert!(app.send_preview().is_none());
+        app.on_key(enter).await.unwrap();
+        assert!(sent_messages.borrow().is_empty());
+
+        app.on_key(enter).await.unwrap();
+        assert_eq!(sent_messages.borrow().len(), 1);
+        assert!(app.send_preview().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_send_debounce() {
+        let (mut app, _events, sent_messages) = test_app();
+        app.config.send_debounce_ms = 60_000;
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+
+        app.get_input().put_char('a');
+        app.on_key(enter).await.unwrap();
+        assert_eq!(sent_messages.borrow().len(), 1);
+
+        app.get_input().put_char('b');
+        app.on_key(enter).await.unwrap();
+        app.on_command(Command::SendMessage).await.unwrap();
+        assert_eq!(sent_messages.borrow().len(), 1);
+        assert_eq!(app.get_input().data, "b");
+
+        app.last_sent_at = None;
+        app.on_command(Command::SendMessage).await.unwrap();
+        assert_eq!(sent_messages.borrow().len(), 2);
+        assert!(app.get_input().data.is_empty());
+    }
+
+    #[test]
+    fn test_highlight_new_messages() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let alice = Uuid::new_v4();
+        app.add_message_to_channel(0, Message::new(alice, None, [], 1, vec![]));
+        assert!(!app.is_highlighted(MessageId::new(channel_id, 1)));
+
+        app.config.highlight_new_messages_ms = 60_000;
+        app.add_message_to_channel(0, Message::new(alice, None, [], 2, vec![]));
+        app.add_message_to_channel(0, Message::new(app.user_id, None, [], 3, vec![]));
+        assert!(app.is_highlighted(MessageId::new(channel_id, 2)));
+        // own messages are not highlighted
+        assert!(!app.is_highlighted(MessageId::new(channel_id, 3)));
+
+        app.config.highlight_new_messages_ms = 0;
+        assert!(!app.is_highlighted(MessageId::new(channel_id, 2)));
+    }
+
+    #[tokio::test]
+    async fn test_contact_notes_and_birthdays() {
+        let (mut app, _events, _sent_messages) = test_app();
+        app.config.notifications = false;
+        let user_id = app.channels.items[0].user().unwrap();
+        let birthday: Birthday = "05-17".parse().unwrap();
+
+        app.on_command(Command::Birthday(Some(birthday)))
+            .await
+            .unwrap();
+        app.on_command(Command::Note("likes green tea".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(
+            app.storage.metadata().contact_notes[&user_id],
+            ContactNotes {
+                birthday: Some(birthday),
+                notes: Some("likes green tea".to_string()),
+            }
+        );
+
+        app.check_birthdays(NaiveDate::from_ymd_opt(2026, 5, 16).unwrap());
+        assert!(app.birthdays_today.is_empty());
+        app.check_birthdays(NaiveDate::from_ymd_opt(2026, 5, 17).unwrap());
+        assert!(app.birthdays_today.contains(&user_id));
+
+        // clearing everything removes the entry
+        app.on_command(Command::Birthday(None)).await.unwrap();
+        assert!(app.birthdays_today.is_empty());
+        app.on_command(Command::Note(String::new())).await.unwrap();
+        assert!(app.storage.metadata().contact_notes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_nick() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let user_id = channel_id.user().unwrap();
+
+        app.on_command(Command::Nick("Bob 🍕".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(app.name_by_id_cached(user_id), "Bob 🍕");
+        let channel = app.storage.channel(channel_id).unwrap();
+        assert_eq!(app.channel_name(&channel), "Bob 🍕");
+        assert_eq!(app.find_channel("bob 🍕").unwrap(), channel_id);
+        assert_eq!(
+            app.storage.metadata().contacts[&user_id]
+                .nickname
+                .as_deref(),
+            Some("Bob 🍕")
+        );
+        // the nickname is only shown, and never stored as the name of the channel
+        let name = app.resolved_name_by_id(user_id).await;
+        assert_ne!(name, "Bob 🍕");
+        app.ensure_contact_channel_exists(user_id, &name).await;
+        assert_eq!(app.storage.channel(channel_id).unwrap().name, name);
+
+        app.on_command(Command::Nick(String::new())).await.unwrap();
+        assert!(app.storage.metadata().contacts.is_empty());
+
+        // groups keep the nickname with the channel
+        let group_id = ChannelId::Group([1; 32]);
+        app.storage.store_channel(test_channel(group_id, "🎉🎉🎉"));
+        app.channels.items.push(group_id);
+        app.sort_channels();
+        app.channels.state.select(app.channel_idx(group_id));
+        app.on_command(Command::Nick("Party".to_string()))
+            .await
+            .unwrap();
+        let group = app.storage.channel(group_id).unwrap();
+        assert_eq!(group.nickname.as_deref(), Some("Party"));
+        assert_eq!(app.channel_name(&group), "Party");
+        assert_eq!(app.find_channel("party").unwrap(), group_id);
+        assert_eq!(app.find_channel("🎉🎉🎉").unwrap(), group_id);
+    }
+
+    #[tokio::test]
+    async fn test_send_input_while_offline() {
+        let (mut app, _events, sent_messages) = test_app();
+        app.set_connection_state(ConnectionState::Offline);
+
+        for c in "Hello, World!".chars() {
+            app.get_input().put_char(c);
+        }
+        app.send_input(0);
+        assert!(sent_messages.borrow().is_empty());
+        assert_eq!(app.queued_messages_len(), 1);
+        assert!(app.get_input().data.is_empty());
+
+        let channel_id = app.channels.items[0];
+        assert_eq!(app.sending_messages_len(channel_id), 1);
+
+        app.set_connection_state(ConnectionState::Connected);
+        assert_eq!(app.queued_messages_len(), 0);
+        assert_eq!(sent_messages.borrow().len(), 1);
+        // sent, but not yet confirmed
+        assert_eq!(app.sending_messages_len(channel_id), 1);
+        assert_eq!(
+            sent_messages.borrow()[0].message.as_deref(),
+            Some("Hello, World!")
+        );
+    }
+
+    #[test]
+    fn test_handle_receipt() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let sender_uuid = channel_id.user().unwrap();
+        for arrived_at in 1..=2 {
+            let message = Message::text(app.user_id, arrived_at, "hi".to_string());
+            app.add_message_to_channel(0, message);
+        }
+
+        let receipt = |app: &App, arrived_at| {
+            app.storage
+                .message(MessageId::new(channel_id, arrived_at))
+                .unwrap()
+                .receipt
+        };
+
+        app.handle_receipt(sender_uuid, Receipt::Read, vec![2, 3]);
+        assert_eq!(receipt(&app, 1), Receipt::Nothing);
+        assert_eq!(receipt(&app, 2), Receipt::Read);
+
+        // receipts never go back
+        app.handle_receipt(sender_uuid, Receipt::Delivered, vec![2]);
+        assert_eq!(receipt(&app, 2), Receipt::Read);
+    }
+
+    #[tokio::test]
+    async fn test_mute_command() {
+        let (mut app, _events, sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+
+        for c in "/mute 1h".chars() {
+            app.get_input().put_char(c);
+        }
+        app.on_key(enter).await.unwrap();
+        assert!(sent_messages.borrow().is_empty());
+        assert!(app.get_input().data.is_empty());
+        assert!(app.is_muted(channel_id));
+
+        for c in "/unmute".chars() {
+            app.get_input().put_char(c);
+        }
+        app.on_key(enter).await.unwrap();
+        assert!(!app.is_muted(channel_id));
+
+        // unknown command is kept in the input
+        for c in "/mutee".chars() {
+            app.get_input().put_char(c);
+        }
+        app.on_key(enter).await.unwrap();
+        assert!(app.command_error.is_some());
+        assert_eq!(app.get_input().data, "/mutee");
+    }
+
+    #[tokio::test]
+    async fn test_pinned_channels_stay_on_top() {
+        let (mut app, _events, _sent_messages) = test_app();
+        for name in ["a", "b"] {
+            let channel_id = ChannelId::User(Uuid::new_v4());
+            app.storage.store_channel(test_channel(channel_id, name));
+            app.channels.items.push(channel_id);
+        }
+        let [test, a, b] = app.channels.items[..] else {
+            panic!("expected 3 channels");
+        };
+
+        app.channels.state.select(Some(2));
+        app.on_command(Command::TogglePin).await.unwrap();
+        assert_eq!(app.channels.items, [b, test, a]);
+        assert_eq!(app.channels.state.selected(), Some(0));
+
+        // new activity moves an unpinned channel only right below the pinned ones
+        app.bubble_up_channel(2);
+        assert_eq!(app.channels.items, [b, a, test]);
+        assert_eq!(app.channels.state.selected(), Some(0));
+
+        app.channels.state.select(Some(2));
+        app.on_command(Command::TogglePin).await.unwrap();
+        assert_eq!(app.channels.items, [test, b, a]);
+        app.on_command(Command::TogglePin).await.unwrap();
+        assert_eq!(app.channels.items, [b, test, a]);
+        assert_eq!(app.channels.state.selected(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_sort_channels_on_interval() {
+        let (mut app, _events, _sent_messages) = test_app();
+        app.config.channel_sort_interval_secs = Some(60);
+        let channel_id = ChannelId::User(Uuid::new_v4());
+        app.storage.store_channel(test_channel(channel_id, "a"));
+        app.channels.items.push(channel_id);
+        let [test, a] = app.channels.items[..] else {
+            panic!("expected 2 channels");
+        };
+
+        // new activity does not change the order until the list is sorted
+        let message = Message {
+            from_id: app.user_id,
+            message: Some("Second message".to_string()),
+            arrived_at: 1,
+            quote: Default::default(),
+            attachments: Default::default(),
+            reactions: Default::default(),
+            receipt: Default::default(),
+            body_ranges: Default::default(),
+            send_failed: Default::default(),
+            edit: Default::default(),
+            edited: Default::default(),
+            forwarded: Default::default(),
+        };
+        app.add_message_to_channel(1, message);
+        assert_eq!(app.channels.items, [test, a]);
+        assert_eq!(app.channels.state.selected(), Some(0));
+
+        app.step_channel_sorting();
+        assert_eq!(app.channels.items, [test, a]);
+
+        app.on_command(Command::SortChannels).await.unwrap();
+        assert_eq!(app.channels.items, [a, test]);
+        assert_eq!(app.channels.state.selected(), Some(1));
+    }
+
+    #[test]
+    fn test_channel_list_sections() {
+        let (mut app, _events, _sent_messages) = test_app();
+        app.config.channel_list.sections = true;
+        app.config.channel_list.direct = ChannelOrder::Alphabetical;
+        app.config.channel_list.groups = ChannelOrder::UnreadFirst;
+        let mut add_channel = |name: &str, id: ChannelId, unread_messages: u32| {
+            app.storage.store_channel(Channel {
+                unread_messages,
+                ..test_channel(id, name)
+            });
+            app.channels.items.push(id);
+            id
+        };
+        let bob = add_channel("bob", ChannelId::User(Uuid::new_v4()), 0);
+        let alice = add_channel("alice", ChannelId::User(Uuid::new_v4()), 0);
+        let read_group = add_channel("read", ChannelId::Group([1; 32]), 0);
+        let unread_group = add_channel("unread", ChannelId::Group([2; 32]), 3);
+        let test = app.channels.items[0];
+        // direct channels are ordered by the names they are shown with
+        for (channel_id, name) in [(test, "zoe"), (bob, "bob"), (alice, "alice")] {
+            let names = app.names.get_mut();
+            names.set_display_name(channel_id.user().unwrap(), name.to_string());
+        }
+
+        app.sort_channels();
+        assert_eq!(
+            app.channels.items,
+            [alice, bob, test, unread_group, read_group]
+        );
+        assert_eq!(app.channel_section(read_group), ChannelSection::Groups);
+        assert_eq!(
+            (0..5)
+                .map(|idx| app.starts_section(idx))
+                .collect::<Vec<_>>(),
+            [true, false, false, true, false]
+        );
+
+        // the headers take a row each
+        assert_eq!(app.channel_at_row(0), Some(0));
+        assert_eq!(app.channel_at_row(1), Some(0));
+        assert_eq!(app.channel_at_row(3), Some(2));
+        assert_eq!(app.channel_at_row(4), Some(3));
+        assert_eq!(app.channel_at_row(5), Some(3));
+        assert_eq!(app.channel_at_row(7), None);
+    }
+
+    #[tokio::test]
+    async fn test_archived_channels_are_hidden() {
+        let (mut app, _events, _sent_messages) = test_app();
+        for name in ["a", "b"] {
+            let channel_id = ChannelId::User(Uuid::new_v4());
+            app.storage.store_channel(test_channel(channel_id, name));
+            app.channels.items.push(channel_id);
+        }
+        let [test, a, b] = app.channels.items[..] else {
+            panic!("expected 3 channels");
+        };
+
+        app.on_command(Command::ToggleArchive).await.unwrap();
+        assert_eq!(app.channels.items, [a, b, test]);
+        assert_eq!(app.visible_channels_len(), 2);
+        assert_eq!(app.channels.state.selected(), Some(0));
+
+        app.select_next_channel();
+        app.select_next_channel();
+        assert_eq!(app.channels.state.selected(), Some(1));
+
+        app.on_command(Command::ToggleArchivedChannels)
+            .await
+            .unwrap();
+        assert_eq!(app.visible_channels_len(), 3);
+        app.select_next_channel();
+        assert_eq!(app.channels.state.selected(), Some(2));
+
+        app.on_command(Command::ToggleArchivedChannels)
+            .await
+            .unwrap();
+        assert_eq!(app.channels.state.selected(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_burn_after_reading_channel() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+
+        app.on_command(Command::ToggleBurnAfterReading)
+            .await
+            .unwrap();
+        let message = Message::new(app.user_id, Some("burn me".into()), [], 1, vec![]);
+        app.add_message_to_channel(0, message);
+        assert_eq!(app.messages[&channel_id].items, [0, 1]);
+
+        // with a single channel, the selection wraps around to the same channel
+        app.select_next_channel();
+        assert_eq!(app.messages[&channel_id].items, [0]);
+        assert!(app.storage.message(MessageId::new(channel_id, 1)).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_scroll_messages() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        for arrived_at in 1..10 {
+            let message = Message::new(app.user_id, Some("msg".into()), [], arrived_at, vec![]);
+            app.add_message_to_channel(0, message);
+        }
+        app.messages.get_mut(&channel_id).unwrap().rendered.page_len = 4;
+        let offset = |app: &App| app.messages[&channel_id].rendered.offset;
+
+        let scroll = Command::Scroll(
+            Widget::Messages,
+            DirectionVertical::Up,
+            MoveAmountVisual::Screen,
+        );
+        app.on_command(scroll).await.unwrap();
+        assert_eq!(offset(&app), 4);
+
+        // the viewport stays on the same messages when scrolled up
+        let message = Message::new(app.user_id, Some("msg".into()), [], 10, vec![]);
+        app.add_message_to_channel(0, message);
+        assert_eq!(offset(&app), 5);
+
+        let scroll = Command::Scroll(
+            Widget::Messages,
+            DirectionVertical::Down,
+            MoveAmountVisual::HalfScreen,
+        );
+        app.on_command(scroll).await.unwrap();
+        assert_eq!(offset(&app), 3);
+
+        let scroll = Command::Scroll(
+            Widget::Messages,
+            DirectionVertical::Up,
+            MoveAmountVisual::End,
+        );
+        app.on_command(scroll).await.unwrap();
+        assert_eq!(offset(&app), 10);
+
+        let scroll = Command::Scroll(
+            Widget::Messages,
+            DirectionVertical::Down,
+            MoveAmountVisual::End,
+        );
+        app.on_command(scroll).await.unwrap();
+        assert_eq!(offset(&app), 0);
+
+        // the viewport sticks to the bottom
+        let message = Message::new(app.user_id, Some("msg".into()), [], 11, vec![]);
+        app.add_message_to_channel(0, message);
+        assert_eq!(offset(&app), 0);
+    }
+
+    #[tokio::test]
+    async fn test_mode_name() {
+        let (mut app, _events, _sent_messages) = test_app();
+        assert_eq!(app.mode_name(), "insert");
+
+        app.on_command(Command::ToggleMultiline).await.unwrap();
+        assert_eq!(app.mode_name(), "multi-line");
+
+        app.on_command(Command::SearchInputHistory).await.unwrap();
+        assert_eq!(app.mode_name(), "search");
+    }
+
+    #[tokio::test]
+    async fn test_question_mark_opens_help_only_with_selected_message() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let question_mark = KeyEvent::new(KeyCode::Char('?'), KeyModifiers::NONE);
+
+        app.on_key(question_mark).await.unwrap();
+        assert!(!app.is_help());
+        assert_eq!(app.get_input().data, "?");
+
+        app.get_input().take();
+        let channel_id = app.channels.items[0];
+        app.messages
+            .get_mut(&channel_id)
+            .unwrap()
+            .state
+            .select(Some(0));
+        app.on_key(question_mark).await.unwrap();
+        assert!(app.is_help());
+        app.on_key(question_mark).await.unwrap();
+        assert!(!app.is_help());
+    }
+
+    #[tokio::test]
+    async fn test_send_escaped_slash() {
+        let (mut app, _events, sent_messages) = test_app();
+        for c in "//shrug".chars() {
+            app.get_input().put_char(c);
+        }
+        app.on_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
+            .await
+            .unwrap();
+        assert_eq!(sent_messages.borrow().len(), 1);
+        assert_eq!(sent_messages.borrow()[0].message.as_deref(), Some("/shrug"));
+    }
+
+    #[tokio::test]
+    async fn test_send_input_remembers_recent_file() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().canonicalize().unwrap();
+        for c in format!("look file://{}", path.display()).chars() {
+            app.get_input().put_char(c);
+        }
+
+        app.send_input(0);
+
+        let recent_files = app.storage.metadata().into_owned().recent_files;
+        assert_eq!(
+            recent_files,
+            [RecentFile {
+                path,
+                kind: RecentFileKind::Sent
+            }]
+        );
+    }
+
     #[tokio::test]
     async fn test_add_reaction_with_emoji() {
         let (mut app, _events, _sent_messages) = test_app();
@@ -1910,6 +7119,67 @@ pub(crate) mod tests {
         assert!(reactions.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_react_with_reaction_picker() {
+        let (mut app, _events, _sent_messages) = test_app();
+
+        let channel_id = app.channels.items[0];
+        app.messages
+            .get_mut(&channel_id)
+            .unwrap()
+            .state
+            .select(Some(0));
+        let arrived_at = app.messages[&channel_id].items[0];
+        let reactions = |app: &App| {
+            app.storage
+                .message(MessageId::new(channel_id, arrived_at))
+                .unwrap()
+                .reactions
+                .clone()
+        };
+
+        app.on_command(Command::ToggleReactionPicker).await.unwrap();
+        assert!(app.reaction_picker.is_shown);
+        app.on_command(Command::ReactSelected).await.unwrap();
+        assert!(!app.reaction_picker.is_shown);
+        assert_eq!(reactions(&app), [(app.user_id, "❤️".to_string())]);
+
+        // selecting the own reaction removes it
+        app.messages
+            .get_mut(&channel_id)
+            .unwrap()
+            .state
+            .select(Some(0));
+        app.on_command(Command::ToggleReactionPicker).await.unwrap();
+        assert!(app.reaction_picker.selected().unwrap().is_own);
+        app.on_command(Command::ReactSelected).await.unwrap();
+        assert!(reactions(&app).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_quick_react_with_number_key() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        app.messages
+            .get_mut(&channel_id)
+            .unwrap()
+            .state
+            .select(Some(0));
+
+        app.on_key(KeyEvent::new(KeyCode::Char('1'), KeyModifiers::NONE))
+            .await
+            .unwrap();
+
+        assert!(app.get_input().data.is_empty());
+        let arrived_at = app.messages[&channel_id].items[0];
+        let reactions = &app
+            .storage
+            .message(MessageId::new(channel_id, arrived_at))
+            .unwrap()
+            .reactions;
+        assert_eq!(reactions[..], [(app.user_id, "👍".to_string())]);
+    }
+
     #[tokio::test]
     async fn test_add_invalid_reaction() {
         let (mut app, _events, _sent_messages) = test_app();
@@ -1935,6 +7205,41 @@ pub(crate) mod tests {
         assert!(reactions.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_group_requests() {
+        let signal_manager = SignalManagerMock::new();
+        let requests = GroupRequests {
+            is_admin: true,
+            link_approval: true,
+            join_requests: vec![GroupRequest {
+                user_id: Uuid::new_v4(),
+                added_by: None,
+                timestamp: 1,
+            }],
+            invites: Vec::new(),
+        };
+        signal_manager
+            .group_requests
+            .borrow_mut()
+            .insert(GroupMasterKeyBytes::default(), requests.clone());
+        let all_requests = signal_manager.group_requests.clone();
+        let (mut app, _events, _sent_messages) = test_app_with(signal_manager);
+
+        app.on_command(Command::Group).await.unwrap();
+        assert!(app.show_channel_info);
+        let info = app.channel_info.as_ref().unwrap();
+        assert_eq!(info.requests.as_ref(), Some(&requests));
+
+        // only admins see the requests
+        all_requests
+            .borrow_mut()
+            .values_mut()
+            .for_each(|requests| requests.is_admin = false);
+        app.channel_info = None;
+        app.step_channel_info().await;
+        assert_eq!(app.channel_info.as_ref().unwrap().requests, None);
+    }
+
     #[test]
     fn test_to_emoji() {
         assert_eq!(to_emoji("🚀"), Some("🚀"));
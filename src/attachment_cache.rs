@@ -0,0 +1,408 @@
+//! Disk usage of the downloaded attachments and deleting them beyond a size limit
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::Context;
+use presage::proto::attachment_pointer::AttachmentIdentifier;
+use presage::proto::AttachmentPointer;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::redact::Sensitive;
+use crate::util::utc_now_timestamp_msec;
+
+/// How long Signal keeps the attachments after uploading them
+const RETENTION_MSEC: u64 = 30 * 24 * 60 * 60 * 1000;
+
+/// Index of the downloaded attachments
+///
+/// Tracks the size of each attachment and when it was downloaded or opened the last time, so
+/// that the least recently used attachments can be deleted when the size limit is exceeded. The
+/// pointers of the attachments are kept, so that deleted attachments can be downloaded again.
+#[derive(Debug, Default)]
+pub struct AttachmentCache {
+    /// File the index is saved to; if unset, the index is kept in memory only
+    path: Option<PathBuf>,
+    entries: BTreeMap<PathBuf, CacheEntry>,
+    is_dirty: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    size: u64,
+    /// When the attachment was downloaded or opened the last time
+    used_at: u64,
+    /// Whether the local copy was deleted
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    deleted: bool,
+    /// Pointer for downloading the attachment again; unknown for attachments downloaded before
+    /// the index existed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pointer: Option<StoredPointer>,
+}
+
+/// Fields of an [`AttachmentPointer`] needed for downloading the attachment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct StoredPointer {
+    cdn_number: Option<u32>,
+    cdn_id: Option<u64>,
+    cdn_key: Option<String>,
+    key: Option<Vec<u8>>,
+    digest: Option<Vec<u8>>,
+    size: Option<u32>,
+    content_type: Option<String>,
+    file_name: Option<String>,
+    upload_timestamp: Option<u64>,
+}
+
+impl From<&AttachmentPointer> for StoredPointer {
+    fn from(pointer: &AttachmentPointer) -> Self {
+        let (cdn_id, cdn_key) = match pointer.attachment_identifier.clone() {
+            Some(AttachmentIdentifier::CdnId(id)) => (Some(id), None),
+            Some(AttachmentIdentifier::CdnKey(key)) => (None, Some(key)),
+            None => (None, None),
+        };
+        Self {
+            cdn_number: pointer.cdn_number,
+            cdn_id,
+            cdn_key,
+            key: pointer.key.clone(),
+            digest: pointer.digest.clone(),
+            size: pointer.size,
+            content_type: pointer.content_type.clone(),
+            file_name: pointer.file_name.clone(),
+            upload_timestamp: pointer.upload_timestamp,
+        }
+    }
+}
+
+impl From<StoredPointer> for AttachmentPointer {
+    fn from(pointer: StoredPointer) -> Self {
+        let attachment_identifier = match (pointer.cdn_id, pointer.cdn_key) {
+            (Some(id), _) => Some(AttachmentIdentifier::CdnId(id)),
+            (None, Some(key)) => Some(AttachmentIdentifier::CdnKey(key)),
+            (None, None) => None,
+        };
+        Self {
+            attachment_identifier,
+            cdn_number: pointer.cdn_number,
+            key: pointer.key,
+            digest: pointer.digest,
+            size: pointer.size,
+            content_type: pointer.content_type,
+            file_name: pointer.file_name,
+            upload_timestamp: pointer.upload_timestamp,
+            ..Default::default()
+        }
+    }
+}
+
+/// Disk usage of the downloaded attachments
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CacheUsage {
+    /// Total size of the local copies in bytes
+    pub bytes: u64,
+    /// Number of local copies
+    pub files: usize,
+    /// Number of deleted or not yet downloaded attachments, which are downloaded when opened
+    pub deleted: usize,
+}
+
+impl AttachmentCache {
+    /// Loads the index saved at `path` and adds the attachments in `files_dir` missing in it
+    pub fn load(path: PathBuf, files_dir: &Path) -> Self {
+        let entries: BTreeMap<PathBuf, CacheEntry> = if path.exists() {
+            File::open(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|f| Ok(serde_json::from_reader(BufReader::new(f))?))
+                .unwrap_or_else(|error| {
+                    warn!(%error, "failed to load attachment index");
+                    Default::default()
+                })
+        } else {
+            Default::default()
+        };
+        let mut cache = Self {
+            path: Some(path),
+            entries,
+            is_dirty: false,
+        };
+        cache.scan(files_dir);
+        cache
+    }
+
+    /// Adds the files in the directory and its subdirectories, which are not indexed yet
+    fn scan(&mut self, dir: &Path) {
+        let Ok(dir_entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in dir_entries.flatten() {
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                self.scan(&path);
+            } else if !self.entries.contains_key(&path) {
+                let used_at = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |since| since.as_millis() as u64);
+                self.entries.insert(
+                    path,
+                    CacheEntry {
+                        size: metadata.len(),
+                        used_at,
+                        deleted: false,
+                        pointer: None,
+                    },
+                );
+                self.is_dirty = true;
+            }
+        }
+    }
+
+    /// Adds a downloaded attachment
+    pub(crate) fn insert(&mut self, path: PathBuf, size: u64, pointer: &AttachmentPointer) {
+        let entry = CacheEntry {
+            size,
+            used_at: utc_now_timestamp_msec(),
+            deleted: false,
+            pointer: Some(pointer.into()),
+        };
+        self.entries.insert(path, entry);
+        self.is_dirty = true;
+    }
+
+    /// Adds an attachment which was not downloaded yet, for downloading it when it is opened
+    pub(crate) fn insert_pending(&mut self, path: PathBuf, size: u64, pointer: &AttachmentPointer) {
+        let entry = CacheEntry {
+            size,
+            used_at: utc_now_timestamp_msec(),
+            deleted: true,
+            pointer: Some(pointer.into()),
+        };
+        self.entries.insert(path, entry);
+        self.is_dirty = true;
+    }
+
+    /// Marks the attachment as used now, e.g. when it is opened
+    pub(crate) fn touch(&mut self, path: &Path) {
+        if let Some(entry) = self.entries.get_mut(path) {
+            entry.used_at = utc_now_timestamp_msec();
+            self.is_dirty = true;
+        }
+    }
+
+    /// Pointer for downloading the deleted attachment again
+    pub(crate) fn pointer(&self, path: &Path) -> Option<AttachmentPointer> {
+        let entry = self.entries.get(path)?;
+        Some(entry.pointer.clone()?.into())
+    }
+
+    pub(crate) fn usage(&self) -> CacheUsage {
+        let mut usage = CacheUsage::default();
+        for entry in self.entries.values() {
+            if entry.deleted {
+                usage.deleted += 1;
+            } else {
+                usage.bytes += entry.size;
+                usage.files += 1;
+            }
+        }
+        usage
+    }
+
+    /// Deletes the least recently used attachments until their total size is within the limit
+    ///
+    /// Attachments deleted elsewhere, e.g. in the attachment gallery, are marked as deleted
+    /// first. Returns the number of attachments deleted now.
+    pub(crate) fn shrink_to(&mut self, max_bytes: u64) -> usize {
+        let is_dirty = &mut self.is_dirty;
+        self.entries.retain(|path, entry| {
+            if !entry.deleted && !path.exists() {
+                entry.deleted = true;
+                *is_dirty = true;
+            }
+            // without pointer, a deleted attachment cannot be downloaded again
+            !entry.deleted || entry.pointer.is_some()
+        });
+        let mut usage = self.usage().bytes;
+        if usage <= max_bytes {
+            return 0;
+        }
+        let mut by_use: Vec<_> = self
+            .entries
+            .iter_mut()
+            .filter(|(_, entry)| !entry.deleted)
+            .collect();
+        by_use.sort_unstable_by_key(|(_, entry)| entry.used_at);
+        let mut count = 0;
+        for (path, entry) in by_use {
+            if usage <= max_bytes {
+                break;
+            }
+            if let Err(error) = std::fs::remove_file(path) {
+                if error.kind() != std::io::ErrorKind::NotFound {
+                    warn!(%error, path =% Sensitive(path.display()), "failed to delete attachment");
+                    continue;
+                }
+            }
+            entry.deleted = true;
+            usage = usage.saturating_sub(entry.size);
+            count += 1;
+        }
+        if count > 0 {
+            info!(count, usage, "deleted least recently used attachments");
+            self.is_dirty = true;
+        }
+        count
+    }
+
+    /// Forgets the pointers of the attachments which are no longer kept by Signal
+    ///
+    /// Attachments without local copy are removed, since they cannot be downloaded anymore.
+    /// Returns the number of removed attachments.
+    pub(crate) fn forget_expired(&mut self, now: u64) -> usize {
+        let mut count = 0;
+        let is_dirty = &mut self.is_dirty;
+        self.entries.retain(|_, entry| {
+            let uploaded_at = entry
+                .pointer
+                .as_ref()
+                .and_then(|pointer| pointer.upload_timestamp);
+            if !uploaded_at.is_some_and(|at| at.saturating_add(RETENTION_MSEC) < now) {
+                return true;
+            }
+            entry.pointer = None;
+            *is_dirty = true;
+            if entry.deleted {
+                count += 1;
+            }
+            !entry.deleted
+        });
+        if count > 0 {
+            info!(count, "forgot expired attachments");
+        }
+        count
+    }
+
+    /// Replaces the deleted attachment with the downloaded copy
+    pub(crate) fn restore(&mut self, path: &Path, downloaded: &Path) -> std::io::Result<()> {
+        if downloaded != path {
+            std::fs::rename(downloaded, path)?;
+        }
+        if let Some(entry) = self.entries.get_mut(path) {
+            entry.deleted = false;
+            entry.used_at = utc_now_timestamp_msec();
+        }
+        self.entries.remove(downloaded);
+        self.is_dirty = true;
+        Ok(())
+    }
+
+    pub fn save(&mut self) -> anyhow::Result<()> {
+        let Some(path) = self.path.as_ref().filter(|_| self.is_dirty) else {
+            return Ok(());
+        };
+        let file = File::create(path).context("failed to save attachment index")?;
+        serde_json::to_writer(BufWriter::new(file), &self.entries)
+            .context("failed to save attachment index")?;
+        self.is_dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pointer(cdn_key: &str) -> AttachmentPointer {
+        AttachmentPointer {
+            attachment_identifier: Some(AttachmentIdentifier::CdnKey(cdn_key.to_string())),
+            cdn_number: Some(3),
+            digest: Some(vec![1, 2, 3]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_shrink_to_deletes_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = AttachmentCache::default();
+        let paths: Vec<_> = ["a.jpg", "b.jpg", "c.jpg"]
+            .into_iter()
+            .map(|name| dir.path().join(name))
+            .collect();
+        for path in &paths {
+            std::fs::write(path, [0; 100]).unwrap();
+            cache.insert(path.clone(), 100, &pointer("key"));
+        }
+        // a.jpg was opened after b.jpg was downloaded
+        cache.entries.get_mut(&paths[0]).unwrap().used_at = u64::MAX;
+        cache.entries.get_mut(&paths[1]).unwrap().used_at = 0;
+
+        assert_eq!(cache.shrink_to(300), 0);
+        assert_eq!(cache.shrink_to(150), 2);
+        assert!(paths[0].exists());
+        assert!(!paths[1].exists());
+        assert!(!paths[2].exists());
+        assert_eq!(
+            cache.usage(),
+            CacheUsage {
+                bytes: 100,
+                files: 1,
+                deleted: 2,
+            }
+        );
+        assert_eq!(cache.pointer(&paths[1]), Some(pointer("key")));
+
+        // restored from a download under another name
+        let downloaded = dir.path().join("b.1.jpg");
+        std::fs::write(&downloaded, [0; 100]).unwrap();
+        cache.restore(&paths[1], &downloaded).unwrap();
+        assert!(paths[1].exists());
+        assert_eq!(cache.usage().files, 2);
+    }
+
+    #[test]
+    fn test_forget_expired() {
+        let mut cache = AttachmentCache::default();
+        let uploaded = |upload_timestamp| AttachmentPointer {
+            upload_timestamp: Some(upload_timestamp),
+            ..pointer("key")
+        };
+        let now = RETENTION_MSEC + 1000;
+        cache.insert("kept.jpg".into(), 100, &uploaded(0));
+        cache.insert_pending("expired.jpg".into(), 100, &uploaded(0));
+        cache.insert_pending("pending.jpg".into(), 100, &uploaded(1000));
+
+        assert_eq!(cache.forget_expired(now), 1);
+        assert_eq!(cache.pointer(Path::new("kept.jpg")), None);
+        assert!(!cache.entries.contains_key(Path::new("expired.jpg")));
+        assert!(cache.pointer(Path::new("pending.jpg")).is_some());
+        assert_eq!(cache.usage().files, 1);
+        assert_eq!(cache.forget_expired(now), 0);
+    }
+
+    #[test]
+    fn test_load_adds_unindexed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let files_dir = dir.path().join("files");
+        std::fs::create_dir_all(files_dir.join("2024-05-17")).unwrap();
+        std::fs::write(files_dir.join("2024-05-17/photo.jpg"), [0; 42]).unwrap();
+
+        let index_path = dir.path().join("attachments.json");
+        let mut cache = AttachmentCache::load(index_path.clone(), &files_dir);
+        assert_eq!(cache.usage().bytes, 42);
+        cache.save().unwrap();
+
+        let cache = AttachmentCache::load(index_path, &files_dir);
+        assert_eq!(cache.usage().files, 1);
+    }
+}
@@ -0,0 +1,425 @@
+use std::path::{Path, PathBuf};
+
+use ratatui::widgets::ListState;
+use uuid::Uuid;
+
+use crate::data::ChannelId;
+use crate::input::Input;
+use crate::signal::Attachment;
+use crate::storage::Storage;
+use crate::util::expand_home;
+
+/// Number of the newest messages of a channel whose attachments are listed
+pub(crate) const GALLERY_MESSAGES_LIMIT: usize = 1000;
+
+/// Popup listing the attachments sent and received in a channel
+#[derive(Default)]
+pub(crate) struct AttachmentGallery {
+    pub is_shown: bool,
+    pub state: ListState,
+    pub filter: AttachmentFilter,
+    /// Input of the path to save the selected attachment to, while saving
+    pub save_as: Option<Input>,
+    /// Existing file the save-as input points to, which is overwritten by saving again
+    pub overwrite: Option<PathBuf>,
+    /// Result of the last action, e.g. the path the attachment was saved to
+    pub status: Option<String>,
+    /// Attachment downloaded again for opening it, which is opened when the download finished
+    pub opening: Option<PathBuf>,
+    /// Whether deleting the selected attachment was requested once; the second request deletes it
+    delete_requested: bool,
+    /// Attachments of the newest messages of the channel, newest first
+    items: Vec<GalleryItem>,
+    filtered_index: Vec<usize /* index into items */>,
+}
+
+pub(crate) struct GalleryItem {
+    pub attachment: Attachment,
+    pub from_id: Uuid,
+    pub arrived_at: u64,
+    /// Whether the local copy still exists
+    pub exists: bool,
+}
+
+/// Type of attachments shown in the gallery
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, strum_macros::Display)]
+pub(crate) enum AttachmentFilter {
+    #[default]
+    All,
+    Images,
+    Audio,
+    Files,
+}
+
+impl AttachmentFilter {
+    fn matches(self, content_type: &str) -> bool {
+        let is_image = content_type.starts_with("image/");
+        let is_audio = content_type.starts_with("audio/");
+        match self {
+            Self::All => true,
+            Self::Images => is_image,
+            Self::Audio => is_audio,
+            Self::Files => !is_image && !is_audio,
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            Self::All => Self::Images,
+            Self::Images => Self::Audio,
+            Self::Audio => Self::Files,
+            Self::Files => Self::All,
+        }
+    }
+}
+
+impl AttachmentGallery {
+    /// Shows the popup with the attachments of the channel
+    pub fn open(&mut self, storage: &dyn Storage, channel_id: ChannelId) {
+        self.items = storage
+            .messages_before(channel_id, u64::MAX, GALLERY_MESSAGES_LIMIT)
+            .into_iter()
+            .flat_map(|message| {
+                let from_id = message.from_id;
+                let arrived_at = message.arrived_at;
+                message
+                    .attachments
+                    .clone()
+                    .into_iter()
+                    .map(move |attachment| GalleryItem {
+                        exists: attachment.filename.exists(),
+                        attachment,
+                        from_id,
+                        arrived_at,
+                    })
+            })
+            .collect();
+        self.items.reverse();
+        self.filter = Default::default();
+        self.save_as = None;
+        self.overwrite = None;
+        self.status = None;
+        self.opening = None;
+        self.apply_filter();
+        self.is_shown = true;
+    }
+
+    pub fn close(&mut self) {
+        self.is_shown = false;
+        self.save_as = None;
+        self.overwrite = None;
+        self.delete_requested = false;
+        self.opening = None;
+        self.items.clear();
+        self.filtered_index.clear();
+    }
+
+    /// Shows the next type of attachments
+    pub fn cycle_filter(&mut self) {
+        self.filter = self.filter.next();
+        self.apply_filter();
+    }
+
+    fn apply_filter(&mut self) {
+        self.filtered_index = self
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| self.filter.matches(&item.attachment.content_type))
+            .map(|(idx, _)| idx)
+            .collect();
+        self.state = Default::default();
+        self.delete_requested = false;
+        if !self.filtered_index.is_empty() {
+            self.state.select(Some(0));
+        }
+    }
+
+    pub fn filtered_items(&self) -> impl Iterator<Item = &GalleryItem> {
+        self.filtered_index.iter().map(|&idx| &self.items[idx])
+    }
+
+    pub fn prev(&mut self) {
+        let selected = self
+            .state
+            .selected()
+            .map(|idx| idx.saturating_sub(1))
+            .unwrap_or(0);
+        self.state.select(Some(selected));
+        self.delete_requested = false;
+    }
+
+    pub fn next(&mut self) {
+        let selected = self
+            .state
+            .selected()
+            .map(|idx| (idx + 1).min(self.filtered_index.len().saturating_sub(1)))
+            .unwrap_or(0);
+        self.state.select(Some(selected));
+        self.delete_requested = false;
+    }
+
+    pub fn selected(&self) -> Option<&GalleryItem> {
+        let idx = *self.filtered_index.get(self.state.selected()?)?;
+        self.items.get(idx)
+    }
+
+    /// Shows the input for saving the selected attachment, prefilled with the downloads directory
+    pub fn start_save_as(&mut self) {
+        let Some(item) = self.selected().filter(|item| item.exists) else {
+            return;
+        };
+        let dir = dirs::download_dir()
+            .or_else(dirs::home_dir)
+            .unwrap_or_default();
+        let path = match item.attachment.filename.file_name() {
+            Some(name) => dir.join(name),
+            None => dir,
+        };
+        let mut input = Input::default();
+        path.display()
+            .to_string()
+            .chars()
+            .for_each(|c| input.put_char(c));
+        self.save_as = Some(input);
+        self.overwrite = None;
+        self.delete_requested = false;
+    }
+
+    pub fn cancel_save_as(&mut self) {
+        self.save_as = None;
+        self.overwrite = None;
+    }
+
+    /// Marks the local copy of the attachment as existing again after downloading it
+    pub fn restore(&mut self, path: &Path) {
+        for item in &mut self.items {
+            if item.attachment.filename == path {
+                item.exists = true;
+            }
+        }
+    }
+
+    /// Copies the selected attachment to the path in the save-as input
+    ///
+    /// If the path is a directory, the attachment is saved in it under its file name. An existing
+    /// file is only overwritten when saving to it again, and never by the attachment itself.
+    pub fn save_selected(&mut self) {
+        let Some(input) = self.save_as.take() else {
+            return;
+        };
+        let Some(source) = self.selected().map(|item| item.attachment.filename.clone()) else {
+            return;
+        };
+        let target = expand_home(input.data.trim());
+        let target = match source.file_name() {
+            Some(name) if target.is_dir() => target.join(name),
+            _ => target,
+        };
+        let overwrite = self.overwrite.take();
+        if target.exists() {
+            if is_same_file(&source, &target) {
+                self.status = Some("the attachment is already saved there".to_string());
+                return;
+            }
+            if overwrite.as_ref() != Some(&target) {
+                self.overwrite = Some(target);
+                self.save_as = Some(input);
+                return;
+            }
+        }
+        self.status = Some(match std::fs::copy(&source, &target) {
+            Ok(_) => format!("saved to {}", target.display()),
+            Err(error) => format!("failed to save: {error}"),
+        });
+    }
+
+    /// Deletes the local copy of the selected attachment, when requested the second time in a row
+    ///
+    /// The attachment stays listed, but can no longer be opened or saved.
+    pub fn delete_selected(&mut self) {
+        let Some(&idx) = self
+            .state
+            .selected()
+            .and_then(|idx| self.filtered_index.get(idx))
+        else {
+            return;
+        };
+        let item = &mut self.items[idx];
+        if !item.exists {
+            return;
+        }
+        if !self.delete_requested {
+            self.delete_requested = true;
+            let name = item.attachment.filename.file_name().unwrap_or_default();
+            self.status = Some(format!(
+                "press d again to delete {}",
+                name.to_string_lossy()
+            ));
+            return;
+        }
+        self.delete_requested = false;
+        self.status = Some(match std::fs::remove_file(&item.attachment.filename) {
+            Ok(()) => {
+                item.exists = false;
+                format!("deleted {}", item.attachment.filename.display())
+            }
+            Err(error) => format!("failed to delete: {error}"),
+        });
+    }
+}
+
+/// Whether both paths refer to the same file, e.g. via a link
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Size in bytes in the largest binary unit, e.g. `1.5 MiB`
+pub(crate) fn display_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next_unit in &UNITS[1..] {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next_unit;
+    }
+    format!("{size:.1} {unit}")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::tests::test_app;
+    use crate::data::Message;
+
+    use super::*;
+
+    fn attachment(dir: &Path, name: &str, content_type: &str) -> Attachment {
+        let filename = dir.join(name);
+        std::fs::write(&filename, name).unwrap();
+        Attachment {
+            id: name.to_string(),
+            content_type: content_type.to_string(),
+            filename,
+            size: name.len() as u32,
+        }
+    }
+
+    #[test]
+    fn test_display_size() {
+        assert_eq!(display_size(512), "512 B");
+        assert_eq!(display_size(1536), "1.5 KiB");
+        assert_eq!(display_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+
+    #[test]
+    fn test_filter_save_and_delete() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let dir = tempfile::tempdir().unwrap();
+        let channel_id = app.channels.items[0];
+        let attachments = [
+            attachment(dir.path(), "photo.jpg", "image/jpeg"),
+            attachment(dir.path(), "voice.m4a", "audio/aac"),
+            attachment(dir.path(), "notes.pdf", "application/pdf"),
+        ];
+        for (timestamp, attachment) in attachments.into_iter().enumerate() {
+            let arrived_at = timestamp as u64 + 1;
+            let message = Message::new(app.user_id, None, [], arrived_at, vec![attachment]);
+            app.storage.store_message(channel_id, message);
+        }
+
+        let mut gallery = AttachmentGallery::default();
+        gallery.open(&*app.storage, channel_id);
+        let names = |gallery: &AttachmentGallery| -> Vec<String> {
+            gallery
+                .filtered_items()
+                .map(|item| item.attachment.id.clone())
+                .collect()
+        };
+        assert_eq!(names(&gallery), ["notes.pdf", "voice.m4a", "photo.jpg"]);
+        gallery.cycle_filter();
+        assert_eq!(gallery.filter, AttachmentFilter::Images);
+        assert_eq!(names(&gallery), ["photo.jpg"]);
+        gallery.cycle_filter();
+        gallery.cycle_filter();
+        assert_eq!(names(&gallery), ["notes.pdf"]);
+
+        // saving into a directory keeps the file name
+        let target = tempfile::tempdir().unwrap();
+        gallery.start_save_as();
+        let input = gallery.save_as.as_mut().unwrap();
+        input.take();
+        target
+            .path()
+            .display()
+            .to_string()
+            .chars()
+            .for_each(|c| input.put_char(c));
+        gallery.save_selected();
+        assert!(gallery.save_as.is_none());
+        assert!(target.path().join("notes.pdf").exists());
+
+        // an existing file is only overwritten when saving again
+        std::fs::write(target.path().join("notes.pdf"), "old").unwrap();
+        gallery.start_save_as();
+        let input = gallery.save_as.as_mut().unwrap();
+        input.take();
+        target
+            .path()
+            .display()
+            .to_string()
+            .chars()
+            .for_each(|c| input.put_char(c));
+        gallery.save_selected();
+        assert!(gallery.save_as.is_some());
+        assert_eq!(gallery.overwrite, Some(target.path().join("notes.pdf")));
+        assert_eq!(
+            std::fs::read_to_string(target.path().join("notes.pdf")).unwrap(),
+            "old"
+        );
+        gallery.save_selected();
+        assert!(gallery.save_as.is_none());
+        assert_eq!(
+            std::fs::read_to_string(target.path().join("notes.pdf")).unwrap(),
+            "notes.pdf"
+        );
+
+        // never over the attachment itself
+        gallery.start_save_as();
+        let input = gallery.save_as.as_mut().unwrap();
+        input.take();
+        dir.path()
+            .display()
+            .to_string()
+            .chars()
+            .for_each(|c| input.put_char(c));
+        gallery.save_selected();
+        assert_eq!(
+            gallery.status.as_deref(),
+            Some("the attachment is already saved there")
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("notes.pdf")).unwrap(),
+            "notes.pdf"
+        );
+
+        // deleting needs to be confirmed
+        gallery.delete_selected();
+        assert!(gallery.selected().unwrap().exists);
+        assert_eq!(
+            gallery.status.as_deref(),
+            Some("press d again to delete notes.pdf")
+        );
+        gallery.delete_selected();
+        assert!(!gallery.selected().unwrap().exists);
+        assert!(!dir.path().join("notes.pdf").exists());
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use ratatui::widgets::ListState;
+use tracing::warn;
+
+use crate::input::Input;
+use crate::redact::Sensitive;
+
+/// Popup for browsing directories and picking files to attach
+#[derive(Default)]
+pub(crate) struct AttachmentPicker {
+    pub is_shown: bool,
+    pub input: Input,
+    pub state: ListState,
+    pub dir: PathBuf,
+    items: Vec<ItemData>,
+    filtered_index: Vec<usize /* index into items */>,
+    marked: BTreeSet<PathBuf>,
+}
+
+pub(crate) struct ItemData {
+    pub path: PathBuf,
+    pub name: String,
+    pub is_dir: bool,
+}
+
+impl AttachmentPicker {
+    /// Shows the popup in the last visited directory, or in the current directory on first use
+    pub fn open(&mut self) {
+        let dir = if self.dir.as_os_str().is_empty() {
+            std::env::current_dir()
+                .ok()
+                .or_else(dirs::home_dir)
+                .unwrap_or_else(|| PathBuf::from("/"))
+        } else {
+            self.dir.clone()
+        };
+        self.marked.clear();
+        self.change_dir(dir);
+        self.is_shown = true;
+    }
+
+    pub fn close(&mut self) {
+        self.is_shown = false;
+        self.marked.clear();
+        self.input.take();
+    }
+
+    fn change_dir(&mut self, dir: PathBuf) {
+        self.input.take();
+        self.state = Default::default();
+        self.items.clear();
+
+        if let Some(parent) = dir.parent() {
+            self.items.push(ItemData {
+                path: parent.to_path_buf(),
+                name: "..".to_string(),
+                is_dir: true,
+            });
+        }
+        match std::fs::read_dir(&dir) {
+            Ok(entries) => {
+                let mut items: Vec<_> = entries
+                    .filter_map(Result::ok)
+                    .map(|entry| ItemData {
+                        name: entry.file_name().to_string_lossy().into_owned(),
+                        is_dir: entry.path().is_dir(),
+                        path: entry.path(),
+                    })
+                    .collect();
+                items.sort_unstable_by(|a, b| {
+                    (!a.is_dir, a.name.to_lowercase()).cmp(&(!b.is_dir, b.name.to_lowercase()))
+                });
+                self.items.extend(items);
+            }
+            Err(error) => {
+                warn!(%error, dir = %Sensitive(dir.display()), "failed to read directory")
+            }
+        }
+        self.dir = dir;
+        self.filtered_index.clear();
+    }
+
+    pub fn prev(&mut self) {
+        let selected = self
+            .state
+            .selected()
+            .map(|idx| idx.saturating_sub(1))
+            .unwrap_or(0);
+        self.state.select(Some(selected));
+    }
+
+    pub fn next(&mut self) {
+        let selected = self.state.selected().map(|idx| idx + 1).unwrap_or(0);
+        self.state.select(Some(selected));
+    }
+
+    /// Hidden files are only shown when the filter starts with a dot
+    fn filter_by_input(&mut self) {
+        let filter = self.input.data.to_lowercase();
+        let show_hidden = filter.starts_with('.');
+        let index = self.items.iter().enumerate().filter_map(|(idx, item)| {
+            let is_hidden = item.name.starts_with('.') && item.name != "..";
+            if (!is_hidden || show_hidden) && item.name.to_lowercase().contains(&filter) {
+                Some(idx)
+            } else {
+                None
+            }
+        });
+        self.filtered_index.clear();
+        self.filtered_index.extend(index);
+    }
+
+    pub fn filtered_names(&mut self) -> impl Iterator<Item = String> + '_ {
+        self.filter_by_input();
+        self.filtered_index.iter().map(|&idx| {
+            let item = &self.items[idx];
+            let mark = if self.marked.contains(&item.path) {
+                "* "
+            } else {
+                "  "
+            };
+            let suffix = if item.is_dir { "/" } else { "" };
+            format!("{mark}{}{suffix}", item.name)
+        })
+    }
+
+    fn selected_item(&self) -> Option<&ItemData> {
+        let idx = self.state.selected()?;
+        let item_idx = *self.filtered_index.get(idx)?;
+        self.items.get(item_idx)
+    }
+
+    /// Marks or unmarks the selected file for attaching
+    pub fn toggle_mark(&mut self) {
+        let Some(item) = self.selected_item() else {
+            return;
+        };
+        if item.is_dir {
+            return;
+        }
+        let path = item.path.clone();
+        if !self.marked.remove(&path) {
+            self.marked.insert(path);
+        }
+        self.next();
+    }
+
+    /// Enters the selected directory, or returns the files to attach
+    ///
+    /// The files to attach are the marked ones, or the selected one if no file is marked. The
+    /// popup is closed after the files are picked.
+    pub fn pick(&mut self) -> Option<Vec<PathBuf>> {
+        if let Some(item) = self.selected_item().filter(|item| item.is_dir) {
+            let dir = item.path.clone();
+            self.change_dir(dir);
+            return None;
+        }
+        let paths = if self.marked.is_empty() {
+            vec![self.selected_item()?.path.clone()]
+        } else {
+            std::mem::take(&mut self.marked).into_iter().collect()
+        };
+        self.close();
+        Some(paths)
+    }
+
+    pub fn marked_count(&self) -> usize {
+        self.marked.len()
+    }
+}
+
+/// Formats the path as an attachment in the input box
+///
+/// Returns `None` if the path cannot be expressed as an attachment, i.e. contains whitespace.
+pub(crate) fn attachment_input(path: &Path) -> Option<String> {
+    let path = path.to_str()?;
+    if path.chars().any(char::is_whitespace) {
+        warn!(path = %Sensitive(path), "paths containing whitespace cannot be attached");
+        return None;
+    }
+    Some(format!("file://{path}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("subdir")).unwrap();
+        std::fs::write(dir.path().join("a.txt"), "a").unwrap();
+        std::fs::write(dir.path().join("b.txt"), "b").unwrap();
+        std::fs::write(dir.path().join(".hidden"), "").unwrap();
+
+        let mut picker = AttachmentPicker {
+            dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        picker.open();
+        let names: Vec<_> = picker.filtered_names().collect();
+        assert_eq!(names, ["  ../", "  subdir/", "  a.txt", "  b.txt"]);
+
+        // mark both files
+        picker.state.select(Some(2));
+        picker.toggle_mark();
+        picker.toggle_mark();
+        assert_eq!(picker.marked_count(), 2);
+
+        let paths = picker.pick().unwrap();
+        assert_eq!(paths, [dir.path().join("a.txt"), dir.path().join("b.txt")]);
+        assert!(!picker.is_shown);
+    }
+
+    #[test]
+    fn test_enter_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("subdir")).unwrap();
+        std::fs::write(dir.path().join("subdir/c.txt"), "c").unwrap();
+
+        let mut picker = AttachmentPicker {
+            dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        picker.open();
+        for c in "sub".chars() {
+            picker.input.put_char(c);
+        }
+        assert_eq!(picker.filtered_names().count(), 1);
+        picker.state.select(Some(0));
+        assert_eq!(picker.pick(), None);
+        assert_eq!(picker.dir, dir.path().join("subdir"));
+
+        let names: Vec<_> = picker.filtered_names().collect();
+        assert_eq!(names, ["  ../", "  c.txt"]);
+    }
+
+    #[test]
+    fn test_attachment_input() {
+        assert_eq!(
+            attachment_input(Path::new("/tmp/a.txt")).as_deref(),
+            Some("file:///tmp/a.txt")
+        );
+        assert_eq!(attachment_input(Path::new("/tmp/a b.txt")), None);
+    }
+}
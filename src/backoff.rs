@@ -1,3 +1,5 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
 use std::time::Duration;
 
 const FIBONACCI_TIMEOUTS: [Duration; 9] = [
@@ -22,15 +24,43 @@ impl Backoff {
         Default::default()
     }
 
+    /// Next timeout, with a jitter of up to 20%, so that clients do not reconnect all at once
     pub fn get(&mut self) -> Duration {
         let timeout = FIBONACCI_TIMEOUTS[self.count];
         if self.count + 1 < FIBONACCI_TIMEOUTS.len() {
             self.count += 1;
         }
-        timeout
+        timeout.mul_f64(1.0 + 0.2 * jitter())
     }
 
     pub fn reset(&mut self) {
         self.count = 0;
     }
 }
+
+/// Random number between 0 and 1
+pub(crate) fn jitter() -> f64 {
+    // the keys of the hasher are random, so no dependency on `rand` is needed
+    let random = RandomState::new().build_hasher().finish();
+    (random % 1000) as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new();
+        let first = backoff.get();
+        assert!(Duration::from_secs(1) <= first && first < Duration::from_millis(1200));
+        let second = backoff.get();
+        assert!(Duration::from_secs(2) <= second && second < Duration::from_millis(2400));
+        for _ in 0..FIBONACCI_TIMEOUTS.len() {
+            backoff.get();
+        }
+        assert!(backoff.get() >= Duration::from_secs(55));
+        backoff.reset();
+        assert!(backoff.get() < Duration::from_millis(1200));
+    }
+}
@@ -0,0 +1,81 @@
+use crate::app::QueuedMessage;
+use crate::input::Input;
+
+/// Scheme of the link the captcha page opens after solving the captcha
+const CAPTCHA_SCHEME: &str = "signalcaptcha://";
+
+/// Popup asking for a solved captcha, after sending was rate limited by the server
+///
+/// The messages rejected by the server are kept, and resent after the captcha is solved.
+#[derive(Default)]
+pub(crate) struct CaptchaPrompt {
+    pub is_shown: bool,
+    pub input: Input,
+    /// Error of the last submitted captcha
+    pub error: Option<String>,
+    /// Token of the pending challenge
+    token: Option<String>,
+    /// Rejected messages by their original timestamp
+    messages: Vec<(u64, QueuedMessage)>,
+}
+
+impl CaptchaPrompt {
+    /// Shows the popup for the challenge with the token, queueing the rejected message
+    ///
+    /// A new challenge replaces the pending one, but the messages rejected before are kept.
+    pub fn open(&mut self, token: String, timestamp: u64, message: QueuedMessage) {
+        if self.token.as_ref() != Some(&token) {
+            self.token = Some(token);
+            self.input.take();
+            self.error = None;
+        }
+        self.messages.push((timestamp, message));
+        self.is_shown = true;
+    }
+
+    /// Shows the popup again, if there is a pending challenge
+    pub fn show(&mut self) {
+        self.is_shown = self.token.is_some();
+    }
+
+    /// Hides the popup, the challenge stays pending
+    pub fn close(&mut self) {
+        self.is_shown = false;
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    /// Captcha from the input with or without the `signalcaptcha://` scheme
+    pub fn captcha(&self) -> Option<&str> {
+        let input = self.input.data.trim();
+        let captcha = input.strip_prefix(CAPTCHA_SCHEME).unwrap_or(input);
+        (!captcha.is_empty()).then_some(captcha)
+    }
+
+    /// Resets the challenge and returns the rejected messages to be resent
+    pub fn solved(&mut self) -> Vec<(u64, QueuedMessage)> {
+        self.is_shown = false;
+        self.token = None;
+        self.error = None;
+        self.input.take();
+        std::mem::take(&mut self.messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_captcha_without_scheme() {
+        let mut prompt = CaptchaPrompt::default();
+        assert_eq!(prompt.captcha(), None);
+
+        "signalcaptcha://signal-hcaptcha.abc\n"
+            .chars()
+            .for_each(|c| prompt.input.put_char(c));
+        assert_eq!(prompt.captcha(), Some("signal-hcaptcha.abc"));
+    }
+}
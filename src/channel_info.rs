@@ -0,0 +1,183 @@
+use itertools::Itertools;
+use phonenumber::PhoneNumber;
+use presage::model::contacts::Contact;
+use uuid::Uuid;
+
+use crate::attachment_gallery::GALLERY_MESSAGES_LIMIT;
+use crate::data::{Channel, ChannelId};
+use crate::signal::GroupRequests;
+use crate::storage::{ContactNotes, Storage};
+
+/// Details of a channel shown in the channel info panel
+///
+/// Collected when the panel is opened or another channel is selected, since it requires looking
+/// up the contact and going through all channels and the newest messages.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ChannelInfo {
+    pub channel_id: ChannelId,
+    /// Phone number of the contact of a direct channel
+    pub phone_number: Option<PhoneNumber>,
+    /// Timer of disappearing messages in seconds, if enabled for the contact
+    pub expire_timer: Option<u32>,
+    /// Names of the groups shared with the contact of a direct channel
+    pub shared_groups: Vec<String>,
+    /// Names of the members of a group channel
+    pub members: Vec<String>,
+    /// Number of attachments in the newest messages of the channel, as listed in the gallery
+    pub attachments: usize,
+    /// Birthday and notes kept about the contact of a direct channel
+    pub notes: ContactNotes,
+    /// Join requests and invites of a group channel, if our user is an admin of the group
+    pub requests: Option<GroupRequests>,
+}
+
+impl ChannelInfo {
+    pub fn collect(
+        storage: &dyn Storage,
+        channel: &Channel,
+        contact: Option<&Contact>,
+        name: impl Fn(Uuid) -> String,
+    ) -> Self {
+        let shared_groups = match channel.id.user() {
+            Some(user_id) => storage
+                .channels()
+                .filter(|group| {
+                    group
+                        .group_data
+                        .as_ref()
+                        .is_some_and(|data| data.members.contains(&user_id))
+                })
+                .map(|group| group.shown_name().to_string())
+                .sorted_unstable()
+                .collect(),
+            None => Vec::new(),
+        };
+        let members = channel
+            .group_data
+            .iter()
+            .flat_map(|data| &data.members)
+            .map(|&id| name(id))
+            .sorted_unstable()
+            .collect();
+        let attachments = storage
+            .messages_before(channel.id, u64::MAX, GALLERY_MESSAGES_LIMIT)
+            .iter()
+            .map(|message| message.attachments.len())
+            .sum();
+        let notes = channel
+            .id
+            .user()
+            .and_then(|user_id| storage.metadata().contact_notes.get(&user_id).cloned())
+            .unwrap_or_default();
+        Self {
+            channel_id: channel.id,
+            phone_number: contact.and_then(|contact| contact.phone_number.clone()),
+            expire_timer: contact
+                .map(|contact| contact.expire_timer)
+                .filter(|&timer| timer > 0),
+            shared_groups,
+            members,
+            attachments,
+            notes,
+            requests: None,
+        }
+    }
+}
+
+/// Initials of the first two words of the name, shown as avatar
+pub(crate) fn initials(name: &str) -> String {
+    name.split_whitespace()
+        .filter_map(|word| word.chars().next())
+        .take(2)
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+/// Duration of a timer in seconds in the largest whole unit, e.g. `1 week`
+pub(crate) fn display_timer(secs: u32) -> String {
+    const UNITS: [(u32, &str); 5] = [
+        (7 * 24 * 3600, "week"),
+        (24 * 3600, "day"),
+        (3600, "hour"),
+        (60, "minute"),
+        (1, "second"),
+    ];
+    let (unit_secs, unit) = UNITS
+        .into_iter()
+        .find(|&(unit_secs, _)| secs % unit_secs == 0 && secs >= unit_secs)
+        .unwrap_or((1, "second"));
+    let count = secs / unit_secs;
+    if count == 1 {
+        format!("1 {unit}")
+    } else {
+        format!("{count} {unit}s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::tests::test_app;
+    use crate::data::{GroupData, TypingSet};
+
+    use super::*;
+
+    #[test]
+    fn test_collect_shared_groups() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let contact_id = Uuid::new_v4();
+        let contact_channel = Channel {
+            id: ChannelId::User(contact_id),
+            name: "Alice".to_string(),
+            group_data: None,
+            unread_messages: 0,
+            typing: TypingSet::SingleTyping(false),
+            muted_until: None,
+            pinned: false,
+            archived: false,
+            burn_after_reading: false,
+            max_messages: None,
+            ignore_mention_all: false,
+            notification_content: None,
+            nickname: None,
+        };
+        for (idx, name) in ["Hiking", "Book club"].into_iter().enumerate() {
+            app.storage.store_channel(Channel {
+                id: ChannelId::Group([idx as u8; 32]),
+                name: name.to_string(),
+                group_data: Some(GroupData {
+                    master_key_bytes: Default::default(),
+                    members: vec![app.user_id, contact_id],
+                    revision: 1,
+                }),
+                typing: TypingSet::GroupTyping(Default::default()),
+                ..contact_channel.clone()
+            });
+        }
+        app.storage.store_channel(contact_channel.clone());
+
+        let info = ChannelInfo::collect(&*app.storage, &contact_channel, None, |_| {
+            "Someone".to_string()
+        });
+        assert_eq!(info.shared_groups, ["Book club", "Hiking"]);
+        assert!(info.members.is_empty());
+        assert_eq!(info.phone_number, None);
+        assert_eq!(info.attachments, 0);
+        assert_eq!(info.notes, ContactNotes::default());
+    }
+
+    #[test]
+    fn test_initials() {
+        assert_eq!(initials("tyler durden"), "TD");
+        assert_eq!(initials("Marla Singer Jr."), "MS");
+        assert_eq!(initials("  "), "");
+    }
+
+    #[test]
+    fn test_display_timer() {
+        assert_eq!(display_timer(30), "30 seconds");
+        assert_eq!(display_timer(60), "1 minute");
+        assert_eq!(display_timer(8 * 3600), "8 hours");
+        assert_eq!(display_timer(7 * 24 * 3600), "1 week");
+        assert_eq!(display_timer(90), "90 seconds");
+    }
+}
@@ -4,13 +4,16 @@ use ratatui::widgets::ListState;
 
 use crate::data::ChannelId;
 use crate::input::Input;
-use crate::storage::Storage;
+use crate::names::Names;
+use crate::storage::{MessageId, Storage};
 
 #[derive(Default)]
 pub(crate) struct SelectChannel {
     pub is_shown: bool,
     pub input: Input,
     pub state: ListState,
+    /// Messages forwarded to the chosen channel, instead of switching to it
+    pub forward: Vec<MessageId>,
     items: Vec<ItemData>,
     filtered_index: Vec<usize /* index into items */>,
 }
@@ -21,13 +24,19 @@ pub(crate) struct ItemData {
 }
 
 impl SelectChannel {
-    pub fn reset(&mut self, storage: &dyn Storage) {
+    /// Lists all channels by the name they are shown with
+    pub fn reset(&mut self, storage: &dyn Storage, names: &Names) {
         self.input.take();
         self.state = Default::default();
+        self.forward.clear();
 
         let items = storage.channels().map(|channel| ItemData {
             channel_id: channel.id,
-            name: channel.name.clone(),
+            name: channel
+                .user_id()
+                .and_then(|id| names.display_name(id))
+                .unwrap_or_else(|| channel.shown_name())
+                .to_string(),
         });
         self.items.clear();
         self.items.extend(items);
@@ -35,7 +44,7 @@ impl SelectChannel {
         self.items.sort_unstable_by_key(|item| {
             let last_message_arrived_at = storage
                 .messages(item.channel_id)
-                .last()
+                .next_back()
                 .map(|message| message.arrived_at);
             (Reverse(last_message_arrived_at), item.name.clone())
         });
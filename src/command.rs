@@ -1,11 +1,14 @@
 use std::collections::HashMap;
 use std::str::FromStr;
+use std::time::Duration;
 
 use crokey::KeyCombination;
 use serde::{Deserialize, Serialize};
 use strum::{EnumIter, EnumProperty, EnumString, VariantNames};
 
 use crate::app::to_emoji;
+use crate::data::NotificationContent;
+use crate::storage::Birthday;
 
 pub type KeybindingConfig = HashMap<KeyCombination, String>;
 pub type ModeKeybindingConfig = HashMap<WindowMode, KeybindingConfig>;
@@ -26,6 +29,7 @@ pub type ModeKeybinding = HashMap<WindowMode, Keybinding>;
 pub enum Widget {
     #[default]
     Help,
+    Messages,
 }
 
 #[derive(
@@ -45,6 +49,15 @@ pub enum DirectionVertical {
     Down,
 }
 
+impl DirectionVertical {
+    pub fn reversed(&self) -> Self {
+        match self {
+            Self::Up => Self::Down,
+            Self::Down => Self::Up,
+        }
+    }
+}
+
 #[derive(
     Clone,
     Default,
@@ -62,6 +75,15 @@ pub enum MoveDirection {
     Next,
 }
 
+impl MoveDirection {
+    pub fn reversed(&self) -> Self {
+        match self {
+            Self::Previous => Self::Next,
+            Self::Next => Self::Previous,
+        }
+    }
+}
+
 #[derive(
     Clone,
     Default,
@@ -95,8 +117,10 @@ pub enum MoveAmountText {
 pub enum MoveAmountVisual {
     #[default]
     Entry,
-    // HalfScreen,
-    // Screen,
+    HalfScreen,
+    Screen,
+    /// Up to the first or the last entry
+    End,
 }
 
 #[derive(
@@ -127,14 +151,29 @@ pub enum MessageSelector {
     Copy,
     strum_macros::Display,
     strum_macros::VariantNames,
+    EnumIter,
 )]
 #[strum(serialize_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
 pub enum WindowMode {
     Anywhere,
     Help,
+    Diagnostics,
+    ErrorLog,
+    Devices,
     ChannelModal,
+    AttachmentPicker,
+    RecentFiles,
+    AttachmentGallery,
+    SaveAttachment,
+    Contacts,
+    RenameContact,
+    ReactionPicker,
+    CaptchaPrompt,
+    MessageSearch,
     Multiline,
+    /// Input box starting with `/`
+    CommandInput,
     MessageSelected,
     Normal,
 }
@@ -154,17 +193,58 @@ pub enum WindowMode {
 pub enum Command {
     #[strum(props(desc = "Do nothing"))]
     NoOp,
-    #[strum(props(desc = "Toggle help panel"))]
-    Help,
+    #[strum(props(
+        desc = "Toggle help panel, or show the usage of a command",
+        usage = "help [command]"
+    ))]
+    Help(Option<String>),
     #[strum(props(desc = "Quit application"))]
     Quit,
     #[strum(props(desc = "Open pop-up for selecting a channel"))]
     ToggleChannelModal,
     #[strum(props(desc = "Switch between single-line and multi-line modes."))]
     ToggleMultiline,
+    #[strum(props(desc = "Send the message in the input box (also in multi-line mode)"))]
+    SendMessage,
     #[strum(props(desc = "Sends emoji from input line as reaction on selected message."))]
     React(Option<String>),
-    #[strum(props(desc = "Scroll a widget", usage = "scroll help up|down entry"))]
+    #[strum(props(
+        desc = "React with one of the favorite reactions on the selected message",
+        usage = "quick_react 1|2|3|4|5|6"
+    ))]
+    #[strum(serialize = "quick_react", to_string = "quick_react {0}")]
+    QuickReact(usize),
+    #[strum(props(desc = "Open pop-up with the reactions on the selected message for reacting"))]
+    ToggleReactionPicker,
+    #[strum(props(
+        desc = "Select next/previous emoji in the reaction pop-up",
+        usage = "select_reaction previous|next"
+    ))]
+    #[strum(serialize = "select_reaction", to_string = "select_reaction {0}")]
+    SelectReaction(MoveDirection),
+    #[strum(props(desc = "React with the selected emoji, or remove our reaction if selected"))]
+    ReactSelected,
+    #[strum(props(desc = "Show/hide the prompt for the captcha required for sending messages"))]
+    ToggleCaptchaPrompt,
+    #[strum(props(desc = "Open pop-up for searching the messages of all channels"))]
+    ToggleMessageSearch,
+    #[strum(props(
+        desc = "Select next/previous message in the message search",
+        usage = "select_search_result previous|next"
+    ))]
+    #[strum(
+        serialize = "select_search_result",
+        to_string = "select_search_result {0}"
+    )]
+    SelectSearchResult(MoveDirection),
+    #[strum(props(desc = "Show/hide diagnostics, e.g. the disk usage of the attachments"))]
+    ToggleDiagnostics,
+    #[strum(props(desc = "Show/hide the recent errors, e.g. of sending messages or saving"))]
+    ToggleErrorLog,
+    #[strum(props(
+        desc = "Scroll a widget",
+        usage = "scroll help|messages up|down entry|half_screen|screen|end"
+    ))]
     #[strum(serialize = "scroll", to_string = "scroll {0} {1} {2}")]
     Scroll(Widget, DirectionVertical, MoveAmountVisual),
     #[strum(props(
@@ -191,7 +271,7 @@ pub enum Command {
     SelectChannelModal(MoveDirection),
     #[strum(props(
         desc = "Select next/previous message",
-        usage = "select_message previous|next entry"
+        usage = "select_message previous|next entry|half_screen|screen|end"
     ))]
     #[strum(serialize = "select_message", to_string = "select_message {0} {1}")]
     SelectMessage(MoveDirection, MoveAmountVisual),
@@ -204,8 +284,8 @@ pub enum Command {
     #[strum(props(desc = "Delete last word."))]
     KillWord,
     #[strum(props(
-        desc = "Copy selected message to clipboard",
-        usage = "copy_message selected"
+        desc = "Copy the selected message, or the marked messages as one text, to clipboard",
+        usage = "copy_message selected|marked"
     ))]
     #[strum(serialize = "copy_message", to_string = "copy_message {0}")]
     CopyMessage(MessageSelector),
@@ -221,22 +301,226 @@ pub enum Command {
     DeleteCharacter(MoveDirection),
     #[strum(props(desc = "Edit selected message"))]
     EditMessage,
+    #[strum(props(desc = "Send the selected message again if sending it failed"))]
+    RetrySend,
+    #[strum(props(desc = "Download the attachments of the selected message not downloaded yet"))]
+    DownloadAttachments,
+    #[strum(props(desc = "Cancel sending the selected message or downloading its attachments"))]
+    Cancel,
+    #[strum(props(
+        desc = "Forward the selected (or marked) messages to a channel chosen in a pop-up"
+    ))]
+    ForwardMessage,
+    #[strum(props(
+        desc = "Mark the selected message for applying a command to several messages, or unmark it"
+    ))]
+    ToggleMessageMark,
+    #[strum(props(desc = "Delete the marked messages and their attachments on this device only"))]
+    DeleteMarkedMessages,
+    #[strum(props(desc = "Delete the selected message and its attachments on this device only"))]
+    DeleteMessageLocally,
+    #[strum(props(
+        desc = "Write the marked messages with their times and senders to a text file",
+        usage = "export_messages <path>"
+    ))]
+    ExportMessages(String),
     #[strum(props(desc = "Try to open the first url in the selected message"))]
     OpenUrl,
+    #[strum(props(
+        desc = "Recall previous/next sent input in the channel",
+        usage = "recall_input previous|next"
+    ))]
+    #[strum(serialize = "recall_input", to_string = "recall_input {0}")]
+    RecallInput(MoveDirection),
+    #[strum(props(desc = "Search backward in the sent inputs of the channel"))]
+    SearchInputHistory,
+    #[strum(props(desc = "Open pop-up for picking files to attach"))]
+    ToggleAttachmentPicker,
+    #[strum(props(
+        desc = "Attach the file to the message in the input box",
+        usage = "attach <path>"
+    ))]
+    Attach(String),
+    #[strum(props(desc = "Complete the command in the input box starting with /"))]
+    CompleteCommand,
+    #[strum(props(
+        desc = "Select next/previous file in attachment picker",
+        usage = "select_attachment previous|next"
+    ))]
+    #[strum(serialize = "select_attachment", to_string = "select_attachment {0}")]
+    SelectAttachment(MoveDirection),
+    #[strum(props(desc = "Mark/unmark selected file in attachment picker"))]
+    MarkAttachment,
+    #[strum(props(desc = "Open pop-up with recently sent and downloaded files"))]
+    ToggleRecentFiles,
+    #[strum(props(
+        desc = "Select next/previous file in recent files",
+        usage = "select_recent_file previous|next"
+    ))]
+    #[strum(serialize = "select_recent_file", to_string = "select_recent_file {0}")]
+    SelectRecentFile(MoveDirection),
+    #[strum(props(desc = "Attach selected recent file to the input"))]
+    AttachRecentFile,
+    #[strum(props(desc = "Open selected recent file"))]
+    OpenRecentFile,
+    #[strum(props(desc = "Open pop-up with the attachments of the selected channel"))]
+    ToggleAttachmentGallery,
+    #[strum(props(
+        desc = "Select next/previous attachment in the attachment gallery",
+        usage = "select_gallery_attachment previous|next"
+    ))]
+    #[strum(
+        serialize = "select_gallery_attachment",
+        to_string = "select_gallery_attachment {0}"
+    )]
+    SelectGalleryAttachment(MoveDirection),
+    #[strum(props(desc = "Show the next type of attachments: all, images, audio or files"))]
+    FilterGalleryAttachments,
+    #[strum(props(desc = "Open selected attachment in the attachment gallery"))]
+    OpenGalleryAttachment,
+    #[strum(props(desc = "Show/hide the input for saving the selected attachment elsewhere"))]
+    ToggleSaveAttachment,
+    #[strum(props(
+        desc = "Delete the local copy of the selected attachment, after repeating it to confirm"
+    ))]
+    DeleteGalleryAttachment,
+    #[strum(props(desc = "Open pop-up with the known contacts"))]
+    ToggleContacts,
+    #[strum(props(
+        desc = "Select next/previous contact in the contacts",
+        usage = "select_contact previous|next"
+    ))]
+    #[strum(serialize = "select_contact", to_string = "select_contact {0}")]
+    SelectContact(MoveDirection),
+    #[strum(props(desc = "Show/hide the input for the nickname of the selected contact"))]
+    ToggleRenameContact,
+    #[strum(props(desc = "Block the selected contact, dropping their messages, or unblock them"))]
+    ToggleBlockContact,
+    #[strum(props(desc = "Open the direct channel of the selected contact"))]
+    OpenContactDm,
+    #[strum(props(
+        desc = "Mute notifications of the selected (or marked) channels, optionally for a duration",
+        usage = "mute [30s|15m|8h|2d|1w]"
+    ))]
+    Mute(Option<Duration>),
+    #[strum(props(desc = "Unmute notifications of the selected (or marked) channels"))]
+    Unmute,
+    #[strum(props(
+        desc = "Open the channel of the contact with the phone number",
+        usage = "new <phone number>"
+    ))]
+    New(String),
+    #[strum(props(
+        desc = "Open the direct channel of a contact given by name, phone number or id",
+        usage = "dm <name|number|uuid>"
+    ))]
+    Dm(String),
+    #[strum(props(
+        desc = "Set the birthday of the contact of the selected channel, or clear it",
+        usage = "birthday [MM-DD|YYYY-MM-DD]"
+    ))]
+    Birthday(Option<Birthday>),
+    #[strum(props(
+        desc = "Set a note about the contact of the selected channel, or clear it",
+        usage = "note [text]"
+    ))]
+    Note(String),
+    #[strum(props(
+        desc = "Set the local name shown for the contact or group of the selected channel, or clear it",
+        usage = "nick [name]"
+    ))]
+    Nick(String),
+    #[strum(props(desc = "Show the join requests and invites of the selected group"))]
+    Group,
+    #[strum(props(desc = "Pin the selected channel to the top of the list, or unpin it"))]
+    TogglePin,
+    #[strum(props(desc = "Archive the selected (or marked) channels, or unarchive them"))]
+    ToggleArchive,
+    #[strum(props(desc = "Show or hide archived channels in the channel list"))]
+    ToggleArchivedChannels,
+    #[strum(props(desc = "Mark the selected channel as burn-after-reading, or unmark it"))]
+    ToggleBurnAfterReading,
+    #[strum(props(
+        desc = "Count @all in the selected group as a mention of you, or stop counting it"
+    ))]
+    ToggleMentionAll,
+    #[strum(props(
+        desc = "Mark the selected channel for applying a command to several channels, or unmark it"
+    ))]
+    ToggleChannelMark,
+    #[strum(props(desc = "Mark the messages of the selected (or marked) channels as read"))]
+    MarkRead,
+    #[strum(props(
+        desc = "Keep only the newest messages of the selected channel, or all of them",
+        usage = "keep_messages [count]"
+    ))]
+    KeepMessages(Option<usize>),
+    #[strum(props(
+        desc = "Show the full text, only the sender or nothing in notifications of the selected channel",
+        usage = "notification_content [full|sender|hidden]"
+    ))]
+    NotificationContent(Option<NotificationContent>),
+    #[strum(props(
+        desc = "Send the snippet from the config to the selected (or marked) channels",
+        usage = "snippet <name>"
+    ))]
+    Snippet(String),
+    #[strum(props(
+        desc = "Export the config and the settings of the channels, but no messages, to a file",
+        usage = "export_profile <path>"
+    ))]
+    ExportProfile(String),
+    #[strum(props(
+        desc = "Import the config and the settings of the channels from an exported file",
+        usage = "import_profile <path>"
+    ))]
+    ImportProfile(String),
+    #[strum(props(
+        desc = "Show or hide the devices linked to the account and their last activity"
+    ))]
+    ToggleDevices,
+    #[strum(props(
+        desc = "Unlink this device from the account, delete its credentials and quit",
+        usage = "unlink_device confirm"
+    ))]
+    UnlinkDevice,
+    #[strum(props(desc = "Quit and link this device again, keeping the messages"))]
+    Relink,
+    #[strum(props(desc = "Show or hide the time since the last message under each channel"))]
+    ToggleChannelDetails,
+    #[strum(props(desc = "Show or hide the panel with the details of the selected channel"))]
+    ToggleChannelInfo,
+    #[strum(props(desc = "Show or hide the channel list"))]
+    ToggleSidebar,
+    #[strum(props(desc = "Show the channel list next to or above the chat"))]
+    ToggleStackedLayout,
+    #[strum(props(desc = "Show the newest message at the top or at the bottom of the chat"))]
+    ToggleMessageOrder,
+    #[strum(props(desc = "Sort the channel list by last activity"))]
+    SortChannels,
+    #[strum(props(
+        desc = "Delete all messages of the selected (or marked) channels and their attachments"
+    ))]
+    Purge,
+    #[strum(props(
+        desc = "Delete the local history of the selected channel and its attachments, after confirming",
+        usage = "clear confirm"
+    ))]
+    Clear,
     // ReplyMessage,
     // DeleteMessage,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, thiserror::Error)]
 pub enum CommandParseError {
+    #[error("no such command: {cmd}")]
     NoSuchCommand {
         cmd: String,
         accept: &'static [&'static str],
     },
-    InsufficientArgs {
-        cmd: String,
-        hint: Option<String>,
-    },
+    #[error("insufficient arguments for {cmd}{}", .hint.as_ref().map(|hint| format!(": {hint}")).unwrap_or_default())]
+    InsufficientArgs { cmd: String, hint: Option<String> },
+    #[error("bad argument {arg}, expected {}", .accept.join("|"))]
     BadEnumArg {
         arg: String,
         accept: &'static [&'static str],
@@ -244,11 +528,14 @@ pub enum CommandParseError {
     },
 }
 
-fn parse(input: &str) -> Result<Command, CommandParseError> {
+pub(crate) fn parse(input: &str) -> Result<Command, CommandParseError> {
     let words: Vec<_> = input.split_whitespace().collect();
     use CommandParseError as E;
 
-    let (cmd_str, args) = words.split_first().unwrap();
+    let (cmd_str, args) = words.split_first().ok_or_else(|| E::NoSuchCommand {
+        cmd: input.to_string(),
+        accept: Command::VARIANTS,
+    })?;
     let cmd = Command::from_str(cmd_str).map_err(|_e| E::NoSuchCommand {
         cmd: cmd_str.to_string(),
         accept: Command::VARIANTS,
@@ -339,6 +626,84 @@ fn parse(input: &str) -> Result<Command, CommandParseError> {
             Ok(Command::SelectChannelModal(direction))
             // Ok(Command::SelectChannelModal(MoveDirection::from_str(args.first().unwrap_or(&""))?))
         }
+        Command::SelectAttachment(_) => {
+            let usage = E::InsufficientArgs {
+                cmd: cmd_str.to_string(),
+                hint: Some(MoveDirection::VARIANTS.join("|")),
+            };
+            let direction = args.first().ok_or(usage)?;
+            let direction = MoveDirection::from_str(direction).map_err(|_e| E::BadEnumArg {
+                arg: direction.to_string(),
+                accept: MoveDirection::VARIANTS,
+                optional: false,
+            })?;
+            Ok(Command::SelectAttachment(direction))
+        }
+        Command::SelectRecentFile(_) => {
+            let usage = E::InsufficientArgs {
+                cmd: cmd_str.to_string(),
+                hint: Some(MoveDirection::VARIANTS.join("|")),
+            };
+            let direction = args.first().ok_or(usage)?;
+            let direction = MoveDirection::from_str(direction).map_err(|_e| E::BadEnumArg {
+                arg: direction.to_string(),
+                accept: MoveDirection::VARIANTS,
+                optional: false,
+            })?;
+            Ok(Command::SelectRecentFile(direction))
+        }
+        Command::SelectContact(_) => {
+            let usage = E::InsufficientArgs {
+                cmd: cmd_str.to_string(),
+                hint: Some(MoveDirection::VARIANTS.join("|")),
+            };
+            let direction = args.first().ok_or(usage)?;
+            let direction = MoveDirection::from_str(direction).map_err(|_e| E::BadEnumArg {
+                arg: direction.to_string(),
+                accept: MoveDirection::VARIANTS,
+                optional: false,
+            })?;
+            Ok(Command::SelectContact(direction))
+        }
+        Command::SelectReaction(_) => {
+            let usage = E::InsufficientArgs {
+                cmd: cmd_str.to_string(),
+                hint: Some(MoveDirection::VARIANTS.join("|")),
+            };
+            let direction = args.first().ok_or(usage)?;
+            let direction = MoveDirection::from_str(direction).map_err(|_e| E::BadEnumArg {
+                arg: direction.to_string(),
+                accept: MoveDirection::VARIANTS,
+                optional: false,
+            })?;
+            Ok(Command::SelectReaction(direction))
+        }
+        Command::SelectSearchResult(_) => {
+            let usage = E::InsufficientArgs {
+                cmd: cmd_str.to_string(),
+                hint: Some(MoveDirection::VARIANTS.join("|")),
+            };
+            let direction = args.first().ok_or(usage)?;
+            let direction = MoveDirection::from_str(direction).map_err(|_e| E::BadEnumArg {
+                arg: direction.to_string(),
+                accept: MoveDirection::VARIANTS,
+                optional: false,
+            })?;
+            Ok(Command::SelectSearchResult(direction))
+        }
+        Command::RecallInput(_) => {
+            let usage = E::InsufficientArgs {
+                cmd: cmd_str.to_string(),
+                hint: Some(MoveDirection::VARIANTS.join("|")),
+            };
+            let direction = args.first().ok_or(usage)?;
+            let direction = MoveDirection::from_str(direction).map_err(|_e| E::BadEnumArg {
+                arg: direction.to_string(),
+                accept: MoveDirection::VARIANTS,
+                optional: false,
+            })?;
+            Ok(Command::RecallInput(direction))
+        }
         Command::SelectMessage(_, _) => {
             let usage = E::InsufficientArgs {
                 cmd: cmd_str.to_string(),
@@ -359,7 +724,7 @@ fn parse(input: &str) -> Result<Command, CommandParseError> {
             })?;
             let amount = MoveAmountVisual::from_str(amount).map_err(|_e| E::BadEnumArg {
                 arg: amount.to_string(),
-                accept: MoveAmountText::VARIANTS,
+                accept: MoveAmountVisual::VARIANTS,
                 optional: false,
             })?;
             Ok(Command::SelectMessage(direction, amount))
@@ -391,10 +756,148 @@ fn parse(input: &str) -> Result<Command, CommandParseError> {
                 },
             }
         }
+        Command::QuickReact(_) => {
+            let usage = E::InsufficientArgs {
+                cmd: cmd_str.to_string(),
+                hint: Some("1|2|3|4|5|6".into()),
+            };
+            let n = args.first().ok_or(usage.clone())?;
+            match n.parse() {
+                Ok(n @ 1..=6) => Ok(Command::QuickReact(n)),
+                _ => Err(usage),
+            }
+        }
+        Command::Mute(_) => {
+            let usage = E::InsufficientArgs {
+                cmd: cmd_str.to_string(),
+                hint: Some("Optional duration, e.g. 30s, 15m, 8h, 2d or 1w".into()),
+            };
+            match args.first() {
+                None => Ok(Command::Mute(None)),
+                Some(s) => parse_duration(s)
+                    .map(|duration| Command::Mute(Some(duration)))
+                    .ok_or(usage),
+            }
+        }
+        Command::New(_) => {
+            if args.is_empty() {
+                return Err(E::InsufficientArgs {
+                    cmd: cmd_str.to_string(),
+                    hint: Some("<phone number>".into()),
+                });
+            }
+            // phone numbers may contain spaces
+            Ok(Command::New(args.join(" ")))
+        }
+        Command::Dm(_) => {
+            if args.is_empty() {
+                return Err(E::InsufficientArgs {
+                    cmd: cmd_str.to_string(),
+                    hint: Some("<name|number|uuid>".into()),
+                });
+            }
+            // names and phone numbers may contain spaces
+            Ok(Command::Dm(args.join(" ")))
+        }
+        Command::Birthday(_) => {
+            let usage = E::InsufficientArgs {
+                cmd: cmd_str.to_string(),
+                hint: Some("Optional birthday, e.g. 05-17 or 1990-05-17".into()),
+            };
+            match args.first() {
+                None => Ok(Command::Birthday(None)),
+                Some(s) => s
+                    .parse()
+                    .map(|birthday| Command::Birthday(Some(birthday)))
+                    .map_err(|_| usage),
+            }
+        }
+        Command::Note(_) => Ok(Command::Note(args.join(" "))),
+        Command::Nick(_) => Ok(Command::Nick(args.join(" "))),
+        Command::Snippet(_) => match args.first() {
+            Some(name) => Ok(Command::Snippet(name.to_string())),
+            None => Err(E::InsufficientArgs {
+                cmd: cmd_str.to_string(),
+                hint: Some("<name>".into()),
+            }),
+        },
+        Command::ExportProfile(_) | Command::ImportProfile(_) | Command::ExportMessages(_) => {
+            if args.is_empty() {
+                return Err(E::InsufficientArgs {
+                    cmd: cmd_str.to_string(),
+                    hint: Some("<path>".into()),
+                });
+            }
+            let path = args.join(" ");
+            Ok(match cmd {
+                Command::ExportProfile(_) => Command::ExportProfile(path),
+                Command::ExportMessages(_) => Command::ExportMessages(path),
+                _ => Command::ImportProfile(path),
+            })
+        }
+        Command::Help(_) => Ok(Command::Help(args.first().map(|name| name.to_string()))),
+        Command::Attach(_) => {
+            if args.is_empty() {
+                return Err(E::InsufficientArgs {
+                    cmd: cmd_str.to_string(),
+                    hint: Some("<path>".into()),
+                });
+            }
+            Ok(Command::Attach(args.join(" ")))
+        }
+        Command::NotificationContent(_) => match args.first() {
+            None => Ok(Command::NotificationContent(None)),
+            Some(s) => s
+                .parse()
+                .map(|content| Command::NotificationContent(Some(content)))
+                .map_err(|_| E::BadEnumArg {
+                    arg: s.to_string(),
+                    accept: NotificationContent::VARIANTS,
+                    optional: true,
+                }),
+        },
+        Command::UnlinkDevice | Command::Clear => match args.first() {
+            Some(&"confirm") => Ok(cmd),
+            _ => Err(E::InsufficientArgs {
+                cmd: cmd_str.to_string(),
+                hint: Some("confirm".into()),
+            }),
+        },
+        Command::KeepMessages(_) => {
+            let usage = E::InsufficientArgs {
+                cmd: cmd_str.to_string(),
+                hint: Some("Optional number of messages to keep, e.g. 500".into()),
+            };
+            match args.first() {
+                None => Ok(Command::KeepMessages(None)),
+                Some(s) => s
+                    .parse()
+                    .ok()
+                    .filter(|&count| count > 0)
+                    .map(|count| Command::KeepMessages(Some(count)))
+                    .ok_or(usage),
+            }
+        }
         _ => Ok(cmd),
     }
 }
 
+/// Parses a duration like `30s`, `15m`, `8h`, `2d` or `1w`
+fn parse_duration(s: &str) -> Option<Duration> {
+    let idx = s.find(|c: char| !c.is_ascii_digit())?;
+    let (value, unit) = s.split_at(idx);
+    let value: u64 = value.parse().ok()?;
+    let secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        "w" => 60 * 60 * 24 * 7,
+        _ => return None,
+    };
+    Some(Duration::from_secs(value.checked_mul(secs)?))
+}
+
 pub const DEFAULT_KEYBINDINGS: &str = r#"
 [anywhere]
 F1 = "help"
@@ -413,8 +916,12 @@ alt-up = "select_message previous entry"
 alt-down = "select_message next entry"
 alt-j = "select_message next entry"
 alt-k = "select_message previous entry"
-pagedown = "select_message next entry"
-pageup = "select_message previous entry"
+pagedown = "scroll messages down screen"
+pageup = "scroll messages up screen"
+alt-pagedown = "scroll messages down half_screen"
+alt-pageup = "scroll messages up half_screen"
+ctrl-home = "scroll messages up end"
+ctrl-end = "scroll messages down end"
 alt-f = "move_text next word"
 ctrl-f = "move_text next character"
 alt-b = "move_text previous word"
@@ -432,12 +939,43 @@ end = "end_of_line"
 ctrl-e = "end_of_line"
 backspace = "delete_character previous"
 tab = "react"
+ctrl-up = "recall_input previous"
+ctrl-down = "recall_input next"
+ctrl-r = "search_input_history"
+ctrl-o = "toggle_attachment_picker"
+alt-o = "toggle_recent_files"
+alt-g = "toggle_attachment_gallery"
+alt-u = "toggle_contacts"
+alt-p = "toggle_pin"
+alt-a = "toggle_archive"
+alt-x = "toggle_channel_mark"
+alt-h = "toggle_archived_channels"
+alt-d = "toggle_channel_details"
+alt-s = "toggle_sidebar"
+alt-l = "toggle_stacked_layout"
+alt-n = "toggle_message_order"
+alt-r = "sort_channels"
+alt-i = "toggle_channel_info"
+ctrl-s = "toggle_message_search"
 
 [message_selected]
+"?" = "help"
 alt-y = "copy_message selected"
 ctrl-e = "edit_message"
+ctrl-r = "retry_send"
+ctrl-d = "download_attachments"
+ctrl-x = "cancel"
+ctrl-f = "forward_message"
+space = "toggle_message_mark"
 ctrl-t = "react :thumbsup:"
 ctrl-h = "react ❤️"
+alt-e = "toggle_reaction_picker"
+1 = "quick_react 1"
+2 = "quick_react 2"
+3 = "quick_react 3"
+4 = "quick_react 4"
+5 = "quick_react 5"
+6 = "quick_react 6"
 
 [channel_modal]
 esc = "toggle_channel_modal"
@@ -465,22 +1003,211 @@ end = "end_of_line"
 ctrl-e = "end_of_line"
 backspace = "delete_character previous"
 
+[attachment_picker]
+esc = "toggle_attachment_picker"
+ctrl-o = "toggle_attachment_picker"
+down = "select_attachment next"
+up = "select_attachment previous"
+ctrl-j = "select_attachment next"
+ctrl-k = "select_attachment previous"
+tab = "mark_attachment"
+left = "move_text previous character"
+right = "move_text next character"
+ctrl-u = "kill_backward_line"
+ctrl-w = "kill_word"
+alt-backspace = "kill_word"
+home = "beginning_of_line"
+end = "end_of_line"
+backspace = "delete_character previous"
+
+[recent_files]
+esc = "toggle_recent_files"
+alt-o = "toggle_recent_files"
+down = "select_recent_file next"
+up = "select_recent_file previous"
+ctrl-j = "select_recent_file next"
+ctrl-k = "select_recent_file previous"
+enter = "attach_recent_file"
+o = "open_recent_file"
+
+[attachment_gallery]
+esc = "toggle_attachment_gallery"
+alt-g = "toggle_attachment_gallery"
+down = "select_gallery_attachment next"
+up = "select_gallery_attachment previous"
+ctrl-j = "select_gallery_attachment next"
+ctrl-k = "select_gallery_attachment previous"
+tab = "filter_gallery_attachments"
+enter = "open_gallery_attachment"
+o = "open_gallery_attachment"
+s = "toggle_save_attachment"
+d = "delete_gallery_attachment"
+
+[save_attachment]
+esc = "toggle_save_attachment"
+left = "move_text previous character"
+right = "move_text next character"
+ctrl-u = "kill_backward_line"
+ctrl-w = "kill_word"
+alt-backspace = "kill_word"
+home = "beginning_of_line"
+end = "end_of_line"
+backspace = "delete_character previous"
+
+[contacts]
+esc = "toggle_contacts"
+alt-u = "toggle_contacts"
+down = "select_contact next"
+up = "select_contact previous"
+ctrl-j = "select_contact next"
+ctrl-k = "select_contact previous"
+enter = "open_contact_dm"
+r = "toggle_rename_contact"
+b = "toggle_block_contact"
+
+[rename_contact]
+esc = "toggle_rename_contact"
+left = "move_text previous character"
+right = "move_text next character"
+ctrl-u = "kill_backward_line"
+ctrl-w = "kill_word"
+alt-backspace = "kill_word"
+home = "beginning_of_line"
+end = "end_of_line"
+backspace = "delete_character previous"
+
+[reaction_picker]
+esc = "toggle_reaction_picker"
+alt-e = "toggle_reaction_picker"
+down = "select_reaction next"
+up = "select_reaction previous"
+ctrl-j = "select_reaction next"
+ctrl-k = "select_reaction previous"
+enter = "react_selected"
+
+[captcha_prompt]
+esc = "toggle_captcha_prompt"
+left = "move_text previous character"
+right = "move_text next character"
+ctrl-u = "kill_backward_line"
+ctrl-w = "kill_word"
+alt-backspace = "kill_word"
+home = "beginning_of_line"
+end = "end_of_line"
+backspace = "delete_character previous"
+
+[message_search]
+esc = "toggle_message_search"
+ctrl-s = "toggle_message_search"
+down = "select_search_result next"
+up = "select_search_result previous"
+ctrl-j = "select_search_result next"
+ctrl-k = "select_search_result previous"
+left = "move_text previous character"
+right = "move_text next character"
+ctrl-u = "kill_backward_line"
+ctrl-w = "kill_word"
+alt-backspace = "kill_word"
+home = "beginning_of_line"
+end = "end_of_line"
+backspace = "delete_character previous"
+
 [multiline]
 down = "move_text next line"
 up = "move_text previous line"
 ctrl-j = "move_text next line"
 ctrl-k = "move_text previous line"
 
+[command_input]
+tab = "complete_command"
+
+[diagnostics]
+esc = "toggle_diagnostics"
+
+[error_log]
+esc = "toggle_error_log"
+
+[devices]
+esc = "toggle_devices"
+
 [help]
 esc = "help"
+"?" = "help"
 ctrl-j = "scroll help down entry"
 ctrl-k = "scroll help up entry"
 down = "scroll help down entry"
 up = "scroll help up entry"
-pagedown = "scroll help down entry"
-pageup = "scroll help up entry"
+pagedown = "scroll help down screen"
+pageup = "scroll help up screen"
+home = "scroll help up end"
+end = "scroll help down end"
 "#;
 
+/// Default keybindings added when `enter` inserts a new line instead of sending the message
+pub const ENTER_NEWLINE_KEYBINDINGS: &str = r#"
+[normal]
+alt-enter = "send_message"
+
+[multiline]
+alt-enter = "send_message"
+"#;
+
+/// Completions of the word at the end of the command line without the leading `/`
+///
+/// The first word is completed to the names of the commands, further words to the values listed
+/// in the usage of the command, e.g. `previous|next`.
+pub(crate) fn complete(line: &str) -> Vec<String> {
+    let mut words: Vec<_> = line.split_whitespace().collect();
+    if line.is_empty() || line.ends_with(char::is_whitespace) {
+        words.push("");
+    }
+    let Some((prefix, previous)) = words.split_last() else {
+        return Vec::new();
+    };
+    let candidates = match previous.split_first() {
+        None => Command::VARIANTS
+            .iter()
+            .copied()
+            .filter(|&name| name != "no_op")
+            .collect(),
+        Some((cmd, args)) => Command::from_str(cmd)
+            .ok()
+            .and_then(|cmd| cmd.get_str("usage"))
+            .and_then(|usage| usage.split_whitespace().nth(args.len() + 1))
+            .map(usage_values)
+            .unwrap_or_default(),
+    };
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(prefix))
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Values of an argument in the usage of a command, e.g. `previous|next`, but not of placeholders
+/// like `<path>` or `[count]`
+fn usage_values(arg: &str) -> Vec<&str> {
+    if arg.starts_with('[') && !arg.contains('|') {
+        return Vec::new();
+    }
+    arg.trim_matches(['[', ']'])
+        .split('|')
+        .filter(|value| {
+            value
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        })
+        .collect()
+}
+
+/// Usage and description of the command, e.g. for `/help mute`
+pub(crate) fn describe(name: &str) -> Option<String> {
+    let cmd = Command::from_str(name).ok()?;
+    let usage = cmd.get_str("usage").unwrap_or(name);
+    let desc = cmd.get_str("desc")?;
+    Some(format!("{usage}: {desc}"))
+}
+
 fn merge_keybinding_configs(mkb1: &mut ModeKeybindingConfig, mkb2: ModeKeybindingConfig) {
     for (mode, kb2) in mkb2 {
         mkb1.entry(mode).or_default().extend(kb2);
@@ -490,9 +1217,15 @@ fn merge_keybinding_configs(mkb1: &mut ModeKeybindingConfig, mkb2: ModeKeybindin
 pub fn get_keybindings(
     keybinding_config: &ModeKeybindingConfig,
     default_bindings: bool,
+    enter_sends: bool,
 ) -> Result<ModeKeybinding, CommandParseError> {
     let mut keybindings = if default_bindings {
-        toml::from_str(DEFAULT_KEYBINDINGS).unwrap()
+        let mut keybindings = toml::from_str(DEFAULT_KEYBINDINGS).unwrap();
+        if !enter_sends {
+            let enter_newline = toml::from_str(ENTER_NEWLINE_KEYBINDINGS).unwrap();
+            merge_keybinding_configs(&mut keybindings, enter_newline);
+        }
+        keybindings
     } else {
         HashMap::new()
     };
@@ -523,9 +1256,14 @@ fn parse_keybindings(kbc: &KeybindingConfig) -> Result<Keybinding, CommandParseE
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use toml;
 
-    use super::{get_keybindings, ModeKeybindingConfig, DEFAULT_KEYBINDINGS};
+    use super::{
+        complete, describe, get_keybindings, parse, Command, MessageSelector, ModeKeybindingConfig,
+        NotificationContent, WindowMode, DEFAULT_KEYBINDINGS, ENTER_NEWLINE_KEYBINDINGS,
+    };
 
     #[test]
     fn default_keybindings_deserialize() {
@@ -534,14 +1272,172 @@ mod tests {
 
     #[test]
     fn default_keybindings_parse() {
-        get_keybindings(&ModeKeybindingConfig::new(), true).unwrap();
+        get_keybindings(&ModeKeybindingConfig::new(), true, true).unwrap();
+    }
+
+    #[test]
+    fn enter_newline_keybindings() {
+        let _keybindings: ModeKeybindingConfig = toml::from_str(ENTER_NEWLINE_KEYBINDINGS).unwrap();
+        let alt_enter = crokey::parse("alt-enter").unwrap();
+
+        let keybindings = get_keybindings(&ModeKeybindingConfig::new(), true, true).unwrap();
+        assert_eq!(
+            keybindings[&WindowMode::Normal].get(&alt_enter),
+            Some(&Command::ToggleMultiline)
+        );
+        let keybindings = get_keybindings(&ModeKeybindingConfig::new(), true, false).unwrap();
+        assert_eq!(
+            keybindings[&WindowMode::Normal].get(&alt_enter),
+            Some(&Command::SendMessage)
+        );
     }
 
     #[test]
     fn custom_keybindings() {
         let bindings: ModeKeybindingConfig =
             toml::from_str("[normal]\n  F1 = \"\"\n  ctrl-h = \"help\"\n").unwrap();
-        get_keybindings(&bindings, true).unwrap();
-        get_keybindings(&bindings, false).unwrap();
+        get_keybindings(&bindings, true, true).unwrap();
+        get_keybindings(&bindings, false, true).unwrap();
+    }
+
+    #[test]
+    fn parse_mute() {
+        assert_eq!(parse("mute").unwrap(), Command::Mute(None));
+        assert_eq!(
+            parse("mute 8h").unwrap(),
+            Command::Mute(Some(Duration::from_secs(8 * 60 * 60)))
+        );
+        assert!(parse("mute 8x").is_err());
+        assert!(parse("mute h").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn parse_birthday_and_note() {
+        assert_eq!(parse("birthday").unwrap(), Command::Birthday(None));
+        assert_eq!(
+            parse("birthday 05-17").unwrap(),
+            Command::Birthday(Some("05-17".parse().unwrap()))
+        );
+        assert!(parse("birthday 17.05.").is_err());
+        assert_eq!(
+            parse("note likes  green tea").unwrap(),
+            Command::Note("likes green tea".to_string())
+        );
+        assert_eq!(parse("note").unwrap(), Command::Note(String::new()));
+        assert_eq!(
+            parse("nick Bob 🍕").unwrap(),
+            Command::Nick("Bob 🍕".to_string())
+        );
+        assert_eq!(parse("nick").unwrap(), Command::Nick(String::new()));
+    }
+
+    #[test]
+    fn parse_keep_messages() {
+        assert_eq!(parse("keep_messages").unwrap(), Command::KeepMessages(None));
+        assert_eq!(
+            parse("keep_messages 500").unwrap(),
+            Command::KeepMessages(Some(500))
+        );
+        assert!(parse("keep_messages 0").is_err());
+        assert!(parse("keep_messages all").is_err());
+    }
+
+    #[test]
+    fn parse_snippet() {
+        assert_eq!(
+            parse("snippet checkin").unwrap(),
+            Command::Snippet("checkin".to_string())
+        );
+        assert!(parse("snippet").is_err());
+    }
+
+    #[test]
+    fn parse_profile() {
+        assert_eq!(
+            parse("export_profile ~/gurk profile.toml").unwrap(),
+            Command::ExportProfile("~/gurk profile.toml".to_string())
+        );
+        assert_eq!(
+            parse("import_profile profile.toml").unwrap(),
+            Command::ImportProfile("profile.toml".to_string())
+        );
+        assert!(parse("import_profile").is_err());
+    }
+
+    #[test]
+    fn parse_marked_messages() {
+        assert_eq!(
+            parse("copy_message marked").unwrap(),
+            Command::CopyMessage(MessageSelector::Marked)
+        );
+        assert_eq!(
+            parse("export_messages ~/log snippets.txt").unwrap(),
+            Command::ExportMessages("~/log snippets.txt".to_string())
+        );
+        assert!(parse("export_messages").is_err());
+    }
+
+    #[test]
+    fn parse_notification_content() {
+        assert_eq!(
+            parse("notification_content sender").unwrap(),
+            Command::NotificationContent(Some(NotificationContent::Sender))
+        );
+        assert_eq!(
+            parse("notification_content").unwrap(),
+            Command::NotificationContent(None)
+        );
+        assert!(parse("notification_content secret").is_err());
+    }
+
+    #[test]
+    fn parse_help_and_attach() {
+        assert_eq!(parse("help").unwrap(), Command::Help(None));
+        assert_eq!(
+            parse("help mute").unwrap(),
+            Command::Help(Some("mute".to_string()))
+        );
+        assert_eq!(
+            parse("attach ~/cat.jpg").unwrap(),
+            Command::Attach("~/cat.jpg".to_string())
+        );
+        assert!(parse("attach").is_err());
+    }
+
+    #[test]
+    fn test_complete() {
+        assert_eq!(complete("unmu"), vec!["unmute"]);
+        assert!(complete("").len() > 10);
+        assert_eq!(complete("select_channel "), vec!["previous", "next"]);
+        assert_eq!(complete("select_message next e"), vec!["entry", "end"]);
+        assert_eq!(complete("mute 1"), vec!["15m", "1w"]);
+        // placeholders are not completed
+        assert!(complete("keep_messages ").is_empty());
+        assert!(complete("birthday ").is_empty());
+        assert!(complete("attach ").is_empty());
+        assert!(complete("unknown ").is_empty());
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(
+            describe("attach").as_deref(),
+            Some("attach <path>: Attach the file to the message in the input box")
+        );
+        assert_eq!(describe("quit").as_deref(), Some("quit: Quit application"));
+        assert_eq!(describe("unknown"), None);
+    }
+
+    #[test]
+    fn parse_unlink_device() {
+        assert_eq!(
+            parse("unlink_device confirm").unwrap(),
+            Command::UnlinkDevice
+        );
+        assert!(parse("unlink_device").is_err());
+        assert!(parse("unlink_device now").is_err());
+        assert_eq!(parse("clear confirm").unwrap(), Command::Clear);
+        assert!(parse("clear").is_err());
     }
 }
@@ -1,11 +1,16 @@
 use anyhow::{anyhow, bail, Context};
+use chrono::NaiveTime;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+use std::collections::BTreeMap;
 use std::fs;
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use crate::command::ModeKeybindingConfig;
+use crate::data::NotificationContent;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Config {
@@ -15,6 +20,9 @@ pub struct Config {
     /// Path to the Signal database containing the linked device data.
     #[serde(default = "default_signal_db_path")]
     pub signal_db_path: PathBuf,
+    /// Directory of the downloaded attachments, their index and the crash reports
+    #[serde(default = "default_data_dir")]
+    pub data_dir: PathBuf,
     /// Whether only to show the first name of a contact
     #[serde(default)]
     pub first_name_only: bool,
@@ -24,6 +32,12 @@ pub struct Config {
     /// Whether to show system notifications on incoming messages
     #[serde(default = "default_true")]
     pub notifications: bool,
+    /// Content of the notifications and when they are suppressed
+    #[serde(default)]
+    pub notification: NotificationConfig,
+    /// Whether the time of the messages is shown with the 24-hour or the 12-hour clock
+    #[serde(default)]
+    pub clock: ClockFormat,
     #[serde(default = "default_true")]
     pub bell: bool,
     /// User configuration
@@ -45,6 +59,107 @@ pub struct Config {
     /// Whether to enable the default keybindings
     #[serde(default = "default_true")]
     pub default_keybindings: bool,
+    /// If set, the first `Enter` shows a preview of the message as it will be sent; the second
+    /// `Enter` sends it
+    #[serde(default)]
+    pub send_preview: bool,
+    /// Whether to suppress notifications for all group channels
+    #[serde(default)]
+    pub mute_groups: bool,
+    /// When to show notifications for reactions
+    #[serde(default)]
+    pub reaction_notifications: ReactionNotifications,
+    /// Whether to show a notification on the birthday of a contact (see the `birthday` command)
+    #[serde(default = "default_true")]
+    pub birthday_reminders: bool,
+    /// Ignore the send key for this many milliseconds after a message was sent, preventing
+    /// accidental double sends (0 disables it)
+    #[serde(default)]
+    pub send_debounce_ms: u64,
+    /// Highlight messages arriving in the selected channel for this many milliseconds (0 disables
+    /// it)
+    #[serde(default)]
+    pub highlight_new_messages_ms: u64,
+    /// Whether `enter` sends the message; if unset, `enter` inserts a new line and `alt+enter`
+    /// sends the message
+    #[serde(default = "default_true")]
+    pub enter_sends: bool,
+    /// Whether to show the time since the last message under each channel
+    #[serde(default)]
+    pub channel_details: bool,
+    /// If set, channels without messages in this many days are dimmed in the channel list
+    #[serde(default)]
+    pub dormant_after_days: Option<u64>,
+    #[serde(default)]
+    pub layout: LayoutConfig,
+    #[serde(default)]
+    pub terminal: TerminalConfig,
+    #[serde(default)]
+    pub theme: ThemeConfig,
+    #[serde(default)]
+    pub mark_read: MarkReadConfig,
+    /// If set, the channel list is sorted by last activity every this many seconds (and on
+    /// `sort_channels`) instead of moving a channel to the top on each new message
+    #[serde(default)]
+    pub channel_sort_interval_secs: Option<u64>,
+    #[serde(default)]
+    pub channel_list: ChannelListConfig,
+    /// Palette of the colors of the senders, e.g. `["red", "#ffaa00", "42"]`
+    ///
+    /// Each sender gets a stable color from the palette. If empty, a default palette is used.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sender_colors: Vec<String>,
+    #[serde(default)]
+    pub phone_numbers: PhoneNumberConfig,
+    /// Reactions sent with the keys `1` to `6` on the selected message, as emoji or `:shortcode:`
+    #[serde(default = "default_favorite_reactions")]
+    pub favorite_reactions: Vec<String>,
+    /// Number of messages per channel loaded at startup and when scrolling up beyond them
+    #[serde(default = "default_history_page_size")]
+    pub history_page_size: usize,
+    /// Maximum number of messages per channel kept in memory with the SQLite storage; older
+    /// messages stay in the database and are read from it when needed. Defaults to
+    /// `history_page_size`.
+    #[serde(default)]
+    pub max_messages_in_memory: Option<usize>,
+    /// If set, the number of unread messages is reported to the terminal as user variable with
+    /// this name on each change, e.g. for showing a badge in the tmux or WezTerm status bar
+    #[serde(default)]
+    pub unread_user_var: Option<String>,
+    /// If set, a summary like "3 unread in 2 chats" is written into the terminal title (the pane
+    /// title in tmux) after the terminal has been unfocused for this many minutes, and into the
+    /// user variable `<unread_user_var>_summary` if that is set. Requires a terminal reporting
+    /// focus changes.
+    #[serde(default)]
+    pub unread_summary_after_minutes: Option<u64>,
+    /// If set, messages older than this many days are deleted together with their attachments
+    #[serde(default)]
+    pub retention_days: Option<u64>,
+    /// If set, only this many of the newest messages of each channel are kept; older messages
+    /// are deleted together with their attachments
+    #[serde(default)]
+    pub max_messages_per_channel: Option<usize>,
+    /// If set, the least recently opened downloaded attachments are deleted when all of them
+    /// take more than this many MiB; deleted attachments can be downloaded again
+    #[serde(default)]
+    pub max_attachments_mb: Option<u64>,
+    #[serde(default)]
+    pub media: MediaConfig,
+    /// Messages sent with `snippet <name>`, with placeholders like `{name}` and `{date}`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub snippets: BTreeMap<String, String>,
+    /// If set, gurk listens on this Unix socket for JSON-RPC requests, e.g. from bots or editors
+    #[serde(default)]
+    pub rpc_socket: Option<PathBuf>,
+    /// Whether to run the D-Bus service `org.gurk.Messenger` (Linux and BSD only)
+    #[serde(default)]
+    pub dbus: bool,
+    /// Shell commands run on events, see [`HooksConfig`]
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    /// If set, `gurk daemon` serves metrics for Prometheus at `http://<address>/metrics`
+    #[serde(default)]
+    pub metrics_address: Option<SocketAddr>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -76,9 +191,12 @@ impl Config {
             user,
             data_path: default_data_path(),
             signal_db_path: default_signal_db_path(),
+            data_dir: default_data_dir(),
             first_name_only: false,
             show_receipts: true,
             notifications: true,
+            notification: Default::default(),
+            clock: Default::default(),
             bell: true,
             #[cfg(feature = "dev")]
             developer: Default::default(),
@@ -87,9 +205,50 @@ impl Config {
             colored_messages: false,
             default_keybindings: true,
             keybindings: ModeKeybindingConfig::default(),
+            send_preview: false,
+            mute_groups: false,
+            reaction_notifications: Default::default(),
+            birthday_reminders: true,
+            send_debounce_ms: 0,
+            highlight_new_messages_ms: 0,
+            enter_sends: true,
+            channel_details: false,
+            dormant_after_days: None,
+            layout: Default::default(),
+            terminal: Default::default(),
+            theme: Default::default(),
+            mark_read: Default::default(),
+            channel_sort_interval_secs: None,
+            channel_list: Default::default(),
+            sender_colors: Vec::new(),
+            phone_numbers: Default::default(),
+            favorite_reactions: default_favorite_reactions(),
+            history_page_size: default_history_page_size(),
+            max_messages_in_memory: None,
+            unread_user_var: None,
+            unread_summary_after_minutes: None,
+            retention_days: None,
+            max_messages_per_channel: None,
+            max_attachments_mb: None,
+            media: Default::default(),
+            snippets: BTreeMap::new(),
+            rpc_socket: None,
+            dbus: false,
+            hooks: Default::default(),
+            metrics_address: None,
         }
     }
 
+    /// Moves the data file, the Signal database, the sqlite database and the attachments into the
+    /// directory
+    pub fn set_data_dir(&mut self, dir: &Path) -> anyhow::Result<()> {
+        self.sqlite.url = SqliteConfig::db_url_in(dir).context("invalid data directory")?;
+        self.data_path = dir.join("gurk.data.json");
+        self.signal_db_path = dir.join("signal-db");
+        self.data_dir = dir.to_path_buf();
+        Ok(())
+    }
+
     /// Tries to load configuration from one of the default locations:
     ///
     /// 1. $XDG_CONFIG_HOME/gurk/gurk.toml
@@ -112,6 +271,17 @@ impl Config {
         self.save_new_at(config_file)
     }
 
+    /// Saves the config to the installed config file, or to the default location
+    pub fn save_installed(&self) -> anyhow::Result<()> {
+        let config_file = match installed_config() {
+            Some(config_file) => config_file,
+            None => dirs::config_dir()
+                .ok_or_else(|| anyhow!("could not find default config directory"))?
+                .join("gurk/gurk.toml"),
+        };
+        self.save(config_file)
+    }
+
     fn save_new_at(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
         // check that config won't be overridden
         if path.as_ref().exists() {
@@ -149,6 +319,385 @@ impl Config {
     }
 }
 
+/// Layout of the channel list (sidebar) and the chat
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct LayoutConfig {
+    /// Width of the channel list in percent of the terminal width
+    #[serde(default = "LayoutConfig::default_sidebar_width")]
+    pub sidebar_width: u16,
+    /// Whether to hide the channel list at startup
+    #[serde(default)]
+    pub hide_sidebar: bool,
+    /// Whether to show the channel list above the chat at startup
+    #[serde(default)]
+    pub stacked: bool,
+    /// If set, the channel list is always shown above the chat in terminals narrower than this
+    /// many columns
+    #[serde(default)]
+    pub stack_below_width: Option<u16>,
+    /// Whether to show the newest message at the top of the chat at startup
+    #[serde(default)]
+    pub newest_first: bool,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            sidebar_width: Self::default_sidebar_width(),
+            hide_sidebar: false,
+            stacked: false,
+            stack_below_width: None,
+            newest_first: false,
+        }
+    }
+}
+
+impl LayoutConfig {
+    fn default_sidebar_width() -> u16 {
+        25
+    }
+}
+
+/// Sections of the channel list and the order of the channels within them
+///
+/// Pinned channels come first, then the other channels, and the archived channels last.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ChannelListConfig {
+    /// Whether the direct and the group channels are listed in separate sections, each under a
+    /// header
+    #[serde(default)]
+    pub sections: bool,
+    #[serde(default)]
+    pub pinned: ChannelOrder,
+    /// Order of the direct channels, or of all unpinned channels if they are not in sections
+    #[serde(default)]
+    pub direct: ChannelOrder,
+    #[serde(default)]
+    pub groups: ChannelOrder,
+    #[serde(default)]
+    pub archived: ChannelOrder,
+}
+
+/// Order of the channels within a section of the channel list
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelOrder {
+    /// Newest message first
+    #[default]
+    Recent,
+    /// By name
+    Alphabetical,
+    /// Channels with unread messages first, then by newest message
+    UnreadFirst,
+}
+
+/// Capabilities of the terminal, detected at startup unless set
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TerminalConfig {
+    /// Whether the terminal can show unicode symbols and borders; if unset, detected from the
+    /// locale
+    #[serde(default)]
+    pub unicode: Option<bool>,
+    /// Number of colors of the terminal; if unset, detected from `$COLORTERM` and `$TERM`
+    #[serde(default)]
+    pub colors: Option<ColorDepth>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum ColorDepth {
+    /// The 8 basic ANSI colors
+    #[serde(rename = "8")]
+    Colors8,
+    #[serde(rename = "256")]
+    Colors256,
+    #[serde(rename = "truecolor")]
+    TrueColor,
+}
+
+/// Colors for a dark or light terminal background
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ThemeConfig {
+    #[serde(default)]
+    pub mode: ThemeMode,
+    /// Local time from which the light theme is used in the `auto` mode, e.g. `07:00`
+    #[serde(default)]
+    pub light_from: Option<String>,
+    /// Local time from which the dark theme is used in the `auto` mode, e.g. `19:30`
+    #[serde(default)]
+    pub dark_from: Option<String>,
+    /// Built-in colors, which the configured `colors` override
+    #[serde(default)]
+    pub preset: ThemePreset,
+    #[serde(default)]
+    pub colors: ThemeColorsConfig,
+}
+
+/// Colors of parts of the UI
+///
+/// Each is set as `"<fg>"`, `"<fg> on <bg>"` or `"on <bg>"`, with colors like in
+/// `sender_colors`, e.g. `"black on #93a1a1"`. Unset parts keep the colors of the preset.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ThemeColorsConfig {
+    /// The selected channel and the selected entries of lists and popups
+    #[serde(default)]
+    pub selected: Option<String>,
+    /// Text of our own messages
+    #[serde(default)]
+    pub own_messages: Option<String>,
+    /// Text of the messages of others
+    #[serde(default)]
+    pub other_messages: Option<String>,
+    /// Time of the messages
+    #[serde(default)]
+    pub timestamps: Option<String>,
+    /// Number of unread messages in the channel list
+    #[serde(default)]
+    pub unread: Option<String>,
+    #[serde(default)]
+    pub status_bar: Option<String>,
+    #[serde(default)]
+    pub popups: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThemePreset {
+    /// The colors gurk is designed with, adapted to the light mode by darkening them
+    #[default]
+    Default,
+    /// Colors of the Solarized palette
+    Solarized,
+}
+
+impl ThemeConfig {
+    /// Times from which the light and the dark theme are used, if both are set
+    pub fn schedule(&self) -> anyhow::Result<Option<(NaiveTime, NaiveTime)>> {
+        let parse = |time: &str| {
+            NaiveTime::parse_from_str(time, "%H:%M")
+                .with_context(|| format!("invalid theme time: {time}, expected HH:MM"))
+        };
+        match (self.light_from.as_deref(), self.dark_from.as_deref()) {
+            (Some(light_from), Some(dark_from)) => {
+                Ok(Some((parse(light_from)?, parse(dark_from)?)))
+            }
+            (None, None) => Ok(None),
+            _ => bail!("theme.light_from and theme.dark_from must be set together"),
+        }
+    }
+}
+
+/// Content of the notifications about messages and when they are suppressed
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct NotificationConfig {
+    /// How much of a message is shown: `full`, `sender` or `hidden` (only "New message");
+    /// can be overridden per channel with `notification_content`
+    #[serde(default)]
+    pub content: NotificationContent,
+    /// Whether to notify while gurk is focused
+    ///
+    /// Without `focus_command`, requires a terminal reporting focus changes.
+    #[serde(default = "default_true")]
+    pub when_focused: bool,
+    /// If set, gurk is focused when this shell command succeeds, e.g. for checking that its tmux
+    /// pane is the active one, instead of relying on the focus reported by the terminal
+    #[serde(default)]
+    pub focus_command: Option<String>,
+    /// Urgency of the notifications about messages; only supported on Linux and BSD
+    #[serde(default)]
+    pub urgency: NotificationUrgency,
+    /// Whether the notifications about messages have the actions "Open" (clicking it) and
+    /// "Mark read", where the notification daemon supports them
+    #[serde(default = "default_true")]
+    pub actions: bool,
+    /// If set, this shell command is run for each notification, e.g. for playing a sound
+    #[serde(default)]
+    pub sound_command: Option<String>,
+}
+
+/// Shell commands run on events
+///
+/// The event is written as JSON to the standard input of the command. Each line the command
+/// writes to its standard output may be an action as JSON, e.g.
+/// `{"action": "reply", "text": "I'm on vacation"}` or `{"action": "mark_read"}`, which is
+/// applied to the channel of the message.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HooksConfig {
+    /// Run for each message received from others
+    #[serde(default)]
+    pub on_message_received: Option<String>,
+    /// Run for each message sent from gurk; replies of hooks are not sent to it
+    #[serde(default)]
+    pub on_message_sent: Option<String>,
+    /// Run once when gurk is started
+    #[serde(default)]
+    pub on_startup: Option<String>,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            content: Default::default(),
+            when_focused: true,
+            focus_command: None,
+            urgency: Default::default(),
+            actions: true,
+            sound_command: None,
+        }
+    }
+}
+
+/// When messages arriving in the selected channel are marked as read
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct MarkReadConfig {
+    /// Whether messages are marked as read while the terminal is not focused
+    ///
+    /// Requires a terminal reporting focus changes.
+    #[serde(default = "default_true")]
+    pub when_unfocused: bool,
+    /// If set, messages are not marked as read after this many minutes without a keypress
+    #[serde(default)]
+    pub idle_minutes: Option<u64>,
+}
+
+impl Default for MarkReadConfig {
+    fn default() -> Self {
+        Self {
+            when_unfocused: true,
+            idle_minutes: None,
+        }
+    }
+}
+
+/// Downloading and sending attachments with less data
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct MediaConfig {
+    /// Whether received attachments are downloaded right away; otherwise they are downloaded
+    /// with `download_attachments` or when opened in the attachment gallery
+    #[serde(default = "default_true")]
+    pub auto_download: bool,
+    /// Whether received attachments are downloaded right away over a metered connection, as
+    /// reported by NetworkManager (Linux and BSD only)
+    #[serde(default = "default_true")]
+    pub auto_download_when_metered: bool,
+    /// If set, received attachments larger than this many MiB are not downloaded right away
+    #[serde(default)]
+    pub auto_download_max_mb: Option<u64>,
+    /// If set, sent images wider or higher than this many pixels are scaled down to fit
+    #[serde(default)]
+    pub image_max_size: Option<u32>,
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        Self {
+            auto_download: true,
+            auto_download_when_metered: true,
+            auto_download_max_mb: None,
+            image_max_size: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThemeMode {
+    #[default]
+    Dark,
+    Light,
+    /// Follows the schedule if set, otherwise the background color of the terminal detected at
+    /// startup
+    Auto,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationUrgency {
+    Low,
+    #[default]
+    Normal,
+    /// Usually stays until dismissed
+    Critical,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReactionNotifications {
+    #[default]
+    Always,
+    /// Only for reactions in direct channels
+    DirectOnly,
+    Never,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum ClockFormat {
+    /// e.g. `15:04`
+    #[default]
+    #[serde(rename = "24h")]
+    H24,
+    /// e.g. ` 3:04pm`
+    #[serde(rename = "12h")]
+    H12,
+}
+
+impl ClockFormat {
+    /// Format of the time followed by a space
+    pub(crate) fn format(self) -> &'static str {
+        match self {
+            Self::H24 => "%R ",
+            Self::H12 => "%l:%M%P ",
+        }
+    }
+
+    /// Width of the formatted time
+    pub(crate) fn width(self) -> usize {
+        match self {
+            Self::H24 => "00:00 ".len(),
+            Self::H12 => "00:00pm ".len(),
+        }
+    }
+}
+
+/// Parsing and formatting of phone numbers
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct PhoneNumberConfig {
+    /// Region of phone numbers entered without country code, as ISO 3166 code, e.g. `DE`
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Format of the phone numbers of the region; other numbers are always shown in the
+    /// international format
+    #[serde(default)]
+    pub format: PhoneNumberFormat,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PhoneNumberFormat {
+    /// E.g. `+49 30 123456`
+    #[default]
+    International,
+    /// E.g. `030 123456`
+    National,
+}
+
+impl PhoneNumberConfig {
+    pub fn region(&self) -> anyhow::Result<Option<phonenumber::country::Id>> {
+        self.region
+            .as_deref()
+            .map(|region| {
+                region
+                    .to_uppercase()
+                    .parse()
+                    .with_context(|| format!("invalid phone number region: {region}"))
+            })
+            .transpose()
+    }
+}
+
+/// Storage of channels and messages in a SQLite database
+///
+/// Enabled by default. When disabled, everything is stored in the JSON file at `data_path`, which
+/// is rewritten on every change.
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct SqliteConfig {
     #[serde(default = "default_true")]
@@ -165,7 +714,7 @@ pub struct SqliteConfig {
 impl Default for SqliteConfig {
     fn default() -> Self {
         Self {
-            enabled: false,
+            enabled: true,
             url: Self::default_db_url(),
             preserve_unencrypted: false,
         }
@@ -174,13 +723,46 @@ impl Default for SqliteConfig {
 
 impl SqliteConfig {
     fn default_db_url() -> Url {
-        let path = default_data_dir().join("gurk.sqlite");
-        format!("sqlite://{}", path.display())
-            .parse()
-            .expect("invalid default sqlite path")
+        Self::db_url_in(&default_data_dir()).expect("invalid default sqlite path")
+    }
+
+    fn db_url_in(dir: &Path) -> anyhow::Result<Url> {
+        let path = dir.join("gurk.sqlite");
+        Ok(format!("sqlite://{}", path.display()).parse()?)
     }
 }
 
+/// Watches the installed config file for changes, e.g. for reloading the theme
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    /// Watches the config file loaded by [`Config::load_installed`], if there is one
+    pub fn installed() -> Option<Self> {
+        let path = installed_config()?;
+        let modified = modified_at(&path);
+        Some(Self { path, modified })
+    }
+
+    /// Loads the config again, if the file was modified since the last call
+    pub fn poll(&mut self) -> Option<anyhow::Result<Config>> {
+        let modified = modified_at(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(Config::load(&self.path))
+    }
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
 /// Get the location of the first found default config file paths
 /// according to the following order:
 ///
@@ -222,7 +804,17 @@ pub fn fallback_data_path() -> Option<PathBuf> {
     dirs::home_dir().map(|p| p.join(".gurk.data.json"))
 }
 
-fn default_data_dir() -> PathBuf {
+fn default_favorite_reactions() -> Vec<String> {
+    ["👍", "❤️", "😂", "😮", "😢", "🙏"]
+        .map(String::from)
+        .to_vec()
+}
+
+fn default_history_page_size() -> usize {
+    500
+}
+
+pub(crate) fn default_data_dir() -> PathBuf {
     match dirs::data_dir() {
         Some(dir) => dir.join("gurk"),
         None => panic!("default data directory not found, $XDG_DATA_HOME and $HOME are unset"),
@@ -291,4 +883,41 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_sqlite_enabled_by_default() {
+        let config: Config = toml::from_str(
+            r#"
+            [user]
+            name = "Tyler Durden"
+            phone_number = "+0000000000"
+            "#,
+        )
+        .unwrap();
+        assert!(config.sqlite.enabled);
+    }
+
+    #[test]
+    fn test_set_data_dir() {
+        let mut config = Config::with_user(example_user());
+        assert_eq!(config.clock, ClockFormat::H24);
+        config.set_data_dir(Path::new("/srv/gurk")).unwrap();
+        assert_eq!(config.data_path, Path::new("/srv/gurk/gurk.data.json"));
+        assert_eq!(config.signal_db_path, Path::new("/srv/gurk/signal-db"));
+        assert_eq!(config.data_dir, Path::new("/srv/gurk"));
+        assert_eq!(config.sqlite.url.as_str(), "sqlite:///srv/gurk/gurk.sqlite");
+
+        let toml = toml::to_string(&config).unwrap();
+        assert!(toml.contains("clock = \"24h\""));
+    }
+
+    #[test]
+    fn test_phone_number_region() {
+        let config: PhoneNumberConfig = toml::from_str("region = \"de\"").unwrap();
+        assert_eq!(config.region().unwrap(), Some(phonenumber::country::Id::DE));
+        assert_eq!(config.format, PhoneNumberFormat::International);
+
+        let config: PhoneNumberConfig = toml::from_str("region = \"Germany\"").unwrap();
+        assert!(config.region().is_err());
+    }
 }
@@ -0,0 +1,319 @@
+//! Known contacts listed in a popup, with the nicknames and the blocked state kept locally
+
+use std::collections::BTreeSet;
+
+use phonenumber::PhoneNumber;
+use presage::proto::sync_message::Blocked;
+use presage::proto::verified;
+use ratatui::widgets::ListState;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::app::App;
+use crate::input::Input;
+use crate::storage::LocalContact;
+
+/// Popup listing the known contacts
+#[derive(Default)]
+pub(crate) struct ContactList {
+    pub is_shown: bool,
+    pub state: ListState,
+    /// Input of the nickname of the selected contact, while renaming
+    pub rename: Option<Input>,
+    /// Result of the last action, e.g. blocking the selected contact
+    pub status: Option<String>,
+    /// Contacts sorted by the name they are shown with
+    items: Vec<ContactItem>,
+}
+
+pub(crate) struct ContactItem {
+    pub id: Uuid,
+    /// Name the contact is shown with, i.e. the nickname if set
+    pub name: String,
+    pub phone_number: Option<PhoneNumber>,
+    pub profile_name: Option<String>,
+    pub nickname: Option<String>,
+    pub blocked: bool,
+    /// Whether the safety number is verified, as synced from the primary device
+    pub verified: bool,
+}
+
+impl ContactList {
+    /// Shows the popup with the contacts
+    pub fn open(&mut self, mut items: Vec<ContactItem>) {
+        items.sort_by_cached_key(|item| item.name.to_lowercase());
+        self.items = items;
+        self.rename = None;
+        self.status = None;
+        self.state = Default::default();
+        if !self.items.is_empty() {
+            self.state.select(Some(0));
+        }
+        self.is_shown = true;
+    }
+
+    pub fn close(&mut self) {
+        self.is_shown = false;
+        self.rename = None;
+        self.items.clear();
+    }
+
+    pub fn items(&self) -> &[ContactItem] {
+        &self.items
+    }
+
+    pub fn prev(&mut self) {
+        let selected = self
+            .state
+            .selected()
+            .map(|idx| idx.saturating_sub(1))
+            .unwrap_or(0);
+        self.state.select(Some(selected));
+    }
+
+    pub fn next(&mut self) {
+        let selected = self
+            .state
+            .selected()
+            .map(|idx| (idx + 1).min(self.items.len().saturating_sub(1)))
+            .unwrap_or(0);
+        self.state.select(Some(selected));
+    }
+
+    pub fn selected(&self) -> Option<&ContactItem> {
+        self.items.get(self.state.selected()?)
+    }
+
+    fn selected_mut(&mut self) -> Option<&mut ContactItem> {
+        self.items.get_mut(self.state.selected()?)
+    }
+
+    /// Shows the input for the nickname of the selected contact, prefilled with the current one
+    pub fn start_rename(&mut self) {
+        let Some(item) = self.selected() else {
+            return;
+        };
+        let mut input = Input::default();
+        item.nickname
+            .iter()
+            .flat_map(|nickname| nickname.chars())
+            .for_each(|c| input.put_char(c));
+        self.rename = Some(input);
+    }
+
+    pub fn cancel_rename(&mut self) {
+        self.rename = None;
+    }
+}
+
+impl App {
+    /// Shows the popup with the contacts of the Signal store, the users of the direct channels,
+    /// and the users with local settings, e.g. blocked ones
+    pub(crate) async fn open_contact_list(&mut self) {
+        let local_contacts = self.storage.metadata().into_owned().contacts;
+        let mut user_ids: BTreeSet<Uuid> = local_contacts.keys().copied().collect();
+        let mut verified_ids = BTreeSet::new();
+        for contact in self.signal_manager.contacts().await {
+            if contact.verified.state() == verified::State::Verified {
+                verified_ids.insert(contact.uuid);
+            }
+            if let Some(phone_number) = contact.phone_number {
+                self.names
+                    .get_mut()
+                    .set_phone_number(contact.uuid, phone_number);
+            }
+            user_ids.insert(contact.uuid);
+        }
+        user_ids.extend(
+            self.storage
+                .channels()
+                .filter_map(|channel| channel.id.user()),
+        );
+        user_ids.remove(&self.user_id);
+
+        let mut items = Vec::with_capacity(user_ids.len());
+        for user_id in user_ids {
+            let local_contact = local_contacts.get(&user_id).cloned().unwrap_or_default();
+            items.push(ContactItem {
+                id: user_id,
+                name: self.name_by_id_cached(user_id),
+                phone_number: self.names.get_mut().phone_number(user_id).cloned(),
+                profile_name: self.signal_manager.profile_name(user_id).await,
+                nickname: local_contact.nickname,
+                blocked: local_contact.blocked,
+                verified: verified_ids.contains(&user_id),
+            });
+        }
+        self.contact_list.open(items);
+    }
+
+    /// Whether the messages, reactions and receipts of the user are dropped
+    pub(crate) fn is_blocked(&self, user_id: Uuid) -> bool {
+        self.names.borrow().is_blocked(user_id)
+    }
+
+    /// Blocks the users blocked on the primary device, synced from there
+    ///
+    /// Users unblocked on the primary device stay blocked here if they were blocked here, since
+    /// blocking is local.
+    pub(crate) fn handle_blocked_sync(&mut self, blocked: &Blocked) {
+        let names = self.names.get_mut();
+        let by_phone_number = blocked.numbers.iter().filter_map(|number| {
+            let phone_number = phonenumber::parse(None, number).ok()?;
+            names.by_phone_number(&phone_number)
+        });
+        let user_ids: BTreeSet<Uuid> = blocked
+            .acis
+            .iter()
+            .filter_map(|aci| aci.parse().ok())
+            .chain(by_phone_number)
+            .filter(|&user_id| user_id != self.user_id && !names.is_blocked(user_id))
+            .collect();
+        for &user_id in &user_ids {
+            self.update_local_contact(user_id, |contact| contact.blocked = true);
+            self.names.get_mut().set_blocked(user_id, true);
+        }
+        if !user_ids.is_empty() {
+            info!(
+                count = user_ids.len(),
+                "blocked contacts synced from primary device"
+            );
+        }
+    }
+
+    /// Sets the nickname the user is shown with everywhere instead of the resolved name, or
+    /// clears it
+    pub(crate) fn set_nickname(&mut self, user_id: Uuid, nickname: Option<String>) {
+        self.update_local_contact(user_id, |contact| contact.nickname.clone_from(&nickname));
+        self.names.get_mut().set_nickname(user_id, nickname);
+        // the messages show the names of the senders
+        self.message_layouts.get_mut().clear();
+    }
+
+    /// Sets the nickname of the selected contact to the one in the rename input
+    ///
+    /// An empty nickname clears it.
+    pub(crate) fn rename_selected_contact(&mut self) {
+        let Some(input) = self.contact_list.rename.take() else {
+            return;
+        };
+        let Some(user_id) = self.contact_list.selected().map(|item| item.id) else {
+            return;
+        };
+        let nickname = Some(input.data.trim().to_string()).filter(|nickname| !nickname.is_empty());
+        self.set_nickname(user_id, nickname.clone());
+        let name = self.name_by_id_cached(user_id);
+        if let Some(item) = self.contact_list.selected_mut() {
+            item.name = name;
+            item.nickname = nickname;
+        }
+    }
+
+    /// Blocks the selected contact, or unblocks them
+    ///
+    /// Blocking is local only: the contact is not blocked on the other devices and is not told
+    /// about it.
+    pub(crate) fn toggle_block_selected_contact(&mut self) {
+        let Some(item) = self.contact_list.selected_mut() else {
+            return;
+        };
+        item.blocked = !item.blocked;
+        let (user_id, blocked) = (item.id, item.blocked);
+        let status = if blocked {
+            format!("blocked {}; their messages are dropped", item.name)
+        } else {
+            format!("unblocked {}", item.name)
+        };
+        self.contact_list.status = Some(status);
+        self.update_local_contact(user_id, |contact| contact.blocked = blocked);
+        self.names.get_mut().set_blocked(user_id, blocked);
+        info!(%user_id, blocked, "changed blocked state of contact");
+    }
+
+    /// Closes the popup and selects the direct channel of the selected contact
+    pub(crate) async fn open_selected_contact_dm(&mut self) {
+        let Some(item) = self.contact_list.selected() else {
+            return;
+        };
+        let (user_id, name) = (item.id, item.name.clone());
+        self.contact_list.close();
+        self.open_user_channel(user_id, &name).await;
+    }
+
+    fn update_local_contact(&mut self, user_id: Uuid, f: impl FnOnce(&mut LocalContact)) {
+        let mut metadata = self.storage.metadata().into_owned();
+        let contact = metadata.contacts.entry(user_id).or_default();
+        f(contact);
+        if contact.is_empty() {
+            metadata.contacts.remove(&user_id);
+        }
+        self.storage.store_metadata(metadata);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::tests::test_app;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rename_and_block_contact() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let user_id = app.channels.items[0].user().unwrap();
+        app.names
+            .get_mut()
+            .set_display_name(user_id, "Alice".to_string());
+
+        app.open_contact_list().await;
+        assert_eq!(app.contact_list.items().len(), 1);
+        assert_eq!(app.contact_list.selected().unwrap().name, "Alice");
+
+        app.contact_list.start_rename();
+        "Ali".chars().for_each(|c| app.get_input().put_char(c));
+        app.rename_selected_contact();
+        assert!(app.contact_list.rename.is_none());
+        assert_eq!(app.contact_list.selected().unwrap().name, "Ali");
+        assert_eq!(app.name_by_id_cached(user_id), "Ali");
+        assert_eq!(
+            app.storage.metadata().contacts[&user_id]
+                .nickname
+                .as_deref(),
+            Some("Ali")
+        );
+
+        app.toggle_block_selected_contact();
+        assert!(app.is_blocked(user_id));
+        app.toggle_block_selected_contact();
+        assert!(!app.is_blocked(user_id));
+
+        // clearing the nickname shows the resolved name again and forgets the contact
+        app.contact_list.start_rename();
+        app.get_input().take();
+        app.rename_selected_contact();
+        assert_eq!(app.name_by_id_cached(user_id), "Alice");
+        assert!(app.storage.metadata().contacts.is_empty());
+
+        app.open_selected_contact_dm().await;
+        assert!(!app.contact_list.is_shown);
+        assert_eq!(app.channels.selected_item(), Some(&user_id.into()));
+    }
+
+    #[tokio::test]
+    async fn test_blocked_sync() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let user_id = app.channels.items[0].user().unwrap();
+        let unknown = Uuid::new_v4();
+        let blocked = Blocked {
+            numbers: vec!["+4917612345678".to_string()],
+            acis: vec![user_id.to_string(), app.user_id.to_string()],
+            group_ids: Vec::new(),
+        };
+
+        app.handle_blocked_sync(&blocked);
+        assert!(app.is_blocked(user_id));
+        assert!(!app.is_blocked(app.user_id));
+        assert!(!app.is_blocked(unknown));
+        assert!(app.storage.metadata().contacts[&user_id].blocked);
+    }
+}
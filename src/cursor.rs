@@ -28,7 +28,6 @@ impl Cursor {
         Default::default()
     }
 
-    #[cfg(test)]
     pub fn end(text: &str) -> Self {
         let idx = snap_to_char(text, text.len());
         let (line, col) = calc_line_column(text, idx);
@@ -125,7 +124,14 @@ impl Cursor {
     }
 
     pub fn delete_line_backward(&mut self, text: &mut String) {
-        (0..self.col.max(1)).for_each(|_| self.delete_backward(text))
+        let end = self.idx;
+        self.start_of_line(text);
+        if self.idx < end {
+            text.replace_range(self.idx..end, "");
+        } else {
+            // already at the beginning of the line => join with the previous line
+            self.delete_backward(text);
+        }
     }
 
     pub fn delete_word_backward(&mut self, text: &mut String) {
@@ -152,16 +158,7 @@ impl Cursor {
                 .map(|(idx, _)| start + idx)
                 .unwrap_or_else(|| text.len());
 
-            let (idx, col) = text[start..end]
-                .char_indices()
-                .take(prev_col + 1)
-                .scan(0, |prev_width, (idx, c)| {
-                    let col = *prev_width;
-                    *prev_width += c.width().unwrap_or(0);
-                    Some((start + idx, col))
-                })
-                .last()
-                .unwrap_or((start, 0));
+            let (idx, col) = position_in_line(text, start, end, prev_col);
 
             self.col = col;
             self.idx = idx;
@@ -200,16 +197,7 @@ impl Cursor {
                 .map(|(idx, _)| idx + 1)
                 .unwrap_or(0);
 
-            let (idx, col) = text[start..end]
-                .char_indices()
-                .take(prev_col + 1)
-                .scan(0, |prev_width, (idx, c)| {
-                    let col = *prev_width;
-                    *prev_width += c.width().unwrap_or(0);
-                    Some((start + idx, col))
-                })
-                .last()
-                .unwrap_or((start, 0));
+            let (idx, col) = position_in_line(text, start, end, prev_col);
 
             self.col = col;
             self.idx = idx;
@@ -306,7 +294,7 @@ impl Cursor {
         let end = text[self.idx..]
             .char_indices()
             .find(|&(_, c)| c == '\n')
-            .map(|(idx, _)| idx)
+            .map(|(idx, _)| self.idx + idx)
             .unwrap_or_else(|| text.len());
         if self.idx == end && end < text.len() {
             text.remove(end);
@@ -319,7 +307,6 @@ impl Cursor {
 /// Snap the byte index `idx` to a char boundary in `s`.
 ///
 /// The snapping is always done to left starting at `idx`.
-#[cfg(test)]
 fn snap_to_char(s: &str, mut idx: usize) -> usize {
     if idx >= s.len() {
         s.len()
@@ -335,7 +322,7 @@ fn snap_to_char(s: &str, mut idx: usize) -> usize {
     }
 }
 
-#[cfg(test)]
+/// Line and display column of the byte index `idx` in `s`
 fn calc_line_column(s: &str, idx: usize) -> (usize, usize) {
     let mut col = 0;
     let mut line = 0;
@@ -345,13 +332,29 @@ fn calc_line_column(s: &str, idx: usize) -> (usize, usize) {
             line += 1;
         }
         if line == 0 {
-            col += 1;
+            col += c.width().unwrap_or(0);
         }
     }
 
     (line, col)
 }
 
+/// Byte index and display column of the position at column `col` in the line `text[start..end]`
+///
+/// If `col` is inside of a wide character, the position before the character is returned. If it
+/// is behind the end of the line, the end of the line is returned.
+fn position_in_line(text: &str, start: usize, end: usize, col: usize) -> (usize, usize) {
+    let mut position = (start, 0);
+    for (idx, c) in text[start..end].char_indices() {
+        let next_col = position.1 + c.width().unwrap_or(0);
+        if next_col > col {
+            break;
+        }
+        position = (start + idx + c.len_utf8(), next_col);
+    }
+    position
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -453,6 +456,65 @@ mod tests {
         assert_eq!(text, "");
     }
 
+    #[test]
+    fn test_delete_suffix_in_later_line() {
+        let mut text = "Hello\nWorld\n!".to_string();
+        let mut cursor = Cursor::at(&text, 8);
+
+        cursor.delete_suffix(&mut text);
+        assert_eq!(cursor, Cursor::new(8, 1, 2));
+        assert_eq!(text, "Hello\nWo\n!");
+    }
+
+    #[test]
+    fn test_cursor_end_wide_characters() {
+        let text = "Hi\n🌍🌍";
+        assert_eq!(Cursor::end(text), Cursor::new(11, 1, 4));
+
+        let mut cursor = Cursor::end(text);
+        cursor.move_left(text);
+        assert_eq!(cursor, Cursor::new(7, 1, 2));
+    }
+
+    #[test]
+    fn test_move_line_to_shorter_line() {
+        let text = "Hello\nab\n🌍🌍🌍";
+        let mut cursor = Cursor::at(text, 4);
+        let position = |cursor: &Cursor| (cursor.idx, cursor.line, cursor.col);
+
+        // behind the end of the shorter line
+        cursor.move_line_down(text);
+        assert_eq!(position(&cursor), (8, 1, 2));
+
+        // wanted column is kept
+        cursor.move_line_down(text);
+        assert_eq!(position(&cursor), (17, 2, 4));
+        cursor.move_line_up(text);
+        assert_eq!(position(&cursor), (8, 1, 2));
+        cursor.move_line_up(text);
+        assert_eq!(position(&cursor), (4, 0, 4));
+
+        // in the middle of a wide character
+        let mut cursor = Cursor::at(text, 3);
+        cursor.move_line_down(text);
+        cursor.move_line_down(text);
+        assert_eq!(position(&cursor), (13, 2, 2));
+    }
+
+    #[test]
+    fn test_delete_line_backward_wide_characters() {
+        let mut text = "Hi\n🌍🌍".to_string();
+        let mut cursor = Cursor::end(&text);
+
+        cursor.delete_line_backward(&mut text);
+        assert_eq!(cursor, Cursor::new(3, 1, 0));
+        assert_eq!(text, "Hi\n");
+
+        cursor.delete_line_backward(&mut text);
+        assert_eq!(cursor, Cursor::new(2, 0, 2));
+        assert_eq!(text, "Hi");
+    }
+
     #[derive(Debug, Clone, Copy)]
     enum Operation {
         Left,
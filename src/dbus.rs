@@ -0,0 +1,251 @@
+//! D-Bus service `org.gurk.Messenger` for desktop integration on Linux and BSD
+//!
+//! The service at `/org/gurk/Messenger` has the methods `SendMessage(channel, text)` and
+//! `SetPresence(presence)`, and emits `MessageReceived(channel, from, text)`. Channels are
+//! identified like in hooks. Besides, the signals of the notification daemon are received, so that
+//! the actions and inline replies (e.g. from KDE Connect) of all notifications are handled. Without
+//! the service, only these signals are received by [`listen_notifications`].
+
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::event::Event;
+
+/// Message passed to the `MessageReceived` signal
+#[derive(Debug, Clone)]
+#[cfg_attr(not(all(unix, not(target_os = "macos"))), allow(dead_code))]
+pub(crate) struct MessageSignal {
+    pub channel: String,
+    pub from: String,
+    pub text: String,
+}
+
+/// Starts the service; the received messages are emitted as signals
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) fn serve(
+    tx: mpsc::UnboundedSender<Event>,
+    messages: mpsc::UnboundedReceiver<MessageSignal>,
+) -> Option<JoinHandle<()>> {
+    Some(tokio::spawn(async move {
+        if let Err(error) = service::run(tx, messages).await {
+            tracing::error!(%error, "D-Bus service failed");
+        }
+    }))
+}
+
+/// D-Bus is only used on Linux and BSD
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+pub(crate) fn serve(
+    _tx: mpsc::UnboundedSender<Event>,
+    _messages: mpsc::UnboundedReceiver<MessageSignal>,
+) -> Option<JoinHandle<()>> {
+    tracing::warn!("the D-Bus service is only supported on Linux and BSD");
+    None
+}
+
+/// Receives the signals of the notification daemon, for handling the actions of the notifications
+/// without running the service
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) fn listen_notifications(tx: mpsc::UnboundedSender<Event>) -> Option<JoinHandle<()>> {
+    Some(tokio::spawn(async move {
+        if let Err(error) = service::listen_notifications(tx).await {
+            tracing::warn!(%error, "failed to receive the actions of notifications");
+        }
+    }))
+}
+
+/// Actions are only supported by the notification daemons of Linux and BSD
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+pub(crate) fn listen_notifications(_tx: mpsc::UnboundedSender<Event>) -> Option<JoinHandle<()>> {
+    None
+}
+
+/// Reports whether the network connection is metered, polled from NetworkManager
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) fn watch_metered(tx: mpsc::UnboundedSender<Event>) -> Option<JoinHandle<()>> {
+    Some(tokio::spawn(async move {
+        if let Err(error) = network_manager::watch_metered(tx).await {
+            tracing::warn!(%error, "failed to query NetworkManager for metered connections");
+        }
+    }))
+}
+
+/// NetworkManager is only used on Linux and BSD
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+pub(crate) fn watch_metered(_tx: mpsc::UnboundedSender<Event>) -> Option<JoinHandle<()>> {
+    tracing::warn!("detecting metered connections is only supported on Linux and BSD");
+    None
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod network_manager {
+    use std::time::Duration;
+
+    use tokio::sync::mpsc;
+
+    use crate::event::Event;
+
+    const NAME: &str = "org.freedesktop.NetworkManager";
+    const PATH: &str = "/org/freedesktop/NetworkManager";
+    const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+    /// `NM_METERED_YES` and `NM_METERED_GUESS_YES`
+    const METERED: [u32; 2] = [1, 3];
+
+    pub(super) async fn watch_metered(tx: mpsc::UnboundedSender<Event>) -> zbus::Result<()> {
+        let connection = zbus::Connection::system().await?;
+        let proxy = zbus::Proxy::new(&connection, NAME, PATH, NAME).await?;
+        let mut is_metered = None;
+        loop {
+            let metered: u32 = proxy.get_property("Metered").await?;
+            let now_metered = METERED.contains(&metered);
+            if is_metered != Some(now_metered) {
+                is_metered = Some(now_metered);
+                if tx.send(Event::Metered(now_metered)).is_err() {
+                    return Ok(()); // app quit
+                }
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod service {
+    use tokio::sync::mpsc;
+    use tokio_stream::StreamExt;
+    use zbus::fdo;
+    use zbus::object_server::SignalContext;
+    use zbus::{MatchRule, MessageStream};
+
+    use super::MessageSignal;
+    use crate::event::{Event, NotificationSignal, Presence};
+    use crate::util;
+
+    const NAME: &str = "org.gurk.Messenger";
+    const PATH: &str = "/org/gurk/Messenger";
+    const NOTIFICATIONS_INTERFACE: &str = "org.freedesktop.Notifications";
+
+    struct Messenger {
+        tx: mpsc::UnboundedSender<Event>,
+    }
+
+    impl Messenger {
+        fn send(&self, event: Event) -> fdo::Result<()> {
+            self.tx
+                .send(event)
+                .map_err(|_| fdo::Error::Failed("gurk is quitting".to_string()))
+        }
+    }
+
+    #[zbus::interface(name = "org.gurk.Messenger")]
+    impl Messenger {
+        async fn send_message(&self, channel: String, text: String) -> fdo::Result<()> {
+            let channel_id = util::parse_channel_id(&channel)
+                .ok_or_else(|| fdo::Error::InvalidArgs(format!("invalid channel {channel}")))?;
+            self.send(Event::SendMessage { channel_id, text })
+        }
+
+        /// `available` or `dnd`
+        async fn set_presence(&self, presence: String) -> fdo::Result<()> {
+            let presence: Presence = presence
+                .parse()
+                .map_err(|error: anyhow::Error| fdo::Error::InvalidArgs(error.to_string()))?;
+            self.send(Event::SetPresence(presence))
+        }
+
+        #[zbus(signal)]
+        async fn message_received(
+            ctxt: &SignalContext<'_>,
+            channel: &str,
+            from: &str,
+            text: &str,
+        ) -> zbus::Result<()>;
+    }
+
+    pub(super) async fn run(
+        tx: mpsc::UnboundedSender<Event>,
+        mut messages: mpsc::UnboundedReceiver<MessageSignal>,
+    ) -> zbus::Result<()> {
+        let connection = zbus::connection::Builder::session()?
+            .name(NAME)?
+            .serve_at(PATH, Messenger { tx: tx.clone() })?
+            .build()
+            .await?;
+        let messenger = connection
+            .object_server()
+            .interface::<_, Messenger>(PATH)
+            .await?;
+        let mut notification_signals = notification_signals(&connection).await?;
+
+        loop {
+            tokio::select! {
+                message = messages.recv() => {
+                    let Some(message) = message else {
+                        break; // app quit
+                    };
+                    Messenger::message_received(
+                        messenger.signal_context(),
+                        &message.channel,
+                        &message.from,
+                        &message.text,
+                    )
+                    .await?;
+                }
+                Some(msg) = notification_signals.next() => {
+                    let Some(event) = notification_event(msg?)? else {
+                        continue;
+                    };
+                    if tx.send(event).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) async fn listen_notifications(tx: mpsc::UnboundedSender<Event>) -> zbus::Result<()> {
+        let connection = zbus::Connection::session().await?;
+        let mut notification_signals = notification_signals(&connection).await?;
+        while let Some(msg) = notification_signals.next().await {
+            let Some(event) = notification_event(msg?)? else {
+                continue;
+            };
+            if tx.send(event).is_err() {
+                break; // app quit
+            }
+        }
+        Ok(())
+    }
+
+    /// Signals of the notification daemon, which are sent to all clients
+    async fn notification_signals(connection: &zbus::Connection) -> zbus::Result<MessageStream> {
+        let rule = MatchRule::builder()
+            .msg_type(zbus::message::Type::Signal)
+            .interface(NOTIFICATIONS_INTERFACE)?
+            .build();
+        MessageStream::for_match_rule(rule, connection, None).await
+    }
+
+    /// Event of the action or the inline reply signaled by the notification daemon
+    fn notification_event(msg: zbus::Message) -> zbus::Result<Option<Event>> {
+        let header = msg.header();
+        let body = msg.body();
+        let (notification_id, signal) = match header.member().map(|member| member.as_str()) {
+            Some("ActionInvoked") => {
+                let (id, key) = body.deserialize::<(u32, String)>()?;
+                (id, NotificationSignal::ActionInvoked(key))
+            }
+            Some("NotificationReplied") => {
+                let (id, text) = body.deserialize::<(u32, String)>()?;
+                (id, NotificationSignal::Replied(text))
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(Event::NotificationSignal {
+            notification_id,
+            signal,
+        }))
+    }
+}
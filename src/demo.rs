@@ -0,0 +1,208 @@
+//! Demo with a fake Signal account and a scripted conversation, for trying out gurk, its themes
+//! and keybindings, and for taking screenshots
+
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::app::{App, ConnectionState};
+use crate::config::{Config, User};
+use crate::data::{Channel, ChannelId, GroupData, Message, TypingSet};
+use crate::event::Event;
+use crate::signal::test::SignalManagerMock;
+use crate::storage::{ForgetfulStorage, MemCache, Storage};
+use crate::util::utc_now_timestamp_msec;
+
+const MINUTE_MSEC: u64 = 60 * 1000;
+const SCRIPT_INTERVAL: Duration = Duration::from_secs(8);
+
+const GROUP: &str = "Book Club";
+const GROUP_MASTER_KEY: [u8; 32] = [7; 32];
+
+/// Contacts with their ids
+const CONTACTS: [(&str, u128); 3] = [("Alice", 1), ("Bob", 2), ("Mom", 3)];
+
+/// Conversation before the demo starts: channel, sender (`None` for the user), text, and minutes
+/// ago
+const HISTORY: &[(&str, Option<&str>, &str, u64)] = &[
+    ("Mom", Some("Mom"), "Did you eat something today?", 300),
+    ("Mom", None, "Yes, mom 🙄", 290),
+    (
+        "Bob",
+        Some("Bob"),
+        "Are we still on for climbing tomorrow?",
+        120,
+    ),
+    ("Bob", None, "Sure, 6pm at the usual place", 115),
+    (GROUP, Some("Alice"), "Who finished the book?", 60),
+    (
+        GROUP,
+        Some("Bob"),
+        "Halfway through, no spoilers please",
+        55,
+    ),
+    (GROUP, None, "Finished it yesterday, the ending is wild", 50),
+    (
+        "Alice",
+        Some("Alice"),
+        "Can you bring the snacks on Friday?",
+        10,
+    ),
+];
+
+/// Messages arriving one after another while the demo runs: channel, sender, and text
+const SCRIPT: &[(&str, &str, &str)] = &[
+    ("Alice", "Alice", "Also, have you seen my umbrella?"),
+    (GROUP, "Bob", "Okay, I finished it. WOW"),
+    (GROUP, "Alice", "Right?! Next month's pick is mine 📚"),
+    ("Mom", "Mom", "Call me when you have time"),
+    ("Bob", "Bob", "Running 10 minutes late"),
+];
+
+/// App with the demo conversation, which never sends anything to Signal
+///
+/// The installed config is used for the theme and the keybindings, but hooks, plugins and
+/// services are left out.
+pub async fn app() -> anyhow::Result<(App, mpsc::UnboundedReceiver<Event>)> {
+    let user = User {
+        name: "You".to_string(),
+        phone_number: "+0000000000".to_string(),
+    };
+    let config = match Config::load_installed()? {
+        Some(config) => Config { user, ..config },
+        None => Config::with_user(user),
+    };
+    app_with(config).await
+}
+
+async fn app_with(mut config: Config) -> anyhow::Result<(App, mpsc::UnboundedReceiver<Event>)> {
+    let signal_manager = SignalManagerMock::new();
+    let user_id = crate::signal::SignalManager::user_id(&signal_manager);
+    config.hooks = Default::default();
+    config.rpc_socket = None;
+    config.dbus = false;
+    config.notifications = false;
+
+    let mut storage = MemCache::new(ForgetfulStorage);
+    for (name, id) in CONTACTS {
+        let id = Uuid::from_u128(id);
+        storage.store_name(id, name.to_string());
+        storage.store_channel(channel(ChannelId::User(id), name, None));
+    }
+    let mut members = vec![user_id];
+    members.extend(CONTACTS.iter().map(|&(_, id)| Uuid::from_u128(id)));
+    let group_data = GroupData {
+        master_key_bytes: GROUP_MASTER_KEY,
+        members,
+        revision: 1,
+    };
+    let group_id = ChannelId::from_master_key_bytes(GROUP_MASTER_KEY)?;
+    storage.store_channel(channel(group_id, GROUP, Some(group_data)));
+
+    let now = utc_now_timestamp_msec();
+    for &(channel_name, from, text, minutes_ago) in HISTORY {
+        let channel_id = channel_id(channel_name, group_id);
+        let from_id = from.map_or(user_id, contact_id);
+        let arrived_at = now - minutes_ago * MINUTE_MSEC;
+        let message = Message::new(from_id, Some(text.to_string()), [], arrived_at, Vec::new());
+        storage.store_message(channel_id, message);
+    }
+
+    let (mut app, events) = App::try_new(config, Box::new(signal_manager), Box::new(storage))?;
+    app.is_demo = true;
+    app.populate_names_cache().await;
+    app.set_connection_state(ConnectionState::Connected);
+    Ok((app, events))
+}
+
+impl App {
+    /// Starts receiving the scripted messages
+    pub fn start_demo_script(&self) {
+        let tx = self.event_tx.clone();
+        let Ok(group_id) = ChannelId::from_master_key_bytes(GROUP_MASTER_KEY) else {
+            return;
+        };
+        tokio::spawn(async move {
+            for &(channel_name, from, text) in SCRIPT {
+                tokio::time::sleep(SCRIPT_INTERVAL).await;
+                let event = Event::DemoMessage {
+                    channel_id: channel_id(channel_name, group_id),
+                    from: contact_id(from),
+                    text: text.to_string(),
+                };
+                if tx.send(event).is_err() {
+                    break; // app quit
+                }
+            }
+        });
+    }
+
+    pub(crate) fn on_demo_message(&mut self, channel_id: ChannelId, from: Uuid, text: String) {
+        let Some(channel_idx) = self.channel_idx(channel_id) else {
+            return;
+        };
+        let message = Message::new(from, Some(text), [], utc_now_timestamp_msec(), Vec::new());
+        self.add_message_to_channel(channel_idx, message);
+    }
+}
+
+fn channel(id: ChannelId, name: &str, group_data: Option<GroupData>) -> Channel {
+    Channel {
+        id,
+        name: name.to_string(),
+        typing: TypingSet::new(group_data.is_some()),
+        group_data,
+        unread_messages: 0,
+        muted_until: None,
+        pinned: false,
+        archived: false,
+        burn_after_reading: false,
+        max_messages: None,
+        ignore_mention_all: false,
+        notification_content: None,
+        nickname: None,
+    }
+}
+
+fn contact_id(name: &str) -> Uuid {
+    CONTACTS
+        .iter()
+        .find(|&&(contact, _)| contact == name)
+        .map(|&(_, id)| Uuid::from_u128(id))
+        .expect("contact of the demo")
+}
+
+fn channel_id(name: &str, group_id: ChannelId) -> ChannelId {
+    if name == GROUP {
+        group_id
+    } else {
+        ChannelId::User(contact_id(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_demo_message() {
+        let user = User {
+            name: "Tyler Durden".to_string(),
+            phone_number: "+0000000000".to_string(),
+        };
+        let (mut app, _events) = app_with(Config::with_user(user)).await.unwrap();
+        assert_eq!(app.channels.items.len(), CONTACTS.len() + 1);
+
+        let channel_id = ChannelId::User(contact_id("Bob"));
+        let unread = |app: &App| app.storage.channel(channel_id).unwrap().unread_messages;
+        assert_eq!(unread(&app), 0);
+        app.handle_event(Event::DemoMessage {
+            channel_id,
+            from: contact_id("Bob"),
+            text: "hi".to_string(),
+        })
+        .unwrap();
+        assert_eq!(unread(&app), 1);
+    }
+}
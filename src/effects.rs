@@ -0,0 +1,132 @@
+//! Long-running effects of the app, e.g. sending a message or downloading an attachment
+//!
+//! Each effect runs in its own task, which reports its progress and its result as an [`Event`].
+//! The effects are tracked until the result is handled, and can be cancelled before.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use tokio::task::AbortHandle;
+
+use crate::signal::SendProgress;
+use crate::storage::MessageId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EffectId(u64);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EffectKind {
+    Send(MessageId),
+    /// Downloading the attachment to the path
+    Download(PathBuf),
+}
+
+#[derive(Debug)]
+pub struct Effect {
+    pub kind: EffectKind,
+    /// Last step reported by the task
+    pub progress: Option<SendProgress>,
+    pub started_at: Instant,
+    abort: AbortHandle,
+}
+
+/// Running effects
+#[derive(Debug, Default)]
+pub struct Effects {
+    running: BTreeMap<EffectId, Effect>,
+    next_id: u64,
+}
+
+impl Effects {
+    /// Tracks the effect, whose task is spawned by `spawn` given the id of the effect
+    pub fn start(
+        &mut self,
+        kind: EffectKind,
+        spawn: impl FnOnce(EffectId) -> AbortHandle,
+    ) -> EffectId {
+        let id = EffectId(self.next_id);
+        self.next_id += 1;
+        let abort = spawn(id);
+        let effect = Effect {
+            kind,
+            progress: None,
+            started_at: Instant::now(),
+            abort,
+        };
+        self.running.insert(id, effect);
+        id
+    }
+
+    pub fn set_progress(&mut self, id: EffectId, progress: SendProgress) {
+        if let Some(effect) = self.running.get_mut(&id) {
+            effect.progress = Some(progress);
+        }
+    }
+
+    /// Stops tracking the effect, after its result was handled
+    pub fn finish(&mut self, kind: &EffectKind) -> Option<Effect> {
+        let id = self.find(kind)?;
+        self.running.remove(&id)
+    }
+
+    /// Aborts the task of the effect; its result is never reported
+    pub fn cancel(&mut self, kind: &EffectKind) -> Option<Effect> {
+        let effect = self.finish(kind)?;
+        effect.abort.abort();
+        Some(effect)
+    }
+
+    pub fn find(&self, kind: &EffectKind) -> Option<EffectId> {
+        self.running
+            .iter()
+            .find(|(_, effect)| &effect.kind == kind)
+            .map(|(&id, _)| id)
+    }
+
+    /// Running effects, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &Effect> {
+        self.running.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.running.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.running.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::data::ChannelId;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancel_aborts_task() {
+        let mut effects = Effects::default();
+        let kind = EffectKind::Send(MessageId::new(ChannelId::User(Default::default()), 1));
+        let mut task = None;
+        let id = effects.start(kind.clone(), |_| {
+            let handle = tokio::spawn(tokio::time::sleep(Duration::from_secs(60)));
+            let abort = handle.abort_handle();
+            task = Some(handle);
+            abort
+        });
+        effects.set_progress(id, SendProgress::Sending);
+        assert_eq!(effects.find(&kind), Some(id));
+        assert_eq!(
+            effects.iter().next().unwrap().progress,
+            Some(SendProgress::Sending)
+        );
+
+        assert!(effects.cancel(&kind).is_some());
+        assert!(effects.is_empty());
+        assert!(task.unwrap().await.unwrap_err().is_cancelled());
+        assert!(effects.cancel(&kind).is_none());
+    }
+}
@@ -0,0 +1,110 @@
+//! Recent errors, shown briefly in the input box and listed in the error log popup
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+
+/// Number of errors kept in the log
+const MAX_ERRORS: usize = 100;
+/// How long a new error is shown in the input box
+const TOAST_DURATION: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum ErrorKind {
+    /// Sending a message failed
+    Send,
+    /// Handling an incoming message or event failed
+    Receive,
+    /// Saving the messages or the attachments failed
+    Storage,
+    Config,
+}
+
+#[derive(Debug, Clone)]
+pub struct ErrorEntry {
+    pub at: DateTime<Local>,
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ErrorLog {
+    /// Newest last
+    entries: VecDeque<ErrorEntry>,
+    /// Whether the popup with the errors is shown
+    pub is_shown: bool,
+    /// Last error of saving the storage, shown in a banner until saving succeeds again
+    pub storage_error: Option<String>,
+    toast_until: Option<Instant>,
+}
+
+impl ErrorLog {
+    pub fn push(&mut self, kind: ErrorKind, message: String) {
+        if self.entries.len() == MAX_ERRORS {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(ErrorEntry {
+            at: Local::now(),
+            kind,
+            message,
+        });
+        self.toast_until = Some(Instant::now() + TOAST_DURATION);
+    }
+
+    /// Errors, newest first
+    pub fn entries(&self) -> impl Iterator<Item = &ErrorEntry> {
+        self.entries.iter().rev()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The newest error, if it just happened
+    pub fn toast(&self) -> Option<&ErrorEntry> {
+        self.toast_until
+            .filter(|&until| Instant::now() < until)
+            .and(self.entries.back())
+    }
+
+    pub fn dismiss_toast(&mut self) {
+        self.toast_until = None;
+    }
+
+    pub fn toggle(&mut self) {
+        self.is_shown = !self.is_shown;
+        self.dismiss_toast();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_log() {
+        let mut log = ErrorLog::default();
+        assert!(log.toast().is_none());
+
+        for idx in 0..MAX_ERRORS + 1 {
+            log.push(ErrorKind::Send, format!("error {idx}"));
+        }
+        assert_eq!(log.len(), MAX_ERRORS);
+        assert_eq!(
+            log.entries().next().unwrap().message,
+            format!("error {MAX_ERRORS}")
+        );
+        assert_eq!(log.entries().last().unwrap().message, "error 1");
+        assert_eq!(log.toast().unwrap().message, format!("error {MAX_ERRORS}"));
+
+        log.toggle();
+        assert!(log.is_shown);
+        assert!(log.toast().is_none());
+    }
+}
@@ -1,3 +1,16 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::bail;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::data::ChannelId;
+use crate::effects::EffectId;
+use crate::hooks::HookAction;
+use crate::rpc::Request;
+use crate::scheduler::Job;
+use crate::signal::{Attachment, SendProgress};
 use crate::storage::MessageId;
 
 #[derive(Debug)]
@@ -6,4 +19,94 @@ pub enum Event {
         message_id: MessageId,
         result: anyhow::Result<()>,
     },
+    /// Step reached by a running effect, see [`crate::effects`]
+    EffectProgress {
+        id: EffectId,
+        progress: SendProgress,
+    },
+    /// Attachment downloaded on request, replacing the deleted or not yet downloaded one at the
+    /// path
+    AttachmentDownloaded {
+        path: PathBuf,
+        result: anyhow::Result<Attachment>,
+    },
+    /// Action chosen in the notification about a message in the channel
+    NotificationAction {
+        channel_id: ChannelId,
+        action: NotificationAction,
+    },
+    /// Actions returned by a hook for the channel
+    HookActions {
+        channel_id: ChannelId,
+        actions: Vec<HookAction>,
+    },
+    /// Request received on the RPC socket; the response is sent to `reply`
+    RpcRequest {
+        request: Request,
+        reply: mpsc::UnboundedSender<String>,
+    },
+    /// Signal of the notification daemon about the notification with the id, received via D-Bus
+    NotificationSignal {
+        notification_id: u32,
+        signal: NotificationSignal,
+    },
+    /// Message to send to the channel, e.g. requested via D-Bus
+    SendMessage {
+        channel_id: ChannelId,
+        text: String,
+    },
+    SetPresence(Presence),
+    /// Whether the network connection is metered, as reported by NetworkManager
+    Metered(bool),
+    /// Background job which is due, run on the next tick
+    JobDue(Job),
+    /// Loads the messages of the next channels, see
+    /// [`crate::app::App::load_messages_in_background`]
+    LoadMessages,
+    /// Scripted message of the demo arriving in the channel
+    DemoMessage {
+        channel_id: ChannelId,
+        from: Uuid,
+        text: String,
+    },
+}
+
+/// Action of the notification about a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotificationAction {
+    /// The notification was clicked
+    Open,
+    MarkRead,
+    /// The text was entered in the notification, e.g. with KDE Connect
+    Reply(String),
+}
+
+/// Signal of the notification daemon
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotificationSignal {
+    /// The action with the key was invoked
+    ActionInvoked(String),
+    /// The text was entered as inline reply
+    Replied(String),
+}
+
+/// Availability of the user; there is no presence in Signal, so it only affects gurk itself
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Presence {
+    #[default]
+    Available,
+    /// No notifications and no bell
+    DoNotDisturb,
+}
+
+impl FromStr for Presence {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "available" => Ok(Self::Available),
+            "dnd" | "do_not_disturb" => Ok(Self::DoNotDisturb),
+            _ => bail!("unknown presence {s}, expected available or dnd"),
+        }
+    }
 }
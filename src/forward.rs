@@ -0,0 +1,205 @@
+//! Forwarding messages to other channels
+
+use std::borrow::Cow;
+
+use tracing::info;
+
+use crate::app::{App, QueuedMessage};
+use crate::data::{ChannelId, Message};
+use crate::storage::MessageId;
+
+impl App {
+    /// Opens the channel pop-up for choosing the channel the marked messages, or else the
+    /// selected one, are forwarded to
+    pub(crate) fn pick_forward_channel(&mut self) {
+        let mut message_ids = self.selected_channel_marks();
+        if message_ids.is_empty() {
+            message_ids.extend(self.selected_message_id());
+        }
+        if message_ids.is_empty() {
+            return;
+        }
+        self.select_channel
+            .reset(&*self.storage, &self.names.borrow());
+        self.select_channel.forward = message_ids;
+        self.select_channel.is_shown = true;
+    }
+
+    /// Sends the texts and the attachments of the messages again to the channel, marked as
+    /// forwarded
+    ///
+    /// Nothing is sent if one of the messages cannot be forwarded, e.g. because its attachments
+    /// are not downloaded.
+    pub(crate) fn forward_messages(&mut self, message_ids: &[MessageId], channel_id: ChannelId) {
+        if self.is_offline() {
+            self.command_error = Some("messages cannot be forwarded while offline".to_string());
+            return;
+        }
+        let mut messages = Vec::with_capacity(message_ids.len());
+        for &message_id in message_ids {
+            let Some(message) = self.storage.message(message_id).map(Cow::into_owned) else {
+                continue;
+            };
+            match self.forwarded_message(&message, channel_id) {
+                Ok(message) => messages.push(message),
+                Err(error) => {
+                    self.command_error = Some(error);
+                    return;
+                }
+            }
+        }
+        for message_id in message_ids {
+            self.marked_messages.remove(message_id);
+        }
+        let count = messages.len();
+        for message in messages {
+            self.send_queued(message, None);
+        }
+        info!(?channel_id, count, "forwarded messages");
+    }
+
+    /// Message sending the text and the attachments of the message, with the mentions replaced
+    /// by the names
+    ///
+    /// The text is sent as it is and never parsed as input, so that links to local files in a
+    /// received message cannot attach these files.
+    fn forwarded_message(
+        &self,
+        message: &Message,
+        channel_id: ChannelId,
+    ) -> Result<QueuedMessage, String> {
+        if message
+            .attachments
+            .iter()
+            .any(|attachment| !attachment.filename.is_file())
+        {
+            return Err("download the attachments before forwarding them".to_string());
+        }
+        let input = self.spelled_out_text(message);
+        if input.is_empty() && message.attachments.is_empty() {
+            return Err("the message has nothing to forward".to_string());
+        }
+        Ok(QueuedMessage {
+            channel_id,
+            input,
+            quote: None,
+            editing: None,
+            attachments: Some(message.attachments.clone()),
+            forwarded: true,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::tests::test_app;
+    use crate::command::Command;
+    use crate::data::{AssociatedValue, BodyRange, MENTION_PLACEHOLDER};
+    use crate::signal::Attachment;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_forward_message() {
+        let (mut app, _events, sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let user_id = channel_id.user().unwrap();
+        app.names
+            .get_mut()
+            .set_display_name(user_id, "Alice".to_string());
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, b"notes").unwrap();
+        let attachment = Attachment {
+            id: "notes".to_string(),
+            content_type: "text/plain".to_string(),
+            filename: path,
+            size: 5,
+        };
+        let mention = BodyRange {
+            start: 0,
+            end: 1,
+            value: AssociatedValue::MentionUuid(user_id),
+        };
+        let message = Message::new(
+            user_id,
+            Some(format!("{MENTION_PLACEHOLDER} look")),
+            [mention],
+            10,
+            vec![attachment],
+        );
+        app.storage.store_message(channel_id, message);
+        let message_id = MessageId::new(channel_id, 10);
+
+        app.on_command(Command::ForwardMessage).await.unwrap();
+        // nothing is selected
+        assert!(!app.select_channel.is_shown);
+
+        app.forward_messages(&[message_id], channel_id);
+        assert_eq!(app.command_error, None);
+        let sent = sent_messages.borrow();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].message.as_deref(), Some("@Alice look"));
+        let forwarded = app
+            .storage
+            .message(MessageId::new(channel_id, sent[0].arrived_at))
+            .unwrap();
+        assert!(forwarded.forwarded);
+    }
+
+    #[tokio::test]
+    async fn test_forward_does_not_attach_linked_files() {
+        let (mut app, _events, sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("id_rsa");
+        std::fs::write(&path, b"secret").unwrap();
+        let text = format!("see file://{} and file://clip", path.display());
+        let message = Message::new(app.user_id, Some(text.clone()), [], 10, Vec::new());
+        app.storage.store_message(channel_id, message);
+
+        app.forward_messages(&[MessageId::new(channel_id, 10)], channel_id);
+        assert_eq!(app.command_error, None);
+        let sent = sent_messages.borrow();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].message.as_deref(), Some(text.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_forward_messages_with_distinct_timestamps() {
+        let (mut app, _events, sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        for arrived_at in [10, 11, 12] {
+            let message = Message::new(app.user_id, Some("hi".into()), [], arrived_at, Vec::new());
+            app.storage.store_message(channel_id, message);
+        }
+        let message_ids = [10, 11, 12].map(|arrived_at| MessageId::new(channel_id, arrived_at));
+
+        app.forward_messages(&message_ids, channel_id);
+        let sent = sent_messages.borrow();
+        assert_eq!(sent.len(), 3);
+        assert!(sent[0].arrived_at < sent[1].arrived_at);
+        assert!(sent[1].arrived_at < sent[2].arrived_at);
+    }
+
+    #[tokio::test]
+    async fn test_forward_requires_downloaded_attachments() {
+        let (mut app, _events, sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let attachment = Attachment {
+            id: "photo".to_string(),
+            content_type: "image/jpeg".to_string(),
+            filename: "/nonexistent/photo.jpg".into(),
+            size: 4,
+        };
+        let message = Message::new(app.user_id, None, [], 10, vec![attachment]);
+        app.storage.store_message(channel_id, message);
+
+        app.forward_messages(&[MessageId::new(channel_id, 10)], channel_id);
+        assert_eq!(
+            app.command_error.as_deref(),
+            Some("download the attachments before forwarding them")
+        );
+        assert!(sent_messages.borrow().is_empty());
+    }
+}
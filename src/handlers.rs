@@ -78,10 +78,7 @@ impl App {
             self.storage.store_message(channel_id, original);
 
             let channel_idx = self
-                .channels
-                .items
-                .iter()
-                .position(|id| id == &channel_id)
+                .channel_idx(channel_id)
                 .context("editing message in non-existent channel")?;
             self.touch_channel(channel_idx);
         }
@@ -97,10 +94,13 @@ impl App {
             .filter_map(|read| {
                 let arrived_at = read.timestamp?;
                 let channel_id = self.storage.message_channel(arrived_at)?;
+                // reading never adds unread messages, so only the newest ones are counted
+                let unread_messages = self.storage.channel(channel_id)?.unread_messages;
                 let num_unread = self
                     .storage
                     .messages(channel_id)
                     .rev()
+                    .take(unread_messages as usize)
                     .take_while(|msg| arrived_at < msg.arrived_at)
                     .count();
                 let num_unread: u32 = num_unread.try_into().ok()?;
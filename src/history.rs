@@ -0,0 +1,211 @@
+//! History of sent inputs per channel
+
+use std::collections::BTreeMap;
+
+use uuid::Uuid;
+
+use crate::command::MoveDirection;
+use crate::data::ChannelId;
+use crate::input::Input;
+use crate::storage::Storage;
+
+/// Maximum number of inputs kept per channel
+const HISTORY_LIMIT: usize = 100;
+
+/// Number of the newest messages of a channel searched for sent inputs
+const HISTORY_SCAN_LIMIT: usize = 1000;
+
+/// History of sent inputs per channel, recalled like in a shell
+///
+/// The history of a channel is initialized lazily from the messages sent by the user.
+#[derive(Debug, Default)]
+pub(crate) struct InputHistory {
+    entries: BTreeMap<ChannelId, Vec<String>>,
+    /// Recalled entry counted from the newest one
+    position: Option<usize>,
+    /// Input which was in the input box before the recalling started
+    stash: Option<String>,
+    /// Reverse search over the history of the current channel
+    pub(crate) search: Option<HistorySearch>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct HistorySearch {
+    pub(crate) query: Input,
+    /// Number of skipped (newer) matches
+    skip: usize,
+}
+
+impl InputHistory {
+    fn load(
+        &mut self,
+        channel_id: ChannelId,
+        storage: &dyn Storage,
+        user_id: Uuid,
+    ) -> &mut Vec<String> {
+        self.entries.entry(channel_id).or_insert_with(|| {
+            let mut entries: Vec<String> = storage
+                .messages_before(channel_id, u64::MAX, HISTORY_SCAN_LIMIT)
+                .into_iter()
+                .rev()
+                .filter(|message| message.from_id == user_id)
+                .filter_map(|message| message.message.clone())
+                .filter(|text| !text.is_empty())
+                .take(HISTORY_LIMIT)
+                .collect();
+            entries.reverse();
+            entries
+        })
+    }
+
+    /// Adds a sent input to the history of the channel and stops recalling
+    pub(crate) fn push(
+        &mut self,
+        channel_id: ChannelId,
+        storage: &dyn Storage,
+        user_id: Uuid,
+        input: String,
+    ) {
+        self.reset();
+        let entries = self.load(channel_id, storage, user_id);
+        if entries.last() != Some(&input) {
+            entries.push(input);
+        }
+        if entries.len() > HISTORY_LIMIT {
+            entries.remove(0);
+        }
+    }
+
+    /// Returns the older (previous) or newer (next) entry relative to the recalled one
+    ///
+    /// Recalling the next entry after the newest one returns the input which was in the input box
+    /// before the recalling started. Returns `None` if there is nothing to recall.
+    pub(crate) fn recall(
+        &mut self,
+        channel_id: ChannelId,
+        storage: &dyn Storage,
+        user_id: Uuid,
+        direction: MoveDirection,
+        current_input: &str,
+    ) -> Option<String> {
+        let position = self.position;
+        let len = self.load(channel_id, storage, user_id).len();
+        match (direction, position) {
+            (MoveDirection::Previous, None) if len > 0 => {
+                self.stash = Some(current_input.to_string());
+                self.position = Some(0);
+            }
+            (MoveDirection::Previous, Some(position)) if position + 1 < len => {
+                self.position = Some(position + 1);
+            }
+            (MoveDirection::Next, Some(0)) => {
+                self.position = None;
+                return Some(self.stash.take().unwrap_or_default());
+            }
+            (MoveDirection::Next, Some(position)) => {
+                self.position = Some(position - 1);
+            }
+            _ => return None,
+        }
+        let position = self.position?;
+        let entries = self.entries.get(&channel_id)?;
+        entries.get(entries.len() - 1 - position).cloned()
+    }
+
+    /// Stops recalling, e.g. after the input was sent or the channel was changed
+    pub(crate) fn reset(&mut self) {
+        self.position = None;
+        self.stash = None;
+        self.search = None;
+    }
+
+    /// Starts the reverse search, or skips to the next older match if already searching
+    pub(crate) fn search_older(
+        &mut self,
+        channel_id: ChannelId,
+        storage: &dyn Storage,
+        user_id: Uuid,
+    ) {
+        self.load(channel_id, storage, user_id);
+        let Some(search) = self.search.as_mut() else {
+            self.search = Some(Default::default());
+            return;
+        };
+        let entries = self.entries.get(&channel_id).map(Vec::as_slice);
+        if search_matches(entries.unwrap_or_default(), &search.query.data)
+            .nth(search.skip + 1)
+            .is_some()
+        {
+            search.skip += 1;
+        }
+    }
+
+    /// The entry matching the reverse search query
+    pub(crate) fn search_match(&self, channel_id: ChannelId) -> Option<&str> {
+        let search = self.search.as_ref()?;
+        let entries = self.entries.get(&channel_id)?;
+        let mut matches = search_matches(entries, &search.query.data);
+        matches
+            .clone()
+            .nth(search.skip)
+            .or_else(|| matches.next())
+            .map(String::as_str)
+    }
+}
+
+fn search_matches<'a>(
+    entries: &'a [String],
+    query: &'a str,
+) -> impl Iterator<Item = &'a String> + Clone {
+    entries
+        .iter()
+        .rev()
+        .filter(move |entry| !query.is_empty() && entry.contains(query))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::storage::{ForgetfulStorage, MemCache};
+
+    fn history_with(entries: &[&str]) -> (InputHistory, ChannelId, MemCache<ForgetfulStorage>) {
+        let storage = MemCache::new(ForgetfulStorage);
+        let channel_id = ChannelId::User(Uuid::nil());
+        let mut history = InputHistory::default();
+        for entry in entries {
+            history.push(channel_id, &storage, Uuid::nil(), entry.to_string());
+        }
+        (history, channel_id, storage)
+    }
+
+    #[test]
+    fn test_recall() {
+        let (mut history, channel_id, storage) = history_with(&["first", "second"]);
+        let mut recall =
+            |direction| history.recall(channel_id, &storage, Uuid::nil(), direction, "draft");
+
+        assert_eq!(recall(MoveDirection::Previous).as_deref(), Some("second"));
+        assert_eq!(recall(MoveDirection::Previous).as_deref(), Some("first"));
+        assert_eq!(recall(MoveDirection::Previous), None);
+        assert_eq!(recall(MoveDirection::Next).as_deref(), Some("second"));
+        assert_eq!(recall(MoveDirection::Next).as_deref(), Some("draft"));
+        assert_eq!(recall(MoveDirection::Next), None);
+    }
+
+    #[test]
+    fn test_search() {
+        let (mut history, channel_id, storage) = history_with(&["foo 1", "bar", "foo 2"]);
+
+        history.search_older(channel_id, &storage, Uuid::nil());
+        assert_eq!(history.search_match(channel_id), None);
+        for c in "foo".chars() {
+            history.search.as_mut().unwrap().query.put_char(c);
+        }
+        assert_eq!(history.search_match(channel_id), Some("foo 2"));
+        history.search_older(channel_id, &storage, Uuid::nil());
+        assert_eq!(history.search_match(channel_id), Some("foo 1"));
+        history.search_older(channel_id, &storage, Uuid::nil());
+        assert_eq!(history.search_match(channel_id), Some("foo 1"));
+    }
+}
@@ -0,0 +1,226 @@
+//! Scripting hooks: shell commands run on events, which can reply with actions
+//!
+//! See [`HooksConfig`](crate::config::HooksConfig) for the protocol.
+
+use std::io::Write as _;
+use std::process::Stdio;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::data::ChannelId;
+use crate::event::Event;
+use crate::signal::Attachment;
+use crate::util;
+
+/// Action returned by a hook, applied to the channel of the message
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum HookAction {
+    /// Sends the text to the channel
+    Reply {
+        text: String,
+    },
+    MarkRead,
+}
+
+/// Event written as JSON to a hook
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum HookEvent<'a> {
+    MessageReceived(MessageEvent<'a>),
+    MessageSent(MessageEvent<'a>),
+    Startup { user: &'a str },
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct MessageEvent<'a> {
+    pub channel: ChannelEvent<'a>,
+    /// Name of the sender
+    pub from: &'a str,
+    pub text: &'a str,
+    pub timestamp: u64,
+    /// File names of the attachments
+    pub attachments: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ChannelEvent<'a> {
+    /// UUID of the contact, or the base64 encoded id of the group
+    pub id: String,
+    pub name: &'a str,
+    pub is_group: bool,
+}
+
+impl<'a> ChannelEvent<'a> {
+    pub fn new(channel_id: ChannelId, name: &'a str) -> Self {
+        Self {
+            id: util::channel_id_to_string(channel_id),
+            name,
+            is_group: matches!(channel_id, ChannelId::Group(_)),
+        }
+    }
+}
+
+/// Channel in the list of channels for scripts
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ChannelSummary {
+    pub id: String,
+    pub name: String,
+    pub is_group: bool,
+    pub unread: u32,
+}
+
+impl ChannelSummary {
+    pub fn new(channel_id: ChannelId, name: String, unread: u32) -> Self {
+        Self {
+            id: util::channel_id_to_string(channel_id),
+            name,
+            is_group: matches!(channel_id, ChannelId::Group(_)),
+            unread,
+        }
+    }
+}
+
+/// File names of the attachments for a hook event
+pub(crate) fn attachment_names(attachments: &[Attachment]) -> Vec<String> {
+    attachments
+        .iter()
+        .map(|attachment| attachment.filename.display().to_string())
+        .collect()
+}
+
+/// Runs the hook in a thread, and sends the returned actions for the channel to the app
+///
+/// Hooks without a channel (like `on_startup`) cannot return actions.
+pub(crate) fn spawn(
+    command: &str,
+    event: &HookEvent,
+    channel_id: Option<ChannelId>,
+    tx: mpsc::UnboundedSender<Event>,
+) {
+    let input = match serde_json::to_vec(event) {
+        Ok(input) => input,
+        Err(error) => {
+            warn!(%error, "failed to serialize the hook event");
+            return;
+        }
+    };
+    let command = command.to_string();
+    std::thread::spawn(move || match run(&command, &input) {
+        Ok(actions) if actions.is_empty() => {}
+        Ok(actions) => match channel_id {
+            Some(channel_id) => {
+                // the app might have quit in the meantime
+                let _ = tx.send(Event::HookActions {
+                    channel_id,
+                    actions,
+                });
+            }
+            None => warn!(%command, "ignoring actions of a hook without channel"),
+        },
+        Err(error) => warn!(%command, %error, "failed to run hook"),
+    });
+}
+
+/// Runs the hook with the input and waits for its actions
+fn run(command: &str, input: &[u8]) -> anyhow::Result<Vec<HookAction>> {
+    let mut child = util::shell_command(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // the hook is free not to read its input
+        let _ = stdin.write_all(input);
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        anyhow::bail!("hook exited with {}", output.status);
+    }
+    Ok(parse_actions(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Actions in the output of a hook, one JSON object per line; other lines are ignored
+fn parse_actions(output: &str) -> Vec<HookAction> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with('{'))
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(action) => Some(action),
+            Err(error) => {
+                warn!(line, %error, "invalid hook action");
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn test_event_json() {
+        let uuid = Uuid::nil();
+        let event = HookEvent::MessageReceived(MessageEvent {
+            channel: ChannelEvent::new(ChannelId::User(uuid), "Tyler"),
+            from: "Tyler",
+            text: "hi",
+            timestamp: 42,
+            attachments: vec!["photo.jpg".to_string()],
+        });
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({
+                "event": "message_received",
+                "channel": {"id": uuid.to_string(), "name": "Tyler", "is_group": false},
+                "from": "Tyler",
+                "text": "hi",
+                "timestamp": 42,
+                "attachments": ["photo.jpg"],
+            })
+        );
+
+        let event = HookEvent::Startup { user: "Tyler" };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({"event": "startup", "user": "Tyler"})
+        );
+    }
+
+    #[test]
+    fn test_parse_actions() {
+        let output = r#"
+            checking the message
+            {"action": "reply", "text": "I'm on vacation"}
+            {"action": "unknown"}
+            {"action": "mark_read"}
+        "#;
+        assert_eq!(
+            parse_actions(output),
+            [
+                HookAction::Reply {
+                    text: "I'm on vacation".to_string()
+                },
+                HookAction::MarkRead,
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run() {
+        let actions = run(
+            r#"grep -q '"text":"ping"' && echo '{"action": "mark_read"}'"#,
+            br#"{"text":"ping"}"#,
+        )
+        .unwrap();
+        assert_eq!(actions, [HookAction::MarkRead]);
+        assert!(run("exit 1", b"").is_err());
+    }
+}
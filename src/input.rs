@@ -75,3 +75,129 @@ impl Input {
         std::mem::take(&mut self.data)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use quickcheck::{Arbitrary, Gen};
+    use quickcheck_macros::quickcheck;
+    use unicode_width::UnicodeWidthChar;
+
+    use super::*;
+
+    #[derive(Debug, Clone, Copy)]
+    enum Edit {
+        Put(char),
+        NewLine,
+        Left,
+        Right,
+        LineDown,
+        LineUp,
+        BackWord,
+        ForwardWord,
+        Home,
+        End,
+        Backspace,
+        DeleteLine,
+        DeleteWord,
+        DeleteSuffix,
+        Take,
+    }
+
+    impl Arbitrary for Edit {
+        fn arbitrary(g: &mut Gen) -> Self {
+            use Edit::*;
+            let c = char::arbitrary(g);
+            // inserting is more likely, so that there is text to move around in
+            *g.choose(&[
+                Put(c),
+                Put(c),
+                Put(c),
+                Put(' '),
+                NewLine,
+                Left,
+                Right,
+                LineDown,
+                LineUp,
+                BackWord,
+                ForwardWord,
+                Home,
+                End,
+                Backspace,
+                DeleteLine,
+                DeleteWord,
+                DeleteSuffix,
+                Take,
+            ])
+            .unwrap()
+        }
+    }
+
+    /// Line and display column of the byte index in the text
+    fn line_column(text: &str, idx: usize) -> (usize, usize) {
+        let before = &text[..idx];
+        let line = before.matches('\n').count();
+        let col = before
+            .rsplit('\n')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .map(|c| c.width().unwrap_or(0))
+            .sum();
+        (line, col)
+    }
+
+    #[quickcheck]
+    fn test_random_edits_keep_cursor_consistent(initial: String, edits: Vec<Edit>) -> bool {
+        let mut input = Input {
+            cursor: Cursor::end(&initial),
+            data: initial,
+        };
+
+        for edit in edits {
+            let chars_count = input.data.chars().count();
+            let is_at_start = input.cursor.idx == 0;
+
+            match edit {
+                Edit::Put(c) => input.put_char(c),
+                Edit::NewLine => input.new_line(),
+                Edit::Left => input.on_left(),
+                Edit::Right => input.on_right(),
+                Edit::LineDown => input.move_line_down(),
+                Edit::LineUp => input.move_line_up(),
+                Edit::BackWord => input.move_back_word(),
+                Edit::ForwardWord => input.move_forward_word(),
+                Edit::Home => input.on_home(),
+                Edit::End => input.on_end(),
+                Edit::Backspace => input.on_backspace(),
+                Edit::DeleteLine => input.on_delete_line(),
+                Edit::DeleteWord => input.on_delete_word(),
+                Edit::DeleteSuffix => input.on_delete_suffix(),
+                Edit::Take => {
+                    input.take();
+                }
+            }
+
+            let new_chars_count = input.data.chars().count();
+            let is_chars_count_consistent = match edit {
+                Edit::Put(_) | Edit::NewLine => new_chars_count == chars_count + 1,
+                Edit::Backspace if is_at_start => new_chars_count == chars_count,
+                Edit::Backspace => new_chars_count + 1 == chars_count,
+                Edit::DeleteLine | Edit::DeleteWord | Edit::DeleteSuffix => {
+                    new_chars_count <= chars_count
+                }
+                Edit::Take => new_chars_count == 0,
+                _ => new_chars_count == chars_count,
+            };
+            let cursor = &input.cursor;
+            let is_on_boundary = input.data.is_char_boundary(cursor.idx);
+            if !is_chars_count_consistent
+                || !is_on_boundary
+                || line_column(&input.data, cursor.idx) != (cursor.line, cursor.col)
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+}
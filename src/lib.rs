@@ -1,21 +1,49 @@
 //! Signal Messenger client for terminal
 
+pub mod activity;
 pub mod app;
+pub mod attachment_cache;
+mod attachment_gallery;
+mod attachment_picker;
 pub mod backoff;
+mod captcha_prompt;
+mod channel_info;
 mod channels;
 pub mod command;
 pub mod config;
+mod contacts;
 pub mod cursor;
-pub mod data;
+mod dbus;
+pub mod demo;
 #[cfg(feature = "dev")]
 pub mod dev;
-pub(crate) mod emoji;
+pub mod effects;
+pub mod error_log;
 pub mod event;
+mod forward;
 mod handlers;
+mod history;
+pub mod hooks;
 pub mod input;
-pub mod receipt;
+mod local_delete;
+mod memory_profile;
+mod message_marks;
+mod message_search;
+pub mod metrics;
+mod names;
+mod plugins;
+mod profile;
+mod rate_limit;
+mod reaction_picker;
+mod recent_files;
+pub mod rpc;
+pub mod scheduler;
+mod setup;
 pub mod shortcuts;
 pub mod signal;
-pub mod storage;
+mod snippet;
 pub mod ui;
 pub mod util;
+
+pub(crate) use gurk_core::emoji;
+pub use gurk_core::{data, receipt, redact, storage};
@@ -0,0 +1,195 @@
+//! Deleting messages and their attachments on this device only
+//!
+//! Unlike deleting a message for everyone, the other members of the channel keep the messages.
+
+use std::path::PathBuf;
+
+use tracing::{info, warn};
+
+use crate::app::{App, DELETE_BATCH};
+use crate::redact::Sensitive;
+use crate::storage::MessageId;
+use crate::ui::Pane;
+
+impl App {
+    /// Deletes the selected message and its attachments
+    pub(crate) fn delete_selected_message_locally(&mut self) {
+        let Some(message_id) = self.selected_message_id() else {
+            return;
+        };
+        if self.is_being_sent(message_id) {
+            self.command_error = Some("cancel sending the message before deleting it".to_string());
+            return;
+        }
+        self.delete_messages_locally(&[message_id]);
+        info!(?message_id, "deleted message locally");
+    }
+
+    /// Deletes all messages of the selected channel and their attachments
+    ///
+    /// The messages queued while offline are dropped as well; messages being sent are kept.
+    pub(crate) fn clear_channel(&mut self) {
+        let Some(&channel_id) = self.channels.selected_item() else {
+            return;
+        };
+        self.outbox
+            .retain(|message| message.channel_id != channel_id);
+        // deleted in batches, newest first, so that not all messages are in memory at once
+        let mut count = 0;
+        let mut skipped = false;
+        let mut before = u64::MAX;
+        loop {
+            let message_ids: Vec<_> = self
+                .storage
+                .messages_before(channel_id, before, DELETE_BATCH)
+                .iter()
+                .map(|message| MessageId::new(channel_id, message.arrived_at))
+                .collect();
+            let deleted = self.delete_messages_locally(&message_ids);
+            count += deleted;
+            skipped |= deleted < message_ids.len();
+            match message_ids.first() {
+                Some(oldest) if message_ids.len() == DELETE_BATCH => before = oldest.arrived_at,
+                _ => break,
+            }
+        }
+        if skipped {
+            self.command_error = Some("messages being sent were not deleted".to_string());
+        }
+        self.dirty.mark(Pane::Channels);
+        info!(?channel_id, count, "cleared channel");
+    }
+
+    /// Deletes the messages including their edits and their attachments from the storage
+    ///
+    /// Messages being sent are skipped. Returns the number of deleted messages.
+    pub(crate) fn delete_messages_locally(&mut self, message_ids: &[MessageId]) -> usize {
+        let mut count = 0;
+        for &message_id in message_ids {
+            if self.is_being_sent(message_id) {
+                continue;
+            }
+            let attachments: Vec<PathBuf> = {
+                let Some(message) = self.storage.message(message_id) else {
+                    continue;
+                };
+                let edits: Vec<_> = self.storage.edits(message_id).collect();
+                message
+                    .attachments
+                    .iter()
+                    .chain(edits.iter().flat_map(|edit| &edit.attachments))
+                    .map(|attachment| attachment.filename.clone())
+                    .collect()
+            };
+            delete_attachment_files(attachments);
+            self.storage.delete_message(message_id);
+            self.forget_deleted_message(message_id);
+            self.marked_messages.remove(&message_id);
+            count += 1;
+
+            let messages = self.messages.entry(message_id.channel_id).or_default();
+            messages
+                .items
+                .retain(|&arrived_at| arrived_at != message_id.arrived_at);
+            let last_idx = messages.items.len().checked_sub(1);
+            if messages.state.selected() > last_idx {
+                messages.state.select(last_idx);
+            }
+            messages.rendered.offset = messages.rendered.offset.min(last_idx.unwrap_or(0));
+        }
+        if count > 0 {
+            self.dirty.mark(Pane::Channels);
+            self.dirty.mark(Pane::Messages);
+        }
+        count
+    }
+}
+
+/// Deletes the attachment files, ignoring the ones already deleted
+pub(crate) fn delete_attachment_files(paths: impl IntoIterator<Item = PathBuf>) {
+    for path in paths {
+        if let Err(error) = std::fs::remove_file(&path) {
+            if error.kind() != std::io::ErrorKind::NotFound {
+                warn!(%error, path =% Sensitive(path.display()), "failed to delete attachment");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::tests::test_app;
+    use crate::app::ConnectionState;
+    use crate::command::Command;
+    use crate::data::Message;
+    use crate::signal::Attachment;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_delete_message_locally() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("photo.jpg");
+        std::fs::write(&path, "jpeg").unwrap();
+        let attachment = Attachment {
+            id: "photo".to_string(),
+            content_type: "image/jpeg".to_string(),
+            filename: path.clone(),
+            size: 4,
+        };
+        let message = Message::new(app.user_id, Some("photo".into()), [], 1, vec![attachment]);
+        app.add_message_to_channel(0, message);
+        let messages = app.messages.get_mut(&channel_id).unwrap();
+        messages.state.select(Some(0));
+
+        app.on_command(Command::DeleteMessageLocally).await.unwrap();
+        assert_eq!(app.messages[&channel_id].items, [0]);
+        assert_eq!(app.storage.message(MessageId::new(channel_id, 1)), None);
+        assert!(!path.exists());
+        // the remaining message is selected
+        assert_eq!(
+            app.selected_message_id(),
+            Some(MessageId::new(channel_id, 0))
+        );
+
+        // messages being sent are kept
+        app.config.send_preview = false;
+        "hello".chars().for_each(|c| app.get_input().put_char(c));
+        app.on_command(Command::SendMessage).await.unwrap();
+        let messages = app.messages.get_mut(&channel_id).unwrap();
+        messages.state.select(Some(0));
+        let sending = app.selected_message_id().unwrap();
+        app.on_command(Command::DeleteMessageLocally).await.unwrap();
+        assert_eq!(
+            app.command_error.as_deref(),
+            Some("cancel sending the message before deleting it")
+        );
+        assert!(app.storage.message(sending).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_clear_channel() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        for arrived_at in 1..=3 {
+            let message = Message::new(app.user_id, Some("hi".into()), [], arrived_at, Vec::new());
+            app.add_message_to_channel(0, message);
+        }
+        app.marked_messages.insert(MessageId::new(channel_id, 2));
+        app.editing = Some(MessageId::new(channel_id, 3));
+        app.set_connection_state(ConnectionState::Offline);
+        app.send_or_queue(channel_id, "queued".to_string(), None, None);
+
+        app.on_command(Command::Clear).await.unwrap();
+        assert!(app.messages[&channel_id].items.is_empty());
+        assert_eq!(app.storage.messages(channel_id).count(), 0);
+        assert!(app.marked_messages.is_empty());
+        // nothing of the channel is sent or edited later
+        assert_eq!(app.editing, None);
+        assert!(app.outbox.is_empty());
+        // the channel itself is kept
+        assert!(app.storage.channel(channel_id).is_some());
+    }
+}
@@ -1,26 +1,41 @@
 //! Signal Messenger client for terminal
 
+use std::future::Future;
+use std::io::Read as _;
+use std::path::PathBuf;
+use std::pin::Pin;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+use std::thread::ThreadId;
 use std::time::{Duration, Instant};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use chrono::{DateTime, Utc};
 use clap::Parser;
 use crossterm::{
     event::{
-        DisableMouseCapture, EnableMouseCapture, Event as CEvent, EventStream, KeyEvent,
-        KeyEventKind, MouseButton, MouseEvent, MouseEventKind,
+        DisableFocusChange, DisableMouseCapture, EnableFocusChange, EnableMouseCapture,
+        Event as CEvent, EventStream, KeyEvent, KeyEventKind, MouseButton, MouseEvent,
+        MouseEventKind,
     },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use gurk::app::App;
+use gurk::activity::ActivityReporter;
+use gurk::app::{AfterQuit, App, ConnectionState};
+use gurk::attachment_cache::AttachmentCache;
 use gurk::backoff::Backoff;
-use gurk::storage::{sync_from_signal, JsonStorage, MemCache, SqliteStorage, Storage};
-use gurk::{config, signal, ui};
+use gurk::command::{DirectionVertical, MoveAmountVisual};
+use gurk::config::{Config, ConfigWatcher, ThemeMode};
+use gurk::error_log::ErrorKind;
+use gurk::storage::{
+    sync_from_signal, BurnAfterReading, JsonStorage, MemCache, SqliteStorage, Storage,
+};
+use gurk::ui::{TerminalCapabilities, Theme};
+use gurk::{config, demo, metrics, rpc, signal, ui};
 use presage::libsignal_service::content::Content;
 use ratatui::{backend::CrosstermBackend, Terminal};
+use serde_json::{json, Value};
 use tokio::select;
 use tokio_stream::StreamExt;
 use tracing::debug;
@@ -31,6 +46,9 @@ const RECEIPT_TICK_PERIOD: u64 = 144;
 const FRAME_BUDGET: Duration = Duration::from_millis(1000 / TARGET_FPS);
 const SAVE_BUDGET: Duration = Duration::from_millis(1000);
 const RECEIPT_BUDGET: Duration = Duration::from_millis(RECEIPT_TICK_PERIOD * 1000 / TARGET_FPS);
+const BACKGROUND_QUERY_TIMEOUT: Duration = Duration::from_millis(200);
+/// Maximum delay of a frame while a burst of events is handled
+const MAX_COALESCE_DELAY: Duration = Duration::from_millis(100);
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
@@ -41,6 +59,53 @@ struct Args {
     /// Relinks the device (helpful when device was unlinked)
     #[clap(long)]
     relink: bool,
+    /// Includes sensitive data like message contents, names and file names in the log
+    #[clap(long)]
+    log_sensitive: bool,
+    /// Runs with a fake Signal account and a scripted conversation, e.g. for trying out themes
+    #[clap(long)]
+    demo: bool,
+    /// Shows the diagnostics with the number of messages and the estimated memory kept for each
+    /// channel
+    #[clap(long)]
+    profile_memory: bool,
+    #[command(subcommand)]
+    command: Option<Subcommand>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Subcommand {
+    /// Receives messages without the TUI, serving the RPC socket and the D-Bus service, and
+    /// showing notifications
+    Daemon,
+    /// Sends a message, via the running gurk if any, and exits when it was sent
+    Send {
+        /// Contact or group, given by its name or id
+        #[arg(long)]
+        to: String,
+        /// Attaches the file; can be given several times
+        #[arg(long, value_name = "FILE")]
+        attach: Vec<PathBuf>,
+        /// Text of the message; read from stdin if omitted or `-`
+        text: Option<String>,
+    },
+    /// Lists the contacts and groups with their unread messages
+    Channels {
+        /// Prints JSON as returned by the control socket
+        #[arg(long)]
+        json: bool,
+    },
+    /// Prints the messages of a contact or group
+    Messages {
+        /// Contact or group, given by its name or id
+        channel: String,
+        /// Only messages arrived after the timestamp in milliseconds
+        #[arg(long, default_value_t = 0)]
+        since: u64,
+        /// Prints JSON as returned by the control socket
+        #[arg(long)]
+        json: bool,
+    },
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -61,10 +126,59 @@ async fn main() -> anyhow::Result<()> {
     };
 
     log_panics::init();
+    install_panic_hook();
+    gurk::redact::set_log_sensitive(args.log_sensitive);
 
-    tokio::task::LocalSet::new()
-        .run_until(run_single_threaded(args.relink))
-        .await
+    if args.demo {
+        return tokio::task::LocalSet::new()
+            .run_until(run_demo(args.profile_memory))
+            .await;
+    }
+
+    match args.command {
+        Some(Subcommand::Daemon) => return run_daemon().await,
+        Some(Subcommand::Send { to, attach, text }) => {
+            return tokio::task::LocalSet::new()
+                .run_until(run_send(&to, &attach, text))
+                .await;
+        }
+        Some(Subcommand::Channels { json }) => {
+            let channels = query("listChannels", Value::Null).await?;
+            return print_result(&channels, json, print_channel);
+        }
+        Some(Subcommand::Messages {
+            channel,
+            since,
+            json,
+        }) => {
+            let params = json!({"channel": channel, "since": since});
+            let messages = query("getMessages", params).await?;
+            return print_result(&messages, json, print_message);
+        }
+        None => {}
+    }
+
+    let mut relink = args.relink;
+    loop {
+        // a new local set for each run, so that the tasks of the previous link are dropped
+        let after_quit = tokio::task::LocalSet::new()
+            .run_until(run_single_threaded(relink, args.profile_memory))
+            .await?;
+        match after_quit {
+            AfterQuit::Exit => return Ok(()),
+            AfterQuit::Relink => relink = true,
+            AfterQuit::DeleteCredentials(signal_db_path) => {
+                std::fs::remove_dir_all(&signal_db_path)
+                    .with_context(|| format!("failed to delete {}", signal_db_path.display()))?;
+                println!(
+                    "Unlinked this device and deleted its credentials in {}.\n\
+                    The messages are kept; start gurk again to link this device anew.",
+                    signal_db_path.display()
+                );
+                return Ok(());
+            }
+        }
+    }
 }
 
 async fn is_online() -> bool {
@@ -73,6 +187,18 @@ async fn is_online() -> bool {
         .is_ok()
 }
 
+/// Theme for the background color of the terminal, queried with OSC 11 or read from `$COLORFGBG`
+fn detect_background_theme() -> Option<Theme> {
+    let background = enable_raw_mode().ok().and_then(|()| {
+        let background = ui::query_background(BACKGROUND_QUERY_TIMEOUT);
+        let _ = disable_raw_mode();
+        background
+    });
+    background
+        .map(Theme::for_background)
+        .or_else(|| Theme::from_colorfgbg(&std::env::var("COLORFGBG").ok()?))
+}
+
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum Event {
@@ -80,18 +206,294 @@ pub enum Event {
     Click(MouseEvent),
     Input(KeyEvent),
     Paste(String),
+    Focus(bool),
     Message(Content),
     Resize { cols: u16, rows: u16 },
     Quit(Option<anyhow::Error>),
     ContactSynced(DateTime<Utc>),
+    Connection(ConnectionState),
     Tick,
     AppEvent(gurk::event::Event),
 }
 
-async fn run_single_threaded(relink: bool) -> anyhow::Result<()> {
-    let (mut signal_manager, config) = signal::ensure_linked_device(relink).await?;
+/// Calls the method of the control socket, on the running gurk if any
+async fn query(method: &str, params: Value) -> anyhow::Result<Value> {
+    if let Some(path) = Config::load_installed()?.and_then(|config| config.rpc_socket) {
+        if let Some(result) = rpc::call(&path, method, params.clone()).await? {
+            return Ok(result);
+        }
+    }
+    let (signal_manager, config) = signal::load_linked_device().await?;
+    let storage = open_storage(&config).await?;
+    let (mut app, _app_events) = App::try_new(config, signal_manager, storage)?;
+    app.populate_names_cache().await;
+    app.call_rpc(method, params)
+}
+
+/// Prints the items of the result as JSON, or each as a line of text
+fn print_result(result: &Value, json: bool, print_item: fn(&Value)) -> anyhow::Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(result)?);
+    } else {
+        result.as_array().into_iter().flatten().for_each(print_item);
+    }
+    Ok(())
+}
+
+fn print_channel(channel: &Value) {
+    let name = channel["name"].as_str().unwrap_or_default();
+    match channel["unread"].as_u64().unwrap_or_default() {
+        0 => println!("{name}"),
+        unread => println!("{name} ({unread} unread)"),
+    }
+}
+
+fn print_message(message: &Value) {
+    let arrived_at = message["timestamp"]
+        .as_i64()
+        .and_then(DateTime::from_timestamp_millis)
+        .map(|dt| {
+            dt.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_default();
+    let from = message["from"].as_str().unwrap_or_default();
+    let text = message["text"].as_str().unwrap_or_default();
+    println!("{arrived_at} {from}: {text}");
+}
+
+/// Sends the message and waits for the result
+///
+/// When gurk is running, the message is passed to it (and queued when it is offline), since the
+/// data can only be used by one gurk.
+async fn run_send(to: &str, attach: &[PathBuf], text: Option<String>) -> anyhow::Result<()> {
+    let mut text = match text.filter(|text| text != "-") {
+        Some(text) => text,
+        None => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .context("failed to read the message from stdin")?;
+            text.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+    // attachments are sent as in the input box
+    for path in attach {
+        let path = std::fs::canonicalize(path)
+            .with_context(|| format!("failed to attach {}", path.display()))?;
+        let url = format!("file://{}", path.display());
+        if url.contains(char::is_whitespace) {
+            bail!("cannot attach {}: whitespace in the path", path.display());
+        }
+        text.push(' ');
+        text.push_str(&url);
+    }
+    if text.trim().is_empty() {
+        bail!("nothing to send");
+    }
+
+    if let Some(path) = Config::load_installed()?.and_then(|config| config.rpc_socket) {
+        let params = json!({"channel": to, "text": text});
+        if rpc::call(&path, "sendMessage", params).await?.is_some() {
+            return Ok(());
+        }
+    }
+
+    let (signal_manager, config) = signal::load_linked_device().await?;
+    let mut storage = open_storage(&config).await?;
+    sync_from_signal(&*signal_manager, &mut *storage).await;
+    let (mut app, mut app_events) = App::try_new(config, signal_manager, storage)?;
+    app.set_headless();
+    let channel_id = app.find_channel(to)?;
+    let sent = app.send_message(channel_id, text);
+    let error = loop {
+        let event = app_events
+            .recv()
+            .await
+            .context("the message was lost while sending")?;
+        let error = match &event {
+            gurk::event::Event::SentTextResult { message_id, result } if *message_id == sent => {
+                Some(result.as_ref().err().map(|error| error.to_string()))
+            }
+            _ => None,
+        };
+        if let Err(error) = app.handle_event(event) {
+            error!(%error, "failed to handle app event");
+        }
+        if let Some(error) = error {
+            break error;
+        }
+    };
+    app.save();
+    match error {
+        Some(error) => bail!("failed to send the message: {error}"),
+        None => Ok(()),
+    }
+}
+
+/// Runs without the TUI until it is interrupted or terminated
+///
+/// While a TUI is running, which took over, the daemon pauses.
+async fn run_daemon() -> anyhow::Result<()> {
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    loop {
+        // a new local set for each run, so that the tasks using the data are dropped when handing
+        // over
+        let lease = tokio::task::LocalSet::new()
+            .run_until(serve_daemon(shutdown.as_mut()))
+            .await?;
+        let Some(lease) = lease else {
+            return Ok(());
+        };
+        info!("handed over to the TUI");
+        select! {
+            _ = rpc::hand_over(lease) => info!("resuming after the TUI quit"),
+            _ = &mut shutdown => return Ok(()),
+        }
+    }
+}
+
+/// Serves until shutdown, or until a TUI asks to take over, returning its connection
+async fn serve_daemon(
+    mut shutdown: Pin<&mut impl Future<Output = ()>>,
+) -> anyhow::Result<Option<tokio::sync::mpsc::UnboundedSender<String>>> {
+    let (signal_manager, config) = signal::load_linked_device().await?;
+    let mut storage = open_storage(&config).await?;
+    sync_from_signal(&*signal_manager, &mut *storage).await;
+
+    let (mut app, mut app_events) = App::try_new(config, signal_manager.clone_boxed(), storage)?;
+    app.set_headless();
+    let mut services = start_app(&mut app).await;
+    let (metrics_tx, metrics_rx) = tokio::sync::watch::channel(app.render_metrics());
+    if let Some(address) = app.config.metrics_address {
+        match metrics::serve(address, metrics_rx).await {
+            Ok(task) => services.push(task),
+            Err(error) => error!(%error, "failed to serve metrics"),
+        }
+    }
+    info!("running as daemon");
+
+    let contact_sync_task = app.request_contacts_sync();
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Event>(100);
+    spawn_receiving(signal_manager, contact_sync_task, tx.clone());
+
+    let tick_task = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RECEIPT_BUDGET);
+        loop {
+            interval.tick().await;
+            if tx.send(Event::Tick).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut last_save_at = Instant::now();
+    let mut res = Ok(None);
+    loop {
+        let event = select! {
+            v = rx.recv() => v,
+            v = app_events.recv() => v.map(Event::AppEvent),
+            _ = &mut shutdown => {
+                info!("shutting down");
+                break;
+            }
+        };
+
+        match event {
+            Some(Event::Tick) => {
+                app.step_receipts();
+                app.step_rate_limited_sends();
+                app.step_retention();
+                app.step_birthday_reminders();
+                app.step_jobs().await;
+                metrics_tx.send_replace(app.render_metrics());
+            }
+            Some(Event::Message(content)) => {
+                app.metrics.last_received_at = Some(Utc::now());
+                if let Err(error) = app.on_message(content).await {
+                    app.metrics.message_failures += 1;
+                    let message = format!("failed on incoming message: {error:#}");
+                    app.report_error(ErrorKind::Receive, message);
+                }
+            }
+            Some(Event::Quit(e)) => {
+                if let Some(e) = e {
+                    res = Err(e);
+                };
+                break;
+            }
+            Some(Event::Connection(state)) => {
+                if matches!(state, ConnectionState::Reconnecting { .. }) {
+                    app.metrics.reconnects += 1;
+                }
+                app.set_connection_state(state);
+            }
+            Some(Event::ContactSynced(at)) => {
+                let mut metadata = app.storage.metadata().into_owned();
+                metadata.contacts_sync_request_at.replace(at);
+                app.storage.store_metadata(metadata);
+                info!(%at, "synced contacts");
+            }
+            Some(Event::AppEvent(event)) => {
+                if let Err(error) = app.handle_event(event) {
+                    app.report_error(ErrorKind::Receive, format!("{error:#}"));
+                }
+            }
+            // terminal events
+            Some(_) => {}
+            None => break,
+        }
+
+        if last_save_at.elapsed() > SAVE_BUDGET {
+            app.save();
+            last_save_at = Instant::now();
+        }
+
+        if let Some(lease) = app.take_detach_lease() {
+            res = Ok(Some(lease));
+            break;
+        }
+    }
+
+    tick_task.abort();
+    for task in services {
+        task.abort();
+    }
+    app.save();
+    res
+}
+
+/// Resolves on Ctrl-C, or when the process is terminated
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match (
+            signal(SignalKind::terminate()),
+            signal(SignalKind::hangup()),
+        ) {
+            (Ok(mut terminate), Ok(mut hangup)) => {
+                select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                    _ = hangup.recv() => {}
+                }
+            }
+            (Err(error), _) | (_, Err(error)) => {
+                error!(%error, "failed to listen for SIGTERM and SIGHUP");
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
 
-    let mut storage: Box<dyn Storage> = if config.sqlite.enabled {
+/// Opens the storage of the config, converting the JSON storage to SQLite on the first run
+async fn open_storage(config: &Config) -> anyhow::Result<Box<dyn Storage>> {
+    let storage: Box<dyn Storage> = if config.sqlite.enabled {
         debug!(
             %config.sqlite.url,
             encrypt = config.passphrase.is_some(),
@@ -124,54 +526,84 @@ async fn run_single_threaded(relink: bool) -> anyhow::Result<()> {
                 info!(?stats, "converted");
             }
         }
-        Box::new(MemCache::new(sqlite_storage))
+        let message_limit = config
+            .max_messages_in_memory
+            .unwrap_or(config.history_page_size);
+        Box::new(BurnAfterReading::new(MemCache::with_message_limit(
+            sqlite_storage,
+            message_limit,
+        )))
     } else {
         let json_storage =
             JsonStorage::new(&config.data_path, config::fallback_data_path().as_deref())?;
-        Box::new(json_storage)
+        Box::new(BurnAfterReading::new(json_storage))
     };
+    Ok(storage)
+}
 
-    sync_from_signal(&*signal_manager, &mut *storage).await;
-
-    let (mut app, mut app_events) = App::try_new(config, signal_manager.clone_boxed(), storage)?;
+/// Loads the attachment cache, the plugins and starts the services of the config
+async fn start_app(app: &mut App) -> Vec<tokio::task::JoinHandle<()>> {
+    let data_dir = &app.config.data_dir;
+    app.attachment_cache =
+        AttachmentCache::load(data_dir.join("attachments.json"), &data_dir.join("files"));
+    app.shrink_attachment_cache();
     app.populate_names_cache().await;
-
-    // sync task can be only spawned after we start to listen to message, because it relies on
-    // message sender to be running
-    let mut contact_sync_task = app.request_contacts_sync();
-
-    let (tx, mut rx) = tokio::sync::mpsc::channel::<Event>(100);
-    tokio::spawn({
-        let tx = tx.clone();
-        async move {
-            let mut reader = EventStream::new().fuse();
-            while let Some(event) = reader.next().await {
-                match event {
-                    Ok(CEvent::Key(key)) => tx.send(Event::Input(key)).await.unwrap(),
-                    Ok(CEvent::Resize(cols, rows)) => {
-                        tx.send(Event::Resize { cols, rows }).await.unwrap()
-                    }
-                    Ok(CEvent::Mouse(button)) => tx.send(Event::Click(button)).await.unwrap(),
-                    Ok(CEvent::Paste(content)) => tx.send(Event::Paste(content)).await.unwrap(),
-                    _ => (),
-                }
-            }
+    app.restore_failed_sends();
+    if let Some(plugins_dir) = dirs::config_dir().map(|dir| dir.join("gurk/plugins")) {
+        if plugins_dir.is_dir() {
+            app.load_plugins(&plugins_dir);
         }
-    });
+    }
+    app.run_startup_hook();
+    [
+        app.serve_rpc(),
+        app.serve_dbus(),
+        app.listen_notifications(),
+        app.watch_metered(),
+        app.start_scheduler(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
 
-    let inner_tx = tx.clone();
+/// Spawns the task receiving the messages, which reconnects when the connection was lost
+///
+/// The contacts are synced after connecting.
+fn spawn_receiving(
+    mut signal_manager: Box<dyn signal::SignalManager>,
+    mut contact_sync_task: Option<impl Future<Output = anyhow::Result<DateTime<Utc>>> + 'static>,
+    inner_tx: tokio::sync::mpsc::Sender<Event>,
+) {
     tokio::task::spawn_local(async move {
         let mut backoff = Backoff::new();
+        let mut has_connected = false;
         loop {
             let mut messages = if !is_online().await {
+                inner_tx
+                    .send(Event::Connection(ConnectionState::Offline))
+                    .await
+                    .expect("logic error: events channel closed");
                 tokio::time::sleep(std::time::Duration::from_secs(10)).await;
                 continue;
             } else {
                 match signal_manager.receive_messages().await {
                     Ok(messages) => {
                         info!("connected and listening for incoming messages");
+                        has_connected = true;
+                        inner_tx
+                            .send(Event::Connection(ConnectionState::Connected))
+                            .await
+                            .expect("logic error: events channel closed");
                         messages
                     }
+                    Err(error) if has_connected => {
+                        // e.g. the server is unreachable for a while
+                        let after = backoff.get();
+                        error!(%error, ?after, "failed to reconnect");
+                        reconnect_after(&inner_tx, after).await;
+                        continue;
+                    }
                     Err(e) => {
                         let e = e.context(
                             "failed to initialize the stream of Signal messages.\n\
@@ -211,44 +643,271 @@ async fn run_single_threaded(relink: bool) -> anyhow::Result<()> {
 
             let after = backoff.get();
             error!(?after, "messages channel disconnected. trying to reconnect");
-            tokio::time::sleep(after).await;
+            reconnect_after(&inner_tx, after).await;
+        }
+    });
+}
+
+/// Shows when reconnecting, and waits until then
+async fn reconnect_after(tx: &tokio::sync::mpsc::Sender<Event>, after: Duration) {
+    let at = Instant::now() + after;
+    tx.send(Event::Connection(ConnectionState::Reconnecting { at }))
+        .await
+        .expect("logic error: events channel closed");
+    tokio::time::sleep(after).await;
+}
+
+async fn run_single_threaded(relink: bool, profile_memory: bool) -> anyhow::Result<AfterQuit> {
+    // a running daemon hands over the connection and the data until the TUI quits
+    let daemon_lease = match Config::load_installed()?.and_then(|config| config.rpc_socket) {
+        Some(path) => rpc::detach_daemon(&path).await?,
+        None => None,
+    };
+    let _daemon_lease = scopeguard::guard(daemon_lease, |lease| {
+        if let Some(lease) = lease {
+            lease.abort();
+        }
+    });
+
+    let (signal_manager, config) = signal::ensure_linked_device(relink).await?;
+    CRASH_REPORT_DIR.get_or_init(|| config.data_dir.clone());
+    // before the TUI starts reading the input
+    let detected_terminal = DetectedTerminal::detect(&config);
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<Event>(100);
+    let (loading_tx, loading) = tokio::sync::watch::channel("opening storage");
+    let load = {
+        let tx = tx.clone();
+        async move {
+            let mut storage = open_storage(&config).await?;
+            show_loading_step(&loading_tx, "syncing contacts and groups").await;
+            sync_from_signal(&*signal_manager, &mut *storage).await;
+
+            show_loading_step(&loading_tx, "loading channels").await;
+            let (mut app, app_events) =
+                App::try_new(config, signal_manager.clone_boxed(), storage)?;
+            detected_terminal.apply(&mut app);
+            app.show_memory_profile(profile_memory);
+            let services = start_app(&mut app).await;
+
+            // sync task can be only spawned after we start to listen to message, because it
+            // relies on message sender to be running
+            let contact_sync_task = app.request_contacts_sync();
+
+            spawn_receiving(signal_manager, contact_sync_task, tx);
+            Ok::<_, anyhow::Error>(LoadedApp {
+                app,
+                app_events,
+                services,
+            })
+        }
+    };
+    run_tui(load, loading, tx, rx).await
+}
+
+/// Runs the app with a fake Signal account, for trying out gurk
+async fn run_demo(profile_memory: bool) -> anyhow::Result<()> {
+    let (mut app, app_events) = demo::app().await?;
+    DetectedTerminal::detect(&app.config).apply(&mut app);
+    app.show_memory_profile(profile_memory);
+    app.start_demo_script();
+    let (tx, rx) = tokio::sync::mpsc::channel::<Event>(100);
+    let (_, loading) = tokio::sync::watch::channel("loading channels");
+    let loaded = LoadedApp {
+        app,
+        app_events,
+        services: Vec::new(),
+    };
+    run_tui(async { Ok(loaded) }, loading, tx, rx).await?;
+    Ok(())
+}
+
+/// Capabilities and background color of the terminal
+struct DetectedTerminal {
+    capabilities: TerminalCapabilities,
+    theme: Option<Theme>,
+}
+
+impl DetectedTerminal {
+    /// Detects the terminal; must be done before the TUI reads the input
+    fn detect(config: &Config) -> Self {
+        let theme = &config.theme;
+        let follows_background = theme.mode == ThemeMode::Auto
+            && theme.light_from.is_none()
+            && theme.dark_from.is_none();
+        Self {
+            capabilities: TerminalCapabilities::detect(&config.terminal),
+            theme: follows_background.then(detect_background_theme).flatten(),
+        }
+    }
+
+    fn apply(self, app: &mut App) {
+        app.terminal = self.capabilities;
+        if let Some(theme) = self.theme {
+            app.theme = theme;
+        }
+    }
+}
+
+/// App loaded while the loading screen is shown
+struct LoadedApp {
+    app: App,
+    app_events: tokio::sync::mpsc::UnboundedReceiver<gurk::event::Event>,
+    services: Vec<tokio::task::JoinHandle<()>>,
+}
+
+/// Shows the step on the loading screen, before continuing to load
+async fn show_loading_step(loading: &tokio::sync::watch::Sender<&'static str>, step: &'static str) {
+    loading.send_replace(step);
+    // the loading is mostly blocking, so give the loading screen a chance to be drawn
+    tokio::task::yield_now().await;
+}
+
+/// Draws the loading screen with the current step until the app is loaded
+async fn show_loading(
+    terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
+    load: impl Future<Output = anyhow::Result<LoadedApp>>,
+    mut loading: tokio::sync::watch::Receiver<&'static str>,
+) -> anyhow::Result<LoadedApp> {
+    tokio::pin!(load);
+    loop {
+        let step = *loading.borrow_and_update();
+        terminal.draw(|f| ui::draw_loading(f, step))?;
+        select! {
+            biased;
+            changed = loading.changed() => {
+                if changed.is_err() {
+                    // no more steps
+                    return load.await;
+                }
+            }
+            loaded = &mut load => return loaded,
+        }
+    }
+}
+
+/// Runs the TUI until quitting
+///
+/// The TUI starts with a loading screen, until the app is loaded by `load`. Then it is
+/// interactive as soon as the channel list is ready, while the messages are loaded in the
+/// background.
+async fn run_tui(
+    load: impl Future<Output = anyhow::Result<LoadedApp>>,
+    loading: tokio::sync::watch::Receiver<&'static str>,
+    tx: tokio::sync::mpsc::Sender<Event>,
+    mut rx: tokio::sync::mpsc::Receiver<Event>,
+) -> anyhow::Result<AfterQuit> {
+    let input_task = tokio::spawn({
+        let tx = tx.clone();
+        async move {
+            let mut reader = EventStream::new().fuse();
+            while let Some(event) = reader.next().await {
+                let event = match event {
+                    Ok(CEvent::Key(key)) => Event::Input(key),
+                    Ok(CEvent::Resize(cols, rows)) => Event::Resize { cols, rows },
+                    Ok(CEvent::Mouse(button)) => Event::Click(button),
+                    Ok(CEvent::Paste(content)) => Event::Paste(content),
+                    Ok(CEvent::FocusGained) => Event::Focus(true),
+                    Ok(CEvent::FocusLost) => Event::Focus(false),
+                    _ => continue,
+                };
+                if tx.send(event).await.is_err() {
+                    // the TUI stopped
+                    break;
+                }
+            }
+        }
+    });
+
+    // quit like with the quit command when the terminal is closed or gurk is terminated
+    let shutdown_task = tokio::spawn({
+        let tx = tx.clone();
+        async move {
+            shutdown_signal().await;
+            let _ = tx.send(Event::Quit(None)).await;
         }
     });
 
     enable_raw_mode()?;
+    UI_THREAD.get_or_init(|| std::thread::current().id());
+    IS_TERMINAL_SETUP.store(true, Ordering::Relaxed);
     let _raw_mode_guard = scopeguard::guard((), |_| {
         disable_raw_mode().unwrap();
+        IS_TERMINAL_SETUP.store(false, Ordering::Relaxed);
     });
 
     let mut stdout = std::io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
     terminal.clear()?;
 
+    let LoadedApp {
+        app,
+        mut app_events,
+        services,
+    } = match show_loading(&mut terminal, load, loading).await {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            input_task.abort();
+            shutdown_task.abort();
+            execute!(
+                terminal.backend_mut(),
+                LeaveAlternateScreen,
+                DisableMouseCapture
+            )?;
+            return Err(error);
+        }
+    };
+    // e.g. the conversion of the storage is printed while loading
+    terminal.clear()?;
+    app.load_messages_in_background();
+
+    // on panic, the terminal is restored by the panic hook, but the data is saved here
+    let mut app = scopeguard::guard_on_unwind(app, |mut app| app.save());
+
+    // focus changes are only needed for keeping messages unread while the terminal is unfocused,
+    // for the unread summary in the title, and for suppressing notifications while focused
+    let notification = &app.config.notification;
+    let reports_focus = !app.config.mark_read.when_unfocused
+        || app.config.unread_summary_after_minutes.is_some()
+        || (!notification.when_focused && notification.focus_command.is_none());
+    if reports_focus {
+        execute!(terminal.backend_mut(), EnableFocusChange)?;
+    }
+
     let mut res = Ok(()); // result on quit
     let mut last_render_at = Instant::now();
     let mut last_save_at = Instant::now();
     let is_render_spawned = Arc::new(AtomicBool::new(false));
+    let mut activity_reporter = (app.config.unread_user_var.is_some()
+        || app.config.unread_summary_after_minutes.is_some())
+    .then(|| ActivityReporter::new(app.config.unread_user_var.clone()));
+
+    // the theme is reloaded when the config file changes
+    let mut config_watcher = ConfigWatcher::installed();
 
     let tick_tx = tx.clone();
-    // Tick to trigger receipt sending
-    tokio::spawn(async move {
+    // Tick to trigger receipt sending, channel sorting and collecting channel details
+    let tick_task = tokio::spawn(async move {
         let mut interval = tokio::time::interval(RECEIPT_BUDGET);
         loop {
             interval.tick().await;
-            tick_tx
-                .send(Event::Tick)
-                .await
-                .expect("Cannot tick: events channel closed.");
+            if tick_tx.send(Event::Tick).await.is_err() {
+                // the TUI stopped
+                break;
+            }
         }
     });
 
     loop {
-        // render
+        // render, unless nothing changed, or more events are pending and handled in the same frame
+        let is_coalescing = (!rx.is_empty() || !app_events.is_empty())
+            && last_render_at.elapsed() < MAX_COALESCE_DELAY;
         let left_frame_budget = FRAME_BUDGET.checked_sub(last_render_at.elapsed());
-        if let Some(budget) = left_frame_budget {
+        if !app.dirty.is_any_dirty() || is_coalescing {
+            // nothing to draw yet
+        } else if let Some(budget) = left_frame_budget {
             // skip frames that render too fast
             if !is_render_spawned.load(Ordering::Relaxed) {
                 let tx = tx.clone();
@@ -258,9 +917,8 @@ async fn run_single_threaded(relink: bool) -> anyhow::Result<()> {
                     // Redraw message is needed to make sure that we render the skipped frame
                     // if it was the last frame in the rendering budget window.
                     tokio::time::sleep(budget).await;
-                    tx.send(Event::Redraw)
-                        .await
-                        .expect("logic error: events channel closed");
+                    // nothing is drawn anymore if the TUI stopped in the meantime
+                    let _ = tx.send(Event::Redraw).await;
                     is_render_spawned.store(false, Ordering::Relaxed);
                 });
             }
@@ -274,39 +932,63 @@ async fn run_single_threaded(relink: bool) -> anyhow::Result<()> {
             v = app_events.recv() => v.map(Event::AppEvent),
         };
 
+        // the tick steps mark the panes they change, and the status bar shows timers
+        match event {
+            Some(Event::Tick) => app.dirty.mark(ui::Pane::StatusBar),
+            Some(Event::Redraw) => (),
+            _ => app.dirty.mark_all(),
+        }
+
         match event {
             Some(Event::Tick) => {
                 app.step_receipts();
+                app.step_highlights();
+                app.step_rate_limited_sends();
+                app.step_channel_sorting();
+                app.step_retention();
+                app.step_birthday_reminders();
+                app.step_theme();
+                app.step_memory_profile();
+                match config_watcher.as_mut().and_then(ConfigWatcher::poll) {
+                    Some(Ok(config)) => app.reload_theme(config.theme),
+                    Some(Err(error)) => {
+                        let message = format!("failed to reload config: {error:#}");
+                        app.report_error(ErrorKind::Config, message);
+                    }
+                    None => (),
+                }
+                app.step_channel_info().await;
+                app.step_jobs().await;
             }
             Some(Event::Click(event)) => match event.kind {
                 MouseEventKind::Down(MouseButton::Left) => {
                     let col = event.column;
                     let row = event.row;
                     if let Some(channel_idx) =
-                        ui::coords_within_channels_view(terminal.get_frame().area(), col, row)
-                            .map(|(_, row)| row as usize)
-                            .filter(|&idx| idx < app.channels.items.len())
+                        ui::coords_within_channels_view(&app, terminal.get_frame().area(), col, row)
+                            .and_then(|(_, row)| app.channel_at_row(row as usize))
                     {
-                        app.channels.state.select(Some(channel_idx));
-                        app.reset_unread_messages();
+                        app.switch_to_channel(channel_idx);
                     }
                 }
                 MouseEventKind::ScrollUp => {
-                    if event.column
-                        < terminal.get_frame().area().width / ui::CHANNEL_VIEW_RATIO as u16
+                    let area = terminal.get_frame().area();
+                    if ui::coords_within_channels_view(&app, area, event.column, event.row)
+                        .is_some()
                     {
                         app.select_previous_channel()
                     } else {
-                        app.on_pgup()
+                        app.scroll_messages(DirectionVertical::Up, MoveAmountVisual::Entry)
                     }
                 }
                 MouseEventKind::ScrollDown => {
-                    if event.column
-                        < terminal.get_frame().area().width / ui::CHANNEL_VIEW_RATIO as u16
+                    let area = terminal.get_frame().area();
+                    if ui::coords_within_channels_view(&app, area, event.column, event.row)
+                        .is_some()
                     {
                         app.select_next_channel()
                     } else {
-                        app.on_pgdn()
+                        app.scroll_messages(DirectionVertical::Down, MoveAmountVisual::Entry)
                     }
                 }
                 _ => {}
@@ -323,10 +1005,13 @@ async fn run_single_threaded(relink: bool) -> anyhow::Result<()> {
                 app.is_multiline_input = true;
                 content.chars().for_each(|c| app.get_input().put_char(c));
                 app.is_multiline_input = multi_line_state;
+                app.update_message_search();
             }
+            Some(Event::Focus(is_focused)) => app.on_focus(is_focused),
             Some(Event::Message(content)) => {
-                if let Err(e) = app.on_message(content).await {
-                    error!("failed on incoming message: {}", e);
+                if let Err(error) = app.on_message(content).await {
+                    let message = format!("failed on incoming message: {error:#}");
+                    app.report_error(ErrorKind::Receive, message);
                 }
             }
             Some(Event::Resize { .. }) | Some(Event::Redraw) => {
@@ -338,6 +1023,7 @@ async fn run_single_threaded(relink: bool) -> anyhow::Result<()> {
                 };
                 break;
             }
+            Some(Event::Connection(state)) => app.set_connection_state(state),
             Some(Event::ContactSynced(at)) => {
                 let mut metadata = app.storage.metadata().into_owned();
                 metadata.contacts_sync_request_at.replace(at);
@@ -346,7 +1032,7 @@ async fn run_single_threaded(relink: bool) -> anyhow::Result<()> {
             }
             Some(Event::AppEvent(event)) => {
                 if let Err(error) = app.handle_event(event) {
-                    error!(%error, "failed to handle app event");
+                    app.report_error(ErrorKind::Receive, format!("{error:#}"));
                 }
             }
             None => {
@@ -354,8 +1040,19 @@ async fn run_single_threaded(relink: bool) -> anyhow::Result<()> {
             }
         }
 
-        if last_save_at.elapsed() > SAVE_BUDGET || app.should_quit {
-            app.storage.save();
+        if let Some(reporter) = activity_reporter.as_mut() {
+            let unread_messages = app.unread_messages_len();
+            if let Err(error) = reporter.report(terminal.backend_mut(), unread_messages) {
+                error!(%error, "failed to report unread messages");
+            }
+            let summary = app.unread_summary();
+            if let Err(error) = reporter.report_summary(terminal.backend_mut(), summary) {
+                error!(%error, "failed to report unread summary");
+            }
+        }
+
+        if last_save_at.elapsed() > SAVE_BUDGET {
+            app.save();
             last_save_at = Instant::now();
         }
 
@@ -364,6 +1061,25 @@ async fn run_single_threaded(relink: bool) -> anyhow::Result<()> {
         }
     }
 
+    // stop reading the input, which is read again when relinking
+    input_task.abort();
+    tick_task.abort();
+    shutdown_task.abort();
+    for task in services {
+        task.abort();
+    }
+
+    app.save();
+
+    if let Some(reporter) = activity_reporter.as_mut() {
+        // reset the badge and the title on exit
+        reporter.report(terminal.backend_mut(), 0).ok();
+        reporter.report_summary(terminal.backend_mut(), None).ok();
+    }
+
+    if reports_focus {
+        execute!(terminal.backend_mut(), DisableFocusChange).unwrap();
+    }
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
@@ -372,5 +1088,69 @@ async fn run_single_threaded(relink: bool) -> anyhow::Result<()> {
     .unwrap();
     terminal.show_cursor().unwrap();
 
-    res
+    let unsent = app.queued_messages_len();
+    if unsent > 0 {
+        eprintln!("{unsent} message(s) queued while offline were not sent");
+    }
+
+    res.map(|()| scopeguard::ScopeGuard::into_inner(app).after_quit)
+}
+
+/// Whether the terminal is in raw mode and in the alternate screen
+static IS_TERMINAL_SETUP: AtomicBool = AtomicBool::new(false);
+
+/// Thread running the TUI
+static UI_THREAD: OnceLock<ThreadId> = OnceLock::new();
+
+/// Data directory of the config, once it is loaded
+static CRASH_REPORT_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Restores the terminal and writes a crash report before the panic is printed
+///
+/// Only panics of the TUI thread crash gurk; panics of other threads, e.g. of spawned tasks, are
+/// just printed.
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if UI_THREAD.get() != Some(&std::thread::current().id()) {
+            default_hook(info);
+            return;
+        }
+        if IS_TERMINAL_SETUP.swap(false, Ordering::Relaxed) {
+            let _ = disable_raw_mode();
+            let _ = execute!(
+                std::io::stdout(),
+                LeaveAlternateScreen,
+                DisableMouseCapture,
+                DisableFocusChange,
+                crossterm::cursor::Show
+            );
+        }
+        match write_crash_report(info) {
+            Ok(path) => eprintln!("gurk crashed; a report was written to {}", path.display()),
+            Err(error) => eprintln!("gurk crashed; failed to write a report: {error:#}"),
+        }
+        default_hook(info);
+    }));
+}
+
+/// Writes the panic and its backtrace to `crash-<time>.txt` in the data directory
+///
+/// Before the config is loaded, the default data directory is used.
+fn write_crash_report(info: &std::panic::PanicHookInfo) -> anyhow::Result<PathBuf> {
+    let dir = match CRASH_REPORT_DIR.get() {
+        Some(dir) => dir.clone(),
+        None => dirs::data_dir().context("no data directory")?.join("gurk"),
+    };
+    std::fs::create_dir_all(&dir)?;
+    let now = Utc::now();
+    let path = dir.join(format!("crash-{}.txt", now.format("%Y%m%dT%H%M%S")));
+    let report = format!(
+        "gurk {} crashed at {}\n\n{info}\n\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        now.to_rfc3339(),
+        std::backtrace::Backtrace::force_capture(),
+    );
+    std::fs::write(&path, report).with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(path)
 }
@@ -0,0 +1,93 @@
+//! Memory used by the messages of each channel, shown in the diagnostics with `--profile-memory`
+
+use std::cmp::Reverse;
+use std::time::{Duration, Instant};
+
+use crate::app::App;
+use crate::data::ChannelId;
+use crate::storage::MemoryUsage;
+
+/// How often the memory profile shown in the diagnostics is collected again
+const PROFILE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ChannelMemory {
+    pub channel_id: ChannelId,
+    pub name: String,
+    /// Number of messages loaded into the message list
+    pub loaded: usize,
+    /// Messages kept in memory by the storage
+    pub in_memory: MemoryUsage,
+}
+
+impl App {
+    /// Opens the diagnostics with the memory profile, if enabled by `--profile-memory`
+    pub fn show_memory_profile(&mut self, enabled: bool) {
+        self.profile_memory = enabled;
+        self.show_diagnostics |= enabled;
+        self.refresh_memory_profile();
+    }
+
+    /// Collects the memory profile again while the diagnostics show it, at most every
+    /// `PROFILE_INTERVAL`
+    pub fn step_memory_profile(&mut self) {
+        let is_recent = self
+            .memory_profiled_at
+            .is_some_and(|at| at.elapsed() < PROFILE_INTERVAL);
+        if !is_recent {
+            self.refresh_memory_profile();
+        }
+    }
+
+    /// Collects the memory profile if the diagnostics show it, or else drops it
+    pub(crate) fn refresh_memory_profile(&mut self) {
+        if self.profile_memory && self.show_diagnostics {
+            self.memory_profile = self.collect_memory_profile();
+            self.memory_profiled_at = Some(Instant::now());
+        } else {
+            self.memory_profile = Vec::new();
+            self.memory_profiled_at = None;
+        }
+    }
+
+    /// Memory used by the messages of each channel, largest first
+    ///
+    /// Goes through all messages kept in memory, so it is only collected when profiling.
+    fn collect_memory_profile(&self) -> Vec<ChannelMemory> {
+        let mut profile: Vec<ChannelMemory> = self
+            .storage
+            .channels()
+            .map(|channel| ChannelMemory {
+                channel_id: channel.id,
+                name: self.channel_name(&channel).into_owned(),
+                loaded: self
+                    .messages
+                    .get(&channel.id)
+                    .map_or(0, |messages| messages.items.len()),
+                in_memory: self.storage.messages_in_memory(channel.id),
+            })
+            .collect();
+        profile.sort_by_key(|channel| Reverse(channel.in_memory.bytes));
+        profile
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::tests::test_app;
+
+    #[test]
+    fn test_memory_profile() {
+        let (mut app, _events, _sent_messages) = test_app();
+        app.step_memory_profile();
+        assert!(app.memory_profile.is_empty());
+
+        app.show_memory_profile(true);
+        let profile = &app.memory_profile;
+        assert_eq!(profile.len(), 1);
+        assert_eq!(profile[0].channel_id, app.channels.items[0]);
+        assert_eq!(profile[0].loaded, 1);
+        assert_eq!(profile[0].in_memory.messages, 1);
+        assert!(profile[0].in_memory.bytes > "First message".len());
+    }
+}
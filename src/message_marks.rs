@@ -0,0 +1,218 @@
+//! Marking several messages of a channel for applying a command to all of them at once
+
+use std::fmt::Write;
+
+use tracing::{error, info};
+
+use crate::app::App;
+use crate::data::{Message, MENTION_PLACEHOLDER};
+use crate::storage::MessageId;
+use crate::ui::Pane;
+use crate::util::{expand_home, utc_timestamp_msec_to_local};
+
+impl App {
+    /// Marks the selected message, or unmarks it
+    pub(crate) fn toggle_message_mark(&mut self) {
+        let Some(message_id) = self.selected_message_id() else {
+            return;
+        };
+        if !self.marked_messages.remove(&message_id) {
+            self.marked_messages.insert(message_id);
+        }
+        self.dirty.mark(Pane::Messages);
+    }
+
+    /// Marked messages of the selected channel, oldest first
+    pub(crate) fn selected_channel_marks(&self) -> Vec<MessageId> {
+        let Some(&channel_id) = self.channels.selected_item() else {
+            return Vec::new();
+        };
+        self.marked_messages
+            .iter()
+            .filter(|message_id| message_id.channel_id == channel_id)
+            .copied()
+            .collect()
+    }
+
+    /// Marked messages of the selected channel a command applies to
+    ///
+    /// The marks are cleared, since the command is applied to the marked messages now.
+    pub(crate) fn take_selected_channel_marks(&mut self) -> Vec<MessageId> {
+        let message_ids = self.selected_channel_marks();
+        for message_id in &message_ids {
+            self.marked_messages.remove(message_id);
+        }
+        if !message_ids.is_empty() {
+            self.dirty.mark(Pane::Messages);
+        }
+        message_ids
+    }
+
+    /// Copies the marked messages to the clipboard as one block of text
+    pub(crate) fn copy_marked_messages(&mut self) {
+        let message_ids = self.take_selected_channel_marks();
+        if message_ids.is_empty() {
+            self.command_error = Some("no messages are marked".to_string());
+            return;
+        }
+        let text = self.messages_text(&message_ids);
+        if let Some(clipboard) = self.clipboard.as_mut() {
+            if let Err(error) = clipboard.set_text(text) {
+                error!(%error, "failed to copy text to clipboard");
+            } else {
+                info!(
+                    count = message_ids.len(),
+                    "copied marked messages to clipboard"
+                );
+            }
+        }
+    }
+
+    /// Writes the marked messages as one block of text to the file
+    pub(crate) fn export_marked_messages(&mut self, path: &str) {
+        let message_ids = self.selected_channel_marks();
+        if message_ids.is_empty() {
+            self.command_error = Some("no messages are marked".to_string());
+            return;
+        }
+        let text = self.messages_text(&message_ids);
+        if let Err(error) = std::fs::write(expand_home(path), text) {
+            self.command_error = Some(format!("failed to export messages: {error}"));
+            return;
+        }
+        self.take_selected_channel_marks();
+        info!(count = message_ids.len(), "exported marked messages");
+    }
+
+    /// Deletes the marked messages and their attachments on this device only
+    ///
+    /// The messages are not deleted for the other members of the channel.
+    pub(crate) fn delete_marked_messages(&mut self) {
+        let message_ids = self.take_selected_channel_marks();
+        if message_ids.is_empty() {
+            self.command_error = Some("no messages are marked".to_string());
+            return;
+        }
+        let count = self.delete_messages_locally(&message_ids);
+        if count < message_ids.len() {
+            self.command_error = Some("messages being sent were not deleted".to_string());
+        }
+        info!(count, "deleted marked messages locally");
+    }
+
+    /// Formats the messages as one block of text, one message per line followed by its
+    /// attachments
+    fn messages_text(&self, message_ids: &[MessageId]) -> String {
+        let mut text = String::new();
+        for &message_id in message_ids {
+            let Some(message) = self.storage.message(message_id) else {
+                continue;
+            };
+            let arrived_at = utc_timestamp_msec_to_local(message.arrived_at).format("%F %R");
+            let name = self.name_by_id_cached(message.from_id);
+            let body = self.spelled_out_text(&message);
+            writeln!(text, "[{arrived_at}] {name}: {body}").expect("formatting message failed");
+            for attachment in &message.attachments {
+                writeln!(text, "<file://{}>", attachment.filename.display())
+                    .expect("formatting attachment failed");
+            }
+        }
+        text
+    }
+
+    /// Text of the message with the mentions replaced by the names
+    pub(crate) fn spelled_out_text(&self, message: &Message) -> String {
+        let mut mentions = message.mentions();
+        let mut text = String::new();
+        for c in message.message.as_deref().unwrap_or_default().chars() {
+            match (c == MENTION_PLACEHOLDER)
+                .then(|| mentions.next())
+                .flatten()
+            {
+                Some(user_id) => {
+                    text.push('@');
+                    text.push_str(&self.name_by_id_cached(user_id));
+                }
+                None => text.push(c),
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::tests::test_app;
+    use crate::command::{Command, MessageSelector};
+    use crate::signal::Attachment;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_marked_messages() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.txt");
+        std::fs::write(&path, "log").unwrap();
+        let attachment = Attachment {
+            id: "log".to_string(),
+            content_type: "text/plain".to_string(),
+            filename: path.clone(),
+            size: 3,
+        };
+        let second = Message::new(app.user_id, Some("second".into()), [], 1, vec![attachment]);
+        app.add_message_to_channel(0, second);
+        let third = Message::new(app.user_id, Some("third".into()), [], 2, Vec::new());
+        app.add_message_to_channel(0, third);
+        let mark = |app: &mut App, arrived_at: usize| {
+            // the newest message is the first one to select
+            let idx = 2 - arrived_at;
+            app.messages
+                .get_mut(&channel_id)
+                .unwrap()
+                .state
+                .select(Some(idx));
+            app.toggle_message_mark();
+        };
+
+        mark(&mut app, 0);
+        mark(&mut app, 2);
+        mark(&mut app, 1);
+        mark(&mut app, 2); // unmarked again
+        let marked = [MessageId::new(channel_id, 0), MessageId::new(channel_id, 1)];
+        assert_eq!(app.selected_channel_marks(), marked);
+
+        app.on_command(Command::ForwardMessage).await.unwrap();
+        assert!(app.select_channel.is_shown);
+        assert_eq!(app.select_channel.forward, marked);
+        app.select_channel.is_shown = false;
+
+        let export = dir.path().join("marked.txt");
+        app.on_command(Command::ExportMessages(
+            export.to_str().unwrap().to_string(),
+        ))
+        .await
+        .unwrap();
+        assert_eq!(app.command_error, None);
+        let exported = std::fs::read_to_string(&export).unwrap();
+        let lines: Vec<_> = exported.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with("] Tyler Durden: First message"));
+        assert!(lines[1].ends_with("] Tyler Durden: second"));
+        assert_eq!(lines[2], format!("<file://{}>", path.display()));
+        // the command was applied to the marked messages
+        assert!(app.marked_messages.is_empty());
+
+        app.on_command(Command::CopyMessage(MessageSelector::Marked))
+            .await
+            .unwrap();
+        assert_eq!(app.command_error.as_deref(), Some("no messages are marked"));
+
+        mark(&mut app, 1);
+        app.on_command(Command::DeleteMarkedMessages).await.unwrap();
+        assert_eq!(app.messages[&channel_id].items, [0, 2]);
+        assert_eq!(app.storage.message(MessageId::new(channel_id, 1)), None);
+        assert!(!path.exists());
+    }
+}
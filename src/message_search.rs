@@ -0,0 +1,181 @@
+use itertools::Itertools;
+use ratatui::widgets::ListState;
+
+use crate::app::App;
+use crate::input::Input;
+use crate::storage::MessageId;
+use crate::util::utc_timestamp_msec_to_local;
+
+/// Maximum number of shown search results
+const RESULTS_LIMIT: usize = 100;
+
+/// Popup for searching the messages of all channels
+///
+/// The search is backed by the full-text index of the storage, so it also finds messages which are
+/// not loaded yet. The storage is only searched when the query changes; the results are kept
+/// ready for drawing them.
+#[derive(Default)]
+pub(crate) struct MessageSearch {
+    pub is_shown: bool,
+    pub input: Input,
+    pub state: ListState,
+    /// Messages matching the query, newest first
+    results: Vec<SearchResult>,
+    /// Query of the results
+    query: String,
+}
+
+/// Message matching the query, as shown in the popup
+pub(crate) struct SearchResult {
+    pub message_id: MessageId,
+    /// Local time the message arrived at
+    pub arrived_at: String,
+    pub channel_name: String,
+    /// Text of the message on one line
+    pub text: String,
+}
+
+impl MessageSearch {
+    pub fn open(&mut self) {
+        self.input.take();
+        self.query.clear();
+        self.results.clear();
+        self.state = Default::default();
+        self.is_shown = true;
+    }
+
+    pub fn close(&mut self) {
+        self.is_shown = false;
+        self.results.clear();
+    }
+
+    /// Query of the input, if the results do not match it yet
+    fn changed_query(&self) -> Option<&str> {
+        (self.input.data != self.query).then_some(self.input.data.as_str())
+    }
+
+    fn set_results(&mut self, query: String, results: Vec<SearchResult>) {
+        self.query = query;
+        self.results = results;
+        self.state = Default::default();
+        if !self.results.is_empty() {
+            self.state.select(Some(0));
+        }
+    }
+
+    pub fn results(&self) -> &[SearchResult] {
+        &self.results
+    }
+
+    pub fn prev(&mut self) {
+        let selected = self
+            .state
+            .selected()
+            .map(|idx| idx.saturating_sub(1))
+            .unwrap_or(0);
+        self.state.select(Some(selected));
+    }
+
+    pub fn next(&mut self) {
+        let selected = self
+            .state
+            .selected()
+            .map(|idx| (idx + 1).min(self.results.len().saturating_sub(1)))
+            .unwrap_or(0);
+        self.state.select(Some(selected));
+    }
+
+    pub fn selected(&self) -> Option<MessageId> {
+        Some(self.results.get(self.state.selected()?)?.message_id)
+    }
+}
+
+impl App {
+    /// Searches the messages, if the query of the message search changed since the last search
+    pub fn update_message_search(&mut self) {
+        if !self.message_search.is_shown {
+            return;
+        }
+        let Some(query) = self.message_search.changed_query().map(str::to_string) else {
+            return;
+        };
+        let results = self
+            .storage
+            .search_messages(&query, RESULTS_LIMIT)
+            .into_iter()
+            .filter_map(|message_id| {
+                let message = self.storage.message(message_id)?;
+                let channel_name = self
+                    .storage
+                    .channel(message_id.channel_id)
+                    .map(|channel| self.channel_name(&channel).into_owned())
+                    .unwrap_or_default();
+                Some(SearchResult {
+                    message_id,
+                    arrived_at: utc_timestamp_msec_to_local(message.arrived_at)
+                        .format("%F %R")
+                        .to_string(),
+                    channel_name,
+                    text: message
+                        .message
+                        .as_deref()
+                        .unwrap_or_default()
+                        .split_whitespace()
+                        .join(" "),
+                })
+            })
+            .collect();
+        self.message_search.set_results(query, results);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use crate::app::tests::test_app;
+    use crate::command::Command;
+    use crate::data::Message;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_search_messages() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        for (arrived_at, text) in [(1, "Meet at the station"), (2, "meeting  postponed")] {
+            let message = Message::new(app.user_id, Some(text.to_string()), [], arrived_at, vec![]);
+            app.storage.store_message(channel_id, message);
+        }
+
+        app.on_command(Command::ToggleMessageSearch).await.unwrap();
+        for c in "mee".chars() {
+            app.on_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))
+                .await
+                .unwrap();
+        }
+        let search = &mut app.message_search;
+        let ids: Vec<_> = search.results().iter().map(|r| r.message_id).collect();
+        assert_eq!(
+            ids,
+            [MessageId::new(channel_id, 2), MessageId::new(channel_id, 1)]
+        );
+        assert_eq!(search.results()[0].channel_name, "test");
+        assert_eq!(search.results()[0].text, "meeting postponed");
+        assert_eq!(search.selected(), Some(MessageId::new(channel_id, 2)));
+        search.next();
+        assert_eq!(search.selected(), Some(MessageId::new(channel_id, 1)));
+
+        // the results are kept until the query changes
+        app.storage.delete_message(MessageId::new(channel_id, 2));
+        app.update_message_search();
+        assert_eq!(app.message_search.results().len(), 2);
+
+        " STAT".chars().for_each(|c| app.get_input().put_char(c));
+        app.update_message_search();
+        let search = &app.message_search;
+        let ids: Vec<_> = search.results().iter().map(|r| r.message_id).collect();
+        assert_eq!(ids, [MessageId::new(channel_id, 1)]);
+        assert_eq!(search.selected(), Some(MessageId::new(channel_id, 1)));
+    }
+}
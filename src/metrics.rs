@@ -0,0 +1,179 @@
+//! Metrics of the daemon in the text format of Prometheus, served over HTTP at `/metrics`
+
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use chrono::{DateTime, Utc};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::app::{App, ConnectionState};
+
+/// How long a client may take to send its request, before its connection is closed
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Counters updated by the app and the event loop
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Messages received from contacts and groups
+    pub messages_received: u64,
+    pub messages_sent: u64,
+    /// Incoming messages which failed to be handled
+    pub message_failures: u64,
+    pub send_failures: u64,
+    pub reconnects: u64,
+    pub last_received_at: Option<DateTime<Utc>>,
+}
+
+impl App {
+    /// Metrics in the text format of Prometheus
+    pub fn render_metrics(&self) -> String {
+        let metrics = &self.metrics;
+        let is_connected = self.connection_state == ConnectionState::Connected;
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+            let _ = writeln!(text, "# HELP gurk_{name} {help}");
+            let _ = writeln!(text, "# TYPE gurk_{name} {kind}");
+            let _ = writeln!(text, "gurk_{name} {value}");
+        };
+        metric(
+            "messages_received_total",
+            "counter",
+            "Messages received from contacts and groups",
+            metrics.messages_received,
+        );
+        metric(
+            "messages_sent_total",
+            "counter",
+            "Messages sent",
+            metrics.messages_sent,
+        );
+        metric(
+            "message_failures_total",
+            "counter",
+            "Incoming messages which failed to be handled",
+            metrics.message_failures,
+        );
+        metric(
+            "send_failures_total",
+            "counter",
+            "Messages which failed to be sent",
+            metrics.send_failures,
+        );
+        metric(
+            "reconnects_total",
+            "counter",
+            "Reconnections after the connection to Signal was lost",
+            metrics.reconnects,
+        );
+        metric(
+            "outbox_messages",
+            "gauge",
+            "Messages queued while offline",
+            self.outbox.len() as u64,
+        );
+        metric(
+            "connected",
+            "gauge",
+            "Whether connected to Signal",
+            is_connected.into(),
+        );
+        if let Some(at) = metrics.last_received_at {
+            metric(
+                "last_received_timestamp_seconds",
+                "gauge",
+                "When the last message was received from Signal",
+                at.timestamp().try_into().unwrap_or_default(),
+            );
+        }
+        text
+    }
+}
+
+/// Serves the latest metrics at `/metrics`
+pub async fn serve(
+    address: SocketAddr,
+    metrics: watch::Receiver<String>,
+) -> anyhow::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(address)
+        .await
+        .with_context(|| format!("failed to bind {address}"))?;
+    Ok(tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let metrics = metrics.borrow().clone();
+                    tokio::spawn(respond(stream, metrics));
+                }
+                Err(error) => {
+                    warn!(%error, "failed to accept a connection to the metrics endpoint");
+                    break;
+                }
+            }
+        }
+    }))
+}
+
+async fn respond(mut stream: TcpStream, metrics: String) {
+    // the request line is all that matters, and fits into the first read
+    let mut request = [0; 1024];
+    let Ok(Ok(len)) = tokio::time::timeout(READ_TIMEOUT, stream.read(&mut request)).await else {
+        return; // failed, or timed out
+    };
+    let request = String::from_utf8_lossy(&request[..len]);
+    let (status, body) = if is_metrics_request(&request) {
+        ("200 OK", metrics)
+    } else {
+        ("404 Not Found", "not found\n".to_string())
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\n\
+        Content-Type: text/plain; version=0.0.4\r\n\
+        Content-Length: {}\r\n\
+        Connection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+}
+
+fn is_metrics_request(request: &str) -> bool {
+    let mut request_line = request.lines().next().unwrap_or_default().split(' ');
+    request_line.next() == Some("GET")
+        && request_line
+            .next()
+            .is_some_and(|path| path == "/metrics" || path.starts_with("/metrics?"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::tests::test_app;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_render_metrics() {
+        let (mut app, _events, _sent_messages) = test_app();
+        app.metrics.messages_received = 3;
+        app.set_connection_state(ConnectionState::Connected);
+        let text = app.render_metrics();
+        assert!(text.contains("# TYPE gurk_messages_received_total counter\n"));
+        assert!(text.contains("\ngurk_messages_received_total 3\n"));
+        assert!(text.contains("\ngurk_connected 1\n"));
+        assert!(!text.contains("last_received"));
+    }
+
+    #[test]
+    fn test_is_metrics_request() {
+        assert!(is_metrics_request(
+            "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n"
+        ));
+        assert!(is_metrics_request("GET /metrics?name[]=x HTTP/1.1\r\n"));
+        assert!(!is_metrics_request("GET / HTTP/1.1\r\n"));
+        assert!(!is_metrics_request("POST /metrics HTTP/1.1\r\n"));
+    }
+}
@@ -0,0 +1,160 @@
+//! Names, nicknames, phone numbers and profile keys of the known users
+//!
+//! Resolving a name requires asking the Signal store or the server, so the names are resolved
+//! once and kept here. Everything showing or looking up a user goes through [`Names`].
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use phonenumber::PhoneNumber;
+use uuid::Uuid;
+
+use crate::signal::ProfileKeyBytes;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct KnownUser {
+    /// Name resolved from the profile or the contacts
+    pub display_name: Option<String>,
+    /// Local name shown instead of the resolved one
+    pub nickname: Option<String>,
+    pub phone_number: Option<PhoneNumber>,
+    pub profile_key: Option<ProfileKeyBytes>,
+}
+
+impl KnownUser {
+    /// The nickname, if set, or the resolved name
+    fn shown_name(&self) -> Option<&str> {
+        self.nickname.as_deref().or(self.display_name.as_deref())
+    }
+}
+
+/// Known users by their id, and the ids by their phone number
+#[derive(Debug, Default)]
+pub(crate) struct Names {
+    users: BTreeMap<Uuid, KnownUser>,
+    /// Ids by phone numbers in E.164 format
+    by_phone_number: BTreeMap<String, Uuid>,
+    /// Users whose messages, reactions and receipts are dropped
+    blocked: BTreeSet<Uuid>,
+}
+
+impl Names {
+    pub fn get(&self, id: Uuid) -> Option<&KnownUser> {
+        self.users.get(&id)
+    }
+
+    /// Name the user is shown with, i.e. the nickname if set
+    pub fn display_name(&self, id: Uuid) -> Option<&str> {
+        self.users.get(&id)?.shown_name()
+    }
+
+    /// Name resolved from the profile or the contacts, ignoring the nickname
+    pub fn resolved_name(&self, id: Uuid) -> Option<&str> {
+        self.users.get(&id)?.display_name.as_deref()
+    }
+
+    /// Sets the resolved name of the user
+    ///
+    /// Returns whether the name changed.
+    pub fn set_display_name(&mut self, id: Uuid, name: String) -> bool {
+        let user = self.users.entry(id).or_default();
+        if user.display_name.as_ref() == Some(&name) {
+            false
+        } else {
+            user.display_name = Some(name);
+            true
+        }
+    }
+
+    /// Sets the nickname shown instead of the resolved name, or clears it
+    pub fn set_nickname(&mut self, id: Uuid, nickname: Option<String>) {
+        self.users.entry(id).or_default().nickname = nickname;
+    }
+
+    pub fn is_blocked(&self, id: Uuid) -> bool {
+        self.blocked.contains(&id)
+    }
+
+    pub fn set_blocked(&mut self, id: Uuid, blocked: bool) {
+        if blocked {
+            self.blocked.insert(id);
+        } else {
+            self.blocked.remove(&id);
+        }
+    }
+
+    pub fn phone_number(&self, id: Uuid) -> Option<&PhoneNumber> {
+        self.users.get(&id)?.phone_number.as_ref()
+    }
+
+    pub fn set_phone_number(&mut self, id: Uuid, phone_number: PhoneNumber) {
+        self.by_phone_number.insert(e164(&phone_number), id);
+        self.users.entry(id).or_default().phone_number = Some(phone_number);
+    }
+
+    pub fn set_profile_key(&mut self, id: Uuid, profile_key: ProfileKeyBytes) {
+        self.users.entry(id).or_default().profile_key = Some(profile_key);
+    }
+
+    pub fn by_phone_number(&self, phone_number: &PhoneNumber) -> Option<Uuid> {
+        self.by_phone_number.get(&e164(phone_number)).copied()
+    }
+
+    pub fn display_names(&self) -> impl Iterator<Item = &str> {
+        self.users.values().filter_map(KnownUser::shown_name)
+    }
+
+    /// Ids of the users named `name`, compared case-insensitively
+    pub fn by_display_name(&self, name: &str) -> impl Iterator<Item = Uuid> + '_ {
+        let name = name.to_lowercase();
+        self.users
+            .iter()
+            .filter(move |(_, user)| {
+                user.shown_name()
+                    .is_some_and(|shown_name| shown_name.to_lowercase() == name)
+            })
+            .map(|(&id, _)| id)
+    }
+}
+
+fn e164(phone_number: &PhoneNumber) -> String {
+    phone_number
+        .format()
+        .mode(phonenumber::Mode::E164)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_by_phone_number_and_name() {
+        let mut names = Names::default();
+        let id = Uuid::new_v4();
+        let number: PhoneNumber = phonenumber::parse(None, "+4917612345678").unwrap();
+
+        assert!(names.set_display_name(id, "Alice".to_string()));
+        assert!(!names.set_display_name(id, "Alice".to_string()));
+        names.set_phone_number(id, number.clone());
+        names.set_profile_key(id, [1; 32]);
+
+        assert_eq!(names.display_name(id), Some("Alice"));
+        assert_eq!(names.by_phone_number(&number), Some(id));
+        assert_eq!(names.phone_number(id), Some(&number));
+        assert_eq!(names.by_display_name("alice").collect::<Vec<_>>(), [id]);
+        assert_eq!(names.get(id).unwrap().profile_key, Some([1; 32]));
+
+        // the nickname takes precedence over the resolved name
+        names.set_nickname(id, Some("Ali".to_string()));
+        assert_eq!(names.display_name(id), Some("Ali"));
+        assert_eq!(names.by_display_name("ali").collect::<Vec<_>>(), [id]);
+        assert_eq!(names.by_display_name("alice").count(), 0);
+        assert_eq!(names.resolved_name(id), Some("Alice"));
+        names.set_nickname(id, None);
+        assert_eq!(names.display_name(id), Some("Alice"));
+
+        assert!(!names.is_blocked(id));
+        names.set_blocked(id, true);
+        assert!(names.is_blocked(id));
+    }
+}
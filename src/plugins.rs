@@ -0,0 +1,382 @@
+//! Lua plugins loaded from `~/.config/gurk/plugins/`
+//!
+//! Plugins run in a sandbox without access to files or processes. They talk to gurk through the
+//! global table `gurk`:
+//!
+//! * `gurk.channels()`: list of the channels, each with `id`, `name`, `is_group` and `unread`
+//! * `gurk.send(channel_id, text)`: sends the text to the channel
+//! * `gurk.register_command(name, function(args) ... end)`: adds the command `/name`
+//! * `gurk.on_message(function(message) ... end)`: called for each received message, with the
+//!   same fields as the event of the `on_message_received` hook
+//! * `gurk.status(function() return "..." end)`: segment shown in the status bar
+//! * `gurk.log(text)`: writes the text to the log
+//!
+//! Plugins are called on the UI thread, e.g. the status segments for every frame, so each call is
+//! interrupted after [`CALL_TIME_LIMIT`], and the memory of all plugins is limited to
+//! [`MEMORY_LIMIT`].
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use anyhow::Context as _;
+use mlua::{Function, HookTriggers, Lua, LuaOptions, LuaSerdeExt, StdLib, Value, VmState};
+use tracing::{info, warn};
+
+use crate::data::ChannelId;
+use crate::hooks::{ChannelSummary, MessageEvent};
+use crate::util;
+
+/// Time after which a call into the plugins is interrupted
+const CALL_TIME_LIMIT: Duration = Duration::from_millis(50);
+
+/// Memory all plugins together may use
+const MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+/// Request of a plugin, applied by the app after the plugin returned
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum PluginAction {
+    Send { channel_id: ChannelId, text: String },
+}
+
+/// State shared between the app and the functions of the `gurk` table
+#[derive(Default)]
+struct State {
+    channels: RefCell<Vec<ChannelSummary>>,
+    commands: RefCell<BTreeMap<String, Function>>,
+    message_handlers: RefCell<Vec<Function>>,
+    status_segments: RefCell<Vec<Function>>,
+    actions: RefCell<Vec<PluginAction>>,
+    /// End of the time of the current call into the plugins
+    deadline: Cell<Option<Instant>>,
+}
+
+pub(crate) struct Plugins {
+    lua: Lua,
+    state: Rc<State>,
+}
+
+impl Plugins {
+    /// Loads all `*.lua` files of the directory in alphabetical order
+    ///
+    /// A plugin failing to load is skipped.
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let plugins = Self::new()?;
+        let mut paths: Vec<_> = std::fs::read_dir(dir)
+            .with_context(|| format!("failed to read {}", dir.display()))?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "lua"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let result = std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|code| plugins.load_source(&path.display().to_string(), &code));
+            match result {
+                Ok(()) => info!(path = %path.display(), "loaded plugin"),
+                Err(error) => warn!(path = %path.display(), %error, "failed to load plugin"),
+            }
+        }
+        Ok(plugins)
+    }
+
+    fn new() -> anyhow::Result<Self> {
+        Self::with_api().map_err(lua_error)
+    }
+
+    fn with_api() -> mlua::Result<Self> {
+        // no `io`, `os` and `package`, so that plugins cannot touch files or run processes
+        let lua = Lua::new_with(
+            StdLib::COROUTINE | StdLib::TABLE | StdLib::STRING | StdLib::UTF8 | StdLib::MATH,
+            LuaOptions::new(),
+        )?;
+        let globals = lua.globals();
+        globals.set("dofile", Value::Nil)?;
+        globals.set("loadfile", Value::Nil)?;
+        // stdout belongs to the UI
+        globals.set("print", Value::Nil)?;
+        lua.set_memory_limit(MEMORY_LIMIT)?;
+
+        let state = Rc::new(State::default());
+        lua.set_hook(HookTriggers::new().every_nth_instruction(1000), {
+            let state = state.clone();
+            move |_, _| match state.deadline.get() {
+                Some(deadline) if Instant::now() > deadline => Err(mlua::Error::RuntimeError(
+                    "plugin interrupted after taking too long".to_string(),
+                )),
+                _ => Ok(VmState::Continue),
+            }
+        });
+        let gurk = lua.create_table()?;
+        gurk.set(
+            "channels",
+            lua.create_function({
+                let state = state.clone();
+                move |lua, ()| lua.to_value(&*state.channels.borrow())
+            })?,
+        )?;
+        gurk.set(
+            "send",
+            lua.create_function({
+                let state = state.clone();
+                move |_, (channel_id, text): (String, String)| {
+                    let channel_id = util::parse_channel_id(&channel_id).ok_or_else(|| {
+                        mlua::Error::RuntimeError(format!("invalid channel id: {channel_id}"))
+                    })?;
+                    state
+                        .actions
+                        .borrow_mut()
+                        .push(PluginAction::Send { channel_id, text });
+                    Ok(())
+                }
+            })?,
+        )?;
+        gurk.set(
+            "register_command",
+            lua.create_function({
+                let state = state.clone();
+                move |_, (name, f): (String, Function)| {
+                    state.commands.borrow_mut().insert(name, f);
+                    Ok(())
+                }
+            })?,
+        )?;
+        gurk.set(
+            "on_message",
+            lua.create_function({
+                let state = state.clone();
+                move |_, f: Function| {
+                    state.message_handlers.borrow_mut().push(f);
+                    Ok(())
+                }
+            })?,
+        )?;
+        gurk.set(
+            "status",
+            lua.create_function({
+                let state = state.clone();
+                move |_, f: Function| {
+                    state.status_segments.borrow_mut().push(f);
+                    Ok(())
+                }
+            })?,
+        )?;
+        gurk.set(
+            "log",
+            lua.create_function(|_, text: String| {
+                info!("plugin: {text}");
+                Ok(())
+            })?,
+        )?;
+        globals.set("gurk", gurk)?;
+
+        Ok(Self { lua, state })
+    }
+
+    fn load_source(&self, name: &str, code: &str) -> anyhow::Result<()> {
+        self.limited(|| self.lua.load(code).set_name(name).exec())
+            .map_err(lua_error)
+    }
+
+    /// Runs the call into the plugins, interrupting it after [`CALL_TIME_LIMIT`]
+    fn limited<T>(&self, call: impl FnOnce() -> mlua::Result<T>) -> mlua::Result<T> {
+        self.state
+            .deadline
+            .set(Some(Instant::now() + CALL_TIME_LIMIT));
+        let result = call();
+        self.state.deadline.set(None);
+        result
+    }
+
+    /// Updates the channels returned by `gurk.channels()`
+    pub fn set_channels(&self, channels: Vec<ChannelSummary>) {
+        *self.state.channels.borrow_mut() = channels;
+    }
+
+    pub fn has_command(&self, name: &str) -> bool {
+        self.state.commands.borrow().contains_key(name)
+    }
+
+    /// Names of the commands registered by plugins
+    pub fn command_names(&self) -> Vec<String> {
+        self.state.commands.borrow().keys().cloned().collect()
+    }
+
+    pub fn run_command(&self, name: &str, args: &str) -> anyhow::Result<()> {
+        let f = self
+            .state
+            .commands
+            .borrow()
+            .get(name)
+            .cloned()
+            .with_context(|| format!("no plugin command {name}"))?;
+        self.limited(|| f.call::<()>(args)).map_err(lua_error)
+    }
+
+    /// Passes the received message to the plugins subscribed to messages
+    pub fn on_message(&self, message: &MessageEvent) {
+        let handlers = self.state.message_handlers.borrow().clone();
+        if handlers.is_empty() {
+            return;
+        }
+        let message = match self.lua.to_value(message) {
+            Ok(message) => message,
+            Err(error) => {
+                warn!(%error, "failed to convert the message for plugins");
+                return;
+            }
+        };
+        for f in handlers {
+            if let Err(error) = self.limited(|| f.call::<()>(message.clone())) {
+                warn!(%error, "plugin failed to handle message");
+            }
+        }
+    }
+
+    /// Texts of the status segments of the plugins; failing segments are left out
+    pub fn status_segments(&self) -> Vec<String> {
+        self.state
+            .status_segments
+            .borrow()
+            .iter()
+            .filter_map(|f| self.limited(|| f.call::<Option<String>>(())).ok().flatten())
+            .filter(|text| !text.is_empty())
+            .collect()
+    }
+
+    /// Takes the requests of the plugins made since the last call
+    pub fn take_actions(&self) -> Vec<PluginAction> {
+        std::mem::take(&mut *self.state.actions.borrow_mut())
+    }
+}
+
+/// Errors of Lua are not `Send`, so they are converted via their message
+fn lua_error(error: mlua::Error) -> anyhow::Error {
+    anyhow::anyhow!("{error}")
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::hooks::ChannelEvent;
+
+    use super::*;
+
+    fn plugins(code: &str) -> Plugins {
+        let plugins = Plugins::new().unwrap();
+        plugins.load_source("test.lua", code).unwrap();
+        plugins
+    }
+
+    #[test]
+    fn test_sandbox() {
+        let plugins = Plugins::new().unwrap();
+        assert!(plugins.load_source("io.lua", "io.open('x')").is_err());
+        assert!(plugins.load_source("os.lua", "os.execute('true')").is_err());
+        assert!(plugins.load_source("dofile.lua", "dofile('x')").is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        let plugins = plugins(
+            r#"
+            gurk.status(function()
+                while true do end
+            end)
+            gurk.status(function()
+                return "ok"
+            end)
+            gurk.register_command("hoard", function()
+                local parts = {}
+                for i = 1, 1e9 do
+                    parts[i] = string.rep("x", 1024) .. i
+                end
+            end)
+            "#,
+        );
+        let started_at = Instant::now();
+        assert_eq!(plugins.status_segments(), ["ok"]);
+        assert!(started_at.elapsed() < Duration::from_secs(5));
+
+        assert!(plugins.run_command("hoard", "").is_err());
+        // the plugins keep working
+        assert_eq!(plugins.status_segments(), ["ok"]);
+    }
+
+    #[test]
+    fn test_commands_and_channels() {
+        let plugins = plugins(
+            r#"
+            gurk.register_command("greet_all", function(args)
+                for _, channel in ipairs(gurk.channels()) do
+                    if not channel.is_group then
+                        gurk.send(channel.id, args .. " " .. channel.name)
+                    end
+                end
+            end)
+            "#,
+        );
+        let user_id = ChannelId::User(Uuid::new_v4());
+        plugins.set_channels(vec![
+            ChannelSummary::new(user_id, "Tyler".to_string(), 0),
+            ChannelSummary::new(ChannelId::Group([0; 32]), "Club".to_string(), 2),
+        ]);
+        assert!(plugins.has_command("greet_all"));
+        assert!(!plugins.has_command("greet"));
+        assert_eq!(plugins.command_names(), ["greet_all"]);
+
+        plugins.run_command("greet_all", "Hi").unwrap();
+        assert_eq!(
+            plugins.take_actions(),
+            [PluginAction::Send {
+                channel_id: user_id,
+                text: "Hi Tyler".to_string()
+            }]
+        );
+        assert!(plugins.take_actions().is_empty());
+
+        assert!(plugins.run_command("greet", "").is_err());
+    }
+
+    #[test]
+    fn test_messages_and_status() {
+        let plugins = plugins(
+            r#"
+            local received = 0
+            gurk.on_message(function(message)
+                received = received + 1
+                if message.text == "ping" then
+                    gurk.send(message.channel.id, "pong")
+                end
+            end)
+            gurk.status(function()
+                return "received " .. received
+            end)
+            gurk.status(function()
+                error("broken")
+            end)
+            "#,
+        );
+        assert_eq!(plugins.status_segments(), ["received 0"]);
+
+        let channel_id = ChannelId::User(Uuid::new_v4());
+        plugins.on_message(&MessageEvent {
+            channel: ChannelEvent::new(channel_id, "Tyler"),
+            from: "Tyler",
+            text: "ping",
+            timestamp: 42,
+            attachments: Vec::new(),
+        });
+        assert_eq!(plugins.status_segments(), ["received 1"]);
+        assert_eq!(
+            plugins.take_actions(),
+            [PluginAction::Send {
+                channel_id,
+                text: "pong".to_string()
+            }]
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! Export and import of the settings profile: the config and the settings of the channels, but
+//! no messages
+
+use std::path::Path;
+
+use anyhow::{bail, Context as _};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, NotificationConfig};
+use crate::data::{Channel, ChannelId, NotificationContent};
+
+/// Version of the profile format
+const PROFILE_VERSION: u32 = 1;
+
+/// Settings for replicating a setup on another machine
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Profile {
+    version: u32,
+    config: Config,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    channels: Vec<ChannelSettings>,
+}
+
+/// Settings of a channel
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ChannelSettings {
+    pub id: ChannelId,
+    /// Name of the channel; only for reading the exported file
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    muted_until: Option<u64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pinned: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    archived: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    burn_after_reading: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_messages: Option<usize>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    ignore_mention_all: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notification_content: Option<NotificationContent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nickname: Option<String>,
+}
+
+impl ChannelSettings {
+    fn new(channel: &Channel) -> Self {
+        Self {
+            id: channel.id,
+            name: channel.name.clone(),
+            muted_until: channel.muted_until,
+            pinned: channel.pinned,
+            archived: channel.archived,
+            burn_after_reading: channel.burn_after_reading,
+            max_messages: channel.max_messages,
+            ignore_mention_all: channel.ignore_mention_all,
+            notification_content: channel.notification_content,
+            nickname: channel.nickname.clone(),
+        }
+    }
+
+    fn is_default(&self) -> bool {
+        self.muted_until.is_none()
+            && !self.pinned
+            && !self.archived
+            && !self.burn_after_reading
+            && self.max_messages.is_none()
+            && !self.ignore_mention_all
+            && self.notification_content.is_none()
+            && self.nickname.is_none()
+    }
+
+    /// Overwrites the settings of the channel
+    pub fn apply(&self, channel: &mut Channel) {
+        channel.muted_until = self.muted_until;
+        channel.pinned = self.pinned;
+        channel.archived = self.archived;
+        channel.burn_after_reading = self.burn_after_reading;
+        channel.max_messages = self.max_messages;
+        channel.ignore_mention_all = self.ignore_mention_all;
+        channel.notification_content = self.notification_content;
+        channel.nickname.clone_from(&self.nickname);
+    }
+}
+
+impl Profile {
+    /// Profile of the config and the channels with changed settings
+    ///
+    /// The passphrase, the shell commands and the local endpoints are not exported.
+    pub fn new<'a>(config: &Config, channels: impl IntoIterator<Item = &'a Channel>) -> Self {
+        let config = Config {
+            passphrase: None,
+            notification: NotificationConfig {
+                focus_command: None,
+                sound_command: None,
+                ..config.notification.clone()
+            },
+            rpc_socket: None,
+            hooks: Default::default(),
+            metrics_address: None,
+            ..config.clone()
+        };
+        let channels = channels
+            .into_iter()
+            .map(ChannelSettings::new)
+            .filter(|settings| !settings.is_default())
+            .collect();
+        Self {
+            version: PROFILE_VERSION,
+            config,
+            channels,
+        }
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let profile: Self = toml::from_str(&content)?;
+        if profile.version > PROFILE_VERSION {
+            bail!("unsupported profile version {}", profile.version);
+        }
+        Ok(profile)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let content = toml::to_string(self)?;
+        std::fs::write(path, content)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Config of the profile, keeping the user and the paths of this machine
+    ///
+    /// The shell commands and the local endpoints of this machine are kept as well, so that
+    /// importing a profile never runs commands from it.
+    pub fn config_for(&self, local: &Config) -> Config {
+        Config {
+            user: local.user.clone(),
+            data_path: local.data_path.clone(),
+            signal_db_path: local.signal_db_path.clone(),
+            sqlite: local.sqlite.clone(),
+            passphrase: local.passphrase.clone(),
+            notification: NotificationConfig {
+                focus_command: local.notification.focus_command.clone(),
+                sound_command: local.notification.sound_command.clone(),
+                ..self.config.notification.clone()
+            },
+            rpc_socket: local.rpc_socket.clone(),
+            hooks: local.hooks.clone(),
+            metrics_address: local.metrics_address,
+            #[cfg(feature = "dev")]
+            developer: local.developer.clone(),
+            ..self.config.clone()
+        }
+    }
+
+    pub fn channels(&self) -> &[ChannelSettings] {
+        &self.channels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use uuid::Uuid;
+
+    use crate::config::User;
+    use crate::data::TypingSet;
+
+    use super::*;
+
+    fn channel(name: &str) -> Channel {
+        Channel {
+            id: ChannelId::User(Uuid::new_v4()),
+            name: name.to_string(),
+            group_data: None,
+            unread_messages: 3,
+            typing: TypingSet::SingleTyping(false),
+            muted_until: None,
+            pinned: false,
+            archived: false,
+            burn_after_reading: false,
+            max_messages: None,
+            ignore_mention_all: false,
+            notification_content: None,
+            nickname: None,
+        }
+    }
+
+    fn config(phone_number: &str, dir: &str) -> Config {
+        let mut config = Config::with_user(User {
+            name: "Tyler Durden".to_string(),
+            phone_number: phone_number.to_string(),
+        });
+        config.set_data_dir(Path::new(dir)).unwrap();
+        config
+    }
+
+    #[test]
+    fn test_export_and_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("profile.toml");
+
+        let mut exported = config("+0000000000", "/home/tyler/gurk");
+        exported.passphrase = Some("secret".to_string());
+        exported.notifications = false;
+        exported
+            .snippets
+            .insert("hi".to_string(), "Hi {first_name}!".to_string());
+        let pinned = Channel {
+            pinned: true,
+            max_messages: Some(100),
+            ..channel("pinned")
+        };
+        Profile::new(&exported, [&channel("plain"), &pinned])
+            .save(&path)
+            .unwrap();
+
+        let profile = Profile::load(&path).unwrap();
+        assert_eq!(profile.config.passphrase, None);
+        assert_eq!(profile.channels().len(), 1);
+        assert_eq!(profile.channels()[0].id, pinned.id);
+
+        let local = config("+0000000000", "/srv/gurk");
+        let imported = profile.config_for(&local);
+        assert!(!imported.notifications);
+        assert_eq!(imported.snippets, exported.snippets);
+        assert_eq!(
+            imported.signal_db_path,
+            PathBuf::from("/srv/gurk/signal-db")
+        );
+
+        let mut local_channel = Channel {
+            unread_messages: 5,
+            ..channel("pinned")
+        };
+        profile.channels()[0].apply(&mut local_channel);
+        assert!(local_channel.pinned);
+        assert_eq!(local_channel.max_messages, Some(100));
+        assert_eq!(local_channel.unread_messages, 5);
+    }
+
+    #[test]
+    fn test_commands_and_endpoints_stay_local() {
+        let mut exported = config("+0000000000", "/home/tyler/gurk");
+        exported.notification.when_focused = true;
+        exported.notification.sound_command = Some("paplay ping.oga".to_string());
+        exported.hooks.on_startup = Some("notify-send started".to_string());
+        exported.rpc_socket = Some("/run/user/1000/gurk.sock".into());
+        exported.metrics_address = Some("127.0.0.1:9100".parse().unwrap());
+        let profile = Profile::new(&exported, []);
+        assert!(profile.config.notification.when_focused);
+        assert_eq!(profile.config.notification.sound_command, None);
+        assert_eq!(profile.config.hooks, Default::default());
+        assert_eq!(profile.config.rpc_socket, None);
+        assert_eq!(profile.config.metrics_address, None);
+
+        // a crafted profile does not run commands when imported
+        let mut local = config("+0000000000", "/srv/gurk");
+        local.notification.focus_command = Some("tmux-focused".to_string());
+        let imported = Profile {
+            config: exported.clone(),
+            ..profile
+        }
+        .config_for(&local);
+        assert!(imported.notification.when_focused);
+        assert_eq!(
+            imported.notification.focus_command.as_deref(),
+            Some("tmux-focused")
+        );
+        assert_eq!(imported.notification.sound_command, None);
+        assert_eq!(imported.hooks, Default::default());
+        assert_eq!(imported.rpc_socket, None);
+        assert_eq!(imported.metrics_address, None);
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::app::QueuedMessage;
+use crate::storage::MessageId;
+
+/// Wait before resending the messages the first time
+const FIRST_RETRY_AFTER: Duration = Duration::from_secs(60);
+/// Maximum wait before resending the messages
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// Messages rejected because sending was rate limited by the server, without a captcha to solve
+///
+/// The messages are resent after waiting; the wait doubles while the server keeps rejecting them,
+/// and is reset once a message is sent.
+#[derive(Debug, Default)]
+pub(crate) struct RateLimitQueue {
+    messages: BTreeMap<MessageId, QueuedMessage>,
+    retry_at: Option<Instant>,
+    /// Wait before the last retry
+    wait: Option<Duration>,
+}
+
+impl RateLimitQueue {
+    /// Queues the rejected message, and schedules resending it unless already scheduled
+    pub fn push(&mut self, message_id: MessageId, message: QueuedMessage, now: Instant) {
+        self.messages.insert(message_id, message);
+        if self.retry_at.is_none() {
+            let wait = self
+                .wait
+                .map_or(FIRST_RETRY_AFTER, |wait| (wait * 2).min(MAX_RETRY_AFTER));
+            self.wait = Some(wait);
+            self.retry_at = Some(now + wait);
+        }
+    }
+
+    /// Takes the messages to resend, if resending is due
+    pub fn take_due(&mut self, now: Instant) -> BTreeMap<MessageId, QueuedMessage> {
+        if self.retry_at.is_some_and(|at| at <= now) {
+            self.retry_at = None;
+            std::mem::take(&mut self.messages)
+        } else {
+            Default::default()
+        }
+    }
+
+    /// Takes the message, e.g. for resending it right away
+    pub fn remove(&mut self, message_id: MessageId) -> Option<QueuedMessage> {
+        let message = self.messages.remove(&message_id);
+        if self.messages.is_empty() {
+            self.retry_at = None;
+        }
+        message
+    }
+
+    pub fn contains(&self, message_id: MessageId) -> bool {
+        self.messages.contains_key(&message_id)
+    }
+
+    /// Resets the wait, after a message was sent
+    pub fn sent(&mut self) {
+        self.wait = None;
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// When the messages are resent
+    pub fn retry_at(&self) -> Option<Instant> {
+        self.retry_at
+    }
+}
@@ -0,0 +1,148 @@
+use ratatui::widgets::ListState;
+use uuid::Uuid;
+
+/// Emojis offered in the reaction picker in addition to the reactions on the message
+const DEFAULT_EMOJIS: [&str; 6] = ["❤️", "👍", "👎", "😂", "😮", "😢"];
+
+/// Popup listing the reactions on the selected message, also used for picking a reaction
+#[derive(Default)]
+pub(crate) struct ReactionPicker {
+    pub is_shown: bool,
+    pub state: ListState,
+    pub items: Vec<ReactionItem>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ReactionItem {
+    pub emoji: String,
+    /// Names of the contacts who reacted with the emoji
+    pub senders: Vec<String>,
+    /// Whether we reacted with the emoji
+    pub is_own: bool,
+}
+
+impl ReactionPicker {
+    /// Opens the popup with the reactions of a message
+    ///
+    /// Reactions are listed by the number of senders, followed by the default emojis nobody
+    /// reacted with.
+    pub fn open(
+        &mut self,
+        reactions: &[(Uuid, String)],
+        user_id: Uuid,
+        name: impl Fn(Uuid) -> String,
+    ) {
+        let mut items: Vec<ReactionItem> = Vec::new();
+        for (sender_id, emoji) in reactions {
+            let idx = match items.iter().position(|item| &item.emoji == emoji) {
+                Some(idx) => idx,
+                None => {
+                    items.push(ReactionItem {
+                        emoji: emoji.clone(),
+                        senders: Vec::new(),
+                        is_own: false,
+                    });
+                    items.len() - 1
+                }
+            };
+            let item = &mut items[idx];
+            item.senders.push(name(*sender_id));
+            item.is_own |= *sender_id == user_id;
+        }
+        // stable sort keeps the order of the first reaction for equal counts
+        items.sort_by_key(|item| std::cmp::Reverse(item.senders.len()));
+        for emoji in DEFAULT_EMOJIS {
+            if !items.iter().any(|item| item.emoji == emoji) {
+                items.push(ReactionItem {
+                    emoji: emoji.to_string(),
+                    senders: Vec::new(),
+                    is_own: false,
+                });
+            }
+        }
+
+        self.items = items;
+        self.state = Default::default();
+        self.state.select(Some(0));
+        self.is_shown = true;
+    }
+
+    pub fn close(&mut self) {
+        self.is_shown = false;
+        self.items.clear();
+    }
+
+    pub fn prev(&mut self) {
+        let selected = self
+            .state
+            .selected()
+            .map(|idx| idx.saturating_sub(1))
+            .unwrap_or(0);
+        self.state.select(Some(selected));
+    }
+
+    pub fn next(&mut self) {
+        let selected = self
+            .state
+            .selected()
+            .map(|idx| (idx + 1).min(self.items.len().saturating_sub(1)))
+            .unwrap_or(0);
+        self.state.select(Some(selected));
+    }
+
+    pub fn selected(&self) -> Option<&ReactionItem> {
+        self.items.get(self.state.selected()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_groups_reactions_by_emoji() {
+        let user_id = Uuid::new_v4();
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+        let reactions = [
+            (alice, "🚀".to_string()),
+            (user_id, "👍".to_string()),
+            (bob, "👍".to_string()),
+        ];
+        let name = |id| {
+            if id == alice {
+                "Alice".to_string()
+            } else if id == bob {
+                "Bob".to_string()
+            } else {
+                "me".to_string()
+            }
+        };
+
+        let mut picker = ReactionPicker::default();
+        picker.open(&reactions, user_id, name);
+        assert!(picker.is_shown);
+        assert_eq!(
+            picker.items[..2],
+            [
+                ReactionItem {
+                    emoji: "👍".to_string(),
+                    senders: vec!["me".to_string(), "Bob".to_string()],
+                    is_own: true,
+                },
+                ReactionItem {
+                    emoji: "🚀".to_string(),
+                    senders: vec!["Alice".to_string()],
+                    is_own: false,
+                },
+            ]
+        );
+        // default emojis without the one already reacted with
+        let emojis: Vec<_> = picker.items[2..]
+            .iter()
+            .map(|item| item.emoji.as_str())
+            .collect();
+        assert_eq!(emojis, ["❤️", "👎", "😂", "😮", "😢"]);
+        assert_eq!(picker.selected().unwrap().emoji, "👍");
+    }
+}
@@ -0,0 +1,49 @@
+use ratatui::widgets::ListState;
+
+use crate::storage::{RecentFile, Storage};
+
+/// Popup listing recently sent and downloaded files
+#[derive(Default)]
+pub(crate) struct RecentFiles {
+    pub is_shown: bool,
+    pub state: ListState,
+    pub items: Vec<RecentFile>,
+}
+
+impl RecentFiles {
+    pub fn open(&mut self, storage: &dyn Storage) {
+        self.items = storage.metadata().recent_files.clone();
+        self.state = Default::default();
+        if !self.items.is_empty() {
+            self.state.select(Some(0));
+        }
+        self.is_shown = true;
+    }
+
+    pub fn close(&mut self) {
+        self.is_shown = false;
+        self.items.clear();
+    }
+
+    pub fn prev(&mut self) {
+        let selected = self
+            .state
+            .selected()
+            .map(|idx| idx.saturating_sub(1))
+            .unwrap_or(0);
+        self.state.select(Some(selected));
+    }
+
+    pub fn next(&mut self) {
+        let selected = self
+            .state
+            .selected()
+            .map(|idx| (idx + 1).min(self.items.len().saturating_sub(1)))
+            .unwrap_or(0);
+        self.state.select(Some(selected));
+    }
+
+    pub fn selected(&self) -> Option<&RecentFile> {
+        self.items.get(self.state.selected()?)
+    }
+}
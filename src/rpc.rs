@@ -0,0 +1,555 @@
+//! JSON-RPC 2.0 over a Unix socket, for driving gurk from external tools
+//!
+//! Requests, responses and notifications are JSON objects, one per line. Methods:
+//!
+//! * `listChannels`: channels with `id`, `name`, `is_group` and `unread`
+//! * `sendMessage` with `{"channel": <id>, "text": <text>}`: sends (or queues) the text; instead
+//!   of the id, the channel can be given by its name
+//! * `getMessages` with `{"channel": <id>, "since": <timestamp>, "limit": <n>}`: the newest
+//!   messages arrived after the timestamp in milliseconds, at most `limit` (by default 100)
+//! * `subscribe`: sends the notification `messageReceived` for each received message on this
+//!   connection
+//! * `detach` (daemon only): the daemon stops receiving and releases its data, and notifies
+//!   `detached`; it resumes when this connection is closed. Used by the TUI for taking over.
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::app::App;
+use crate::data::ChannelId;
+use crate::event::Event;
+use crate::hooks::{self, ChannelEvent, MessageEvent};
+
+#[cfg(unix)]
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Number of messages returned by `getMessages` without a `limit`
+const DEFAULT_MESSAGES_LIMIT: usize = 100;
+/// How long the TUI waits for the daemon to hand over, e.g. while it is saving its data
+#[cfg(unix)]
+const DETACH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    /// Missing for notifications, which get no response
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// Error of a method, with a JSON-RPC error code
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn invalid_params(message: impl ToString) -> Self {
+        Self {
+            code: INVALID_PARAMS,
+            message: message.to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SendMessageParams {
+    channel: String,
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct GetMessagesParams {
+    channel: String,
+    #[serde(default)]
+    since: u64,
+    #[serde(default = "default_messages_limit")]
+    limit: usize,
+}
+
+fn default_messages_limit() -> usize {
+    DEFAULT_MESSAGES_LIMIT
+}
+
+/// Listens on the socket and passes the requests to the app as events
+#[cfg(unix)]
+pub(crate) fn serve(
+    path: &std::path::Path,
+    tx: mpsc::UnboundedSender<Event>,
+) -> anyhow::Result<tokio::task::JoinHandle<()>> {
+    use std::os::unix::fs::FileTypeExt;
+
+    use anyhow::Context as _;
+    use tracing::warn;
+
+    // a socket left behind by a previous run, but never another file at the configured path
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)
+            .with_context(|| format!("failed to remove the old socket {}", path.display()))?,
+        Ok(_) => anyhow::bail!("{} exists and is not a socket", path.display()),
+        Err(_) => {}
+    }
+    // only the user may connect, since the socket allows sending messages on their behalf
+    // SAFETY: umask only swaps the file mode creation mask of the process
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = tokio::net::UnixListener::bind(path);
+    // SAFETY: see above
+    unsafe { libc::umask(umask) };
+    let listener = listener.with_context(|| format!("failed to bind {}", path.display()))?;
+    Ok(tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle_connection(stream, tx.clone()));
+                }
+                Err(error) => {
+                    warn!(%error, "failed to accept a connection to the RPC socket");
+                    break;
+                }
+            }
+        }
+    }))
+}
+
+/// Reads the requests of the connection and writes the responses and notifications
+///
+/// The connection stays open for writing notifications after the client finished sending.
+#[cfg(unix)]
+async fn handle_connection(stream: tokio::net::UnixStream, tx: mpsc::UnboundedSender<Event>) {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let (reader, mut writer) = stream.into_split();
+    let (reply, mut replies) = mpsc::unbounded_channel::<String>();
+    let writer_task = tokio::spawn(async move {
+        while let Some(mut line) = replies.recv().await {
+            line.push('\n');
+            if writer.write_all(line.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(request) => {
+                let event = Event::RpcRequest {
+                    request,
+                    reply: reply.clone(),
+                };
+                if tx.send(event).is_err() {
+                    break; // app quit
+                }
+            }
+            Err(error) => {
+                let error = RpcError {
+                    code: PARSE_ERROR,
+                    message: error.to_string(),
+                };
+                let _ = reply.send(response(Value::Null, Err(error)));
+            }
+        }
+    }
+    drop(reply);
+    let _ = writer_task.await;
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> String {
+    let response = match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(RpcError { code, message }) => {
+            json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+        }
+    };
+    response.to_string()
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(RpcError::invalid_params)
+}
+
+impl App {
+    /// Calls the method of the request and sends the response to the connection
+    pub(crate) fn on_rpc_request(
+        &mut self,
+        request: Request,
+        reply: mpsc::UnboundedSender<String>,
+    ) {
+        let result = self.call_rpc_method(&request.method, request.params, &reply);
+        if let Some(id) = request.id {
+            // the client might have disconnected in the meantime
+            let _ = reply.send(response(id, result));
+        }
+    }
+
+    fn call_rpc_method(
+        &mut self,
+        method: &str,
+        params: Value,
+        reply: &mpsc::UnboundedSender<String>,
+    ) -> Result<Value, RpcError> {
+        match method {
+            "listChannels" => Ok(json!(self.channel_summaries())),
+            "sendMessage" => {
+                let params: SendMessageParams = parse_params(params)?;
+                let channel_id = self.rpc_channel_id(&params.channel)?;
+                self.send_or_queue(channel_id, params.text, None, None);
+                Ok(Value::Null)
+            }
+            "getMessages" => {
+                let params: GetMessagesParams = parse_params(params)?;
+                let channel_id = self.rpc_channel_id(&params.channel)?;
+                let channel = self.storage.channel(channel_id).expect("checked channel");
+                let messages: Vec<_> = self
+                    .storage
+                    .messages_before(channel_id, u64::MAX, params.limit)
+                    .into_iter()
+                    .filter(|message| message.arrived_at > params.since)
+                    .map(|message| {
+                        json!(MessageEvent {
+                            channel: ChannelEvent::new(channel_id, &channel.name),
+                            from: &self.name_by_id_cached(message.from_id),
+                            text: message.message.as_deref().unwrap_or_default(),
+                            timestamp: message.arrived_at,
+                            attachments: hooks::attachment_names(&message.attachments),
+                        })
+                    })
+                    .collect();
+                Ok(Value::Array(messages))
+            }
+            "subscribe" => {
+                self.rpc_subscribers.push(reply.clone());
+                Ok(Value::Bool(true))
+            }
+            "detach" if self.is_headless => {
+                self.detach_lease = Some(reply.clone());
+                Ok(Value::Bool(true))
+            }
+            _ => Err(RpcError {
+                code: METHOD_NOT_FOUND,
+                message: format!("unknown method {method}"),
+            }),
+        }
+    }
+
+    /// Calls the method without a connection, e.g. for the command line
+    pub fn call_rpc(&mut self, method: &str, params: Value) -> anyhow::Result<Value> {
+        let (reply, _) = mpsc::unbounded_channel();
+        self.call_rpc_method(method, params, &reply)
+            .map_err(|error| anyhow::anyhow!(error.message))
+    }
+
+    fn rpc_channel_id(&self, channel: &str) -> Result<ChannelId, RpcError> {
+        self.find_channel(channel).map_err(RpcError::invalid_params)
+    }
+}
+
+/// Sends the received message to the connections subscribed to messages
+pub(crate) fn notify_subscribers(
+    subscribers: &mut Vec<mpsc::UnboundedSender<String>>,
+    message: &MessageEvent,
+) {
+    if subscribers.is_empty() {
+        return;
+    }
+    let notification =
+        json!({"jsonrpc": "2.0", "method": "messageReceived", "params": message}).to_string();
+    // drops the connections which were closed
+    subscribers.retain(|reply| reply.send(notification.clone()).is_ok());
+}
+
+/// Asks the daemon listening on the socket to hand over the connection to Signal and its data
+///
+/// Returns `None` when no daemon is running. The daemon resumes when the returned task is aborted,
+/// or gurk exits. Fails if the daemon does not hand over within [`DETACH_TIMEOUT`].
+#[cfg(unix)]
+pub async fn detach_daemon(path: &std::path::Path) -> anyhow::Result<Option<JoinHandle<()>>> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let Ok(stream) = tokio::net::UnixStream::connect(path).await else {
+        // no daemon, or a socket left behind
+        return Ok(None);
+    };
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let handshake = async {
+        let mut request = json!({"jsonrpc": "2.0", "id": 1, "method": "detach"}).to_string();
+        request.push('\n');
+        writer.write_all(request.as_bytes()).await?;
+        while let Some(line) = lines.next_line().await? {
+            let message: Value = serde_json::from_str(&line)?;
+            if let Some(error) = message.get("error") {
+                anyhow::bail!(
+                    "{} is not served by a gurk daemon: {}",
+                    path.display(),
+                    error["message"]
+                );
+            }
+            if message["method"] == "detached" {
+                return Ok(());
+            }
+        }
+        anyhow::bail!("the daemon closed the connection while handing over")
+    };
+    tokio::time::timeout(DETACH_TIMEOUT, handshake)
+        .await
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "the daemon at {} did not hand over within {}s",
+                path.display(),
+                DETACH_TIMEOUT.as_secs()
+            )
+        })??;
+    // the connection is kept open until the task is aborted
+    Ok(Some(tokio::spawn(async move {
+        let _writer = writer;
+        while let Ok(Some(_)) = lines.next_line().await {}
+    })))
+}
+
+/// Calls the method of the gurk listening on the socket, and returns the result
+///
+/// Returns `None` when gurk is not running.
+#[cfg(unix)]
+pub async fn call(
+    path: &std::path::Path,
+    method: &str,
+    params: Value,
+) -> anyhow::Result<Option<Value>> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let Ok(stream) = tokio::net::UnixStream::connect(path).await else {
+        return Ok(None);
+    };
+    let (reader, mut writer) = stream.into_split();
+    let mut request =
+        json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params}).to_string();
+    request.push('\n');
+    writer.write_all(request.as_bytes()).await?;
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        let mut message: Value = serde_json::from_str(&line)?;
+        if message["id"] != 1 {
+            continue; // a notification
+        }
+        if let Some(error) = message.get("error") {
+            anyhow::bail!("{}", error["message"].as_str().unwrap_or("unknown error"));
+        }
+        return Ok(Some(message["result"].take()));
+    }
+    anyhow::bail!("gurk closed the connection without responding")
+}
+
+/// There is no socket on other platforms
+#[cfg(not(unix))]
+pub async fn call(
+    _path: &std::path::Path,
+    _method: &str,
+    _params: Value,
+) -> anyhow::Result<Option<Value>> {
+    Ok(None)
+}
+
+/// There is no socket on other platforms
+#[cfg(not(unix))]
+pub async fn detach_daemon(_path: &std::path::Path) -> anyhow::Result<Option<JoinHandle<()>>> {
+    Ok(None)
+}
+
+/// Notifies the TUI that the daemon handed over, and waits until its connection is closed
+///
+/// The connection is pinged, since a closed connection is only noticed when writing to it.
+pub async fn hand_over(lease: mpsc::UnboundedSender<String>) {
+    let detached = json!({"jsonrpc": "2.0", "method": "detached"}).to_string();
+    let ping = json!({"jsonrpc": "2.0", "method": "ping"}).to_string();
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+    let mut notification = detached;
+    while lease.send(notification).is_ok() {
+        interval.tick().await;
+        notification = ping.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::tests::test_app;
+    use crate::util;
+
+    use super::*;
+
+    fn call(app: &mut App, request: Value) -> (Value, mpsc::UnboundedReceiver<String>) {
+        let (reply, mut replies) = mpsc::unbounded_channel();
+        let request = serde_json::from_value(request).unwrap();
+        app.on_rpc_request(request, reply);
+        let response = serde_json::from_str(&replies.try_recv().unwrap()).unwrap();
+        (response, replies)
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_serve_socket() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (tx, _events) = mpsc::unbounded_channel();
+
+        let file = dir.path().join("notes.txt");
+        std::fs::write(&file, "notes").unwrap();
+        assert!(serve(&file, tx.clone()).is_err());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "notes");
+
+        let path = dir.path().join("gurk.sock");
+        serve(&path, tx.clone()).unwrap().abort();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // the socket left behind is replaced
+        serve(&path, tx).unwrap().abort();
+    }
+
+    #[tokio::test]
+    async fn test_methods() {
+        let (mut app, _events, sent_messages) = test_app();
+        let channel_id = util::channel_id_to_string(app.channels.items[0]);
+
+        let (response, _) = call(
+            &mut app,
+            json!({"jsonrpc": "2.0", "id": 1, "method": "listChannels"}),
+        );
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"][0]["id"], channel_id);
+
+        let (response, _) = call(
+            &mut app,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "sendMessage",
+                "params": {"channel": channel_id, "text": "Hello from a bot"},
+            }),
+        );
+        assert_eq!(response["result"], Value::Null);
+        assert_eq!(sent_messages.borrow().len(), 1);
+
+        let (response, _) = call(
+            &mut app,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 3,
+                "method": "getMessages",
+                "params": {"channel": channel_id},
+            }),
+        );
+        let messages = response["result"].as_array().unwrap();
+        assert_eq!(messages.last().unwrap()["text"], "Hello from a bot");
+        let since = messages.last().unwrap()["timestamp"].clone();
+
+        let (response, _) = call(
+            &mut app,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 4,
+                "method": "getMessages",
+                "params": {"channel": channel_id, "since": since},
+            }),
+        );
+        assert_eq!(response["result"], json!([]));
+
+        let (response, _) = call(
+            &mut app,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 5,
+                "method": "getMessages",
+                "params": {"channel": channel_id, "limit": 1},
+            }),
+        );
+        let messages = response["result"].as_array().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["text"], "Hello from a bot");
+
+        let (response, _) = call(
+            &mut app,
+            json!({"jsonrpc": "2.0", "id": 6, "method": "sendMessage", "params": {}}),
+        );
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+
+        let (response, _) = call(
+            &mut app,
+            json!({"jsonrpc": "2.0", "id": 7, "method": "nope"}),
+        );
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let (response, mut notifications) = call(
+            &mut app,
+            json!({"jsonrpc": "2.0", "id": 1, "method": "subscribe"}),
+        );
+        assert_eq!(response["result"], true);
+
+        let channel_id = app.channels.items[0];
+        notify_subscribers(
+            &mut app.rpc_subscribers,
+            &MessageEvent {
+                channel: ChannelEvent::new(channel_id, "Tyler"),
+                from: "Tyler",
+                text: "hi",
+                timestamp: 42,
+                attachments: Vec::new(),
+            },
+        );
+        let notification: Value = serde_json::from_str(&notifications.try_recv().unwrap()).unwrap();
+        assert_eq!(notification["method"], "messageReceived");
+        assert_eq!(notification["params"]["text"], "hi");
+
+        drop(notifications);
+        notify_subscribers(
+            &mut app.rpc_subscribers,
+            &MessageEvent {
+                channel: ChannelEvent::new(channel_id, "Tyler"),
+                from: "Tyler",
+                text: "bye",
+                timestamp: 43,
+                attachments: Vec::new(),
+            },
+        );
+        assert!(app.rpc_subscribers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_detach() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let (response, _) = call(
+            &mut app,
+            json!({"jsonrpc": "2.0", "id": 1, "method": "detach"}),
+        );
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+        assert!(app.take_detach_lease().is_none());
+
+        app.set_headless();
+        let (response, mut replies) = call(
+            &mut app,
+            json!({"jsonrpc": "2.0", "id": 2, "method": "detach"}),
+        );
+        assert_eq!(response["result"], true);
+        let lease = app.take_detach_lease().unwrap();
+
+        let hand_over = tokio::spawn(hand_over(lease));
+        let notification: Value = serde_json::from_str(&replies.recv().await.unwrap()).unwrap();
+        assert_eq!(notification["method"], "detached");
+        drop(replies);
+        hand_over.await.unwrap();
+    }
+}
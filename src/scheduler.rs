@@ -0,0 +1,197 @@
+//! Background jobs refreshing the data which changes on the server, e.g. the names of contacts
+//! and groups
+//!
+//! A single scheduler task tells the app when a job is due; the app runs it on the next tick.
+//! While offline, the scheduler is paused, and the jobs which became due run after reconnecting.
+
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use strum::IntoEnumIterator;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::debug;
+
+use crate::app::App;
+use crate::backoff::jitter;
+use crate::event::Event;
+
+/// Delay of the first runs after the start, so that they do not slow it down
+const FIRST_RUN_AFTER: Duration = Duration::from_secs(60);
+/// Maximum delay added to the period of a job, so that the jobs do not run all at once
+const MAX_JITTER: f64 = 0.1;
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    strum_macros::EnumIter,
+    strum_macros::Display,
+)]
+#[strum(serialize_all = "snake_case")]
+pub enum Job {
+    /// Resolves the names of the contacts again, from their profiles and the contacts
+    Profiles,
+    /// Updates the names and the members of the groups
+    Groups,
+    /// Forgets the attachments which are no longer kept by Signal and were not downloaded
+    ExpiredAttachments,
+}
+
+impl Job {
+    pub fn period(self) -> Duration {
+        const HOUR: Duration = Duration::from_secs(60 * 60);
+        match self {
+            Self::Profiles => 6 * HOUR,
+            Self::Groups => HOUR,
+            Self::ExpiredAttachments => 24 * HOUR,
+        }
+    }
+
+    /// Delay until the next run, with a jitter of up to 10% of the period
+    fn next_run_after(self) -> Duration {
+        self.period().mul_f64(1.0 + MAX_JITTER * jitter())
+    }
+}
+
+/// Schedules the jobs, and keeps the due ones until the app runs them
+#[derive(Debug)]
+pub struct Scheduler {
+    is_paused: watch::Sender<bool>,
+    due: BTreeSet<Job>,
+}
+
+impl Default for Scheduler {
+    /// Paused until connected
+    fn default() -> Self {
+        Self {
+            is_paused: watch::Sender::new(true),
+            due: Default::default(),
+        }
+    }
+}
+
+impl Scheduler {
+    /// Spawns the task sending [`Event::JobDue`] when a job is due
+    pub fn start(&self, tx: mpsc::UnboundedSender<Event>) -> JoinHandle<()> {
+        tokio::spawn(run(tx, self.is_paused.subscribe()))
+    }
+
+    pub fn set_paused(&self, is_paused: bool) {
+        self.is_paused.send_replace(is_paused);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.is_paused.borrow()
+    }
+
+    pub(crate) fn set_due(&mut self, job: Job) {
+        self.due.insert(job);
+    }
+
+    /// Takes the next due job, unless paused
+    pub(crate) fn take_due(&mut self) -> Option<Job> {
+        if self.is_paused() {
+            return None;
+        }
+        self.due.pop_first()
+    }
+}
+
+async fn run(tx: mpsc::UnboundedSender<Event>, mut is_paused: watch::Receiver<bool>) {
+    let mut next_runs: Vec<(Job, Instant)> = Job::iter()
+        .map(|job| {
+            (
+                job,
+                Instant::now() + FIRST_RUN_AFTER.mul_f64(1.0 + jitter()),
+            )
+        })
+        .collect();
+    loop {
+        let Some((job, at)) = next_runs.iter_mut().min_by_key(|(_, at)| *at) else {
+            return;
+        };
+        tokio::time::sleep_until(*at).await;
+        // jobs which became due while offline run after reconnecting
+        if is_paused.wait_for(|is_paused| !is_paused).await.is_err() {
+            return; // app quit
+        }
+        debug!(%job, "job is due");
+        if tx.send(Event::JobDue(*job)).is_err() {
+            return; // app quit
+        }
+        *at = Instant::now() + job.next_run_after();
+    }
+}
+
+impl App {
+    /// Starts the scheduler of the background jobs
+    pub fn start_scheduler(&self) -> Option<JoinHandle<()>> {
+        Some(self.scheduler.start(self.event_tx.clone()))
+    }
+
+    /// Runs the next due job
+    pub async fn step_jobs(&mut self) {
+        let Some(job) = self.scheduler.take_due() else {
+            return;
+        };
+        debug!(%job, "running job");
+        match job {
+            Job::Profiles => self.populate_names_cache().await,
+            Job::Groups => self.refresh_groups().await,
+            Job::ExpiredAttachments => self.forget_expired_attachments(),
+        }
+        self.dirty.mark_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::tests::test_app;
+    use crate::app::ConnectionState;
+
+    use super::*;
+
+    #[test]
+    fn test_next_run_after() {
+        for job in Job::iter() {
+            let after = job.next_run_after();
+            assert!(job.period() <= after);
+            assert!(after <= job.period().mul_f64(1.0 + MAX_JITTER));
+        }
+    }
+
+    #[test]
+    fn test_take_due_unless_paused() {
+        let mut scheduler = Scheduler::default();
+        scheduler.set_due(Job::Groups);
+        scheduler.set_due(Job::Profiles);
+        scheduler.set_due(Job::Groups);
+        assert_eq!(scheduler.take_due(), None);
+
+        scheduler.set_paused(false);
+        assert_eq!(scheduler.take_due(), Some(Job::Profiles));
+        assert_eq!(scheduler.take_due(), Some(Job::Groups));
+        assert_eq!(scheduler.take_due(), None);
+    }
+
+    #[tokio::test]
+    async fn test_step_jobs_refreshes_groups_when_connected() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let channel_id = app.channels.items[0];
+        app.handle_event(Event::JobDue(Job::Groups)).unwrap();
+        app.step_jobs().await;
+        assert_eq!(app.storage.channel(channel_id).unwrap().name, "test");
+
+        app.set_connection_state(ConnectionState::Connected);
+        app.step_jobs().await;
+        let channel = app.storage.channel(channel_id).unwrap();
+        assert_eq!(channel.name, "some_group");
+        assert_eq!(channel.group_data.as_ref().unwrap().revision, 0);
+    }
+}
@@ -0,0 +1,457 @@
+//! First-run setup wizard asking for the settings, and the screen for linking this device
+
+use std::io::Stdout;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context as _};
+use arboard::Clipboard;
+use crossterm::event::{
+    Event as CEvent, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers,
+};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use presage::manager::Registered;
+use presage_store_sled::SledStore;
+use qrcode::QrCode;
+use ratatui::backend::CrosstermBackend;
+use ratatui::Terminal;
+use tokio::select;
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+
+use crate::config::{self, ClockFormat, Config, User};
+use crate::input::Input;
+use crate::signal::{self, PresageManager, SignalManager};
+use crate::ui;
+use crate::util::expand_home;
+
+/// Number of the preferences on the preferences step
+const PREFERENCES_LEN: usize = 2;
+/// Width of the light border around the QR code in modules, which scanners need
+const QR_QUIET_ZONE: isize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SetupStep {
+    Name,
+    DataDir,
+    Preferences,
+    /// Showing the QR code for linking this device
+    Linking,
+    /// Linked, waiting for the confirmation
+    Linked,
+    Done,
+    Aborted,
+}
+
+pub(crate) struct SetupWizard {
+    pub step: SetupStep,
+    /// Name of the user; if empty, the name of the Signal profile is used
+    pub name: Input,
+    /// Directory of the messages and the Signal database
+    pub data_dir: Input,
+    pub notifications: bool,
+    pub clock: ClockFormat,
+    /// Selected preference on the preferences step
+    pub selected: usize,
+    /// Error of the current step
+    pub error: Option<String>,
+    /// Linked user, shown for confirmation
+    pub user: Option<User>,
+    /// Name of this device in the list of linked devices
+    pub device_name: String,
+    /// Provisioning url for linking this device
+    pub link_url: Option<String>,
+    /// QR code of the provisioning url as lines of text
+    pub link_qr: Option<Vec<String>>,
+    /// Result of copying the provisioning url to the clipboard
+    pub link_notice: Option<String>,
+}
+
+impl SetupWizard {
+    fn new() -> Self {
+        let mut data_dir = Input::default();
+        config::default_data_dir()
+            .display()
+            .to_string()
+            .chars()
+            .for_each(|c| data_dir.put_char(c));
+        Self {
+            step: SetupStep::Name,
+            name: Input::default(),
+            data_dir,
+            notifications: true,
+            clock: ClockFormat::H24,
+            selected: 0,
+            error: None,
+            user: None,
+            device_name: signal::device_name(),
+            link_url: None,
+            link_qr: None,
+            link_notice: None,
+        }
+    }
+
+    /// Labels of the preferences and whether they are enabled
+    pub fn preferences(&self) -> [(&'static str, bool); PREFERENCES_LEN] {
+        [
+            ("Show notifications", self.notifications),
+            ("24-hour clock", self.clock == ClockFormat::H24),
+        ]
+    }
+
+    fn on_key(&mut self, key: KeyEvent) {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            self.step = SetupStep::Aborted;
+            return;
+        }
+        match key.code {
+            KeyCode::Enter => return self.next(),
+            KeyCode::Esc => return self.back(),
+            _ => {}
+        }
+        match self.step {
+            SetupStep::Name | SetupStep::DataDir => {
+                let input = if self.step == SetupStep::Name {
+                    &mut self.name
+                } else {
+                    &mut self.data_dir
+                };
+                match key.code {
+                    KeyCode::Char(c) => input.put_char(c),
+                    KeyCode::Backspace => input.on_backspace(),
+                    KeyCode::Left => input.on_left(),
+                    KeyCode::Right => input.on_right(),
+                    KeyCode::Home => input.on_home(),
+                    KeyCode::End => input.on_end(),
+                    _ => {}
+                }
+            }
+            SetupStep::Preferences => match key.code {
+                KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+                KeyCode::Down => self.selected = (self.selected + 1).min(PREFERENCES_LEN - 1),
+                KeyCode::Char(' ') => self.toggle_selected(),
+                _ => {}
+            },
+            SetupStep::Linking | SetupStep::Linked | SetupStep::Done | SetupStep::Aborted => {}
+        }
+    }
+
+    fn toggle_selected(&mut self) {
+        match self.selected {
+            0 => self.notifications = !self.notifications,
+            _ => {
+                self.clock = match self.clock {
+                    ClockFormat::H24 => ClockFormat::H12,
+                    ClockFormat::H12 => ClockFormat::H24,
+                }
+            }
+        }
+    }
+
+    fn next(&mut self) {
+        self.error = None;
+        self.step = match self.step {
+            SetupStep::Name => SetupStep::DataDir,
+            SetupStep::DataDir if self.data_dir.data.trim().is_empty() => {
+                self.error = Some("the data directory is required".to_string());
+                SetupStep::DataDir
+            }
+            SetupStep::DataDir => SetupStep::Preferences,
+            SetupStep::Preferences => SetupStep::Linking,
+            SetupStep::Linked => SetupStep::Done,
+            step => step,
+        };
+    }
+
+    fn back(&mut self) {
+        self.error = None;
+        self.step = match self.step {
+            SetupStep::Name => SetupStep::Aborted,
+            SetupStep::DataDir => SetupStep::Name,
+            SetupStep::Preferences => SetupStep::DataDir,
+            step => step,
+        };
+    }
+
+    fn copy_link_url(&mut self) {
+        let Some(url) = self.link_url.clone() else {
+            return;
+        };
+        let notice = match Clipboard::new().and_then(|mut clipboard| clipboard.set_text(url)) {
+            Ok(()) => "Copied the link to the clipboard.".to_string(),
+            Err(error) => format!("Failed to copy the link: {error}"),
+        };
+        self.link_notice = Some(notice);
+    }
+
+    /// Config with the chosen settings; the phone number is only known after linking
+    fn config(&self) -> anyhow::Result<Config> {
+        let user = User {
+            name: self.name.data.trim().to_string(),
+            phone_number: String::new(),
+        };
+        let mut config = Config::with_user(user);
+        config.set_data_dir(&expand_home(self.data_dir.data.trim()))?;
+        config.notifications = self.notifications;
+        config.clock = self.clock;
+        Ok(config)
+    }
+}
+
+/// Runs the setup wizard, links this device and saves the new config
+pub(crate) async fn run() -> anyhow::Result<(Box<dyn SignalManager>, Config)> {
+    let mut wizard = SetupWizard::new();
+    let mut events = EventStream::new();
+
+    let mut terminal = enter_screen()?;
+    let result = async {
+        run_until(&mut terminal, &mut events, &mut wizard, SetupStep::Linking).await?;
+        if wizard.step == SetupStep::Aborted {
+            bail!("setup aborted");
+        }
+
+        let mut config = wizard.config()?;
+        let (manager, user) = link(&mut terminal, &mut events, &mut wizard, &config).await?;
+        if config.user.name.is_empty() {
+            config.user.name = user.name;
+        }
+        config.user.phone_number = user.phone_number;
+        config.save_new().context("failed to init config file")?;
+
+        wizard.user = Some(config.user.clone());
+        wizard.step = SetupStep::Linked;
+        run_until(&mut terminal, &mut events, &mut wizard, SetupStep::Done).await?;
+        Ok::<_, anyhow::Error>((manager, config))
+    }
+    .await;
+    leave_screen()?;
+    let (manager, config) = result?;
+
+    Ok((
+        Box::new(PresageManager::new(manager, config.data_dir.clone())),
+        config,
+    ))
+}
+
+/// Links this device again with the settings of the config
+pub(crate) async fn relink(
+    config: &Config,
+) -> anyhow::Result<(presage::Manager<SledStore, Registered>, User)> {
+    let mut wizard = SetupWizard::new();
+    let mut events = EventStream::new();
+
+    let mut terminal = enter_screen()?;
+    let result = link(&mut terminal, &mut events, &mut wizard, config).await;
+    leave_screen()?;
+    result
+}
+
+/// Handles the keys until the wizard reaches the step or is aborted
+async fn run_until(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    events: &mut EventStream,
+    wizard: &mut SetupWizard,
+    step: SetupStep,
+) -> anyhow::Result<()> {
+    while wizard.step != step && wizard.step != SetupStep::Aborted {
+        terminal.draw(|f| ui::draw_setup(f, wizard))?;
+        match events.next().await {
+            Some(Ok(CEvent::Key(key))) if key.kind == KeyEventKind::Press => wizard.on_key(key),
+            Some(Ok(_)) => {}
+            Some(Err(error)) => return Err(error.into()),
+            None => bail!("terminal closed"),
+        }
+    }
+    Ok(())
+}
+
+/// Links this device, showing the QR code of the provisioning url and the progress
+///
+/// If linking fails, the error is shown and linking is retried on `Enter`.
+async fn link(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    events: &mut EventStream,
+    wizard: &mut SetupWizard,
+    config: &Config,
+) -> anyhow::Result<(presage::Manager<SledStore, Registered>, User)> {
+    wizard.step = SetupStep::Linking;
+    loop {
+        wizard.error = None;
+        wizard.link_url = None;
+        wizard.link_qr = None;
+        wizard.link_notice = None;
+
+        let store = signal::open_store(config).await?;
+        let (url_tx, mut url_rx) = mpsc::unbounded_channel();
+        let linking = signal::link_device(store, wizard.device_name.clone(), move |url| {
+            // the url is only shown, so it does not matter when the screen is gone
+            url_tx.send(url).ok();
+            Ok(())
+        });
+        tokio::pin!(linking);
+
+        let result = loop {
+            terminal.draw(|f| ui::draw_setup(f, wizard))?;
+            select! {
+                result = &mut linking => break result,
+                Some(url) = url_rx.recv() => {
+                    wizard.link_qr = qr_lines(&url);
+                    wizard.link_url = Some(url);
+                }
+                event = events.next() => match event {
+                    Some(Ok(CEvent::Key(key))) if key.kind == KeyEventKind::Press => {
+                        if is_abort(key) {
+                            bail!("linking aborted");
+                        } else if key.code == KeyCode::Char('c') {
+                            wizard.copy_link_url();
+                        }
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(error)) => return Err(error.into()),
+                    None => bail!("terminal closed"),
+                },
+            }
+        };
+        let error = match result {
+            Ok(linked) => return Ok(linked),
+            Err(error) => error,
+        };
+
+        wizard.error = Some(format!("{error:#}"));
+        loop {
+            terminal.draw(|f| ui::draw_setup(f, wizard))?;
+            match events.next().await {
+                Some(Ok(CEvent::Key(key))) if key.kind == KeyEventKind::Press => {
+                    if is_abort(key) {
+                        return Err(error);
+                    } else if key.code == KeyCode::Enter {
+                        break;
+                    }
+                }
+                Some(Ok(_)) => {}
+                Some(Err(error)) => return Err(error.into()),
+                None => bail!("terminal closed"),
+            }
+        }
+    }
+}
+
+fn is_abort(key: KeyEvent) -> bool {
+    key.code == KeyCode::Esc
+        || key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)
+}
+
+/// Renders the QR code with unicode half blocks, two rows of modules per line
+///
+/// The light modules are drawn, so the lines have to be shown in a light color on a dark
+/// background.
+fn qr_lines(data: &str) -> Option<Vec<String>> {
+    let code = QrCode::new(data).ok()?;
+    let width = code.width() as isize;
+    let colors = code.to_colors();
+    let is_light = |x: isize, y: isize| {
+        let is_quiet_zone = x < 0 || y < 0 || x >= width || y >= width;
+        is_quiet_zone || colors[(y * width + x) as usize] == qrcode::Color::Light
+    };
+    let lines = (-QR_QUIET_ZONE..width + QR_QUIET_ZONE)
+        .step_by(2)
+        .map(|y| {
+            (-QR_QUIET_ZONE..width + QR_QUIET_ZONE)
+                .map(|x| match (is_light(x, y), is_light(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                })
+                .collect()
+        })
+        .collect();
+    Some(lines)
+}
+
+fn enter_screen() -> anyhow::Result<Terminal<CrosstermBackend<Stdout>>> {
+    enable_raw_mode()?;
+    let mut stdout = std::io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+    terminal.clear()?;
+    Ok(terminal)
+}
+
+fn leave_screen() -> anyhow::Result<()> {
+    execute!(std::io::stdout(), LeaveAlternateScreen)?;
+    disable_raw_mode()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(wizard: &mut SetupWizard, code: KeyCode) {
+        wizard.on_key(KeyEvent::new(code, KeyModifiers::NONE));
+    }
+
+    fn type_text(wizard: &mut SetupWizard, text: &str) {
+        text.chars().for_each(|c| press(wizard, KeyCode::Char(c)));
+    }
+
+    #[test]
+    fn test_wizard_steps() {
+        let mut wizard = SetupWizard::new();
+        type_text(&mut wizard, "Tyler");
+        press(&mut wizard, KeyCode::Enter);
+        assert_eq!(wizard.step, SetupStep::DataDir);
+
+        wizard.data_dir.take();
+        press(&mut wizard, KeyCode::Enter);
+        assert_eq!(wizard.step, SetupStep::DataDir);
+        assert!(wizard.error.is_some());
+        type_text(&mut wizard, "/tmp/gurk");
+        press(&mut wizard, KeyCode::Enter);
+        assert_eq!(wizard.step, SetupStep::Preferences);
+        assert_eq!(wizard.error, None);
+
+        press(&mut wizard, KeyCode::Down);
+        press(&mut wizard, KeyCode::Char(' '));
+        press(&mut wizard, KeyCode::Enter);
+        assert_eq!(wizard.step, SetupStep::Linking);
+
+        let config = wizard.config().unwrap();
+        assert_eq!(config.user.name, "Tyler");
+        assert!(config.notifications);
+        assert_eq!(config.clock, ClockFormat::H12);
+        assert_eq!(config.signal_db_path, PathBuf::from("/tmp/gurk/signal-db"));
+        assert_eq!(config.sqlite.url.as_str(), "sqlite:///tmp/gurk/gurk.sqlite");
+    }
+
+    #[test]
+    fn test_qr_lines() {
+        let code = QrCode::new("sgnl://linkdevice?uuid=abc").unwrap();
+        let size = code.width() + 2 * QR_QUIET_ZONE as usize;
+        let lines = qr_lines("sgnl://linkdevice?uuid=abc").unwrap();
+        assert_eq!(lines.len(), size.div_ceil(2));
+        assert!(lines.iter().all(|line| line.chars().count() == size));
+        // the quiet zone and the top left finder pattern
+        assert_eq!(lines[0].chars().next(), Some('█'));
+        let second_line: Vec<_> = lines[1].chars().collect();
+        let x = QR_QUIET_ZONE as usize;
+        assert_eq!(second_line[x - 1..x + 2], ['█', ' ', '▄']);
+    }
+
+    #[test]
+    fn test_wizard_back_and_abort() {
+        let mut wizard = SetupWizard::new();
+        press(&mut wizard, KeyCode::Enter);
+        press(&mut wizard, KeyCode::Esc);
+        assert_eq!(wizard.step, SetupStep::Name);
+        press(&mut wizard, KeyCode::Esc);
+        assert_eq!(wizard.step, SetupStep::Aborted);
+
+        let mut wizard = SetupWizard::new();
+        wizard.on_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert_eq!(wizard.step, SetupStep::Aborted);
+    }
+}
@@ -0,0 +1,152 @@
+//! Signal backend from `gurk-core` and linking of this device
+
+use anyhow::{bail, Context as _};
+use presage::manager::Registered;
+use presage::{libsignal_service::configuration::SignalServers, model::identity::OnNewIdentity};
+use presage_store_sled::{MigrationConflictStrategy, SledStore};
+
+use crate::config::{Config, User};
+use crate::setup;
+
+pub use gurk_core::signal::*;
+
+/// Makes sure that we have a linked device.
+///
+/// Either,
+///
+/// 1. runs the setup wizard (if no config file found), which links a new device and writes a new
+///    config file with the chosen settings, username and phone number, or
+/// 2. loads the config file and tries to create the Signal manager from configured Signal database
+///    path.
+pub async fn ensure_linked_device(
+    relink: bool,
+) -> anyhow::Result<(Box<dyn SignalManager>, Config)> {
+    let Some(config) = Config::load_installed()? else {
+        return setup::run().await;
+    };
+
+    if !relink {
+        let store = open_store(&config).await?;
+        match presage::Manager::load_registered(store).await {
+            Ok(manager) => {
+                // done loading manager from store
+                return Ok((
+                    Box::new(PresageManager::new(manager, config.data_dir.clone())),
+                    config,
+                ));
+            }
+            Err(presage::Error::NotYetRegisteredError) => {}
+            Err(error) => {
+                // Linking requires a connection anyway, so e.g. when offline, better fail
+                // here, than to start linking a new device.
+                return Err(anyhow::Error::from(error)).context(
+                    "failed to load the linked device; \
+                    if it was unlinked, please restart with the '--relink' flag",
+                );
+            }
+        }
+    }
+
+    // faulty manager, or explicit relink
+    // => link device
+    let (manager, user) = setup::relink(&config).await?;
+
+    // check that config fits the profile
+    if config.user.phone_number != user.phone_number {
+        bail!("Wrong phone number in the config. Please adjust it.");
+    }
+
+    Ok((
+        Box::new(PresageManager::new(manager, config.data_dir.clone())),
+        config,
+    ))
+}
+
+/// Loads the linked device without linking a new one, e.g. when running without a terminal
+pub async fn load_linked_device() -> anyhow::Result<(Box<dyn SignalManager>, Config)> {
+    let config = Config::load_installed()?
+        .context("no config found; please run gurk once to link this device")?;
+    let store = open_store(&config).await?;
+    let manager = presage::Manager::load_registered(store)
+        .await
+        .context("failed to load the linked device; please run gurk to link this device")?;
+    Ok((
+        Box::new(PresageManager::new(manager, config.data_dir.clone())),
+        config,
+    ))
+}
+
+/// Opens the Signal database of the config
+pub(crate) async fn open_store(config: &Config) -> anyhow::Result<SledStore> {
+    let store = SledStore::open_with_passphrase(
+        &config.signal_db_path,
+        config.passphrase.as_ref(),
+        MigrationConflictStrategy::BackupAndDrop,
+        OnNewIdentity::Trust,
+    )
+    .await?;
+    Ok(store)
+}
+
+/// Name of this device in the list of linked devices, e.g. `gurk@laptop`
+pub(crate) fn device_name() -> String {
+    let at_hostname = hostname::get()
+        .ok()
+        .and_then(|hostname| {
+            hostname
+                .to_string_lossy()
+                .split('.')
+                .find(|s| !s.is_empty())
+                .map(|s| format!("@{s}"))
+        })
+        .unwrap_or_default();
+    format!("gurk{at_hostname}")
+}
+
+/// Links this device as a new secondary device
+///
+/// The provisioning url is passed to `show_url`, e.g. for showing it as QR code. Returns the
+/// user with the name from the Signal profile.
+pub(crate) async fn link_device(
+    store: SledStore,
+    device_name: String,
+    show_url: impl FnOnce(String) -> anyhow::Result<()>,
+) -> anyhow::Result<(presage::Manager<SledStore, Registered>, User)> {
+    let (tx, rx) = futures_channel::oneshot::channel();
+    let (mut manager, _) = tokio::try_join!(
+        async move {
+            presage::Manager::link_secondary_device(
+                store,
+                SignalServers::Production,
+                device_name,
+                tx,
+            )
+            .await
+            .map_err(anyhow::Error::from)
+        },
+        async move {
+            match rx.await {
+                Ok(url) => show_url(url.to_string()),
+                Err(e) => bail!("error linking device: {}", e),
+            }
+        }
+    )?;
+
+    // get profile
+    let phone_number = manager
+        .registration_data()
+        .phone_number
+        .format()
+        .mode(phonenumber::Mode::E164)
+        .to_string();
+    let profile = manager
+        .retrieve_profile()
+        .await
+        .context("failed to get the user profile")?;
+    let name = profile
+        .name
+        .map(|name| name.given_name)
+        .unwrap_or_else(whoami::username);
+
+    Ok((manager, User { name, phone_number }))
+}
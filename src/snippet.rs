@@ -0,0 +1,57 @@
+//! Messages kept in the config, with placeholders filled in for each channel they are sent to
+
+use chrono::{DateTime, Local};
+
+/// Replaces the placeholders of the snippet
+///
+/// Supported are the `{name}` and `{first_name}` of the contact or group, and the local `{date}`,
+/// `{time}` and `{weekday}` at sending. Other text in braces is kept as is.
+pub(crate) fn fill(snippet: &str, name: &str, now: DateTime<Local>) -> String {
+    let mut filled = String::with_capacity(snippet.len());
+    let mut rest = snippet;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        let value = match &rest[1..end] {
+            "name" => name.to_string(),
+            "first_name" => name.split_whitespace().next().unwrap_or(name).to_string(),
+            "date" => now.format("%Y-%m-%d").to_string(),
+            "time" => now.format("%H:%M").to_string(),
+            "weekday" => now.format("%A").to_string(),
+            _ => {
+                filled.push('{');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        filled.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    filled.push_str(rest);
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_fill() {
+        let now = Local.with_ymd_and_hms(2024, 5, 17, 9, 30, 0).unwrap();
+        assert_eq!(
+            fill("Hi {first_name}, happy {weekday}!", "Tyler Durden", now),
+            "Hi Tyler, happy Friday!"
+        );
+        assert_eq!(
+            fill("{name}: check-in {date} {time}", "Paper Street", now),
+            "Paper Street: check-in 2024-05-17 09:30"
+        );
+        // unknown or unclosed placeholders are kept
+        assert_eq!(fill("{x} {{name}} {name", "Bob", now), "{x} {Bob} {name");
+    }
+}
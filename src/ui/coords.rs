@@ -1,15 +1,112 @@
-use ratatui::layout::Rect;
+use ratatui::layout::{Constraint, Layout, Margin, Position, Rect};
 
-use super::CHANNEL_VIEW_RATIO;
+use crate::app::App;
 
-pub fn coords_within_channels_view(area: Rect, x: u16, y: u16) -> Option<(u16, u16)> {
-    if y < 1 {
-        None
-    }
-    // 1 offset around the view for taking the border into account
-    else if 0 < x && x < area.width / CHANNEL_VIEW_RATIO as u16 && 0 < y && y + 1 < area.height {
-        Some((x - 1, y - 1))
+use super::{CHANNEL_INFO_WIDTH, STACKED_SIDEBAR_HEIGHT, STATUS_BAR_HEIGHT};
+
+/// Areas of the main screen
+pub(crate) struct MainAreas {
+    /// Channel list, unless it is hidden
+    pub(crate) channels: Option<Rect>,
+    pub(crate) chat: Rect,
+    /// Panel with the details of the selected channel right of the chat, if shown
+    pub(crate) channel_info: Option<Rect>,
+    pub(crate) status_bar: Rect,
+}
+
+/// Splits the screen into the channel list, the chat and the status bar
+pub(crate) fn main_areas(app: &App, area: Rect) -> MainAreas {
+    let [main_area, status_bar] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(STATUS_BAR_HEIGHT)]).areas(area);
+    let (channels, chat) = if app.sidebar_hidden {
+        (None, main_area)
+    } else if app.is_stacked_layout(area.width) {
+        let [channels, chat] = Layout::vertical([
+            Constraint::Percentage(STACKED_SIDEBAR_HEIGHT),
+            Constraint::Min(0),
+        ])
+        .areas(main_area);
+        (Some(channels), chat)
+    } else {
+        let sidebar_width = app.config.layout.sidebar_width.min(100);
+        let [channels, chat] =
+            Layout::horizontal([Constraint::Percentage(sidebar_width), Constraint::Min(0)])
+                .areas(main_area);
+        (Some(channels), chat)
+    };
+    // the panel is only shown if the chat stays wider than it
+    let (chat, channel_info) = if app.show_channel_info && CHANNEL_INFO_WIDTH * 2 <= chat.width {
+        let [chat, channel_info] =
+            Layout::horizontal([Constraint::Min(0), Constraint::Length(CHANNEL_INFO_WIDTH)])
+                .areas(chat);
+        (chat, Some(channel_info))
     } else {
-        None
+        (chat, None)
+    };
+    MainAreas {
+        channels,
+        chat,
+        channel_info,
+        status_bar,
+    }
+}
+
+/// Converts the screen coordinates to coordinates within the channel list (without borders)
+pub fn coords_within_channels_view(app: &App, area: Rect, x: u16, y: u16) -> Option<(u16, u16)> {
+    let channels = main_areas(app, area).channels?;
+    let inner = channels.inner(Margin::new(1, 1));
+    inner
+        .contains(Position::new(x, y))
+        .then(|| (x - inner.x, y - inner.y))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::tests::test_app;
+
+    use super::*;
+
+    #[test]
+    fn test_coords_within_channels_view() {
+        let (mut app, _events, _sent_messages) = test_app();
+        let area = Rect::new(0, 0, 100, 40);
+
+        // channel list on the left, 25% wide
+        assert_eq!(coords_within_channels_view(&app, area, 1, 1), Some((0, 0)));
+        assert_eq!(coords_within_channels_view(&app, area, 10, 5), Some((9, 4)));
+        assert_eq!(coords_within_channels_view(&app, area, 30, 5), None);
+
+        // channel list above the chat, 30% high
+        app.stacked_layout = true;
+        assert_eq!(
+            coords_within_channels_view(&app, area, 30, 5),
+            Some((29, 4))
+        );
+        assert_eq!(coords_within_channels_view(&app, area, 30, 20), None);
+
+        app.sidebar_hidden = true;
+        assert_eq!(coords_within_channels_view(&app, area, 1, 1), None);
+    }
+
+    #[test]
+    fn test_channel_info_right_of_chat() {
+        let (mut app, _events, _sent_messages) = test_app();
+        app.show_channel_info = true;
+
+        let areas = main_areas(&app, Rect::new(0, 0, 100, 40));
+        assert_eq!(
+            areas.channel_info,
+            Some(Rect::new(
+                100 - CHANNEL_INFO_WIDTH,
+                0,
+                CHANNEL_INFO_WIDTH,
+                39
+            ))
+        );
+        assert_eq!(areas.chat.right(), 100 - CHANNEL_INFO_WIDTH);
+
+        // chat too narrow
+        let areas = main_areas(&app, Rect::new(0, 0, 60, 40));
+        assert_eq!(areas.channel_info, None);
     }
 }
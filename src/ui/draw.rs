@@ -1,6 +1,7 @@
 //! Draw the UI
 
 use std::fmt;
+use std::time::{Duration, Instant};
 
 use chrono::Datelike;
 use itertools::Itertools;
@@ -18,20 +19,56 @@ use ratatui::{
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 use uuid::Uuid;
 
-use crate::app::App;
+use crate::app::{App, ConnectionState, SendPreview};
+use crate::attachment_gallery::display_size;
+use crate::attachment_picker::AttachmentPicker;
+use crate::captcha_prompt::CaptchaPrompt;
+use crate::channel_info::{display_timer, initials};
 use crate::channels::SelectChannel;
 use crate::command::{Command, WindowMode};
+use crate::config::ClockFormat;
 use crate::cursor::Cursor;
-use crate::data::{AssociatedValue, Message};
+use crate::data::{AssociatedValue, Channel, ChannelId, Message, MENTION_ALL};
+use crate::effects::EffectKind;
+use crate::event::Presence;
+use crate::reaction_picker::ReactionPicker;
 use crate::receipt::{Receipt, ReceiptEvent};
-use crate::storage::MessageId;
-use crate::util::utc_timestamp_msec_to_local;
-
+use crate::recent_files::RecentFiles;
+use crate::setup::{SetupStep, SetupWizard};
+use crate::signal::{LinkedDevice, CAPTCHA_URL};
+use crate::storage::{MemoryUsage, MessageId, RecentFileKind};
+use crate::util::{utc_now_timestamp_msec, utc_timestamp_msec_to_local};
+
+use super::coords::{main_areas, MainAreas};
+use super::layout_cache::LayoutOptions;
 use super::name_resolver::NameResolver;
-use super::CHANNEL_VIEW_RATIO;
+use super::panes::Pane;
+use super::theme::Palette;
 
 /// The main function drawing the UI for each frame
 pub fn draw(f: &mut Frame, app: &mut App) {
+    draw_app(f, app);
+    app.theme.apply(f.buffer_mut());
+    app.terminal.degrade(f.buffer_mut());
+    app.dirty.clear();
+}
+
+/// Draws the pane, unless it did not change since the last frame and is copied from it
+fn draw_pane(
+    f: &mut Frame,
+    app: &mut App,
+    pane: Pane,
+    area: Rect,
+    draw: fn(&mut Frame, &mut App, Rect),
+) {
+    if !app.dirty.is_dirty(pane) && app.pane_buffers.restore(pane, f.buffer_mut(), area) {
+        return;
+    }
+    draw(f, app, area);
+    app.pane_buffers.store(pane, f.buffer_mut(), area);
+}
+
+fn draw_app(f: &mut Frame, app: &mut App) {
     if app.is_help() {
         // Display shortcut panel
         let chunks = Layout::default()
@@ -45,37 +82,77 @@ pub fn draw(f: &mut Frame, app: &mut App) {
         draw_help(f, app, chunks[1]);
         return;
     }
-    let chunks = Layout::default()
-        .constraints(
-            [
-                Constraint::Ratio(1, CHANNEL_VIEW_RATIO),
-                Constraint::Ratio(3, CHANNEL_VIEW_RATIO),
-            ]
-            .as_ref(),
-        )
-        .direction(Direction::Horizontal)
-        .split(f.area());
-
-    draw_channels(f, app, chunks[0]);
-    draw_chat(f, app, chunks[1]);
+    let MainAreas {
+        channels,
+        chat,
+        channel_info,
+        status_bar,
+    } = main_areas(app, f.area());
+
+    if let Some(channels) = channels {
+        draw_pane(f, app, Pane::Channels, channels, draw_channels);
+    }
+    draw_chat(f, app, chat);
+    if let Some(channel_info) = channel_info {
+        draw_channel_info(f, app, channel_info);
+    }
+    draw_status_bar(f, app, status_bar);
 
     if app.select_channel.is_shown {
-        draw_select_channel_popup(f, &mut app.select_channel);
+        draw_select_channel_popup(f, &mut app.select_channel, &app.palette);
+    }
+    if app.attachment_picker.is_shown {
+        draw_attachment_picker_popup(f, &mut app.attachment_picker, &app.palette);
+    }
+    if app.recent_files.is_shown {
+        draw_recent_files_popup(f, &mut app.recent_files, &app.palette);
+    }
+    if app.attachment_gallery.is_shown {
+        draw_attachment_gallery_popup(f, app);
+    }
+    if app.contact_list.is_shown {
+        draw_contact_list_popup(f, app);
     }
+    if app.reaction_picker.is_shown {
+        draw_reaction_picker_popup(f, &mut app.reaction_picker, &app.palette);
+    }
+    if app.captcha_prompt.is_shown {
+        draw_captcha_prompt_popup(f, &app.captcha_prompt, &app.palette);
+    }
+    if app.message_search.is_shown {
+        draw_message_search_popup(f, app);
+    }
+    if app.show_diagnostics {
+        draw_diagnostics_popup(f, app);
+    }
+    if app.error_log.is_shown {
+        draw_error_log_popup(f, app);
+    }
+    if let Some(devices) = &app.linked_devices {
+        draw_devices_popup(f, app, devices);
+    }
+}
+
+/// Clears the area of a popup and fills it with the colors of popups
+fn clear_popup(f: &mut Frame, area: Rect, palette: &Palette) {
+    f.render_widget(Clear, area);
+    f.render_widget(Block::default().style(palette.popups), area);
 }
 
-fn draw_select_channel_popup(f: &mut Frame, select_channel: &mut SelectChannel) {
+fn draw_select_channel_popup(f: &mut Frame, select_channel: &mut SelectChannel, palette: &Palette) {
     let area = centered_rect(60, 60, f.area());
     let chunks = Layout::default()
         .constraints([Constraint::Length(1 + 2), Constraint::Min(0)].as_ref())
         .direction(Direction::Vertical)
         .split(area);
-    f.render_widget(Clear, area);
-    let input = Paragraph::new(Text::from(select_channel.input.data.clone())).block(
-        Block::default()
-            .borders(Borders::ALL)
-            .title("Select channel"),
-    );
+    clear_popup(f, area, palette);
+    let title = if select_channel.forward.is_empty() {
+        "Select channel"
+    } else {
+        "Forward to"
+    };
+    let input = Paragraph::new(Text::from(select_channel.input.data.clone()))
+        .block(Block::default().borders(Borders::ALL).title(title));
     f.render_widget(input, chunks[0]);
     let cursor = &select_channel.input.cursor;
     f.set_cursor_position((
@@ -94,41 +171,684 @@ fn draw_select_channel_popup(f: &mut Frame, select_channel: &mut SelectChannel)
     }
     let list = List::new(items)
         .block(Block::default().borders(Borders::ALL))
-        .highlight_style(Style::default().fg(Color::Black).bg(Color::Gray));
+        .highlight_style(palette.selected);
     f.render_stateful_widget(list, chunks[1], &mut select_channel.state);
 }
 
+fn draw_attachment_picker_popup(f: &mut Frame, picker: &mut AttachmentPicker, palette: &Palette) {
+    let area = centered_rect(60, 60, f.area());
+    let chunks = Layout::default()
+        .constraints([Constraint::Length(1 + 2), Constraint::Min(0)].as_ref())
+        .direction(Direction::Vertical)
+        .split(area);
+    clear_popup(f, area, palette);
+    let title = format!("Attach files from {}", picker.dir.display());
+    let input = Paragraph::new(Text::from(picker.input.data.clone()))
+        .block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(input, chunks[0]);
+    let cursor = &picker.input.cursor;
+    f.set_cursor_position((
+        chunks[0].x + cursor.col as u16 + 1,
+        chunks[0].y + cursor.line as u16 + 1,
+    ));
+    let items: Vec<_> = picker.filtered_names().map(ListItem::new).collect();
+    match picker.state.selected() {
+        Some(idx) if items.len() <= idx => {
+            picker.state.select(items.len().checked_sub(1));
+        }
+        None if !items.is_empty() => {
+            picker.state.select(Some(0));
+        }
+        _ => (),
+    }
+    let hint = match picker.marked_count() {
+        0 => "Enter: open/attach, Tab: mark".to_string(),
+        n => format!("Enter: attach {n} marked, Tab: mark"),
+    };
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(hint))
+        .highlight_style(palette.selected);
+    f.render_stateful_widget(list, chunks[1], &mut picker.state);
+}
+
+fn draw_recent_files_popup(f: &mut Frame, recent_files: &mut RecentFiles, palette: &Palette) {
+    let area = centered_rect(60, 60, f.area());
+    clear_popup(f, area, palette);
+    let items: Vec<_> = recent_files
+        .items
+        .iter()
+        .map(|file| {
+            let kind = match file.kind {
+                RecentFileKind::Sent => "sent",
+                RecentFileKind::Downloaded => "recv",
+            };
+            ListItem::new(format!("{kind} {}", file.path.display()))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Recent files (Enter: attach, o: open)"),
+        )
+        .highlight_style(palette.selected);
+    f.render_stateful_widget(list, area, &mut recent_files.state);
+}
+
+fn draw_attachment_gallery_popup(f: &mut Frame, app: &mut App) {
+    let area = centered_rect(80, 60, f.area());
+    clear_popup(f, area, &app.palette);
+    let gallery = &app.attachment_gallery;
+    let footer_height = match (&gallery.save_as, &gallery.status) {
+        (Some(_), _) => 1 + 2,
+        (None, Some(_)) => 1,
+        (None, None) => 0,
+    };
+    let chunks = Layout::default()
+        .constraints([Constraint::Min(0), Constraint::Length(footer_height)].as_ref())
+        .direction(Direction::Vertical)
+        .split(area);
+
+    let items: Vec<_> = gallery
+        .filtered_items()
+        .map(|item| {
+            let arrived_at = utc_timestamp_msec_to_local(item.arrived_at).format("%F %R");
+            let from = app.name_by_id_cached(item.from_id);
+            let name = item
+                .attachment
+                .filename
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let size = display_size(item.attachment.size.into());
+            let text = format!("{arrived_at} {from}: {name} ({size})");
+            if item.exists {
+                ListItem::new(text)
+            } else {
+                ListItem::new(format!("{text} deleted")).style(Style::default().fg(Color::DarkGray))
+            }
+        })
+        .collect();
+    let title = format!(
+        "Attachments: {} (Tab: filter, Enter: open, s: save as, d: delete)",
+        gallery.filter
+    );
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(app.palette.selected);
+
+    if let Some(save_as) = gallery.save_as.as_ref() {
+        let title = if gallery.overwrite.is_some() {
+            "File exists (Enter: overwrite, Esc: cancel)"
+        } else {
+            "Save as (Enter: save, Esc: cancel)"
+        };
+        let input = Paragraph::new(Text::from(save_as.data.clone()))
+            .block(Block::default().borders(Borders::ALL).title(title));
+        f.render_widget(input, chunks[1]);
+        let cursor = &save_as.cursor;
+        f.set_cursor_position((
+            chunks[1].x + cursor.col as u16 + 1,
+            chunks[1].y + cursor.line as u16 + 1,
+        ));
+    } else if let Some(status) = gallery.status.as_ref() {
+        f.render_widget(Paragraph::new(status.as_str()), chunks[1]);
+    }
+    f.render_stateful_widget(list, chunks[0], &mut app.attachment_gallery.state);
+}
+
+fn draw_contact_list_popup(f: &mut Frame, app: &mut App) {
+    let area = centered_rect(80, 60, f.area());
+    clear_popup(f, area, &app.palette);
+    let contact_list = &app.contact_list;
+    let footer_height = match (&contact_list.rename, &contact_list.status) {
+        (Some(_), _) => 1 + 2,
+        (None, Some(_)) => 1,
+        (None, None) => 0,
+    };
+    let chunks = Layout::default()
+        .constraints([Constraint::Min(0), Constraint::Length(footer_height)].as_ref())
+        .direction(Direction::Vertical)
+        .split(area);
+
+    let dimmed = Style::default().fg(Color::DarkGray);
+    let items: Vec<_> = contact_list
+        .items()
+        .iter()
+        .map(|item| {
+            let mut spans = vec![Span::raw(item.name.clone())];
+            if item.nickname.is_some() {
+                spans.push(Span::styled(" (nickname)", dimmed));
+            }
+            if let Some(phone_number) = item.phone_number.as_ref() {
+                spans.push(Span::raw(format!(
+                    "  {}",
+                    app.format_phone_number(phone_number)
+                )));
+            }
+            if let Some(profile_name) = item.profile_name.as_ref() {
+                spans.push(Span::raw(format!("  profile: {profile_name}")));
+            }
+            if item.verified {
+                spans.push(Span::styled(
+                    "  verified",
+                    Style::default().fg(Color::Green),
+                ));
+            }
+            if item.blocked {
+                spans.push(Span::styled("  blocked", Style::default().fg(Color::Red)));
+            }
+            spans.push(Span::styled(format!("  {}", item.id), dimmed));
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Contacts (Enter: open, r: rename, b: block/unblock)"),
+        )
+        .highlight_style(app.palette.selected);
+
+    if let Some(rename) = contact_list.rename.as_ref() {
+        let input = Paragraph::new(Text::from(rename.data.clone())).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Nickname (Enter: save, empty: clear, Esc: cancel)"),
+        );
+        f.render_widget(input, chunks[1]);
+        let cursor = &rename.cursor;
+        f.set_cursor_position((
+            chunks[1].x + cursor.col as u16 + 1,
+            chunks[1].y + cursor.line as u16 + 1,
+        ));
+    } else if let Some(status) = contact_list.status.as_ref() {
+        f.render_widget(Paragraph::new(status.as_str()), chunks[1]);
+    }
+    f.render_stateful_widget(list, chunks[0], &mut app.contact_list.state);
+}
+
+fn draw_message_search_popup(f: &mut Frame, app: &mut App) {
+    let area = centered_rect(80, 60, f.area());
+    let chunks = Layout::default()
+        .constraints([Constraint::Length(1 + 2), Constraint::Min(0)].as_ref())
+        .direction(Direction::Vertical)
+        .split(area);
+    clear_popup(f, area, &app.palette);
+
+    let search = &app.message_search;
+    let input = Paragraph::new(Text::from(search.input.data.clone())).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Search messages"),
+    );
+    f.render_widget(input, chunks[0]);
+    let cursor = &search.input.cursor;
+    f.set_cursor_position((
+        chunks[0].x + cursor.col as u16 + 1,
+        chunks[0].y + cursor.line as u16 + 1,
+    ));
+
+    let items: Vec<_> = search
+        .results()
+        .iter()
+        .map(|result| {
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{} ", result.arrived_at)),
+                Span::styled(
+                    result.channel_name.clone(),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(format!(": {}", result.text)),
+            ]))
+        })
+        .collect();
+    let title = format!("{} messages (Enter: show)", items.len());
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(app.palette.selected);
+    f.render_stateful_widget(list, chunks[1], &mut app.message_search.state);
+}
+
+fn draw_reaction_picker_popup(
+    f: &mut Frame,
+    reaction_picker: &mut ReactionPicker,
+    palette: &Palette,
+) {
+    let area = centered_rect(60, 60, f.area());
+    clear_popup(f, area, palette);
+    let items: Vec<_> = reaction_picker
+        .items
+        .iter()
+        .map(|item| {
+            let mut line = Line::from(item.emoji.clone());
+            if !item.senders.is_empty() {
+                line.push_span(format!(" {} ", item.senders.len()));
+                line.push_span(Span::styled(
+                    item.senders.join(", "),
+                    Style::default().fg(Color::Gray),
+                ));
+            }
+            if item.is_own {
+                line = line.patch_style(Style::default().add_modifier(Modifier::BOLD));
+            }
+            ListItem::new(line)
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Reactions (Enter: react, or remove own reaction)"),
+        )
+        .highlight_style(palette.selected);
+    f.render_stateful_widget(list, area, &mut reaction_picker.state);
+}
+
+/// Draws a single line with the connection state, the selected channel, the total number of unread
+/// messages and the input mode
+fn draw_captcha_prompt_popup(f: &mut Frame, prompt: &CaptchaPrompt, palette: &Palette) {
+    let area = centered_rect(60, 40, f.area());
+    let chunks = Layout::default()
+        .constraints([Constraint::Min(0), Constraint::Length(1 + 2)].as_ref())
+        .direction(Direction::Vertical)
+        .split(area);
+    clear_popup(f, area, palette);
+
+    let mut lines = vec![
+        Line::from("Sending was rate limited by Signal. To continue, solve the captcha at"),
+        Line::from(""),
+        Line::styled(CAPTCHA_URL, Style::default().fg(Color::Cyan)),
+        Line::from(""),
+        Line::from("and paste the signalcaptcha:// link of the \"Open Signal\" button below."),
+    ];
+    if let Some(error) = prompt.error.as_ref() {
+        lines.push(Line::from(""));
+        lines.push(Line::styled(
+            format!("Error: {error}"),
+            Style::default().fg(Color::Red),
+        ));
+    }
+    let instructions = Paragraph::new(Text::from(lines))
+        .wrap(Wrap { trim: false })
+        .block(Block::default().borders(Borders::ALL).title("Captcha"));
+    f.render_widget(instructions, chunks[0]);
+
+    let input = Paragraph::new(Text::from(prompt.input.data.clone())).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Enter: submit, Esc: later"),
+    );
+    f.render_widget(input, chunks[1]);
+    let cursor = &prompt.input.cursor;
+    f.set_cursor_position((
+        chunks[1].x + cursor.col as u16 + 1,
+        chunks[1].y + cursor.line as u16 + 1,
+    ));
+}
+
+fn draw_diagnostics_popup(f: &mut Frame, app: &App) {
+    let area = if app.profile_memory {
+        centered_rect(60, 70, f.area())
+    } else {
+        centered_rect(50, 30, f.area())
+    };
+    clear_popup(f, area, &app.palette);
+
+    let usage = app.attachment_cache.usage();
+    let limit = match app.config.max_attachments_mb {
+        Some(max_mb) => format!("{max_mb} MiB"),
+        None => "none".to_string(),
+    };
+    let mut lines = vec![
+        Line::from(format!(
+            "Attachments: {} files, {}",
+            usage.files,
+            display_size(usage.bytes)
+        )),
+        Line::from(format!("Limit: {limit}")),
+        Line::from(format!(
+            "Not downloaded: {} attachments, downloaded when opened",
+            usage.deleted
+        )),
+        Line::from(format!("Channels: {}", app.channels.items.len())),
+    ];
+    if app.profile_memory {
+        let profile = &app.memory_profile;
+        let in_memory = profile
+            .iter()
+            .fold(MemoryUsage::default(), |total, channel| {
+                total + channel.in_memory
+            });
+        lines.push(Line::from(""));
+        lines.push(Line::from(format!(
+            "Messages in memory: {}, ~{}",
+            in_memory.messages,
+            display_size(in_memory.bytes as u64)
+        )));
+        let limit = if app.config.sqlite.enabled {
+            let limit = app
+                .config
+                .max_messages_in_memory
+                .unwrap_or(app.config.history_page_size);
+            format!("{limit} per channel")
+        } else {
+            "none, the JSON storage keeps all messages in memory".to_string()
+        };
+        lines.push(Line::from(format!("Limit: {limit}")));
+        // the channels using the most memory, as many as fit into the popup
+        let rows = (area.height as usize).saturating_sub(lines.len() + 2);
+        lines.extend(profile.iter().take(rows).map(|channel| {
+            Line::from(format!(
+                "  {}: {} in memory, ~{}, {} loaded",
+                channel.name,
+                channel.in_memory.messages,
+                display_size(channel.in_memory.bytes as u64),
+                channel.loaded
+            ))
+        }));
+    }
+    let diagnostics = Paragraph::new(Text::from(lines))
+        .wrap(Wrap { trim: false })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Diagnostics (Esc: close)"),
+        );
+    f.render_widget(diagnostics, area);
+}
+
+fn draw_error_log_popup(f: &mut Frame, app: &App) {
+    let area = centered_rect(70, 50, f.area());
+    clear_popup(f, area, &app.palette);
+
+    let mut lines: Vec<Line> = app
+        .error_log
+        .entries()
+        .map(|entry| {
+            Line::from(vec![
+                Span::styled(
+                    format!("{} {:<8} ", entry.at.format("%m-%d %H:%M:%S"), entry.kind),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::raw(entry.message.clone()),
+            ])
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::from("No errors"));
+    }
+    let errors = Paragraph::new(Text::from(lines))
+        .wrap(Wrap { trim: false })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Errors ({}, Esc: close)", app.error_log.len())),
+        );
+    f.render_widget(errors, area);
+}
+
+fn draw_devices_popup(f: &mut Frame, app: &App, devices: &Result<Vec<LinkedDevice>, String>) {
+    let area = centered_rect(60, 40, f.area());
+    clear_popup(f, area, &app.palette);
+
+    let device_id = app.device_id();
+    let mut lines = vec![
+        Line::from(format!("This device: {device_id}")),
+        Line::from(""),
+    ];
+    match devices {
+        Ok(devices) => lines.extend(devices.iter().map(|device| {
+            // the primary device has the id 1 and no name
+            let name = match (device.id, device.name.as_deref()) {
+                (1, None) => "primary device",
+                (_, name) => name.unwrap_or("unnamed"),
+            };
+            let line = format!(
+                "{:>3}  {name:<24}  last seen {}",
+                device.id,
+                // the server reports the last activity with the precision of a day
+                device.last_seen.format("%Y-%m-%d"),
+            );
+            if device.id == device_id {
+                Line::from(Span::styled(
+                    line,
+                    Style::default().add_modifier(Modifier::BOLD),
+                ))
+            } else {
+                Line::from(line)
+            }
+        })),
+        Err(error) => lines.push(Line::from(Span::styled(
+            error.as_str(),
+            Style::default().fg(Color::Red),
+        ))),
+    }
+    let devices = Paragraph::new(Text::from(lines))
+        .wrap(Wrap { trim: false })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Linked devices (Esc: close)"),
+        );
+    f.render_widget(devices, area);
+}
+
+/// Sends taking longer are shown in the status bar
+const SLOW_SEND_AFTER: Duration = Duration::from_secs(1);
+
+fn draw_status_bar(f: &mut Frame, app: &App, area: Rect) {
+    let connection_color = match app.connection_state {
+        ConnectionState::Connected => Color::Green,
+        ConnectionState::Connecting | ConnectionState::Reconnecting { .. } => Color::Yellow,
+        ConnectionState::Offline => Color::Red,
+    };
+    let mut spans = vec![Span::styled(
+        format!(" ● {} ", app.connection_state),
+        Style::default().fg(connection_color),
+    )];
+
+    if let Some(channel) = app
+        .channels
+        .selected_item()
+        .and_then(|&channel_id| app.storage.channel(channel_id))
+    {
+        let mut label = format!("│ {}", app.channel_name(&channel));
+        if let Some(group_data) = &channel.group_data {
+            label.push_str(&format!(" ({} members)", group_data.members.len()));
+        }
+        label.push(' ');
+        spans.push(Span::raw(label));
+    }
+
+    let unread_messages = app.unread_messages_len();
+    if unread_messages > 0 {
+        spans.push(Span::styled(
+            format!("│ {unread_messages} unread "),
+            Style::default().add_modifier(Modifier::BOLD),
+        ));
+    }
+
+    if !app.marked_channels.is_empty() {
+        spans.push(Span::raw(format!(
+            "│ {} marked ",
+            app.marked_channels.len()
+        )));
+    }
+
+    let marked_messages = app.selected_channel_marks().len();
+    if marked_messages > 0 {
+        spans.push(Span::raw(format!("│ {marked_messages} messages marked ")));
+    }
+
+    let queued_messages = app.queued_messages_len();
+    if queued_messages > 0 {
+        spans.push(Span::raw(format!("│ {queued_messages} queued ")));
+    }
+    // short sends are not worth showing
+    let slow_sends: Vec<_> = app
+        .effects
+        .iter()
+        .filter(|effect| matches!(effect.kind, EffectKind::Send(_)))
+        .filter(|effect| effect.started_at.elapsed() > SLOW_SEND_AFTER)
+        .collect();
+    if let Some(progress) = slow_sends.iter().rev().find_map(|effect| effect.progress) {
+        spans.push(Span::raw(format!("│ {progress} ")));
+    } else if !slow_sends.is_empty() {
+        spans.push(Span::raw(format!("│ sending {} ", slow_sends.len())));
+    }
+    let downloads = app
+        .effects
+        .iter()
+        .filter(|effect| matches!(effect.kind, EffectKind::Download(_)))
+        .count();
+    if downloads > 0 {
+        spans.push(Span::raw(format!("│ downloading {downloads} ")));
+    }
+    if let Some(at) = app.rate_limited.retry_at() {
+        let after = at.saturating_duration_since(Instant::now());
+        spans.push(Span::styled(
+            format!("│ rate limited, resending in {}s ", after.as_secs() + 1),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+
+    spans.push(Span::raw(format!("│ {} ", app.mode_name())));
+
+    if app.presence == Presence::DoNotDisturb {
+        spans.push(Span::raw("│ do not disturb "));
+    }
+
+    for segment in app.plugin_status_segments() {
+        spans.push(Span::raw(format!("│ {segment} ")));
+    }
+
+    if let Some(status) = app.send_status() {
+        let color = if status.is_error {
+            Color::LightRed
+        } else {
+            Color::LightGreen
+        };
+        spans.push(Span::styled(
+            format!("│ {} ", status.text),
+            Style::default().fg(color),
+        ));
+    } else if let Some(error) = app.error_log.toast() {
+        spans.push(Span::styled(
+            format!("│ {} ", error.message),
+            Style::default().fg(Color::LightRed),
+        ));
+    }
+
+    let status_bar = Paragraph::new(Line::from(spans)).style(app.palette.status_bar);
+    f.render_widget(status_bar, area);
+}
+
 fn draw_channels(f: &mut Frame, app: &mut App, area: Rect) {
     let channel_list_width = area.width.saturating_sub(2) as usize;
+    let now = utc_now_timestamp_msec();
+    let visible_channels_len = app.visible_channels_len();
+    let archived_channels_len = app.channels.items.len() - visible_channels_len;
+    let dormant_before = app
+        .config
+        .dormant_after_days
+        .map(|days| now.saturating_sub(days.saturating_mul(DAY_MSEC)));
+    let icons = app.terminal.channel_icons();
     let channels = app
         .channels
         .items
         .iter()
-        .filter_map(|&channel_id| app.storage.channel(channel_id))
-        .map(|channel| {
-            let unread_messages_label = if channel.unread_messages != 0 {
+        .take(visible_channels_len)
+        .enumerate()
+        .filter_map(|(channel_idx, &channel_id)| {
+            Some((channel_idx, app.storage.channel(channel_id)?))
+        })
+        .map(|(channel_idx, channel)| {
+            let last_message_arrived_at = app.last_message_arrived_at(channel.id);
+            let unread_label = if channel.unread_messages != 0 {
                 format!(" ({})", channel.unread_messages)
             } else {
                 String::new()
             };
-            let label = format!("{}{}", app.channel_name(&channel), unread_messages_label);
-            let label_width = label.width();
-            let label = if label.width() <= channel_list_width || unread_messages_label.is_empty() {
-                label
+            let mut icons_label = String::new();
+            if channel.is_muted(now) {
+                icons_label.push(' ');
+                icons_label.push_str(icons.muted);
+            }
+            if channel.pinned {
+                icons_label.push(' ');
+                icons_label.push_str(icons.pinned);
+            }
+            if channel.burn_after_reading {
+                icons_label.push(' ');
+                icons_label.push_str(icons.burn_after_reading);
+            }
+            if channel
+                .id
+                .user()
+                .is_some_and(|user_id| app.birthdays_today.contains(&user_id))
+            {
+                icons_label.push(' ');
+                icons_label.push_str(icons.birthday);
+            }
+            if app.marked_channels.contains(&channel.id) {
+                icons_label.push(' ');
+                icons_label.push_str(icons.marked);
+            }
+            let name = app.channel_name(&channel).into_owned();
+            let label_width = name.width() + unread_label.width() + icons_label.width();
+            let name = if label_width <= channel_list_width
+                || (unread_label.is_empty() && icons_label.is_empty())
+            {
+                name
             } else {
                 let diff = label_width - channel_list_width;
-                let mut end = channel.name.width().saturating_sub(diff);
-                while !channel.name.is_char_boundary(end) {
+                let mut end = name.width().saturating_sub(diff);
+                while !name.is_char_boundary(end) {
                     end += 1;
                 }
-                format!("{}{}", &channel.name[0..end], unread_messages_label)
+                name[0..end].to_string()
             };
-            ListItem::new(vec![Line::from(Span::raw(label))])
+            let dormant = dormant_before
+                .is_some_and(|before| last_message_arrived_at.unwrap_or_default() < before);
+            let style = if channel.archived || dormant {
+                Style::default().fg(Color::DarkGray)
+            } else {
+                Style::default()
+            };
+            let mut lines = Vec::new();
+            if app.starts_section(channel_idx) {
+                let title = app.channel_section(channel.id).title();
+                lines.push(Line::styled(
+                    title,
+                    Style::default()
+                        .fg(Color::DarkGray)
+                        .add_modifier(Modifier::BOLD),
+                ));
+            }
+            lines.push(Line::from(vec![
+                Span::raw(name),
+                Span::styled(unread_label, app.palette.unread),
+                Span::raw(icons_label),
+            ]));
+            if app.show_channel_details {
+                let details = display_last_activity(last_message_arrived_at, now);
+                lines.push(Line::styled(
+                    format!("  {details}"),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            ListItem::new(lines).style(style)
         });
 
+    let title = if archived_channels_len > 0 {
+        format!("Channels (+{archived_channels_len} archived)")
+    } else {
+        "Channels".to_string()
+    };
     let channels = List::new(channels)
-        .block(Block::default().borders(Borders::ALL).title("Channels"))
-        .highlight_style(Style::default().fg(Color::Black).bg(Color::Gray));
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(app.palette.selected);
     let no_channels = channels.is_empty();
     f.render_stateful_widget(channels, area, &mut app.channels.state);
 
@@ -143,6 +863,147 @@ fn draw_channels(f: &mut Frame, app: &mut App, area: Rect) {
     };
 }
 
+const DAY_MSEC: u64 = 24 * 60 * 60 * 1000;
+
+/// Describes how many days ago the last message of a channel arrived
+fn draw_channel_info(f: &mut Frame, app: &App, area: Rect) {
+    let block = Block::default().borders(Borders::ALL).title("Details");
+    let channel = app
+        .channels
+        .state
+        .selected()
+        .and_then(|idx| app.channels.items.get(idx))
+        .and_then(|&channel_id| app.storage.channel(channel_id));
+    let (Some(channel), Some(info)) = (channel, app.channel_info.as_ref()) else {
+        f.render_widget(block, area);
+        return;
+    };
+    if info.channel_id != channel.id {
+        // not collected yet
+        f.render_widget(block, area);
+        return;
+    }
+
+    let label_style = Style::default().fg(Color::Yellow);
+    let name = app.channel_name(&channel).into_owned();
+    let avatar = format!(" {} ", initials(&name));
+    let mut lines = vec![
+        Line::from(format!("╭{}╮", "─".repeat(avatar.width()))),
+        Line::from(format!("│{avatar}│")),
+        Line::from(format!("╰{}╯", "─".repeat(avatar.width()))),
+        Line::styled(name, Style::default().add_modifier(Modifier::BOLD)),
+        Line::from(""),
+    ];
+    let mut field = |label: &str, value: String| {
+        lines.push(Line::styled(label.to_string(), label_style));
+        lines.push(Line::from(value));
+    };
+    if let Some(uuid) = channel.user_id() {
+        if let Some(phone_number) = info.phone_number.as_ref() {
+            field("Phone number", app.format_phone_number(phone_number));
+        }
+        field("UUID", uuid.to_string());
+        if let Some(birthday) = info.notes.birthday {
+            field("Birthday", birthday.to_string());
+        }
+        if let Some(notes) = info.notes.notes.as_ref() {
+            field("Notes", notes.clone());
+        }
+    }
+    let now = utc_now_timestamp_msec();
+    let muted = match channel.muted_until {
+        Some(Channel::MUTED_FOREVER) => "yes".to_string(),
+        Some(muted_until) if now < muted_until => {
+            let muted_until = utc_timestamp_msec_to_local(muted_until);
+            format!("until {}", muted_until.format("%F %R"))
+        }
+        _ => "no".to_string(),
+    };
+    field("Muted", muted);
+    let notification_content = match channel.notification_content {
+        Some(content) => format!("{} (this chat)", content.as_str()),
+        None => app.config.notification.content.as_str().to_string(),
+    };
+    field("Notifications", notification_content);
+    if channel.group_data.is_some() {
+        let mention_all = if channel.ignore_mention_all {
+            "ignored"
+        } else {
+            "mention you"
+        };
+        field("@all", mention_all.to_string());
+    }
+    if channel.id.is_user() {
+        let timer = info.expire_timer.map(display_timer);
+        field(
+            "Disappearing messages",
+            timer.unwrap_or_else(|| "off".to_string()),
+        );
+    }
+    field("Attachments", info.attachments.to_string());
+
+    let (title, names) = if channel.id.is_user() {
+        ("Groups in common", &info.shared_groups)
+    } else {
+        ("Members", &info.members)
+    };
+    lines.push(Line::styled(
+        format!("{title} ({})", names.len()),
+        label_style,
+    ));
+    lines.extend(names.iter().map(|name| Line::from(format!("  {name}"))));
+
+    if let Some(requests) = info.requests.as_ref() {
+        let join_title = if requests.link_approval {
+            "Join requests (group link needs approval)"
+        } else {
+            "Join requests"
+        };
+        let sections = [
+            (join_title, &requests.join_requests),
+            ("Pending invites", &requests.invites),
+        ];
+        for (title, requests) in sections {
+            lines.push(Line::styled(
+                format!("{title} ({})", requests.len()),
+                label_style,
+            ));
+            lines.extend(requests.iter().enumerate().map(|(idx, request)| {
+                let requested_at = utc_timestamp_msec_to_local(request.timestamp).format("%F");
+                let mut line = format!(
+                    "  {}. {} ({requested_at}",
+                    idx + 1,
+                    app.name_by_id_cached(request.user_id)
+                );
+                if let Some(added_by) = request.added_by {
+                    line.push_str(", invited by ");
+                    line.push_str(&app.name_by_id_cached(added_by));
+                }
+                line.push(')');
+                Line::from(line)
+            }));
+        }
+    }
+
+    let paragraph = Paragraph::new(Text::from(lines))
+        .block(block)
+        .wrap(Wrap { trim: false });
+    f.render_widget(paragraph, area);
+}
+
+fn display_last_activity(last_message_arrived_at: Option<u64>, now: u64) -> String {
+    let Some(arrived_at) = last_message_arrived_at else {
+        return "no messages".to_string();
+    };
+    let days = utc_timestamp_msec_to_local(now).num_days_from_ce()
+        - utc_timestamp_msec_to_local(arrived_at).num_days_from_ce();
+    match days {
+        ..=0 => "last message today".to_string(),
+        1 => "last message yesterday".to_string(),
+        days => format!("last message {days} days ago"),
+    }
+}
+
 fn wrap(text: &str, mut cursor: Cursor, width: usize) -> (String, Cursor, usize) {
     let mut res = String::new();
 
@@ -191,13 +1052,28 @@ fn wrap(text: &str, mut cursor: Cursor, width: usize) -> (String, Cursor, usize)
 
 fn draw_chat(f: &mut Frame, app: &mut App, area: Rect) {
     let text_width = area.width.saturating_sub(2) as usize;
+    let input = match app.input_history.search.as_ref() {
+        Some(search) => &search.query,
+        None => &app.input,
+    };
     let (wrapped_input, cursor, num_input_lines) =
-        wrap(&app.input.data, app.input.cursor.clone(), text_width);
+        wrap(&input.data, input.cursor.clone(), text_width);
+
+    let is_searching = app.input_history.search.is_some();
+    let preview = if is_searching {
+        Some(displayed_history_search(app))
+    } else {
+        app.send_preview()
+            .map(|preview| displayed_send_preview(app, preview))
+    };
 
+    let storage_error = app.error_log.storage_error.as_ref();
     let chunks = Layout::default()
         .constraints(
             [
+                Constraint::Length(storage_error.is_some() as u16),
                 Constraint::Min(0),
+                Constraint::Length(preview.is_some() as u16),
                 Constraint::Length(num_input_lines as u16 + 2),
             ]
             .as_ref(),
@@ -205,27 +1081,122 @@ fn draw_chat(f: &mut Frame, app: &mut App, area: Rect) {
         .direction(Direction::Vertical)
         .split(area);
 
-    draw_messages(f, app, chunks[0]);
+    if let Some(error) = storage_error {
+        let banner = Paragraph::new(format!(
+            "Messages are not saved: {error} (see toggle_error_log)"
+        ))
+        .style(Style::default().fg(Color::White).bg(Color::Red));
+        f.render_widget(banner, chunks[0]);
+    }
+
+    draw_pane(f, app, Pane::Messages, chunks[1], draw_messages);
 
     let title = match (app.is_editing(), app.is_multiline_input) {
+        _ if is_searching => "Search sent messages (Enter to accept, Esc to cancel)",
+        _ if preview.is_some() => "Input (Preview, Enter to send, Esc to cancel)",
         (true, true) => "Input (Editing, Multiline)",
         (true, false) => "Input (Editing)",
         (false, true) => "Input (Multiline)",
         (false, false) => "Input",
     };
 
-    let input = Paragraph::new(Text::from(wrapped_input))
-        .block(Block::default().borders(Borders::ALL).title(title));
-    f.render_widget(input, chunks[1]);
+    if let Some(preview) = preview {
+        f.render_widget(Paragraph::new(preview), chunks[2]);
+    }
+
+    // the recipient is shown prominently to prevent sending to the wrong channel
+    let recipient = app
+        .channels
+        .selected_item()
+        .and_then(|&channel_id| app.storage.channel(channel_id))
+        .filter(|_| !is_searching)
+        .map(|channel| {
+            let name = app.channel_name(&channel).into_owned();
+            (name, app.channel_color(channel.id))
+        });
+
+    let mut title = vec![Span::from(title)];
+    if let Some((name, color)) = &recipient {
+        title.push(Span::from(" → "));
+        title.push(Span::styled(
+            name.clone(),
+            Style::default().fg(*color).add_modifier(Modifier::BOLD),
+        ));
+    }
+    if let Some(error) = app.command_error.as_ref() {
+        title.push(Span::styled(
+            format!(" ({error})"),
+            Style::default().fg(Color::Red),
+        ));
+    } else if let Some(hint) = app.command_hint.as_ref() {
+        title.push(Span::styled(
+            format!(" ({hint})"),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+
+    let mut block = Block::default()
+        .borders(Borders::ALL)
+        .title(Line::from(title));
+    if let Some((_, color)) = recipient {
+        block = block.border_style(Style::default().fg(color));
+    }
+    let input = Paragraph::new(Text::from(wrapped_input)).block(block);
+    f.render_widget(input, chunks[3]);
     if !app.select_channel.is_shown {
         f.set_cursor_position((
-            chunks[1].x + cursor.col as u16 + 1,  // +1 for frame
-            chunks[1].y + cursor.line as u16 + 1, // +1 for frame
+            chunks[3].x + cursor.col as u16 + 1,  // +1 for frame
+            chunks[3].y + cursor.line as u16 + 1, // +1 for frame
         ));
     }
 }
 
+/// Current match of the input history search
+fn displayed_history_search(app: &App) -> Line<'static> {
+    let found = app
+        .channels
+        .selected_item()
+        .and_then(|&channel_id| app.input_history.search_match(channel_id));
+    Line::from(vec![
+        Span::styled("Found: ", Style::default().add_modifier(Modifier::BOLD)),
+        Span::from(found.unwrap_or_default().replace('\n', " ")),
+    ])
+}
+
+/// One line preview of the message to be sent with resolved names and shortcodes
+fn displayed_send_preview(app: &App, preview: &SendPreview) -> Line<'static> {
+    let names = NameResolver::compute(app, std::iter::empty());
+
+    let label = if preview.editing { "Edit: " } else { "Send: " };
+    let mut spans = vec![Span::styled(
+        label,
+        Style::default().add_modifier(Modifier::BOLD),
+    )];
+    if let Some(quote) = preview
+        .quote
+        .as_ref()
+        .and_then(|quote| displayed_quote(&names, quote))
+    {
+        spans.push(Span::styled(
+            format!("> {} ", quote.replace('\n', " ")),
+            Style::default().fg(Color::Rgb(150, 150, 150)),
+        ));
+    }
+    spans.push(Span::from(preview.text.replace('\n', " ")));
+    if !preview.attachments.is_empty() {
+        spans.push(Span::styled(
+            format!(" <attached: {}>", preview.attachments.join(", ")),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    Line::from(spans)
+}
+
 fn prepare_receipts(app: &mut App, height: usize) {
+    // shown messages are only read while the user is reading
+    if !app.is_reading() {
+        return;
+    }
     let user_id = app.user_id;
     let channel_id = match app.channels.selected_item() {
         Some(channel_id) => *channel_id,
@@ -248,11 +1219,13 @@ fn prepare_receipts(app: &mut App, height: usize) {
         messages.rendered.offset
     };
 
-    let read_messages: Vec<Message> = app
-        .storage
-        .messages(channel_id)
+    // only the loaded messages are looked up, not the whole history of the channel
+    let read_messages: Vec<Message> = messages
+        .items
+        .iter()
         .rev()
         .skip(offset)
+        .filter_map(|&arrived_at| app.storage.message(MessageId::new(channel_id, arrived_at)))
         .filter_map(|message| {
             if let Receipt::Delivered = message.receipt {
                 if message.from_id != user_id {
@@ -303,12 +1276,14 @@ fn draw_messages(f: &mut Frame, app: &mut App, area: Rect) {
         .expect("non-existent channel");
 
     let writing_people = app.writing_people(&channel);
+    app.message_layouts.get_mut().next_frame(&app.palette);
 
     // Calculate the offset in messages we start rendering with.
     // `offset` includes the selected message (if any), and is at most height-many messages to
     // the selected message, since we can't render more than height-many of them.
     let messages = &app.messages[&channel_id];
-    let offset = if let Some(selected) = messages.state.selected() {
+    let selected = messages.state.selected();
+    let offset = if let Some(selected) = selected {
         messages
             .rendered
             .offset
@@ -316,6 +1291,123 @@ fn draw_messages(f: &mut Frame, app: &mut App, area: Rect) {
     } else {
         messages.rendered.offset
     };
+
+    let mut rendered = render_messages(app, channel_id, offset, selected, width, height);
+    if selected.is_none() && rendered.offset > 0 && rendered.reached_top {
+        // Scrolled up to the oldest message: find the smallest offset which still shows it, so
+        // that the viewport is filled.
+        let (mut lo, mut hi) = (0, rendered.offset);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if render_messages(app, channel_id, mid, None, width, height).reached_top {
+                hi = mid;
+            } else {
+                lo = mid + 1;
+            }
+        }
+        if lo < rendered.offset {
+            rendered = render_messages(app, channel_id, lo, None, width, height);
+        }
+    }
+    let RenderedMessages {
+        mut items,
+        offset,
+        prefix_width,
+        ..
+    } = rendered;
+    let page_len = items.len();
+
+    // add unread messages line
+    let unread_messages = channel.unread_messages as usize;
+    if unread_messages > 0 && unread_messages < items.len() {
+        let new_message_line = "-".repeat(prefix_width)
+            + "new messages"
+            + &"-".repeat(width.saturating_sub(prefix_width));
+        items.insert(unread_messages, ListItem::new(Span::from(new_message_line)));
+    }
+
+    let mut title = "Messages".to_string();
+    if app.is_loading_messages(channel_id) {
+        title.push_str(" (loading…)");
+    }
+    let sending_messages = app.sending_messages_len(channel_id);
+    if sending_messages > 0 {
+        title.push_str(&format!(" ({sending_messages} sending…)"));
+    }
+    if let Some(writing_people) = writing_people {
+        title.push(' ');
+        title.push_str(&writing_people);
+    }
+    let mut block = Block::default().title(title).borders(Borders::ALL);
+    // the newer messages are hidden at the bottom, or at the top with the newest message first
+    let direction = if app.newest_first {
+        if offset > 0 {
+            block = block.title(Line::from(format!(" ↑ {offset} more above ")).right_aligned());
+        }
+        ListDirection::TopToBottom
+    } else {
+        if offset > 0 {
+            block =
+                block.title_bottom(Line::from(format!(" ↓ {offset} more below ")).right_aligned());
+        }
+        ListDirection::BottomToTop
+    };
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(app.palette.selected)
+        .direction(direction);
+
+    // re-borrow channel messages mutably
+    let messages = app
+        .messages
+        .get_mut(&channel_id)
+        .expect("non-existent channel");
+
+    // update selected state to point within `items`
+    let state = &mut messages.state;
+    let selected_global = state.selected();
+    if let Some(selected) = selected_global {
+        state.select(Some(selected - offset));
+    }
+
+    f.render_stateful_widget(list, area, state);
+
+    // restore selected state and update offset
+    state.select(selected_global);
+    messages.rendered.offset = offset;
+    messages.rendered.page_len = page_len;
+}
+
+/// Messages rendered into the viewport
+struct RenderedMessages {
+    /// List items from the bottom to the top
+    items: Vec<ListItem<'static>>,
+    /// Offset of the bottom message counted from the newest message
+    offset: usize,
+    /// Whether the oldest message of the channel is rendered
+    reached_top: bool,
+    /// Width of the time, receipt and name in front of the messages
+    prefix_width: usize,
+}
+
+/// Renders the messages of the channel from the bottom to the top
+///
+/// Rendering starts at the message `offset` messages away from the newest one, and stops when the
+/// viewport of `height` lines is full, unless the `selected` message was not rendered yet. In the
+/// latter case, messages are dropped from the bottom until the selected message fits.
+///
+/// Only the messages in the viewport are laid out, and their layouts are cached in
+/// [`App::message_layouts`], so that the length of the history does not slow down drawing.
+fn render_messages(
+    app: &App,
+    channel_id: ChannelId,
+    offset: usize,
+    selected: Option<usize>,
+    width: usize,
+    height: usize,
+) -> RenderedMessages {
+    let messages = &app.messages[&channel_id];
     let messages_to_render = messages
         .items
         .iter()
@@ -333,9 +1425,8 @@ fn draw_messages(f: &mut Frame, app: &mut App, area: Rect) {
     let max_username_width = names.max_name_width();
 
     // message display options
-    const TIME_WIDTH: usize = 6; // width of "00:00 "
     const DELIMITER_WIDTH: usize = 2;
-    let mut prefix_width = TIME_WIDTH + max_username_width + DELIMITER_WIDTH;
+    let mut prefix_width = app.config.clock.width() + max_username_width + DELIMITER_WIDTH;
     if app.config.show_receipts {
         prefix_width += RECEIPT_WIDTH;
     }
@@ -346,14 +1437,30 @@ fn draw_messages(f: &mut Frame, app: &mut App, area: Rect) {
         utc_timestamp_msec_to_local(messages_to_render.clone().next().unwrap_or_default())
             .num_days_from_ce();
 
-    let messages_from_offset = messages_to_render
-        .flat_map(|arrived_at| {
-            let Some(msg) = app.storage.message(MessageId::new(channel_id, arrived_at)) else {
-                return [None, None];
-            };
-            let date_division = display_date_line(msg.arrived_at, &mut previous_msg_day, width);
+    let options = LayoutOptions {
+        prefix_width,
+        height,
+        show_receipts: app.config.show_receipts,
+        colored_messages: app.config.colored_messages,
+        clock: app.config.clock,
+    };
+    let mut layouts = app.message_layouts.borrow_mut();
+
+    // accumulate messages as long they fit into the list height, or up to the selected message
+    let mut items: Vec<ListItem<'static>> = Vec::new();
+    let mut items_height = 0;
+    let mut is_full = false;
+    let selected = selected.unwrap_or(0);
+
+    'messages: for arrived_at in messages_to_render {
+        let message_id = MessageId::new(channel_id, arrived_at);
+        let Some(msg) = app.storage.message(message_id) else {
+            continue;
+        };
+        let date_division = display_date_line(msg.arrived_at, &mut previous_msg_day, width);
+        let item = layouts.get_or_insert_with(message_id, &msg, width, options, || {
             let show_receipt = ShowReceipt::from_msg(&msg, app.user_id, app.config.show_receipts);
-            let msg = display_message(
+            display_message(
                 &names,
                 &msg,
                 &prefix,
@@ -361,24 +1468,27 @@ fn draw_messages(f: &mut Frame, app: &mut App, area: Rect) {
                 height,
                 show_receipt,
                 app.config.colored_messages,
-            );
-            [date_division, msg]
-        })
-        .flatten();
-
-    // counters to accumulate messages as long they fit into the list height,
-    // or up to the selected message
-    let mut items_height = 0;
-    let selected = messages.state.selected().unwrap_or(0);
-
-    let mut items: Vec<ListItem<'static>> = messages_from_offset
-        .enumerate()
-        .take_while(|(idx, item)| {
+                app.config.clock,
+                &app.palette,
+            )
+        });
+        let item = if app.marked_messages.contains(&message_id) {
+            item.map(|item| item.style(Style::default().bg(Color::Blue)))
+        } else if app.is_highlighted(message_id) {
+            item.map(|item| item.style(Style::default().bg(Color::DarkGray)))
+        } else {
+            item
+        };
+        for item in [date_division, item].into_iter().flatten() {
             items_height += item.height();
-            items_height <= height || offset + *idx <= selected
-        })
-        .map(|(_, item)| item)
-        .collect();
+            if items_height > height && offset + items.len() > selected {
+                is_full = true;
+                break 'messages;
+            }
+            items.push(item);
+        }
+    }
+    let reached_top = !is_full && offset + height >= messages.items.len();
 
     // calculate the new offset by counting the messages down:
     // we known that we either stopped at the last fitting message or at the selected message
@@ -395,49 +1505,17 @@ fn draw_messages(f: &mut Frame, app: &mut App, area: Rect) {
     let offset = offset + first_idx;
     items = items.split_off(first_idx);
 
-    // add unread messages line
-    let unread_messages = channel.unread_messages as usize;
-    if unread_messages > 0 && unread_messages < items.len() {
-        let new_message_line = "-".repeat(prefix_width)
-            + "new messages"
-            + &"-".repeat(width.saturating_sub(prefix_width));
-        items.insert(unread_messages, ListItem::new(Span::from(new_message_line)));
-    }
-
-    let title: String = if let Some(writing_people) = writing_people {
-        format!("Messages {writing_people}")
-    } else {
-        "Messages".to_string()
-    };
-
-    let list = List::new(items)
-        .block(Block::default().title(title).borders(Borders::ALL))
-        .highlight_style(Style::default().fg(Color::Black).bg(Color::Gray))
-        .direction(ListDirection::BottomToTop);
-
-    // re-borrow channel messages mutably
-    let messages = app
-        .messages
-        .get_mut(&channel_id)
-        .expect("non-existent channel");
-
-    // update selected state to point within `items`
-    let state = &mut messages.state;
-    let selected_global = state.selected();
-    if let Some(selected) = selected_global {
-        state.select(Some(selected - offset));
+    RenderedMessages {
+        items,
+        offset,
+        reached_top,
+        prefix_width,
     }
-
-    f.render_stateful_widget(list, area, state);
-
-    // restore selected state and update offset
-    state.select(selected_global);
-    messages.rendered.offset = offset;
 }
 
-fn display_time(timestamp: u64) -> String {
+fn display_time(timestamp: u64, clock: ClockFormat) -> String {
     utc_timestamp_msec_to_local(timestamp)
-        .format("%R ")
+        .format(clock.format())
         .to_string()
 }
 
@@ -479,6 +1557,13 @@ fn display_receipt(receipt: Receipt, show: ShowReceipt) -> &'static str {
     }
 }
 
+/// Minimal width of the message text next to the hanging indentation
+///
+/// If the pane is narrower, the header (receipt, time and name) is rendered on its own line and
+/// the text is indented by [`NARROW_INDENT`] only.
+const MIN_TEXT_WIDTH: usize = 20;
+const NARROW_INDENT: &str = "  ";
+
 #[allow(clippy::too_many_arguments)]
 fn display_message(
     names: &NameResolver,
@@ -488,16 +1573,15 @@ fn display_message(
     height: usize,
     show_receipt: ShowReceipt,
     colored_messages: bool,
+    clock: ClockFormat,
+    palette: &Palette,
 ) -> Option<ListItem<'static>> {
     let receipt = Span::styled(
         display_receipt(msg.receipt, show_receipt),
         Style::default().fg(Color::Yellow),
     );
 
-    let time = Span::styled(
-        display_time(msg.arrived_at),
-        Style::default().fg(Color::Yellow),
-    );
+    let time = Span::styled(display_time(msg.arrived_at, clock), palette.timestamps);
 
     let (from, from_color) = names.resolve(msg.from_id);
 
@@ -515,10 +1599,6 @@ fn display_message(
     );
     let delimiter = Span::from(": ");
 
-    let wrap_opts = textwrap::Options::new(width)
-        .initial_indent(prefix)
-        .subsequent_indent(prefix);
-
     // collect message text
     let text = msg.message.clone().unwrap_or_default();
     let mut text = replace_mentions(msg, names, text);
@@ -528,88 +1608,192 @@ fn display_message(
     }
     add_reactions(msg, &mut text);
     add_edited(msg, &mut text);
+    add_forwarded(msg, &mut text);
 
     let mut spans: Vec<Line> = vec![];
 
+    // The header is prepended to the first wrapped line. Continuation lines are indented by the
+    // width of the header (hanging indentation), unless the pane is too narrow for it.
+    let header = vec![receipt, time, from, delimiter];
+    let (indent, mut header) = if prefix.width() + MIN_TEXT_WIDTH <= width {
+        (prefix, Some(header))
+    } else {
+        spans.push(Line::from(header));
+        (NARROW_INDENT, None)
+    };
+    let mut styled_lines = |lines: Vec<String>, style: Style| {
+        lines
+            .into_iter()
+            .map(|line| match header.take() {
+                Some(mut header) => {
+                    let line = line.strip_prefix(indent).unwrap_or(&line).to_owned();
+                    header.push(Span::styled(line, style));
+                    Line::from(header)
+                }
+                None => Span::styled(line, style).into(),
+            })
+            .collect::<Vec<_>>()
+    };
+
     // prepend quote if any
     let quote_text = msg
         .quote
         .as_ref()
         .and_then(|quote| displayed_quote(names, quote));
     if let Some(quote_text) = quote_text.as_ref() {
-        let quote_prefix = format!("{prefix}> ");
-        let quote_wrap_opts = textwrap::Options::new(width.saturating_sub(2))
-            .initial_indent(&quote_prefix)
-            .subsequent_indent(&quote_prefix);
+        let quote_indent = format!("{indent}> ");
         let quote_style = Style::default().fg(Color::Rgb(150, 150, 150));
-        spans = textwrap::wrap(quote_text, quote_wrap_opts)
-            .into_iter()
-            .enumerate()
-            .map(|(idx, line)| {
-                let res = if idx == 0 {
-                    vec![
-                        receipt.clone(),
-                        time.clone(),
-                        from.clone(),
-                        delimiter.clone(),
-                        Span::styled(line.strip_prefix(prefix).unwrap().to_owned(), quote_style),
-                    ]
-                } else {
-                    vec![Span::styled(line.into_owned(), quote_style)]
-                };
-                Line::from(res)
-            })
-            .collect();
+        spans.extend(styled_lines(
+            wrap_text(quote_text, &quote_indent, width),
+            quote_style,
+        ));
     }
 
-    let add_time = spans.is_empty();
     let message_style = if colored_messages {
         Style::default().fg(from_color)
+    } else if msg.from_id == names.user_id() {
+        palette.own_messages
     } else {
-        Style::default()
+        palette.other_messages
     };
-    spans.extend(
-        textwrap::wrap(&text, &wrap_opts)
-            .into_iter()
-            .enumerate()
-            .map(|(idx, line)| {
-                let res = if add_time && idx == 0 {
-                    vec![
-                        receipt.clone(),
-                        time.clone(),
-                        from.clone(),
-                        delimiter.clone(),
-                        Span::styled(line.strip_prefix(prefix).unwrap().to_owned(), message_style),
-                    ]
-                } else {
-                    vec![Span::styled(line.into_owned(), message_style)]
-                };
-                Line::from(res)
-            }),
-    );
+    for block in split_code_blocks(&text) {
+        let lines = match block {
+            TextBlock::Text(text) => styled_lines(wrap_text(text, indent, width), message_style),
+            TextBlock::Code(code) => styled_lines(wrap_code(code, indent, width), code_style()),
+        };
+        spans.extend(lines);
+    }
 
     if let Some(reason) = msg.send_failed.as_deref() {
-        let error = format!("[Could no send: {reason}]");
+        let error = format!("[Could not send: {reason}; select it and retry with retry_send]");
         let error_style = Style::default().fg(Color::Red);
-        spans.extend(
-            textwrap::wrap(&error, &wrap_opts)
-                .into_iter()
-                .map(|line| Span::styled(line.into_owned(), error_style).into()),
-        );
+        spans.extend(styled_lines(wrap_text(&error, indent, width), error_style));
+    }
+
+    let note_style = Style::default()
+        .fg(Color::DarkGray)
+        .add_modifier(Modifier::ITALIC);
+    for note in reaction_notes(names, msg) {
+        spans.extend(styled_lines(wrap_text(&note, indent, width), note_style));
     }
 
     if spans.len() > height {
         // span is too big to be shown fully
         spans.resize(height - 1, Line::from(""));
-        spans.push(Line::from(format!("{prefix}[...]")));
+        spans.push(Line::from(format!("{indent}[...]")));
     }
     Some(ListItem::new(Text::from(spans)))
 }
 
+/// Word-wraps the text at the width, indenting each line
+fn wrap_text(text: &str, indent: &str, width: usize) -> Vec<String> {
+    let wrap_opts = textwrap::Options::new(width)
+        .initial_indent(indent)
+        .subsequent_indent(indent);
+    textwrap::wrap(text, wrap_opts)
+        .into_iter()
+        .map(|line| line.into_owned())
+        .collect()
+}
+
+const CODE_FENCE: &str = "```";
+const CODE_GUTTER: &str = "│ ";
+
+fn code_style() -> Style {
+    Style::default().bg(Color::Rgb(40, 40, 40))
+}
+
+/// Part of a message text
+#[derive(Debug, PartialEq, Eq)]
+enum TextBlock<'a> {
+    Text(&'a str),
+    /// Lines between the fences of a code block
+    Code(&'a str),
+}
+
+/// Splits the text into code blocks fenced by triple backticks and the text around them
+///
+/// Fences must be at the beginning of a line. The language after an opening fence is ignored, and
+/// an opening fence without a closing one does not start a code block. Anything after a closing
+/// fence in the same line (e.g. reactions) is kept as text.
+fn split_code_blocks(text: &str) -> Vec<TextBlock<'_>> {
+    let mut blocks = Vec::new();
+    let mut rest = text;
+    while let Some(open) = find_code_fence(rest, 0) {
+        let Some(code_start) = rest[open..].find('\n').map(|idx| open + idx + 1) else {
+            break;
+        };
+        let Some(close) = find_code_fence(rest, code_start) else {
+            break;
+        };
+        if open > 0 {
+            // without the line break before the fence
+            blocks.push(TextBlock::Text(&rest[..open - 1]));
+        }
+        let code = &rest[code_start..close];
+        blocks.push(TextBlock::Code(code.strip_suffix('\n').unwrap_or(code)));
+
+        let fence_end = close + rest[close..].find(CODE_FENCE).unwrap_or(0) + CODE_FENCE.len();
+        let after_fence = rest[fence_end..].trim_start_matches(' ');
+        rest = after_fence.strip_prefix('\n').unwrap_or(after_fence);
+    }
+    if !rest.is_empty() || blocks.is_empty() {
+        blocks.push(TextBlock::Text(rest));
+    }
+    blocks
+}
+
+/// Byte index of the first line at or after `from` starting with a code fence
+///
+/// `from` must be the beginning of a line.
+fn find_code_fence(text: &str, from: usize) -> Option<usize> {
+    let mut line_start = from;
+    for line in text[from..].split_inclusive('\n') {
+        if line.trim_start().starts_with(CODE_FENCE) {
+            return Some(line_start);
+        }
+        line_start += line.len();
+    }
+    None
+}
+
+/// Hard-wraps the lines of a code block at the width
+///
+/// In contrast to [`wrap_text`], the indentation and spacing of the code is preserved. Each line
+/// is prefixed with a gutter marking the code block.
+fn wrap_code(code: &str, indent: &str, width: usize) -> Vec<String> {
+    let indent = format!("{indent}{CODE_GUTTER}");
+    let code_width = width.saturating_sub(indent.width()).max(1);
+    let mut lines = Vec::new();
+    for code_line in code.lines() {
+        let code_line = code_line.replace('\t', "    ");
+        let mut line = indent.clone();
+        let mut line_width = 0;
+        for c in code_line.trim_end().chars() {
+            let c_width = c.width().unwrap_or(0);
+            if line_width > 0 && line_width + c_width > code_width {
+                lines.push(std::mem::replace(&mut line, indent.clone()));
+                line_width = 0;
+            }
+            line.push(c);
+            line_width += c_width;
+        }
+        lines.push(line);
+    }
+    lines
+}
+
 fn replace_mentions(msg: &Message, names: &NameResolver, text: String) -> String {
     if msg.body_ranges.is_empty() {
         return text;
     }
+    if names.mentions_all(msg) {
+        // show an expanded `@all` as sent instead of the names of all members
+        let expanded = vec!["￼"; msg.body_ranges.len()].join(" ");
+        if text.contains(&expanded) {
+            return text.replacen(&expanded, MENTION_ALL, 1);
+        }
+    }
 
     let ac = aho_corasick::AhoCorasickBuilder::new()
         .build(std::iter::repeat("￼").take(msg.body_ranges.len())) // TODO: cache
@@ -687,12 +1871,33 @@ fn add_reactions(msg: &Message, out: &mut dyn fmt::Write) {
     }
 }
 
+/// Notes about the reactions of others to our message, e.g. `Alice reacted ❤️ to your message`
+fn reaction_notes(names: &NameResolver, msg: &Message) -> Vec<String> {
+    if msg.from_id != names.user_id() {
+        return Vec::new();
+    }
+    msg.reactions
+        .iter()
+        .filter(|(from_id, _)| *from_id != msg.from_id)
+        .map(|(from_id, emoji)| {
+            let (name, _) = names.resolve(*from_id);
+            format!("{name} reacted {emoji} to your message")
+        })
+        .collect()
+}
+
 fn add_edited(msg: &Message, out: &mut dyn fmt::Write) {
     if msg.edited {
         write!(out, " [edited]").expect("formatting edited failed")
     }
 }
 
+fn add_forwarded(msg: &Message, out: &mut dyn fmt::Write) {
+    if msg.forwarded {
+        write!(out, " [forwarded]").expect("formatting forwarded failed")
+    }
+}
+
 fn help_commands<'a>() -> Vec<Line<'a>> {
     let commands = <Command as strum::IntoEnumIterator>::iter()
         .map(|cmd| {
@@ -718,18 +1923,16 @@ fn help_commands<'a>() -> Vec<Line<'a>> {
     v
 }
 
-fn bindings(app: &App) -> Vec<Line> {
-    [
-        WindowMode::Normal,
-        WindowMode::Anywhere,
-        WindowMode::Help,
-        WindowMode::ChannelModal,
-        WindowMode::Multiline,
-        WindowMode::MessageSelected,
-    ]
-    .iter()
-    .map(|mode| bindings_mode(app, mode))
-    .concat()
+/// Active key bindings (incl. the configured ones) grouped by mode
+fn bindings<'a>(app: &App) -> Vec<Line<'a>> {
+    <WindowMode as strum::IntoEnumIterator>::iter()
+        .filter(|mode| {
+            app.mode_keybindings
+                .get(mode)
+                .is_some_and(|kb| !kb.is_empty())
+        })
+        .map(|mode| bindings_mode(app, &mode))
+        .concat()
 }
 
 fn bindings_mode<'a>(app: &App, mode: &WindowMode) -> Vec<Line<'a>> {
@@ -770,10 +1973,20 @@ fn bindings_mode<'a>(app: &App, mode: &WindowMode) -> Vec<Line<'a>> {
 }
 
 fn draw_help(f: &mut Frame, app: &mut App, area: Rect) {
-    let mut command_bindings = help_commands();
-    command_bindings.extend(bindings(app));
+    let mut command_bindings = bindings(app);
+    command_bindings.push(Line::default());
+    command_bindings.extend(help_commands());
+
+    // prevent overscrolling
+    let page_height = area.height.saturating_sub(2);
+    let max_scroll = u16::try_from(command_bindings.len())
+        .unwrap_or(u16::MAX)
+        .saturating_sub(page_height);
+    app.help_page_height = page_height;
+    app.help_scroll.0 = app.help_scroll.0.min(max_scroll);
+
     let command_bindings = Paragraph::new(Text::from(command_bindings))
-        .block(Block::bordered().title("Available commands and configured shortcuts"))
+        .block(Block::bordered().title("Configured shortcuts and available commands (esc: close)"))
         .scroll(app.help_scroll);
     f.render_widget(command_bindings, area);
 }
@@ -784,6 +1997,193 @@ fn displayed_quote(names: &NameResolver, quote: &Message) -> Option<String> {
     Some(replace_mentions(quote, names, text))
 }
 
+/// Draws the screen shown while the data is loaded at startup, with the current step
+pub fn draw_loading(f: &mut Frame, step: &str) {
+    let area = f.area();
+    let text = Paragraph::new(format!("{step}…"))
+        .block(
+            Block::bordered()
+                .title("gurk")
+                .padding(Padding::top(area.height.saturating_sub(2) / 2)),
+        )
+        .centered();
+    f.render_widget(text, area);
+}
+
+/// Draws the first-run setup wizard
+pub(crate) fn draw_setup(f: &mut Frame, wizard: &SetupWizard) {
+    if wizard.step == SetupStep::Linking {
+        draw_linking(f, wizard);
+        return;
+    }
+    let area = centered_rect(60, 60, f.area());
+    let (number, title) = match wizard.step {
+        SetupStep::Name => (1, "Your name"),
+        SetupStep::DataDir => (2, "Data directory"),
+        SetupStep::Preferences => (3, "Preferences"),
+        _ => (4, "Linking"),
+    };
+    let input = match wizard.step {
+        SetupStep::Name => Some(&wizard.name),
+        SetupStep::DataDir => Some(&wizard.data_dir),
+        _ => None,
+    };
+    let chunks = Layout::default()
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(if input.is_some() { 1 + 2 } else { 0 }),
+        ])
+        .direction(Direction::Vertical)
+        .split(area);
+
+    let mut lines = match wizard.step {
+        SetupStep::Name => vec![
+            Line::from("Welcome to gurk! Your name is shown in the chats."),
+            Line::from(""),
+            Line::from("Leave it empty to use the name of your Signal profile."),
+        ],
+        SetupStep::DataDir => vec![
+            Line::from("The messages and the Signal database of this device are stored in"),
+            Line::from("this directory."),
+        ],
+        SetupStep::Preferences => {
+            let mut lines: Vec<_> = wizard
+                .preferences()
+                .into_iter()
+                .enumerate()
+                .map(|(idx, (label, enabled))| {
+                    let line = Line::from(format!("[{}] {label}", if enabled { "x" } else { " " }));
+                    if idx == wizard.selected {
+                        line.style(Style::default().add_modifier(Modifier::REVERSED))
+                    } else {
+                        line
+                    }
+                })
+                .collect();
+            lines.push(Line::from(""));
+            lines.push(Line::from(
+                "Space: toggle, Enter: link this device with the Signal app on your phone",
+            ));
+            lines
+        }
+        _ => match &wizard.user {
+            Some(user) => vec![
+                Line::from(format!("Linked as {} ({}).", user.name, user.phone_number)),
+                Line::from(""),
+                Line::from("Press Enter to start gurk."),
+            ],
+            None => vec![],
+        },
+    };
+    if let Some(error) = wizard.error.as_ref() {
+        lines.push(Line::from(""));
+        lines.push(Line::styled(
+            format!("Error: {error}"),
+            Style::default().fg(Color::Red),
+        ));
+    }
+    let text = Paragraph::new(Text::from(lines))
+        .wrap(Wrap { trim: false })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Setup {number}/4: {title}")),
+        );
+    f.render_widget(text, chunks[0]);
+
+    if let Some(input) = input {
+        let paragraph = Paragraph::new(Text::from(input.data.clone())).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Enter: next, Esc: back"),
+        );
+        f.render_widget(paragraph, chunks[1]);
+        f.set_cursor_position((
+            chunks[1].x + input.cursor.col as u16 + 1,
+            chunks[1].y + input.cursor.line as u16 + 1,
+        ));
+    }
+}
+
+/// Draws the QR code for linking this device and the progress of linking
+fn draw_linking(f: &mut Frame, wizard: &SetupWizard) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Setup 4/4: Linking");
+    let area = block.inner(f.area());
+    f.render_widget(block, f.area());
+
+    let mut header = vec![
+        Line::from(format!(
+            "Linking this device as \"{}\".",
+            wizard.device_name
+        )),
+        Line::from(""),
+    ];
+    let mut footer = vec![];
+    match (&wizard.error, &wizard.link_url) {
+        (Some(error), _) => {
+            header.push(Line::styled(
+                format!("Error: {error}"),
+                Style::default().fg(Color::Red),
+            ));
+            header.push(Line::from("Enter: retry, Esc: quit"));
+        }
+        (None, None) => header.push(Line::from("Requesting a link from the Signal server...")),
+        (None, Some(url)) => {
+            header.push(Line::from(
+                "In the Signal app on your phone, open Settings > Linked devices, tap on \"+\" \
+                and scan the QR code:",
+            ));
+            footer.push(Line::from(
+                "If the QR code does not fit into the terminal, turn the link into a QR code \
+                elsewhere:",
+            ));
+            footer.push(Line::styled(url.clone(), Style::default().fg(Color::Cyan)));
+            footer.push(Line::from(""));
+            footer.push(Line::from(
+                wizard
+                    .link_notice
+                    .clone()
+                    .unwrap_or_else(|| "c: copy the link, Esc: quit".to_string()),
+            ));
+        }
+    }
+    let qr = match (&wizard.error, &wizard.link_qr) {
+        (None, Some(qr)) => qr.as_slice(),
+        _ => &[],
+    };
+
+    let chunks = Layout::default()
+        .constraints([
+            Constraint::Length(4),
+            Constraint::Length(qr.len() as u16),
+            Constraint::Min(0),
+        ])
+        .direction(Direction::Vertical)
+        .split(area);
+    f.render_widget(
+        Paragraph::new(Text::from(header)).wrap(Wrap { trim: false }),
+        chunks[0],
+    );
+    // the light modules are drawn, so the colors are fixed regardless of the terminal theme
+    let qr_style = Style::default().fg(Color::White).bg(Color::Black);
+    let qr_lines: Vec<_> = qr.iter().map(|line| Line::raw(line.clone())).collect();
+    let qr_width = qr.first().map_or(0, |line| line.chars().count() as u16);
+    let qr_area = Rect {
+        width: qr_width.min(chunks[1].width),
+        ..chunks[1]
+    };
+    f.render_widget(
+        Paragraph::new(Text::from(qr_lines)).style(qr_style),
+        qr_area,
+    );
+    f.render_widget(
+        Paragraph::new(Text::from(footer)).wrap(Wrap { trim: false }),
+        chunks[2],
+    );
+}
+
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
@@ -850,6 +2250,7 @@ mod tests {
             send_failed: Default::default(),
             edit: Default::default(),
             edited: Default::default(),
+            forwarded: Default::default(),
         }
     }
 
@@ -868,13 +2269,15 @@ mod tests {
             HEIGHT,
             ShowReceipt::Never,
             false,
+            ClockFormat::H24,
+            &Palette::default(),
         );
 
         let expected = ListItem::new(Text::from(vec![
             Line::from(vec![
                 Span::styled("", Style::default().fg(Color::Yellow)),
                 Span::styled(
-                    display_time(msg.arrived_at),
+                    display_time(msg.arrived_at, ClockFormat::H24),
                     Style::default().fg(Color::Yellow),
                 ),
                 Span::styled("boxdot", Style::default().fg(Color::Green)),
@@ -904,13 +2307,15 @@ mod tests {
             HEIGHT,
             ShowReceipt::Never,
             false,
+            ClockFormat::H24,
+            &Palette::default(),
         );
 
         let expected = ListItem::new(Text::from(vec![
             Line::from(vec![
                 Span::styled("", Style::default().fg(Color::Yellow)),
                 Span::styled(
-                    display_time(msg.arrived_at),
+                    display_time(msg.arrived_at, ClockFormat::H24),
                     Style::default().fg(Color::Yellow),
                 ),
                 Span::styled("boxdot", Style::default().fg(Color::Green)),
@@ -936,12 +2341,22 @@ mod tests {
             ..test_message()
         };
         let show_receipt = ShowReceipt::from_msg(&msg, USER_ID, true);
-        let rendered = display_message(&names, &msg, PREFIX, WIDTH, HEIGHT, show_receipt, false);
+        let rendered = display_message(
+            &names,
+            &msg,
+            PREFIX,
+            WIDTH,
+            HEIGHT,
+            show_receipt,
+            false,
+            ClockFormat::H24,
+            &Palette::default(),
+        );
 
         let expected = ListItem::new(Text::from(vec![Line::from(vec![
             Span::styled("○ ", Style::default().fg(Color::Yellow)),
             Span::styled(
-                display_time(msg.arrived_at),
+                display_time(msg.arrived_at, ClockFormat::H24),
                 Style::default().fg(Color::Yellow),
             ),
             Span::styled("boxdot", Style::default().fg(Color::Green)),
@@ -960,12 +2375,22 @@ mod tests {
             ..test_message()
         };
         let show_receipt = ShowReceipt::from_msg(&msg, USER_ID, true);
-        let rendered = display_message(&names, &msg, PREFIX, WIDTH, HEIGHT, show_receipt, false);
+        let rendered = display_message(
+            &names,
+            &msg,
+            PREFIX,
+            WIDTH,
+            HEIGHT,
+            show_receipt,
+            false,
+            ClockFormat::H24,
+            &Palette::default(),
+        );
 
         let expected = ListItem::new(Text::from(vec![Line::from(vec![
             Span::styled("◉ ", Style::default().fg(Color::Yellow)),
             Span::styled(
-                display_time(msg.arrived_at),
+                display_time(msg.arrived_at, ClockFormat::H24),
                 Style::default().fg(Color::Yellow),
             ),
             Span::styled("boxdot", Style::default().fg(Color::Green)),
@@ -984,12 +2409,22 @@ mod tests {
             ..test_message()
         };
         let show_receipt = ShowReceipt::from_msg(&msg, USER_ID, true);
-        let rendered = display_message(&names, &msg, PREFIX, WIDTH, HEIGHT, show_receipt, false);
+        let rendered = display_message(
+            &names,
+            &msg,
+            PREFIX,
+            WIDTH,
+            HEIGHT,
+            show_receipt,
+            false,
+            ClockFormat::H24,
+            &Palette::default(),
+        );
 
         let expected = ListItem::new(Text::from(vec![Line::from(vec![
             Span::styled("● ", Style::default().fg(Color::Yellow)),
             Span::styled(
-                display_time(msg.arrived_at),
+                display_time(msg.arrived_at, ClockFormat::H24),
                 Style::default().fg(Color::Yellow),
             ),
             Span::styled("boxdot", Style::default().fg(Color::Green)),
@@ -1008,12 +2443,22 @@ mod tests {
             ..test_message()
         };
         let show_receipt = ShowReceipt::from_msg(&msg, USER_ID, false);
-        let rendered = display_message(&names, &msg, PREFIX, WIDTH, HEIGHT, show_receipt, false);
+        let rendered = display_message(
+            &names,
+            &msg,
+            PREFIX,
+            WIDTH,
+            HEIGHT,
+            show_receipt,
+            false,
+            ClockFormat::H24,
+            &Palette::default(),
+        );
 
         let expected = ListItem::new(Text::from(vec![Line::from(vec![
             Span::styled("", Style::default().fg(Color::Yellow)),
             Span::styled(
-                display_time(msg.arrived_at),
+                display_time(msg.arrived_at, ClockFormat::H24),
                 Style::default().fg(Color::Yellow),
             ),
             Span::styled("boxdot", Style::default().fg(Color::Green)),
@@ -1034,12 +2479,22 @@ mod tests {
             ..test_message()
         };
         let show_receipt = ShowReceipt::from_msg(&msg, USER_ID, true);
-        let rendered = display_message(&names, &msg, PREFIX, WIDTH, HEIGHT, show_receipt, false);
+        let rendered = display_message(
+            &names,
+            &msg,
+            PREFIX,
+            WIDTH,
+            HEIGHT,
+            show_receipt,
+            false,
+            ClockFormat::H24,
+            &Palette::default(),
+        );
 
         let expected = ListItem::new(Text::from(vec![Line::from(vec![
             Span::styled("  ", Style::default().fg(Color::Yellow)),
             Span::styled(
-                display_time(msg.arrived_at),
+                display_time(msg.arrived_at, ClockFormat::H24),
                 Style::default().fg(Color::Yellow),
             ),
             Span::styled("boxdot", Style::default().fg(Color::Green)),
@@ -1072,13 +2527,23 @@ mod tests {
             ..test_message()
         };
         let show_receipt = ShowReceipt::from_msg(&msg, USER_ID, true);
-        let rendered = display_message(&names, &msg, PREFIX, WIDTH, HEIGHT, show_receipt, false);
+        let rendered = display_message(
+            &names,
+            &msg,
+            PREFIX,
+            WIDTH,
+            HEIGHT,
+            show_receipt,
+            false,
+            ClockFormat::H24,
+            &Palette::default(),
+        );
 
         let expected = ListItem::new(Text::from(vec![
             Line::from(vec![
                 Span::styled("  ", Style::default().fg(Color::Yellow)),
                 Span::styled(
-                    display_time(msg.arrived_at),
+                    display_time(msg.arrived_at, ClockFormat::H24),
                     Style::default().fg(Color::Yellow),
                 ),
                 Span::styled("boxdot", Style::default().fg(Color::Green)),
@@ -1089,4 +2554,251 @@ mod tests {
         ]));
         assert_eq!(rendered, Some(expected));
     }
+
+    #[test]
+    fn test_display_wide_characters_wrap_by_width() {
+        let names = name_resolver();
+        let msg = Message {
+            message: Some("漢字".repeat(15)),
+            ..test_message()
+        };
+        let rendered = display_message(
+            &names,
+            &msg,
+            PREFIX,
+            WIDTH,
+            HEIGHT,
+            ShowReceipt::Never,
+            false,
+            ClockFormat::H24,
+            &Palette::default(),
+        );
+
+        // 42 columns next to the prefix fit 21 double width characters
+        let expected = ListItem::new(Text::from(vec![
+            Line::from(vec![
+                Span::styled("", Style::default().fg(Color::Yellow)),
+                Span::styled(
+                    display_time(msg.arrived_at, ClockFormat::H24),
+                    Style::default().fg(Color::Yellow),
+                ),
+                Span::styled("boxdot", Style::default().fg(Color::Green)),
+                Span::raw(": "),
+                Span::raw(format!("{}漢", "漢字".repeat(10))),
+            ]),
+            Line::from(vec![Span::raw(format!("{PREFIX}字{}", "漢字".repeat(4)))]),
+        ]));
+        assert_eq!(rendered, Some(expected));
+    }
+
+    #[test]
+    fn test_display_message_in_narrow_pane() {
+        let names = name_resolver();
+        let msg = Message {
+            message: Some("Hello, World!".into()),
+            ..test_message()
+        };
+        let rendered = display_message(
+            &names,
+            &msg,
+            PREFIX,
+            PREFIX.len() + MIN_TEXT_WIDTH - 1,
+            HEIGHT,
+            ShowReceipt::Never,
+            false,
+            ClockFormat::H24,
+            &Palette::default(),
+        );
+
+        let expected = ListItem::new(Text::from(vec![
+            Line::from(vec![
+                Span::styled("", Style::default().fg(Color::Yellow)),
+                Span::styled(
+                    display_time(msg.arrived_at, ClockFormat::H24),
+                    Style::default().fg(Color::Yellow),
+                ),
+                Span::styled("boxdot", Style::default().fg(Color::Green)),
+                Span::raw(": "),
+            ]),
+            Line::from(vec![Span::raw("  Hello, World!")]),
+        ]));
+        assert_eq!(rendered, Some(expected));
+    }
+
+    #[test]
+    fn test_split_code_blocks() {
+        use TextBlock::*;
+        assert_eq!(split_code_blocks("no code"), [Text("no code")]);
+        assert_eq!(
+            split_code_blocks("Look:\n```rust\nfn main() {\n    todo!()\n}\n```\nnice"),
+            [
+                Text("Look:"),
+                Code("fn main() {\n    todo!()\n}"),
+                Text("nice")
+            ]
+        );
+        assert_eq!(
+            split_code_blocks("```\nls -la\n``` [👍]"),
+            [Code("ls -la"), Text("[👍]")]
+        );
+        // unclosed fence
+        assert_eq!(split_code_blocks("```\nls -la"), [Text("```\nls -la")]);
+    }
+
+    #[test]
+    fn test_reaction_notes_on_own_messages() {
+        let (app, _events, _sent_messages) = crate::app::tests::test_app();
+        let names = NameResolver::compute(&app, []);
+        let other_id = Uuid::new_v4();
+        let msg = Message {
+            from_id: app.user_id,
+            reactions: vec![
+                (app.user_id, "👍".to_string()),
+                (other_id, "❤️".to_string()),
+            ],
+            ..test_message()
+        };
+        assert_eq!(
+            reaction_notes(&names, &msg),
+            [format!("{other_id} reacted ❤️ to your message")]
+        );
+
+        let msg = Message {
+            from_id: other_id,
+            ..msg
+        };
+        assert!(reaction_notes(&names, &msg).is_empty());
+    }
+
+    #[test]
+    fn test_display_code_block_preserves_indentation() {
+        let names = name_resolver();
+        let msg = Message {
+            message: Some("Look:\n```\nif x {\n    y();\n}\n```".into()),
+            ..test_message()
+        };
+        let rendered = display_message(
+            &names,
+            &msg,
+            PREFIX,
+            WIDTH,
+            HEIGHT,
+            ShowReceipt::Never,
+            false,
+            ClockFormat::H24,
+            &Palette::default(),
+        );
+
+        let expected = ListItem::new(Text::from(vec![
+            Line::from(vec![
+                Span::styled("", Style::default().fg(Color::Yellow)),
+                Span::styled(
+                    display_time(msg.arrived_at, ClockFormat::H24),
+                    Style::default().fg(Color::Yellow),
+                ),
+                Span::styled("boxdot", Style::default().fg(Color::Green)),
+                Span::raw(": "),
+                Span::raw("Look:"),
+            ]),
+            Line::from(Span::styled(format!("{PREFIX}│ if x {{"), code_style())),
+            Line::from(Span::styled(format!("{PREFIX}│     y();"), code_style())),
+            Line::from(Span::styled(format!("{PREFIX}│ }}"), code_style())),
+        ]));
+        assert_eq!(rendered, Some(expected));
+    }
+
+    #[test]
+    fn test_wrap_code_breaks_long_lines() {
+        assert_eq!(wrap_code("  abcdef", "", 6), ["│   ab", "│ cdef"]);
+    }
+
+    #[test]
+    fn test_display_last_activity() {
+        let now = 1642334397421;
+        assert_eq!(display_last_activity(None, now), "no messages");
+        assert_eq!(display_last_activity(Some(now), now), "last message today");
+        assert_eq!(
+            display_last_activity(Some(now - DAY_MSEC), now),
+            "last message yesterday"
+        );
+        assert_eq!(
+            display_last_activity(Some(now - 5 * DAY_MSEC), now),
+            "last message 5 days ago"
+        );
+    }
+
+    #[test]
+    fn test_draw() {
+        let (mut app, _events, _sent_messages) = crate::app::tests::test_app();
+        let mut terminal =
+            ratatui::Terminal::new(ratatui::backend::TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|f| draw(f, &mut app)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("test"));
+        assert!(screen.contains("First message"));
+    }
+
+    #[test]
+    fn test_draw_reads_messages_only_while_reading() {
+        let (mut app, _events, _sent_messages) = crate::app::tests::test_app();
+        let channel_id = app.channels.items[0];
+        let mut message = Message::new(Uuid::new_v4(), Some("hi".into()), [], 1, Vec::new());
+        message.receipt = Receipt::Delivered;
+        app.storage.store_message(channel_id, message);
+        let receipt = |app: &App| {
+            app.storage
+                .message(MessageId::new(channel_id, 1))
+                .unwrap()
+                .receipt
+        };
+        let mut terminal =
+            ratatui::Terminal::new(ratatui::backend::TestBackend::new(80, 24)).unwrap();
+
+        app.config.mark_read.when_unfocused = false;
+        app.on_focus(false);
+        terminal.draw(|f| draw(f, &mut app)).unwrap();
+        assert_eq!(receipt(&app), Receipt::Delivered);
+
+        app.on_focus(true);
+        terminal.draw(|f| draw(f, &mut app)).unwrap();
+        assert_eq!(receipt(&app), Receipt::Read);
+    }
+
+    #[test]
+    fn test_draw_copies_unchanged_panes() {
+        let (mut app, _events, _sent_messages) = crate::app::tests::test_app();
+        let mut terminal =
+            ratatui::Terminal::new(ratatui::backend::TestBackend::new(80, 24)).unwrap();
+        let mut draw_screen = |app: &mut App| {
+            terminal.draw(|f| draw(f, app)).unwrap();
+            let buffer = terminal.backend().buffer();
+            buffer
+                .content()
+                .iter()
+                .map(|cell| cell.symbol())
+                .collect::<String>()
+        };
+        assert!(draw_screen(&mut app).contains("First message"));
+        assert!(!app.dirty.is_any_dirty());
+
+        let channel_id = app.channels.items[0];
+        let arrived_at = app.messages[&channel_id].items[0];
+        let mut message = app
+            .storage
+            .message(MessageId::new(channel_id, arrived_at))
+            .unwrap()
+            .into_owned();
+        message.message = Some("Changed message".to_string());
+        app.storage.store_message(channel_id, message);
+
+        assert!(draw_screen(&mut app).contains("First message"));
+        app.dirty.mark(Pane::Messages);
+        assert!(draw_screen(&mut app).contains("Changed message"));
+    }
 }
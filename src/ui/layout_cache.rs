@@ -0,0 +1,147 @@
+//! Cache of the wrapped messages, so that redrawing a channel does not wrap its messages again
+
+use std::collections::BTreeMap;
+
+use ratatui::widgets::ListItem;
+
+use crate::config::ClockFormat;
+use crate::data::Message;
+use crate::storage::MessageId;
+
+use super::theme::Palette;
+
+/// Maximum number of cached layouts, before the ones not drawn recently are dropped
+const MAX_LAYOUTS: usize = 2048;
+
+/// Options a message is laid out with, apart from the width
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LayoutOptions {
+    pub prefix_width: usize,
+    /// Height of the viewport, which limits the height of a message
+    pub height: usize,
+    pub show_receipts: bool,
+    pub colored_messages: bool,
+    pub clock: ClockFormat,
+}
+
+#[derive(Debug)]
+struct Layout {
+    /// The message as it was laid out; the layout is stale when the message changed
+    message: Message,
+    options: LayoutOptions,
+    item: Option<ListItem<'static>>,
+    drawn_at_frame: u64,
+}
+
+/// Layouts of the drawn messages keyed by message and width
+///
+/// A layout is reused as long as the message and the options it was laid out with are unchanged.
+/// Changes which are not visible in the message, i.e. the names of the contacts and the palette,
+/// clear the whole cache.
+#[derive(Debug, Default)]
+pub(crate) struct LayoutCache {
+    layouts: BTreeMap<(MessageId, usize), Layout>,
+    palette: Option<Palette>,
+    frame: u64,
+}
+
+impl LayoutCache {
+    /// Starts drawing a new frame with the palette
+    pub fn next_frame(&mut self, palette: &Palette) {
+        if self.palette.as_ref() != Some(palette) {
+            self.clear();
+            self.palette = Some(palette.clone());
+        }
+        self.frame += 1;
+    }
+
+    /// Returns the layout of the message, laying it out with `layout` if not cached
+    pub fn get_or_insert_with(
+        &mut self,
+        message_id: MessageId,
+        message: &Message,
+        width: usize,
+        options: LayoutOptions,
+        layout: impl FnOnce() -> Option<ListItem<'static>>,
+    ) -> Option<ListItem<'static>> {
+        let frame = self.frame;
+        if let Some(cached) = self.layouts.get_mut(&(message_id, width)) {
+            if cached.options == options && &cached.message == message {
+                cached.drawn_at_frame = frame;
+                return cached.item.clone();
+            }
+        }
+        if self.layouts.len() >= MAX_LAYOUTS {
+            // keep the layouts of the current and the previous frame
+            self.layouts
+                .retain(|_, layout| layout.drawn_at_frame + 1 >= frame);
+        }
+        let item = layout();
+        self.layouts.insert(
+            (message_id, width),
+            Layout {
+                message: message.clone(),
+                options,
+                item: item.clone(),
+                drawn_at_frame: frame,
+            },
+        );
+        item
+    }
+
+    pub fn clear(&mut self) {
+        self.layouts.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.layouts.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use crate::data::ChannelId;
+
+    use super::*;
+
+    fn options() -> LayoutOptions {
+        LayoutOptions {
+            prefix_width: 10,
+            height: 20,
+            show_receipts: true,
+            colored_messages: false,
+            clock: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_layout_is_reused_until_message_changes() {
+        let mut cache = LayoutCache::default();
+        let message_id = MessageId::new(ChannelId::User(Default::default()), 1);
+        let mut message = Message::new(Default::default(), Some("hello".into()), vec![], 1, vec![]);
+        let layouts = Cell::new(0);
+        let layout = || {
+            layouts.set(layouts.get() + 1);
+            Some(ListItem::new("hello"))
+        };
+
+        cache.get_or_insert_with(message_id, &message, 80, options(), layout);
+        cache.get_or_insert_with(message_id, &message, 80, options(), layout);
+        assert_eq!(layouts.get(), 1);
+
+        // another width is laid out separately
+        cache.get_or_insert_with(message_id, &message, 40, options(), layout);
+        assert_eq!(layouts.get(), 2);
+        assert_eq!(cache.len(), 2);
+
+        message.reactions.push((Default::default(), "👍".into()));
+        cache.get_or_insert_with(message_id, &message, 80, options(), layout);
+        assert_eq!(layouts.get(), 3);
+
+        cache.clear();
+        cache.get_or_insert_with(message_id, &message, 80, options(), layout);
+        assert_eq!(layouts.get(), 4);
+    }
+}
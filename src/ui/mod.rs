@@ -4,9 +4,25 @@
 
 mod coords;
 mod draw;
+mod layout_cache;
 mod name_resolver;
+mod panes;
+mod terminal;
+mod theme;
 
 pub use coords::coords_within_channels_view;
-pub use draw::draw;
+pub(crate) use draw::draw_setup;
+pub use draw::{draw, draw_loading};
+pub(crate) use layout_cache::LayoutCache;
+pub(crate) use name_resolver::sender_palette;
+pub(crate) use panes::PaneBuffers;
+pub use panes::{DirtyPanes, Pane};
+pub use terminal::TerminalCapabilities;
+pub use theme::{query_background, Palette, Theme};
 
-pub const CHANNEL_VIEW_RATIO: u32 = 4;
+/// Height of the channel list above the chat in percent, if the layout is stacked
+pub const STACKED_SIDEBAR_HEIGHT: u16 = 30;
+/// Width of the panel with the details of the selected channel
+pub const CHANNEL_INFO_WIDTH: u16 = 32;
+/// Height of the status bar below the channels and the chat
+pub const STATUS_BAR_HEIGHT: u16 = 1;
@@ -1,11 +1,15 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::Context;
 
 use ratatui::style::Color;
 use unicode_width::UnicodeWidthStr;
 use uuid::Uuid;
 
 use crate::app::App;
+use crate::data::{ChannelId, Message};
 use crate::storage::MessageId;
 
 /// Once constructed for a channel, resolves uuid to name and color
@@ -13,8 +17,11 @@ use crate::storage::MessageId;
 /// Construction takes time, lookup (resolving) is fast
 pub struct NameResolver<'a> {
     app: Option<&'a App>,
+    user_id: Uuid,
     names_and_colors: HashMap<Uuid, (String, Color)>,
     max_name_width: usize,
+    /// Members of the group channel; empty for direct channels
+    group_members: Vec<Uuid>,
 }
 
 impl<'a> NameResolver<'a> {
@@ -24,7 +31,16 @@ impl<'a> NameResolver<'a> {
     ) -> Self {
         let mut names_and_colors: HashMap<Uuid, (String, Color)> = Default::default();
         names_and_colors.insert(app.user_id, app.name_and_color(app.user_id));
+        let mut group_members = None;
         for message_id in relevant_message_ids {
+            if !message_id.channel_id.is_user() {
+                group_members.get_or_insert_with(|| {
+                    app.storage
+                        .channel(message_id.channel_id)
+                        .and_then(|channel| Some(channel.group_data.as_ref()?.members.clone()))
+                        .unwrap_or_default()
+                });
+            }
             if let Some(message) = app.storage.message(message_id) {
                 names_and_colors
                     .entry(message.from_id)
@@ -39,8 +55,9 @@ impl<'a> NameResolver<'a> {
                         let (_, self_color) =
                             names_and_colors.get_mut(&app.user_id).expect("logic error");
                         if self_color == &contact_color {
-                            if let Some(idx) = USER_COLORS.iter().position(|&c| c == *self_color) {
-                                *self_color = USER_COLORS[(idx + 1) % USER_COLORS.len()];
+                            let palette = &app.sender_colors;
+                            if let Some(idx) = palette.iter().position(|&c| c == *self_color) {
+                                *self_color = palette[(idx + 1) % palette.len()];
                             }
                         }
                         break; // amortize direct channel
@@ -57,8 +74,10 @@ impl<'a> NameResolver<'a> {
 
         Self {
             app: Some(app),
+            user_id: app.user_id,
             names_and_colors,
             max_name_width,
+            group_members: group_members.unwrap_or_default(),
         }
     }
 
@@ -68,11 +87,27 @@ impl<'a> NameResolver<'a> {
             .get(&id)
             .map(|(name, color)| (name.into(), *color))
             .unwrap_or_else(|| {
-                let name = self.app.expect("logic error").name_by_id_cached(id).into();
-                (name, Color::Magenta)
+                let app = self.app.expect("logic error");
+                (app.name_by_id_cached(id).into(), app.sender_color(id))
             })
     }
 
+    /// Id of our user
+    pub(super) fn user_id(&self) -> Uuid {
+        self.user_id
+    }
+
+    /// Whether the message mentions exactly the group members except its sender, like an
+    /// expanded `@all`
+    pub(super) fn mentions_all(&self, message: &Message) -> bool {
+        let others = self
+            .group_members
+            .iter()
+            .filter(|&&id| id != message.from_id)
+            .count();
+        message.body_ranges.len() == others && message.mentions_all(&self.group_members)
+    }
+
     /// Returns the char width of the longest name
     pub(super) fn max_name_width(&self) -> usize {
         self.max_name_width
@@ -83,8 +118,10 @@ impl<'a> NameResolver<'a> {
     pub fn single_user(user_id: Uuid, username: String, color: Color) -> NameResolver<'static> {
         NameResolver {
             app: None,
+            user_id,
             names_and_colors: [(user_id, (username, color))].into_iter().collect(),
             max_name_width: 6,
+            group_members: Vec::new(),
         }
     }
 }
@@ -92,9 +129,25 @@ impl<'a> NameResolver<'a> {
 impl App {
     fn name_and_color(&self, id: Uuid) -> (String, Color) {
         let name = self.name_by_id_cached(id);
-        let color = user_color(&name);
         let name = displayed_name(name, self.config.first_name_only);
-        (name, color)
+        (name, self.sender_color(id))
+    }
+
+    /// Stable color of the sender from the configured palette
+    pub(crate) fn sender_color(&self, id: Uuid) -> Color {
+        sender_color(id, &self.sender_colors)
+    }
+
+    /// Stable color of the channel, which is the color of the contact for direct channels
+    pub(crate) fn channel_color(&self, channel_id: ChannelId) -> Color {
+        match channel_id {
+            ChannelId::User(id) => self.sender_color(id),
+            ChannelId::Group(group_id) => {
+                let mut id = [0; 16];
+                id.copy_from_slice(&group_id[..16]);
+                self.sender_color(Uuid::from_bytes(id))
+            }
+        }
     }
 }
 
@@ -107,7 +160,7 @@ fn displayed_name(name: String, first_name_only: bool) -> String {
     }
 }
 
-const USER_COLORS: &[Color] = &[
+const DEFAULT_SENDER_COLORS: &[Color] = &[
     Color::Red,
     Color::Green,
     Color::Yellow,
@@ -117,10 +170,52 @@ const USER_COLORS: &[Color] = &[
     Color::Gray,
 ];
 
-// Randomly but deterministically choose a color for a username
-fn user_color(username: &str) -> Color {
-    let idx = username
-        .bytes()
-        .fold(0, |sum, b| (sum + usize::from(b)) % USER_COLORS.len());
-    USER_COLORS[idx]
+/// Parses the palette of the sender colors from the config
+///
+/// Falls back to the default palette, if no colors are configured.
+pub(crate) fn sender_palette(colors: &[String]) -> anyhow::Result<Vec<Color>> {
+    if colors.is_empty() {
+        return Ok(DEFAULT_SENDER_COLORS.to_vec());
+    }
+    colors
+        .iter()
+        .map(|color| {
+            Color::from_str(color)
+                .ok()
+                .with_context(|| format!("invalid sender color: {color}"))
+        })
+        .collect()
+}
+
+/// Randomly but deterministically chooses a color for a sender
+///
+/// The palette must not be empty.
+fn sender_color(id: Uuid, palette: &[Color]) -> Color {
+    let idx = id.as_u128() % palette.len() as u128;
+    palette[idx as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sender_palette() {
+        assert_eq!(sender_palette(&[]).unwrap(), DEFAULT_SENDER_COLORS.to_vec());
+        let colors = ["red".to_string(), "#ffaa00".to_string(), "42".to_string()];
+        assert_eq!(
+            sender_palette(&colors).unwrap(),
+            [Color::Red, Color::Rgb(0xff, 0xaa, 0x00), Color::Indexed(42)]
+        );
+        assert!(sender_palette(&["no color".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_sender_color_is_stable() {
+        let palette = sender_palette(&[]).unwrap();
+        let id = Uuid::from_u128(10);
+        assert_eq!(sender_color(id, &palette), palette[10 % palette.len()]);
+        assert_eq!(sender_color(id, &palette), sender_color(id, &palette));
+        assert_eq!(sender_color(id, &[Color::Blue]), Color::Blue);
+    }
 }
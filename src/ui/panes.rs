@@ -0,0 +1,135 @@
+//! Tracking of the panes changed since the last frame
+//!
+//! A frame is only drawn if a pane changed. The channel list and the messages are expensive to
+//! lay out, so when they did not change, they are copied from the previous frame instead.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use strum::IntoEnumIterator;
+
+/// Maximum age of a copied pane, so that the parts of it depending on the time are updated,
+/// e.g. the muted channels whose mute expired
+const MAX_PANE_AGE: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, strum_macros::EnumIter)]
+pub enum Pane {
+    Channels,
+    Messages,
+    Input,
+    ChannelInfo,
+    StatusBar,
+}
+
+/// Panes changed since the last frame
+#[derive(Debug)]
+pub struct DirtyPanes(BTreeSet<Pane>);
+
+impl Default for DirtyPanes {
+    /// Everything is drawn in the first frame
+    fn default() -> Self {
+        Self(Pane::iter().collect())
+    }
+}
+
+impl DirtyPanes {
+    pub fn mark(&mut self, pane: Pane) {
+        self.0.insert(pane);
+    }
+
+    pub fn mark_all(&mut self) {
+        self.0.extend(Pane::iter());
+    }
+
+    pub fn is_dirty(&self, pane: Pane) -> bool {
+        self.0.contains(&pane)
+    }
+
+    /// Whether a frame has to be drawn
+    pub fn is_any_dirty(&self) -> bool {
+        !self.0.is_empty()
+    }
+
+    /// Marks everything as drawn
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// Panes of the previous frames, copied from the buffer of the frame after drawing them
+#[derive(Debug, Default)]
+pub(crate) struct PaneBuffers {
+    buffers: BTreeMap<Pane, (Buffer, Instant)>,
+}
+
+impl PaneBuffers {
+    /// Copies the drawn pane from the buffer of the frame
+    pub fn store(&mut self, pane: Pane, buf: &Buffer, area: Rect) {
+        let area = area.intersection(buf.area);
+        let mut copy = Buffer::empty(area);
+        for position in area.positions() {
+            copy[position] = buf[position].clone();
+        }
+        self.buffers.insert(pane, (copy, Instant::now()));
+    }
+
+    /// Copies the pane into the buffer of the frame, if it was drawn recently in the same area
+    ///
+    /// Returns whether the pane was copied.
+    pub fn restore(&self, pane: Pane, buf: &mut Buffer, area: Rect) -> bool {
+        match self.buffers.get(&pane) {
+            Some((copy, drawn_at))
+                if copy.area == area.intersection(buf.area)
+                    && drawn_at.elapsed() < MAX_PANE_AGE =>
+            {
+                buf.merge(copy);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::{Color, Style};
+
+    use super::*;
+
+    #[test]
+    fn test_dirty_panes() {
+        let mut dirty = DirtyPanes::default();
+        assert!(dirty.is_dirty(Pane::Messages));
+        dirty.clear();
+        assert!(!dirty.is_any_dirty());
+
+        dirty.mark(Pane::StatusBar);
+        assert!(dirty.is_any_dirty());
+        assert!(dirty.is_dirty(Pane::StatusBar));
+        assert!(!dirty.is_dirty(Pane::Messages));
+
+        dirty.mark_all();
+        assert!(Pane::iter().all(|pane| dirty.is_dirty(pane)));
+    }
+
+    #[test]
+    fn test_restore_pane_in_same_area() {
+        let screen = Rect::new(0, 0, 20, 5);
+        let pane = Rect::new(2, 1, 10, 2);
+        let mut buf = Buffer::empty(screen);
+        buf.set_string(2, 1, "hello", Style::default().fg(Color::Red));
+
+        let mut buffers = PaneBuffers::default();
+        buffers.store(Pane::Messages, &buf, pane);
+
+        let mut next = Buffer::empty(screen);
+        assert!(!buffers.restore(Pane::Channels, &mut next, pane));
+        assert!(!buffers.restore(Pane::Messages, &mut next, Rect::new(0, 0, 10, 2)));
+        assert_eq!(next, Buffer::empty(screen));
+
+        assert!(buffers.restore(Pane::Messages, &mut next, pane));
+        assert_eq!(next, buf);
+    }
+}
@@ -0,0 +1,294 @@
+//! Graceful degradation on terminals without unicode or with few colors
+
+use ratatui::buffer::Buffer;
+use ratatui::style::Color;
+
+use crate::config::{ColorDepth, TerminalConfig};
+
+/// Replacements of the unicode symbols drawn by gurk itself, e.g. borders and receipts
+const ASCII_SYMBOLS: [(&str, &str); 23] = [
+    ("─", "-"),
+    ("│", "|"),
+    ("┌", "+"),
+    ("┐", "+"),
+    ("└", "+"),
+    ("┘", "+"),
+    ("╭", "+"),
+    ("╮", "+"),
+    ("╰", "+"),
+    ("╯", "+"),
+    ("├", "+"),
+    ("┤", "+"),
+    ("┬", "+"),
+    ("┴", "+"),
+    ("┼", "+"),
+    ("●", "*"),
+    ("○", "o"),
+    ("◉", "O"),
+    ("↑", "^"),
+    ("↓", "v"),
+    ("→", ">"),
+    ("…", "."),
+    ("✓", "v"),
+];
+
+/// What the terminal can display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerminalCapabilities {
+    pub unicode: bool,
+    pub colors: ColorDepth,
+}
+
+impl Default for TerminalCapabilities {
+    fn default() -> Self {
+        Self {
+            unicode: true,
+            colors: ColorDepth::TrueColor,
+        }
+    }
+}
+
+/// Icons shown after the name of a channel in the channel list
+pub(crate) struct ChannelIcons {
+    pub muted: &'static str,
+    pub pinned: &'static str,
+    pub burn_after_reading: &'static str,
+    pub birthday: &'static str,
+    pub marked: &'static str,
+}
+
+impl TerminalCapabilities {
+    /// Detects the capabilities from the environment, unless they are set in the config
+    pub fn detect(config: &TerminalConfig) -> Self {
+        Self::from_env(config, |name| std::env::var(name).ok())
+    }
+
+    fn from_env(config: &TerminalConfig, var: impl Fn(&str) -> Option<String>) -> Self {
+        let term = var("TERM").unwrap_or_default();
+        let unicode = config.unicode.unwrap_or_else(|| {
+            // the first set variable determines the character set, like in `setlocale`
+            let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+                .into_iter()
+                .find_map(|name| var(name).filter(|value| !value.is_empty()));
+            match locale {
+                Some(locale) => {
+                    let locale = locale.to_ascii_lowercase();
+                    locale.contains("utf-8") || locale.contains("utf8")
+                }
+                // e.g. on Windows, where no locale is set
+                None => !is_serial_term(&term),
+            }
+        });
+        let colors = config.colors.unwrap_or_else(|| {
+            let colorterm = var("COLORTERM").unwrap_or_default();
+            if colorterm == "truecolor" || colorterm == "24bit" || term.is_empty() {
+                ColorDepth::TrueColor
+            } else if term.contains("256color") {
+                ColorDepth::Colors256
+            } else {
+                ColorDepth::Colors8
+            }
+        });
+        Self { unicode, colors }
+    }
+
+    pub(crate) fn channel_icons(&self) -> ChannelIcons {
+        if self.unicode {
+            ChannelIcons {
+                muted: "🔇",
+                pinned: "📌",
+                burn_after_reading: "🔥",
+                birthday: "🎂",
+                marked: "☑",
+            }
+        } else {
+            ChannelIcons {
+                muted: "[m]",
+                pinned: "[p]",
+                burn_after_reading: "[b]",
+                birthday: "[bd]",
+                marked: "[x]",
+            }
+        }
+    }
+
+    /// Replaces the symbols and colors of the drawn frame, which the terminal cannot display
+    pub(crate) fn degrade(&self, buf: &mut Buffer) {
+        if self.unicode && self.colors == ColorDepth::TrueColor {
+            return;
+        }
+        for cell in &mut buf.content {
+            if !self.unicode {
+                if let Some((_, ascii)) = ASCII_SYMBOLS
+                    .iter()
+                    .find(|(symbol, _)| *symbol == cell.symbol())
+                {
+                    cell.set_symbol(ascii);
+                }
+            }
+            cell.fg = reduce_color(cell.fg, self.colors);
+            cell.bg = match (self.colors, cell.bg) {
+                // keep the status bar and highlights distinguishable from gray text
+                (ColorDepth::Colors8, Color::DarkGray) => Color::Blue,
+                (colors, bg) => reduce_color(bg, colors),
+            };
+        }
+    }
+}
+
+/// Terminals of serial consoles, which usually cannot display unicode
+fn is_serial_term(term: &str) -> bool {
+    term == "dumb" || term == "linux" || term.starts_with("vt")
+}
+
+/// Nearest color of the color depth
+fn reduce_color(color: Color, colors: ColorDepth) -> Color {
+    match (colors, color) {
+        (ColorDepth::TrueColor, _) => color,
+        (ColorDepth::Colors256, Color::Rgb(r, g, b)) => {
+            // 6x6x6 color cube of the 256 colors
+            let level = |c: u8| (u16::from(c) * 5 + 127) / 255;
+            let idx = 16 + 36 * level(r) + 6 * level(g) + level(b);
+            Color::Indexed(idx as u8)
+        }
+        (ColorDepth::Colors256, _) => color,
+        (ColorDepth::Colors8, Color::Rgb(r, g, b)) => basic_color(r, g, b),
+        (ColorDepth::Colors8, Color::Indexed(idx)) => match idx {
+            0..=15 => ansi_color(idx),
+            16..=231 => {
+                let level = |c: u8| [0, 95, 135, 175, 215, 255][usize::from(c)];
+                let idx = idx - 16;
+                basic_color(level(idx / 36), level(idx / 6 % 6), level(idx % 6))
+            }
+            _ => {
+                // grayscale ramp
+                let gray = 8 + (idx - 232) * 10;
+                basic_color(gray, gray, gray)
+            }
+        },
+        (ColorDepth::Colors8, Color::LightRed) => Color::Red,
+        (ColorDepth::Colors8, Color::LightGreen) => Color::Green,
+        (ColorDepth::Colors8, Color::LightYellow) => Color::Yellow,
+        (ColorDepth::Colors8, Color::LightBlue) => Color::Blue,
+        (ColorDepth::Colors8, Color::LightMagenta) => Color::Magenta,
+        (ColorDepth::Colors8, Color::LightCyan) => Color::Cyan,
+        (ColorDepth::Colors8, Color::DarkGray | Color::White) => Color::Gray,
+        (ColorDepth::Colors8, _) => color,
+    }
+}
+
+fn ansi_color(idx: u8) -> Color {
+    [
+        Color::Black,
+        Color::Red,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::Gray,
+    ][usize::from(idx % 8)]
+}
+
+/// Basic color with the channels of the color above the half
+fn basic_color(r: u8, g: u8, b: u8) -> Color {
+    let bit = |c: u8, value: u8| if c >= 128 { value } else { 0 };
+    ansi_color(bit(r, 1) | bit(g, 2) | bit(b, 4))
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::layout::Rect;
+    use ratatui::style::Style;
+
+    use super::*;
+
+    fn detect(vars: &[(&str, &str)]) -> TerminalCapabilities {
+        TerminalCapabilities::from_env(&TerminalConfig::default(), |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn test_detect() {
+        let capabilities = detect(&[
+            ("TERM", "xterm-256color"),
+            ("COLORTERM", "truecolor"),
+            ("LANG", "de_DE.UTF-8"),
+        ]);
+        assert_eq!(capabilities, TerminalCapabilities::default());
+
+        let capabilities = detect(&[("TERM", "xterm-256color"), ("LANG", "en_US.utf8")]);
+        assert!(capabilities.unicode);
+        assert_eq!(capabilities.colors, ColorDepth::Colors256);
+
+        // LC_ALL takes precedence over LANG
+        let capabilities = detect(&[("TERM", "linux"), ("LC_ALL", "C"), ("LANG", "C.UTF-8")]);
+        assert!(!capabilities.unicode);
+        assert_eq!(capabilities.colors, ColorDepth::Colors8);
+
+        let capabilities = detect(&[("TERM", "vt220")]);
+        assert!(!capabilities.unicode);
+
+        let config = TerminalConfig {
+            unicode: Some(true),
+            colors: Some(ColorDepth::Colors256),
+        };
+        let capabilities = TerminalCapabilities::from_env(&config, |_| None);
+        assert!(capabilities.unicode);
+        assert_eq!(capabilities.colors, ColorDepth::Colors256);
+    }
+
+    #[test]
+    fn test_reduce_color() {
+        let rgb = Color::Rgb(0xff, 0xaa, 0x00);
+        assert_eq!(reduce_color(rgb, ColorDepth::TrueColor), rgb);
+        assert_eq!(
+            reduce_color(rgb, ColorDepth::Colors256),
+            Color::Indexed(214)
+        );
+        assert_eq!(reduce_color(rgb, ColorDepth::Colors8), Color::Yellow);
+        assert_eq!(
+            reduce_color(Color::Indexed(21), ColorDepth::Colors8),
+            Color::Blue
+        );
+        assert_eq!(
+            reduce_color(Color::Indexed(9), ColorDepth::Colors8),
+            Color::Red
+        );
+        assert_eq!(
+            reduce_color(Color::LightCyan, ColorDepth::Colors8),
+            Color::Cyan
+        );
+        assert_eq!(
+            reduce_color(Color::Reset, ColorDepth::Colors8),
+            Color::Reset
+        );
+    }
+
+    #[test]
+    fn test_degrade() {
+        let mut buf = Buffer::empty(Rect::new(0, 0, 4, 1));
+        buf.set_string(
+            0,
+            0,
+            "╭─●x",
+            Style::default().fg(Color::LightGreen).bg(Color::DarkGray),
+        );
+        let capabilities = TerminalCapabilities {
+            unicode: false,
+            colors: ColorDepth::Colors8,
+        };
+        capabilities.degrade(&mut buf);
+        let mut expected = Buffer::empty(Rect::new(0, 0, 4, 1));
+        expected.set_string(
+            0,
+            0,
+            "+-*x",
+            Style::default().fg(Color::Green).bg(Color::Blue),
+        );
+        assert_eq!(buf, expected);
+    }
+}
@@ -0,0 +1,374 @@
+//! Dark and light color themes, switched by the background of the terminal or a schedule, and
+//! the configurable colors of the parts of the UI
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::NaiveTime;
+use ratatui::buffer::Buffer;
+use ratatui::style::{Color, Modifier, Style};
+
+use crate::config::{ThemeConfig, ThemePreset};
+
+/// Colors of the UI for a dark or light terminal background
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Theme {
+    /// The colors gurk is designed with
+    #[default]
+    Dark,
+    /// Darker text colors and lighter backgrounds
+    Light,
+}
+
+impl Theme {
+    /// Theme for the background color of the terminal
+    pub fn for_background((r, g, b): (u8, u8, u8)) -> Self {
+        let luma = 299 * u32::from(r) + 587 * u32::from(g) + 114 * u32::from(b);
+        if luma > 128 * 1000 {
+            Self::Light
+        } else {
+            Self::Dark
+        }
+    }
+
+    /// Theme for `$COLORFGBG`, e.g. `15;0`, set by some terminals
+    pub fn from_colorfgbg(value: &str) -> Option<Self> {
+        let bg: u8 = value.rsplit(';').next()?.parse().ok()?;
+        Some(if bg == 7 || bg == 15 {
+            Self::Light
+        } else {
+            Self::Dark
+        })
+    }
+
+    /// Theme of the schedule at the local time
+    ///
+    /// The light theme is used from `light_from` until `dark_from`, also over midnight.
+    pub fn scheduled((light_from, dark_from): (NaiveTime, NaiveTime), time: NaiveTime) -> Self {
+        let is_light = if light_from <= dark_from {
+            light_from <= time && time < dark_from
+        } else {
+            time < dark_from || light_from <= time
+        };
+        if is_light {
+            Self::Light
+        } else {
+            Self::Dark
+        }
+    }
+
+    /// Adapts the colors of the drawn frame to the theme
+    ///
+    /// Dark backgrounds are lightened, and text colors which are hard to read on a light
+    /// background are darkened.
+    pub(crate) fn apply(self, buf: &mut Buffer) {
+        if self == Self::Dark {
+            return;
+        }
+        for cell in &mut buf.content {
+            cell.bg = light_bg(cell.bg);
+            if is_light(cell.bg) {
+                cell.fg = dark_fg(cell.fg);
+            }
+        }
+    }
+}
+
+/// Colors of the parts of the UI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    pub selected: Style,
+    pub own_messages: Style,
+    pub other_messages: Style,
+    pub timestamps: Style,
+    pub unread: Style,
+    pub status_bar: Style,
+    pub popups: Style,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::preset(ThemePreset::Default)
+    }
+}
+
+impl Palette {
+    pub fn preset(preset: ThemePreset) -> Self {
+        match preset {
+            ThemePreset::Default => Self {
+                selected: Style::default().fg(Color::Black).bg(Color::Gray),
+                own_messages: Style::default(),
+                other_messages: Style::default(),
+                timestamps: Style::default().fg(Color::Yellow),
+                unread: Style::default(),
+                status_bar: Style::default().fg(Color::White).bg(Color::DarkGray),
+                popups: Style::default(),
+            },
+            ThemePreset::Solarized => {
+                const BASE03: Color = Color::Rgb(0x00, 0x2b, 0x36);
+                const BASE02: Color = Color::Rgb(0x07, 0x36, 0x42);
+                const BASE0: Color = Color::Rgb(0x83, 0x94, 0x96);
+                const BASE1: Color = Color::Rgb(0x93, 0xa1, 0xa1);
+                const YELLOW: Color = Color::Rgb(0xb5, 0x89, 0x00);
+                const ORANGE: Color = Color::Rgb(0xcb, 0x4b, 0x16);
+                const CYAN: Color = Color::Rgb(0x2a, 0xa1, 0x98);
+                Self {
+                    selected: Style::default().fg(BASE03).bg(BASE1),
+                    own_messages: Style::default().fg(CYAN),
+                    other_messages: Style::default().fg(BASE0),
+                    timestamps: Style::default().fg(YELLOW),
+                    unread: Style::default().fg(ORANGE).add_modifier(Modifier::BOLD),
+                    status_bar: Style::default().fg(BASE1).bg(BASE02),
+                    popups: Style::default().fg(BASE0).bg(BASE03),
+                }
+            }
+        }
+    }
+
+    /// Colors of the preset, overridden by the colors set in the config
+    pub fn from_config(config: &ThemeConfig) -> anyhow::Result<Self> {
+        let mut palette = Self::preset(config.preset);
+        let colors = &config.colors;
+        let parts = [
+            (&mut palette.selected, &colors.selected),
+            (&mut palette.own_messages, &colors.own_messages),
+            (&mut palette.other_messages, &colors.other_messages),
+            (&mut palette.timestamps, &colors.timestamps),
+            (&mut palette.unread, &colors.unread),
+            (&mut palette.status_bar, &colors.status_bar),
+            (&mut palette.popups, &colors.popups),
+        ];
+        for (style, value) in parts {
+            if let Some(value) = value {
+                *style = parse_style(value)?;
+            }
+        }
+        Ok(palette)
+    }
+}
+
+/// Parses `"<fg>"`, `"<fg> on <bg>"` or `"on <bg>"`
+fn parse_style(value: &str) -> anyhow::Result<Style> {
+    let value = value.trim();
+    let (fg, bg) = match value.strip_prefix("on ") {
+        Some(bg) => ("", Some(bg)),
+        None => match value.split_once(" on ") {
+            Some((fg, bg)) => (fg, Some(bg)),
+            None => (value, None),
+        },
+    };
+    let parse = |color: &str| {
+        Color::from_str(color.trim())
+            .ok()
+            .with_context(|| format!("invalid theme color: {value}"))
+    };
+    let mut style = Style::default();
+    if !fg.is_empty() {
+        style = style.fg(parse(fg)?);
+    }
+    if let Some(bg) = bg {
+        style = style.bg(parse(bg)?);
+    }
+    Ok(style)
+}
+
+fn light_bg(color: Color) -> Color {
+    match color {
+        Color::Black => Color::White,
+        Color::DarkGray => Color::Gray,
+        Color::Rgb(r, g, b) if r == g && g == b && r < 128 => Color::Rgb(255 - r, 255 - g, 255 - b),
+        color => color,
+    }
+}
+
+fn is_light(bg: Color) -> bool {
+    match bg {
+        // the background of the terminal
+        Color::Reset => true,
+        Color::Gray | Color::White => true,
+        Color::Rgb(r, g, b) => Theme::for_background((r, g, b)) == Theme::Light,
+        _ => false,
+    }
+}
+
+fn dark_fg(color: Color) -> Color {
+    match color {
+        Color::White => Color::Black,
+        Color::Gray => Color::DarkGray,
+        Color::Yellow | Color::LightYellow => Color::Indexed(136),
+        Color::Cyan | Color::LightCyan => Color::Indexed(30),
+        Color::LightRed => Color::Red,
+        Color::LightGreen => Color::Green,
+        Color::LightBlue => Color::Blue,
+        Color::LightMagenta => Color::Magenta,
+        Color::Rgb(r, g, b) if r == g && g == b => Color::Rgb(255 - r, 255 - g, 255 - b),
+        Color::Rgb(r, g, b) if Theme::for_background((r, g, b)) == Theme::Light => {
+            let darken = |c: u8| (u16::from(c) * 3 / 5) as u8;
+            Color::Rgb(darken(r), darken(g), darken(b))
+        }
+        color => color,
+    }
+}
+
+/// Queries the background color of the terminal with OSC 11
+///
+/// Must be called in raw mode, before the events of the terminal are read. The query is followed
+/// by a request of the device attributes, which all terminals answer, so that terminals without
+/// support for OSC 11 are detected without waiting for the timeout. The response is read on the
+/// calling thread, waiting for each byte until the timeout is over.
+#[cfg(unix)]
+pub fn query_background(timeout: Duration) -> Option<(u8, u8, u8)> {
+    use std::io::{Read, Write};
+    use std::os::fd::AsRawFd;
+    use std::time::Instant;
+
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()?;
+    tty.write_all(b"\x1b]11;?\x1b\\\x1b[c").ok()?;
+    tty.flush().ok()?;
+
+    let deadline = Instant::now() + timeout;
+    // read byte by byte, so that no input after the response is consumed
+    let mut response = Vec::new();
+    let mut byte = [0];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let mut pollfd = libc::pollfd {
+            fd: tty.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout_ms = remaining.as_millis().try_into().unwrap_or(libc::c_int::MAX);
+        // SAFETY: exactly one valid pollfd is passed
+        let ready = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+        if ready <= 0 {
+            // timed out, or failed
+            return None;
+        }
+        tty.read_exact(&mut byte).ok()?;
+        response.push(byte[0]);
+        let is_attributes_end = byte[0] == b'c' && response.windows(3).any(|seq| seq == b"\x1b[?");
+        if is_attributes_end {
+            break;
+        }
+    }
+    parse_background(&response)
+}
+
+#[cfg(not(unix))]
+pub fn query_background(_timeout: Duration) -> Option<(u8, u8, u8)> {
+    None
+}
+
+/// Parses the OSC 11 response, e.g. `ESC ] 11 ; rgb:ffff/ffff/dddd ESC \`
+fn parse_background(response: &[u8]) -> Option<(u8, u8, u8)> {
+    let response = std::str::from_utf8(response).ok()?;
+    let (_, color) = response.split_once("]11;rgb:")?;
+    let end = color.find(['\x07', '\x1b'])?;
+    let mut channels = color[..end].split('/').map(|channel| {
+        // channels have 1 to 4 hex digits
+        let value = u32::from_str_radix(channel, 16).ok()?;
+        let max = (1u32 << (4 * channel.len().clamp(1, 4))) - 1;
+        Some((value * 255 / max) as u8)
+    });
+    let rgb = (channels.next()??, channels.next()??, channels.next()??);
+    channels.next().is_none().then_some(rgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::layout::Rect;
+    use ratatui::style::Style;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_background() {
+        let response = b"\x1b]11;rgb:ffff/ffff/dddd\x1b\\\x1b[?62;22c";
+        assert_eq!(parse_background(response), Some((255, 255, 221)));
+        assert_eq!(
+            parse_background(b"\x1b]11;rgb:1e/1e/2e\x07"),
+            Some((30, 30, 46))
+        );
+        // no support for OSC 11
+        assert_eq!(parse_background(b"\x1b[?62;22c"), None);
+        assert_eq!(parse_background(b"\x1b]11;rgb:ffff/ffff\x07"), None);
+    }
+
+    #[test]
+    fn test_detect_theme() {
+        assert_eq!(Theme::for_background((255, 255, 221)), Theme::Light);
+        assert_eq!(Theme::for_background((30, 30, 46)), Theme::Dark);
+        assert_eq!(Theme::from_colorfgbg("0;15"), Some(Theme::Light));
+        assert_eq!(Theme::from_colorfgbg("15;default;0"), Some(Theme::Dark));
+        assert_eq!(Theme::from_colorfgbg(""), None);
+    }
+
+    #[test]
+    fn test_scheduled_theme() {
+        let time = |time| NaiveTime::parse_from_str(time, "%H:%M").unwrap();
+        let day = (time("07:00"), time("19:30"));
+        assert_eq!(Theme::scheduled(day, time("12:00")), Theme::Light);
+        assert_eq!(Theme::scheduled(day, time("19:30")), Theme::Dark);
+        assert_eq!(Theme::scheduled(day, time("03:00")), Theme::Dark);
+        // light at night
+        let night = (time("22:00"), time("06:00"));
+        assert_eq!(Theme::scheduled(night, time("23:00")), Theme::Light);
+        assert_eq!(Theme::scheduled(night, time("05:59")), Theme::Light);
+        assert_eq!(Theme::scheduled(night, time("12:00")), Theme::Dark);
+    }
+
+    #[test]
+    fn test_palette_from_config() {
+        let mut config = ThemeConfig::default();
+        assert_eq!(Palette::from_config(&config).unwrap(), Palette::default());
+
+        config.preset = ThemePreset::Solarized;
+        config.colors.selected = Some("black on #93a1a1".to_string());
+        config.colors.timestamps = Some("on 42".to_string());
+        let palette = Palette::from_config(&config).unwrap();
+        assert_eq!(
+            palette.selected,
+            Style::default()
+                .fg(Color::Black)
+                .bg(Color::Rgb(0x93, 0xa1, 0xa1))
+        );
+        assert_eq!(palette.timestamps, Style::default().bg(Color::Indexed(42)));
+        assert_eq!(
+            palette.status_bar,
+            Palette::preset(ThemePreset::Solarized).status_bar
+        );
+
+        config.colors.unread = Some("no color".to_string());
+        assert!(Palette::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_apply_light_theme() {
+        let mut buf = Buffer::empty(Rect::new(0, 0, 3, 1));
+        buf.set_string(0, 0, "a", Style::default().fg(Color::Yellow));
+        buf.set_string(
+            1,
+            0,
+            "b",
+            Style::default().fg(Color::White).bg(Color::DarkGray),
+        );
+        buf.set_string(
+            2,
+            0,
+            "c",
+            Style::default().fg(Color::Yellow).bg(Color::Blue),
+        );
+        Theme::Light.apply(&mut buf);
+
+        assert_eq!(buf[(0, 0)].fg, Color::Indexed(136));
+        assert_eq!(buf[(1, 0)].fg, Color::Black);
+        assert_eq!(buf[(1, 0)].bg, Color::Gray);
+        // text on a colored background stays as is
+        assert_eq!(buf[(2, 0)].fg, Color::Yellow);
+    }
+}
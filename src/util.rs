@@ -1,9 +1,17 @@
-use chrono::{DateTime, Local};
-use phonenumber::PhoneNumber;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use base64::prelude::*;
+use phonenumber::{country, Mode, PhoneNumber};
 use ratatui::widgets::ListState;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
+pub use gurk_core::util::{utc_now_timestamp_msec, utc_timestamp_msec_to_local};
+
+use crate::config::PhoneNumberFormat;
+use crate::data::ChannelId;
+
 const MESSAGE_SCROLL_BACK: bool = false;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +34,8 @@ impl<T: Eq> Eq for StatefulList<T> {}
 #[derive(Debug, Clone, Default)]
 pub struct Rendered {
     pub offset: usize,
+    /// Number of entries shown in the viewport at the last render
+    pub page_len: usize,
 }
 
 impl<T> Default for StatefulList<T> {
@@ -93,19 +103,6 @@ impl<T> StatefulList<T> {
     }
 }
 
-pub fn utc_timestamp_msec_to_local(timestamp: u64) -> DateTime<Local> {
-    DateTime::from_timestamp((timestamp / 1000) as i64, (timestamp % 1000) as u32)
-        .expect("invalid datetime")
-        .with_timezone(&Local)
-}
-
-pub fn utc_now_timestamp_msec() -> u64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_millis() as u64
-}
-
 pub fn is_phone_number(s: impl AsRef<str>) -> bool {
     // Note: previously we formatted phone numbers sometimes incorrectly (not always as E164). So,
     // some users might still have them stored with spaces and dashes. So, we strip them here, even
@@ -114,6 +111,72 @@ pub fn is_phone_number(s: impl AsRef<str>) -> bool {
     stripped.parse::<PhoneNumber>().is_ok()
 }
 
+/// Parses a loosely formatted phone number, e.g. `(030) 123-456`
+///
+/// Numbers without country code are parsed as numbers of the region.
+pub fn parse_phone_number(s: &str, region: Option<country::Id>) -> Option<PhoneNumber> {
+    let number = phonenumber::parse(region, s).ok()?;
+    phonenumber::is_valid(&number).then_some(number)
+}
+
+/// Replaces the leading `~/` of the path with the home directory
+pub(crate) fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => Path::new(path).to_path_buf(),
+    }
+}
+
+/// Id of the channel for scripts: the UUID of the contact, or the base64 encoded id of the group
+pub(crate) fn channel_id_to_string(channel_id: ChannelId) -> String {
+    match channel_id {
+        ChannelId::User(uuid) => uuid.to_string(),
+        ChannelId::Group(id) => BASE64_STANDARD.encode(id),
+    }
+}
+
+/// Parses the id of a channel formatted by [`channel_id_to_string`]
+pub(crate) fn parse_channel_id(s: &str) -> Option<ChannelId> {
+    if let Ok(uuid) = s.parse() {
+        return Some(ChannelId::User(uuid));
+    }
+    let bytes = BASE64_STANDARD.decode(s).ok()?;
+    ChannelId::try_from(&bytes[..]).ok()
+}
+
+/// Command running the given command line in the shell, without any input or output
+pub(crate) fn shell_command(command: &str) -> Command {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut cmd = Command::new(shell);
+    cmd.args([flag, command])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    cmd
+}
+
+/// Formats the phone number for display
+///
+/// Only numbers of the region are shown in the given format, other numbers are shown in the
+/// international format.
+pub fn format_phone_number(
+    number: &PhoneNumber,
+    region: Option<country::Id>,
+    format: PhoneNumberFormat,
+) -> String {
+    let mode = match format {
+        PhoneNumberFormat::National if region.is_some() && number.country().id() == region => {
+            Mode::National
+        }
+        _ => Mode::International,
+    };
+    number.format().mode(mode).to_string()
+}
+
 // Based on Alacritty, APACHE-2.0 License
 pub const URL_REGEX: &str =
     "(ipfs:|ipns:|magnet:|mailto:|gemini:|gopher:|https:|http:|news:|file:|git:|ssh:|ftp:)\
@@ -153,8 +216,45 @@ impl LazyRegex {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_channel_id_to_string() {
+        let user = ChannelId::User(uuid::Uuid::new_v4());
+        assert_eq!(parse_channel_id(&channel_id_to_string(user)), Some(user));
+        let group = ChannelId::Group([42; 32]);
+        assert_eq!(parse_channel_id(&channel_id_to_string(group)), Some(group));
+        assert_eq!(parse_channel_id("unknown"), None);
+    }
+
     #[test]
     fn test_is_phone_number() {
         assert!(is_phone_number("+1 000-000-0000"));
     }
+
+    #[test]
+    fn test_parse_phone_number_in_region() {
+        let number = parse_phone_number("+49 30 123456", None).unwrap();
+        let region = Some(country::Id::DE);
+        assert_eq!(parse_phone_number("(030) 123-456", region), Some(number));
+        assert_eq!(parse_phone_number("030 123456", None), None);
+        assert_eq!(parse_phone_number("not a number", region), None);
+    }
+
+    #[test]
+    fn test_format_phone_number() {
+        let region = Some(country::Id::DE);
+        let local = parse_phone_number("030 123456", region).unwrap();
+        let foreign = parse_phone_number("+1 201-555-0123", region).unwrap();
+        let national = PhoneNumberFormat::National;
+        let international = PhoneNumberFormat::International;
+        assert_eq!(format_phone_number(&local, region, national), "030 123456");
+        assert_eq!(
+            format_phone_number(&local, region, international),
+            "+49 30 123456"
+        );
+        assert_eq!(
+            format_phone_number(&foreign, region, national),
+            "+1 201-555-0123"
+        );
+        assert_eq!(format_phone_number(&local, None, national), "+49 30 123456");
+    }
 }
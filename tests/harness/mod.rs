@@ -0,0 +1,214 @@
+//! End-to-end test harness
+//!
+//! Drives the app through scripted steps (key presses, incoming messages, resizes) against the
+//! mock Signal backend. After each step, the app is rendered into a test terminal, like in the
+//! main loop, so that the steps see the same viewport state as a user would.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use gurk::app::App;
+use gurk::config::{Config, User};
+use gurk::data::{ChannelId, Message};
+use gurk::signal::test::SignalManagerMock;
+use gurk::storage::{BurnAfterReading, ForgetfulStorage, MemCache, Storage};
+use gurk::ui;
+use presage::libsignal_service::content::{Content, ContentBody, Metadata};
+use presage::libsignal_service::protocol::ServiceId;
+use presage::proto::DataMessage;
+use ratatui::backend::TestBackend;
+use ratatui::Terminal;
+use uuid::Uuid;
+
+/// Timestamp of the first scripted incoming message
+const START_TIMESTAMP: u64 = 1_700_000_000_000;
+
+/// Single scripted step
+pub enum Step<'a> {
+    /// Presses a key combination, e.g. `"alt-j"`, `"ctrl-end"` or `"enter"`
+    Key(&'a str),
+    /// Types the text into the input box, one key per character
+    Type(&'a str),
+    /// Receives a direct message from a contact
+    Receive { from: Uuid, text: &'a str },
+    /// Receives the last direct message again, like a replayed envelope
+    Replay { from: Uuid, text: &'a str },
+    /// Resizes the terminal to the given width and height
+    Resize(u16, u16),
+}
+
+pub struct Harness {
+    pub app: App,
+    pub sent_messages: Rc<RefCell<Vec<Message>>>,
+    terminal: Terminal<TestBackend>,
+    /// Timestamp of the next incoming message
+    next_timestamp: u64,
+}
+
+impl Harness {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self::with_config(width, height, |_| {})
+    }
+
+    pub fn with_config(width: u16, height: u16, configure: impl FnOnce(&mut Config)) -> Self {
+        let mut config = Config::with_user(User {
+            name: "Tyler Durden".to_string(),
+            phone_number: "+0000000000".to_string(),
+        });
+        config.notifications = false;
+        configure(&mut config);
+
+        let signal_manager = SignalManagerMock::new();
+        let sent_messages = signal_manager.sent_messages.clone();
+        let storage = BurnAfterReading::new(MemCache::new(ForgetfulStorage));
+        let (app, _events) =
+            App::try_new(config, Box::new(signal_manager), Box::new(storage)).unwrap();
+        let terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+
+        let mut harness = Self {
+            app,
+            sent_messages,
+            terminal,
+            next_timestamp: START_TIMESTAMP,
+        };
+        harness.render();
+        harness
+    }
+
+    /// Adds a contact with a known name
+    pub fn add_contact(&mut self, name: &str) -> Uuid {
+        let id = Uuid::new_v4();
+        self.app.storage.store_name(id, name.to_string());
+        id
+    }
+
+    pub async fn run<'a>(&mut self, steps: impl IntoIterator<Item = Step<'a>>) {
+        for step in steps {
+            self.step(step).await;
+        }
+    }
+
+    pub async fn step(&mut self, step: Step<'_>) {
+        match step {
+            Step::Key(key) => self.app.on_key(parse_key(key)).await.unwrap(),
+            Step::Type(text) => {
+                for c in text.chars() {
+                    let key = KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+                    self.app.on_key(key).await.unwrap();
+                }
+            }
+            Step::Receive { from, text } => {
+                let content = direct_message(from, self.app.user_id, self.next_timestamp, text);
+                self.next_timestamp += 1;
+                self.app.on_message(content).await.unwrap();
+            }
+            Step::Replay { from, text } => {
+                let timestamp = self.next_timestamp - 1;
+                let content = direct_message(from, self.app.user_id, timestamp, text);
+                self.app.on_message(content).await.unwrap();
+            }
+            Step::Resize(width, height) => self.terminal.backend_mut().resize(width, height),
+        }
+        self.render();
+    }
+
+    /// Renders the app and returns the lines of the screen
+    pub fn render(&mut self) -> Vec<String> {
+        self.terminal.draw(|f| ui::draw(f, &mut self.app)).unwrap();
+        self.screen()
+    }
+
+    /// Lines of the screen at the last render
+    pub fn screen(&self) -> Vec<String> {
+        let buffer = self.terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Row of the first line on the screen containing the text
+    pub fn row_of(&self, text: &str) -> Option<usize> {
+        self.screen().iter().position(|line| line.contains(text))
+    }
+
+    pub fn screen_contains(&self, text: &str) -> bool {
+        self.row_of(text).is_some()
+    }
+
+    /// Channel of the direct messages with the contact
+    pub fn channel_idx(&self, contact_id: Uuid) -> Option<usize> {
+        self.app
+            .channels
+            .items
+            .iter()
+            .position(|&channel_id| channel_id == ChannelId::User(contact_id))
+    }
+
+    /// Texts of the messages persisted in the channel, oldest first
+    pub fn stored_texts(&self, channel_id: ChannelId) -> Vec<String> {
+        self.app
+            .storage
+            .messages(channel_id)
+            .filter_map(|message| message.message.clone())
+            .collect()
+    }
+}
+
+/// Parses a key combination in the format of the keybindings, e.g. `"alt-j"`
+fn parse_key(key: &str) -> KeyEvent {
+    let mut parts: Vec<_> = key.split('-').collect();
+    let code = parts.pop().expect("empty key");
+    let modifiers = parts
+        .into_iter()
+        .fold(KeyModifiers::NONE, |modifiers, modifier| {
+            modifiers
+                | match modifier {
+                    "ctrl" => KeyModifiers::CONTROL,
+                    "alt" => KeyModifiers::ALT,
+                    "shift" => KeyModifiers::SHIFT,
+                    _ => panic!("unknown modifier in key: {key}"),
+                }
+        });
+    let code = match code {
+        "enter" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        c if c.chars().count() == 1 => KeyCode::Char(c.chars().next().unwrap()),
+        _ => panic!("unknown key: {key}"),
+    };
+    KeyEvent::new(code, modifiers)
+}
+
+fn direct_message(from: Uuid, to: Uuid, timestamp: u64, text: &str) -> Content {
+    Content {
+        metadata: Metadata {
+            sender: ServiceId::Aci(from.into()),
+            destination: ServiceId::Aci(to.into()),
+            sender_device: 1,
+            timestamp,
+            needs_receipt: false,
+            unidentified_sender: false,
+            server_guid: None,
+        },
+        body: ContentBody::DataMessage(DataMessage {
+            body: Some(text.to_string()),
+            timestamp: Some(timestamp),
+            profile_key: Some(vec![0; 32]),
+            ..Default::default()
+        }),
+    }
+}
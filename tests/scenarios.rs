@@ -0,0 +1,234 @@
+mod harness;
+
+use harness::{Harness, Step};
+
+#[tokio::test]
+async fn test_incoming_messages_move_channels_up() {
+    let mut harness = Harness::new(80, 24);
+    let alice = harness.add_contact("Alice");
+    let bob = harness.add_contact("Bob");
+
+    harness
+        .run([
+            Step::Receive {
+                from: alice,
+                text: "hi from alice",
+            },
+            Step::Receive {
+                from: bob,
+                text: "hi from bob",
+            },
+        ])
+        .await;
+    assert_eq!(harness.channel_idx(bob), Some(0));
+    assert_eq!(harness.channel_idx(alice), Some(1));
+    assert!(harness.row_of("Bob") < harness.row_of("Alice"));
+
+    // a pinned channel stays on top
+    harness
+        .run([Step::Key("down"), Step::Key("down"), Step::Key("alt-p")])
+        .await;
+    assert_eq!(harness.channel_idx(alice), Some(0));
+    assert!(harness.screen_contains("hi from alice"));
+    harness
+        .step(Step::Receive {
+            from: bob,
+            text: "still there?",
+        })
+        .await;
+    assert_eq!(harness.channel_idx(alice), Some(0));
+    assert!(harness.row_of("Alice") < harness.row_of("Bob"));
+}
+
+#[tokio::test]
+async fn test_scroll_keeps_viewport_on_new_messages() {
+    let mut harness = Harness::new(60, 20);
+    let alice = harness.add_contact("Alice");
+    let texts: Vec<_> = (0..40).map(|i| format!("message {i}")).collect();
+    harness
+        .run(texts.iter().map(|text| Step::Receive { from: alice, text }))
+        .await;
+    harness.step(Step::Key("down")).await;
+    assert!(harness.screen_contains("message 39"));
+    assert!(!harness.screen_contains("more below"));
+
+    harness.step(Step::Key("pageup")).await;
+    let channel_id = harness.app.channels.items[0];
+    let offset = harness.app.messages[&channel_id].rendered.offset;
+    assert!(offset > 0);
+    assert!(!harness.screen_contains("message 39"));
+    assert!(harness.screen_contains(&format!("↓ {offset} more below")));
+    let top_row = harness.row_of("message 20");
+
+    // a new message does not move the viewport
+    harness
+        .step(Step::Receive {
+            from: alice,
+            text: "message 40",
+        })
+        .await;
+    assert_eq!(
+        harness.app.messages[&channel_id].rendered.offset,
+        offset + 1
+    );
+    assert_eq!(harness.row_of("message 20"), top_row);
+    assert!(harness.screen_contains(&format!("↓ {} more below", offset + 1)));
+
+    harness.step(Step::Key("ctrl-end")).await;
+    assert!(harness.screen_contains("message 40"));
+    assert!(!harness.screen_contains("more below"));
+}
+
+#[tokio::test]
+async fn test_sent_message_is_persisted() {
+    let mut harness = Harness::new(80, 24);
+    let alice = harness.add_contact("Alice");
+    harness
+        .run([
+            Step::Receive {
+                from: alice,
+                text: "how are you?",
+            },
+            Step::Key("down"),
+            Step::Type("fine, thanks"),
+            Step::Key("enter"),
+        ])
+        .await;
+
+    assert_eq!(harness.sent_messages.borrow().len(), 1);
+    let channel_id = harness.app.channels.items[harness.channel_idx(alice).unwrap()];
+    assert_eq!(
+        harness.stored_texts(channel_id),
+        ["how are you?", "fine, thanks"]
+    );
+    assert!(harness.screen_contains("fine, thanks"));
+    assert!(harness.app.input.data.is_empty());
+}
+
+#[tokio::test]
+async fn test_resize_stacks_channel_list_above_chat() {
+    let mut harness = Harness::with_config(80, 24, |config| {
+        config.layout.stack_below_width = Some(60);
+    });
+    let alice = harness.add_contact("Alice");
+    harness
+        .run([
+            Step::Receive {
+                from: alice,
+                text: "hello",
+            },
+            Step::Key("down"),
+        ])
+        .await;
+    // channel list and chat side by side
+    assert_eq!(harness.screen()[0].matches('┌').count(), 2);
+
+    harness.step(Step::Resize(40, 24)).await;
+    assert_eq!(harness.screen()[0].matches('┌').count(), 1);
+    assert!(harness.row_of("Alice") < harness.row_of("hello"));
+}
+
+#[tokio::test]
+async fn test_input_title_shows_recipient() {
+    let mut harness = Harness::new(80, 24);
+    let alice = harness.add_contact("Alice");
+    let bob = harness.add_contact("Bob");
+    harness
+        .run([
+            Step::Receive {
+                from: alice,
+                text: "hi from alice",
+            },
+            Step::Receive {
+                from: bob,
+                text: "hi from bob",
+            },
+            Step::Key("down"),
+        ])
+        .await;
+    let selected = harness.app.channels.state.selected();
+    let (selected_name, other_name) = if selected == harness.channel_idx(alice) {
+        ("Alice", "Bob")
+    } else {
+        ("Bob", "Alice")
+    };
+    assert_eq!(input_title(&harness), format!("Input → {selected_name}"));
+
+    let key = if selected == Some(0) { "down" } else { "up" };
+    harness.step(Step::Key(key)).await;
+    assert_eq!(input_title(&harness), format!("Input → {other_name}"));
+}
+
+/// Title in the top border of the input block, which is the lowest block on the screen
+fn input_title(harness: &Harness) -> String {
+    let screen = harness.screen();
+    let row = screen
+        .iter()
+        .rposition(|line| line.contains("┌Input"))
+        .expect("no input block drawn");
+    // the input line and the bottom border follow
+    assert!(screen[row + 2].contains('└'));
+    let line = &screen[row];
+    let start = line.find("┌Input").unwrap() + '┌'.len_utf8();
+    line[start..].split('─').next().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_newest_message_first() {
+    let mut harness = Harness::with_config(60, 20, |config| {
+        config.layout.newest_first = true;
+    });
+    let alice = harness.add_contact("Alice");
+    let texts: Vec<_> = (0..40).map(|i| format!("message {i}")).collect();
+    harness
+        .run(texts.iter().map(|text| Step::Receive { from: alice, text }))
+        .await;
+    harness.step(Step::Key("down")).await;
+    assert!(harness.row_of("message 39").unwrap() < harness.row_of("message 38").unwrap());
+    assert!(harness.row_of("message 38") < harness.row_of("Input"));
+
+    // scrolling down goes to older messages
+    harness.step(Step::Key("pagedown")).await;
+    let channel_id = harness.app.channels.items[0];
+    let offset = harness.app.messages[&channel_id].rendered.offset;
+    assert!(offset > 0);
+    assert!(!harness.screen_contains("message 39"));
+    assert!(harness.screen_contains(&format!("↑ {offset} more above")));
+
+    harness.step(Step::Key("alt-n")).await;
+    assert!(harness.screen_contains(&format!("↓ {offset} more below")));
+    let newest_shown = format!("message {}", 39 - offset);
+    let older = format!("message {}", 38 - offset);
+    assert!(harness.row_of(&older).unwrap() < harness.row_of(&newest_shown).unwrap());
+}
+
+#[tokio::test]
+async fn test_replayed_message_is_not_duplicated() {
+    let mut harness = Harness::new(80, 24);
+    let alice = harness.add_contact("Alice");
+    harness
+        .run([
+            Step::Receive {
+                from: alice,
+                text: "only once",
+            },
+            Step::Replay {
+                from: alice,
+                text: "only once",
+            },
+        ])
+        .await;
+
+    let channel_id = harness.app.channels.items[harness.channel_idx(alice).unwrap()];
+    assert_eq!(harness.stored_texts(channel_id), ["only once"]);
+    assert_eq!(harness.app.messages[&channel_id].items.len(), 1);
+    assert_eq!(
+        harness
+            .app
+            .storage
+            .channel(channel_id)
+            .unwrap()
+            .unread_messages,
+        1
+    );
+}